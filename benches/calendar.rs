@@ -0,0 +1,81 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+use calendar_lib::calendar::Calendar;
+use calendar_lib::event::Event;
+
+const NUM_EVENTS: i64 = 10_000;
+
+fn base_datetime() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2022, 1, 1)
+        .unwrap()
+        .and_time(NaiveTime::from_hms_opt(9, 0, 0).unwrap())
+}
+
+/// Builds an event starting `offset` hours after the base datetime
+fn make_event(offset: i64, recurrence: Option<&str>) -> Event {
+    let start = base_datetime() + Duration::hours(offset);
+    Event::new(
+        &format!("event {offset}"),
+        "benchmark event",
+        &start.format("%d/%m/%Y").to_string(),
+        &start.format("%H:%M").to_string(),
+        1.0,
+        None,
+        recurrence,
+        Some(vec![format!("tag{}", offset % 10)]),
+    )
+}
+
+/// Builds a calendar with `n` non-overlapping events, one every 2 hours
+fn make_calendar(n: i64, recurrence: Option<&str>) -> Calendar {
+    let mut cal = Calendar::new("bench", "bench");
+    for i in 0..n {
+        cal.add_event(make_event(2 * i, recurrence));
+    }
+    cal
+}
+
+fn bench_add_event(c: &mut Criterion) {
+    let cal = make_calendar(NUM_EVENTS, None);
+    c.bench_function("add_event (10k events)", |b| {
+        b.iter_batched(
+            || (cal.clone(), make_event(2 * NUM_EVENTS + 1, None)),
+            |(mut cal, ev)| black_box(cal.add_event(ev)),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn bench_list_events_between(c: &mut Criterion) {
+    let cal = make_calendar(NUM_EVENTS, None);
+    let from = base_datetime() + Duration::days(100);
+    let until = from + Duration::days(30);
+    c.bench_function("list_events_between (10k events, 1 month)", |b| {
+        b.iter(|| black_box(cal.list_events_between(Some(from), Some(until))))
+    });
+    c.bench_function("list_events_between (10k events, all)", |b| {
+        b.iter(|| black_box(cal.list_events_between(None, None)))
+    });
+    c.bench_function("list_events_tagged (10k events)", |b| {
+        b.iter(|| black_box(cal.list_events_tagged(String::from("tag3"))))
+    });
+}
+
+fn bench_recurrence_expansion(c: &mut Criterion) {
+    let cal = make_calendar(NUM_EVENTS / 10, Some("daily 100"));
+    let from = base_datetime() + Duration::days(100);
+    let until = from + Duration::days(30);
+    c.bench_function(
+        "recurrence expansion (1k events x 100 daily, 1 month)",
+        |b| b.iter(|| black_box(cal.list_events_between(Some(from), Some(until)))),
+    );
+}
+
+criterion_group!(
+    benches,
+    bench_add_event,
+    bench_list_events_between,
+    bench_recurrence_expansion
+);
+criterion_main!(benches);
@@ -0,0 +1,6 @@
+//! Fuzzes the parsing of a line of the shell, e.g. an add command
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| calendar_lib::fuzzing::command(data));
@@ -0,0 +1,6 @@
+//! Fuzzes the parsing of an iCalendar document, imported as `add --from-file` does
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| calendar_lib::fuzzing::ics(data));
@@ -0,0 +1,6 @@
+//! Fuzzes the parsing of a recurrence, e.g. "monthly 5 2 on last fri"
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| calendar_lib::fuzzing::recurrence(data));
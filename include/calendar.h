@@ -0,0 +1,55 @@
+/*
+ * C ABI of the calendar library (see src/ffi.rs). Build the shared library with
+ *   cargo rustc --lib --release --no-default-features --features ffi --crate-type cdylib
+ *
+ * Strings are UTF-8 and NUL-terminated. The strings returned are freed with
+ * calendar_string_free, the calendars with calendar_free. On errors, calendar_last_error
+ * describes the last error on the calling thread.
+ */
+#ifndef CALENDAR_H
+#define CALENDAR_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Calendar Calendar;
+
+typedef enum {
+    CALENDAR_OK = 0,
+    CALENDAR_INVALID_ARGUMENT = 1,
+    CALENDAR_NOT_FOUND = 2,
+    CALENDAR_PARSING_FAILED = 3,
+    CALENDAR_FAILED = 4,
+    CALENDAR_PANICKED = 5,
+} CalendarStatus;
+
+/* Message of the last error on the thread (or NULL), valid until the next call */
+const char *calendar_last_error(void);
+
+/* Returns NULL on errors */
+Calendar *calendar_new(const char *owner, const char *name);
+Calendar *calendar_from_json(const char *json);
+char *calendar_to_json(Calendar *cal);
+void calendar_free(Calendar *cal);
+void calendar_string_free(char *s);
+
+/* start_date is %d/%m/%Y or %Y-%m-%d, start_time %H:%M; description, location,
+ * recurrence (e.g. "weekly 10") and eid may be NULL */
+CalendarStatus calendar_add_event(Calendar *cal, const char *title, const char *description,
+                                  const char *start_date, const char *start_time, double hours,
+                                  const char *location, const char *recurrence, uint64_t *eid);
+CalendarStatus calendar_remove_event(Calendar *cal, uint64_t eid);
+
+/* Writes to out a JSON array of the occurrences between from and until (ISO 8601, e.g.
+ * 2022-07-13T09:30:00, or NULL), to be freed with calendar_string_free */
+CalendarStatus calendar_events_between(Calendar *cal, const char *from, const char *until,
+                                       char **out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use crate::calendar::Calendar;
+use crate::event::{Event, WorkPlace};
+use crate::habits;
+use crate::itinerary;
+
+/// Tags kept as they are, since the calendar treats their events differently
+const KEPT_TAGS: [&str; 2] = [habits::HABIT_TAG, itinerary::TRAVEL_TAG];
+
+/// Stable pseudonyms of the names in a calendar: the same name always gets the same
+/// pseudonym, numbered in the order the names are met, e.g. "Location 2"
+#[derive(Debug, Default)]
+pub struct Pseudonyms {
+    names: HashMap<(&'static str, String), String>,
+    counts: HashMap<&'static str, usize>,
+}
+
+impl Pseudonyms {
+    /// Returns the pseudonym of a name of this kind, e.g. "Event 3" for kind "Event"
+    pub fn name(&mut self, kind: &'static str, name: &str) -> String {
+        if name.is_empty() {
+            return String::new();
+        }
+        let counts = &mut self.counts;
+        self.names
+            .entry((kind, name.to_string()))
+            .or_insert_with(|| {
+                let n = counts.entry(kind).or_default();
+                *n += 1;
+                match kind {
+                    "email" => format!("person{}@example.com", n),
+                    _ => format!("{} {}", kind, n),
+                }
+            })
+            .clone()
+    }
+
+    /// Scrambles a text keeping its shape: letters become x, digits 0 and the email
+    /// addresses their pseudonyms, while spaces, punctuation and line breaks are kept
+    pub fn scramble(&mut self, text: &str) -> String {
+        text.split_inclusive(char::is_whitespace)
+            .map(|word| {
+                let core = word.trim_matches(|c: char| !c.is_alphanumeric());
+                if is_email(core) {
+                    word.replacen(core, &self.name("email", &core.to_lowercase()), 1)
+                } else {
+                    word.chars()
+                        .map(|c| match c {
+                            c if c.is_uppercase() => 'X',
+                            c if c.is_alphabetic() => 'x',
+                            c if c.is_numeric() => '0',
+                            c => c,
+                        })
+                        .collect()
+                }
+            })
+            .collect()
+    }
+
+    /// Returns a copy of the event with its texts replaced by pseudonyms, and without the
+    /// position of its location and its attachments: times, durations, recurrences and the
+    /// other settings are kept
+    pub fn event(&mut self, ev: &Event) -> Event {
+        let mut anon = ev.clone();
+        anon.set_title(&self.name("Event", ev.get_title()));
+        anon.set_description(&self.scramble(ev.get_description()));
+        anon.set_location(&self.name("Location", ev.get_location()));
+        anon.set_geo(None);
+        anon.set_attachments(Vec::new());
+        anon.set_resources(
+            ev.get_resources()
+                .iter()
+                .map(|r| self.name("Resource", r))
+                .collect(),
+        );
+        anon.set_tags(
+            ev.get_metadata()
+                .get_tags()
+                .iter()
+                .map(|t| match KEPT_TAGS.contains(&t.as_str()) {
+                    true => t.clone(),
+                    false => self.name("tag", t),
+                })
+                .collect(),
+        );
+        if let Some(WorkPlace::Other(place)) = ev.get_working_location() {
+            let place = self.name("Location", place);
+            anon.set_working_location(Some(WorkPlace::Other(place)));
+        }
+        anon
+    }
+}
+
+/// Returns whether the word looks like an email address
+fn is_email(word: &str) -> bool {
+    let word = word.strip_prefix("mailto:").unwrap_or(word);
+    match word.split_once('@') {
+        Some((user, domain)) => !user.is_empty() && domain.contains('.') && !domain.contains('@'),
+        None => false,
+    }
+}
+
+/// Returns a copy of the calendar, with the events anonymized (see [`Pseudonyms::event`]) to
+/// export it safely, e.g. to reproduce a bug
+pub fn calendar(cal: &Calendar) -> Calendar {
+    let mut anon = cal.clone();
+    anon.set_owner("anonymous");
+    let mut pseudonyms = Pseudonyms::default();
+    for (eid, ev) in cal.events_by_eid() {
+        anon.insert_event(eid, pseudonyms.event(ev));
+    }
+    anon
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use crate::anonymize::{calendar, Pseudonyms};
+    use crate::calendar::Calendar;
+    use crate::event::Event;
+
+    #[test]
+    /// tests replacing the texts of the events with stable pseudonyms
+    fn test_anonymize() {
+        let mut p = Pseudonyms::default();
+        assert_eq!(
+            p.scramble("Call Anna (anna@acme.com) at 15:30,\nthen <Bob@Acme.com>"),
+            "Xxxx Xxxx (person1@example.com) xx 00:00,\nxxxx <person2@example.com>"
+        );
+        assert_eq!(
+            p.scramble("cc: ANNA@acme.com, mailto:x@y"),
+            "xx: person1@example.com, xxxxxx:x@x"
+        );
+
+        let mut cal = Calendar::new("Jane Doe", "work");
+        let mut standup = Event::new(
+            "standup",
+            "with anna@acme.com",
+            "11/07/2022",
+            "09:30",
+            1.0,
+            Some("room 1"),
+            Some("daily 10"),
+            Some(vec![String::from("team"), String::from("habit")]),
+        );
+        standup.set_resources(vec![String::from("room 1")]);
+        standup.set_duration(&Duration::minutes(15));
+        cal.insert_event(1, standup.clone());
+        let review = Event::new("review", "", "12/07/2022", "14:00", 1.0, None, None, None);
+        cal.insert_event(2, review);
+        cal.insert_event(3, standup);
+
+        let anon = calendar(&cal);
+        assert_eq!(anon.get_owner(), "anonymous");
+        let ev = |eid| anon.peek_event(eid).unwrap();
+        assert_eq!(ev(1).get_title(), "Event 1");
+        assert_eq!(ev(2).get_title(), "Event 2");
+        // the same texts get the same pseudonyms
+        assert_eq!(ev(3).get_title(), "Event 1");
+        assert_eq!(ev(1).get_description(), "xxxx person1@example.com");
+        assert_eq!(ev(1).get_location(), "Location 1");
+        assert_eq!(ev(1).get_resources(), ["Resource 1"]);
+        assert_eq!(ev(1).get_metadata().get_tags(), ["tag 1", "habit"]);
+        assert_eq!(ev(2).get_location(), "");
+        // the times are kept
+        let original = cal.peek_event(1).unwrap();
+        assert_eq!(ev(1).get_start(), original.get_start());
+        assert_eq!(ev(1).get_duration(), original.get_duration());
+        assert!(ev(1).occurrences().eq(original.occurrences()));
+    }
+}
@@ -0,0 +1,230 @@
+use std::env;
+use std::fmt::Write;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::calendar::Calendar;
+use crate::clock;
+
+/// What a modification of a calendar changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Add,
+    Update,
+    Remove,
+    /// The name or the owner of the calendar
+    Meta,
+    Availability,
+}
+
+/// A modification of a calendar: who made it and when, with the values before and after it
+/// (the whole event for the changes to events)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub time: NaiveDateTime,
+    pub user: String,
+    pub action: AuditAction,
+    /// eid of the event changed, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eid: Option<u64>,
+    #[serde(default)]
+    pub old: Option<Value>,
+    #[serde(default)]
+    pub new: Option<Value>,
+}
+
+/// Receives the modifications made to the calendars, e.g. to persist them
+pub trait ChangeListener {
+    fn changed(&mut self, calendar_id: &str, entry: &AuditEntry);
+}
+
+/// Returns the name of the user running the program, to whom the modifications are ascribed
+pub fn current_user() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| String::from("unknown"))
+}
+
+fn to_value<T: Serialize>(v: &T) -> Option<Value> {
+    serde_json::to_value(v).ok()
+}
+
+/// Computes the modifications made by `user` at `time` to turn `before` into `after`
+pub fn changes(
+    before: &Calendar,
+    after: &Calendar,
+    user: &str,
+    time: NaiveDateTime,
+) -> Vec<AuditEntry> {
+    let entry = |action, eid, old, new| AuditEntry {
+        time,
+        user: user.to_string(),
+        action,
+        eid,
+        old,
+        new,
+    };
+    let mut entries = Vec::new();
+    if before.get_name() != after.get_name() || before.get_owner() != after.get_owner() {
+        let meta = |cal: &Calendar| Some(json!({"name": cal.get_name(), "owner": cal.get_owner()}));
+        entries.push(entry(AuditAction::Meta, None, meta(before), meta(after)));
+    }
+    if before.get_availability() != after.get_availability() {
+        entries.push(entry(
+            AuditAction::Availability,
+            None,
+            to_value(&before.get_availability()),
+            to_value(&after.get_availability()),
+        ));
+    }
+    let diff = before.diff(after);
+    let event = |cal: &Calendar, eid| cal.peek_event(eid).and_then(to_value);
+    for eid in diff.removed {
+        entries.push(entry(
+            AuditAction::Remove,
+            Some(eid),
+            event(before, eid),
+            None,
+        ));
+    }
+    for eid in diff.added {
+        entries.push(entry(AuditAction::Add, Some(eid), None, event(after, eid)));
+    }
+    for eid in diff.edited {
+        entries.push(entry(
+            AuditAction::Update,
+            Some(eid),
+            event(before, eid),
+            event(after, eid),
+        ));
+    }
+    entries
+}
+
+/// Notifies the listener of the modifications made by `user` to turn `before` into `after`
+pub fn emit(before: &Calendar, after: &Calendar, user: &str, listener: &mut dyn ChangeListener) {
+    for entry in changes(before, after, user, clock::now().naive_local()) {
+        listener.changed(after.get_id(), &entry);
+    }
+}
+
+fn show(v: Option<&Value>) -> String {
+    v.map_or(String::from("-"), Value::to_string)
+}
+
+/// Describes the values that differ between `old` and `new`: only the differing fields
+/// of objects are shown
+fn describe(old: Option<&Value>, new: Option<&Value>) -> Vec<String> {
+    match (old, new) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            let mut fields: Vec<&String> = old.keys().chain(new.keys()).collect();
+            fields.sort_unstable();
+            fields.dedup();
+            fields
+                .into_iter()
+                .filter(|f| old.get(*f) != new.get(*f))
+                .map(|f| format!("{}: {} -> {}", f, show(old.get(f)), show(new.get(f))))
+                .collect()
+        }
+        (old, new) => vec![format!("{} -> {}", show(old), show(new))],
+    }
+}
+
+/// Formats the entries of an audit log, one per line followed by what changed
+pub fn render(entries: &[AuditEntry]) -> String {
+    let mut out = String::new();
+    for e in entries {
+        let _ = write!(
+            out,
+            "{} {} {:?}",
+            e.time.format("%d/%m/%Y %H:%M:%S"),
+            e.user,
+            e.action
+        );
+        if let Some(eid) = e.eid {
+            let _ = write!(out, " {}", eid);
+        }
+        let _ = writeln!(out);
+        let title = |v: Option<&Value>| v.and_then(|v| v.get("title")).cloned();
+        match e.action {
+            AuditAction::Add => {
+                if let Some(t) = title(e.new.as_ref()) {
+                    let _ = writeln!(out, "\ttitle: {}", t);
+                }
+            }
+            AuditAction::Remove => {
+                if let Some(t) = title(e.old.as_ref()) {
+                    let _ = writeln!(out, "\ttitle: {}", t);
+                }
+            }
+            _ => {
+                for line in describe(e.old.as_ref(), e.new.as_ref()) {
+                    let _ = writeln!(out, "\t{}", line);
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::audit::{changes, render, AuditAction};
+    use crate::calendar::Calendar;
+    use crate::event::Event;
+
+    #[test]
+    /// tests recording the modifications of a calendar
+    fn test_changes() {
+        let time = NaiveDate::from_ymd_opt(2022, 7, 13)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let mut before = Calendar::new("owner", "work");
+        let ev = Event::new("review", "", "13/07/2022", "09:30", 1.0, None, None, None);
+        before.add_event(ev.clone());
+        let eid = before.events_by_eid()[0].0;
+        assert!(changes(&before, &before, "alice", time).is_empty());
+
+        let mut after = before.clone();
+        after.set_name("job");
+        after.get_event(eid).unwrap().set_title("retro");
+        after.add_event(Event::new(
+            "standup",
+            "",
+            "14/07/2022",
+            "09:00",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        let entries = changes(&before, &after, "alice", time);
+        let actions: Vec<AuditAction> = entries.iter().map(|e| e.action).collect();
+        assert_eq!(
+            actions,
+            [AuditAction::Meta, AuditAction::Add, AuditAction::Update]
+        );
+        assert!(entries.iter().all(|e| e.user == "alice" && e.time == time));
+        assert_eq!(entries[2].eid, Some(eid));
+        assert_eq!(entries[2].old.as_ref().unwrap()["title"], "review");
+        assert_eq!(entries[2].new.as_ref().unwrap()["title"], "retro");
+
+        let out = render(&entries);
+        assert!(out.contains("13/07/2022 12:00:00 alice Meta"));
+        assert!(out.contains("\tname: \"work\" -> \"job\""));
+        assert!(out.contains("\ttitle: \"standup\""));
+        assert!(out.contains("\ttitle: \"review\" -> \"retro\""));
+        assert!(!out.contains("\tduration"));
+
+        let entries = changes(&after, &before, "bob", time);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1].action, AuditAction::Remove);
+        assert!(entries[1].new.is_none());
+    }
+}
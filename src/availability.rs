@@ -0,0 +1,422 @@
+use std::fmt::{self, Display, Write};
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::calendar::Calendar;
+use crate::clock;
+use crate::event::{Event, Transparency};
+use crate::{freebusy, ics, ics_writer, planner};
+
+/// Tag of the events booked through the appointment page (see [`render_booking_page`])
+pub const BOOKING_TAG: &str = "booking";
+
+/// A recurring window of time in which the owner of the calendar can be booked,
+/// e.g. office hours on tuesday and thursday from 14:00 to 17:00
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct Availability {
+    pub label: String,
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+/// Parses a list of days separated by commas, each either a single day or a range of days:
+/// e.g. "tue,thu" or "mon-fri"
+pub(crate) fn parse_days(s: &str) -> Result<Vec<Weekday>, String> {
+    let day = |d: &str| {
+        d.trim()
+            .parse::<Weekday>()
+            .map_err(|_| format!("invalid day {}", d))
+    };
+    let mut days = Vec::new();
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (mut d, last) = (day(first)?, day(last)?);
+                days.push(d);
+                while d != last {
+                    d = d.succ();
+                    days.push(d);
+                }
+            }
+            None => days.push(day(part)?),
+        }
+    }
+    days.sort_unstable_by_key(|d| d.num_days_from_monday());
+    days.dedup();
+    Ok(days)
+}
+
+/// Parses a range of hours of the same day, e.g. "14:00-17:00"
+pub(crate) fn parse_hours(hours: &str) -> Result<(NaiveTime, NaiveTime), String> {
+    let (start, end) = hours
+        .split_once('-')
+        .ok_or_else(|| format!("invalid hours {}: expected %H:%M-%H:%M", hours))?;
+    let time = |t: &str| {
+        NaiveTime::parse_from_str(t.trim(), "%H:%M").map_err(|_| format!("invalid time {}", t))
+    };
+    let (start, end) = (time(start)?, time(end)?);
+    if start >= end {
+        return Err(format!(
+            "invalid hours {}: the window must end after it starts",
+            hours
+        ));
+    }
+    Ok((start, end))
+}
+
+impl Availability {
+    /// Builds an availability window given its days ("tue,thu", "mon-fri") and
+    /// hours ("14:00-17:00")
+    pub fn parse(label: &str, days: &str, hours: &str) -> Result<Availability, String> {
+        let (start, end) = parse_hours(hours)?;
+        Ok(Availability {
+            label: label.to_string(),
+            days: parse_days(days)?,
+            start,
+            end,
+        })
+    }
+
+    /// Returns the windows of time on the days from `first` to `last` (included)
+    pub fn windows(
+        &self,
+        first: NaiveDate,
+        last: NaiveDate,
+    ) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        first
+            .iter_days()
+            .take_while(|d| *d <= last)
+            .filter(|d| self.days.contains(&d.weekday()))
+            .map(|d| (d.and_time(self.start), d.and_time(self.end)))
+            .collect()
+    }
+}
+
+impl Display for Availability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days: Vec<String> = self.days.iter().map(|d| d.to_string()).collect();
+        write!(
+            f,
+            "{}: {} {}-{}",
+            self.label,
+            days.join(","),
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+/// Computes the slots of the availability windows of the calendar on the days from `first`
+/// to `last` that are still bookable after `now`, i.e. not taken by busy events
+pub fn bookable_slots(
+    cal: &Calendar,
+    first: NaiveDate,
+    last: NaiveDate,
+    now: NaiveDateTime,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let windows = cal
+        .get_availability()
+        .iter()
+        .flat_map(|a| a.windows(first, last))
+        .collect();
+    // overlapping windows are merged, not to offer the same time twice
+    let merged = freebusy::merge(windows);
+    planner::subtract_busy(cal, merged, now)
+}
+
+/// Splits the bookable slots into back-to-back appointments of the given duration, starting on
+/// multiples of it since midnight
+pub fn appointments(
+    slots: &[(NaiveDateTime, NaiveDateTime)],
+    duration: Duration,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let step = duration.num_seconds().max(1);
+    let mut appointments = Vec::new();
+    for (start, end) in slots {
+        let since_midnight = start.num_seconds_from_midnight() as i64;
+        let mut t = *start + Duration::seconds((step - since_midnight % step) % step);
+        while t + duration <= *end {
+            appointments.push((t, t + duration));
+            t += duration;
+        }
+    }
+    appointments
+}
+
+/// Renders a standalone HTML page listing the bookable slots of the calendar, that can be
+/// shared without revealing the events in it
+pub fn render_html(cal: &Calendar, slots: &[(NaiveDateTime, NaiveDateTime)]) -> String {
+    let title = format!("Availability of {}", cal.get_owner());
+    render_page(&title, "", slots, |start, end| {
+        format!("{}-{}", start.format("%H:%M"), end.format("%H:%M"))
+    })
+}
+
+/// Renders a standalone HTML page listing the appointments that can be booked in the
+/// calendar: each one links to an invitation (see [`booking_request`]) to be sent back to
+/// the owner, who accepts it
+pub fn render_booking_page(
+    cal: &Calendar,
+    appointments: &[(NaiveDateTime, NaiveDateTime)],
+) -> String {
+    let title = format!("Book an appointment with {}", cal.get_owner());
+    let intro = format!(
+        "Download the invitation of the time you prefer and send it to {}: the appointment \
+        is booked once accepted.",
+        cal.get_owner()
+    );
+    render_page(&title, &intro, appointments, |start, end| {
+        format!(
+            "<a download=\"appointment-{}.ics\" href=\"data:text/calendar;charset=utf-8,{}\">{}-{}</a>",
+            start.format("%Y%m%dT%H%M"),
+            percent_encode(&booking_request(cal, start, end)),
+            start.format("%H:%M"),
+            end.format("%H:%M")
+        )
+    })
+}
+
+/// Renders an HTML page listing the slots by day, each as the given HTML
+fn render_page(
+    title: &str,
+    intro: &str,
+    slots: &[(NaiveDateTime, NaiveDateTime)],
+    item: impl Fn(NaiveDateTime, NaiveDateTime) -> String,
+) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{0}</title></head>\n\
+        <body>\n<h1>{0}</h1>\n",
+        escape_html(title)
+    );
+    if !intro.is_empty() {
+        let _ = writeln!(out, "<p>{}</p>", escape_html(intro));
+    }
+    if slots.is_empty() {
+        out.push_str("<p>No available slots</p>\n");
+    }
+    let mut day: Option<NaiveDate> = None;
+    for (start, end) in slots {
+        if day != Some(start.date()) {
+            if day.is_some() {
+                out.push_str("</ul>\n");
+            }
+            let _ = writeln!(out, "<h2>{}</h2>\n<ul>", start.format("%A %d/%m/%Y"));
+            day = Some(start.date());
+        }
+        let _ = writeln!(out, "<li>{}</li>", item(*start, *end));
+    }
+    if day.is_some() {
+        out.push_str("</ul>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Generates the iCalendar invitation booking an appointment in the calendar: the owner adds
+/// the event by accepting it (see [`accept_booking`])
+pub fn booking_request(cal: &Calendar, start: NaiveDateTime, end: NaiveDateTime) -> String {
+    let fmt = "%Y%m%dT%H%M%S";
+    ics::vcalendar(vec![vec![
+        String::from("BEGIN:VEVENT"),
+        format!("UID:appointment-{}-{}", cal.get_id(), start.format(fmt)),
+        format!(
+            "DTSTAMP:{}",
+            clock::now().with_timezone(&Utc).format("%Y%m%dT%H%M%SZ")
+        ),
+        format!("DTSTART:{}", start.format(fmt)),
+        format!("DTEND:{}", end.format(fmt)),
+        format!(
+            "SUMMARY:{}",
+            ics_writer::escape_text(&format!("Appointment with {}", cal.get_owner()))
+        ),
+        String::from("END:VEVENT"),
+    ]])
+}
+
+/// Adds an appointment requested with an invitation to the calendar, tagged as a booking,
+/// if it is still within one of the `bookable` slots. Returns its eid
+pub fn accept_booking(
+    cal: &mut Calendar,
+    mut ev: Event,
+    bookable: &[(NaiveDateTime, NaiveDateTime)],
+) -> Result<u64, String> {
+    let (start, end) = (ev.get_start(), ev.occurrence_end(ev.get_start()));
+    if ev.get_recurrence().is_some() {
+        return Err(String::from("recurrent appointments cannot be booked"));
+    }
+    if !bookable.iter().any(|(s, e)| *s <= start && end <= *e) {
+        return Err(format!(
+            "{}-{} is not bookable anymore",
+            start.format("%d/%m/%Y %H:%M"),
+            end.format("%H:%M")
+        ));
+    }
+    let mut tags = ev.get_metadata().get_tags();
+    if !tags.iter().any(|t| t == BOOKING_TAG) {
+        tags.push(String::from(BOOKING_TAG));
+    }
+    ev.set_tags(tags);
+    ev.set_transparency(Transparency::Busy);
+    cal.add_event(ev)
+        .added
+        .ok_or_else(|| String::from("already booked"))
+}
+
+/// Encodes the characters that cannot appear in a URL
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => {
+                let _ = write!(out, "%{:02X}", b);
+            }
+        }
+    }
+    out
+}
+
+/// Escapes the characters with a special meaning in HTML, in texts and attribute values
+pub fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, Weekday};
+
+    use crate::availability::{
+        accept_booking, appointments, bookable_slots, parse_days, render_booking_page, render_html,
+        Availability, BOOKING_TAG,
+    };
+    use crate::calendar::Calendar;
+    use crate::event::Event;
+    use crate::testing::at;
+
+    #[test]
+    /// tests parsing availability declarations
+    fn test_parse() {
+        assert_eq!(parse_days("thu,tue").unwrap(), [Weekday::Tue, Weekday::Thu]);
+        assert_eq!(parse_days("fri-mon").unwrap().len(), 4);
+        assert!(parse_days("tue,someday").is_err());
+        let a = Availability::parse("office hours", "tue,thu", "14:00-17:00").unwrap();
+        assert_eq!(a.to_string(), "office hours: Tue,Thu 14:00-17:00");
+        assert!(Availability::parse("x", "tue", "17:00-14:00").is_err());
+        assert!(Availability::parse("x", "tue", "14:00").is_err());
+    }
+
+    #[test]
+    /// tests computing the bookable slots, around the events
+    fn test_bookable_slots() {
+        let mut cal = Calendar::new("owner", "work");
+        cal.add_availability(
+            Availability::parse("office hours", "tue,thu", "14:00-17:00").unwrap(),
+        );
+        cal.add_availability(Availability::parse("extra", "thu", "16:00-18:00").unwrap());
+        cal.add_event(Event::new(
+            "meeting",
+            "",
+            "12/07/2022",
+            "15:00",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        let first = NaiveDate::from_ymd_opt(2022, 7, 11).unwrap();
+        let last = NaiveDate::from_ymd_opt(2022, 7, 17).unwrap();
+        let slots = bookable_slots(&cal, first, last, at(11, 0, 0));
+        assert_eq!(
+            slots,
+            [
+                (at(12, 14, 0), at(12, 15, 0)),
+                (at(12, 16, 0), at(12, 17, 0)),
+                (at(14, 14, 0), at(14, 18, 0)),
+            ]
+        );
+        let html = render_html(&cal, &slots);
+        assert_eq!(html.matches("<li>").count(), 3);
+        assert!(!html.contains("meeting"));
+        let html = render_booking_page(&Calendar::new("O'Brien & <co>", "work"), &[]);
+        assert!(html.contains("with O&#39;Brien &amp; &lt;co&gt;</h1>"));
+    }
+
+    #[test]
+    /// tests splitting the bookable slots into appointments, and booking them
+    fn test_booking() {
+        let mut cal = Calendar::new("owner", "work");
+        cal.add_availability(Availability::parse("office hours", "tue", "14:00-17:00").unwrap());
+        cal.add_event(Event::new(
+            "meeting",
+            "",
+            "12/07/2022",
+            "15:10",
+            0.0,
+            None,
+            None,
+            None,
+        ));
+        cal.update_event(cal.events_by_eid()[0].0, |ev| {
+            ev.set_duration(&Duration::minutes(20))
+        })
+        .unwrap();
+        let day = NaiveDate::from_ymd_opt(2022, 7, 12).unwrap();
+        let slots = bookable_slots(&cal, day, day, at(11, 0, 0));
+        let times = appointments(&slots, Duration::minutes(30));
+        assert_eq!(
+            times,
+            [
+                (at(12, 14, 0), at(12, 14, 30)),
+                (at(12, 14, 30), at(12, 15, 0)),
+                (at(12, 15, 30), at(12, 16, 0)),
+                (at(12, 16, 0), at(12, 16, 30)),
+                (at(12, 16, 30), at(12, 17, 0)),
+            ]
+        );
+        let html = render_booking_page(&cal, &times);
+        assert_eq!(html.matches("href=\"data:text/calendar").count(), 5);
+        assert!(html.contains("DTSTART%3A20220712T143000"));
+        assert!(!html.contains("meeting"));
+
+        let request = |h, m| {
+            let mut ev = Event::new(
+                "Appointment with owner",
+                "",
+                "12/07/2022",
+                "",
+                0.0,
+                None,
+                None,
+                None,
+            );
+            ev.set_start_time((h, m, 0));
+            ev.set_duration(&Duration::minutes(30));
+            ev
+        };
+        let eid = accept_booking(&mut cal, request(14, 30), &slots).unwrap();
+        assert!(cal.peek_event(eid).unwrap().has_tag(BOOKING_TAG));
+        // taken by the meeting
+        assert!(accept_booking(&mut cal, request(15, 0), &slots).is_err());
+        // once booked, the appointment is not offered anymore
+        let slots = bookable_slots(&cal, day, day, at(11, 0, 0));
+        assert!(accept_booking(&mut cal, request(14, 30), &slots).is_err());
+        assert_eq!(appointments(&slots, Duration::minutes(30)).len(), 4);
+    }
+}
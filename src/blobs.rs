@@ -0,0 +1,68 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::calendar_error::CalendarError;
+use crate::users::hex;
+
+/// Directory of the contents of the attachments inside the data directory, beside the
+/// calendars: each one is stored once, in a file named after its hash
+pub const BLOBS_DIR: &str = "blobs";
+
+fn io_error(path: &Path, e: impl std::fmt::Display) -> CalendarError {
+    CalendarError::Unknown(format!("{}: {}", path.display(), e))
+}
+
+fn blob_path(hash: &str, data_dir: &Path) -> Result<PathBuf, CalendarError> {
+    if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(CalendarError::Unknown(format!(
+            "Invalid blob hash {}",
+            hash
+        )));
+    }
+    Ok(data_dir.join(BLOBS_DIR).join(hash.to_lowercase()))
+}
+
+/// Stores the data in the data directory, unless already there, returning its hash
+pub fn store(data: &[u8], data_dir: &Path) -> Result<String, CalendarError> {
+    let hash = hex(&Sha256::digest(data));
+    let path = blob_path(&hash, data_dir)?;
+    if !path.exists() {
+        let dir = data_dir.join(BLOBS_DIR);
+        fs::create_dir_all(&dir).map_err(|e| io_error(&dir, e))?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| io_error(&path, e))?;
+    }
+    Ok(hash)
+}
+
+/// Reads the data with the given hash
+pub fn load(hash: &str, data_dir: &Path) -> Result<Vec<u8>, CalendarError> {
+    let path = blob_path(hash, data_dir)?;
+    fs::read(&path).map_err(|e| io_error(&path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::blobs::{load, store, BLOBS_DIR};
+    use crate::testing::TempDir;
+
+    #[test]
+    /// tests storing the same data once and reading it back by hash
+    fn test_store_load() {
+        let dir = TempDir::new("blobs");
+        let hash = store(b"minutes", &dir).unwrap();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(store(b"minutes", &dir).unwrap(), hash);
+        assert_ne!(store(b"agenda", &dir).unwrap(), hash);
+        assert_eq!(fs::read_dir(dir.join(BLOBS_DIR)).unwrap().count(), 2);
+        assert_eq!(load(&hash, &dir).unwrap(), b"minutes");
+        assert!(load("../../etc/passwd", &dir).is_err());
+        assert!(load(&"0".repeat(64), &dir).is_err());
+    }
+}
@@ -1,48 +1,201 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt::{Debug, Display};
-use std::hash::{Hash, Hasher};
+use std::sync::mpsc::{self, Receiver, Sender};
 
-use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
-use log::warn;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
 use serde::{Deserialize, Serialize};
 
+use crate::availability::Availability;
 use crate::calendar_error::CalendarError;
-use crate::event::{Cadence, Event, Recurrence};
+use crate::clock;
+use crate::event::Event;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// Maximum length (in bytes) of a calendar id, and hence of a calendar file stem
+pub const MAX_ID_LEN: usize = 64;
+
+/// Version of the format calendars are saved in, increased on every incompatible change.
+/// Version 1 stores event durations in seconds instead of minutes
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Calendar {
+    /// Version of the format of the file the calendar was read from (0 if unversioned)
+    #[serde(default)]
+    version: u32,
+    /// Stable identifier of the calendar, used for its file name and in CLI references
+    #[serde(default)]
+    id: String,
     owner: String,
     name: String,
     events: HashMap<u64, Event>,
+    /// When the owner of the calendar can be booked
+    #[serde(default)]
+    availability: Vec<Availability>,
+    /// Dates of the holiday calendars referenced by the recurrences of the events,
+    /// by calendar id: not saved, loaded along with the calendar
+    #[serde(skip)]
+    holidays: HashMap<String, BTreeSet<NaiveDate>>,
+    /// Whether the calendar was read from a file in an older format and migrated in memory
+    /// only, so that its file is rewritten at the next save: not saved
+    #[serde(skip)]
+    migrated: bool,
+    /// Receivers of the changes to the events: not saved, cloned nor compared
+    #[serde(skip)]
+    subscribers: Subscribers,
 }
 
-/// Given a recurrence and starting date and time, computes the dates and times
-/// of the recurrences of the event and returns them as a vector
-fn expand_recurrence(rec: &Recurrence, dt: &NaiveDate, tm: &NaiveTime) -> Vec<NaiveDateTime> {
-    let mut rec_dates = Vec::new();
-    for i in 0..=rec.repetitions() {
-        let x = NaiveDateTime::new(*dt, *tm);
-        let dt_new = match rec.cadence() {
-            Cadence::Secondly => x + Duration::seconds(i as i64),
-            Cadence::Minutely => x + Duration::minutes(i as i64),
-            Cadence::Hourly => x.checked_add_signed(Duration::hours(i as i64)).unwrap(),
-            Cadence::Daily => x.checked_add_signed(Duration::days(i as i64)).unwrap(),
-            Cadence::Weekly => x.checked_add_signed(Duration::weeks(i as i64)).unwrap(),
-            Cadence::Monthly => x.with_month(dt.month() + i as u32).unwrap(),
-            Cadence::Yearly => x.with_year(dt.year() + i as i32).unwrap(),
-        };
-        rec_dates.push(dt_new);
+/// A change to an event of a calendar, as sent to its subscribers (see [`Calendar::subscribe`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalendarChange {
+    Added(u64),
+    Removed(u64),
+    /// The event with this eid was modified, or replaced by another one
+    Updated(u64),
+}
+
+/// The subscribers of a calendar, that are not part of its contents: a clone of the calendar
+/// has none, and they do not affect its equality
+#[derive(Default)]
+struct Subscribers(Vec<Sender<CalendarChange>>);
+
+impl Clone for Subscribers {
+    fn clone(&self) -> Self {
+        Subscribers::default()
+    }
+}
+
+impl PartialEq for Subscribers {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Debug for Subscribers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} subscribers", self.0.len())
+    }
+}
+
+/// What adding an event to a calendar did (see [`Calendar::add_event`])
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddOutcome {
+    /// The eid of the event, if added
+    pub added: Option<u64>,
+    /// The eids of the identical events already in the calendar, if not added
+    pub duplicates: Vec<u64>,
+    /// The eids of the events the added one overlaps with
+    pub overlaps: Vec<u64>,
+}
+
+impl AddOutcome {
+    pub fn is_added(&self) -> bool {
+        self.added.is_some()
+    }
+}
+
+/// A dependency of an event on another one that is not met (see
+/// [`Calendar::broken_dependencies`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrokenDependency {
+    /// The eid of the event depending on the other one
+    pub eid: u64,
+    /// The eid of the event it depends on
+    pub prerequisite: u64,
+    /// When the event it depends on ends, after the start of the event, or None if it was
+    /// removed
+    pub prerequisite_end: Option<NaiveDateTime>,
+}
+
+/// Changes to the events of a calendar, as computed by [`Calendar::diff`]
+#[derive(Debug, Default, PartialEq)]
+pub struct CalendarDiff {
+    /// eids of the events only in the new calendar
+    pub added: Vec<u64>,
+    /// eids of the events only in the old calendar
+    pub removed: Vec<u64>,
+    /// eids of the events in both calendars, but with different contents
+    pub edited: Vec<u64>,
+}
+
+impl CalendarDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.edited.is_empty()
+    }
+}
+
+/// Builds an identifier suitable for file names and CLI references out of a
+/// (possibly unicode, with spaces) display name: alphanumeric ascii characters are
+/// lowercased and kept, any other run of characters is replaced by a single '-'.
+/// The result is truncated to the maximum id length accepted by the storage
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if slug.len() == MAX_ID_LEN {
+            break;
+        }
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        String::from("calendar")
+    } else {
+        slug.to_string()
     }
-    rec_dates
 }
 
 impl Calendar {
     pub fn new(owner_name: &str, calendar_name: &str) -> Calendar {
         Calendar {
+            version: FORMAT_VERSION,
+            id: slugify(calendar_name),
             owner: String::from(owner_name),
             name: String::from(calendar_name),
             events: HashMap::new(),
+            availability: Vec::new(),
+            holidays: HashMap::new(),
+            migrated: false,
+            subscribers: Subscribers::default(),
+        }
+    }
+
+    /// Returns the version of the format this calendar is stored in
+    pub fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    /// Upgrades a calendar read from a file in an older format to the current one.
+    /// Returns whether the calendar was changed, and thus needs to be saved again
+    /// (see [`Calendar::is_migrated`])
+    pub fn migrate(&mut self) -> bool {
+        if self.version >= FORMAT_VERSION {
+            return false;
         }
+        if self.version < 1 {
+            // durations were stored in minutes, but have been read as seconds
+            for ev in self.events.values_mut() {
+                let minutes = ev.get_duration();
+                ev.set_duration(&Duration::minutes(minutes));
+            }
+        }
+        self.version = FORMAT_VERSION;
+        self.migrated = true;
+        true
+    }
+
+    /// Returns whether the calendar was migrated from an older format since it was read, and
+    /// its file is still in that format
+    pub fn is_migrated(&self) -> bool {
+        self.migrated
+    }
+
+    /// Returns the stable identifier of this calendar
+    pub fn get_id(&self) -> &str {
+        &self.id
     }
 
     pub fn get_owner(&self) -> &str {
@@ -57,6 +210,11 @@ impl Calendar {
         self.events.len()
     }
 
+    /// Sets the identifier of this calendar. The display name is left untouched
+    pub fn set_id(&mut self, s: &str) {
+        self.id = String::from(s);
+    }
+
     pub fn set_owner(&mut self, s: &str) {
         self.owner = String::from(s);
     }
@@ -65,8 +223,89 @@ impl Calendar {
         self.name = String::from(s);
     }
 
+    /// Returns the windows of time in which the owner of the calendar can be booked
+    pub fn get_availability(&self) -> &[Availability] {
+        &self.availability
+    }
+
+    pub fn add_availability(&mut self, window: Availability) {
+        self.availability.push(window);
+    }
+
+    /// Removes the availability window at the given position, if any
+    pub fn remove_availability(&mut self, index: usize) -> Option<Availability> {
+        (index < self.availability.len()).then(|| self.availability.remove(index))
+    }
+
+    pub fn set_availability(&mut self, windows: Vec<Availability>) {
+        self.availability = windows;
+    }
+
+    /// Returns the ids of the holiday calendars referenced by the recurrences of the events
+    pub fn holiday_calendars(&self) -> BTreeSet<String> {
+        self.events
+            .values()
+            .filter_map(|ev| ev.get_recurrence()?.holidays()?.calendar.clone())
+            .collect()
+    }
+
+    /// Sets the dates of the holiday calendar with the given id
+    pub fn set_holidays(&mut self, calendar: &str, dates: BTreeSet<NaiveDate>) {
+        self.holidays.insert(calendar.to_string(), dates);
+    }
+
+    /// Returns the dates of the holiday calendar of the recurrence of the event, if it has one
+    /// and they are known
+    pub fn holidays_of(&self, ev: &Event) -> Option<&BTreeSet<NaiveDate>> {
+        ev.get_recurrence()
+            .and_then(|rec| rec.holidays()?.calendar.as_ref())
+            .and_then(|id| self.holidays.get(id))
+    }
+
+    /// Returns the days on which some event of this calendar takes place, as the holidays
+    /// of the calendars referencing it
+    pub fn event_days(&self) -> BTreeSet<NaiveDate> {
+        let mut days = BTreeSet::new();
+        for ev in self.events.values() {
+            for start in ev.occurrences() {
+                // an event ending at midnight does not take the next day
+                let last = (ev.occurrence_end(start) - Duration::seconds(1)).max(start);
+                days.extend(start.date().iter_days().take_while(|d| *d <= last.date()));
+            }
+        }
+        days
+    }
+
+    /// Copies the settings of another calendar (currently its availability) and, if
+    /// `with_events`, its events with the same eids
+    pub fn copy_from(&mut self, other: &Calendar, with_events: bool) {
+        self.availability = other.availability.clone();
+        if with_events {
+            for (eid, ev) in other.events.iter() {
+                self.insert_event(*eid, ev.clone());
+            }
+        }
+    }
+
     pub fn clear(&mut self) {
-        self.events.clear();
+        let eids: Vec<u64> = self.events.drain().map(|(eid, _)| eid).collect();
+        for eid in eids {
+            self.publish(CalendarChange::Removed(eid));
+        }
+    }
+
+    /// Returns a receiver of the changes made to the events from now on. The changes made
+    /// through [`Calendar::get_event`] are not sent: use [`Calendar::update_event`] instead.
+    /// The receiver is disconnected when the calendar is dropped
+    pub fn subscribe(&mut self) -> Receiver<CalendarChange> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.0.push(tx);
+        rx
+    }
+
+    /// Sends the change to the subscribers, forgetting the ones that dropped their receiver
+    fn publish(&mut self, change: CalendarChange) {
+        self.subscribers.0.retain(|tx| tx.send(change).is_ok());
     }
 
     pub fn get_event(&mut self, eid: u64) -> Result<&mut Event, CalendarError> {
@@ -77,99 +316,394 @@ impl Calendar {
         }
     }
 
-    pub fn add_event(&mut self, ev: Event) -> bool {
-        let mut h = std::collections::hash_map::DefaultHasher::new();
-        ev.hash(&mut h);
-        let ev_hash = h.finish();
-        if self.events.contains_key(&ev_hash) {
-            warn!(
-                "Event with hash {} already in this calendar: calendar not modified",
-                ev_hash
-            );
-            eprintln!(
-                "Event \"{}\" already in this calendar: calendar not modified",
-                ev.get_title()
-            );
-            return false;
-        }
-        // Warn the user if this event overlaps with some other event
-        for e in self.events.values() {
-            if e.overlaps(&ev) {
-                e.hash(&mut h);
-                let e_hash = h.finish();
-                warn!(
-                    "Warning: the event {} overlaps with event {}",
-                    ev_hash, e_hash
-                );
-                eprintln!(
-                    "Warning: the event \"{}\" overlaps with event \"{}\"",
-                    ev.get_title(),
-                    e.get_title()
-                );
+    /// Returns a random eid not used by any event of this calendar. Eids are stored along
+    /// with the events, so they never depend on their contents
+    fn new_eid(&self) -> u64 {
+        loop {
+            let eid = rand::random();
+            if !self.events.contains_key(&eid) {
+                return eid;
             }
         }
-        self.events.insert(ev_hash, ev);
-        true
     }
 
-    /// Removes an event, given its hash
+    /// Modifies the event with the given eid through `f`, returning its result. If the event
+    /// changed, its modification time is updated and its revision increased
+    pub fn update_event<R>(
+        &mut self,
+        eid: u64,
+        f: impl FnOnce(&mut Event) -> R,
+    ) -> Result<R, CalendarError> {
+        let ev = self.get_event(eid)?;
+        let before = ev.clone();
+        let result = f(ev);
+        if *ev != before {
+            ev.touch();
+            self.publish(CalendarChange::Updated(eid));
+        }
+        Ok(result)
+    }
+
+    /// Adds an event with a new eid, unless an identical one is already in the calendar.
+    /// Returns its eid, or the ones of the duplicates, and the events it overlaps with
+    pub fn add_event(&mut self, ev: Event) -> AddOutcome {
+        let mut duplicates: Vec<u64> = self
+            .events
+            .iter()
+            .filter(|(_, e)| **e == ev)
+            .map(|(eid, _)| *eid)
+            .collect();
+        if !duplicates.is_empty() {
+            duplicates.sort_unstable();
+            return AddOutcome {
+                duplicates,
+                ..AddOutcome::default()
+            };
+        }
+        let ev_eid = self.new_eid();
+        let mut overlaps: Vec<u64> = self
+            .events
+            .iter()
+            .filter(|(_, e)| e.overlaps(&ev))
+            .map(|(eid, _)| *eid)
+            .collect();
+        overlaps.sort_unstable();
+        self.events.insert(ev_eid, ev);
+        self.publish(CalendarChange::Added(ev_eid));
+        AddOutcome {
+            added: Some(ev_eid),
+            duplicates,
+            overlaps,
+        }
+    }
+
+    /// Inserts an event with the given eid, replacing any event with the same eid
+    pub(crate) fn insert_event(&mut self, eid: u64, ev: Event) {
+        let change = match self.events.insert(eid, ev) {
+            Some(_) => CalendarChange::Updated(eid),
+            None => CalendarChange::Added(eid),
+        };
+        self.publish(change);
+    }
+
+    /// Removes an event, given its eid
     pub fn remove_event(&mut self, eid: u64) -> Result<Event, CalendarError> {
         match self.events.remove(&eid) {
-            Some(event) => Ok(event),
+            Some(event) => {
+                self.publish(CalendarChange::Removed(eid));
+                Ok(event)
+            }
             None => Err(CalendarError::EventNotFound(eid)),
         }
     }
 
-    /// TODO: provide some helpers like before
+    /// Returns the occurrences of the events overlapping with the interval, sorted by their
+    /// start: the ones started before `from` are included if still ongoing, e.g. the second
+    /// day of a conference
     pub fn list_events_between(
         &self,
         from: Option<NaiveDateTime>,
         until: Option<NaiveDateTime>,
-    ) -> Vec<Event> {
-        let mut events_between = Vec::new();
+    ) -> Vec<Cow<'_, Event>> {
+        self.list_occurrences_between(from, until)
+            .into_iter()
+            .map(|(_, ev)| ev)
+            .collect()
+    }
+
+    /// Returns the occurrences of the events overlapping with the interval, as
+    /// [`Calendar::list_events_between`], each with the eid of its event
+    pub fn list_occurrences_between(
+        &self,
+        from: Option<NaiveDateTime>,
+        until: Option<NaiveDateTime>,
+    ) -> Vec<(u64, Cow<'_, Event>)> {
+        // (start of the occurrence, eid, event, whether the occurrence is a recurrence of the
+        // event): the events are cloned only after having been selected and sorted
+        let mut events_between: Vec<(NaiveDateTime, u64, &Event, bool)> = Vec::new();
         let from_dt = from.unwrap_or(NaiveDateTime::MIN);
         let until_dt = until.unwrap_or(NaiveDateTime::MAX);
 
-        for ev in self.events.values() {
-            let ev_dt = ev.get_start_date().and_time(ev.get_start_time());
+        for (eid, ev) in self.events.iter() {
+            let ev_dt = ev.get_start();
+            if ev_dt > until_dt {
+                continue;
+            }
             // If the event is recurrent then expand its recurrent dates
             // if any of those is equal to the current then add the modified event to output vec
-            if let Some(rec) = ev.get_recurrence() {
-                for rec_dt in expand_recurrence(rec, &ev.get_start_date(), &ev.get_start_time()) {
-                    if rec_dt >= from_dt && rec_dt <= until_dt {
-                        // Since cloning is expensive it is done only on recurrences that should appear
-                        // in the output vector
-                        let mut ev2 = ev.clone();
-                        ev2.set_start_date((rec_dt.day(), rec_dt.month(), rec_dt.year()));
-                        ev2.set_start_time((rec_dt.hour(), rec_dt.minute(), rec_dt.second()));
-                        events_between.push(ev2);
-                    }
+            let recurrent = ev.get_recurrence().is_some();
+            for dt in ev.occurrences_avoiding(self.holidays_of(ev)) {
+                if dt > until_dt {
+                    break;
+                }
+                if dt >= from_dt || ev.occurrence_end(dt) > from_dt {
+                    events_between.push((dt, *eid, ev, recurrent));
                 }
-            } else if ev_dt <= until_dt && ev_dt >= from_dt {
-                events_between.push(ev.clone());
             }
         }
         // sorts events by their start date and then start time
-        events_between.sort_unstable_by(|e1, e2| {
-            if e1.get_start_date().cmp(&e2.get_start_date()) == core::cmp::Ordering::Equal {
-                e1.get_start_time().cmp(&e2.get_start_time())
-            } else {
-                e1.get_start_date().cmp(&e2.get_start_date())
-            }
-        });
+        events_between.sort_unstable_by_key(|(dt, _, _, _)| *dt);
         events_between
+            .into_iter()
+            .map(|(dt, eid, ev, recurrence)| {
+                if recurrence {
+                    // Since cloning is expensive it is done only on recurrences that should appear
+                    // in the output vector
+                    let mut ev2 = ev.clone();
+                    ev2.set_start_date((dt.day(), dt.month(), dt.year()));
+                    ev2.set_start_time((dt.hour(), dt.minute(), dt.second()));
+                    (eid, Cow::Owned(ev2))
+                } else {
+                    (eid, Cow::Borrowed(ev))
+                }
+            })
+            .collect()
+    }
+
+    /// Computes the changes needed to turn this calendar into `other`
+    pub fn diff(&self, other: &Calendar) -> CalendarDiff {
+        let mut diff = CalendarDiff::default();
+        for (eid, ev) in other.events.iter() {
+            match self.events.get(eid) {
+                None => diff.added.push(*eid),
+                Some(old) if old != ev => diff.edited.push(*eid),
+                _ => (),
+            }
+        }
+        for eid in self.events.keys() {
+            if !other.events.contains_key(eid) {
+                diff.removed.push(*eid);
+            }
+        }
+        diff.added.sort_unstable();
+        diff.removed.sort_unstable();
+        diff.edited.sort_unstable();
+        diff
+    }
+
+    /// Returns the events along with their eids, sorted by eid
+    pub fn events_by_eid(&self) -> Vec<(u64, &Event)> {
+        let mut events: Vec<(u64, &Event)> =
+            self.events.iter().map(|(eid, ev)| (*eid, ev)).collect();
+        events.sort_unstable_by_key(|(eid, _)| *eid);
+        events
+    }
+
+    /// Returns the eid of an event as listed by this calendar: either a stored event or an
+    /// occurrence of a recurrent one
+    pub fn eid_of(&self, ev: &Event) -> Option<u64> {
+        self.events.iter().find_map(|(eid, e)| {
+            if e == ev {
+                return Some(*eid);
+            }
+            // the occurrences only differ from the event in their start
+            let start = e.get_start();
+            let mut first = ev.clone();
+            first.set_start_date((start.day(), start.month(), start.year()));
+            first.set_start_time((start.hour(), start.minute(), start.second()));
+            (e.get_recurrence().is_some() && first == *e).then_some(*eid)
+        })
+    }
+
+    /// Returns the eid of the event imported with the given UID, if any: either the UID of the
+    /// event in the calendar it was imported from, or its eid if exported by this calendar
+    pub fn eid_by_uid(&self, uid: &str) -> Option<u64> {
+        self.events
+            .iter()
+            .find_map(|(eid, ev)| (ev.get_uid() == Some(uid)).then_some(*eid))
+            .or_else(|| uid.parse().ok().filter(|eid| self.events.contains_key(eid)))
+    }
+
+    /// Joins two events into the one starting first, removing the other (see [`Event::joined`]).
+    /// Returns the eid of the joined event
+    pub fn join_events(
+        &mut self,
+        eid1: u64,
+        eid2: u64,
+        tolerance: Duration,
+    ) -> Result<u64, CalendarError> {
+        if eid1 == eid2 {
+            return Err(CalendarError::Unknown(String::from(
+                "an event cannot be joined with itself",
+            )));
+        }
+        let ev1 = self
+            .peek_event(eid1)
+            .ok_or(CalendarError::EventNotFound(eid1))?;
+        let ev2 = self
+            .peek_event(eid2)
+            .ok_or(CalendarError::EventNotFound(eid2))?;
+        let (keep, first, other, second) = match ev2.get_start() < ev1.get_start() {
+            true => (eid2, ev2, eid1, ev1),
+            false => (eid1, ev1, eid2, ev2),
+        };
+        let joined = first.joined(second, tolerance)?;
+        self.update_event(keep, |ev| *ev = joined)?;
+        self.remove_event(other)?;
+        Ok(keep)
+    }
+
+    /// Moves the events with the given eids by `offset`, forward or backward if negative
+    /// (see [`Event::move_by`]): all of them, or none if one is missing or cannot be moved
+    pub fn move_events(&mut self, eids: &[u64], offset: Duration) -> Result<(), CalendarError> {
+        let mut moved = Vec::with_capacity(eids.len());
+        for eid in eids {
+            let mut ev = self
+                .peek_event(*eid)
+                .ok_or(CalendarError::EventNotFound(*eid))?
+                .clone();
+            if !ev.move_by(offset) {
+                return Err(CalendarError::Unknown(format!(
+                    "event {} cannot be moved out of the range of the dates",
+                    eid
+                )));
+            }
+            moved.push((*eid, ev));
+        }
+        for (eid, ev) in moved {
+            self.update_event(eid, |old| *old = ev)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the dependencies of the events (see [`Event::dependencies`]) that are not met,
+    /// by eid: the events depended on that were removed, or whose first occurrence ends after
+    /// the start of the first occurrence of the event depending on them
+    pub fn broken_dependencies(&self) -> Vec<BrokenDependency> {
+        let mut broken = Vec::new();
+        for (eid, ev) in self.events_by_eid() {
+            for prerequisite in ev.dependencies() {
+                let prerequisite_end = match self.peek_event(prerequisite) {
+                    Some(other) => {
+                        let end = other.occurrence_end(other.get_start());
+                        if end <= ev.get_start() {
+                            continue;
+                        }
+                        Some(end)
+                    }
+                    None => None,
+                };
+                broken.push(BrokenDependency {
+                    eid,
+                    prerequisite,
+                    prerequisite_end,
+                });
+            }
+        }
+        broken
+    }
+
+    /// Returns the eids of the events scheduled after the ones with the given eids (see
+    /// [`Event::get_follows`]), directly or through other events, excluding those eids
+    pub fn followers(&self, eids: &[u64]) -> Vec<u64> {
+        let mut found: BTreeSet<u64> = eids.iter().copied().collect();
+        let mut followers = Vec::new();
+        let mut grown = true;
+        while grown {
+            grown = false;
+            for (eid, ev) in self.events_by_eid() {
+                let after = ev.get_follows().is_some_and(|f| found.contains(&f.eid));
+                if after && found.insert(eid) {
+                    followers.push(eid);
+                    grown = true;
+                }
+            }
+        }
+        followers
+    }
+
+    /// Returns the starts of the occurrences of the event with the given eid until `until`
+    /// (included), skipping the holidays of its recurrence
+    pub fn occurrences_until(&self, eid: u64, until: NaiveDateTime) -> Vec<NaiveDateTime> {
+        let Some(ev) = self.events.get(&eid) else {
+            return Vec::new();
+        };
+        ev.occurrences_avoiding(self.holidays_of(ev))
+            .take_while(|dt| *dt <= until)
+            .collect()
     }
 
-    pub fn list_events_tagged(&self, tag: String) -> Vec<Event> {
+    /// Returns the start of the last occurrence of the event with the given eid ended by
+    /// `now`, if any
+    pub fn last_ended_occurrence(&self, eid: u64, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        let ev = self.events.get(&eid)?;
+        self.occurrences_until(eid, now)
+            .into_iter()
+            .rfind(|dt| ev.occurrence_end(*dt) <= now)
+    }
+
+    /// Returns the event with the given eid, if any
+    pub fn peek_event(&self, eid: u64) -> Option<&Event> {
+        self.events.get(&eid)
+    }
+
+    /// Returns all the tags used by the events in this calendar, sorted and without duplicates
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .events
+            .values()
+            .flat_map(|ev| ev.get_metadata().get_tags())
+            .collect();
+        tags.sort_unstable();
+        tags.dedup();
+        tags
+    }
+
+    /// Returns the events with the tag, with their eids, sorted by their start
+    pub fn list_events_tagged(&self, tag: String) -> Vec<(u64, Cow<'_, Event>)> {
         let mut filtered_events = Vec::new();
-        for ev in self.events.values() {
-            let ev_tags = ev.get_metadata().get_tags();
-            if ev_tags.contains(&tag) {
-                filtered_events.push(ev.clone());
+        for (eid, ev) in self.events.iter() {
+            if ev.has_tag(&tag) {
+                filtered_events.push((*eid, Cow::Borrowed(ev)));
             }
         }
+        filtered_events.sort_unstable_by_key(|(_, ev)| ev.get_start());
         filtered_events
     }
+
+    /// Returns the events related to the event `eid`, directly or through other events,
+    /// in either direction, with their eids, sorted by their start
+    pub fn list_related(&self, eid: u64) -> Vec<(u64, Cow<'_, Event>)> {
+        if !self.events.contains_key(&eid) {
+            return Vec::new();
+        }
+        let mut seen = HashSet::from([eid]);
+        let mut queue = VecDeque::from([eid]);
+        while let Some(current) = queue.pop_front() {
+            let linked = self
+                .events
+                .get(&current)
+                .map(|ev| ev.get_related_to().to_vec())
+                .unwrap_or_default();
+            let linking = self
+                .events
+                .iter()
+                .filter(|(_, ev)| ev.get_related_to().contains(&current))
+                .map(|(e, _)| *e);
+            for e in linked.into_iter().chain(linking) {
+                if self.events.contains_key(&e) && seen.insert(e) {
+                    queue.push_back(e);
+                }
+            }
+        }
+        seen.remove(&eid);
+        let mut related: Vec<(u64, Cow<'_, Event>)> = seen
+            .iter()
+            .filter_map(|e| Some((*e, Cow::Borrowed(self.events.get(e)?))))
+            .collect();
+        related.sort_unstable_by_key(|(_, ev)| ev.get_start());
+        related
+    }
+
+    /// Returns a copy of the calendar that can be shared: the details of the events that
+    /// are not public are hidden (see [`Event::redact`])
+    pub fn redacted(&self) -> Calendar {
+        let mut cal = self.clone();
+        for ev in cal.events.values_mut() {
+            ev.redact();
+        }
+        cal
+    }
 }
 
 impl Display for Calendar {
@@ -188,7 +722,7 @@ impl Display for Calendar {
             self.name,
             self.owner,
             tot_events,
-            Local::now().format("%A %d/%m/%Y - %H:%M")
+            clock::now().format("%A %d/%m/%Y - %H:%M")
         )
     }
 }
@@ -196,45 +730,53 @@ impl Display for Calendar {
 impl Default for Calendar {
     fn default() -> Self {
         Calendar {
+            version: FORMAT_VERSION,
+            id: String::from("default"),
             owner: String::from("default"),
             name: String::from("default"),
             events: HashMap::new(),
+            availability: Vec::new(),
+            holidays: HashMap::new(),
+            migrated: false,
+            subscribers: Subscribers::default(),
         }
     }
 }
 #[cfg(test)]
 mod tests {
-    use chrono::{Datelike, Local, Timelike};
+    use chrono::{Datelike, Duration, NaiveDate, Timelike};
     use std::collections::HashMap;
-    use std::hash::{Hash, Hasher};
 
-    use crate::calendar::Calendar;
-    use crate::event::{self, Event};
-
-    fn get_hash(e: &Event) -> u64 {
-        let mut h = std::collections::hash_map::DefaultHasher::new();
-        e.hash(&mut h);
-        h.finish()
-    }
+    use crate::calendar::{
+        slugify, AddOutcome, BrokenDependency, Calendar, CalendarChange, FORMAT_VERSION, MAX_ID_LEN,
+    };
+    use crate::calendar_error::CalendarError;
+    use crate::clock::{self, FixedClock};
+    use crate::event::{self, Event, Follows};
 
     #[test]
     /// tests the event addition method
     fn test_event_addition() {
         let e1 = Event::default();
         let e2 = Event::default();
-        let e1_hash = get_hash(&e1);
-        let e2_hash = get_hash(&e2);
 
         let mut empty_cal = Calendar::new("owner", "test");
+        empty_cal.add_event(e1.clone());
+        empty_cal.add_event(e2.clone());
+        let e1_eid = empty_cal.eid_of(&e1).unwrap();
+        let e2_eid = empty_cal.eid_of(&e2).unwrap();
+        assert_ne!(e1_eid, e2_eid);
+
         let full_cal = Calendar {
+            version: FORMAT_VERSION,
+            id: String::from("test"),
             owner: String::from("owner"),
             name: String::from("test"),
-            events: HashMap::from([(e1_hash, e1.clone()), (e2_hash, e2.clone())]),
+            events: HashMap::from([(e1_eid, e1), (e2_eid, e2)]),
+            availability: Vec::new(),
+            holidays: HashMap::new(),
+            ..Default::default()
         };
-
-        empty_cal.add_event(e1);
-        empty_cal.add_event(e2);
-
         assert_eq!(empty_cal, full_cal);
     }
 
@@ -287,8 +829,8 @@ mod tests {
         assert_eq!(cal.events.len(), v.len());
 
         for ev in &v {
-            let h = get_hash(ev);
-            assert!(cal.events.contains_key(&h));
+            let eid = cal.eid_of(ev).unwrap();
+            assert_eq!(cal.events.get(&eid), Some(ev));
         }
     }
 
@@ -296,10 +838,10 @@ mod tests {
     /// tests the event deletion method
     fn test_event_deletion() {
         let e = Event::default();
-        let eid = get_hash(&e);
 
         let mut cal = Calendar::new("owner", "test");
-        cal.add_event(e);
+        cal.add_event(e.clone());
+        let eid = cal.eid_of(&e).unwrap();
 
         assert!(cal.remove_event(rand::random()).is_err());
         assert!(cal.remove_event(eid).is_ok());
@@ -309,7 +851,10 @@ mod tests {
     #[test]
     /// test week filter
     fn test_week_filter() {
-        let dt = Local::now().naive_local();
+        let dt = NaiveDate::from_ymd_opt(2022, 7, 13)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap();
         let mut cal = Calendar::new("owner", "test");
         for offt in -365..365 {
             let date_offt = dt.checked_add_signed(chrono::Duration::days(offt)).unwrap();
@@ -369,8 +914,23 @@ mod tests {
         let mut ev2 = ev;
         // but if the event is mutated than it should have a different hash and hence be added
         ev2.set_title("Random");
-        cal.add_event(ev2);
+        cal.add_event(ev2.clone());
         assert_eq!(cal.events.len(), 2);
+
+        let eid = cal.eid_of(&ev2).unwrap();
+        assert_eq!(
+            cal.add_event(ev2.clone()),
+            AddOutcome {
+                duplicates: vec![eid],
+                ..AddOutcome::default()
+            }
+        );
+        ev2.set_title("Overlapping");
+        let outcome = cal.add_event(ev2);
+        assert!(outcome.is_added());
+        assert!(outcome.duplicates.is_empty());
+        assert_eq!(outcome.overlaps.len(), 2);
+        assert!(outcome.overlaps.contains(&eid));
     }
 
     #[test]
@@ -422,4 +982,406 @@ mod tests {
         cal.clear();
         assert_eq!(0, cal.list_events_between(None, None).len());
     }
+
+    #[test]
+    /// tests the generation of calendar ids from display names
+    fn test_slugify() {
+        assert_eq!(slugify("work"), "work");
+        assert_eq!(slugify("My Work Calendar"), "my-work-calendar");
+        assert_eq!(slugify("  uni / courses 2022 "), "uni-courses-2022");
+        assert_eq!(slugify("../../evil"), "evil");
+        assert_eq!(slugify("città"), "citt");
+        assert_eq!(slugify("日本"), "calendar");
+        assert_eq!(slugify(&"a".repeat(100)).len(), MAX_ID_LEN);
+
+        let mut cal = Calendar::new("owner", "Some Name");
+        assert_eq!(cal.get_id(), "some-name");
+        // renaming the calendar does not change its id
+        cal.set_name("Another name");
+        assert_eq!(cal.get_id(), "some-name");
+    }
+
+    #[test]
+    /// tests that the eids of the events do not depend on their contents
+    fn test_eids() {
+        let ev = Event::new(
+            "review",
+            "",
+            "13/07/2022",
+            "09:30",
+            1.0,
+            None,
+            Some("weekly 3"),
+            None,
+        );
+        let mut cal = Calendar::new("owner", "test");
+        assert!(cal.add_event(ev.clone()).is_added());
+        let eid = cal.eid_of(&ev).unwrap();
+        cal.get_event(eid).unwrap().set_title("retro");
+        // the eid is kept when the event changes, and survives saving the calendar
+        let copy: Calendar = serde_json::from_str(&serde_json::to_string(&cal).unwrap()).unwrap();
+        assert_eq!(copy.events_by_eid()[0].0, eid);
+        assert_eq!(cal.eid_of(&ev), None);
+        // occurrences of recurrent events have the eid of the event
+        let occurrences = cal.list_events_between(None, None);
+        assert!(occurrences.len() > 1);
+        assert!(occurrences.iter().all(|o| cal.eid_of(o) == Some(eid)));
+        let listed = cal.list_occurrences_between(None, None);
+        assert_eq!(listed.len(), occurrences.len());
+        assert!(listed.iter().all(|(e, _)| *e == eid));
+    }
+
+    #[test]
+    /// tests that modifying events through the calendar keeps track of their versions
+    fn test_update_event() {
+        let ev = Event::new("review", "", "13/07/2022", "09:30", 1.0, None, None, None);
+        let mut cal = Calendar::new("owner", "test");
+        cal.add_event(ev.clone());
+        let eid = cal.eid_of(&ev).unwrap();
+        let modified = |cal: &Calendar| {
+            let metadata = cal.peek_event(eid).unwrap().get_metadata();
+            (metadata.get_revision(), metadata.get_modification())
+        };
+        let (revision, time) = modified(&cal);
+        assert_eq!(revision, 0);
+
+        // not modifying the event leaves it as it is
+        assert_eq!(cal.update_event(eid, |e| e.get_title().len()).unwrap(), 6);
+        assert_eq!(modified(&cal), (0, time));
+        let later = NaiveDate::from_ymd_opt(2022, 7, 14)
+            .unwrap()
+            .and_hms_opt(8, 0, 0)
+            .unwrap();
+        clock::with(FixedClock::at(later), || {
+            cal.update_event(eid, |e| e.set_title("retro")).unwrap()
+        });
+        let (revision, time) = modified(&cal);
+        assert_eq!(revision, 1);
+        assert_eq!(time.naive_local(), later);
+        cal.update_event(eid, |e| e.set_location("room 1")).unwrap();
+        assert_eq!(modified(&cal).0, 2);
+        assert!(cal.update_event(42, |e| e.set_title("x")).is_err());
+    }
+
+    #[test]
+    /// tests the computation of changes between calendars
+    fn test_diff() {
+        let e1 = Event::new("e1", "", "01/01/2022", "10:00", 1.0, None, None, None);
+        let e2 = Event::new("e2", "", "02/01/2022", "10:00", 1.0, None, None, None);
+        let e3 = Event::new("e3", "", "03/01/2022", "10:00", 1.0, None, None, None);
+
+        let mut old = Calendar::new("owner", "test");
+        old.add_event(e1.clone());
+        old.add_event(e2.clone());
+        let (h1, h2) = (old.eid_of(&e1).unwrap(), old.eid_of(&e2).unwrap());
+        assert!(old.diff(&old.clone()).is_empty());
+
+        let mut new = old.clone();
+        new.remove_event(h1).unwrap();
+        new.add_event(e3.clone());
+        let h3 = new.eid_of(&e3).unwrap();
+        new.get_event(h2).unwrap().set_title("edited");
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, vec![h3]);
+        assert_eq!(diff.removed, vec![h1]);
+        assert_eq!(diff.edited, vec![h2]);
+    }
+
+    #[test]
+    /// tests that the subscribers receive the changes to the events
+    fn test_subscribe() {
+        let e1 = Event::new("e1", "", "01/01/2022", "10:00", 1.0, None, None, None);
+        let e2 = Event::new("e2", "", "02/01/2022", "10:00", 1.0, None, None, None);
+        let mut cal = Calendar::new("owner", "test");
+        cal.add_event(e1.clone());
+        let changes = cal.subscribe();
+        cal.add_event(e2.clone());
+        let (h1, h2) = (cal.eid_of(&e1).unwrap(), cal.eid_of(&e2).unwrap());
+        cal.update_event(h1, |ev| ev.set_title("edited")).unwrap();
+        // not a change
+        cal.update_event(h2, |ev| ev.get_start()).unwrap();
+        cal.remove_event(h2).unwrap();
+        let mut copy = cal.clone();
+        assert_eq!(copy, cal);
+        copy.add_event(e2);
+        cal.clear();
+        assert_eq!(
+            changes.try_iter().collect::<Vec<_>>(),
+            vec![
+                CalendarChange::Added(h2),
+                CalendarChange::Updated(h1),
+                CalendarChange::Removed(h2),
+                CalendarChange::Removed(h1),
+            ]
+        );
+        drop(cal);
+        assert!(changes.recv().is_err());
+    }
+
+    #[test]
+    /// tests listing the occurrences started before the interval, if still ongoing
+    fn test_list_ongoing() {
+        let mut conference = Event::new(
+            "conference",
+            "",
+            "01/07/2022",
+            "09:00",
+            1.0,
+            None,
+            None,
+            None,
+        );
+        conference.set_duration(&Duration::hours(57));
+        let lunch = Event::new("lunch", "", "01/07/2022", "12:30", 1.0, None, None, None);
+        let mut cal = Calendar::new("owner", "test");
+        cal.insert_event(1, conference);
+        cal.insert_event(2, lunch);
+        let titles = |d, h| -> Vec<String> {
+            let from = NaiveDate::from_ymd_opt(2022, 7, d)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap();
+            cal.list_events_between(Some(from), Some(from + Duration::hours(14)))
+                .iter()
+                .map(|ev| ev.get_title().to_string())
+                .collect()
+        };
+        assert_eq!(titles(1, 0), ["conference", "lunch"]);
+        assert_eq!(titles(1, 14), ["conference"]);
+        assert_eq!(titles(3, 0), ["conference"]);
+        // ended at 18:00 on the 3rd
+        assert!(titles(3, 18).is_empty());
+    }
+
+    #[test]
+    /// tests following the relations between events
+    fn test_list_related() {
+        let e1 = Event::new("kickoff", "", "01/01/2022", "10:00", 1.0, None, None, None);
+        let (h1, h2, h3) = (1, 2, 3);
+        let mut e2 = Event::new(
+            "follow-up",
+            "",
+            "08/01/2022",
+            "10:00",
+            1.0,
+            None,
+            None,
+            None,
+        );
+        e2.add_related(h1);
+        let mut e3 = Event::new("review", "", "15/01/2022", "10:00", 1.0, None, None, None);
+        e3.add_related(h2);
+        // a dangling relation is ignored
+        e3.add_related(42);
+        let e4 = Event::new(
+            "unrelated",
+            "",
+            "03/01/2022",
+            "10:00",
+            1.0,
+            None,
+            None,
+            None,
+        );
+        let mut cal = Calendar::new("owner", "test");
+        for (eid, ev) in [(h1, e1), (h2, e2), (h3, e3), (4, e4)] {
+            cal.insert_event(eid, ev);
+        }
+        let titles = |eid| -> Vec<String> {
+            cal.list_related(eid)
+                .iter()
+                .map(|(_, ev)| ev.get_title().to_string())
+                .collect()
+        };
+        assert_eq!(titles(h1), ["follow-up", "review"]);
+        assert_eq!(titles(h3), ["kickoff", "follow-up"]);
+        assert_eq!(titles(h2), ["kickoff", "review"]);
+        assert!(titles(42).is_empty());
+    }
+
+    #[test]
+    /// tests finding the dependencies not met, as the events depended on are moved or removed
+    fn test_broken_dependencies() {
+        let review = Event::new("review", "", "13/07/2022", "09:00", 1.0, None, None, None);
+        let mut report = Event::new("report", "", "13/07/2022", "10:00", 1.0, None, None, None);
+        report.add_dependency(1);
+        let mut slides = Event::new("slides", "", "14/07/2022", "10:00", 1.0, None, None, None);
+        slides.set_follows(Some(Follows {
+            eid: 2,
+            gap: Duration::zero(),
+        }));
+        let mut cal = Calendar::new("owner", "test");
+        for (eid, ev) in [(1, review), (2, report), (3, slides)] {
+            cal.insert_event(eid, ev);
+        }
+        assert!(cal.broken_dependencies().is_empty());
+        cal.move_events(&[1], Duration::minutes(30)).unwrap();
+        let end = NaiveDate::from_ymd_opt(2022, 7, 13)
+            .unwrap()
+            .and_hms_opt(10, 30, 0)
+            .unwrap();
+        assert_eq!(
+            cal.broken_dependencies(),
+            vec![BrokenDependency {
+                eid: 2,
+                prerequisite: 1,
+                prerequisite_end: Some(end)
+            }]
+        );
+        // the event scheduled after another one depends on it too
+        cal.move_events(&[2], Duration::days(1)).unwrap();
+        assert_eq!(
+            cal.broken_dependencies()
+                .iter()
+                .map(|b| (b.eid, b.prerequisite))
+                .collect::<Vec<_>>(),
+            vec![(3, 2)]
+        );
+        cal.remove_event(2).unwrap();
+        assert_eq!(
+            cal.broken_dependencies(),
+            vec![BrokenDependency {
+                eid: 3,
+                prerequisite: 2,
+                prerequisite_end: None
+            }]
+        );
+    }
+
+    #[test]
+    /// tests moving some events, and that none is moved if one of them cannot be
+    fn test_move_events() {
+        let mut standup = Event::new(
+            "standup",
+            "",
+            "03/10/2022",
+            "09:00",
+            1.0,
+            None,
+            Some("daily 10"),
+            None,
+        );
+        standup.pause(
+            NaiveDate::from_ymd_opt(2022, 10, 6).unwrap(),
+            NaiveDate::from_ymd_opt(2022, 10, 7).unwrap(),
+        );
+        let review = Event::new("review", "", "07/10/2022", "23:30", 1.0, None, None, None);
+        let mut cal = Calendar::new("owner", "test");
+        for (eid, ev) in [(1, standup), (2, review), (3, Event::default())] {
+            cal.insert_event(eid, ev);
+        }
+        cal.move_events(&[1, 2], Duration::weeks(1) + Duration::hours(1))
+            .unwrap();
+        let standup = cal.peek_event(1).unwrap();
+        assert_eq!(
+            standup.get_start(),
+            NaiveDate::from_ymd_opt(2022, 10, 10)
+                .unwrap()
+                .and_hms_opt(10, 0, 0)
+                .unwrap()
+        );
+        // the pauses are moved with the occurrences
+        let pauses = standup.get_recurrence().unwrap().pauses();
+        assert_eq!(
+            pauses[0].from,
+            NaiveDate::from_ymd_opt(2022, 10, 13).unwrap()
+        );
+        assert_eq!(
+            pauses[0].until,
+            NaiveDate::from_ymd_opt(2022, 10, 14).unwrap()
+        );
+        // across midnight
+        assert_eq!(
+            cal.peek_event(2).unwrap().get_start(),
+            NaiveDate::from_ymd_opt(2022, 10, 15)
+                .unwrap()
+                .and_hms_opt(0, 30, 0)
+                .unwrap()
+        );
+        cal.move_events(&[2], -Duration::hours(1)).unwrap();
+        assert_eq!(
+            cal.peek_event(2)
+                .unwrap()
+                .get_start()
+                .format("%d/%m/%Y %H:%M")
+                .to_string(),
+            "14/10/2022 23:30"
+        );
+        // a missing event, or one that cannot be moved: the others are left where they are
+        let before = cal.clone();
+        assert!(cal.move_events(&[1, 4], Duration::days(1)).is_err());
+        assert!(cal
+            .move_events(&[1, 2], Duration::days(365 * 300_000))
+            .is_err());
+        assert_eq!(cal, before);
+    }
+
+    #[test]
+    /// tests joining adjacent and overlapping events
+    fn test_join_events() {
+        let tags = |t: &str| Some(vec![t.to_string()]);
+        let mut talk = Event::new(
+            "talk",
+            "slides",
+            "13/07/2022",
+            "10:00",
+            1.0,
+            None,
+            None,
+            tags("conf"),
+        );
+        talk.set_location("hall");
+        let qa = Event::new(
+            "Q&A",
+            "questions",
+            "13/07/2022",
+            "11:03",
+            1.0,
+            None,
+            None,
+            tags("qa"),
+        );
+        let lunch = Event::new(
+            "lunch",
+            "",
+            "13/07/2022",
+            "12:30",
+            1.0,
+            None,
+            None,
+            tags("conf"),
+        );
+        let mut cal = Calendar::new("owner", "test");
+        for (eid, ev) in [(1, talk), (2, qa), (3, lunch)] {
+            cal.insert_event(eid, ev);
+        }
+        let minutes = Duration::minutes;
+        assert!(cal.join_events(1, 2, minutes(2)).is_err());
+        // in any order, into the first one
+        assert_eq!(cal.join_events(2, 1, minutes(5)).unwrap(), 1);
+        assert!(cal.peek_event(2).is_none());
+        let joined = cal.peek_event(1).unwrap();
+        assert_eq!(joined.get_title(), "talk + Q&A");
+        assert_eq!(joined.get_description(), "slides\n\nquestions");
+        assert_eq!(joined.get_location(), "hall");
+        assert_eq!(joined.get_duration(), minutes(123).num_seconds());
+        assert_eq!(joined.get_metadata().get_tags(), ["conf", "qa"]);
+        assert_eq!(joined.get_metadata().get_revision(), 1);
+        // the lunch starts 27 minutes after the end
+        assert!(cal.join_events(1, 3, minutes(15)).is_err());
+        assert!(cal.join_events(1, 3, minutes(30)).is_ok());
+        assert_eq!(cal.get_size(), 1);
+
+        let mut standup = Event::new("standup", "", "14/07/2022", "09:00", 1.0, None, None, None);
+        standup.set_recurrence("daily 5");
+        cal.insert_event(4, standup);
+        let sync = Event::new("sync", "", "14/07/2022", "09:30", 1.0, None, None, None);
+        cal.insert_event(5, sync);
+        assert!(cal.join_events(4, 5, minutes(5)).is_err());
+        assert!(cal.join_events(5, 5, minutes(5)).is_err());
+        assert!(matches!(
+            cal.join_events(5, 6, minutes(5)),
+            Err(CalendarError::EventNotFound(6))
+        ));
+    }
 }
@@ -6,8 +6,12 @@ use std::io::Error;
 pub enum CalendarError {
     CalendarNotFound(String),
     CalendarAlreadyExists(String),
+    InvalidCalendarName(String),
     EventNotFound(u64),
+    /// The recurrence of an event makes its occurrences overlap
+    InvalidRecurrence(String),
     IcsParsingFailed(String),
+    InvalidConfig(String),
     Unknown(String),
 }
 
@@ -16,8 +20,11 @@ impl Display for CalendarError {
         match self {
             Self::CalendarNotFound(_) => write!(f, "Calendar not found"),
             Self::CalendarAlreadyExists(_) => write!(f, "The calendar already exists"),
+            Self::InvalidCalendarName(s) => write!(f, "Invalid calendar name {s}"),
             Self::EventNotFound(_) => write!(f, "Event not found!"),
+            Self::InvalidRecurrence(_) => write!(f, "Invalid recurrence"),
             Self::IcsParsingFailed(_) => write!(f, "Failed parsing .ics file"),
+            Self::InvalidConfig(_) => write!(f, "Invalid configuration"),
             Self::Unknown(s) => write!(f, "Unknown error: {s}"),
         }
     }
@@ -27,8 +34,11 @@ impl Debug for CalendarError {
         match self {
             Self::CalendarNotFound(s) => write!(f, "Calendar {s} not found"),
             Self::CalendarAlreadyExists(s) => write!(f, "Calendar {s} already exists"),
+            Self::InvalidCalendarName(s) => write!(f, "Invalid calendar name {s}"),
             Self::EventNotFound(eid) => write!(f, "Event {} not found!", eid),
+            Self::InvalidRecurrence(s) => write!(f, "Invalid recurrence: {s}"),
             Self::IcsParsingFailed(file) => write!(f, "Failed parsing {file}"),
+            Self::InvalidConfig(s) => write!(f, "Invalid configuration: {s}"),
             Self::Unknown(s) => write!(f, "Unknown error: {s}"),
         }
     }
@@ -38,4 +48,3 @@ impl From<Error> for CalendarError {
         Self::CalendarNotFound(format!("Calendar not found: {}", e))
     }
 }
-
@@ -1,21 +1,69 @@
+use std::collections::BTreeSet;
 use std::env;
 use std::ffi::OsStr;
-use std::fs::{self, File};
-use std::io::BufReader;
-use std::io::BufWriter;
-use std::io::Read;
-use std::path::Path;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::result::Result;
 
-use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
-use clap::{ArgGroup, Args, Parser, Subcommand};
+use chrono::{
+    DateTime, Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, Timelike,
+};
+use chrono_tz::Tz;
+use clap::{ArgGroup, Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use icalendar::parser::{Component, Property};
+use serde::{Deserialize, Serialize};
 
-use crate::calendar::Calendar;
+use crate::anonymize;
+use crate::audit;
+use crate::availability::{self, Availability};
+use crate::blobs;
+use crate::calendar::{slugify, AddOutcome, BrokenDependency, Calendar, CalendarChange};
 use crate::calendar_error::CalendarError;
-use crate::event::Event;
+use crate::clock;
+use crate::config::{self, ResourceConfig};
+use crate::conflicts;
+use crate::countdown;
+use crate::course::Course;
+use crate::digest::{self, DigestFormat};
+use crate::doctor;
+use crate::dump;
+use crate::editor;
+use crate::event::{
+    self, Attachment, Cadence, CheckIn, Class, Color, Event, Follows, Geo, HolidayAction,
+    HolidayRule, SunTime, Transparency, WorkPlace,
+};
+use crate::exchange;
+use crate::freebusy::{self, FbType};
+use crate::habits;
+use crate::http;
+use crate::ics;
+use crate::ics_writer::unescape_text;
+use crate::itinerary;
+use crate::maintenance;
+use crate::notify;
+use crate::planner;
+use crate::prompt::{Answers, Prompt, Terminal};
+use crate::qr;
+use crate::query::Query;
+use crate::report;
+use crate::resources::{self, DoubleBooking};
+use crate::review;
+use crate::rotation::{self, Rotation};
+use crate::snapshot;
+use crate::stats::{self, Bucket, StatsFormat};
+use crate::storage;
+use crate::subscription::{self, Subscription};
+use crate::sync::{self, Conflict, ConflictPolicy};
+use crate::table::{Column, Table};
+use crate::timeline;
+use crate::users::{Access, Users};
+use crate::working_location;
+use crate::zones;
 
-use log::{error, info, warn};
+use log::{error, info};
 
 /// Simple calendar program
 #[derive(Parser)]
@@ -24,104 +72,227 @@ pub struct Cli {
     /// Specifies a subcommand
     #[clap(subcommand)]
     pub subcommand: Option<Commands>,
-    /// View this calendar (if it exists)
+    /// View this calendar (if it exists), given its id or name: it is searched in the data
+    /// directory, then in the shared roots of its configuration
     #[clap(short, long)]
     pub view: Option<String>,
-    /// Edit an existing calendar
+    /// Edit an existing calendar, given its id or name
     #[clap(short, long)]
     pub edit: Option<String>,
     /// Create a calendar
     #[clap(short, long)]
     pub create: Option<String>,
+    /// Create a calendar, asking for its owner, name and id
+    #[clap(long, conflicts_with_all = &["create", "view", "edit", "delete", "list"])]
+    pub create_interactive: bool,
     /// Specify the calendar's name
     #[clap(short, long)]
     pub name: Option<String>,
-    /// Delete a calendar
+    /// Specify the calendar's id (defaults to one derived from its name)
+    #[clap(long, requires = "create")]
+    pub id: Option<String>,
+    /// Create a calendar with the owner and settings of another one (given its id or name)
+    #[clap(long, number_of_values = 2, value_names = &["SRC", "DST"], conflicts_with_all = &["create", "create-interactive", "view", "edit", "delete", "list"])]
+    pub clone: Option<Vec<String>>,
+    /// Do not copy the events of the calendar being cloned
+    #[clap(long, requires = "clone")]
+    pub without_events: bool,
+    /// Delete a calendar, given its id or name
     #[clap(short, long)]
     pub delete: Option<String>,
-    /// List all known calendars
+    /// List all known calendars, in the data directory and in the shared roots
     #[clap(short, long)]
     pub list: bool,
+    /// Output format
+    #[clap(long, value_enum, default_value = "text")]
+    pub output: OutputFormat,
+    /// Only report errors
+    #[clap(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Increase the logging verbosity, can be repeated (info, debug, trace).
+    /// No short flag is provided since -v is --view
+    #[clap(long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+    /// Emit diagnostics as JSON objects, one per line
+    #[clap(long)]
+    pub log_json: bool,
+    /// Print the changes that would be made, without writing anything
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Execute the commands in this file (one per line) on the calendar, saving it once
+    #[clap(long)]
+    pub script: Option<String>,
+    /// Run a daemon keeping the calendars in memory, that answers the list queries
+    /// on calendars opened with --view (unix only)
+    #[clap(long)]
+    pub daemon: bool,
+    /// Token authenticating the user to the daemon, if it has users
+    /// (defaults to $CALENDA_RS_TOKEN)
+    #[clap(long)]
+    pub token: Option<String>,
+    /// Make the daemon hide the titles and descriptions of the events that are not public
+    #[clap(long, requires = "daemon")]
+    pub redact: bool,
+    /// Directory of the calendars and of the configuration (defaults to $CALENDA_RS_DATA,
+    /// or to ./data)
+    #[clap(long)]
+    pub data_dir: Option<PathBuf>,
+    /// Further commands, separated by ';' on the command line
+    #[clap(skip)]
+    pub chained: Vec<Commands>,
 }
 
-fn read_calendar(p: &Path) -> Result<Calendar, CalendarError> {
-    let p2 = &p.with_extension("json");
-    if Path::exists(p2) {
-        let f = File::open(p2)?;
-        let reader = BufReader::new(f);
-        if let Ok(cal) = serde_json::from_reader(reader) {
-            return Ok(cal);
-        }
-    }
-    Err(CalendarError::CalendarNotFound(
-        p2.to_string_lossy().to_string(),
-    ))
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text
+    Text,
+    /// Machine readable JSON
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// iCalendar file with all the events
+    Ics,
+    /// Mermaid Gantt diagram of the events, grouped by their first tag
+    MermaidGantt,
+    /// PlantUML Gantt diagram of the events, grouped by their first tag
+    PlantumlGantt,
 }
 
-fn create_calendar(calname: &str, cal_owner: &str, p: &Path) -> Result<Calendar, CalendarError> {
-    let cal_file = p.join(calname).with_extension("json");
-    let dir_iter = fs::read_dir(p)?;
+/// Summary of a calendar stored in the data directory
+#[derive(Serialize)]
+struct CalendarSummary {
+    id: String,
+    name: String,
+    owner: String,
+    path: PathBuf,
+    /// Whether the calendar is in one of the shared roots, and cannot be edited
+    shared: bool,
+    events: usize,
+    next_event: Option<NaiveDateTime>,
+    file_size: u64,
+    last_modified: Option<DateTime<Local>>,
+}
 
-    for entry in dir_iter.flatten() {
-        if entry.path() == cal_file {
-            return Err(CalendarError::CalendarAlreadyExists(calname.to_string()));
+impl CalendarSummary {
+    fn new(cal: &Calendar, path: PathBuf, shared: bool) -> CalendarSummary {
+        let now = clock::now().naive_local();
+        let metadata = fs::metadata(&path).ok();
+        CalendarSummary {
+            id: cal.get_id().to_string(),
+            name: cal.get_name().to_string(),
+            owner: cal.get_owner().to_string(),
+            shared,
+            events: cal.get_size(),
+            // the ongoing events are not the next one
+            next_event: cal
+                .list_events_between(Some(now), None)
+                .iter()
+                .map(|ev| ev.get_start())
+                .find(|start| *start >= now),
+            file_size: metadata.as_ref().map_or(0, |m| m.len()),
+            last_modified: metadata
+                .and_then(|m| m.modified().ok())
+                .map(DateTime::<Local>::from),
+            path,
         }
     }
-    Ok(Calendar::new(calname, cal_owner))
 }
 
-fn delete_calendar(calname: &str, p: &Path) -> bool {
-    let cal_file = p.join(calname).with_extension("json");
-    let dir_iter = fs::read_dir(p).expect(&format!("Calendar not found: {}", cal_file.display()));
-    for entry in dir_iter.flatten() {
-        if entry.path() == cal_file {
-            return fs::remove_file(entry.path()).is_ok();
-        }
+impl Display for CalendarSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} [{}] (owned by {}) @ {}{}\n\t{} events, next: {}, {} bytes, last modified: {}",
+            self.name,
+            self.id,
+            if self.owner.is_empty() {
+                "<unknown>"
+            } else {
+                &self.owner
+            },
+            self.path.display(),
+            if self.shared {
+                " (shared, read-only)"
+            } else {
+                ""
+            },
+            self.events,
+            self.next_event.map_or("-".to_string(), |dt| dt
+                .format("%d/%m/%Y %H:%M")
+                .to_string()),
+            self.file_size,
+            self.last_modified.map_or("-".to_string(), |dt| dt
+                .format("%d/%m/%Y %H:%M")
+                .to_string()),
+        )
     }
-    false
 }
 
-fn list_calendars(p: &Path) {
-    let mut known_cals = Vec::new();
-    let dir_iter = fs::read_dir(p).unwrap();
-    for ent in dir_iter.flatten() {
-        let p = ent.path();
-        let ext = p.extension();
-        let stem = p.file_stem().unwrap();
-        if let Some(s) = ext {
-            if s.eq("json") {
-                known_cals.push((read_calendar(&p.with_file_name(stem)), p.clone()));
+/// Returns the directories the calendars are searched in (see [`config::data_roots`]), only
+/// the data directory if its configuration cannot be read
+fn data_roots(data_dir: &Path) -> Vec<PathBuf> {
+    config::data_roots(data_dir).unwrap_or_else(|e| {
+        report::warning(format!("{:?}: the shared roots are not searched", e));
+        vec![data_dir.to_path_buf()]
+    })
+}
+
+fn list_calendars(p: &Path, output: OutputFormat) -> Result<(), CalendarError> {
+    let mut summaries = Vec::new();
+    for (cal, root) in storage::known_in_roots(&data_roots(p)) {
+        match cal {
+            (Ok(cal), path) => summaries.push(CalendarSummary::new(&cal, path, root != p)),
+            (Err(e), path) => {
+                report::warning(format!(
+                    "Cannot read calendar at {}: {:?}",
+                    path.display(),
+                    e
+                ));
             }
         }
     }
-    println!("Known calendars: ");
-    for cal in known_cals {
-        if let (Ok(cal), path) = cal {
-            println!(
-                "{} (owned by {}) @ {}",
-                cal.get_name(),
-                if cal.get_owner().is_empty() {
-                    "<unknown>"
-                } else {
-                    cal.get_owner()
-                },
-                path.display()
-            );
-        } else {
-            eprintln!("Error for calendar!");
+    summaries.sort_by(|a, b| a.id.cmp(&b.id));
+    match output {
+        OutputFormat::Text => {
+            println!("Known calendars: ");
+            for s in summaries {
+                println!("{}", s);
+            }
         }
+        OutputFormat::Json => match serde_json::to_string_pretty(&summaries) {
+            Ok(s) => println!("{}", s),
+            Err(e) => return Err(CalendarError::Unknown(e.to_string())),
+        },
     }
-}
-
-pub fn save_calendar(cal: &Calendar, p: &Path) -> bool {
-    let f = File::create(p).unwrap();
-    let writer = BufWriter::new(f);
-    serde_json::to_writer_pretty(writer, cal).is_ok()
+    Ok(())
 }
 
 impl Cli {
+    /// Parses the command line. Several subcommands can be given, separated by a ';' argument
+    /// (escaped from the shell), as in `-e work add ... \\; remove ... \\; list`
     pub fn parse_cli() -> Cli {
-        Cli::parse()
+        let args: Vec<String> = env::args().collect();
+        let mut segments = args.split(|arg| arg == ";");
+        let mut cli = Cli::parse_from(segments.next().unwrap_or_default());
+        for seg in segments {
+            match parse_command(seg) {
+                Ok(cmd) => cli.chained.push(cmd),
+                Err(e) => e.exit(),
+            }
+        }
+        cli
+    }
+
+    /// Returns the verbosity requested on the command line: -1 if quiet,
+    /// else the number of times --verbose was given
+    pub fn verbosity(&self) -> i8 {
+        if self.quiet {
+            -1
+        } else {
+            self.verbose.min(i8::MAX as u8) as i8
+        }
     }
 
     pub fn exec_commands(
@@ -134,23 +305,68 @@ impl Cli {
                 if args.edit.is_none() {
                     readonly = true;
                 }
-                read_calendar(&data_dir.join(Path::new(&s))).and_then(|c| Ok(Some(c)))
+                let res = match storage::resolve_in_roots(s, &data_roots(data_dir)) {
+                    Ok((cal, root)) if root == data_dir => Ok(cal),
+                    // the calendars in the shared roots are only viewed
+                    Ok((_, root)) if !readonly => Err(CalendarError::Unknown(format!(
+                        "{} is in the shared root {}, that is read-only: view it with --view",
+                        s,
+                        root.display()
+                    ))),
+                    Ok((cal, root)) => {
+                        info!("{} read from the shared root {}", s, root.display());
+                        return (readonly, Ok(Some(cal)));
+                    }
+                    Err(e) => Err(e),
+                };
+                let auto_refresh = || {
+                    readonly && config::load(data_dir).is_ok_and(|c| c.subscriptions.auto_refresh)
+                };
+                if let Ok(cal) = res.as_ref() {
+                    if auto_refresh() {
+                        // the calendar is shown as it is, the refreshed feed on the next view
+                        subscription::refresh_in_background(
+                            cal.get_id(),
+                            clock::now().naive_local(),
+                            data_dir,
+                        );
+                    }
+                }
+                res.map(Some)
             }
             Cli {
                 create: Some(owner),
                 name,
+                id,
                 ..
             } => {
                 let mut calname = owner;
                 if let Some(n) = name {
                     calname = n;
                 }
-                create_calendar(calname, owner, data_dir).and_then(|c| Ok(Some(c)))
+                storage::create_calendar(calname, owner, id.as_deref(), data_dir).map(Some)
             }
+            Cli {
+                create_interactive: true,
+                ..
+            } => Terminal::new()
+                .and_then(|t| calendar_from_prompts(&mut Prompt::new(t), data_dir))
+                .map(Some),
+            Cli {
+                clone: Some(names), ..
+            } => storage::clone_calendar(&names[0], &names[1], !args.without_events, data_dir)
+                .map(Some),
             Cli {
                 delete: Some(s), ..
             } => {
-                if delete_calendar(s, data_dir) {
+                if args.dry_run {
+                    storage::resolve_calendar(s, data_dir)
+                        .and_then(|cal| storage::calendar_path(cal.get_id(), data_dir))
+                        .map(|path| {
+                            println!("Would delete {}", path.display());
+                            None
+                        })
+                } else if storage::delete_calendar(s, data_dir) {
                     Ok(None)
                 } else {
                     Err(CalendarError::CalendarNotFound(s.to_string()))
@@ -158,26 +374,69 @@ impl Cli {
             }
             Cli { list: true, .. } => {
                 readonly = true;
-                list_calendars(data_dir);
                 // NOTE: this value is ignored
-                Ok(None)
+                list_calendars(data_dir, args.output).map(|_| None)
             }
+            Cli {
+                subcommand: Some(Commands::User(x)),
+                ..
+            } => handle_user(x, data_dir).map(|_| None),
+            Cli {
+                subcommand: Some(Commands::Log(x)),
+                ..
+            } => handle_log(x, data_dir).map(|_| None),
+            Cli {
+                subcommand: Some(Commands::Snapshot(x)),
+                ..
+            } => handle_snapshot(x, data_dir).map(|_| None),
+            Cli {
+                subcommand: Some(Commands::Dump(x)),
+                ..
+            } => handle_dump(x, data_dir).map(|_| None),
+            Cli {
+                subcommand: Some(Commands::Restore(x)),
+                ..
+            } => handle_restore(x, data_dir).map(|_| None),
+            Cli {
+                subcommand: Some(Commands::Doctor(x)),
+                ..
+            } => handle_doctor(x, data_dir).map(|_| None),
+            Cli {
+                subcommand: Some(Commands::Maintenance),
+                ..
+            } => handle_maintenance(data_dir).map(|_| None),
+            Cli {
+                subcommand: Some(Commands::Conflicts(x)),
+                ..
+            } => handle_conflicts(x, data_dir).map(|_| None),
+            Cli {
+                subcommand: Some(Commands::Resources(x)),
+                ..
+            } => handle_resources(x, data_dir).map(|_| None),
+            Cli {
+                subcommand: Some(Commands::Ics(x)),
+                ..
+            } => handle_ics_cmd(x).map(|_| None),
+            Cli {
+                subcommand: Some(Commands::DataPath(x)),
+                ..
+            } => handle_path(x, data_dir).map(|_| None),
+            Cli {
+                subcommand: Some(Commands::OpenDataDir),
+                ..
+            } => open_data_dir(data_dir).map(|_| None),
             Cli {
                 subcommand: Some(_),
                 list: false,
                 ..
             } => {
                 // FIXME: maybe use the default calendar and allow only reads on it
-                warn!("Unspecified calendar: aborting.");
-                //eprintln!("Unspecified calendar: aborting.");
                 Err(CalendarError::CalendarNotFound(
-                    "Unspecified calendar: aborting.".to_string(),
+                    "(unspecified, select one with --view or --edit)".to_string(),
                 ))
             }
             _ => {
                 let a: String = env::args().collect();
-                warn!("Unrecognized command or option: {}", a);
-                //eprintln!("Unrecognized command or option: {}", a);
                 Err(CalendarError::Unknown(format!(
                     "Unrecognized command or option: {a}"
                 )))
@@ -187,6 +446,411 @@ impl Cli {
     }
 }
 
+/// Used to parse a single subcommand, without the global flags
+#[derive(Parser)]
+#[clap(name = "calenda-rs", no_binary_name = true)]
+struct SingleCommand {
+    #[clap(subcommand)]
+    command: Commands,
+}
+
+pub(crate) fn parse_command<T: AsRef<str>>(words: &[T]) -> Result<Commands, clap::Error> {
+    SingleCommand::try_parse_from(words.iter().map(|w| w.as_ref())).map(|c| c.command)
+}
+
+/// Splits a line into words, separated by whitespace. Quotes (single or double) group words,
+/// and a backslash escapes the following character
+pub(crate) fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', _) => match chars.next() {
+                Some(escaped) => {
+                    word.push(escaped);
+                    in_word = true;
+                }
+                None => return Err("trailing backslash".to_string()),
+            },
+            (c, Some(q)) if c == q => quote = None,
+            (c, Some(_)) => word.push(c),
+            ('"', None) | ('\'', None) => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (c, None) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (c, None) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err("unterminated quote".to_string());
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Prints the help message listing all subcommands
+pub(crate) fn print_commands_help() {
+    let _ = SingleCommand::command().print_help();
+}
+
+/// Reads the commands in a script file: each line holds a subcommand with its arguments,
+/// empty lines and lines starting with '#' are ignored
+pub fn parse_script(path: &Path) -> Result<Vec<Commands>, CalendarError> {
+    let contents = fs::read_to_string(path)?;
+    let mut commands = Vec::new();
+    for (lineno, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let cmd = split_words(line)
+            .and_then(|words| parse_command(&words).map_err(|e| e.to_string()))
+            .map_err(|e| {
+                CalendarError::Unknown(format!("{}:{}: {}", path.display(), lineno + 1, e))
+            })?;
+        commands.push(cmd);
+    }
+    Ok(commands)
+}
+
+/// Executes a subcommand on the calendar, returning whether it succeeded. The data directory
+/// is where the configuration of the commands that need one is read from. The dependencies
+/// not met that involve the events changed are warned about
+pub fn exec_subcommand(cal: &mut Calendar, cmd: Commands, readonly: bool, data_dir: &Path) -> bool {
+    let changes = cal.subscribe();
+    let ok = run_subcommand(cal, cmd, readonly, data_dir);
+    let changed: Vec<u64> = changes
+        .try_iter()
+        .map(|change| match change {
+            CalendarChange::Added(eid)
+            | CalendarChange::Removed(eid)
+            | CalendarChange::Updated(eid) => eid,
+        })
+        .collect();
+    if ok && !changed.is_empty() {
+        warn_dependencies(cal, &changed);
+    }
+    ok
+}
+
+fn run_subcommand(cal: &mut Calendar, cmd: Commands, readonly: bool, data_dir: &Path) -> bool {
+    match (cmd, readonly) {
+        (Commands::Add(x), false) => match handle_add(cal, x, data_dir) {
+            Ok(x) => x,
+            Err(e) => {
+                report::error(&e);
+                false
+            }
+        },
+        (Commands::Edit(x), false) => match handle_edit(cal, x, data_dir) {
+            Ok(x) => x,
+            Err(e) => {
+                report::error(&e);
+                false
+            }
+        },
+        (Commands::Remove(rm), false) => handle_remove(cal, rm),
+        (Commands::List(l), _) => handle_list(cal, l),
+        (Commands::Show(x), _) => handle_show(cal, x, data_dir),
+        (Commands::Share(x), _) => match handle_share(cal, x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Export(x), _) => match handle_export(cal, x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Import(x), false) => match handle_import(cal, x, data_dir) {
+            Ok(x) => x,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        // the changes can be previewed in a read-only calendar, but not applied to it
+        (Commands::Import(x), true) if x.diff => match handle_import(cal, x, data_dir) {
+            Ok(x) => x,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Plan(x), false) => handle_plan(cal, x, data_dir),
+        // the tasks can be planned in a read-only calendar, but not added to it
+        (Commands::Plan(x), true) if !x.accept => handle_plan(cal, x, data_dir),
+        (Commands::Schedule(x), false) => handle_schedule(cal, x, data_dir),
+        // the meeting times can be found in a read-only calendar, but not booked in it
+        (Commands::Schedule(x), true) if x.book.is_none() => handle_schedule(cal, x, data_dir),
+        (Commands::Countdown(x), _) => match handle_countdown(cal, x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::CheckIn(x), false) => match handle_checkin(cal, x) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Review(x), _) => {
+            handle_review(cal, x);
+            true
+        }
+        (Commands::Join(x), false) => match handle_join(cal, x) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Project(x), _) => match handle_project(cal, x) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Shift(x), false) => match handle_shift(cal, x) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Done(x), false) => match handle_done(cal, x) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Habits, _) => {
+            print!(
+                "{}",
+                render_habits(
+                    cal,
+                    terminal_size::terminal_size().map(|(w, _)| usize::from(w.0))
+                )
+            );
+            true
+        }
+        (Commands::Location(x), false) => match handle_location(cal, x) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Snooze(x), _) => match handle_snooze(cal, &x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Digest(d), _) => match handle_digest(cal, d, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (
+            Commands::Availability(a @ (AvailabilityCmd::List | AvailabilityCmd::Export { .. })),
+            _,
+        ) => handle_availability(cal, a),
+        (Commands::Availability(a), false) => handle_availability(cal, a),
+        (Commands::Slots(x @ SlotsCmd::Publish { .. }), _) | (Commands::Slots(x), false) => {
+            match handle_slots(cal, x, data_dir) {
+                Ok(()) => true,
+                Err(e) => {
+                    report::error(format!("{:?}", e));
+                    false
+                }
+            }
+        }
+        (Commands::Course(x), false) => handle_course(cal, x),
+        (Commands::Rotation(x), false) => match handle_rotation(cal, x) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Itinerary(x), false) => match handle_itinerary(cal, x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Exchange(x), false) => match handle_exchange(cal, x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Subscribe(x), false) => match handle_subscribe(cal, x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Refresh(x), false) => match handle_refresh(cal, x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Sync(x @ SyncCmd::Conflicts), _) | (Commands::Sync(x), false) => {
+            match handle_sync(cal, x, data_dir) {
+                Ok(()) => true,
+                Err(e) => {
+                    report::error(format!("{:?}", e));
+                    false
+                }
+            }
+        }
+        (Commands::FreeBusy(x), _) => handle_freebusy(cal, x),
+        (Commands::Stats(x), _) => match handle_stats(cal, x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Heatmap(x), _) => match handle_heatmap(cal, x) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Set(params), false) => handle_params(cal, params),
+        (Commands::Shell, _) => {
+            report::error("The shell can only be started from the command line");
+            false
+        }
+        (Commands::User(x), _) => match handle_user(&x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Log(x), _) => match handle_log(&x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Snapshot(x), _) => match handle_snapshot(&x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Dump(x), _) => match handle_dump(&x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Restore(x), _) => match handle_restore(&x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Doctor(x), _) => match handle_doctor(&x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Maintenance, _) => match handle_maintenance(data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Conflicts(x), _) => match handle_conflicts(&x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Resources(x), _) => match handle_resources(&x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::Ics(x), _) => match handle_ics_cmd(&x) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::DataPath(x), _) => match handle_path(&x, data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (Commands::OpenDataDir, _) => match open_data_dir(data_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                false
+            }
+        },
+        (_, true) => {
+            report::warning(format!(
+                "Calendar {} cannot be modified! (rerun with --edit)",
+                cal.get_name()
+            ));
+            false
+        }
+    }
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Adds a new event
@@ -195,10 +859,133 @@ pub enum Commands {
     Remove(Remove),
     /// Edit an event, given its eid
     Edit(Edit),
+    /// Joins two adjacent or overlapping events into the one starting first, lasting until
+    /// the later end, with the descriptions and the tags of both
+    Join(Join),
+    /// Moves a set of events (by eid, by tag or selected by an expression) forward or
+    /// backward by the same offset, all of them or none
+    Shift(Shift),
     /// Lists events with some filter
     List(Filter),
+    /// Shows all the details of an event, given its eid
+    Show(Show),
+    /// Exports a single event as an iCalendar file, e.g. to attach it to an email
+    Share(Share),
+    /// Exports all the events of the calendar as an iCalendar file, optionally anonymized
+    /// to attach it to a bug report, or as a Gantt diagram of the projects to embed in docs
+    Export(Export),
+    /// Imports the events of an iCalendar file, matched by UID with the stored ones, or
+    /// prints what importing it would change
+    Import(Import),
     /// Sets some parameter about the calendar
     Set(CalParams),
+    /// Renders the agenda of tomorrow (or of this week), and optionally emails it
+    #[clap(alias = "agenda")]
+    Digest(Digest),
+    /// Lists the upcoming events with the time remaining until they start
+    Countdown(Countdown),
+    /// Reminds again of the current (or next) occurrence of an event after a while, through
+    /// the webhooks called by the daemon
+    Snooze(Snooze),
+    /// Records the outcome of the last occurrence of an event that ended, with a note and
+    /// optionally a rating
+    #[clap(name = "checkin")]
+    CheckIn(CheckInArgs),
+    /// Lists the past events of today (or of this week) with the outcomes recorded by checkin
+    Review(Review),
+    /// Marks an occurrence of a habit (a recurrent event tagged "habit") as done
+    Done(Done),
+    /// Lists the habits (the recurrent events tagged "habit") with their streaks and how
+    /// often they were done
+    Habits,
+    /// Sets where the owner works on some days, shown as a banner in the lists of today and
+    /// of this week
+    #[clap(subcommand)]
+    Location(LocationCmd),
+    /// Schedules a list of tasks in the free time of the calendar
+    Plan(Plan),
+    /// Finds the times when this and other calendars (or attendees, given their free/busy
+    /// data) are all free for a meeting, and optionally books the meeting in each calendar
+    Schedule(Schedule),
+    /// Manages the recurring windows of time in which the owner can be booked
+    #[clap(subcommand)]
+    Availability(AvailabilityCmd),
+    /// Publishes the appointments that can be booked in the calendar, and books the ones
+    /// requested
+    #[clap(subcommand)]
+    Slots(SlotsCmd),
+    /// Adds the weekly meetings of a course over a term
+    #[clap(subcommand)]
+    Course(CourseCmd),
+    /// Adds the shifts of a rotation of the members of a team (e.g. on-call), or swaps them
+    #[clap(subcommand)]
+    Rotation(RotationCmd),
+    /// Adds the flights of a travel itinerary (the .ics file of an airline, or the text of
+    /// its confirmation email): the flights, from the check-in, and the arrivals, in the
+    /// time zones of the airports
+    Itinerary(Itinerary),
+    /// Exports the busy intervals of the calendar as an iCalendar VFREEBUSY component,
+    /// without the details of the events
+    #[clap(name = "freebusy")]
+    FreeBusy(FreeBusy),
+    /// Aggregates the events by day or by week (busy hours, number of events, hours by tag),
+    /// e.g. to plot them
+    Stats(Stats),
+    /// Draws the hours scheduled per day of a year as a heatmap, a column per week
+    Heatmap(Heatmap),
+    /// Draws the events of a project as a timeline
+    #[clap(subcommand)]
+    Project(ProjectCmd),
+    /// Pulls the events of an Exchange/Office 365 calendar into this one: the events
+    /// changed both locally and in Exchange since the last pull are conflicts to resolve
+    Exchange(Exchange),
+    /// Lists and resolves the conflicts between local and remote changes left by the pulls
+    #[clap(subcommand)]
+    Sync(SyncCmd),
+    /// Subscribes the calendar to a remote iCalendar feed: its events are replaced by those
+    /// of the feed, refreshed periodically
+    Subscribe(Subscribe),
+    /// Fetches the feed the calendar is subscribed to, if stale
+    Refresh(Refresh),
+    /// Opens an interactive shell on the calendar
+    Shell,
+    /// Manages the users of the daemon and their access to the calendars
+    #[clap(subcommand)]
+    User(UserCmd),
+    /// Shows the audit log of a calendar: who modified it, when and how
+    Log(Log),
+    /// Takes snapshots of a calendar and restores it to one of them
+    #[clap(subcommand)]
+    Snapshot(SnapshotCmd),
+    /// Writes all the calendars and the configuration as a single JSON document, e.g. for
+    /// backups or to be queried with jq
+    Dump(Dump),
+    /// Restores the calendars and the configuration written by dump
+    Restore(Restore),
+    /// Checks the calendars and the attachments in the data directory for problems, and
+    /// repairs the ones it can
+    Doctor(Doctor),
+    /// Runs the housekeeping of the data directory, e.g. from cron: deletes the old
+    /// snapshots, archives the old events and refreshes the feeds (as set in the
+    /// configuration), removes the temporary files, compacts the change logs and checks for
+    /// problems
+    Maintenance,
+    /// Lists and merges the conflicting copies of the files left in the data directory by
+    /// sync tools (Syncthing, Dropbox)
+    #[clap(subcommand)]
+    Conflicts(ConflictsCmd),
+    /// Lists the rooms and other resources the events can book, and the ones double-booked
+    /// by the events of the calendars
+    #[clap(subcommand)]
+    Resources(ResourcesCmd),
+    /// Checks iCalendar files, e.g. the feeds published by a team in CI
+    #[clap(subcommand)]
+    Ics(IcsCmd),
+    /// Prints the path of the data directory, or of the file of a calendar
+    #[clap(name = "path")]
+    DataPath(DataPath),
+    /// Opens the data directory in the file manager
+    OpenDataDir,
 }
 
 #[derive(Args)]
@@ -223,14 +1010,95 @@ pub struct Add {
     /// The event's location, as a string
     location: Option<String>,
     #[clap(group = "input")]
-    /// The event's recurrence
+    /// The event's recurrence: cadence, repetitions and optionally interval ("weekly 10 2"),
+    /// the day of the month ("monthly 12 on third thu") or a cron expression ("cron(0 9 * * 1-5) 20").
+    /// The occurrences keep their local time across DST, unless it ends with utc ("daily 5 utc")
     recurrence: Option<String>,
     #[clap(group = "input")]
     // The event's tags
     tags: Vec<String>,
+    #[clap(long, group = "input")]
+    /// Whether the event blocks its time (busy) or not (free, as holidays or reminders)
+    transparency: Option<Transparency>,
+    #[clap(long, group = "input")]
+    /// Who may see the details of the event: public, private or confidential
+    class: Option<Class>,
+    #[clap(long, group = "input")]
+    /// What happens to the occurrences falling on holidays: skip, or shift to the next
+    /// working day
+    on_holiday: Option<HolidayAction>,
+    #[clap(long, group = "input", requires = "on-holiday")]
+    /// Id of the calendar whose events are holidays for the occurrences of this event
+    holidays: Option<String>,
+    #[clap(long, group = "input", requires = "on-holiday")]
+    /// Treats saturdays and sundays as holidays for the occurrences of this event
+    weekends: bool,
+    #[clap(long, group = "input")]
+    /// Relates the event to the event with this eid (can be repeated)
+    related_to: Vec<u64>,
+    #[clap(long, group = "input")]
+    /// Makes the event depend on the event with this eid, that has to end before it starts
+    /// (can be repeated)
+    depends_on: Vec<u64>,
+    #[clap(long, group = "input")]
+    /// Position of the location of the event, as latitude and longitude ("41.9,12.5")
+    geo: Option<Geo>,
+    #[clap(long, group = "input")]
+    /// Time zone of the location ("America/New_York", or "auto" to infer it from --geo): the
+    /// start is in this zone, and the event is shown in it too
+    zone: Option<String>,
+    #[clap(long, group = "input")]
+    /// Reminds of each occurrence this long before its start ("1w", "1d", "2h", "30m"):
+    /// can be repeated, for several reminders
+    remind: Vec<String>,
+    #[clap(long, group = "input")]
+    /// Start the occurrences at a time relative to the sun instead of the start time
+    /// ("30m before sunset", "1h after sunrise"), computed for every date at the position of
+    /// the event or at the location in the configuration
+    sun: Option<String>,
+    #[clap(long, group = "input")]
+    /// Books the room or other resource with this name, among the ones in the configuration:
+    /// can be repeated
+    resource: Vec<String>,
+    #[clap(long, group = "input")]
+    /// Color the event is shown in: red, orange, yellow, green, cyan, blue, purple, pink or
+    /// gray
+    color: Option<Color>,
+    #[clap(long, group = "input")]
+    /// Marks the event as important, emphasized in the lists
+    important: bool,
+    #[clap(long, group = "input")]
+    /// Marks the event as a milestone of a project, with no duration: it does not conflict
+    /// with the other events, and it is drawn as a diamond in the timelines
+    milestone: bool,
+    #[clap(long, group = "input", conflicts_with_all = &["sun", "zone"])]
+    /// Starts the event after the end of (the first occurrence of) the event with this eid:
+    /// the arguments after the description are then the duration, location, recurrence and
+    /// tags
+    after: Option<u64>,
+    #[clap(long, group = "input", requires = "after")]
+    /// How long after the end of that event to start ("15m", "1h"), right after it if missing
+    gap: Option<String>,
+    #[clap(long, group = "input", requires = "after")]
+    /// Keeps the event scheduled after that one: shifting that event offers to shift this one
+    /// by the same offset
+    keep_dependency: bool,
     #[clap(long, group = "ics", conflicts_with = "input")]
-    /// Load the event to be added from an .ics file (iCalendar format)
+    /// Load the events to be added from an .ics file (iCalendar format), or from the .ics
+    /// files in an Apple Calendar backup bundle (.icbu)
     from_file: Option<String>,
+    #[clap(long, conflicts_with_all = &["input", "ics"])]
+    /// Read the events to be added from the standard input: an iCalendar document, a JSON
+    /// event or array of events, or one event per line, with the same arguments as this
+    /// subcommand
+    stdin: bool,
+    #[clap(long, conflicts_with_all = &["input", "ics", "stdin"])]
+    /// Ask for the fields of the event one at a time, with defaults and tag completion
+    interactive: bool,
+    #[clap(long, conflicts_with_all = &["input", "ics", "stdin", "interactive"])]
+    /// Read the events to be added from the system clipboard: an iCalendar document, JSON
+    /// events or one event per line, as for --stdin
+    from_clipboard: bool,
 }
 
 #[derive(Args)]
@@ -258,27 +1126,171 @@ pub struct Edit {
     /// The event's location, as a string
     location: Option<String>,
     #[clap(group = "input")]
-    /// The event's recurrence
+    /// The event's recurrence: cadence, repetitions and optionally interval ("weekly 10 2"),
+    /// the day of the month ("monthly 12 on third thu") or a cron expression ("cron(0 9 * * 1-5) 20").
+    /// The occurrences keep their local time across DST, unless it ends with utc ("daily 5 utc")
     recurrence: Option<String>,
     #[clap(group = "input")]
     // The event's tags
     tags: Vec<String>,
+    #[clap(long, group = "input")]
+    /// Whether the event blocks its time (busy) or not (free, as holidays or reminders)
+    transparency: Option<Transparency>,
+    #[clap(long, group = "input")]
+    /// Who may see the details of the event: public, private or confidential
+    class: Option<Class>,
+    #[clap(long, group = "input")]
+    /// What happens to the occurrences falling on holidays: skip, or shift to the next
+    /// working day
+    on_holiday: Option<HolidayAction>,
+    #[clap(long, group = "input", requires = "on-holiday")]
+    /// Id of the calendar whose events are holidays for the occurrences of this event
+    holidays: Option<String>,
+    #[clap(long, group = "input", requires = "on-holiday")]
+    /// Treats saturdays and sundays as holidays for the occurrences of this event
+    weekends: bool,
+    #[clap(long, group = "input")]
+    /// Relates the event to the event with this eid (can be repeated)
+    related_to: Vec<u64>,
+    #[clap(long, group = "input")]
+    /// Position of the location of the event, as latitude and longitude ("41.9,12.5")
+    geo: Option<Geo>,
+    #[clap(long, group = "input")]
+    /// Replaces the reminders of each occurrence with the ones due this long before its start
+    /// ("1w", "1d"), or removes them if none: can be repeated
+    remind: Vec<String>,
+    #[clap(long, group = "input")]
+    /// Start the occurrences at a time relative to the sun ("30m before sunset"), or at the
+    /// start time again if none
+    sun: Option<String>,
+    #[clap(long, group = "input")]
+    /// Replaces the resources booked by the event with the ones with this name, or frees them
+    /// if none: can be repeated
+    resource: Vec<String>,
+    #[clap(long, group = "input")]
+    /// Color the event is shown in (red, orange, yellow, green, cyan, blue, purple, pink or
+    /// gray), or the default one if "none"
+    color: Option<String>,
+    #[clap(long, group = "input", conflicts_with = "not-important")]
+    /// Marks the event as important, emphasized in the lists
+    important: bool,
+    #[clap(long, group = "input")]
+    /// Marks the event as not important
+    not_important: bool,
+    #[clap(long, group = "input", conflicts_with = "not-milestone")]
+    /// Marks the event as a milestone, with no duration (unless given)
+    milestone: bool,
+    #[clap(long, group = "input")]
+    /// Marks the event as not a milestone
+    not_milestone: bool,
+    #[clap(long, group = "input", requires = "pause-until")]
+    /// Pauses the occurrences of a recurrent event from this date (dd/mm/yyyy), e.g. during
+    /// the holidays: they still count as repetitions
+    pause_from: Option<String>,
+    #[clap(long, group = "input", requires = "pause-from")]
+    /// Last date (dd/mm/yyyy) of the pause of the occurrences
+    pause_until: Option<String>,
+    #[clap(long, group = "input", conflicts_with = "pause-from")]
+    /// Resumes the occurrences of a paused recurrent event, removing all its pauses
+    resume: bool,
+    #[clap(long, group = "input")]
+    /// Makes the event depend on the event with this eid, that has to end before it starts
+    /// (can be repeated)
+    depends_on: Vec<u64>,
+    #[clap(long, group = "input", conflicts_with = "depends-on")]
+    /// Removes the dependencies of the event on other events
+    no_dependencies: bool,
+    #[clap(
+        long,
+        conflicts_with_all = &[
+            "title", "description", "start-date", "start-time", "duration", "location",
+            "recurrence", "tags", "transparency", "class", "on-holiday", "related-to", "geo",
+            "remind", "sun", "resource", "color", "important", "not-important", "milestone",
+            "not-milestone", "pause-from", "pause-until", "resume", "depends-on",
+            "no-dependencies", "from-file",
+        ]
+    )]
+    /// Edit all the fields of the event in $VISUAL (or $EDITOR), as a commented TOML buffer
+    editor: bool,
     #[clap(long, group = "ics", conflicts_with = "input")]
     /// Load the events to be modified from an .ics file (iCalendar format)
     from_file: Option<String>,
 }
 
 #[derive(Args)]
-pub struct Remove {
-    /// The id of the event to be removed
+pub struct Show {
+    /// The eid of the event to be shown
     eid: u64,
-    #[clap(short, long)]
-    /// Delete all events starting at the given date
+    #[clap(long, conflicts_with = "ics")]
+    /// Shows the event as JSON, as it is stored
+    json: bool,
+    #[clap(long)]
+    /// Shows the event as an iCalendar VEVENT
+    ics: bool,
+}
+
+#[derive(Args)]
+pub struct Share {
+    /// The eid of the event to be shared
+    eid: u64,
+    /// writes the event to this file, instead of printing it
+    #[clap(long)]
+    out: Option<String>,
+    /// shows the event as a QR code, for a phone to scan and import it (with the `qr` feature)
+    #[clap(long, conflicts_with = "out")]
+    qr: bool,
+    /// hides the details of the event if it is not public, leaving only its times
+    #[clap(long)]
+    redact: bool,
+}
+
+#[derive(Args)]
+pub struct Export {
+    /// writes the events to this file, instead of printing them
+    #[clap(long)]
+    out: Option<String>,
+    /// replaces the titles, locations and resources with stable pseudonyms (e.g. "Event 3")
+    /// and scrambles the descriptions, replacing the email addresses in them: the times,
+    /// durations and recurrences are kept
+    #[clap(long)]
+    anonymize: bool,
+    /// hides the details of the events that are not public, leaving only their times
+    #[clap(long)]
+    redact: bool,
+    /// what the events are written as
+    #[clap(long, value_enum, default_value = "ics")]
+    format: ExportFormat,
+    /// first day of the Gantt diagram (the first start of the events if missing)
+    #[clap(long, value_parser = parse_date)]
+    from: Option<NaiveDate>,
+    /// last day of the Gantt diagram (the last end of the events if missing)
+    #[clap(long, value_parser = parse_date)]
+    until: Option<NaiveDate>,
+    /// only the events with this tag in the Gantt diagram
+    #[clap(long)]
+    tag: Option<String>,
+}
+
+#[derive(Args)]
+pub struct Import {
+    /// the .ics file (or Apple Calendar backup bundle) with the events
+    file: String,
+    /// prints the events that would be added, updated and removed, without importing them
+    #[clap(long)]
+    diff: bool,
+}
+
+#[derive(Args)]
+pub struct Remove {
+    /// The id of the event to be removed
+    eid: u64,
+    #[clap(short, long)]
+    /// Delete all events starting at the given date
     from: Option<String>,
     #[clap(short, long)]
     /// Delete all events until the given date
     to: Option<String>,
-    #[clap(short, long)]
+    #[clap(long)]
     /// Filter function for events to be removed
     filter: Option<String>,
     #[clap(short, long)]
@@ -286,7 +1298,7 @@ pub struct Remove {
     all: bool,
 }
 
-#[derive(Args)]
+#[derive(Args, Clone, Serialize, Deserialize)]
 pub struct Filter {
     /// filters events occurring today
     #[clap(short, long)]
@@ -306,6 +1318,556 @@ pub struct Filter {
     /// filters by tag
     #[clap(long)]
     tag: Option<String>,
+    /// lists the events related to the event with this eid, directly or not
+    #[clap(long)]
+    #[serde(default)]
+    related_to: Option<u64>,
+    /// filters events created since the given date (dd/mm/yyyy, optionally followed by hh:mm)
+    #[clap(long, value_parser = parse_since)]
+    #[serde(default)]
+    created_after: Option<NaiveDateTime>,
+    /// filters events modified since the given date (dd/mm/yyyy, optionally followed by hh:mm)
+    #[clap(long, value_parser = parse_since)]
+    #[serde(default)]
+    modified_since: Option<NaiveDateTime>,
+    /// hides the titles and descriptions of the events that are not public
+    #[clap(long)]
+    #[serde(default)]
+    redact: bool,
+    /// shows the whole descriptions under the events
+    #[clap(long)]
+    #[serde(default)]
+    full: bool,
+    /// lists only the events marked as important
+    #[clap(long)]
+    #[serde(default)]
+    important_only: bool,
+    /// lists the dependencies that are not met instead: the events starting before the end
+    /// of an event they depend on, or depending on an event removed
+    #[clap(long)]
+    #[serde(default)]
+    blocked: bool,
+    /// columns the table of events is fitted to, if any (see [`Filter::fit_terminal`])
+    #[clap(skip)]
+    #[serde(default)]
+    width: Option<usize>,
+    /// whether the events are drawn in their colors (see [`Filter::fit_terminal`])
+    #[clap(skip)]
+    #[serde(default)]
+    color: bool,
+}
+
+impl Filter {
+    /// Fits the events listed to the width of the terminal, if the output is one, and colors
+    /// them unless NO_COLOR is set
+    pub fn fit_terminal(self) -> Filter {
+        let width = terminal_size::terminal_size().map(|(w, _)| usize::from(w.0));
+        let color = env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal();
+        Filter {
+            width: width.or(self.width),
+            color: color || self.color,
+            ..self
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct Subscribe {
+    /// URL of the feed
+    url: String,
+    /// minutes after which the feed is fetched again
+    #[clap(long, default_value_t = subscription::DEFAULT_REFRESH_MINUTES)]
+    every: u32,
+}
+
+#[derive(Args)]
+pub struct Refresh {
+    /// fetches the feed even if it is not stale
+    #[clap(long)]
+    force: bool,
+}
+
+#[derive(Args)]
+pub struct Exchange {
+    /// id of the Exchange calendar to pull (the default one if missing)
+    #[clap(long)]
+    source: Option<String>,
+    /// pulls all the events again, instead of the changes since the last pull
+    #[clap(long)]
+    full: bool,
+    /// how the events changed both locally and in Exchange are resolved (by default, the
+    /// user is asked if running in a terminal, otherwise they are deferred)
+    #[clap(long, value_enum)]
+    on_conflict: Option<ConflictPolicy>,
+}
+
+#[derive(Args)]
+pub struct Digest {
+    /// the agenda of the rest of the week, instead of tomorrow's
+    #[clap(short, long)]
+    week: bool,
+    /// format of the agenda
+    #[clap(long, value_enum, default_value = "text")]
+    format: DigestFormat,
+    /// sends the agenda to this address (through the SMTP server in the configuration),
+    /// instead of printing it
+    #[clap(long)]
+    email: Option<String>,
+    /// hides the titles and descriptions of the events that are not public
+    #[clap(long)]
+    redact: bool,
+    /// lists the reminders due in the days of the agenda, after the events
+    #[clap(long)]
+    reminders: bool,
+}
+
+#[derive(Args)]
+pub struct Countdown {
+    /// only the events with this tag
+    #[clap(long)]
+    tag: Option<String>,
+    /// how many events to list
+    #[clap(long, default_value = "10")]
+    limit: usize,
+    /// events starting within this many hours are shown in red (overrides the configuration)
+    #[clap(long)]
+    urgent_hours: Option<u32>,
+    /// events starting within this many days are shown in yellow (overrides the configuration)
+    #[clap(long)]
+    soon_days: Option<u32>,
+    /// never colors the output (it is not colored anyway if not a terminal, or if NO_COLOR is set)
+    #[clap(long)]
+    no_color: bool,
+}
+
+#[derive(Args)]
+pub struct Plan {
+    /// file listing the tasks, one per line: title, estimated duration in hours and tags
+    #[clap(long)]
+    tasks: String,
+    /// period to schedule the tasks in: today, tomorrow, this week, next week
+    /// or %d/%m/%Y-%d/%m/%Y
+    #[clap(long, default_value = "this week")]
+    within: String,
+    /// adds the proposed events to the calendar, instead of just showing them
+    #[clap(long)]
+    accept: bool,
+}
+
+#[derive(Args)]
+pub struct Schedule {
+    /// the other calendars that must be free (ids or names, separated by commas)
+    #[clap(long, value_delimiter = ',')]
+    with: Vec<String>,
+    /// busy times of other attendees, read from an iCalendar file with VFREEBUSY components
+    /// (can be repeated)
+    #[clap(long)]
+    freebusy: Vec<String>,
+    /// duration of the meeting, e.g. 1h, 45m or 1h30m
+    #[clap(long)]
+    duration: String,
+    /// period to find the meeting times in: today, tomorrow, this week, next week,
+    /// next N days, next N weeks or %d/%m/%Y-%d/%m/%Y
+    #[clap(long, default_value = "next 5 days")]
+    within: String,
+    /// how many meeting times are proposed, from the best one
+    #[clap(long, default_value = "5")]
+    limit: usize,
+    /// adds a tentative event at the proposed time with this rank (1 is the best one) to this
+    /// calendar and to the other calendars
+    #[clap(long)]
+    book: Option<usize>,
+    /// title of the event booked
+    #[clap(long, default_value = "Meeting")]
+    title: String,
+}
+
+#[derive(Args)]
+pub struct FreeBusy {
+    /// period to export: today, tomorrow, this week, next week or %d/%m/%Y-%d/%m/%Y
+    #[clap(long, default_value = "this week")]
+    within: String,
+    /// writes the VFREEBUSY component to this file, instead of printing it
+    #[clap(long)]
+    output: Option<String>,
+}
+
+#[derive(Args)]
+pub struct Stats {
+    /// period to aggregate: today, tomorrow, this week, next week or %d/%m/%Y-%d/%m/%Y
+    #[clap(long, default_value = "this week")]
+    within: String,
+    /// aggregates by day or by week
+    #[clap(long, value_enum, default_value = "day")]
+    by: Bucket,
+    /// format of the aggregates
+    #[clap(long, value_enum, default_value = "text")]
+    output: StatsFormat,
+    /// draws the busy hours, the events and the hours of each tag as sparklines, after the
+    /// table
+    #[clap(long)]
+    sparkline: bool,
+}
+
+#[derive(Args)]
+pub struct Heatmap {
+    /// year to draw (the current one if missing)
+    #[clap(long)]
+    year: Option<i32>,
+    /// never colors the output (it is not colored anyway if not a terminal, or if NO_COLOR is set)
+    #[clap(long)]
+    no_color: bool,
+}
+
+#[derive(Subcommand)]
+pub enum ProjectCmd {
+    /// Draws the events (grouped by their first tag) as bars over the days, a column per day,
+    /// and the milestones as diamonds
+    Timeline {
+        /// only the events with this tag
+        #[clap(long)]
+        tag: Option<String>,
+        /// first day drawn (the monday of this week if missing)
+        #[clap(long, value_parser = parse_date)]
+        from: Option<NaiveDate>,
+        /// last day drawn (four weeks after the first one if missing)
+        #[clap(long, value_parser = parse_date)]
+        until: Option<NaiveDate>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AvailabilityCmd {
+    /// Adds an availability window
+    Add {
+        /// what the window is for, e.g. "office hours"
+        #[clap(long, default_value = "available")]
+        label: String,
+        /// days of the window, e.g. tue,thu or mon-fri
+        #[clap(long)]
+        days: String,
+        /// hours of the window, e.g. 14:00-17:00
+        #[clap(long)]
+        hours: String,
+    },
+    /// Removes an availability window, given its index in the list
+    Remove { index: usize },
+    /// Lists the availability windows
+    List,
+    /// Exports the slots still bookable in the availability windows
+    Export {
+        /// period to export: today, tomorrow, this week, next week or %d/%m/%Y-%d/%m/%Y
+        #[clap(long, default_value = "next week")]
+        within: String,
+        /// format of the export
+        #[clap(long, value_enum, default_value = "html")]
+        format: AvailabilityFormat,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SlotsCmd {
+    /// Writes a static HTML page listing the appointments free in the availability windows
+    /// (or in the working hours, without any), each with an invitation to send back to book it
+    Publish {
+        /// duration of the appointments, e.g. 30m, 1h or 1h30m
+        #[clap(long, default_value = "30m")]
+        duration: String,
+        /// period to publish: today, tomorrow, this week, next week, next N days,
+        /// next N weeks or %d/%m/%Y-%d/%m/%Y
+        #[clap(long, default_value = "next 2 weeks")]
+        within: String,
+        /// writes the page to this file, instead of printing it
+        #[clap(long)]
+        output: Option<String>,
+    },
+    /// Books the appointments requested by the invitations in an .ics file, if still free
+    Accept { file: String },
+}
+
+#[derive(Args)]
+pub struct Log {
+    /// id or name of the calendar
+    calendar: String,
+    /// Only show the modifications of the event with this eid
+    #[clap(long)]
+    event: Option<u64>,
+}
+
+#[derive(Args)]
+pub struct Dump {
+    /// File to write the dump to (the standard output if missing or -)
+    file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct CheckInArgs {
+    /// The eid of the event
+    eid: u64,
+    /// how the event went
+    #[clap(long)]
+    note: String,
+    /// from 1 to 5
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..=5))]
+    rating: Option<u8>,
+}
+
+#[derive(Args)]
+pub struct Review {
+    /// the past events of this week, instead of today's
+    #[clap(short, long)]
+    week: bool,
+}
+
+#[derive(Args)]
+#[clap(group(ArgGroup::new("selection").required(true).multiple(true)))]
+pub struct Shift {
+    /// the eids of the events to be moved
+    #[clap(group = "selection")]
+    eids: Vec<u64>,
+    /// how much to move the events by ("1w", "2d", "1h30m"), backward if it starts with '-'
+    #[clap(long, allow_hyphen_values = true)]
+    by: String,
+    /// moves the events with this tag
+    #[clap(long, group = "selection")]
+    tag: Option<String>,
+    /// moves the events meeting all the conditions of this expression, joined by "and":
+    /// title, description, location, tag or color compared with =, != or ~ (contains), date
+    /// with =, !=, <, <=, > or >=, important or recurrent with = true/false. For example
+    /// 'tag = course101 and date >= 01/10/2022'
+    #[clap(long = "where", group = "selection", value_parser = Query::parse)]
+    query: Option<Query>,
+    /// moves the events scheduled after the ones moved (see add --keep-dependency) too,
+    /// without asking
+    #[clap(long, conflicts_with = "without-dependents")]
+    with_dependents: bool,
+    /// leaves the events scheduled after the ones moved where they are, without asking
+    #[clap(long)]
+    without_dependents: bool,
+}
+
+#[derive(Args)]
+pub struct Join {
+    eid1: u64,
+    eid2: u64,
+    /// the longest gap allowed between the end of the first event and the start of the second
+    #[clap(long, default_value = "5m")]
+    tolerance: String,
+}
+
+#[derive(Args)]
+pub struct Done {
+    /// The eid of the habit
+    eid: u64,
+    /// the day of the occurrence done (%d/%m/%Y), today if missing
+    #[clap(long)]
+    date: Option<String>,
+}
+
+#[derive(Args)]
+pub struct Snooze {
+    /// The eid of the event
+    eid: u64,
+    /// How long to snooze the reminder for ("10m", "1h")
+    #[clap(long = "for", default_value = "10m")]
+    duration: String,
+}
+
+#[derive(Args)]
+pub struct DataPath {
+    /// Print the path of the file of this calendar (by id or name)
+    #[clap(long)]
+    calendar: Option<String>,
+}
+
+#[derive(Args)]
+pub struct Restore {
+    /// File to read the dump from (the standard input if missing or -)
+    file: Option<PathBuf>,
+    /// Replace the calendars and the configuration already in the data directory
+    #[clap(long)]
+    overwrite: bool,
+}
+
+#[derive(Args)]
+pub struct Doctor {
+    /// Repair the problems that can be repaired automatically
+    #[clap(long)]
+    fix: bool,
+    /// Report the calendars taking more than this many megabytes, with their change log
+    #[clap(long, default_value = "10")]
+    max_size_mb: u64,
+}
+
+#[derive(Subcommand)]
+pub enum SnapshotCmd {
+    /// Takes a snapshot of the current state of a calendar (by id or name)
+    Create { calendar: String },
+    /// Lists the snapshots of a calendar, from the oldest one
+    List { calendar: String },
+    /// Restores a calendar to a snapshot, given a prefix of its hash or a date: the last
+    /// snapshot taken by then (e.g. 11/07/2022 or "11/07/2022 18:00"). A snapshot of the
+    /// current state is taken first
+    Restore { calendar: String, at: String },
+}
+
+#[derive(Subcommand)]
+pub enum IcsCmd {
+    /// Checks the file against the structural rules of RFC 5545, listing the problems found:
+    /// fails if there are any
+    Validate { file: String },
+}
+
+#[derive(Subcommand)]
+pub enum ConflictsCmd {
+    /// Lists the conflicting copies, along with the file they are a copy of
+    List,
+    /// Merges the conflicting copies of the calendars into them (the events changed since
+    /// their last snapshot, or the latest version of the ones changed in both) and removes them
+    Resolve,
+}
+
+#[derive(Subcommand)]
+pub enum ResourcesCmd {
+    /// Lists the resources in the configuration, along with their capacity
+    List,
+    /// Lists the times from now on when a resource is booked by more events than its capacity
+    Conflicts,
+}
+
+#[derive(Subcommand)]
+pub enum SyncCmd {
+    /// Lists the conflicts left unresolved by the pulls
+    Conflicts,
+    /// Resolves the conflict on the event with the given eid, or all of them
+    Resolve {
+        eid: Option<u64>,
+        #[clap(long, value_enum, default_value = "ask")]
+        keep: ConflictPolicy,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum UserCmd {
+    /// Adds a user, printing the token it authenticates with
+    Add { name: String },
+    /// Removes a user
+    Remove { name: String },
+    /// Gives a user read or write access to a calendar (by id, or * for all of them)
+    Grant {
+        name: String,
+        calendar: String,
+        #[clap(long, value_enum, default_value = "read")]
+        access: Access,
+    },
+    /// Takes away the access of a user to a calendar
+    Revoke { name: String, calendar: String },
+    /// Lists the users and their access to the calendars
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum LocationCmd {
+    /// Sets the working location of the days from --from to --to, replacing the one already
+    /// set on any of them, e.g. `location set office --from mon --to wed`
+    Set {
+        /// home, office or any other place
+        place: String,
+        /// first day: today, tomorrow, a weekday (the next one) or a date as dd/mm/yyyy
+        #[clap(long, default_value = "today")]
+        from: String,
+        /// last day, the first one if missing
+        #[clap(long)]
+        to: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CourseCmd {
+    /// Adds one weekly event for each day of the week the course meets on, e.g.
+    /// `course add algebra --days mon,wed --hours 10:00-11:30 --from 15/09/2022 --until 20/12/2022`
+    Add {
+        /// title of the meetings
+        title: String,
+        /// days of the meetings, e.g. mon,wed or mon-fri
+        #[clap(long)]
+        days: String,
+        /// hours of the meetings, e.g. 10:00-11:30
+        #[clap(long)]
+        hours: String,
+        /// first day of the term
+        #[clap(long)]
+        from: String,
+        /// last day of the term
+        #[clap(long)]
+        until: String,
+        /// a day without meetings (can be repeated)
+        #[clap(long)]
+        except: Vec<String>,
+        /// id of the calendar whose events are holidays, without meetings
+        #[clap(long)]
+        holidays: Option<String>,
+        /// where the course meets
+        #[clap(long)]
+        location: Option<String>,
+        /// tags of the meetings (can be repeated)
+        #[clap(long)]
+        tag: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RotationCmd {
+    /// Adds a recurring shift for each member, taking turns in order, e.g.
+    /// `rotation create --members alice,bob,carol --cadence weekly --start 05/09/2022`
+    Create {
+        /// name of the rotation, the tag of its shifts
+        #[clap(long, default_value = "on-call")]
+        name: String,
+        /// the members, in the order they take their shifts, e.g. alice,bob,carol
+        #[clap(long)]
+        members: String,
+        /// how long each shift lasts: daily or weekly
+        #[clap(long, default_value = "weekly")]
+        cadence: String,
+        /// first day of the first shift
+        #[clap(long, value_parser = parse_date)]
+        start: NaiveDate,
+        /// last day a shift can start on (13 weeks after the start if missing)
+        #[clap(long, value_parser = parse_date)]
+        until: Option<NaiveDate>,
+    },
+    /// Hands the shift covering a day over to another member, e.g. to trade shifts
+    Swap {
+        /// a day of the shift
+        #[clap(value_parser = parse_date)]
+        date: NaiveDate,
+        /// the member taking the shift
+        member: String,
+        /// name of the rotation
+        #[clap(long, default_value = "on-call")]
+        name: String,
+    },
+}
+
+#[derive(Args)]
+pub struct Itinerary {
+    /// the .ics file or the text with the itinerary: read from the standard input if not given
+    file: Option<String>,
+    /// time to be at the airport before each departure ("2h", "90m")
+    #[clap(long, default_value = "2h")]
+    check_in: String,
+    /// time to get out of the airport after each arrival, the length of the arrival events
+    #[clap(long, default_value = "30m")]
+    after: String,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum AvailabilityFormat {
+    /// a shareable HTML page
+    Html,
+    /// an iCalendar VFREEBUSY component
+    Ics,
 }
 
 #[derive(Args)]
@@ -318,78 +1880,260 @@ pub struct CalParams {
     owner: Option<String>,
 }
 
-fn ics_parse_date_time(prop: &Property) -> (chrono::NaiveDate, chrono::NaiveTime) {
-    let dt = NaiveDateTime::parse_from_str(prop.val.as_str(), "%Y%m%dT%H%M%SZ")
-        .expect("Failed to parse the DTSTART field");
-    (dt.date(), dt.time())
+/// Parses a DATE-TIME (in UTC, as "20220713T093000Z", or local, as exported by macOS along
+/// with a TZID) or a DATE value, that starts at midnight
+fn ics_parse_date_time(prop: &Property) -> Option<(chrono::NaiveDate, chrono::NaiveTime)> {
+    let val = prop.val.as_str().trim_end_matches('Z');
+    let dt = NaiveDateTime::parse_from_str(val, "%Y%m%dT%H%M%S")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(val, "%Y%m%d")
+                .ok()
+                .map(|d| d.and_time(NaiveTime::MIN))
+        });
+    if dt.is_none() {
+        report::warning(format!(
+            "Cannot parse the {} field {}",
+            prop.name.as_str(),
+            val
+        ));
+    }
+    dt.map(|dt| (dt.date(), dt.time()))
+}
+
+/// Returns the value of the parameter of the property, unquoted
+fn ics_param<'a>(prop: &'a Property, key: &str) -> Option<&'a str> {
+    prop.params
+        .iter()
+        .find(|p| p.key.as_str() == key)
+        .and_then(|p| p.val.as_ref())
+        .map(|v| v.as_str().trim_matches('"'))
+}
+
+/// Converts the BYDAY and BYSETPOS parts of a RRULE ("3TH", or "MO,TU,WE,TH,FR" and "-1")
+/// to the syntax of the day of the month of a recurrence ("3 thu"), if they describe one
+fn ics_month_day(byday: &str, bysetpos: Option<&str>) -> Option<String> {
+    let mut pos = bysetpos.map(|p| p.trim().to_string());
+    let mut days = Vec::new();
+    for day in byday.split(',') {
+        let day = day.trim();
+        if !day.is_ascii() {
+            return None;
+        }
+        let (n, code) = day.split_at(day.len().checked_sub(2)?);
+        if !n.is_empty() {
+            pos = Some(n.trim_start_matches('+').to_string());
+        }
+        days.push(match code {
+            "MO" => "mon",
+            "TU" => "tue",
+            "WE" => "wed",
+            "TH" => "thu",
+            "FR" => "fri",
+            "SA" => "sat",
+            "SU" => "sun",
+            _ => return None,
+        });
+    }
+    Some(format!("{} {}", pos?, days.join(",")))
+}
+
+/// Stores the contents of an inline ATTACH property in the data directory, returning the
+/// attachment of the event
+fn ics_attachment(prop: &Property, data_dir: &Path) -> Result<Attachment, CalendarError> {
+    let data = ics::decode_binary(prop.val.as_str())
+        .map_err(|e| CalendarError::IcsParsingFailed(format!("ATTACH: {}", e)))?;
+    let hash = blobs::store(&data, data_dir)?;
+    let filename = ["FILENAME", "X-FILENAME", "X-APPLE-FILENAME"]
+        .iter()
+        .find_map(|key| ics_param(prop, key));
+    Ok(Attachment {
+        hash,
+        size: data.len() as u64,
+        fmttype: ics_param(prop, "FMTTYPE").map(String::from),
+        filename: filename.map(String::from),
+    })
 }
 
-fn match_property(ev: &mut Event, comp: Component) {
+/// Sets the fields of the event from the properties of the VEVENT component. The inline
+/// attachments are stored in the data directory, if any, and left out otherwise
+pub(crate) fn match_property(ev: &mut Event, comp: Component, data_dir: Option<&Path>) {
+    // the recurrence is replaced by RRULE, that may come after EXDATE
+    let mut exdates = BTreeSet::new();
     for prop in comp.properties.iter() {
         match prop.name.as_str() {
-            "SUMMARY" => ev.set_title(prop.val.as_str()),
-            "DESCRIPTION" => ev.set_description(prop.val.as_str()),
+            "UID" => ev.set_uid(prop.val.as_str()),
+            "SEQUENCE" => {
+                if let Ok(sequence) = prop.val.as_str().parse() {
+                    ev.set_revision(sequence);
+                }
+            }
+            "SUMMARY" => ev.set_title(&unescape_text(prop.val.as_str())),
+            "DESCRIPTION" => ev.set_description(&unescape_text(prop.val.as_str())),
             "DTSTART" => {
-                let (date, time) = ics_parse_date_time(prop);
-                ev.set_start_date((date.day(), date.month(), date.year()));
-                ev.set_start_time((time.hour(), time.minute(), time.second()));
+                if let Some((date, time)) = ics_parse_date_time(prop) {
+                    ev.set_start_date((date.day(), date.month(), date.year()));
+                    ev.set_start_time((time.hour(), time.minute(), time.second()));
+                }
             }
             "DTEND" => {
-                let (end_date, end_time) = ics_parse_date_time(prop);
-                let start_date = ev.get_start_date();
-                let start_time = ev.get_start_time();
-                let dur = end_date.and_time(end_time) - start_date.and_time(start_time);
-                ev.set_duration(&dur);
+                if let Some((end_date, end_time)) = ics_parse_date_time(prop) {
+                    let start_date = ev.get_start_date();
+                    let start_time = ev.get_start_time();
+                    let dur = end_date.and_time(end_time) - start_date.and_time(start_time);
+                    ev.set_duration(&dur);
+                }
+            }
+            "LOCATION" => ev.set_location(&unescape_text(prop.val.as_str())),
+            "GEO" => ev.set_geo(prop.val.as_str().parse().ok()),
+            // added by Apple Calendar: the value is a geo URI, the name of the place is X-TITLE
+            "X-APPLE-STRUCTURED-LOCATION" => {
+                if let Ok(geo) = prop.val.as_str().parse() {
+                    ev.set_geo(Some(geo));
+                }
+                if ev.get_location().is_empty() {
+                    if let Some(title) = ics_param(prop, "X-TITLE") {
+                        ev.set_location(title);
+                    }
+                }
+            }
+            "X-APPLE-TRAVEL-DURATION" => {
+                if let Some(travel_time) = ics::parse_duration(prop.val.as_str()) {
+                    ev.set_travel_time(&travel_time);
+                }
+            }
+            "TRANSP" => {
+                if let Ok(transp) = prop.val.as_str().parse() {
+                    ev.set_transparency(transp);
+                }
+            }
+            "X-WORKING-LOCATION" => {
+                if let Ok(place) = unescape_text(prop.val.as_str()).parse() {
+                    ev.set_working_location(Some(place));
+                }
+            }
+            "CLASS" => {
+                if let Ok(class) = prop.val.as_str().parse() {
+                    ev.set_class(class);
+                }
+            }
+            // any CSS color name, only the ones that can be shown are kept
+            "COLOR" => {
+                if let Ok(color) = prop.val.as_str().parse() {
+                    ev.set_color(Some(color));
+                }
+            }
+            "X-CALENDA-RS-IMPORTANT" => {
+                ev.set_important(prop.val.as_str().eq_ignore_ascii_case("TRUE"));
+            }
+            "X-CALENDA-RS-MILESTONE" => {
+                ev.set_milestone(prop.val.as_str().eq_ignore_ascii_case("TRUE"));
+            }
+            "ATTACH" => {
+                let inline =
+                    ics_param(prop, "ENCODING").is_some_and(|e| e.eq_ignore_ascii_case("BASE64"));
+                if let (true, Some(data_dir)) = (inline, data_dir) {
+                    match ics_attachment(prop, data_dir) {
+                        Ok(attachment) => ev.add_attachment(attachment),
+                        Err(e) => report::warning(format!("{:?}: attachment left out", e)),
+                    }
+                }
+            }
+            // only events of this calendar can be referred to, by their eid
+            "RELATED-TO" => {
+                if let Ok(eid) = prop.val.as_str().parse() {
+                    ev.add_related(eid);
+                }
             }
-            "LOCATION" => ev.set_location(prop.val.as_str()),
             "RRULE" => {
                 let mut rec = String::new();
+                let (mut byday, mut bysetpos) = (None, None);
                 for param in prop.val.as_str().split(';') {
                     let x: Vec<&str> = param.splitn(2, '=').collect();
                     match x[0] {
                         // See https://icalendar.org/iCalendar-RFC-5545/3-3-10-recurrence-rule.html
-                        "FREQ" => rec = x[1].to_owned() + " " + &rec,
-                        "COUNT" => rec.push_str(&(x[1].to_owned() + " ")),
-                        "INTERVAL" => rec.push_str(&(x[1].to_owned() + " ")),
+                        "FREQ" if x.len() == 2 => rec = x[1].to_owned() + " " + &rec,
+                        // the first occurrence is not a repetition
+                        "COUNT" => {
+                            if let Some(count) = x.get(1).and_then(|c| c.parse::<usize>().ok()) {
+                                rec.push_str(&format!("{} ", count.saturating_sub(1)));
+                            }
+                        }
+                        "INTERVAL" if x.len() == 2 => rec.push_str(&(x[1].to_owned() + " ")),
+                        "BYDAY" => byday = x.get(1).copied(),
+                        "BYSETPOS" => bysetpos = x.get(1).copied(),
                         _ => (),
                     }
                 }
+                if let Some(month_day) = byday.and_then(|days| ics_month_day(days, bysetpos)) {
+                    rec.push_str(&format!("on {}", month_day));
+                }
                 ev.set_recurrence(&rec)
             }
+            "EXDATE" => {
+                for val in prop.val.as_str().split(',') {
+                    if let Some(date) = val
+                        .get(..8)
+                        .and_then(|d| NaiveDate::parse_from_str(d, "%Y%m%d").ok())
+                    {
+                        exdates.insert(date);
+                    }
+                }
+            }
             // property ignored by the event struct
             _ => (),
         }
     }
+    if !exdates.is_empty() {
+        ev.set_exdates(exdates);
+    }
+}
+
+/// Collects the .ics files inside the directory, recursively
+fn ics_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            ics_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "ics") {
+            files.push(path);
+        }
+    }
+    Ok(())
 }
 
-fn handle_ics(fpath: &str) -> Result<Vec<Event>, String> {
+/// An event read to be imported, and whether it cancels the stored event with the same UID
+/// (METHOD:CANCEL or STATUS:CANCELLED in iCalendar)
+pub(crate) struct Imported {
+    pub(crate) event: Event,
+    cancelled: bool,
+}
+
+impl From<Event> for Imported {
+    fn from(event: Event) -> Self {
+        Imported {
+            event,
+            cancelled: false,
+        }
+    }
+}
+
+/// Reads the events in an .ics file, or in all the .ics files inside an Apple Calendar
+/// backup bundle (.icbu), storing their attachments in the data directory
+fn handle_ics(fpath: &str, data_dir: &Path) -> Result<Vec<Imported>, String> {
     let path = Path::new(fpath);
-    if path.exists() && path.extension().unwrap_or(OsStr::new("ics")) == "ics" {
-        let ics_file = fs::File::open(path);
-        if let Err(e) = ics_file {
-            return Err(e.to_string());
-        }
-        let mut buf = String::new();
-        if let Err(e) = ics_file.unwrap().read_to_string(&mut buf) {
-            return Err(format!("Cannot read ics file: {}", e));
-        } else {
-            // File read into the buf String: parse it with the iCalendar library
-            let str_unfolded = icalendar::parser::unfold(&buf);
-            return match icalendar::parser::read_calendar(&str_unfolded) {
-                Ok(cal) => {
-                    let mut events = Vec::new();
-                    for comp in cal.components {
-                        if comp.name == "VEVENT" {
-                            let mut e = Event::default();
-                            match_property(&mut e, comp);
-                            events.push(e);
-                        }
-                    }
-                    Ok(events)
-                }
-                Err(s) => Err(format!("Error parsing {}: {}", path.display(), s)),
-            };
+    if path.is_dir() && path.extension().is_some_and(|ext| ext == "icbu") {
+        let mut files = Vec::new();
+        ics_files(path, &mut files).map_err(|e| format!("Cannot read {}: {}", fpath, e))?;
+        files.sort();
+        let mut events = Vec::new();
+        for file in files {
+            events.append(&mut read_ics(&file, data_dir)?);
         }
+        return Ok(events);
+    }
+    if path.is_file() && path.extension().unwrap_or(OsStr::new("ics")) == "ics" {
+        return read_ics(path, data_dir);
     }
     Err(format!(
         "{} does not exists or is not a valid .ics file",
@@ -397,210 +2141,2905 @@ fn handle_ics(fpath: &str) -> Result<Vec<Event>, String> {
     ))
 }
 
-pub fn handle_add(cal: &mut Calendar, x: Add) -> Result<bool, CalendarError> {
-    // if the flag --from-file is given it takes precedence
-    if let Some(path) = x.from_file {
-        match handle_ics(&path) {
-            Ok(events) => {
-                let mut imported: usize = 0;
-                let total_events = events.len();
-                for ev in events {
-                    if cal.add_event(ev) {
-                        imported += 1;
-                    }
-                }
-                info!(
-                    "Imported {} (total: {}) events from {}",
-                    imported, total_events, &path
-                );
-                println!(
-                    "Imported {} (total: {}) events from {}",
-                    imported, total_events, &path
-                );
-                Ok(true)
-            }
-            Err(e) => Err(CalendarError::IcsParsingFailed(e)),
-        }
-    } else {
-        let default_values = Event::default();
-        let title = match x.title {
-            Some(val) => val,
-            None => default_values.get_title().to_string(),
-        };
-        let description = match x.description {
-            Some(val) => val,
-            None => default_values.get_description().to_string(),
-        };
-        let start_date = match x.start_date {
-            Some(val) => val,
-            None => default_values.get_start_date().to_string(),
-        };
-        let start_time = match x.start_time {
-            Some(val) => val,
-            None => default_values.get_start_time().to_string(),
-        };
-        let duration = match x.duration {
-            Some(val) => val.parse().unwrap(),
-            None => default_values.get_duration() as f32,
-        };
-        let loc = x.location.as_deref();
-        let rec = x.recurrence.as_deref();
+fn read_ics(path: &Path, data_dir: &Path) -> Result<Vec<Imported>, String> {
+    let buf = fs::read_to_string(path).map_err(|e| format!("Cannot read ics file: {}", e))?;
+    parse_ics(&buf, Some(data_dir)).map_err(|s| format!("Error parsing {}: {}", path.display(), s))
+}
 
-        let tags = if !x.tags.is_empty() {
-            Some(x.tags)
-        } else {
-            None
-        };
+/// Parses the events of an iCalendar document
+pub(crate) fn parse_ics(buf: &str, data_dir: Option<&Path>) -> Result<Vec<Imported>, String> {
+    // the iCalendar library panics on the documents cut short, e.g. pasted partially
+    if !buf.trim_end().ends_with("END:VCALENDAR") {
+        return Err(String::from(
+            "END:VCALENDAR missing, the document is incomplete",
+        ));
+    }
+    // parse the file with the iCalendar library
+    let str_unfolded = icalendar::parser::unfold(buf);
+    if let Some(e) = ics::structure_error(&str_unfolded) {
+        return Err(e);
+    }
+    let cal = icalendar::parser::read_calendar(&str_unfolded)?;
+    // the organizer cancels all the events of the document at once with METHOD:CANCEL
+    let cancel = cal
+        .properties
+        .iter()
+        .any(|p| p.name == "METHOD" && p.val.as_str().eq_ignore_ascii_case("CANCEL"));
+    let mut events = Vec::new();
+    for comp in cal.components {
+        if comp.name == "VEVENT" {
+            let cancelled = cancel
+                || comp.properties.iter().any(|p| {
+                    p.name == "STATUS" && p.val.as_str().eq_ignore_ascii_case("CANCELLED")
+                });
+            let mut event = Event::default();
+            match_property(&mut event, comp, data_dir);
+            events.push(Imported { event, cancelled });
+        }
+    }
+    Ok(events)
+}
+
+/// Imports the events in the .ics file (or Apple Calendar backup bundle) at `path`
+fn import_file(cal: &mut Calendar, path: &str, data_dir: &Path) -> Result<bool, CalendarError> {
+    let events = handle_ics(path, data_dir).map_err(CalendarError::IcsParsingFailed)?;
+    let mut imported: usize = 0;
+    let total_events = events.len();
+    let mut summary = ImportSummary::default();
+    for ev in events {
+        if summary.import(cal, ev) {
+            imported += 1;
+        }
+    }
+    summary.report();
+    info!(
+        "Imported {} (total: {}) events from {}",
+        imported, total_events, path
+    );
+    println!(
+        "Imported {} (total: {}) events from {}",
+        imported, total_events, path
+    );
+    Ok(true)
+}
+
+/// Describes the changes importing the events would make to the calendar, one per line:
+/// the events added (+), updated with the fields changed (~) and removed as cancelled (-)
+fn import_diff(cal: &Calendar, events: Vec<Imported>) -> String {
+    let mut after = cal.clone();
+    let mut summary = ImportSummary::default();
+    for ev in events {
+        summary.import(&mut after, ev);
+    }
+    let diff = cal.diff(&after);
+    let describe = |eid: u64, ev: &Event| {
+        format!(
+            "\"{}\" [{}] {} ({})",
+            ev.get_title(),
+            eid,
+            ev.get_start().format("%d/%m/%Y %H:%M"),
+            ev.get_uid().unwrap_or("no UID")
+        )
+    };
+    let mut out = String::new();
+    for eid in diff.added.iter() {
+        if let Some(ev) = after.peek_event(*eid) {
+            out.push_str(&format!("+ {}\n", describe(*eid, ev)));
+        }
+    }
+    for eid in diff.edited.iter() {
+        if let (Some(old), Some(new)) = (cal.peek_event(*eid), after.peek_event(*eid)) {
+            out.push_str(&format!(
+                "~ {}: {}\n",
+                describe(*eid, new),
+                sync::differing_fields(old, new).join(", ")
+            ));
+        }
+    }
+    for eid in diff.removed.iter() {
+        if let Some(ev) = cal.peek_event(*eid) {
+            out.push_str(&format!("- {}\n", describe(*eid, ev)));
+        }
+    }
+    out.push_str(&format!(
+        "{} to add, {} to update, {} to remove\n",
+        diff.added.len(),
+        diff.edited.len(),
+        diff.removed.len()
+    ));
+    out
+}
+
+pub fn handle_import(
+    cal: &mut Calendar,
+    x: Import,
+    data_dir: &Path,
+) -> Result<bool, CalendarError> {
+    if !x.diff {
+        return import_file(cal, &x.file, data_dir);
+    }
+    let events = handle_ics(&x.file, data_dir).map_err(CalendarError::IcsParsingFailed)?;
+    print!("{}", import_diff(cal, events));
+    Ok(true)
+}
+
+pub fn handle_add(cal: &mut Calendar, x: Add, data_dir: &Path) -> Result<bool, CalendarError> {
+    // if the flag --from-file is given it takes precedence
+    if let Some(path) = x.from_file {
+        import_file(cal, &path, data_dir)
+    } else if x.interactive {
+        let ev = event_from_prompts(&mut Prompt::new(Terminal::new()?), cal)?;
+        ev.check_recurrence()?;
+        let added = add_event_warn(cal, ev);
+        if let Some(eid) = added {
+            warn_over_budget(cal, eid, data_dir);
+        }
+        Ok(added.is_some())
+    } else if x.stdin || x.from_clipboard {
+        let (input, source) = if x.stdin {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            (input, "stdin")
+        } else {
+            (read_clipboard()?, "the clipboard")
+        };
+        let (added, total, failed) = add_events_from(cal, &input, data_dir);
+        info!(
+            "Imported {} (total: {}) events from {}",
+            added, total, source
+        );
+        println!(
+            "Imported {} (total: {}) events from {}, {} invalid",
+            added, total, source, failed
+        );
+        // the valid events are added anyway, unless there are none
+        Ok(failed == 0 || failed < total)
+    } else {
+        let keep = x.keep_dependency;
+        match event_from_args(x, data_dir).and_then(|ev| place_after(cal, ev, keep)) {
+            Ok(ev) => {
+                ev.check_recurrence()?;
+                ev.check_milestone()?;
+                let booked = !ev.get_resources().is_empty();
+                let added = add_event_warn(cal, ev);
+                if let (Some(eid), true) = (added, booked) {
+                    warn_double_bookings(cal, eid, data_dir);
+                }
+                if let Some(eid) = added {
+                    warn_over_budget(cal, eid, data_dir);
+                }
+                Ok(added.is_some())
+            }
+            Err(e) => Err(CalendarError::Unknown(e)),
+        }
+    }
+}
+
+/// Returns the text in the system clipboard, read with the tools of the platform
+fn read_clipboard() -> Result<String, CalendarError> {
+    let readers: &[(&str, &[&str])] = if cfg!(target_os = "macos") {
+        &[("pbpaste", &[])]
+    } else if cfg!(windows) {
+        &[(
+            "powershell",
+            &["-NoProfile", "-Command", "Get-Clipboard -Raw"],
+        )]
+    } else {
+        &[
+            ("wl-paste", &["--no-newline"]),
+            ("xclip", &["-selection", "clipboard", "-o"]),
+            ("xsel", &["--clipboard", "--output"]),
+        ]
+    };
+    let mut errors = Vec::new();
+    for (program, args) in readers {
+        match Command::new(program).args(*args).output() {
+            Ok(out) if out.status.success() => {
+                return String::from_utf8(out.stdout).map_err(|e| {
+                    CalendarError::Unknown(format!("The clipboard is not text: {}", e))
+                });
+            }
+            Ok(out) => errors.push(format!("{}: {}", program, out.status)),
+            Err(e) => errors.push(format!("{}: {}", program, e)),
+        }
+    }
+    Err(CalendarError::Unknown(format!(
+        "Cannot read the clipboard ({})",
+        errors.join(", ")
+    )))
+}
+
+/// Builds the event described by the positional arguments of the add subcommand
+pub(crate) fn event_from_args(mut x: Add, data_dir: &Path) -> Result<Event, String> {
+    let default_values = Event::default();
+    if x.after.is_some() {
+        // the start is not given: the arguments after the description are the next fields
+        let mut rest = [
+            x.start_date.take(),
+            x.start_time.take(),
+            x.duration.take(),
+            x.location.take(),
+            x.recurrence.take(),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(std::mem::take(&mut x.tags));
+        x.duration = rest.next();
+        x.location = rest.next();
+        x.recurrence = rest.next();
+        x.tags = rest.collect();
+    }
+    let title = match x.title {
+        Some(val) => val,
+        None => default_values.get_title().to_string(),
+    };
+    let description = match x.description {
+        Some(val) => val,
+        None => default_values.get_description().to_string(),
+    };
+    let start_date = match x.start_date {
+        Some(val) => val,
+        None => default_values.get_start_date().to_string(),
+    };
+    let start_time = match x.start_time {
+        Some(val) => val,
+        None => default_values.get_start_time().to_string(),
+    };
+    let duration = match x.duration {
+        Some(val) => match val.parse().ok().and_then(event::duration_from_hours) {
+            Some(duration) => duration,
+            None => return Err(format!("Invalid duration {}", val)),
+        },
+        None if x.milestone => Duration::zero(),
+        None => Duration::seconds(default_values.get_duration()),
+    };
+    let loc = x.location.as_deref();
+    let rec = x.recurrence.as_deref();
+
+    let tags = if !x.tags.is_empty() {
+        Some(x.tags)
+    } else {
+        None
+    };
+
+    let mut ev = Event::new(
+        &title,
+        &description,
+        &start_date,
+        &start_time,
+        0.0,
+        loc,
+        rec,
+        tags,
+    );
+    ev.set_duration(&duration);
+    if let Some(transp) = x.transparency {
+        ev.set_transparency(transp);
+    }
+    if let Some(class) = x.class {
+        ev.set_class(class);
+    }
+    ev.set_color(x.color);
+    ev.set_important(x.important);
+    ev.set_milestone(x.milestone);
+    if let Some(rule) = holiday_rule(x.on_holiday, x.weekends, x.holidays)? {
+        if !ev.set_holidays(Some(rule)) {
+            return Err("--on-holiday needs a recurrent event".to_string());
+        }
+    }
+    for eid in x.related_to {
+        ev.add_related(eid);
+    }
+    for eid in x.depends_on {
+        ev.add_dependency(eid);
+    }
+    ev.set_geo(x.geo);
+    if let Some(zone) = x.zone {
+        set_zone(&mut ev, &zone, data_dir)?;
+    }
+    ev.set_reminders(lead_times(&x.remind)?);
+    ev.set_resources(resource_names(&x.resource, data_dir).map_err(|e| format!("{:?}", e))?);
+    if let Some(sun) = x.sun {
+        let sun = sun_time(&sun, ev.get_geo(), data_dir).map_err(|e| format!("{:?}", e))?;
+        if !ev.set_sun(sun) {
+            return Err(format!(
+                "No {} on the start date of the event",
+                sun_event(sun)
+            ));
+        }
+    }
+    if let Some(eid) = x.after {
+        let gap = match x.gap {
+            Some(gap) => {
+                event::parse_lead_time(&gap).ok_or_else(|| format!("Invalid gap {}", gap))?
+            }
+            None => Duration::zero(),
+        };
+        ev.set_follows(Some(Follows { eid, gap }));
+    }
+    Ok(ev)
+}
+
+/// Starts the event after the end of the first occurrence of the event it follows, if any
+/// (see `add --after`): the dependency is kept only if `keep`
+fn place_after(cal: &Calendar, mut ev: Event, keep: bool) -> Result<Event, String> {
+    let Some(follows) = ev.get_follows() else {
+        return Ok(ev);
+    };
+    let anchor = cal
+        .peek_event(follows.eid)
+        .ok_or_else(|| format!("Event {} not found", follows.eid))?;
+    let start = anchor
+        .occurrence_end(anchor.get_start())
+        .checked_add_signed(follows.gap)
+        .ok_or_else(|| format!("Invalid gap after event {}", follows.eid))?;
+    ev.set_start_date((start.day(), start.month(), start.year()));
+    ev.set_start_time((start.hour(), start.minute(), 0));
+    if !keep {
+        ev.set_follows(None);
+    }
+    Ok(ev)
+}
+
+/// Sets the time zone of the event (inferred from its position if "auto"), moving its start
+/// from that zone to the one of the configuration
+fn set_zone(ev: &mut Event, zone: &str, data_dir: &Path) -> Result<(), String> {
+    let tz: Tz = if zone.eq_ignore_ascii_case("auto") {
+        let geo = ev
+            .get_geo()
+            .ok_or("--zone auto needs the position of the event (--geo)")?;
+        zones::time_zone_at(geo).ok_or_else(|| format!("Cannot infer the time zone at {}", geo))?
+    } else {
+        zone.parse()
+            .map_err(|_| format!("Unknown time zone {}", zone))?
+    };
+    let home = config::load(data_dir)
+        .and_then(|config| config.time_zone())
+        .map_err(|e| format!("{:?}", e))?;
+    let start = zones::convert(ev.get_start(), Some(tz), home)
+        .ok_or_else(|| format!("The start of the event does not exist in {}", tz))?;
+    ev.set_start_date((start.day(), start.month(), start.year()));
+    ev.set_start_time((start.hour(), start.minute(), 0));
+    ev.set_time_zone(Some(tz));
+    Ok(())
+}
+
+/// Parses the lead times of the reminders of an event ("none" for no reminders)
+fn lead_times(leads: &[String]) -> Result<Vec<Duration>, String> {
+    leads
+        .iter()
+        .filter(|lead| !lead.eq_ignore_ascii_case("none"))
+        .map(|lead| {
+            event::parse_lead_time(lead).ok_or_else(|| format!("Invalid reminder {}", lead))
+        })
+        .collect()
+}
+
+/// Checks that the resources are in the configuration ("none" for no resources)
+fn resource_names(names: &[String], data_dir: &Path) -> Result<Vec<String>, CalendarError> {
+    let names: Vec<String> = names
+        .iter()
+        .filter(|name| !name.eq_ignore_ascii_case("none"))
+        .cloned()
+        .collect();
+    if names.is_empty() {
+        return Ok(names);
+    }
+    let known = config::load(data_dir)?.resources;
+    match names
+        .iter()
+        .find(|name| !known.iter().any(|r| &r.name == *name))
+    {
+        Some(name) => Err(CalendarError::InvalidConfig(format!(
+            "Unknown resource {}: add it to the resources in the configuration",
+            name
+        ))),
+        None => Ok(names),
+    }
+}
+
+/// Parses the time relative to the sun of an event at `geo`, or at the location in the
+/// configuration if the event has no position ("none" for no time relative to the sun)
+fn sun_time(s: &str, geo: Option<Geo>, data_dir: &Path) -> Result<Option<SunTime>, CalendarError> {
+    if s.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+    let place = match geo {
+        Some(geo) => geo,
+        None => match config::load(data_dir)?.location {
+            Some(location) => location
+                .parse()
+                .map_err(|e| CalendarError::InvalidConfig(format!("location {}", e)))?,
+            None => {
+                return Err(CalendarError::InvalidConfig(String::from(
+                    "no location to compute the times of the sun at: set the position of the \
+                     event (--geo) or the location in the configuration",
+                )))
+            }
+        },
+    };
+    SunTime::parse(s, place)
+        .map(Some)
+        .map_err(CalendarError::Unknown)
+}
+
+fn sun_event(sun: Option<SunTime>) -> String {
+    sun.map(|sun| format!("{:?}", sun.event()).to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Parses a date in one of the formats accepted on the command line
+fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    ["%d/%m/%Y", "%Y-%m-%d"]
+        .iter()
+        .find_map(|fmt| NaiveDate::parse_from_str(s, fmt).ok())
+        .ok_or_else(|| format!("Invalid date {}: expected dd/mm/yyyy", s))
+}
+
+/// Parses a date, optionally followed by a time (the start of the day if missing)
+fn parse_since(s: &str) -> Result<NaiveDateTime, String> {
+    NaiveDateTime::parse_from_str(s, "%d/%m/%Y %H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M"))
+        .or_else(|_| parse_date(s).map(|d| d.and_time(NaiveTime::MIN)))
+}
+
+/// Builds an event asking the user for its fields: the tags are completed among
+/// those already used in the calendar
+pub fn event_from_prompts<A: Answers>(
+    p: &mut Prompt<A>,
+    cal: &Calendar,
+) -> Result<Event, CalendarError> {
+    let now = clock::now().naive_local();
+    let title = p.required("Title", None)?;
+    let description = p.text("Description", "")?;
+    let today = now.format("%d/%m/%Y").to_string();
+    let date = p.ask("Start date", Some(&today), &[], parse_date)?;
+    let next_hour = format!("{:02}:00", (now.hour() + 1) % 24);
+    let time = p.ask("Start time", Some(&next_hour), &[], |s| {
+        NaiveTime::parse_from_str(s, "%H:%M")
+            .map_err(|_| format!("Invalid time {}: expected hh:mm", s))
+    })?;
+    let duration = p.ask("Duration (hours)", Some("1"), &[], |s| {
+        s.parse()
+            .ok()
+            .and_then(event::duration_from_hours)
+            .ok_or_else(|| format!("Invalid duration {}", s))
+    })?;
+    let location = p.text("Location", "")?;
+    let recurrence = p.ask(
+        "Recurrence (e.g. \"weekly 10\", empty if none)",
+        Some(""),
+        &[],
+        |s| {
+            let mut ev = Event::default();
+            ev.set_recurrence(s);
+            match ev.get_recurrence() {
+                _ if s.is_empty() => Ok(None),
+                Some(_) => Ok(Some(s.to_string())),
+                None => Err(format!("Invalid recurrence {}", s)),
+            }
+        },
+    )?;
+    let tags: Vec<String> = p.ask("Tags (separated by spaces)", Some(""), &cal.tags(), |s| {
+        Ok(s.split_whitespace().map(String::from).collect())
+    })?;
+    let transparency: Transparency = p.ask("Busy or free", Some("busy"), &[], str::parse)?;
+    let class: Class = p.ask(
+        "Class (public, private or confidential)",
+        Some("public"),
+        &[],
+        str::parse,
+    )?;
+
+    let mut ev = Event::new(
+        &title,
+        &description,
+        &date.format("%d/%m/%Y").to_string(),
+        &time.format("%H:%M").to_string(),
+        0.0,
+        Some(location.as_str()).filter(|l| !l.is_empty()),
+        recurrence.as_deref(),
+        Some(tags).filter(|t| !t.is_empty()),
+    );
+    ev.set_duration(&duration);
+    ev.set_transparency(transparency);
+    ev.set_class(class);
+    Ok(ev)
+}
+
+/// Creates a calendar in the data directory, asking the user for its owner, name and id
+pub fn calendar_from_prompts<A: Answers>(
+    p: &mut Prompt<A>,
+    data_dir: &Path,
+) -> Result<Calendar, CalendarError> {
+    let user = env::var("USER").unwrap_or_default();
+    let owner = p.required("Owner", Some(user.as_str()).filter(|u| !u.is_empty()))?;
+    let name = p.required("Name", Some(&owner))?;
+    let id = p.ask("Id", Some(&slugify(&name)), &[], |s| {
+        storage::validate_id(s)
+            .map(|_| s.to_string())
+            .map_err(|e| format!("{:?}", e))
+    })?;
+    storage::create_calendar(&name, &owner, Some(&id), data_dir)
+}
+
+/// Builds the holiday rule of a recurrence from the command line arguments
+fn holiday_rule(
+    action: Option<HolidayAction>,
+    weekends: bool,
+    calendar: Option<String>,
+) -> Result<Option<HolidayRule>, String> {
+    match action {
+        Some(_) if !weekends && calendar.is_none() => {
+            Err("--on-holiday needs --weekends or a --holidays calendar".to_string())
+        }
+        Some(action) => Ok(Some(HolidayRule {
+            action,
+            weekends,
+            calendar,
+        })),
+        None => Ok(None),
+    }
+}
+
+/// Parses the events in `input`, either an iCalendar document, a JSON event, a JSON array of
+/// events or one event per line (as the arguments of the add subcommand), and adds them to
+/// the calendar. Invalid events are reported, with their line (or position in the array);
+/// returns the number of events added, the total number of events and the number of
+/// invalid ones
+fn add_events_from(cal: &mut Calendar, input: &str, data_dir: &Path) -> (usize, usize, usize) {
+    let mut events = Vec::new();
+    let trimmed = input.trim_start();
+    let ics = trimmed.starts_with("BEGIN:VCALENDAR");
+    let json = trimmed.starts_with('[') || trimmed.starts_with('{');
+    if ics {
+        match parse_ics(input, Some(data_dir)) {
+            Ok(parsed) => {
+                events.extend(
+                    parsed
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, imported)| (i + 1, Ok(imported))),
+                );
+            }
+            Err(e) => {
+                report::error(format!("Invalid iCalendar document: {}", e));
+                return (0, 1, 1);
+            }
+        }
+    } else if trimmed.starts_with('{') {
+        let ev = serde_json::from_str::<Event>(input).map_err(|e| e.to_string());
+        events.push((1, ev.map(Imported::from)));
+    } else if json {
+        match serde_json::from_str::<Vec<serde_json::Value>>(input) {
+            Ok(values) => {
+                for (i, val) in values.into_iter().enumerate() {
+                    let ev = serde_json::from_value::<Event>(val).map_err(|e| e.to_string());
+                    events.push((i + 1, ev.map(Imported::from)));
+                }
+            }
+            Err(e) => {
+                report::error(format!("Invalid JSON array of events: {}", e));
+                return (0, 1, 1);
+            }
+        }
+    } else {
+        for (i, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let ev = split_words(line).and_then(|words| {
+                let words: Vec<&str> = std::iter::once("add")
+                    .chain(words.iter().map(|w| w.as_str()))
+                    .collect();
+                match parse_command(&words) {
+                    Ok(Commands::Add(x))
+                        if x.from_file.is_none() && !x.stdin && !x.from_clipboard =>
+                    {
+                        let keep = x.keep_dependency;
+                        event_from_args(x, data_dir).and_then(|ev| place_after(cal, ev, keep))
+                    }
+                    Ok(_) => Err("only the event arguments are allowed".to_string()),
+                    Err(e) => Err(e
+                        .to_string()
+                        .lines()
+                        .next()
+                        .unwrap_or_default()
+                        .trim_start_matches("error: ")
+                        .to_string()),
+                }
+            });
+            events.push((i + 1, ev.map(Imported::from)));
+        }
+    }
+    let total = events.len();
+    let mut added = 0;
+    let mut failed = 0;
+    let mut summary = ImportSummary::default();
+    for (n, ev) in events {
+        match ev {
+            Ok(ev) => {
+                if summary.import(cal, ev) {
+                    added += 1;
+                }
+            }
+            Err(e) => {
+                let what = if ics || json { "event" } else { "line" };
+                report::error(format!("{} {}: {}", what, n, e));
+                failed += 1;
+            }
+        }
+    }
+    summary.report();
+    (added, total, failed)
+}
+
+/// Adds the event to the calendar, warning if it is a duplicate or overlaps with other events.
+/// Returns its eid, if added
+fn add_event_warn(cal: &mut Calendar, ev: Event) -> Option<u64> {
+    let title = ev.get_title().to_string();
+    let outcome = cal.add_event(ev);
+    match outcome.added {
+        Some(eid) => {
+            for other in outcome.overlaps {
+                report::warning(format!(
+                    "the event \"{}\" ({}) overlaps with event \"{}\" ({})",
+                    title,
+                    eid,
+                    cal.peek_event(other).map_or("", |e| e.get_title()),
+                    other
+                ));
+            }
+            Some(eid)
+        }
+        None => {
+            for eid in outcome.duplicates {
+                report::warning(format!(
+                    "Event \"{}\" ({}) already in this calendar: calendar not modified",
+                    title, eid
+                ));
+            }
+            None
+        }
+    }
+}
+
+/// Warns about the resources booked by the event with this eid that are double-booked from
+/// now on, by the events of this calendar and of the others in the data directory
+fn warn_double_bookings(cal: &Calendar, eid: u64, data_dir: &Path) {
+    let resources = match config::load(data_dir) {
+        Ok(config) => config.resources,
+        Err(e) => {
+            report::warning(format!("{:?}", e));
+            Vec::new()
+        }
+    };
+    let others = read_calendars(Some(cal.get_id()), data_dir);
+    let cals: Vec<&Calendar> = std::iter::once(cal).chain(others.iter()).collect();
+    let now = clock::now().naive_local();
+    let key = (cal.get_id().to_string(), eid);
+    for booking in resources::double_bookings(&cals, &resources, now) {
+        if booking.events.contains(&key) {
+            report::warning(render_double_booking(&booking, &cals, &resources));
+        }
+    }
+}
+
+/// Warns about the weeks in which the tags of the event with this eid take longer than their
+/// budgets (in the configuration): the first year of the recurrent events is checked
+fn warn_over_budget(cal: &Calendar, eid: u64, data_dir: &Path) {
+    let budgets = match config::load(data_dir) {
+        Ok(config) => config.budgets,
+        Err(e) => {
+            report::warning(format!("{:?}", e));
+            return;
+        }
+    };
+    let Some(ev) = cal.peek_event(eid) else {
+        return;
+    };
+    let tags = ev.get_metadata().get_tags();
+    if !tags.iter().any(|tag| budgets.contains_key(tag)) {
+        return;
+    }
+    let start = ev.get_start();
+    let end = match ev.get_recurrence() {
+        Some(_) => start + Duration::weeks(52),
+        None => ev.occurrence_end(start),
+    };
+    for over in stats::over_budget(cal, &budgets, start.date(), end.date()) {
+        if tags.contains(&over.tag) {
+            report::warning(over.to_string());
+        }
+    }
+}
+
+/// Reads the calendars in the data directory and in the shared roots (except the one with the
+/// given id) sorted by id, warning about the ones that cannot be read
+fn read_calendars(except: Option<&str>, data_dir: &Path) -> Vec<Calendar> {
+    let mut cals = Vec::new();
+    for ((cal, path), _) in storage::known_in_roots(&data_roots(data_dir)) {
+        match cal {
+            Ok(cal) if Some(cal.get_id()) == except => (),
+            Ok(cal) => cals.push(cal),
+            Err(e) => report::warning(format!("{}: {:?}", path.display(), e)),
+        }
+    }
+    cals.sort_by(|a, b| a.get_id().cmp(b.get_id()));
+    cals
+}
+
+/// Describes a double-booked resource and the events booking it
+fn render_double_booking(
+    booking: &DoubleBooking,
+    cals: &[&Calendar],
+    resources: &[ResourceConfig],
+) -> String {
+    let events: Vec<String> = booking
+        .events
+        .iter()
+        .map(|(id, eid)| {
+            let title = cals
+                .iter()
+                .find(|c| c.get_id() == id)
+                .and_then(|c| c.peek_event(*eid))
+                .map_or("", |ev| ev.get_title());
+            format!("\"{}\" ({}, {})", title, id, eid)
+        })
+        .collect();
+    format!(
+        "{} double-booked on {} (capacity {}): {}",
+        booking.resource,
+        booking.start.format("%d/%m/%Y %H:%M"),
+        resources::capacity(&booking.resource, resources),
+        events.join(", ")
+    )
+}
+
+/// The duplicates and the overlaps found while importing events, reported together once
+/// all of them have been added
+#[derive(Default)]
+struct ImportSummary {
+    /// Title and eid of the stored events replaced by a later revision (a higher SEQUENCE)
+    updated: Vec<(String, u64)>,
+    /// Title and eid of the stored events removed as cancelled
+    cancelled: Vec<(String, u64)>,
+    /// Title of the event, and eid of the identical one already in the calendar
+    duplicates: Vec<(String, u64)>,
+    /// Title and eid of the event, and eids of the events it overlaps with
+    overlaps: Vec<(String, u64, Vec<u64>)>,
+}
+
+impl ImportSummary {
+    /// Imports the event: the stored event with the same UID, if any, is removed if the
+    /// imported one is cancelled, or replaced if this is a later revision of it (otherwise
+    /// the imported one is a duplicate). Returns whether the calendar changed
+    fn import(&mut self, cal: &mut Calendar, imported: Imported) -> bool {
+        let Imported {
+            event: ev,
+            cancelled,
+        } = imported;
+        let title = ev.get_title().to_string();
+        let Some(eid) = ev.get_uid().and_then(|uid| cal.eid_by_uid(uid)) else {
+            if cancelled {
+                report::warning(format!(
+                    "\"{}\" is cancelled, but not in the calendar",
+                    title
+                ));
+                return false;
+            }
+            return self.add(cal, ev);
+        };
+        let revision = cal
+            .peek_event(eid)
+            .map(|stored| stored.get_metadata().get_revision())
+            .unwrap_or_default();
+        if cancelled {
+            let _ = cal.remove_event(eid);
+            self.cancelled.push((title, eid));
+            true
+        } else if ev.get_metadata().get_revision() > revision {
+            cal.insert_event(eid, ev);
+            self.updated.push((title, eid));
+            true
+        } else {
+            self.duplicates.push((title, eid));
+            false
+        }
+    }
+
+    /// Adds the event to the calendar, unless a duplicate, returning whether it was added
+    fn add(&mut self, cal: &mut Calendar, ev: Event) -> bool {
+        let title = ev.get_title().to_string();
+        // the events are imported anyway, as written by the other calendars
+        if let Err(e) = ev.check_recurrence().and_then(|_| ev.check_milestone()) {
+            report::warning(format!("{:?}", e));
+        }
+        let AddOutcome {
+            added,
+            duplicates,
+            overlaps,
+        } = cal.add_event(ev);
+        match added {
+            Some(eid) => {
+                if !overlaps.is_empty() {
+                    self.overlaps.push((title, eid, overlaps));
+                }
+                true
+            }
+            None => {
+                self.duplicates
+                    .extend(duplicates.into_iter().map(|eid| (title.clone(), eid)));
+                false
+            }
+        }
+    }
+
+    /// Renders a table of the duplicates (in yellow) and the overlaps (in red), if any
+    fn render(&self, color: bool) -> String {
+        if self.duplicates.is_empty() && self.overlaps.is_empty() {
+            return String::new();
+        }
+        let mut rows = vec![(
+            "",
+            String::from("Eid"),
+            String::from("Event"),
+            String::from("Same as / overlapping with"),
+        )];
+        for (title, eid) in self.duplicates.iter() {
+            rows.push((
+                "duplicate",
+                String::from("-"),
+                title.clone(),
+                eid.to_string(),
+            ));
+        }
+        for (title, eid, others) in self.overlaps.iter() {
+            let others: Vec<String> = others.iter().map(|e| e.to_string()).collect();
+            rows.push(("overlap", eid.to_string(), title.clone(), others.join(", ")));
+        }
+        let eid_width = rows.iter().map(|r| r.1.len()).max().unwrap_or(0);
+        let title_width = rows.iter().map(|r| r.2.chars().count()).max().unwrap_or(0);
+        let mut out = format!(
+            "{} duplicate events not imported, {} imported events overlapping with others:\n",
+            self.duplicates.len(),
+            self.overlaps.len()
+        );
+        for (kind, eid, title, others) in rows {
+            let paint = match (color, kind) {
+                (true, "duplicate") => "\x1b[33m",
+                (true, "overlap") => "\x1b[31m",
+                _ => "",
+            };
+            let reset = if paint.is_empty() { "" } else { "\x1b[0m" };
+            out.push_str(&format!(
+                "{}{:<10} {:<eid_width$} {:<title_width$} {}{}\n",
+                paint, kind, eid, title, others, reset
+            ));
+        }
+        out
+    }
+
+    /// Reports the duplicates and the overlaps as a single warning, if any, after the events
+    /// updated and cancelled
+    fn report(&self) {
+        for (title, eid) in self.updated.iter() {
+            println!("Updated \"{}\" ({})", title, eid);
+        }
+        for (title, eid) in self.cancelled.iter() {
+            println!("Removed \"{}\" ({}), cancelled", title, eid);
+        }
+        let color = env::var_os("NO_COLOR").is_none() && io::stderr().is_terminal();
+        let table = self.render(color);
+        if !table.is_empty() {
+            report::warning(table.trim_end());
+        }
+    }
+}
+
+pub fn handle_edit(cal: &mut Calendar, x: Edit, data_dir: &Path) -> Result<bool, CalendarError> {
+    if x.from_file.is_some() {
+        return Err(CalendarError::Unknown("Unimplemented!".to_owned()));
+    }
+    if x.editor {
+        let editor = std::env::var("VISUAL")
+            .or_else(|_| std::env::var("EDITOR"))
+            .unwrap_or_else(|_| String::from("vi"));
+        let ev = cal
+            .peek_event(x.eid)
+            .ok_or(CalendarError::EventNotFound(x.eid))?;
+        return match editor::edit(ev, &editor, x.eid)? {
+            Some(edited) => {
+                cal.update_event(x.eid, |ev| *ev = edited)?;
+                Ok(true)
+            }
+            None => {
+                info!("Event {} not changed", x.eid);
+                Ok(false)
+            }
+        };
+    }
+    let duration_given = x.duration.is_some();
+    let rule =
+        holiday_rule(x.on_holiday, x.weekends, x.holidays).map_err(CalendarError::Unknown)?;
+    let sun = match &x.sun {
+        Some(s) => {
+            let ev = cal
+                .peek_event(x.eid)
+                .ok_or(CalendarError::EventNotFound(x.eid))?;
+            Some(sun_time(s, x.geo.or(ev.get_geo()), data_dir)?)
+        }
+        None => None,
+    };
+    let color = match x.color.as_deref() {
+        Some("none") => Some(None),
+        Some(color) => Some(Some(color.parse().map_err(CalendarError::Unknown)?)),
+        None => None,
+    };
+    let pause = match (&x.pause_from, &x.pause_until) {
+        (Some(from), Some(until)) => Some((
+            parse_date(from).map_err(CalendarError::Unknown)?,
+            parse_date(until).map_err(CalendarError::Unknown)?,
+        )),
+        _ => None,
+    };
+    let start_time = x.start_time.is_some();
+    let reminders = lead_times(&x.remind).map_err(CalendarError::Unknown)?;
+    let resources = resource_names(&x.resource, data_dir)?;
+    let rebook = !x.resource.is_empty();
+    let edited = cal.update_event(x.eid, |ev| {
+        if let Some(title) = x.title {
+            ev.set_title(&title);
+        }
+        if let Some(descr) = x.description {
+            ev.set_description(&descr);
+        }
+        if let Some(s) = x.start_date {
+            let date_formats = vec!["%d/%m/%Y", "%Y-%m-%d"];
+            for fmt in date_formats {
+                if let Ok(val) = NaiveDate::parse_from_str(&s, fmt) {
+                    ev.set_start_date((val.day(), val.month(), val.year()));
+                    break;
+                }
+            }
+        }
+        if let Some(s) = x.start_time {
+            let time_formats = vec!["%H:%M", "%H:%M:%S"];
+            for fmt in time_formats {
+                if let Ok(val) = NaiveTime::parse_from_str(&s, fmt) {
+                    ev.set_start_time((val.hour(), val.minute(), val.second()));
+                    break;
+                }
+            }
+        }
+        if let Some(duration) = x.duration {
+            ev.set_duration(&Duration::hours(duration.parse::<i32>().unwrap().into()));
+        }
+        if let Some(loc) = x.location {
+            ev.set_location(&loc);
+        }
+        if let Some(rec) = x.recurrence {
+            ev.set_recurrence(&rec);
+        }
+        if !x.tags.is_empty() {
+            ev.set_tags(x.tags);
+        }
+        if let Some(transp) = x.transparency {
+            ev.set_transparency(transp);
+        }
+        if let Some(class) = x.class {
+            ev.set_class(class);
+        }
+        if let Some(color) = color {
+            ev.set_color(color);
+        }
+        if x.important || x.not_important {
+            ev.set_important(x.important);
+        }
+        if x.milestone || x.not_milestone {
+            ev.set_milestone(x.milestone);
+        }
+        // a milestone has no duration, unless given (which is then an error)
+        if x.milestone && !duration_given {
+            ev.set_duration(&Duration::zero());
+        }
+        if let Some(rule) = rule {
+            if !ev.set_holidays(Some(rule)) {
+                return Err(CalendarError::Unknown(
+                    "--on-holiday needs a recurrent event".to_string(),
+                ));
+            }
+        }
+        if x.resume && !ev.set_pauses(Vec::new()) {
+            return Err(CalendarError::Unknown(
+                "--resume needs a recurrent event".to_string(),
+            ));
+        }
+        if let Some((from, until)) = pause {
+            if !ev.pause(from, until) {
+                return Err(CalendarError::Unknown(
+                    "--pause-from needs a recurrent event".to_string(),
+                ));
+            }
+        }
+        for eid in x.related_to {
+            ev.add_related(eid);
+        }
+        if x.no_dependencies {
+            ev.set_depends_on(Vec::new());
+        }
+        for eid in x.depends_on {
+            ev.add_dependency(eid);
+        }
+        if x.geo.is_some() {
+            ev.set_geo(x.geo);
+        }
+        if !x.remind.is_empty() {
+            ev.set_reminders(reminders);
+        }
+        if rebook {
+            ev.set_resources(resources);
+        }
+        // an explicit start time replaces the one relative to the sun, which is otherwise
+        // recomputed for the (possibly new) start date
+        let sun = match (sun, start_time) {
+            (Some(sun), _) => sun,
+            (None, true) => None,
+            (None, false) => ev.get_sun(),
+        };
+        if !ev.set_sun(sun) {
+            return Err(CalendarError::Unknown(format!(
+                "No {} on the start date of the event",
+                sun_event(sun)
+            )));
+        }
+        ev.check_recurrence()?;
+        ev.check_milestone()?;
+        Ok(true)
+    })??;
+    // a new time can double-book the resources as well
+    if cal
+        .peek_event(x.eid)
+        .is_some_and(|ev| !ev.get_resources().is_empty())
+    {
+        warn_double_bookings(cal, x.eid, data_dir);
+    }
+    Ok(edited)
+}
+
+pub fn handle_countdown(
+    cal: &Calendar,
+    x: Countdown,
+    data_dir: &Path,
+) -> Result<(), CalendarError> {
+    let mut thresholds = config::load(data_dir)?.countdown;
+    if let Some(hours) = x.urgent_hours {
+        thresholds.urgent_hours = hours;
+    }
+    if let Some(days) = x.soon_days {
+        thresholds.soon_days = days;
+    }
+    let color = !x.no_color && env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal();
+    print!(
+        "{}",
+        countdown::render(
+            cal,
+            clock::now().naive_local(),
+            x.tag.as_deref(),
+            x.limit,
+            &thresholds,
+            color
+        )
+    );
+    Ok(())
+}
+
+pub fn handle_subscribe(
+    cal: &mut Calendar,
+    x: Subscribe,
+    data_dir: &Path,
+) -> Result<(), CalendarError> {
+    let mut client = http::Client::new(&config::load(data_dir)?.http, data_dir)?;
+    let mut sub = Subscription::new(&x.url, x.every);
+    let now = clock::now().naive_local();
+    let count = subscription::fetch_into(cal, &mut sub, now, &mut client)?;
+    // only once the feed could be fetched
+    subscription::save(cal.get_id(), &sub, data_dir)?;
+    println!("Subscribed to {}: {} events fetched", x.url, count);
+    Ok(())
+}
+
+pub fn handle_refresh(
+    cal: &mut Calendar,
+    x: Refresh,
+    data_dir: &Path,
+) -> Result<(), CalendarError> {
+    let mut client = http::Client::new(&config::load(data_dir)?.http, data_dir)?;
+    let now = clock::now().naive_local();
+    match subscription::refresh(cal, now, x.force, &mut client, data_dir)? {
+        Some(count) => println!("{} events fetched", count),
+        None if subscription::load(cal.get_id(), data_dir)?.is_none() => {
+            return Err(CalendarError::Unknown(format!(
+                "Calendar {} is not subscribed to any feed",
+                cal.get_id()
+            )))
+        }
+        None => info!("The feed of {} is up to date", cal.get_id()),
+    }
+    Ok(())
+}
+
+pub fn handle_exchange(
+    cal: &mut Calendar,
+    x: Exchange,
+    data_dir: &Path,
+) -> Result<(), CalendarError> {
+    let config = config::load(data_dir)?;
+    let mut client = http::Client::new(&config.http, data_dir)?;
+    let pulled = exchange::pull(
+        cal,
+        &config.exchange,
+        x.source.as_deref(),
+        x.full,
+        &mut client,
+        data_dir,
+    )?;
+    println!(
+        "{} events added or updated, {} removed",
+        pulled.updated, pulled.removed
+    );
+    let interactive = io::stdin().is_terminal();
+    let policy = x.on_conflict.unwrap_or(if interactive {
+        ConflictPolicy::Ask
+    } else {
+        ConflictPolicy::Defer
+    });
+    resolve_conflicts(cal, pulled.conflicts, policy, data_dir)
+}
+
+/// Resolves the conflicts with the policy, storing the ones deferred
+fn resolve_conflicts(
+    cal: &mut Calendar,
+    conflicts: Vec<Conflict>,
+    policy: ConflictPolicy,
+    data_dir: &Path,
+) -> Result<(), CalendarError> {
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+    let mut prompt = Prompt::new(Terminal::new()?);
+    let mut deferred = Vec::new();
+    for conflict in conflicts {
+        match sync::resolve(&conflict, policy, &mut prompt)? {
+            Some(resolved) => sync::apply(cal, conflict.eid, resolved)?,
+            None => deferred.push(conflict),
+        }
+    }
+    if !deferred.is_empty() {
+        println!("{} conflicts deferred: see sync conflicts", deferred.len());
+    }
+    sync::defer(cal.get_id(), deferred, data_dir)
+}
+
+pub fn handle_sync(cal: &mut Calendar, x: SyncCmd, data_dir: &Path) -> Result<(), CalendarError> {
+    let stored = sync::load_conflicts(cal.get_id(), data_dir)?;
+    match x {
+        SyncCmd::Conflicts => {
+            for c in &stored {
+                println!(
+                    "{} \"{}\" (since {})\n{}",
+                    c.eid,
+                    c.local.get_title(),
+                    c.time.format("%d/%m/%Y %H:%M"),
+                    sync::describe(c)
+                );
+            }
+            Ok(())
+        }
+        SyncCmd::Resolve { eid, keep } => {
+            let (chosen, rest): (Vec<Conflict>, Vec<Conflict>) = stored
+                .into_iter()
+                .partition(|c| eid.is_none_or(|eid| c.eid == eid));
+            if let (Some(eid), true) = (eid, chosen.is_empty()) {
+                return Err(CalendarError::Unknown(format!(
+                    "No conflict on event {}",
+                    eid
+                )));
+            }
+            sync::save_conflicts(cal.get_id(), &rest, data_dir)?;
+            resolve_conflicts(cal, chosen, keep, data_dir)
+        }
+    }
+}
+
+pub fn handle_digest(cal: &Calendar, x: Digest, data_dir: &Path) -> Result<(), CalendarError> {
+    let (first, last) = digest::period(clock::now().date_naive(), x.week);
+    let body = if x.redact {
+        digest::render(&cal.redacted(), first, last, x.format, x.reminders)
+    } else {
+        digest::render(cal, first, last, x.format, x.reminders)
+    };
+    match x.email {
+        Some(to) => {
+            let smtp = config::load(data_dir)?.smtp.ok_or_else(|| {
+                CalendarError::InvalidConfig("no smtp server configured".to_string())
+            })?;
+            digest::send(
+                &smtp,
+                &to,
+                &digest::subject(cal, first, last),
+                body,
+                x.format,
+            )?;
+            info!("Agenda of {} sent to {}", cal.get_name(), to);
+            Ok(())
+        }
+        None => {
+            print!("{}", body);
+            Ok(())
+        }
+    }
+}
+
+pub fn handle_plan(cal: &mut Calendar, x: Plan, data_dir: &Path) -> bool {
+    match plan_tasks(cal, x, data_dir) {
+        Ok(()) => true,
+        Err(e) => {
+            report::error(format!("{:?}", e));
+            false
+        }
+    }
+}
+
+/// Schedules the tasks of the plan in the free slots of the calendar, and adds them
+/// to the calendar if the plan is accepted
+fn plan_tasks(cal: &mut Calendar, x: Plan, data_dir: &Path) -> Result<(), CalendarError> {
+    let now = clock::now().naive_local();
+    let (first, last) = planner::parse_within(&x.within, now.date())
+        .ok_or_else(|| CalendarError::Unknown(format!("Invalid period {}", x.within)))?;
+    let tasks = planner::parse_tasks(&fs::read_to_string(&x.tasks)?)
+        .map_err(|e| CalendarError::Unknown(format!("{}: {}", x.tasks, e)))?;
+    let hours = config::load(data_dir)?.working_hours;
+    let slots = planner::free_slots(cal, first, last, &hours, now);
+    let (planned, unscheduled) = planner::schedule(tasks, slots);
+    for ev in planned.iter() {
+        let start = ev.get_start();
+        println!(
+            "{} {}-{}  {}",
+            start.format("%a %d/%m/%Y"),
+            start.format("%H:%M"),
+            ev.occurrence_end(start).format("%H:%M"),
+            ev.get_title()
+        );
+    }
+    for task in unscheduled.iter() {
+        report::warning(format!(
+            "No free slot for \"{}\" ({} minutes)",
+            task.title,
+            task.duration.num_minutes()
+        ));
+    }
+    if !x.accept {
+        println!("Rerun with --accept to add these events to the calendar");
+        return Ok(());
+    }
+    let mut added = 0;
+    for ev in planned {
+        if add_event_warn(cal, ev).is_some() {
+            added += 1;
+        }
+    }
+    println!("Added {} events to {}", added, cal.get_name());
+    Ok(())
+}
+
+pub fn handle_schedule(cal: &mut Calendar, x: Schedule, data_dir: &Path) -> bool {
+    match schedule_meeting(cal, x, data_dir) {
+        Ok(()) => true,
+        Err(e) => {
+            report::error(format!("{:?}", e));
+            false
+        }
+    }
+}
+
+/// Proposes the times when the calendars are all free for the meeting, booking the chosen one
+fn schedule_meeting(cal: &mut Calendar, x: Schedule, data_dir: &Path) -> Result<(), CalendarError> {
+    let now = clock::now().naive_local();
+    let (first, last) = planner::parse_within(&x.within, now.date())
+        .ok_or_else(|| CalendarError::Unknown(format!("Invalid period {}", x.within)))?;
+    let duration = planner::parse_duration(&x.duration)
+        .ok_or_else(|| CalendarError::Unknown(format!("Invalid duration {}", x.duration)))?;
+    let others = x
+        .with
+        .iter()
+        .map(|key| storage::resolve_in_roots(key, &data_roots(data_dir)).map(|(cal, _)| cal))
+        .collect::<Result<Vec<Calendar>, CalendarError>>()?;
+    let mut busy = Vec::new();
+    for path in x.freebusy.iter() {
+        let periods = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| freebusy::parse_vfreebusy(&text))
+            .map_err(|e| CalendarError::Unknown(format!("{}: {}", path, e)))?;
+        busy.extend(periods);
+    }
+    let hours = config::load(data_dir)?.working_hours;
+    let cals: Vec<&Calendar> = std::iter::once(&*cal).chain(others.iter()).collect();
+    let slots = planner::common_free_slots(&cals, &busy, first, last, &hours, now);
+    let times = planner::meeting_times(&slots, duration, Duration::minutes(30));
+    if times.is_empty() {
+        return Err(CalendarError::Unknown(format!(
+            "No common free time for a meeting of {} minutes",
+            duration.num_minutes()
+        )));
+    }
+    for (i, (start, end)) in times.iter().take(x.limit).enumerate() {
+        println!(
+            "{}. {} {}-{}",
+            i + 1,
+            start.format("%a %d/%m/%Y"),
+            start.format("%H:%M"),
+            end.format("%H:%M")
+        );
+    }
+    let rank = match x.book {
+        Some(rank) => rank,
+        None => {
+            println!("Rerun with --book N to add the meeting at time N to the calendars");
+            return Ok(());
+        }
+    };
+    let start = match times.get(rank.wrapping_sub(1)) {
+        Some((start, _)) if rank <= x.limit => *start,
+        _ => {
+            return Err(CalendarError::Unknown(format!(
+                "No meeting time ranked {}",
+                rank
+            )))
+        }
+    };
+    let mut ev = Event::default();
+    ev.set_title(&x.title);
+    ev.set_start_date((start.day(), start.month(), start.year()));
+    ev.set_start_time((start.hour(), start.minute(), start.second()));
+    ev.set_duration(&duration);
+    ev.set_tags(vec![String::from("tentative")]);
+    add_event_warn(cal, ev.clone());
+    for before in others {
+        let mut after = before.clone();
+        after.add_event(ev.clone());
+        if !storage::save_audited(&before, &after, &audit::current_user(), data_dir) {
+            report::warning(format!("Cannot write calendar {}", after.get_id()));
+        }
+    }
+    println!(
+        "Tentative meeting booked on {} in {} calendars",
+        start.format("%a %d/%m/%Y %H:%M"),
+        x.with.len() + 1
+    );
+    Ok(())
+}
+
+/// Builds the course described by the arguments of the course subcommand
+fn course_from_args(x: CourseCmd) -> Result<Course, String> {
+    let CourseCmd::Add {
+        title,
+        days,
+        hours,
+        from,
+        until,
+        except,
+        holidays,
+        location,
+        tag,
+    } = x;
+    let (start, end) = availability::parse_hours(&hours)?;
+    Ok(Course {
+        title,
+        days: availability::parse_days(&days)?,
+        start,
+        end,
+        first: parse_date(&from)?,
+        last: parse_date(&until)?,
+        except: except
+            .iter()
+            .map(|d| parse_date(d))
+            .collect::<Result<_, _>>()?,
+        holidays,
+        location,
+        tags: tag,
+    })
+}
+
+pub fn handle_user(x: &UserCmd, data_dir: &Path) -> Result<(), CalendarError> {
+    let mut users = Users::load(data_dir)?;
+    match x {
+        UserCmd::Add { name } => {
+            let token = users.add(name)?;
+            println!("Token of {} (it cannot be shown again): {}", name, token);
+        }
+        UserCmd::Remove { name } => users.remove(name)?,
+        UserCmd::Grant {
+            name,
+            calendar,
+            access,
+        } => users.grant(name, calendar, *access)?,
+        UserCmd::Revoke { name, calendar } => users.revoke(name, calendar)?,
+        UserCmd::List => {
+            for user in users.list() {
+                let calendars: Vec<String> = user
+                    .calendars
+                    .iter()
+                    .map(|(cal, access)| format!("{} ({:?})", cal, access).to_lowercase())
+                    .collect();
+                println!("{}: {}", user.name, calendars.join(", "));
+            }
+            return Ok(());
+        }
+    }
+    users.save(data_dir)
+}
+
+pub fn handle_log(x: &Log, data_dir: &Path) -> Result<(), CalendarError> {
+    let cal = storage::resolve_calendar(&x.calendar, data_dir)?;
+    let entries: Vec<audit::AuditEntry> = storage::read_audit_log(cal.get_id(), data_dir)?
+        .into_iter()
+        .filter(|e| x.event.is_none() || e.eid == x.event)
+        .collect();
+    print!("{}", audit::render(&entries));
+    Ok(())
+}
+
+pub fn handle_snapshot(x: &SnapshotCmd, data_dir: &Path) -> Result<(), CalendarError> {
+    let now = clock::now().naive_local();
+    match x {
+        SnapshotCmd::Create { calendar } => {
+            let cal = storage::resolve_calendar(calendar, data_dir)?;
+            let s = snapshot::create(&cal, now, data_dir)?;
+            println!("Snapshot {} of {} taken", &s.hash[..12], cal.get_id());
+        }
+        SnapshotCmd::List { calendar } => {
+            let cal = storage::resolve_calendar(calendar, data_dir)?;
+            for s in snapshot::list(cal.get_id(), data_dir)? {
+                println!("{} {}", &s.hash[..12], s.time.format("%d/%m/%Y %H:%M:%S"));
+            }
+        }
+        SnapshotCmd::Restore { calendar, at } => {
+            let cal = storage::resolve_calendar(calendar, data_dir)?;
+            let snapshots = snapshot::list(cal.get_id(), data_dir)?;
+            let found = snapshot::find(&snapshots, at)?;
+            let mut restored = snapshot::load(cal.get_id(), found, data_dir)?;
+            restored.set_id(cal.get_id());
+            // the current state can be restored in turn
+            let current = snapshot::create(&cal, now, data_dir)?;
+            if !storage::save_audited(&cal, &restored, &audit::current_user(), data_dir) {
+                return Err(CalendarError::Unknown(format!(
+                    "Cannot write calendar {}",
+                    cal.get_id()
+                )));
+            }
+            println!(
+                "Calendar {} restored to snapshot {} of {} (the previous state is snapshot {})",
+                cal.get_id(),
+                &found.hash[..12],
+                found.time.format("%d/%m/%Y %H:%M:%S"),
+                &current.hash[..12]
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the file given as argument, unless it is missing or `-` (the standard stream)
+fn file_arg(file: &Option<PathBuf>) -> Option<&Path> {
+    file.as_deref().filter(|f| *f != Path::new("-"))
+}
+
+pub fn handle_dump(x: &Dump, data_dir: &Path) -> Result<(), CalendarError> {
+    let dump = dump::create(data_dir)?;
+    let mut contents =
+        serde_json::to_string_pretty(&dump).map_err(|e| CalendarError::Unknown(e.to_string()))?;
+    contents.push('\n');
+    match file_arg(&x.file) {
+        Some(path) => fs::write(path, contents)
+            .map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))?,
+        None => print!("{}", contents),
+    }
+    info!("Dumped {} calendars", dump.calendars.len());
+    Ok(())
+}
+
+pub fn handle_doctor(x: &Doctor, data_dir: &Path) -> Result<(), CalendarError> {
+    let issues = doctor::scan(data_dir, x.max_size_mb.saturating_mul(1024 * 1024))?;
+    let fixed = if x.fix {
+        doctor::fix(&issues, &audit::current_user(), data_dir)?
+    } else {
+        Vec::new()
+    };
+    for issue in issues.iter() {
+        let status = if fixed.contains(issue) {
+            " [fixed]"
+        } else if issue.is_fixable() && !x.fix {
+            " [fixable]"
+        } else {
+            ""
+        };
+        println!("{}{}", issue, status);
+    }
+    let left = issues.len() - fixed.len();
+    match left {
+        0 if issues.is_empty() => println!("No problems found"),
+        0 => println!("Repaired {} problems", fixed.len()),
+        left => {
+            return Err(CalendarError::Unknown(format!(
+                "{} problems left ({} can be repaired with --fix)",
+                left,
+                issues
+                    .iter()
+                    .filter(|i| i.is_fixable() && !fixed.contains(i))
+                    .count()
+            )))
+        }
+    }
+    Ok(())
+}
+
+pub fn handle_maintenance(data_dir: &Path) -> Result<(), CalendarError> {
+    let config = config::load(data_dir)?;
+    let now = clock::now().naive_local();
+    let summary = maintenance::run(&config, &audit::current_user(), now, data_dir);
+    for error in summary.errors.iter() {
+        report::warning(error);
+    }
+    for issue in summary.issues.iter() {
+        println!("{}", issue);
+    }
+    print!("{}", summary);
+    match summary.is_ok() {
+        true => Ok(()),
+        false if summary.errors.is_empty() => Err(CalendarError::Unknown(String::from(
+            "Problems found: run doctor to repair them",
+        ))),
+        false => Err(CalendarError::Unknown(String::from("Some tasks failed"))),
+    }
+}
+
+pub fn handle_conflicts(x: &ConflictsCmd, data_dir: &Path) -> Result<(), CalendarError> {
+    let found = conflicts::find(data_dir)?;
+    match x {
+        ConflictsCmd::List => {
+            for c in found.iter() {
+                println!("{} (copy of {})", c.path.display(), c.original.display());
+            }
+        }
+        ConflictsCmd::Resolve => {
+            let mut left = 0;
+            for c in found.iter() {
+                match conflicts::resolve(c, &audit::current_user(), data_dir) {
+                    Ok((before, after)) => {
+                        let diff = before.diff(&after);
+                        println!(
+                            "Merged {} into {}: {} added, {} updated, {} removed",
+                            c.path.display(),
+                            after.get_id(),
+                            diff.added.len(),
+                            diff.edited.len(),
+                            diff.removed.len()
+                        );
+                    }
+                    Err(e) => {
+                        report::error(format!("{}: cannot be merged ({:?})", c.path.display(), e));
+                        left += 1;
+                    }
+                }
+            }
+            if left > 0 {
+                return Err(CalendarError::Unknown(format!(
+                    "{} conflicting copies left, to be resolved by hand",
+                    left
+                )));
+            }
+        }
+    }
+    if found.is_empty() {
+        println!("No conflicting copies found");
+    }
+    Ok(())
+}
+
+pub fn handle_ics_cmd(x: &IcsCmd) -> Result<(), CalendarError> {
+    let IcsCmd::Validate { file } = x;
+    let doc =
+        fs::read_to_string(file).map_err(|e| CalendarError::Unknown(format!("{}: {}", file, e)))?;
+    let problems = ics::validate(&doc);
+    for problem in problems.iter() {
+        println!("{}: {}", file, problem);
+    }
+    match problems.len() {
+        0 => {
+            println!("{}: valid", file);
+            Ok(())
+        }
+        n => Err(CalendarError::Unknown(format!(
+            "{} problems found in {}",
+            n, file
+        ))),
+    }
+}
+
+pub fn handle_resources(x: &ResourcesCmd, data_dir: &Path) -> Result<(), CalendarError> {
+    let resources = config::load(data_dir)?.resources;
+    match x {
+        ResourcesCmd::List => {
+            for r in resources.iter() {
+                println!("{} (capacity {})", r.name, r.capacity);
+            }
+            if resources.is_empty() {
+                println!("No resources in the configuration");
+            }
+        }
+        ResourcesCmd::Conflicts => {
+            let calendars = read_calendars(None, data_dir);
+            let cals: Vec<&Calendar> = calendars.iter().collect();
+            let now = clock::now().naive_local();
+            let found = resources::double_bookings(&cals, &resources, now);
+            for booking in found.iter() {
+                println!("{}", render_double_booking(booking, &cals, &resources));
+            }
+            if found.is_empty() {
+                println!("No double-booked resources");
+            }
+        }
+    }
+    Ok(())
+}
 
-        let ev = Event::new(
-            &title,
-            &description,
-            &start_date,
-            &start_time,
+pub fn handle_checkin(cal: &mut Calendar, x: CheckInArgs) -> Result<(), CalendarError> {
+    let now = clock::now();
+    let occurrence = match cal.last_ended_occurrence(x.eid, now.naive_local()) {
+        Some(occurrence) => occurrence,
+        None if cal.peek_event(x.eid).is_none() => return Err(CalendarError::EventNotFound(x.eid)),
+        None => {
+            return Err(CalendarError::Unknown(format!(
+                "Event {} has not ended yet",
+                x.eid
+            )))
+        }
+    };
+    let title = cal.update_event(x.eid, |ev| {
+        ev.check_in(CheckIn {
+            occurrence,
+            note: x.note,
+            rating: x.rating,
+            time: now,
+        });
+        ev.get_title().to_string()
+    })?;
+    println!(
+        "Checked in \"{}\" of {}",
+        title,
+        occurrence.format("%d/%m/%Y %H:%M")
+    );
+    Ok(())
+}
+
+pub fn handle_review(cal: &Calendar, x: Review) {
+    let now = clock::now().naive_local();
+    let today = now.date();
+    let first = match x.week {
+        true => today - Duration::days(today.weekday().num_days_from_monday() as i64),
+        false => today,
+    };
+    print!(
+        "{}",
+        review::render(cal, first.and_time(NaiveTime::MIN), now)
+    );
+}
+
+pub fn handle_join(cal: &mut Calendar, x: Join) -> Result<(), CalendarError> {
+    let tolerance = event::parse_lead_time(&x.tolerance)
+        .ok_or_else(|| CalendarError::Unknown(format!("Invalid tolerance: {}", x.tolerance)))?;
+    let eid = cal.join_events(x.eid1, x.eid2, tolerance)?;
+    if let Some(ev) = cal.peek_event(eid) {
+        let start = ev.get_start();
+        println!(
+            "Joined into event {}: \"{}\" from {} to {}",
+            eid,
+            ev.get_title(),
+            start.format("%d/%m/%Y %H:%M"),
+            ev.occurrence_end(start).format("%d/%m/%Y %H:%M")
+        );
+    }
+    Ok(())
+}
+
+/// Parses the offset of the shift command: a lead time (see [`event::parse_lead_time`]),
+/// negative if it starts with '-'
+fn parse_offset(s: &str) -> Option<Duration> {
+    match s.trim().strip_prefix('-') {
+        Some(back) => event::parse_lead_time(back).map(|d| -d),
+        None => event::parse_lead_time(s.trim().strip_prefix('+').unwrap_or(s)),
+    }
+}
+
+pub fn handle_shift(cal: &mut Calendar, x: Shift) -> Result<(), CalendarError> {
+    let offset = parse_offset(&x.by)
+        .ok_or_else(|| CalendarError::Unknown(format!("Invalid offset: {}", x.by)))?;
+    let mut selected: Vec<(u64, &Event)> = cal
+        .events_by_eid()
+        .into_iter()
+        .filter(|(eid, ev)| {
+            (x.eids.is_empty() || x.eids.contains(eid))
+                && x.tag.as_ref().is_none_or(|tag| ev.has_tag(tag))
+                && x.query.as_ref().is_none_or(|q| q.matches(ev))
+        })
+        .collect();
+    if let Some(eid) = x.eids.iter().find(|eid| cal.peek_event(**eid).is_none()) {
+        return Err(CalendarError::EventNotFound(*eid));
+    }
+    if selected.is_empty() {
+        return Err(CalendarError::Unknown(String::from("no events to move")));
+    }
+    selected.sort_by_key(|(_, ev)| ev.get_start());
+    let mut eids: Vec<u64> = selected.into_iter().map(|(eid, _)| eid).collect();
+    let dependents = cal.followers(&eids);
+    if !dependents.is_empty() {
+        let titles: Vec<String> = dependents
+            .iter()
+            .filter_map(|eid| cal.peek_event(*eid))
+            .map(|ev| format!("\"{}\"", ev.get_title()))
+            .collect();
+        let question = format!(
+            "Move the events scheduled after them too ({})?",
+            titles.join(", ")
+        );
+        let with = if x.with_dependents || x.without_dependents {
+            x.with_dependents
+        } else if io::stdin().is_terminal() {
+            Prompt::new(Terminal::new()?).confirm(&question, true)?
+        } else {
+            report::warning(format!(
+                "{} events scheduled after them not moved: use --with-dependents to move them",
+                dependents.len()
+            ));
+            false
+        };
+        if with {
+            eids.extend(dependents);
+        }
+    }
+    cal.move_events(&eids, offset)?;
+    for eid in eids.iter() {
+        if let Some(ev) = cal.peek_event(*eid) {
+            println!(
+                "Moved event {}: \"{}\" to {}",
+                eid,
+                ev.get_title(),
+                ev.get_start().format("%d/%m/%Y %H:%M")
+            );
+        }
+    }
+    println!("{} events moved", eids.len());
+    Ok(())
+}
+
+pub fn handle_location(cal: &mut Calendar, x: LocationCmd) -> Result<(), CalendarError> {
+    let LocationCmd::Set { place, from, to } = x;
+    let place: WorkPlace = place.parse().map_err(CalendarError::Unknown)?;
+    let (first, last) =
+        working_location::parse_range(&from, to.as_deref(), clock::now().date_naive())
+            .map_err(CalendarError::Unknown)?;
+    working_location::set(cal, &place, first, last);
+    match first == last {
+        true => println!("Working from {} on {}", place, first.format("%a %d/%m/%Y")),
+        false => println!(
+            "Working from {} from {} to {}",
+            place,
+            first.format("%a %d/%m/%Y"),
+            last.format("%a %d/%m/%Y")
+        ),
+    }
+    Ok(())
+}
+
+pub fn handle_done(cal: &mut Calendar, x: Done) -> Result<(), CalendarError> {
+    let today = clock::now().date_naive();
+    let date = match &x.date {
+        Some(date) => NaiveDate::parse_from_str(date, "%d/%m/%Y")
+            .map_err(|e| CalendarError::Unknown(format!("Invalid date {}: {}", date, e)))?,
+        None => today,
+    };
+    if date > today {
+        return Err(CalendarError::Unknown(String::from(
+            "Only the occurrences until today can be done",
+        )));
+    }
+    let ev = cal
+        .peek_event(x.eid)
+        .ok_or(CalendarError::EventNotFound(x.eid))?;
+    if !habits::is_habit(ev) {
+        return Err(CalendarError::Unknown(format!(
+            "Event {} is not a habit: tag a recurrent event with \"{}\"",
+            x.eid,
+            habits::HABIT_TAG
+        )));
+    }
+    let until = (date + Duration::days(1)).and_time(NaiveTime::MIN) - Duration::seconds(1);
+    let occurrence = cal
+        .occurrences_until(x.eid, until)
+        .into_iter()
+        .rfind(|dt| dt.date() == date)
+        .ok_or_else(|| {
+            CalendarError::Unknown(format!(
+                "Event {} does not occur on {}",
+                x.eid,
+                date.format("%d/%m/%Y")
+            ))
+        })?;
+    let title = cal.update_event(x.eid, |ev| {
+        ev.mark_done(occurrence);
+        ev.get_title().to_string()
+    })?;
+    if let Some(streaks) = habits::streaks(cal, x.eid, today) {
+        println!(
+            "Done \"{}\" of {}: {}",
+            title,
+            date.format("%d/%m/%Y"),
+            streaks
+        );
+    }
+    Ok(())
+}
+
+/// Renders a table of the habits of the calendar, by title, with their streaks and how often
+/// they were done, fitted to `width` (if any)
+fn render_habits(cal: &Calendar, width: Option<usize>) -> String {
+    let today = clock::now().date_naive();
+    let mut table = Table::new(vec![
+        Column::flexible("Habit"),
+        Column::fixed("Streak"),
+        Column::fixed("Longest"),
+        Column::fixed("Done"),
+        Column::fixed("Eid"),
+    ]);
+    let mut events: Vec<(u64, &Event)> = cal
+        .events_by_eid()
+        .into_iter()
+        .filter(|(_, ev)| habits::is_habit(ev))
+        .collect();
+    events.sort_by(|a, b| a.1.get_title().cmp(b.1.get_title()));
+    for (eid, ev) in events {
+        let Some(streaks) = habits::streaks(cal, eid, today) else {
+            continue;
+        };
+        let details = ev.humanize_recurrence().map(|r| format!("Repeats {}", r));
+        table.add_row(
+            vec![
+                ev.get_title().to_string(),
+                streaks.current.to_string(),
+                streaks.longest.to_string(),
+                format!("{}/{} ({:.0}%)", streaks.done, streaks.due, streaks.rate()),
+                eid.to_string(),
+            ],
+            details.into_iter().collect(),
+        );
+    }
+    if table.is_empty() {
+        return format!(
+            "No habits: tag a recurrent event with \"{}\"\n",
+            habits::HABIT_TAG
+        );
+    }
+    table.render(width)
+}
+
+pub fn handle_snooze(cal: &Calendar, x: &Snooze, data_dir: &Path) -> Result<(), CalendarError> {
+    let duration = event::parse_lead_time(&x.duration).ok_or_else(|| {
+        CalendarError::Unknown(format!("Invalid snooze duration: {}", x.duration))
+    })?;
+    let s = notify::snooze(cal, x.eid, clock::now().naive_local(), duration, data_dir)?;
+    println!(
+        "Reminder of event {} (starting at {}) snoozed until {}",
+        x.eid,
+        s.start.format("%d/%m/%Y %H:%M"),
+        s.until.format("%d/%m/%Y %H:%M")
+    );
+    Ok(())
+}
+
+pub fn handle_path(x: &DataPath, data_dir: &Path) -> Result<(), CalendarError> {
+    match &x.calendar {
+        Some(c) => {
+            let (cal, root) = storage::resolve_in_roots(c, &data_roots(data_dir))?;
+            println!("{}", storage::calendar_path(cal.get_id(), &root)?.display());
+        }
+        None => println!("{}", data_dir.display()),
+    }
+    Ok(())
+}
+
+/// Opens the data directory with the file manager of the platform
+pub fn open_data_dir(data_dir: &Path) -> Result<(), CalendarError> {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    let io_error = |e: io::Error| CalendarError::Unknown(format!("{}: {}", opener, e));
+    let status = Command::new(opener)
+        .arg(data_dir)
+        .status()
+        .map_err(io_error)?;
+    // explorer exits with 1 even when the directory is opened
+    if !status.success() && !cfg!(windows) {
+        return Err(CalendarError::Unknown(format!(
+            "{} failed: {}",
+            opener, status
+        )));
+    }
+    Ok(())
+}
+
+pub fn handle_restore(x: &Restore, data_dir: &Path) -> Result<(), CalendarError> {
+    let contents = match file_arg(&x.file) {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))?,
+        None => {
+            let mut contents = String::new();
+            io::stdin()
+                .read_to_string(&mut contents)
+                .map_err(|e| CalendarError::Unknown(format!("standard input: {}", e)))?;
+            contents
+        }
+    };
+    let dump: dump::Dump = serde_json::from_str(&contents)
+        .map_err(|e| CalendarError::Unknown(format!("Not a dump: {}", e)))?;
+    let has_config = dump.config.is_some();
+    let restored = dump::restore(dump, x.overwrite, &audit::current_user(), data_dir)?;
+    println!(
+        "Restored {} calendars{}: {}",
+        restored.len(),
+        if has_config {
+            " and the configuration"
+        } else {
+            ""
+        },
+        restored.join(", ")
+    );
+    Ok(())
+}
+
+/// Returns whether the daemon can execute the command on behalf of a client: only the
+/// subcommands that change the calendar and nothing else are allowed, without the flags
+/// reading or writing files, the terminal or the clipboard of the user running it
+pub(crate) fn runs_in_daemon(cmd: &Commands) -> bool {
+    match cmd {
+        Commands::Add(x) => {
+            x.from_file.is_none() && !x.stdin && !x.interactive && !x.from_clipboard
+        }
+        Commands::Edit(x) => x.from_file.is_none() && !x.editor,
+        // without either flag, moving the dependent events is asked in the terminal
+        Commands::Shift(x) => x.with_dependents || x.without_dependents,
+        Commands::Availability(x) => {
+            matches!(
+                x,
+                AvailabilityCmd::Add { .. } | AvailabilityCmd::Remove { .. }
+            )
+        }
+        Commands::Remove(_)
+        | Commands::Join(_)
+        | Commands::Set(_)
+        | Commands::Snooze(_)
+        | Commands::CheckIn(_)
+        | Commands::Done(_)
+        | Commands::Location(_)
+        | Commands::Course(_)
+        | Commands::Rotation(_) => true,
+        _ => false,
+    }
+}
+
+pub fn handle_course(cal: &mut Calendar, x: CourseCmd) -> bool {
+    match course_from_args(x).and_then(|course| course.events()) {
+        Ok(events) => {
+            let added = events
+                .into_iter()
+                .filter(|ev| add_event_warn(cal, ev.clone()).is_some())
+                .count();
+            info!("Added {} weekly meetings", added);
+            added > 0
+        }
+        Err(e) => {
+            report::error(format!("Invalid course: {}", e));
+            false
+        }
+    }
+}
+
+pub fn handle_rotation(cal: &mut Calendar, x: RotationCmd) -> Result<(), CalendarError> {
+    match x {
+        RotationCmd::Create {
+            name,
+            members,
+            cadence,
+            start,
+            until,
+        } => {
+            let rotation = Rotation {
+                name,
+                members: members
+                    .split(',')
+                    .map(|m| m.trim().to_string())
+                    .filter(|m| !m.is_empty())
+                    .collect(),
+                cadence: cadence
+                    .parse()
+                    .map_err(|_| CalendarError::Unknown(format!("Invalid cadence {}", cadence)))?,
+                first: start,
+                last: until.unwrap_or(start + Duration::weeks(13) - Duration::days(1)),
+            };
+            let events = rotation
+                .events()
+                .map_err(|e| CalendarError::Unknown(format!("Invalid rotation: {}", e)))?;
+            let added = events
+                .into_iter()
+                .filter(|ev| add_event_warn(cal, ev.clone()).is_some())
+                .count();
+            println!(
+                "Added the shifts of {} members to the rotation {}",
+                added, rotation.name
+            );
+        }
+        RotationCmd::Swap { date, member, name } => {
+            let previous = rotation::swap(cal, &name, date, &member)?;
+            println!(
+                "The shift of {} on {} is now taken by {}",
+                previous,
+                date.format("%d/%m/%Y"),
+                member
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Adds the flights of the itinerary, each followed by its arrival, related to it
+pub fn handle_itinerary(
+    cal: &mut Calendar,
+    x: Itinerary,
+    data_dir: &Path,
+) -> Result<(), CalendarError> {
+    let lead = |s: &str| {
+        event::parse_lead_time(s)
+            .ok_or_else(|| CalendarError::Unknown(format!("Invalid duration {}", s)))
+    };
+    let (check_in, after) = (lead(&x.check_in)?, lead(&x.after)?);
+    let input = match &x.file {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| CalendarError::Unknown(format!("{}: {}", path, e)))?,
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
+    };
+    let flights = if input.trim_start().starts_with("BEGIN:VCALENDAR") {
+        let (flights, others) =
+            itinerary::parse_ics(&input).map_err(CalendarError::IcsParsingFailed)?;
+        if others > 0 {
+            info!(
+                "{} events of the itinerary are not flights: left out",
+                others
+            );
+        }
+        flights
+    } else {
+        itinerary::parse_text(&input)
+    };
+    if flights.is_empty() {
+        return Err(CalendarError::Unknown(String::from(
+            "No flights found: each needs its number, airports and times",
+        )));
+    }
+    let home = config::load(data_dir)?.time_zone()?;
+    let mut added = 0;
+    for flight in flights {
+        let Some((ev, mut arrival)) = flight.events(home, check_in, after) else {
+            report::warning(format!(
+                "the times of the flight {} do not exist: flight left out",
+                flight.number
+            ));
+            continue;
+        };
+        if let Some(eid) = add_event_warn(cal, ev) {
+            arrival.add_related(eid);
+            add_event_warn(cal, arrival);
+            added += 1;
+        }
+    }
+    println!("Added {} flights", added);
+    Ok(())
+}
+
+pub fn handle_availability(cal: &mut Calendar, x: AvailabilityCmd) -> bool {
+    match x {
+        AvailabilityCmd::Add { label, days, hours } => {
+            match Availability::parse(&label, &days, &hours) {
+                Ok(a) => {
+                    info!("Added availability {}", a);
+                    cal.add_availability(a);
+                    true
+                }
+                Err(e) => {
+                    report::error(format!("Invalid availability: {}", e));
+                    false
+                }
+            }
+        }
+        AvailabilityCmd::Remove { index } => match cal.remove_availability(index) {
+            Some(a) => {
+                info!("Removed availability {}", a);
+                true
+            }
+            None => {
+                report::error(format!("No availability window {}", index));
+                false
+            }
+        },
+        AvailabilityCmd::List => {
+            for (i, a) in cal.get_availability().iter().enumerate() {
+                println!("{}: {}", i, a);
+            }
+            true
+        }
+        AvailabilityCmd::Export { within, format } => {
+            let now = clock::now().naive_local();
+            let (first, last) = match planner::parse_within(&within, now.date()) {
+                Some(period) => period,
+                None => {
+                    report::error(format!("Invalid period {}", within));
+                    return false;
+                }
+            };
+            let slots = availability::bookable_slots(cal, first, last, now);
+            match format {
+                AvailabilityFormat::Html => print!("{}", availability::render_html(cal, &slots)),
+                AvailabilityFormat::Ics => print!(
+                    "{}",
+                    freebusy::vfreebusy(
+                        cal.get_owner(),
+                        first.and_hms_opt(0, 0, 0).unwrap(),
+                        (last + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap(),
+                        &slots,
+                        FbType::Free
+                    )
+                ),
+            }
+            true
+        }
+    }
+}
+
+pub fn handle_slots(cal: &mut Calendar, x: SlotsCmd, data_dir: &Path) -> Result<(), CalendarError> {
+    let now = clock::now().naive_local();
+    match x {
+        SlotsCmd::Publish {
             duration,
-            loc,
-            rec,
-            tags,
+            within,
+            output,
+        } => {
+            let duration = planner::parse_duration(&duration)
+                .ok_or_else(|| CalendarError::Unknown(format!("Invalid duration {}", duration)))?;
+            let (first, last) = planner::parse_within(&within, now.date())
+                .ok_or_else(|| CalendarError::Unknown(format!("Invalid period {}", within)))?;
+            let slots = bookable(cal, first, last, now, data_dir)?;
+            let times = availability::appointments(&slots, duration);
+            let html = availability::render_booking_page(cal, &times);
+            match output {
+                Some(path) => {
+                    fs::write(&path, html)
+                        .map_err(|e| CalendarError::Unknown(format!("{}: {}", path, e)))?;
+                    info!(
+                        "{} appointments of {} written to {}",
+                        times.len(),
+                        cal.get_name(),
+                        path
+                    );
+                }
+                None => print!("{}", html),
+            }
+        }
+        SlotsCmd::Accept { file } => {
+            let requests = handle_ics(&file, data_dir).map_err(CalendarError::IcsParsingFailed)?;
+            let mut rejected = 0;
+            for ev in requests
+                .into_iter()
+                .filter(|r| !r.cancelled)
+                .map(|r| r.event)
+            {
+                let day = ev.get_start().date();
+                let slots = bookable(cal, day, day, now, data_dir)?;
+                let (title, start) = (ev.get_title().to_string(), ev.get_start());
+                match availability::accept_booking(cal, ev, &slots) {
+                    Ok(eid) => println!(
+                        "Booked \"{}\" on {} ({})",
+                        title,
+                        start.format("%d/%m/%Y %H:%M"),
+                        eid
+                    ),
+                    Err(e) => {
+                        report::error(format!("\"{}\" not booked: {}", title, e));
+                        rejected += 1;
+                    }
+                }
+            }
+            if rejected > 0 {
+                return Err(CalendarError::Unknown(format!(
+                    "{} appointments not booked",
+                    rejected
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the slots that can be booked in the calendar: the ones free in its availability
+/// windows, or in the working hours in the configuration if it has none
+fn bookable(
+    cal: &Calendar,
+    first: NaiveDate,
+    last: NaiveDate,
+    now: NaiveDateTime,
+    data_dir: &Path,
+) -> Result<Vec<(NaiveDateTime, NaiveDateTime)>, CalendarError> {
+    if cal.get_availability().is_empty() {
+        let hours = config::load(data_dir)?.working_hours;
+        Ok(planner::free_slots(cal, first, last, &hours, now))
+    } else {
+        Ok(availability::bookable_slots(cal, first, last, now))
+    }
+}
+
+pub fn handle_freebusy(cal: &Calendar, x: FreeBusy) -> bool {
+    let (first, last) = match planner::parse_within(&x.within, clock::now().date_naive()) {
+        Some(period) => period,
+        None => {
+            report::error(format!("Invalid period {}", x.within));
+            return false;
+        }
+    };
+    let from = first.and_hms_opt(0, 0, 0).unwrap();
+    let until = (last + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap();
+    let busy = freebusy::busy_intervals(cal, from, until);
+    let ics = freebusy::vfreebusy(cal.get_owner(), from, until, &busy, FbType::Busy);
+    match x.output {
+        Some(path) => match fs::write(&path, ics) {
+            Ok(()) => {
+                info!("Busy intervals of {} written to {}", cal.get_name(), path);
+                true
+            }
+            Err(e) => {
+                report::error(format!("Cannot write {}: {}", path, e));
+                false
+            }
+        },
+        None => {
+            print!("{}", ics);
+            true
+        }
+    }
+}
+
+pub fn handle_stats(cal: &Calendar, x: Stats, data_dir: &Path) -> Result<(), CalendarError> {
+    let (first, last) = planner::parse_within(&x.within, clock::now().date_naive())
+        .ok_or_else(|| CalendarError::Unknown(format!("Invalid period {}", x.within)))?;
+    if x.sparkline && x.output != StatsFormat::Text {
+        return Err(CalendarError::Unknown(String::from(
+            "the sparklines are only drawn with the text output",
+        )));
+    }
+    let periods = stats::aggregate(cal, first, last, x.by);
+    match x.output {
+        StatsFormat::Text => print!("{}", stats::render_text(&periods, x.sparkline)),
+        StatsFormat::Csv => print!("{}", stats::render_csv(&periods)),
+        StatsFormat::Json => match serde_json::to_string_pretty(&periods) {
+            Ok(json) => println!("{}", json),
+            Err(e) => return Err(CalendarError::Unknown(e.to_string())),
+        },
+    }
+    let budgets = config::load(data_dir)?.budgets;
+    for over in stats::over_budget(cal, &budgets, first, last) {
+        report::warning(over.to_string());
+    }
+    Ok(())
+}
+
+pub fn handle_project(cal: &Calendar, x: ProjectCmd) -> Result<(), CalendarError> {
+    let ProjectCmd::Timeline { tag, from, until } = x;
+    let today = clock::now().date_naive();
+    let from = from
+        .unwrap_or_else(|| today - Duration::days(today.weekday().num_days_from_monday().into()));
+    let until = until.unwrap_or(from + Duration::days(27));
+    if until < from {
+        return Err(CalendarError::Unknown(String::from(
+            "the last day of the timeline is before the first one",
+        )));
+    }
+    let tasks = timeline::tasks(cal, from, until, tag.as_deref());
+    if tasks.is_empty() {
+        println!(
+            "No events from {} to {}",
+            from.format("%d/%m/%Y"),
+            until.format("%d/%m/%Y")
+        );
+    } else {
+        print!("{}", timeline::render(&tasks, from, until));
+    }
+    Ok(())
+}
+
+pub fn handle_heatmap(cal: &Calendar, x: Heatmap) -> Result<(), CalendarError> {
+    let year = x.year.unwrap_or_else(|| clock::now().year());
+    let (first, last) = NaiveDate::from_ymd_opt(year, 1, 1)
+        .zip(NaiveDate::from_ymd_opt(year, 12, 31))
+        .ok_or_else(|| CalendarError::Unknown(format!("Invalid year {}", year)))?;
+    let days = stats::aggregate(cal, first, last, Bucket::Day);
+    let color = !x.no_color && env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal();
+    print!("{}", stats::render_heatmap(&days, color));
+    Ok(())
+}
+
+pub fn handle_show(cal: &Calendar, x: Show, data_dir: &Path) -> bool {
+    let ev = match cal.peek_event(x.eid) {
+        Some(ev) => ev,
+        None => {
+            report::error(format!("{:?}", CalendarError::EventNotFound(x.eid)));
+            return false;
+        }
+    };
+    if x.json {
+        match serde_json::to_string_pretty(ev) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                report::error(format!("Cannot serialize event {}: {}", x.eid, e));
+                return false;
+            }
+        }
+    } else if x.ics {
+        let data = |a: &Attachment| match blobs::load(&a.hash, data_dir) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                report::warning(format!("{:?}: attachment left out", e));
+                None
+            }
+        };
+        match event_ics(x.eid, ev, cal.holidays_of(ev), data_dir, data) {
+            Ok(ics) => print!("{}", ics),
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                return false;
+            }
+        }
+    } else {
+        match config::load(data_dir).and_then(|config| config.time_zone()) {
+            Ok(home) => print!("{}", render_event(cal, x.eid, ev, home)),
+            Err(e) => {
+                report::error(format!("{:?}", e));
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Returns an iCalendar document with the event, in the time zone of the configuration
+fn event_ics(
+    eid: u64,
+    ev: &Event,
+    holidays: Option<&BTreeSet<NaiveDate>>,
+    data_dir: &Path,
+    data: impl Fn(&Attachment) -> Option<Vec<u8>>,
+) -> Result<String, CalendarError> {
+    let tz = config::load(data_dir)?.time_zone()?;
+    let mut components = Vec::new();
+    if let Some(tz) = tz {
+        let (from, until) = ics::span(ev);
+        components.push(ics::vtimezone(tz, from, until));
+    }
+    components.push(ics::vevent(eid, ev, tz, holidays, data));
+    Ok(ics::vcalendar(components))
+}
+
+pub fn handle_share(cal: &Calendar, x: Share, data_dir: &Path) -> Result<(), CalendarError> {
+    let mut ev = cal
+        .peek_event(x.eid)
+        .ok_or(CalendarError::EventNotFound(x.eid))?
+        .clone();
+    if x.redact {
+        ev.redact();
+    }
+    let ev = &ev;
+    if x.qr {
+        print!(
+            "{}",
+            qr::render(&qr::payload(x.eid, ev, cal.holidays_of(ev)))?
+        );
+        return Ok(());
+    }
+    // the attachments are left out, to keep the file light enough for an email
+    let ics = event_ics(x.eid, ev, cal.holidays_of(ev), data_dir, |_| None)?;
+    match x.out {
+        Some(path) => {
+            fs::write(&path, ics)
+                .map_err(|e| CalendarError::Unknown(format!("{}: {}", path, e)))?;
+            info!("\"{}\" written to {}", ev.get_title(), path);
+        }
+        None => print!("{}", ics),
+    }
+    Ok(())
+}
+
+/// Exports all the events in the time zone of the configuration, without their attachments
+pub fn handle_export(cal: &Calendar, x: Export, data_dir: &Path) -> Result<(), CalendarError> {
+    let redacted;
+    let cal = match x.redact {
+        true => {
+            redacted = cal.redacted();
+            &redacted
+        }
+        false => cal,
+    };
+    let anonymized;
+    let cal = match x.anonymize {
+        true => {
+            anonymized = anonymize::calendar(cal);
+            &anonymized
+        }
+        false => cal,
+    };
+    let out = match x.format {
+        ExportFormat::Ics => export_ics(cal, data_dir)?,
+        format => {
+            let spans = cal.events_by_eid().into_iter().map(|(_, ev)| ics::span(ev));
+            let (Some(first), Some(last)) = (
+                x.from.or(spans.clone().map(|(from, _)| from.date()).min()),
+                x.until.or(spans.map(|(_, until)| until.date()).max()),
+            ) else {
+                return Err(CalendarError::Unknown(String::from(
+                    "no events to draw in the diagram",
+                )));
+            };
+            if last < first {
+                return Err(CalendarError::Unknown(String::from(
+                    "the last day of the diagram is before the first one",
+                )));
+            }
+            let tasks = timeline::tasks(cal, first, last, x.tag.as_deref());
+            match format {
+                ExportFormat::MermaidGantt => {
+                    timeline::mermaid_gantt(&tasks, cal.get_name(), |eid| {
+                        cal.peek_event(eid).is_some_and(|ev| ev.is_important())
+                    })
+                }
+                _ => timeline::plantuml_gantt(&tasks, cal.get_name(), first),
+            }
+        }
+    };
+    match x.out {
+        Some(path) => {
+            fs::write(&path, out)
+                .map_err(|e| CalendarError::Unknown(format!("{}: {}", path, e)))?;
+            info!("Calendar exported to {}", path);
+        }
+        None => print!("{}", out),
+    }
+    Ok(())
+}
+
+/// Writes all the events of the calendar as an iCalendar file, with the time zone set in
+/// the configuration
+fn export_ics(cal: &Calendar, data_dir: &Path) -> Result<String, CalendarError> {
+    let tz = config::load(data_dir)?.time_zone()?;
+    let events = cal.events_by_eid();
+    let mut components = Vec::new();
+    let spans = events.iter().map(|(_, ev)| ics::span(ev));
+    if let (Some(tz), Some(from), Some(until)) = (
+        tz,
+        spans.clone().map(|(from, _)| from).min(),
+        spans.map(|(_, until)| until).max(),
+    ) {
+        components.push(ics::vtimezone(tz, from, until));
+    }
+    for (eid, ev) in events.iter() {
+        components.push(ics::vevent(*eid, ev, tz, cal.holidays_of(ev), |_| None));
+    }
+    Ok(ics::vcalendar(components))
+}
+
+/// Describes all the details of an event, one per line, with the times in the zone `home`
+/// (the one of the system if None) and in the one of its location, if set
+pub fn render_event(cal: &Calendar, eid: u64, ev: &Event, home: Option<Tz>) -> String {
+    let mut out = String::new();
+    let mut field = |name: &str, value: &str| {
+        if !value.is_empty() {
+            out.push_str(&format!("{:<12}{}\n", format!("{}:", name), value));
+        }
+    };
+    let start = ev.get_start();
+    let end = ev.occurrence_end(start);
+    field("Title", ev.get_title());
+    field("Eid", &eid.to_string());
+    field(
+        "When",
+        &format!(
+            "{} - {}",
+            start.format("%a %d/%m/%Y %H:%M"),
+            end.format("%a %d/%m/%Y %H:%M")
+        ),
+    );
+    if let Some(tz) = ev.get_time_zone() {
+        let local = |t| zones::convert(t, home, Some(tz));
+        if let (Some(start), Some(end)) = (local(start), local(end)) {
+            field(
+                "Local time",
+                &format!(
+                    "{} - {} ({})",
+                    start.format("%a %d/%m/%Y %H:%M"),
+                    end.format("%a %d/%m/%Y %H:%M"),
+                    tz
+                ),
+            );
+        }
+    }
+    if let Some(recurrence) = ev.humanize_recurrence() {
+        field("Repeats", &recurrence);
+    }
+    field("Location", ev.get_location());
+    if let Some(geo) = ev.get_geo() {
+        field("Geo", &geo.to_string());
+    }
+    if let Some(sun) = ev.get_sun() {
+        field("Sun", &format!("{} at {}", sun, sun.place()));
+    }
+    let reminders: Vec<String> = ev
+        .get_reminders()
+        .iter()
+        .map(|lead| event::format_lead_time(*lead))
+        .collect();
+    if !reminders.is_empty() {
+        field("Reminders", &format!("{} before", reminders.join(", ")));
+    }
+    if !ev.get_resources().is_empty() {
+        field("Resources", &ev.get_resources().join(", "));
+    }
+    for attachment in ev.get_attachments() {
+        let mut details = vec![format!("{} bytes", attachment.size)];
+        if let Some(fmttype) = &attachment.fmttype {
+            details.insert(0, fmttype.clone());
+        }
+        let name = attachment
+            .filename
+            .as_deref()
+            .unwrap_or(&attachment.hash[..12]);
+        field("Attachment", &format!("{} ({})", name, details.join(", ")));
+    }
+    if ev.get_travel_time() > Duration::zero() {
+        field(
+            "Travel",
+            &format!("{} minutes", ev.get_travel_time().num_minutes()),
+        );
+    }
+    if let Some(rec) = ev.get_recurrence() {
+        let mut desc = match rec.cadence() {
+            Cadence::Cron(expr) => format!("cron \"{}\"", expr),
+            cadence => format!("{:?}", cadence).to_lowercase(),
+        };
+        if let Some(interval) = rec.interval() {
+            desc.push_str(&format!(", every {}", interval));
+        }
+        if let Some(month_day) = rec.month_day() {
+            desc.push_str(&format!(", on {}", month_day));
+        }
+        desc.push_str(&format!(", {} repetitions", rec.repetitions()));
+        if let Some(rule) = rec.holidays() {
+            let mut holidays = Vec::new();
+            if rule.weekends {
+                holidays.push(String::from("weekends"));
+            }
+            if let Some(calendar) = &rule.calendar {
+                holidays.push(format!("calendar {}", calendar));
+            }
+            desc.push_str(&format!(
+                ", {:?} on holidays ({})",
+                rule.action,
+                holidays.join(", ")
+            ));
+        }
+        if !rec.exdates().is_empty() {
+            let exdates: Vec<String> = rec
+                .exdates()
+                .iter()
+                .map(|d| d.format("%d/%m/%Y").to_string())
+                .collect();
+            desc.push_str(&format!(", except {}", exdates.join(", ")));
+        }
+        for pause in rec.pauses() {
+            desc.push_str(&format!(", paused {}", pause));
+        }
+        field("Recurrence", &desc);
+    }
+    field("Tags", &ev.get_metadata().get_tags().join(", "));
+    field(
+        "Status",
+        &format!("{:?}, {:?}", ev.get_transparency(), ev.get_class()).to_lowercase(),
+    );
+    if let Some(color) = ev.get_color() {
+        field("Color", &color.to_string());
+    }
+    if ev.is_important() {
+        field("Important", "yes");
+    }
+    if ev.is_milestone() {
+        field("Milestone", "yes");
+    }
+    if let Some(follows) = ev.get_follows() {
+        let anchor = cal
+            .peek_event(follows.eid)
+            .map_or("missing event", |anchor| anchor.get_title());
+        let gap = match follows.gap.is_zero() {
+            true => String::from("right after it"),
+            false => format!("{} after it", event::format_lead_time(follows.gap)),
+        };
+        field("After", &format!("{} [{}], {}", anchor, follows.eid, gap));
+    }
+    for eid in ev.get_metadata().get_depends_on() {
+        let prerequisite = cal
+            .peek_event(*eid)
+            .map_or("missing event", |other| other.get_title());
+        field("Depends on", &format!("{} [{}]", prerequisite, eid));
+    }
+    for (_, related) in cal.list_related(eid) {
+        field(
+            "Related",
+            &format!(
+                "{} ({})",
+                related.get_title(),
+                related.get_start().format("%d/%m/%Y %H:%M")
+            ),
         );
-        Ok(cal.add_event(ev))
+    }
+    let metadata = ev.get_metadata();
+    field(
+        "Created",
+        &metadata.get_creation().format("%d/%m/%Y %H:%M").to_string(),
+    );
+    field(
+        "Modified",
+        &metadata
+            .get_modification()
+            .format("%d/%m/%Y %H:%M")
+            .to_string(),
+    );
+    if !ev.get_description().is_empty() {
+        out.push_str(&format!("\n{}\n", ev.get_description()));
+    }
+    out
+}
+
+pub fn handle_list(cal: &Calendar, x: Filter) -> bool {
+    print!("{}", render_list(cal, x.fit_terminal()));
+    true
+}
+
+/// Describes a dependency that is not met, e.g. `"report" [2] starts at 13/07/2022 09:00,
+/// before "review" [1] ends at 13/07/2022 10:00`
+fn describe_broken(cal: &Calendar, broken: &BrokenDependency) -> String {
+    let title = |eid: u64| {
+        cal.peek_event(eid)
+            .map_or(String::new(), |ev| format!("\"{}\" ", ev.get_title()))
+    };
+    let start = cal
+        .peek_event(broken.eid)
+        .map(|ev| ev.get_start().format("%d/%m/%Y %H:%M").to_string())
+        .unwrap_or_default();
+    match broken.prerequisite_end {
+        Some(end) => format!(
+            "{}[{}] starts at {}, before {}[{}] ends at {}",
+            title(broken.eid),
+            broken.eid,
+            start,
+            title(broken.prerequisite),
+            broken.prerequisite,
+            end.format("%d/%m/%Y %H:%M")
+        ),
+        None => format!(
+            "{}[{}] depends on event {}, that was removed",
+            title(broken.eid),
+            broken.eid,
+            broken.prerequisite
+        ),
     }
 }
 
-pub fn handle_edit(cal: &mut Calendar, x: Edit) -> Result<bool, CalendarError> {
-    if let Some(_) = x.from_file {
-        return Err(CalendarError::Unknown("Unimplemented!".to_owned()));
-    }
-    match cal.get_event(x.eid) {
-        Ok(ev) => {
-            if let Some(title) = x.title {
-                ev.set_title(&title);
-            }
-            if let Some(descr) = x.description {
-                ev.set_description(&descr);
-            }
-            if let Some(s) = x.start_date {
-                let date_formats = vec!["%d/%m/%Y", "%Y-%m-%d"];
-                for fmt in date_formats {
-                    if let Ok(val) = NaiveDate::parse_from_str(&s, fmt) {
-                        ev.set_start_date((val.day(), val.month(), val.year()));
-                        break;
-                    }
-                }
-            }
-            if let Some(s) = x.start_time {
-                let time_formats = vec!["%H:%M", "%H:%M:%S"];
-                for fmt in time_formats {
-                    if let Ok(val) = NaiveTime::parse_from_str(&s, fmt) {
-                        ev.set_start_time((val.hour(), val.minute(), val.second()));
-                        break;
-                    }
-                }
-            }
-            if let Some(duration) = x.duration {
-                ev.set_duration(&Duration::hours(duration.parse::<i32>().unwrap().into()));
-            }
-            if let Some(loc) = x.location {
-                ev.set_location(&loc);
-            }
-            if let Some(rec) = x.recurrence {
-                ev.set_recurrence(&rec);
-            }
-            if x.tags.len() > 0 {
-                ev.set_tags(x.tags);
-            }
-            Ok(true)
+/// Warns about the dependencies not met that involve the events with the given eids, as
+/// the ones depending on the others or depended on
+fn warn_dependencies(cal: &Calendar, eids: &[u64]) {
+    for broken in cal.broken_dependencies() {
+        if eids.contains(&broken.eid) || eids.contains(&broken.prerequisite) {
+            report::warning(format!(
+                "dependency not met: {}",
+                describe_broken(cal, &broken)
+            ));
         }
-        _ => Err(CalendarError::Unknown("Unimplemented!".to_string())),
     }
 }
 
-pub fn handle_list(cal: &Calendar, x: Filter) -> bool {
-    let dt = Local::now().naive_local();
-    /// TODO: error handling in the match arms abstracted into a function
-    let events = match x {
-        Filter { today: true, .. } => {
-            let start = dt.with_hour(0).unwrap().with_minute(0).unwrap();
-            let end = dt.with_hour(23).unwrap().with_minute(59).unwrap();
-            cal.list_events_between(Some(start), Some(end))
-        }
-        Filter { week: true, .. } => {
-            let weekday = dt.weekday();
-            let start = dt
-                .with_day(dt.day() - weekday.num_days_from_monday())
-                .unwrap()
-                .with_hour(0)
-                .unwrap()
-                .with_minute(0)
-                .unwrap();
-            let end = dt
-                .with_day(dt.day() - weekday.num_days_from_sunday())
-                .unwrap()
-                .with_hour(0)
-                .unwrap()
-                .with_minute(0)
-                .unwrap();
-            cal.list_events_between(Some(start), Some(end))
-        }
-        Filter { month: true, .. } => {
-            let start = dt
-                .with_day(1)
-                .unwrap()
-                .with_hour(0)
-                .unwrap()
-                .with_minute(0)
-                .unwrap();
-            let end = dt
-                .with_day(31)
-                .unwrap_or(dt.with_day(30).unwrap())
-                .with_hour(23)
-                .unwrap()
-                .with_minute(59)
-                .unwrap();
-            cal.list_events_between(Some(start), Some(end))
+/// Renders the events of the calendar selected by the filter
+pub fn render_list(cal: &Calendar, x: Filter) -> String {
+    if x.redact {
+        return render_list(&cal.redacted(), Filter { redact: false, ..x });
+    }
+    if x.blocked {
+        return cal
+            .broken_dependencies()
+            .iter()
+            .map(|broken| describe_broken(cal, broken) + "\n")
+            .collect();
+    }
+    let today = clock::now().date_naive();
+    let day_start = |d: NaiveDate| d.and_hms_opt(0, 0, 0).unwrap();
+    let day_end = |d: NaiveDate| d.and_hms_opt(23, 59, 0).unwrap();
+    let (created_after, modified_since) = (x.created_after, x.modified_since);
+    let (full, width, color, important_only) = (x.full, x.width, x.color, x.important_only);
+    // the first and last day of the views of a period, showing the events on all their days
+    let period = if x.today {
+        Some((today, today))
+    } else if x.week {
+        let monday = today - Duration::days(today.weekday().num_days_from_monday().into());
+        Some((monday, monday + Duration::days(6)))
+    } else if x.month {
+        let first = today.with_day(1).unwrap();
+        Some((first, first + Months::new(1) - Duration::days(1)))
+    } else {
+        None
+    };
+    // the working locations of the days are shown above the events of today and of this week
+    let banner = period.filter(|_| x.today || x.week);
+    // TODO: error handling in the match arms abstracted into a function
+    let mut events = match (period, x) {
+        (Some((first, last)), _) => {
+            cal.list_occurrences_between(Some(day_start(first)), Some(day_end(last)))
         }
-        Filter { tag: Some(tag), .. } => cal.list_events_tagged(tag),
-        Filter {
-            today: false,
-            week: false,
-            month: false,
-            from: None,
-            until: None,
-            tag: None,
-        } => {
+        (
+            _,
+            Filter {
+                related_to: Some(eid),
+                ..
+            },
+        ) => cal.list_related(eid),
+        (_, Filter { tag: Some(tag), .. }) => cal.list_events_tagged(tag),
+        (
+            _,
+            Filter {
+                from: None,
+                until: None,
+                tag: None,
+                related_to: None,
+                created_after: None,
+                modified_since: None,
+                ..
+            },
+        ) => {
             // by default list all events starting from today
-            let start = dt.with_hour(0).unwrap().with_minute(0).unwrap();
-            cal.list_events_between(Some(start), None)
+            cal.list_occurrences_between(Some(day_start(today)), None)
         }
-        Filter {
-            from: x, until: y, ..
-        } => {
-            /// FIXME: Some error handling here
-            let from_dt = match x {
-                Some(s) => Some(
-                    NaiveDateTime::parse_from_str(&s, "%d/%m/%Y")
-                        .unwrap_or(chrono::NaiveDateTime::MIN),
-                ),
-                None => None,
-            };
-            let until_dt = match y {
-                Some(s) => Some(
-                    NaiveDateTime::parse_from_str(&s, "%d/%m/%Y")
-                        .unwrap_or(chrono::NaiveDateTime::MAX),
-                ),
-                None => None,
-            };
-            cal.list_events_between(from_dt, until_dt)
+        (
+            _,
+            Filter {
+                from: x, until: y, ..
+            },
+        ) => {
+            // FIXME: Some error handling here
+            let from_dt = x.map(|s| parse_date(&s).map_or(NaiveDateTime::MIN, day_start));
+            let until_dt = y.map(|s| parse_date(&s).map_or(NaiveDateTime::MAX, day_end));
+            cal.list_occurrences_between(from_dt, until_dt)
         }
     };
-    println!("{}", cal);
-    for ev in events {
-        println!("{}", ev);
+    // the occurrences of recurrent events have the timestamps of the event
+    events.retain(|(_, ev)| {
+        let metadata = ev.get_metadata();
+        created_after.is_none_or(|t| metadata.get_creation().naive_local() >= t)
+            && modified_since.is_none_or(|t| metadata.get_modification().naive_local() >= t)
+    });
+    if banner.is_some() {
+        events.retain(|(_, ev)| ev.get_working_location().is_none());
     }
-    true
+    if important_only {
+        events.retain(|(_, ev)| ev.is_important());
+    }
+    let mut table = Table::new(vec![
+        Column::fixed("Start"),
+        Column::fixed("Duration"),
+        Column::flexible("Title"),
+        Column::flexible("Location"),
+        Column::flexible("Tags"),
+        Column::fixed("Eid"),
+    ]);
+    // the rows are sorted by their start, the continuations on the following days included
+    let mut rows = Vec::new();
+    for (eid, ev) in events {
+        let mut details = Vec::new();
+        // the occurrences are described by the recurrence of their event
+        let stored = cal.peek_event(eid);
+        if let Some(recurrence) = stored.and_then(|ev| ev.humanize_recurrence()) {
+            details.push(format!("Repeats {}", recurrence));
+        }
+        if full && !ev.get_description().is_empty() {
+            details.push(ev.get_description().to_string());
+        }
+        let start = ev.get_start();
+        let days = match period {
+            Some((first, last)) => ev
+                .occurrence_days(start)
+                .into_iter()
+                .filter(|(from, _)| (first..=last).contains(&from.date()))
+                .collect(),
+            None => vec![(start, ev.occurrence_end(start))],
+        };
+        // the important events and the milestones are marked even if not colored
+        let marker = match (ev.is_important(), ev.is_milestone()) {
+            (true, true) => "! ◆ ",
+            (true, false) => "! ",
+            (false, true) => "◆ ",
+            (false, false) => "",
+        };
+        let mut style = String::new();
+        if color {
+            if let Some(c) = ev.get_color() {
+                style.push_str(c.ansi());
+            }
+            if ev.is_important() {
+                style.push_str("\x1b[1m");
+            }
+        }
+        for (from, until) in days {
+            let (title, length) = if from == start {
+                (format!("{}{}", marker, ev.get_title()), ev.get_duration())
+            } else {
+                let title = format!("{}{} (cont.)", marker, ev.get_title());
+                (title, (until - from).num_seconds())
+            };
+            let cells = vec![
+                from.format("%d/%m/%Y %H:%M").to_string(),
+                planner::format_duration(Duration::seconds(length)),
+                title,
+                ev.get_location().to_string(),
+                ev.get_metadata().get_tags().join(", "),
+                eid.to_string(),
+            ];
+            // the details are shown once, under the first day in the view
+            rows.push((from, cells, std::mem::take(&mut details), style.clone()));
+        }
+    }
+    rows.sort_by_key(|(from, _, _, _)| *from);
+    for (_, cells, details, style) in rows {
+        table.add_styled_row(cells, details, &style);
+    }
+    let mut out = format!("{}\n", cal);
+    if let Some((first, last)) = banner {
+        out.push_str(&working_location::banner(cal, first, last));
+    }
+    if !table.is_empty() {
+        out.push_str(&table.render(width));
+    }
+    out
 }
 
 pub fn handle_remove(cal: &mut Calendar, x: Remove) -> bool {
-    if x.all {}
     match x {
         Remove { all: true, .. } => {
             let calsize = cal.get_size();
@@ -636,6 +5075,48 @@ pub fn handle_remove(cal: &mut Calendar, x: Remove) -> bool {
     }
 }
 
+/// Prints the changes between the calendar `before` and `after` the commands were
+/// executed, and the file that would be written with the result
+pub fn print_dry_run(before: &Calendar, after: &Calendar, path: &Path) {
+    let diff = before.diff(after);
+    if before.get_name() != after.get_name() {
+        println!(
+            "Would rename calendar: {} -> {}",
+            before.get_name(),
+            after.get_name()
+        );
+    }
+    if before.get_owner() != after.get_owner() {
+        println!(
+            "Would change owner: {} -> {}",
+            before.get_owner(),
+            after.get_owner()
+        );
+    }
+    for eid in diff.added.iter() {
+        if let Some(ev) = after.peek_event(*eid) {
+            println!("Would add event:\n{}", ev);
+        }
+    }
+    for eid in diff.removed.iter() {
+        if let Some(ev) = before.peek_event(*eid) {
+            println!("Would remove event:\n{}", ev);
+        }
+    }
+    for eid in diff.edited.iter() {
+        if let (Some(old), Some(new)) = (before.peek_event(*eid), after.peek_event(*eid)) {
+            println!("Would edit event:\n{}\ninto:\n{}", old, new);
+        }
+    }
+    if diff.is_empty()
+        && before.get_name() == after.get_name()
+        && before.get_owner() == after.get_owner()
+    {
+        println!("No changes to events");
+    }
+    println!("Would write {}", path.display());
+}
+
 pub fn handle_params(cal: &mut Calendar, params: CalParams) -> bool {
     if let Some(s) = params.name {
         cal.set_name(&s);
@@ -645,3 +5126,1380 @@ pub fn handle_params(cal: &mut Calendar, params: CalParams) -> bool {
     }
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use chrono::{Duration, NaiveDate, NaiveTime};
+
+    use crate::blobs;
+    use crate::calendar::Calendar;
+    use crate::calendar_error::CalendarError;
+    use crate::cli::{
+        add_events_from, event_from_args, event_from_prompts, handle_add, handle_done, handle_edit,
+        handle_export, handle_ics, handle_itinerary, handle_location, handle_share, handle_shift,
+        handle_sync, ics_month_day, import_diff, parse_command, parse_ics, render_event,
+        render_habits, render_list, split_words, CalendarSummary, Cli, Commands, Filter,
+        ImportSummary, SyncCmd,
+    };
+    use crate::clock::{self, FixedClock};
+    use crate::config::CONFIG_FILE;
+    use crate::event::{Class, Color, Event};
+    use crate::ics;
+    use crate::ics_writer;
+    use crate::prompt::Prompt;
+    use crate::sync::{self, Conflict, ConflictPolicy};
+    use crate::testing::{ScriptedAnswers, TempDir};
+    use chrono_tz::Tz;
+    use clap::CommandFactory;
+    use textwrap::core::display_width;
+
+    #[test]
+    /// checks the consistency of the command line definition
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    /// tests that the next event of a calendar is the first one starting after now, not the
+    /// ones in progress
+    fn test_calendar_summary() {
+        let mut cal = Calendar::new("owner", "work");
+        for (title, time) in [("workshop", "09:00"), ("review", "14:00")] {
+            cal.add_event(Event::new(
+                title,
+                "",
+                "13/07/2022",
+                time,
+                2.0,
+                None,
+                None,
+                None,
+            ));
+        }
+        let now = NaiveDate::from_ymd_opt(2022, 7, 13)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        let summary = clock::with(FixedClock::at(now), || {
+            CalendarSummary::new(&cal, PathBuf::from("work.json"), false)
+        });
+        assert_eq!(summary.next_event, Some(now + Duration::hours(4)));
+    }
+
+    #[test]
+    /// tests converting the BYDAY and BYSETPOS parts of RRULEs
+    fn test_ics_month_day() {
+        assert_eq!(ics_month_day("3TH", None).as_deref(), Some("3 thu"));
+        assert_eq!(ics_month_day("-1FR", None).as_deref(), Some("-1 fri"));
+        assert_eq!(
+            ics_month_day("MO,TU,WE,TH,FR", Some("-1")).as_deref(),
+            Some("-1 mon,tue,wed,thu,fri")
+        );
+        // every monday: not a day of the month
+        assert_eq!(ics_month_day("MO", None), None);
+        assert_eq!(ics_month_day("XX", Some("1")), None);
+    }
+
+    #[test]
+    /// tests that RRULE COUNT is the number of occurrences, the first one included
+    fn test_rrule_count() {
+        let occurrences = |rrule: &str| {
+            let doc = format!(
+                "BEGIN:VEVENT\r\nSUMMARY:standup\r\nDTSTART:20220704T093000Z\r\n\
+                DTEND:20220704T094500Z\r\nRRULE:{}\r\nEND:VEVENT\r\n",
+                rrule
+            );
+            let mut comps = icalendar::parser::read_calendar(&doc).unwrap().components;
+            let mut ev = Event::default();
+            super::match_property(&mut ev, comps.remove(0), None);
+            ev.occurrences().count()
+        };
+        assert_eq!(occurrences("FREQ=DAILY;COUNT=3"), 3);
+        assert_eq!(occurrences("FREQ=WEEKLY;COUNT=10;INTERVAL=2"), 10);
+        // a single occurrence: not recurrent
+        assert_eq!(occurrences("FREQ=DAILY;COUNT=1"), 1);
+    }
+
+    #[test]
+    /// tests describing the details of an event
+    fn test_render_event() {
+        let mut cal = Calendar::new("owner", "test");
+        let kickoff = Event::new("kickoff", "", "06/07/2022", "10:00", 1.0, None, None, None);
+        cal.add_event(kickoff.clone());
+        let kickoff_eid = cal.eid_of(&kickoff).unwrap();
+        let mut ev = Event::new(
+            "review",
+            "the whole\ndescription",
+            "13/07/2022",
+            "09:30",
+            2.0,
+            Some("room 1"),
+            Some("weekly 3 2"),
+            Some(vec![String::from("work"), String::from("q3")]),
+        );
+        ev.add_related(kickoff_eid);
+        let out = render_event(&cal, 7, &ev, None);
+        for line in [
+            "Title:      review",
+            "Eid:        7",
+            "When:       Wed 13/07/2022 09:30 - Wed 13/07/2022 11:30",
+            "Location:   room 1",
+            "Recurrence: weekly, every 2, 3 repetitions",
+            "Tags:       work, q3",
+            "Status:     busy, public",
+            "\nthe whole\ndescription\n",
+        ] {
+            assert!(out.contains(line), "{}", line);
+        }
+        // the event is not in the calendar, so it is not related to anything
+        assert!(!out.contains("Related"));
+        let eid = 8;
+        cal.insert_event(eid, ev.clone());
+        assert!(
+            render_event(&cal, eid, &ev, None).contains("Related:    kickoff (06/07/2022 10:00)")
+        );
+    }
+
+    #[test]
+    /// tests splitting script lines into words
+    fn test_split_words() {
+        assert_eq!(
+            split_words("list --today").unwrap(),
+            vec!["list", "--today"]
+        );
+        assert_eq!(
+            split_words("add \"some title\" 'a \"quoted\" descr'  10/03/2022").unwrap(),
+            vec!["add", "some title", "a \"quoted\" descr", "10/03/2022"]
+        );
+        assert_eq!(
+            split_words("add a\\ b \"\"").unwrap(),
+            vec!["add", "a b", ""]
+        );
+        assert!(split_words("add \"unterminated").is_err());
+        assert!(split_words("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    /// tests parsing subcommands without the global flags
+    fn test_parse_command() {
+        assert!(matches!(
+            parse_command(&["list", "--today"]),
+            Ok(Commands::List(_))
+        ));
+        assert!(matches!(
+            parse_command(&["remove", "1234"]),
+            Ok(Commands::Remove(_))
+        ));
+        assert!(parse_command(&["frobnicate"]).is_err());
+        assert!(parse_command(&["-e", "work", "list"]).is_err());
+    }
+
+    #[test]
+    /// tests listing the events by their creation and modification times
+    fn test_list_since() {
+        let mut cal = Calendar::new("owner", "test");
+        let old = Event::new("old", "", "13/07/2020", "09:30", 1.0, None, None, None);
+        let mut old = serde_json::to_value(old).unwrap();
+        old["metadata"]["creation"] = "2020-07-01T10:00:00+00:00".into();
+        old["metadata"]["modification"] = "2020-07-01T10:00:00+00:00".into();
+        let old: Event = serde_json::from_value(old).unwrap();
+        cal.add_event(old.clone());
+        cal.add_event(Event::new(
+            "new",
+            "",
+            "14/07/2020",
+            "09:30",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        let list = |cal: &Calendar, words: &[&str]| match parse_command(words) {
+            Ok(Commands::List(filter)) => render_list(cal, filter),
+            _ => panic!("{:?} is not a list command", words),
+        };
+        let out = list(&cal, &["list", "--created-after", "01/01/2022"]);
+        assert!(out.contains(" new ") && !out.contains(" old "));
+        let out = list(&cal, &["list", "--modified-since", "2020-06-30 12:00"]);
+        assert!(out.contains(" new ") && out.contains(" old "));
+        assert!(parse_command(&["list", "--created-after", "yesterday"]).is_err());
+
+        let eid = cal.eid_of(&old).unwrap();
+        cal.update_event(eid, |e| e.set_title("edited")).unwrap();
+        let out = list(&cal, &["list", "--modified-since", "01/01/2022"]);
+        assert!(out.contains(" edited "));
+    }
+
+    #[test]
+    /// tests listing the events in a table fitted to the width, with their whole
+    /// descriptions, wrapped
+    fn test_list_descriptions() {
+        let mut cal = Calendar::new("owner", "test");
+        let description = "Ordine del giorno: più attività, perché sì. ".repeat(3);
+        // fixed eids, for the widths of the columns not to depend on them
+        cal.insert_event(
+            1,
+            Event::new(
+                "review",
+                &description,
+                "14/07/2022",
+                "09:30",
+                1.0,
+                None,
+                None,
+                None,
+            ),
+        );
+        let list = |words: &[&str], width: Option<usize>| match parse_command(words) {
+            Ok(Commands::List(filter)) => render_list(&cal, Filter { width, ..filter }),
+            _ => panic!("{:?} is not a list command", words),
+        };
+        let out = list(&["list", "--from", "01/07/2022"], None);
+        let lines: Vec<&str> = out.lines().rev().take(2).collect();
+        assert!(lines[1].starts_with("Start             Duration  Title   Location  Tags"));
+        assert!(lines[0].starts_with("14/07/2022 09:30  1h        review"));
+        assert!(!out.contains("Ordine"));
+        let out = list(&["list", "--from", "01/07/2022", "--full"], None);
+        assert!(out.contains(&format!("\n  {}\n", description.trim_end())));
+        let out = list(&["list", "--from", "01/07/2022", "--full"], Some(80));
+        assert!(out.lines().all(|line| display_width(line) <= 80), "{}", out);
+        assert!(out.contains(
+            "\n  Ordine del giorno: più attività, perché sì. Ordine del giorno: più attività,\n"
+        ));
+
+        // the titles are cut to fit the width
+        cal.insert_event(
+            2,
+            Event::new(
+                &"Riunione di coordinamento ".repeat(4),
+                "",
+                "15/07/2022",
+                "09:30",
+                1.0,
+                None,
+                None,
+                None,
+            ),
+        );
+        let filter = match parse_command(&["list", "--from", "01/07/2022"]) {
+            Ok(Commands::List(filter)) => filter,
+            _ => panic!("not a list command"),
+        };
+        let out = render_list(
+            &cal,
+            Filter {
+                width: Some(80),
+                ..filter
+            },
+        );
+        assert!(out.lines().all(|line| display_width(line) <= 80), "{}", out);
+        assert!(
+            out.contains(" Riunione di coordinamento Ri...  "),
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    /// tests adding and editing milestones, marked in the lists
+    fn test_milestone() {
+        let mut cal = Calendar::new("owner", "test");
+        let dir = TempDir::new("milestone");
+        let run = |cal: &mut Calendar, words: &[&str]| match parse_command(words) {
+            Ok(Commands::Add(x)) => handle_add(cal, x, &dir),
+            Ok(Commands::Edit(x)) => handle_edit(cal, x, &dir),
+            _ => panic!("{:?} is not an add or edit command", words),
+        };
+        let words = ["add", "release", "", "15/07/2022", "17:00", "--milestone"];
+        assert!(run(&mut cal, &words).unwrap());
+        let (eid, ev) = cal.events_by_eid()[0];
+        assert!(ev.is_milestone());
+        assert_eq!(ev.get_duration(), 0);
+        let words = ["add", "x", "", "15/07/2022", "17:00", "1", "--milestone"];
+        assert!(run(&mut cal, &words).is_err());
+        // a milestone does not overlap with the events at its time
+        let words = ["add", "party", "", "15/07/2022", "16:00", "3"];
+        assert!(run(&mut cal, &words).unwrap());
+        let out = match parse_command(&["list", "--from", "01/07/2022"]) {
+            Ok(Commands::List(filter)) => render_list(&cal, filter),
+            _ => panic!("not a list command"),
+        };
+        assert!(out.contains("  ◆ release  "), "{}", out);
+        let eid = eid.to_string();
+        assert!(run(&mut cal, &["edit", &eid, "--not-milestone"]).unwrap());
+        let ev = cal.events_by_eid()[0].1;
+        assert!(!ev.is_milestone());
+    }
+
+    #[test]
+    /// tests marking the important events, coloring them and listing only them
+    fn test_list_important() {
+        let dir = TempDir::new("list-important");
+        let mut cal = Calendar::new("owner", "test");
+        let add = |words: &[&str]| match parse_command(words) {
+            Ok(Commands::Add(x)) => event_from_args(x, &dir).unwrap(),
+            _ => panic!("{:?} is not an add command", words),
+        };
+        let ev = add(&[
+            "add",
+            "deadline",
+            "",
+            "14/07/2022",
+            "09:30",
+            "1",
+            "--important",
+        ]);
+        assert!(ev.is_important());
+        cal.insert_event(1, ev);
+        let ev = add(&[
+            "add",
+            "lunch",
+            "",
+            "15/07/2022",
+            "12:30",
+            "1",
+            "--color",
+            "Green",
+        ]);
+        assert_eq!(ev.get_color(), Some(Color::Green));
+        cal.insert_event(2, ev);
+        assert!(parse_command(&[
+            "add",
+            "x",
+            "",
+            "15/07/2022",
+            "12:30",
+            "1",
+            "--color",
+            "teal"
+        ])
+        .is_err());
+        let list = |words: &[&str], color: bool| match parse_command(words) {
+            Ok(Commands::List(filter)) => render_list(&cal, Filter { color, ..filter }),
+            _ => panic!("{:?} is not a list command", words),
+        };
+        let out = list(&["list", "--from", "01/07/2022"], false);
+        assert!(out.contains("  ! deadline  ") && out.contains("  lunch  "));
+        assert!(!out.contains('\x1b'));
+        let out = list(&["list", "--from", "01/07/2022"], true);
+        assert!(out.contains("\x1b[1m14/07/2022 09:30"), "{}", out);
+        assert!(out.contains(&format!("{}15/07/2022 12:30", Color::Green.ansi())));
+        let out = list(&["list", "--from", "01/07/2022", "--important-only"], false);
+        assert!(out.contains("deadline") && !out.contains("lunch"));
+
+        let edit = |cal: &mut Calendar, words: &[&str]| match parse_command(words) {
+            Ok(Commands::Edit(x)) => handle_edit(cal, x, &dir),
+            _ => panic!("{:?} is not an edit command", words),
+        };
+        edit(
+            &mut cal,
+            &["edit", "1", "--not-important", "--color", "red"],
+        )
+        .unwrap();
+        edit(&mut cal, &["edit", "2", "--color", "none"]).unwrap();
+        assert!(edit(&mut cal, &["edit", "2", "--color", "teal"]).is_err());
+        let (deadline, lunch) = (cal.peek_event(1).unwrap(), cal.peek_event(2).unwrap());
+        assert!(!deadline.is_important());
+        assert_eq!(deadline.get_color(), Some(Color::Red));
+        assert_eq!(lunch.get_color(), None);
+    }
+
+    #[test]
+    /// tests moving the events selected by eid, by tag or by an expression
+    fn test_shift() {
+        let mut cal = Calendar::new("owner", "test");
+        let tagged = |title: &str, date: &str, tag: &str| {
+            Event::new(
+                title,
+                "",
+                date,
+                "09:00",
+                1.0,
+                None,
+                None,
+                Some(vec![String::from(tag)]),
+            )
+        };
+        cal.insert_event(1, tagged("lecture 1", "03/10/2022", "course101"));
+        cal.insert_event(2, tagged("lecture 2", "10/10/2022", "course101"));
+        cal.insert_event(3, tagged("dentist", "05/10/2022", "health"));
+        let shift = |cal: &mut Calendar, words: &[&str]| match parse_command(words) {
+            Ok(Commands::Shift(x)) => handle_shift(cal, x),
+            _ => panic!("{:?} is not a shift command", words),
+        };
+        let start = |cal: &Calendar, eid: u64| {
+            let ev = cal.peek_event(eid).unwrap();
+            ev.get_start().format("%d/%m/%Y %H:%M").to_string()
+        };
+        shift(&mut cal, &["shift", "--tag", "course101", "--by", "1w"]).unwrap();
+        assert_eq!(start(&cal, 1), "10/10/2022 09:00");
+        assert_eq!(start(&cal, 2), "17/10/2022 09:00");
+        assert_eq!(start(&cal, 3), "05/10/2022 09:00");
+        let query = "tag = course101 and date > 10/10/2022";
+        shift(&mut cal, &["shift", "--where", query, "--by", "-2d1h"]).unwrap();
+        assert_eq!(start(&cal, 1), "10/10/2022 09:00");
+        assert_eq!(start(&cal, 2), "15/10/2022 08:00");
+        shift(&mut cal, &["shift", "1", "3", "--by", "+30m"]).unwrap();
+        assert_eq!(start(&cal, 1), "10/10/2022 09:30");
+        assert_eq!(start(&cal, 3), "05/10/2022 09:30");
+        // nothing selected, a missing eid or an invalid offset: nothing is moved
+        let before = cal.clone();
+        assert!(shift(&mut cal, &["shift", "--tag", "nope", "--by", "1w"]).is_err());
+        assert!(shift(&mut cal, &["shift", "1", "4", "--by", "1w"]).is_err());
+        assert!(shift(&mut cal, &["shift", "1", "--by", "soon"]).is_err());
+        assert_eq!(cal, before);
+        assert!(parse_command(&["shift", "--by", "1w"]).is_err());
+        assert!(parse_command(&["shift", "--where", "tag course101", "--by", "1w"]).is_err());
+    }
+
+    #[test]
+    /// tests adding events after the end of others, and shifting them together
+    fn test_add_after() {
+        let dir = TempDir::new("add-after");
+        let mut cal = Calendar::new("owner", "test");
+        cal.insert_event(
+            1,
+            Event::new("talk", "", "13/07/2022", "10:00", 1.0, None, None, None),
+        );
+        let add = |cal: &mut Calendar, words: &[&str]| match parse_command(words) {
+            Ok(Commands::Add(x)) => handle_add(cal, x, &dir).unwrap(),
+            _ => panic!("{:?} is not an add command", words),
+        };
+        let find = |cal: &Calendar, title: &str| {
+            cal.events_by_eid()
+                .into_iter()
+                .find(|(_, ev)| ev.get_title() == title)
+                .map(|(eid, ev)| (eid, ev.clone()))
+                .unwrap()
+        };
+        // the arguments after the description are the duration, location, recurrence and tags
+        add(
+            &mut cal,
+            &[
+                "add",
+                "--after",
+                "1",
+                "--gap",
+                "15m",
+                "--keep-dependency",
+                "Q&A",
+                "",
+                "2",
+            ],
+        );
+        let (qa, ev) = find(&cal, "Q&A");
+        assert_eq!(
+            ev.get_start().format("%d/%m/%Y %H:%M").to_string(),
+            "13/07/2022 11:15"
+        );
+        assert_eq!(ev.get_duration(), 2 * 3600);
+        assert!(
+            render_event(&cal, qa, &ev, None).contains("After:      talk [1], 15 minutes after it")
+        );
+        add(
+            &mut cal,
+            &[
+                "add",
+                "--after",
+                &qa.to_string(),
+                "lunch",
+                "",
+                "1",
+                "hall",
+                "",
+                "food",
+            ],
+        );
+        let (_, lunch) = find(&cal, "lunch");
+        assert_eq!(lunch.get_start().format("%H:%M").to_string(), "13:15");
+        assert_eq!(lunch.get_location(), "hall");
+        assert!(lunch.has_tag("food"));
+        // the dependency is not kept without --keep-dependency
+        assert!(lunch.get_follows().is_none());
+        assert_eq!(cal.followers(&[1]), vec![qa]);
+        assert!(parse_command(&["add", "x", "--gap", "15m"]).is_err());
+        assert!(parse_command(&["add", "--after", "9", "x", "", "1"]).is_ok());
+
+        let shift = |cal: &mut Calendar, words: &[&str]| match parse_command(words) {
+            Ok(Commands::Shift(x)) => handle_shift(cal, x).unwrap(),
+            _ => panic!("{:?} is not a shift command", words),
+        };
+        shift(
+            &mut cal,
+            &["shift", "1", "--by", "1h", "--without-dependents"],
+        );
+        assert_eq!(
+            find(&cal, "talk").1.get_start().format("%H:%M").to_string(),
+            "11:00"
+        );
+        assert_eq!(
+            find(&cal, "Q&A").1.get_start().format("%H:%M").to_string(),
+            "11:15"
+        );
+        shift(&mut cal, &["shift", "1", "--by", "1d", "--with-dependents"]);
+        assert_eq!(
+            find(&cal, "Q&A")
+                .1
+                .get_start()
+                .format("%d/%m/%Y %H:%M")
+                .to_string(),
+            "14/07/2022 11:15"
+        );
+        assert_eq!(
+            find(&cal, "lunch")
+                .1
+                .get_start()
+                .format("%d/%m/%Y")
+                .to_string(),
+            "13/07/2022"
+        );
+    }
+
+    #[test]
+    /// tests listing the events of today, this week and this month
+    fn test_list_periods() {
+        let mut cal = Calendar::new("owner", "test");
+        let days = [
+            "30/06/2022",
+            "01/07/2022",
+            "03/07/2022",
+            "04/07/2022",
+            "31/07/2022",
+        ];
+        for day in days.iter().chain(["01/08/2022"].iter()) {
+            cal.add_event(Event::new(day, "", day, "09:30", 1.0, None, None, None));
+        }
+        let list = |words: &[&str]| match parse_command(words) {
+            Ok(Commands::List(filter)) => {
+                // on Friday 1 July
+                let now = NaiveDate::from_ymd_opt(2022, 7, 1)
+                    .unwrap()
+                    .and_hms_opt(10, 0, 0)
+                    .unwrap();
+                let out = clock::with(FixedClock::at(now), || render_list(&cal, filter));
+                days.iter()
+                    .chain(["01/08/2022"].iter())
+                    .filter(|day| out.contains(&format!("{} 09:30", day)))
+                    .count()
+            }
+            _ => panic!("{:?} is not a list command", words),
+        };
+        assert_eq!(list(&["list", "--today"]), 1);
+        assert_eq!(list(&["list", "--week"]), 3);
+        assert_eq!(list(&["list", "--month"]), 4);
+        assert_eq!(list(&["list"]), 5);
+    }
+
+    #[test]
+    /// tests setting the working location of some days, shown as a banner in the lists
+    fn test_location() {
+        let mut cal = Calendar::new("owner", "test");
+        let standup = Event::new("standup", "", "05/07/2022", "09:30", 1.0, None, None, None);
+        cal.insert_event(1, standup);
+        // on Friday 1 July
+        let now = NaiveDate::from_ymd_opt(2022, 7, 1)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        let mut run = |words: &[&str]| match parse_command(words) {
+            Ok(Commands::Location(x)) => {
+                clock::with(FixedClock::at(now), || handle_location(&mut cal, x))
+            }
+            _ => panic!("{:?} is not a location command", words),
+        };
+        // from the next Monday
+        assert!(run(&["location", "set", "office", "--from", "mon", "--to", "wed"]).is_ok());
+        assert!(run(&["location", "set", "home", "--from", "06/07/2022"]).is_ok());
+        assert!(run(&["location", "set", "home", "--from", "wed", "--to", "today"]).is_err());
+        assert!(run(&["location", "set", "office", "--from", "someday"]).is_err());
+        assert_eq!(cal.get_size(), 4);
+
+        let list = |words: &[&str]| match parse_command(words) {
+            Ok(Commands::List(filter)) => {
+                // on Tuesday 5 July
+                let now = NaiveDate::from_ymd_opt(2022, 7, 5)
+                    .unwrap()
+                    .and_hms_opt(10, 0, 0)
+                    .unwrap();
+                clock::with(FixedClock::at(now), || render_list(&cal, filter))
+            }
+            _ => panic!("{:?} is not a list command", words),
+        };
+        let out = list(&["list", "--week"]);
+        assert!(
+            out.contains("\nMon 04/07: office\nTue 05/07: office\nWed 06/07: home\n"),
+            "{}",
+            out
+        );
+        assert!(!out.contains("Working from"));
+        assert!(out.contains("standup"));
+        let out = list(&["list", "--today"]);
+        assert!(out.contains("\nTue 05/07: office\n"));
+        assert!(!out.contains("Mon 04/07"));
+        // listed as events elsewhere
+        assert!(list(&["list", "--month"]).contains("Working from home"));
+    }
+
+    #[test]
+    /// tests showing the multi-day events on all the days of the views they cover
+    fn test_list_spanning() {
+        let mut cal = Calendar::new("owner", "test");
+        let mut conference = Event::new(
+            "conference",
+            "",
+            "30/06/2022",
+            "09:00",
+            1.0,
+            Some("Bologna"),
+            None,
+            None,
+        );
+        // until 18:00 on 2 July
+        conference.set_duration(&Duration::hours(57));
+        cal.insert_event(1, conference);
+        let dinner = Event::new("dinner", "", "01/07/2022", "20:00", 2.0, None, None, None);
+        cal.insert_event(2, dinner);
+        let list = |words: &[&str]| match parse_command(words) {
+            Ok(Commands::List(filter)) => {
+                // on Friday 1 July
+                let now = NaiveDate::from_ymd_opt(2022, 7, 1)
+                    .unwrap()
+                    .and_hms_opt(10, 0, 0)
+                    .unwrap();
+                let out = clock::with(FixedClock::at(now), || render_list(&cal, filter));
+                out.lines()
+                    .skip_while(|l| !l.starts_with("Start"))
+                    .skip(1)
+                    .map(|l| l.split_whitespace().take(5).collect::<Vec<_>>().join(" "))
+                    .collect::<Vec<_>>()
+            }
+            _ => panic!("{:?} is not a list command", words),
+        };
+        // started yesterday, still going on today
+        assert_eq!(
+            list(&["list", "--today"]),
+            [
+                "01/07/2022 00:00 1d conference (cont.)",
+                "01/07/2022 20:00 2h dinner 2"
+            ]
+        );
+        assert_eq!(
+            list(&["list", "--week"]),
+            [
+                "30/06/2022 09:00 2d9h conference Bologna",
+                "01/07/2022 00:00 1d conference (cont.)",
+                "01/07/2022 20:00 2h dinner 2",
+                "02/07/2022 00:00 18h conference (cont.)"
+            ]
+        );
+    }
+
+    #[test]
+    /// tests summarizing the duplicates and the overlaps of the imported events
+    fn test_import_summary() {
+        let dir = TempDir::new("import-summary");
+        let mut cal = Calendar::new("owner", "test");
+        let input = "standup descr 01/01/2022 10:00 1\n\
+            review descr 01/01/2022 10:00 2\n\
+            lunch descr 01/01/2022 13:00 1\n";
+        assert_eq!(add_events_from(&mut cal, input, &dir), (3, 3, 0));
+        let standup = cal
+            .events_by_eid()
+            .into_iter()
+            .find(|(_, ev)| ev.get_title() == "standup");
+        let standup = standup.unwrap().0;
+
+        let mut summary = ImportSummary::default();
+        let ev = |title: &str, time: &str| {
+            Event::new(title, "", "01/01/2022", time, 1.0, None, None, None)
+        };
+        let copy = cal.peek_event(standup).unwrap().clone();
+        assert!(!summary.add(&mut cal, copy));
+        assert!(summary.add(&mut cal, ev("retro", "10:00")));
+        assert!(summary.add(&mut cal, ev("walk", "18:00")));
+        let eid = cal
+            .events_by_eid()
+            .into_iter()
+            .find(|(_, ev)| ev.get_title() == "retro");
+        let eid = eid.unwrap().0;
+        assert_eq!(summary.duplicates, [(String::from("standup"), standup)]);
+        assert_eq!(summary.overlaps.len(), 1);
+        assert_eq!(summary.overlaps[0].1, eid);
+        assert_eq!(summary.overlaps[0].2.len(), 2);
+        let table = summary.render(false);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("1 duplicate events not imported, 1 imported"));
+        assert!(lines[2].starts_with("duplicate ") && lines[2].ends_with(&standup.to_string()));
+        assert!(lines[3].starts_with(&format!("overlap    {}", eid)));
+        assert!(!table.contains('\x1b'));
+        assert!(summary.render(true).contains("\x1b[31m"));
+        assert!(ImportSummary::default().render(true).is_empty());
+    }
+
+    #[test]
+    /// tests adding events read one per line
+    fn test_add_events_from_lines() {
+        let dir = TempDir::new("add-events-from-lines");
+        let mut cal = Calendar::new("owner", "test");
+        let input = "# a comment\n\
+            \"First event\" descr 01/01/2022 10:00 1\n\
+            \n\
+            Second descr 02/01/2022 10:00 notanumber\n\
+            Third descr 03/01/2022 10:00 1 --frobnicate\n\
+            Fourth descr 04/01/2022 10:00 2 Home \"daily 2\" --transparency free \
+            --remind 1w --remind \"2 days\"\n";
+        assert_eq!(add_events_from(&mut cal, input, &dir), (2, 4, 2));
+        let titles: Vec<String> = cal
+            .list_events_between(None, None)
+            .iter()
+            .map(|ev| ev.get_title().to_string())
+            .collect();
+        assert_eq!(titles, ["First event", "Fourth", "Fourth", "Fourth"]);
+        let (eid, fourth) = cal
+            .events_by_eid()
+            .into_iter()
+            .find(|(_, ev)| ev.get_title() == "Fourth")
+            .unwrap();
+        assert!(render_event(&cal, eid, fourth, None).contains("Reminders:  1 week, 2 days before"));
+    }
+
+    #[test]
+    /// tests adding events starting at a time relative to the sun
+    fn test_add_sun() {
+        let dir = TempDir::new("add-sun");
+        let mut cal = Calendar::new("owner", "test");
+        let input = "Aperitivo descr 01/07/2022 18:00 2 --sun \"30m before sunset\"\n";
+        // no position and no location in the configuration
+        assert_eq!(add_events_from(&mut cal, input, &dir), (0, 1, 1));
+
+        fs::write(dir.join(CONFIG_FILE), r#"{"location": "41.9028,12.4964"}"#).unwrap();
+        let input = format!(
+            "{}{}",
+            input, "Midnight descr 01/07/2022 00:00 1 --geo 78.2232,15.6267 --sun sunset\n"
+        );
+        assert_eq!(add_events_from(&mut cal, &input, &dir), (1, 2, 1));
+        let (eid, ev) = cal.events_by_eid()[0];
+        let sun = ev.get_sun().unwrap();
+        assert_eq!(sun.place().to_string(), "41.902800,12.496400");
+        assert_eq!(sun.offset(), -30);
+        assert_ne!(
+            ev.get_start_time(),
+            NaiveTime::from_hms_opt(18, 0, 0).unwrap()
+        );
+        assert!(render_event(&cal, eid, ev, None).contains("Sun:        30m before sunset at 41.9"));
+    }
+
+    #[test]
+    /// tests adding events in the time zone of their location, and showing them in both zones
+    fn test_add_zone() {
+        let dir = TempDir::new("add-zone");
+        fs::write(dir.join(CONFIG_FILE), r#"{"timezone": "Europe/Rome"}"#).unwrap();
+        let mut cal = Calendar::new("owner", "test");
+        // no position to infer the zone from, and an unknown zone
+        let input = "Landing descr 20/10/2026 17:30 1 --zone auto\n\
+                     Landing descr 20/10/2026 17:30 1 --zone Mars/Olympus\n";
+        assert_eq!(add_events_from(&mut cal, input, &dir), (0, 2, 2));
+
+        let input = "Landing descr 20/10/2026 17:30 1 --geo 40.64,-73.78 --zone auto\n";
+        assert_eq!(add_events_from(&mut cal, input, &dir), (1, 1, 0));
+        let (eid, ev) = cal.events_by_eid()[0];
+        assert_eq!(ev.get_time_zone(), Some(Tz::America__New_York));
+        assert_eq!(
+            ev.get_start_time(),
+            NaiveTime::from_hms_opt(23, 30, 0).unwrap()
+        );
+        let out = render_event(&cal, eid, ev, Some(Tz::Europe__Rome));
+        assert!(out.contains("When:       Tue 20/10/2026 23:30 - Wed 21/10/2026 00:30"));
+        assert!(out.contains(
+            "Local time: Tue 20/10/2026 17:30 - Tue 20/10/2026 18:30 (America/New_York)"
+        ));
+    }
+
+    #[test]
+    /// tests adding events read as a JSON array
+    fn test_add_events_from_json() {
+        let dir = TempDir::new("add-events-from-json");
+        let mut ev = Event::default();
+        ev.set_title("from json");
+        let input = serde_json::to_string(&vec![
+            serde_json::to_value(&ev).unwrap(),
+            serde_json::json!({"title": "invalid"}),
+        ])
+        .unwrap();
+        let mut cal = Calendar::new("owner", "test");
+        assert_eq!(add_events_from(&mut cal, &input, &dir), (1, 2, 1));
+        assert_eq!(cal.list_events_between(None, None)[0].as_ref(), &ev);
+        assert_eq!(add_events_from(&mut cal, "[ not json", &dir), (0, 1, 1));
+    }
+
+    #[test]
+    /// tests detecting the format of the events pasted: iCalendar, JSON or arguments
+    fn test_add_events_detect() {
+        let dir = TempDir::new("add-events-detect");
+        let mut cal = Calendar::new("owner", "test");
+        let ics = ics_writer::write_lines([
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            "BEGIN:VEVENT",
+            "UID:1",
+            "SUMMARY:from ics",
+            "DTSTART:20220713T093000",
+            "DTEND:20220713T103000",
+            "COLOR:Purple",
+            "X-CALENDA-RS-IMPORTANT:TRUE",
+            "END:VEVENT",
+            "END:VCALENDAR",
+        ]);
+        assert_eq!(
+            add_events_from(&mut cal, &format!("\n{}", ics), &dir),
+            (1, 1, 0)
+        );
+        let mut ev = Event::new(
+            "from json",
+            "",
+            "14/07/2022",
+            "09:30",
+            1.0,
+            None,
+            None,
+            None,
+        );
+        ev.set_location("room 1");
+        let json = serde_json::to_string_pretty(&ev).unwrap();
+        assert_eq!(add_events_from(&mut cal, &json, &dir), (1, 1, 0));
+        let text = "\"from text\" \"\" 15/07/2022 09:30 1\n";
+        assert_eq!(add_events_from(&mut cal, text, &dir), (1, 1, 0));
+        let titles: Vec<String> = cal
+            .list_events_between(None, None)
+            .iter()
+            .map(|ev| ev.get_title().to_string())
+            .collect();
+        assert_eq!(titles, ["from ics", "from json", "from text"]);
+        let from_ics = &cal.list_events_between(None, None)[0];
+        assert_eq!(from_ics.get_color(), Some(Color::Purple));
+        assert!(from_ics.is_important());
+        assert_eq!(
+            add_events_from(&mut cal, "{\"title\": \"invalid\"}", &dir),
+            (0, 1, 1)
+        );
+        assert_eq!(
+            add_events_from(&mut cal, "BEGIN:VCALENDAR\nBEGIN:VEVENT", &dir),
+            (0, 1, 1)
+        );
+    }
+
+    #[test]
+    /// tests building an event from the answers to the prompts
+    fn test_event_from_prompts() {
+        let cal = Calendar::new("owner", "work");
+        let mut p = Prompt::new(ScriptedAnswers::new(&[
+            "",
+            "review",
+            "",
+            "31/02/2022",
+            "13/07/2022",
+            "9",
+            "09:30",
+            "1.5",
+            "room 1",
+            "every day",
+            "weekly 3",
+            "work q3",
+            "",
+            "private",
+        ]));
+        let ev = event_from_prompts(&mut p, &cal).unwrap();
+        assert_eq!(ev.get_title(), "review");
+        assert_eq!(ev.get_description(), "");
+        assert_eq!(
+            ev.get_start().format("%d/%m/%Y %H:%M").to_string(),
+            "13/07/2022 09:30"
+        );
+        assert_eq!(ev.get_duration(), 5400);
+        assert_eq!(ev.get_location(), "room 1");
+        assert_eq!(ev.get_recurrence().map(|r| r.syntax()).unwrap(), "weekly 3");
+        assert_eq!(ev.get_metadata().get_tags(), ["work", "q3"]);
+        assert_eq!(ev.get_class(), Class::Private);
+        // giving up half way
+        let mut p = Prompt::new(ScriptedAnswers::new(&["review"]));
+        assert!(event_from_prompts(&mut p, &cal).is_err());
+    }
+
+    #[test]
+    /// tests importing the events in an Apple Calendar backup bundle
+    fn test_import_icbu() {
+        let dir = TempDir::new("import.icbu");
+        let events = dir.join("home.calendar").join("Events");
+        fs::create_dir_all(&events).unwrap();
+        let ics = [
+            "BEGIN:VCALENDAR",
+            "BEGIN:VEVENT",
+            "SUMMARY:dinner",
+            "DTSTART;TZID=Europe/Rome:20220713T200000",
+            "DTEND;TZID=Europe/Rome:20220713T220000",
+            "X-APPLE-TRAVEL-DURATION;VALUE=DURATION:PT45M",
+            "X-APPLE-STRUCTURED-LOCATION;VALUE=URI;X-TITLE=\"Da Mario\":geo:45.4642,9.19",
+            "END:VEVENT",
+            "END:VCALENDAR",
+        ];
+        fs::write(events.join("1.ics"), ics.join("\r\n")).unwrap();
+        fs::write(dir.join("Info.plist"), "not an ics file").unwrap();
+
+        let imported = handle_ics(dir.to_str().unwrap(), &dir).unwrap();
+        assert_eq!(imported.len(), 1);
+        let ev = &imported[0].event;
+        assert_eq!(ev.get_title(), "dinner");
+        assert_eq!(
+            ev.get_start().format("%d/%m/%Y %H:%M").to_string(),
+            "13/07/2022 20:00"
+        );
+        assert_eq!(ev.get_duration(), 7200);
+        assert_eq!(ev.get_location(), "Da Mario");
+        assert_eq!(ev.get_geo().unwrap().to_string(), "45.464200,9.190000");
+        assert_eq!(ev.get_travel_time(), Duration::minutes(45));
+    }
+
+    #[test]
+    /// tests importing inline attachments and exporting them back
+    fn test_ics_attachments() {
+        let dir = TempDir::new("attachments");
+        let attach = "ATTACH;FMTTYPE=text/plain;FILENAME=notes.txt;ENCODING=BASE64;\
+            VALUE=BINARY:aGVsbG8sIHdvcmxk";
+        let ics = [
+            "BEGIN:VCALENDAR",
+            "BEGIN:VEVENT",
+            "SUMMARY:review",
+            "DTSTART:20220713T093000",
+            "DTEND:20220713T103000",
+            attach,
+            "ATTACH:https://example.com/slides.pdf",
+            "ATTACH;ENCODING=BASE64;VALUE=BINARY:not base64!",
+            "END:VEVENT",
+            "END:VCALENDAR",
+        ];
+        let file = dir.join("review.ics");
+        fs::write(&file, ics_writer::write_lines(ics)).unwrap();
+
+        let imported = handle_ics(file.to_str().unwrap(), &dir).unwrap();
+        let attachments = imported[0].event.get_attachments();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].size, 12);
+        assert_eq!(attachments[0].fmttype.as_deref(), Some("text/plain"));
+        assert_eq!(attachments[0].filename.as_deref(), Some("notes.txt"));
+        assert_eq!(
+            blobs::load(&attachments[0].hash, &dir).unwrap(),
+            b"hello, world"
+        );
+        let mut cal = Calendar::new("owner", "work");
+        cal.insert_event(1, imported[0].event.clone());
+        assert!(render_event(&cal, 1, &imported[0].event, None)
+            .contains("notes.txt (text/plain, 12 bytes)"));
+
+        // exported inline, and imported again as the same attachment
+        let lines = ics::vevent(1, &imported[0].event, None, None, |a| {
+            blobs::load(&a.hash, &dir).ok()
+        });
+        assert!(lines.iter().any(|l| l == attach));
+        fs::write(&file, ics::vcalendar(vec![lines])).unwrap();
+        let reimported = handle_ics(file.to_str().unwrap(), &dir).unwrap();
+        assert_eq!(reimported[0].event.get_attachments(), attachments);
+        assert!(ics::vevent(1, &imported[0].event, None, None, |_| None)
+            .iter()
+            .all(|l| !l.starts_with("ATTACH")));
+    }
+
+    #[test]
+    /// tests sharing a single event as a minimal iCalendar file
+    fn test_share() {
+        let dir = TempDir::new("share");
+        let mut cal = Calendar::new("owner", "work");
+        let mut review = Event::new("review", "", "13/07/2022", "09:30", 1.0, None, None, None);
+        review.set_location("room 1");
+        cal.insert_event(1, review);
+        cal.insert_event(
+            2,
+            Event::new("lunch", "", "13/07/2022", "12:30", 1.0, None, None, None),
+        );
+        let file = dir.join("review.ics");
+        let share = |words: &[&str]| match parse_command(words) {
+            Ok(Commands::Share(x)) => handle_share(&cal, x, &dir),
+            _ => panic!("{:?} is not a share command", words),
+        };
+        share(&["share", "1", "--out", file.to_str().unwrap()]).unwrap();
+        let ics = fs::read_to_string(&file).unwrap();
+        for line in ["VERSION:2.0", "PRODID:-//calenda-rs//EN", "UID:1"] {
+            assert!(ics.lines().any(|l| l == line), "{} missing", line);
+        }
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        let shared = handle_ics(file.to_str().unwrap(), &dir).unwrap();
+        assert_eq!(shared.len(), 1);
+        assert_eq!(shared[0].event.get_title(), "review");
+        assert_eq!(shared[0].event.get_location(), "room 1");
+        assert!(matches!(
+            share(&["share", "3"]),
+            Err(CalendarError::EventNotFound(3))
+        ));
+    }
+
+    #[test]
+    /// tests exporting all the events, anonymized
+    fn test_export() {
+        let dir = TempDir::new("export");
+        let mut cal = Calendar::new("owner", "work");
+        let review = Event::new(
+            "review",
+            "ask anna@acme.com",
+            "13/07/2022",
+            "09:30",
+            1.0,
+            Some("room 1"),
+            Some("weekly 3"),
+            None,
+        );
+        cal.insert_event(1, review);
+        cal.insert_event(
+            2,
+            Event::new("lunch", "", "13/07/2022", "12:30", 1.0, None, None, None),
+        );
+        let file = dir.join("work.ics");
+        let export = |words: &[&str]| match parse_command(words) {
+            Ok(Commands::Export(x)) => handle_export(&cal, x, &dir),
+            _ => panic!("{:?} is not an export command", words),
+        };
+        let out = file.to_str().unwrap();
+        export(&["export", "--out", out]).unwrap();
+        let ics = fs::read_to_string(&file).unwrap();
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+        assert!(ics.contains("SUMMARY:review"));
+
+        export(&["export", "--anonymize", "--out", out]).unwrap();
+        let ics = fs::read_to_string(&file).unwrap();
+        for text in ["review", "lunch", "room 1", "anna", "acme"] {
+            assert!(!ics.contains(text), "{} in {}", text, ics);
+        }
+        let exported = handle_ics(out, &dir).unwrap();
+        assert_eq!(exported.len(), 2);
+        let (review, original) = (&exported[0].event, cal.peek_event(1).unwrap());
+        assert_eq!(review.get_title(), "Event 1");
+        assert_eq!(review.get_description(), "xxx person1@example.com");
+        assert_eq!(review.get_location(), "Location 1");
+        assert_eq!(review.get_start(), original.get_start());
+        assert_eq!(review.get_duration(), original.get_duration());
+        assert!(review.occurrences().eq(original.occurrences()));
+    }
+
+    #[test]
+    /// tests that the texts with commas, semicolons, backslashes, newlines and wide characters
+    /// are imported as they were exported
+    fn test_ics_text_round_trip() {
+        let description = format!(
+            "Agenda: budget, hiring; C:\\shared\\notes\nthen lunch 🍝\r\n{}",
+            "è, €; 😀 ".repeat(30)
+        );
+        let ev = Event::new(
+            "review, final; 2/2",
+            &description,
+            "13/07/2022",
+            "09:30",
+            1.0,
+            Some("room 1, floor 2"),
+            None,
+            None,
+        );
+        let exported = ics::vcalendar(vec![ics::vevent(1, &ev, None, None, |_| None)]);
+        assert!(exported.split("\r\n").all(|line| line.len() <= 75));
+        let imported = parse_ics(&exported, None).unwrap();
+        assert_eq!(imported.len(), 1);
+        let ev = &imported[0].event;
+        assert_eq!(ev.get_title(), "review, final; 2/2");
+        assert_eq!(ev.get_description(), description.replace("\r\n", "\n"));
+        assert_eq!(ev.get_location(), "room 1, floor 2");
+    }
+
+    #[test]
+    /// tests rejecting the events repeating before their occurrences end
+    fn test_add_overlapping_recurrence() {
+        let dir = TempDir::new("add-recurrence");
+        let mut cal = Calendar::new("owner", "test");
+        let add = |cal: &mut Calendar, words: &[&str]| match parse_command(words) {
+            Ok(Commands::Add(x)) => handle_add(cal, x, &dir),
+            _ => panic!("{:?} is not an add command", words),
+        };
+        let shift = ["add", "shift", "", "04/07/2022", "09:00", "2", "office"];
+        let hourly = [&shift[..], &["hourly 5"]].concat();
+        assert!(matches!(
+            add(&mut cal, &hourly),
+            Err(CalendarError::InvalidRecurrence(_))
+        ));
+        assert_eq!(cal.get_size(), 0);
+        let daily = [&shift[..], &["daily 5"]].concat();
+        assert!(add(&mut cal, &daily).unwrap());
+        // imported anyway
+        let input = "shift descr 11/07/2022 09:00 2 office \"hourly 5\"\n";
+        assert_eq!(add_events_from(&mut cal, input, &dir), (1, 1, 0));
+    }
+
+    #[test]
+    /// tests updating and cancelling the imported events with the same UID
+    fn test_import_sequence() {
+        let dir = TempDir::new("import-sequence");
+        let mut cal = Calendar::new("owner", "work");
+        let invite = |method: &str, sequence: u32, start: &str| {
+            ics_writer::write_lines([
+                "BEGIN:VCALENDAR",
+                &format!("METHOD:{}", method),
+                "BEGIN:VEVENT",
+                "UID:4f1c@example.com",
+                &format!("SEQUENCE:{}", sequence),
+                "SUMMARY:planning",
+                &format!("DTSTART:20220713T{}00", start),
+                "DTEND:20220713T180000",
+                "END:VEVENT",
+                "END:VCALENDAR",
+            ])
+        };
+        assert_eq!(
+            add_events_from(&mut cal, &invite("REQUEST", 0, "1500"), &dir),
+            (1, 1, 0)
+        );
+        let eid = cal.eid_by_uid("4f1c@example.com").unwrap();
+        // the same revision again
+        assert_eq!(
+            add_events_from(&mut cal, &invite("REQUEST", 0, "1500"), &dir),
+            (0, 1, 0)
+        );
+        // moved by the organizer
+        assert_eq!(
+            add_events_from(&mut cal, &invite("REQUEST", 2, "1600"), &dir),
+            (1, 1, 0)
+        );
+        // an earlier revision
+        assert_eq!(
+            add_events_from(&mut cal, &invite("REQUEST", 1, "1500"), &dir),
+            (0, 1, 0)
+        );
+        assert_eq!(cal.get_size(), 1);
+        let ev = cal.peek_event(eid).unwrap();
+        assert_eq!(ev.get_start().format("%H:%M").to_string(), "16:00");
+        assert_eq!(ev.get_metadata().get_revision(), 2);
+        // the events exported by this calendar are matched by their eid
+        let exported = ics::vcalendar(vec![ics::vevent(eid, ev, None, None, |_| None)])
+            .replace("SUMMARY:planning", "SUMMARY:planning\r\nSTATUS:CANCELLED");
+        assert_eq!(add_events_from(&mut cal, &exported, &dir), (1, 1, 0));
+        assert_eq!(cal.get_size(), 0);
+        add_events_from(&mut cal, &invite("REQUEST", 2, "1600"), &dir);
+        assert_eq!(
+            add_events_from(&mut cal, &invite("CANCEL", 3, "1600"), &dir),
+            (1, 1, 0)
+        );
+        assert_eq!(cal.get_size(), 0);
+        // nothing to cancel
+        assert_eq!(
+            add_events_from(&mut cal, &invite("CANCEL", 3, "1600"), &dir),
+            (0, 1, 0)
+        );
+    }
+
+    #[test]
+    /// tests describing the events an iCalendar file would add, update and remove, matched
+    /// by UID, without changing the calendar
+    fn test_import_diff() {
+        let mut cal = Calendar::new("owner", "team");
+        let vevent = |uid: &str, sequence: u32, summary: &str, start: &str, status: &str| {
+            vec![
+                String::from("BEGIN:VEVENT"),
+                format!("UID:{}", uid),
+                format!("SEQUENCE:{}", sequence),
+                format!("SUMMARY:{}", summary),
+                format!("DTSTART:20220713T{}00", start),
+                String::from("DTEND:20220713T180000"),
+                format!("STATUS:{}", status),
+                String::from("END:VEVENT"),
+            ]
+        };
+        let document = |events: Vec<Vec<String>>| {
+            let mut lines = vec![String::from("BEGIN:VCALENDAR")];
+            lines.extend(events.into_iter().flatten());
+            lines.push(String::from("END:VCALENDAR"));
+            ics_writer::write_lines(lines)
+        };
+        let first = document(vec![
+            vevent("planning@team", 0, "planning", "1500", "CONFIRMED"),
+            vevent("retro@team", 0, "retro", "1700", "CONFIRMED"),
+        ]);
+        for ev in parse_ics(&first, None).unwrap() {
+            cal.add_event(ev.event);
+        }
+        let planning = cal.eid_by_uid("planning@team").unwrap();
+        let retro = cal.eid_by_uid("retro@team").unwrap();
+        assert_eq!(
+            import_diff(&cal, parse_ics(&first, None).unwrap()),
+            "0 to add, 0 to update, 0 to remove\n"
+        );
+        let second = document(vec![
+            vevent("planning@team", 1, "planning", "1600", "CONFIRMED"),
+            vevent("retro@team", 1, "retro", "1700", "CANCELLED"),
+            vevent("demo@team", 0, "demo", "1000", "CONFIRMED"),
+        ]);
+        let diff = import_diff(&cal, parse_ics(&second, None).unwrap());
+        let lines: Vec<&str> = diff.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("+ \"demo\" ["));
+        assert!(lines[0].ends_with("] 13/07/2022 10:00 (demo@team)"));
+        assert!(lines[1].starts_with(&format!(
+            "~ \"planning\" [{}] 13/07/2022 16:00 (planning@team): ",
+            planning
+        )));
+        assert!(lines[1].contains("start_time"));
+        assert_eq!(
+            lines[2],
+            format!("- \"retro\" [{}] 13/07/2022 17:00 (retro@team)", retro)
+        );
+        assert_eq!(lines[3], "1 to add, 1 to update, 1 to remove");
+        assert_eq!(cal.get_size(), 2);
+    }
+
+    #[test]
+    /// tests adding the flights of an itinerary, with their arrivals related to them
+    fn test_itinerary() {
+        let dir = TempDir::new("itinerary");
+        fs::write(dir.join(CONFIG_FILE), r#"{"timezone": "Europe/Rome"}"#).unwrap();
+        let file = dir.join("booking.txt");
+        fs::write(&file, "Flight AZ 610 on 20/10/2026: FCO 10:15 - JFK 14:05").unwrap();
+        let mut cal = Calendar::new("owner", "travel");
+        let itinerary = |cal: &mut Calendar, words: &[&str]| match parse_command(words) {
+            Ok(Commands::Itinerary(x)) => handle_itinerary(cal, x, &dir),
+            _ => panic!("{:?} is not an itinerary command", words),
+        };
+        let path = file.to_str().unwrap();
+        itinerary(&mut cal, &["itinerary", path, "--check-in", "3h"]).unwrap();
+        let events = cal.events_by_eid();
+        assert_eq!(events.len(), 2);
+        let (eid, flight) = events
+            .iter()
+            .find(|(_, ev)| ev.get_title().starts_with("Flight"))
+            .unwrap();
+        let (_, arrival) = events
+            .iter()
+            .find(|(_, ev)| ev.get_title() == "Arrival AZ610 at JFK")
+            .unwrap();
+        assert_eq!(flight.get_travel_time(), Duration::hours(3));
+        assert_eq!(arrival.get_related_to(), [*eid]);
+        assert_eq!(
+            arrival.get_start_time(),
+            NaiveTime::from_hms_opt(20, 5, 0).unwrap()
+        );
+
+        fs::write(&file, "Hotel Arts, check-in 20/10/2026 15:00").unwrap();
+        assert!(itinerary(&mut cal, &["itinerary", path]).is_err());
+    }
+
+    #[test]
+    /// tests resolving the conflicts deferred by a pull
+    fn test_sync_resolve() {
+        let dir = TempDir::new("sync");
+        let mut cal = Calendar::new("owner", "work");
+        let base = Event::new("review", "", "13/07/2022", "09:30", 1.0, None, None, None);
+        let mut local = base.clone();
+        local.set_title("retro");
+        let mut remote = base.clone();
+        remote.set_location("room 1");
+        cal.insert_event(1, local.clone());
+        let conflict = |eid| Conflict {
+            eid,
+            time: base.get_start(),
+            base: base.clone(),
+            local: local.clone(),
+            remote: remote.clone(),
+        };
+        sync::defer("work", vec![conflict(1), conflict(2)], &dir).unwrap();
+
+        let resolve = |eid| SyncCmd::Resolve {
+            eid: Some(eid),
+            keep: ConflictPolicy::Remote,
+        };
+        assert!(handle_sync(&mut cal, resolve(3), &dir).is_err());
+        handle_sync(&mut cal, resolve(1), &dir).unwrap();
+        assert_eq!(cal.peek_event(1).unwrap().get_location(), "room 1");
+        assert_eq!(cal.peek_event(1).unwrap().get_metadata().get_revision(), 1);
+        let left = sync::load_conflicts("work", &dir).unwrap();
+        assert_eq!(left, [conflict(2)]);
+        // deferring again keeps the conflict
+        let defer = SyncCmd::Resolve {
+            eid: None,
+            keep: ConflictPolicy::Defer,
+        };
+        handle_sync(&mut cal, defer, &dir).unwrap();
+        assert_eq!(sync::load_conflicts("work", &dir).unwrap(), left);
+    }
+
+    #[test]
+    /// tests marking the occurrences of a habit as done, and listing the habits
+    fn test_done() {
+        let mut cal = Calendar::new("owner", "me");
+        let tags = Some(vec![String::from("habit")]);
+        let run = Event::new(
+            "run",
+            "",
+            "11/07/2022",
+            "07:00",
+            1.0,
+            None,
+            Some("daily 30"),
+            tags,
+        );
+        cal.insert_event(1, run);
+        cal.insert_event(
+            2,
+            Event::new(
+                "read",
+                "",
+                "11/07/2022",
+                "21:00",
+                1.0,
+                None,
+                Some("daily 30"),
+                None,
+            ),
+        );
+        let now = NaiveDate::from_ymd_opt(2022, 7, 13)
+            .unwrap()
+            .and_hms_opt(8, 0, 0)
+            .unwrap();
+        let done = |cal: &mut Calendar, words: &[&str]| match parse_command(words) {
+            Ok(Commands::Done(x)) => clock::with(FixedClock::at(now), || handle_done(cal, x)),
+            _ => panic!("{:?} is not a done command", words),
+        };
+        done(&mut cal, &["done", "1"]).unwrap();
+        done(&mut cal, &["done", "1", "--date", "12/07/2022"]).unwrap();
+        assert!(done(&mut cal, &["done", "1", "--date", "14/07/2022"]).is_err());
+        assert!(done(&mut cal, &["done", "1", "--date", "10/07/2022"]).is_err());
+        assert!(done(&mut cal, &["done", "2"]).is_err());
+        assert!(matches!(
+            done(&mut cal, &["done", "3"]),
+            Err(CalendarError::EventNotFound(3))
+        ));
+        let ev = cal.peek_event(1).unwrap();
+        let at = |d| {
+            NaiveDate::from_ymd_opt(2022, 7, d)
+                .unwrap()
+                .and_hms_opt(7, 0, 0)
+                .unwrap()
+        };
+        assert!(ev.get_metadata().is_done(at(13)) && !ev.get_metadata().is_done(at(11)));
+
+        let habits = clock::with(FixedClock::at(now), || render_habits(&cal, None));
+        let row = habits.lines().find(|l| l.starts_with("run")).unwrap();
+        assert_eq!(
+            row.split_whitespace().collect::<Vec<_>>(),
+            ["run", "2", "2", "2/3", "(67%)", "1"]
+        );
+        assert!(!habits.contains("read"));
+        let none = render_habits(&Calendar::new("owner", "empty"), None);
+        assert!(none.starts_with("No habits"));
+    }
+}
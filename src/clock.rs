@@ -0,0 +1,123 @@
+//! The current time as seen by the library: the system clock, unless replaced (e.g. by the
+//! clock of a browser front end, or by a fixed time in tests)
+
+use std::cell::RefCell;
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+
+/// A source of the current time
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Local>;
+}
+
+/// The clock of the system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+/// A clock stopped at a given time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub DateTime<Local>);
+
+impl FixedClock {
+    /// Returns a clock stopped at the given local time (the earliest one, if the time is
+    /// ambiguous; the same time in UTC, if it does not exist)
+    pub fn at(dt: NaiveDateTime) -> FixedClock {
+        let now = Local
+            .from_local_datetime(&dt)
+            .earliest()
+            .unwrap_or_else(|| Local.from_utc_datetime(&dt));
+        FixedClock(now)
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Local> {
+        self.0
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> DateTime<Local> {
+        (**self).now()
+    }
+}
+
+/// The clock of the process, if replaced
+static CLOCK: RwLock<Option<Arc<dyn Clock>>> = RwLock::new(None);
+
+thread_local! {
+    /// The clock of the current thread, if replaced: takes precedence over the one of the process
+    static THREAD_CLOCK: RefCell<Option<Arc<dyn Clock>>> = const { RefCell::new(None) };
+}
+
+/// Replaces the clock of the whole process
+pub fn set(clock: impl Clock + 'static) {
+    *CLOCK.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(clock));
+}
+
+/// Restores the previous clock of the thread when dropped, even if `with` panics
+struct Restore(Option<Arc<dyn Clock>>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        THREAD_CLOCK.with(|c| *c.borrow_mut() = previous);
+    }
+}
+
+/// Calls `f` with the clock replaced on the current thread only
+pub fn with<R>(clock: impl Clock + 'static, f: impl FnOnce() -> R) -> R {
+    let previous = THREAD_CLOCK.with(|c| c.replace(Some(Arc::new(clock))));
+    let _restore = Restore(previous);
+    f()
+}
+
+/// Returns the clock of the current thread, e.g. to hand it to the threads it spawns
+pub fn current() -> Arc<dyn Clock> {
+    if let Some(clock) = THREAD_CLOCK.with(|c| c.borrow().clone()) {
+        return clock;
+    }
+    match CLOCK.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        Some(clock) => clock.clone(),
+        None => Arc::new(SystemClock),
+    }
+}
+
+/// Returns the current time according to the clock
+pub fn now() -> DateTime<Local> {
+    current().now()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::clock::{self, FixedClock};
+
+    #[test]
+    /// tests replacing the clock on the current thread
+    fn test_with() {
+        let t = NaiveDate::from_ymd_opt(2022, 7, 13)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap();
+        let now = clock::with(FixedClock::at(t), || {
+            let inner = t + chrono::Duration::hours(1);
+            let nested = clock::with(FixedClock::at(inner), clock::now);
+            assert_eq!(nested.naive_local(), inner);
+            clock::now()
+        });
+        assert_eq!(now.naive_local(), t);
+        assert_ne!(clock::now().naive_local(), t);
+        // the other threads keep their clock
+        let other = clock::with(FixedClock::at(t), || std::thread::spawn(clock::now).join());
+        assert_ne!(other.unwrap().naive_local(), t);
+    }
+}
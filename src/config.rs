@@ -0,0 +1,481 @@
+use std::collections::BTreeMap;
+#[cfg(feature = "cli")]
+use std::fs;
+#[cfg(feature = "cli")]
+use std::path::{Path, PathBuf};
+
+use chrono::{NaiveTime, Weekday};
+#[cfg(feature = "cli")]
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "cli")]
+use crate::calendar_error::CalendarError;
+
+/// Name of the configuration file, inside the data directory
+pub const CONFIG_FILE: &str = "config.json";
+/// Environment variable overriding the data directory
+pub const DATA_DIR_VAR: &str = "CALENDA_RS_DATA";
+
+/// User configuration, read from the data directory. Every section is optional,
+/// and the features needing a missing section report it when used
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// Server used to send emails
+    pub smtp: Option<SmtpConfig>,
+    /// Webhooks called by the daemon when events start
+    pub webhooks: Vec<WebhookConfig>,
+    /// Broker the daemon publishes the state of the calendars to
+    pub mqtt: Option<MqttConfig>,
+    /// gRPC interface served by the daemon, besides its socket
+    pub grpc: Option<GrpcConfig>,
+    /// Where the times of the sun are computed for the events without a position, as
+    /// latitude and longitude ("41.9,12.5")
+    pub location: Option<String>,
+    /// IANA name of the time zone of the times of the events ("Europe/Rome"), written along
+    /// with them in iCalendar files: defaults to the one of the system
+    pub timezone: Option<String>,
+    /// When tasks can be scheduled by the planner
+    pub working_hours: WorkingHours,
+    /// When the countdown highlights the upcoming events
+    pub countdown: CountdownThresholds,
+    /// Access to Exchange/Office 365, to pull events from
+    pub exchange: ExchangeConfig,
+    /// Refreshing the calendars subscribed to remote feeds
+    pub subscriptions: SubscriptionsConfig,
+    /// Requests to the remote servers the integrations download data from
+    pub http: HttpConfig,
+    /// How the calendars are written to their files
+    pub storage: StorageConfig,
+    /// Rooms and other resources the events can book, shared by all the calendars
+    pub resources: Vec<ResourceConfig>,
+    /// Housekeeping done by the maintenance subcommand
+    pub maintenance: MaintenanceConfig,
+    /// Hours per week the events with each tag can take (e.g. {"meetings": 10}): adding
+    /// events beyond the budget of a tag is warned about
+    pub budgets: BTreeMap<String, f64>,
+    /// Shared data directories (e.g. a network mount) searched in order for the calendars
+    /// not in this one, relative to it: their calendars can be viewed, but not edited
+    pub shared_roots: Vec<String>,
+}
+
+#[cfg(feature = "cli")]
+impl Config {
+    /// Returns the time zone of the times of the events: the one configured, else the one of
+    /// the system, if known
+    pub fn time_zone(&self) -> Result<Option<Tz>, CalendarError> {
+        match &self.timezone {
+            Some(name) => name
+                .parse()
+                .map(Some)
+                .map_err(|_| CalendarError::InvalidConfig(format!("Unknown time zone {}", name))),
+            None => Ok(iana_time_zone::get_timezone()
+                .ok()
+                .and_then(|name| name.parse().ok())),
+        }
+    }
+}
+
+/// A room or another resource booked by the events: it is double-booked when more than
+/// `capacity` events take it at the same time
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ResourceConfig {
+    pub name: String,
+    #[serde(default = "default_capacity")]
+    pub capacity: usize,
+}
+
+/// Format of the calendar files: compact files are smaller, while sorting the events by
+/// id keeps the diffs between two saves minimal (when versioning or syncing the data
+/// directory)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub pretty: bool,
+    pub sorted: bool,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            pretty: true,
+            sorted: false,
+        }
+    }
+}
+
+/// Retention periods of the maintenance subcommand, in days: without one, the data is
+/// kept forever
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    /// Snapshots taken longer ago are deleted, but the last one of each calendar
+    pub snapshot_retention_days: Option<u32>,
+    /// Events ended longer ago are moved to the archive
+    pub archive_after_days: Option<u32>,
+}
+
+/// Behaviour of the HTTP client (see [`crate::http::Client`])
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub timeout_secs: u64,
+    /// URL of the proxy, defaults to the one in $HTTPS_PROXY or $ALL_PROXY, if any
+    pub proxy: Option<String>,
+    /// Minimum time between two requests to the same host
+    pub min_interval_ms: u64,
+    /// Whether the responses are cached in the data directory
+    pub cache: bool,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            timeout_secs: 30,
+            proxy: None,
+            min_interval_ms: 1000,
+            cache: true,
+        }
+    }
+}
+
+/// Whether viewing a calendar subscribed to a stale feed refreshes it in the background
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SubscriptionsConfig {
+    pub auto_refresh: bool,
+}
+
+impl Default for SubscriptionsConfig {
+    fn default() -> Self {
+        SubscriptionsConfig { auto_refresh: true }
+    }
+}
+
+/// Access to the Microsoft Graph API, and the range of the events pulled from Exchange
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ExchangeConfig {
+    /// OAuth access token, defaults to $CALENDA_RS_GRAPH_TOKEN
+    pub token: Option<String>,
+    pub base_url: String,
+    /// Days before today the events are pulled from...
+    pub past_days: u32,
+    /// ...and days after today they are pulled until
+    pub future_days: u32,
+}
+
+impl Default for ExchangeConfig {
+    fn default() -> Self {
+        ExchangeConfig {
+            token: None,
+            base_url: String::from("https://graph.microsoft.com/v1.0"),
+            past_days: 30,
+            future_days: 365,
+        }
+    }
+}
+
+/// Thresholds of the countdown: the events starting within `urgent_hours` are shown in red,
+/// those starting within `soon_days` in yellow
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct CountdownThresholds {
+    pub urgent_hours: u32,
+    pub soon_days: u32,
+}
+
+impl Default for CountdownThresholds {
+    fn default() -> Self {
+        CountdownThresholds {
+            urgent_hours: 24,
+            soon_days: 7,
+        }
+    }
+}
+
+/// Working hours, the same on every working day
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WorkingHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub days: Vec<Weekday>,
+}
+
+impl Default for WorkingHours {
+    fn default() -> Self {
+        WorkingHours {
+            start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            days: vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+            ],
+        }
+    }
+}
+
+/// Connection parameters of an SMTP server
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SmtpConfig {
+    pub host: String,
+    /// Defaults to the submission port, with STARTTLS
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Sender address of the emails
+    pub from: String,
+}
+
+/// An HTTP endpoint receiving a POST request when an event starts
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Body of the request, where `{calendar}`, `{title}`, `{description}`, `{location}`,
+    /// `{start}` and `{end}` are replaced by the values of the event, and `{reminder}` by the
+    /// lead time of the reminder (empty when the event starts). `{calendar_id}` and `{eid}`
+    /// identify the event, e.g. for an action snoozing the reminder
+    #[serde(default = "default_template")]
+    pub template: String,
+    /// Content type of the body: values are escaped as JSON strings if it is JSON
+    #[serde(default = "default_content_type")]
+    pub content_type: String,
+    /// Ids of the calendars whose events trigger the webhook (all of them if empty)
+    #[serde(default)]
+    pub calendars: Vec<String>,
+}
+
+/// Connection parameters of an MQTT broker, and the topics the state of the calendars is
+/// published to (as retained messages)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Topic of the next event to start, as JSON (`null` if there is none)
+    #[serde(default = "default_next_event_topic")]
+    pub next_event_topic: String,
+    /// Topic of the current state: `busy` during (busy) events, `free` otherwise
+    #[serde(default = "default_busy_topic")]
+    pub busy_topic: String,
+    /// Ids of the calendars whose events are considered (all of them if empty)
+    #[serde(default)]
+    pub calendars: Vec<String>,
+}
+
+/// Address the daemon serves its gRPC interface on (see proto/calendar.proto)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GrpcConfig {
+    #[serde(default = "default_grpc_listen")]
+    pub listen: String,
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_client_id() -> String {
+    String::from("calenda-rs")
+}
+
+fn default_next_event_topic() -> String {
+    String::from("calendar/next_event")
+}
+
+fn default_busy_topic() -> String {
+    String::from("calendar/busy")
+}
+
+fn default_grpc_listen() -> String {
+    String::from("127.0.0.1:50051")
+}
+
+fn default_template() -> String {
+    String::from("{title} starts at {start}")
+}
+
+fn default_content_type() -> String {
+    String::from("text/plain")
+}
+
+fn default_capacity() -> usize {
+    1
+}
+
+/// Returns the absolute path of the data directory: the given one, else the one in
+/// $CALENDA_RS_DATA, else the "data" directory in the current one.
+/// Relative paths are relative to the current directory
+#[cfg(feature = "cli")]
+pub fn data_dir(arg: Option<&Path>) -> Result<PathBuf, CalendarError> {
+    let dir = match arg {
+        Some(dir) => dir.to_path_buf(),
+        None => std::env::var_os(DATA_DIR_VAR)
+            .filter(|v| !v.is_empty())
+            .map_or_else(|| PathBuf::from("data"), PathBuf::from),
+    };
+    Ok(std::env::current_dir()?.join(dir))
+}
+
+/// Returns the directories the calendars are searched in, in order: the data directory, then
+/// the shared roots set in its configuration
+#[cfg(feature = "cli")]
+pub fn data_roots(data_dir: &Path) -> Result<Vec<PathBuf>, CalendarError> {
+    let mut roots = vec![data_dir.to_path_buf()];
+    roots.extend(
+        load(data_dir)?
+            .shared_roots
+            .iter()
+            .map(|root| data_dir.join(root)),
+    );
+    Ok(roots)
+}
+
+/// Reads the configuration in the data directory: the default one is returned if there
+/// is no configuration file, an error if it is not valid
+#[cfg(feature = "cli")]
+pub fn load(data_dir: &Path) -> Result<Config, CalendarError> {
+    let path = data_dir.join(CONFIG_FILE);
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+    let contents = fs::read_to_string(&path)?;
+    serde_json::from_str(&contents)
+        .map_err(|e| CalendarError::InvalidConfig(format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(all(test, feature = "cli"))]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use chrono::{NaiveTime, Weekday};
+
+    use crate::config::{data_dir, data_roots, load, Config, CONFIG_FILE};
+    use crate::testing::TempDir;
+
+    #[test]
+    /// tests reading missing, partial and invalid configurations
+    fn test_load() {
+        let dir = TempDir::new("config");
+        let _ = fs::remove_file(dir.join(CONFIG_FILE));
+        assert_eq!(load(&dir).unwrap(), Config::default());
+
+        fs::write(
+            dir.join(CONFIG_FILE),
+            r#"{"smtp": {"host": "mail.example.com", "from": "me@example.com"}}"#,
+        )
+        .unwrap();
+        let smtp = load(&dir).unwrap().smtp.unwrap();
+        assert_eq!(smtp.host, "mail.example.com");
+        assert_eq!(smtp.port, None);
+
+        fs::write(
+            dir.join(CONFIG_FILE),
+            r#"{"webhooks": [{"url": "https://ntfy.sh/topic"}]}"#,
+        )
+        .unwrap();
+        let config = load(&dir).unwrap();
+        assert!(config.smtp.is_none());
+        assert_eq!(config.webhooks[0].content_type, "text/plain");
+        assert!(config.webhooks[0].calendars.is_empty());
+
+        fs::write(dir.join(CONFIG_FILE), r#"{"mqtt": {"host": "localhost"}}"#).unwrap();
+        let mqtt = load(&dir).unwrap().mqtt.unwrap();
+        assert_eq!(mqtt.port, 1883);
+        assert_eq!(mqtt.busy_topic, "calendar/busy");
+
+        fs::write(
+            dir.join(CONFIG_FILE),
+            r#"{"working_hours": {"start": "08:30", "end": "12:00", "days": ["Sat"]}}"#,
+        )
+        .unwrap();
+        let hours = load(&dir).unwrap().working_hours;
+        assert_eq!(hours.start, NaiveTime::from_hms_opt(8, 30, 0).unwrap());
+        assert_eq!(hours.days, [Weekday::Sat]);
+
+        fs::write(dir.join(CONFIG_FILE), r#"{"countdown": {"soon_days": 3}}"#).unwrap();
+        let countdown = load(&dir).unwrap().countdown;
+        assert_eq!((countdown.urgent_hours, countdown.soon_days), (24, 3));
+
+        fs::write(
+            dir.join(CONFIG_FILE),
+            r#"{"exchange": {"token": "secret"}}"#,
+        )
+        .unwrap();
+        let exchange = load(&dir).unwrap().exchange;
+        assert_eq!(exchange.token.as_deref(), Some("secret"));
+        assert_eq!(exchange.future_days, 365);
+        assert!(load(&dir).unwrap().subscriptions.auto_refresh);
+
+        fs::write(
+            dir.join(CONFIG_FILE),
+            r#"{"subscriptions": {"auto_refresh": false}}"#,
+        )
+        .unwrap();
+        assert!(!load(&dir).unwrap().subscriptions.auto_refresh);
+
+        fs::write(dir.join(CONFIG_FILE), r#"{"http": {"timeout_secs": 5}}"#).unwrap();
+        let http = load(&dir).unwrap().http;
+        assert_eq!((http.timeout_secs, http.min_interval_ms), (5, 1000));
+        assert!(http.cache);
+
+        fs::write(dir.join(CONFIG_FILE), r#"{"storage": {"sorted": true}}"#).unwrap();
+        let storage = load(&dir).unwrap().storage;
+        assert!(storage.pretty && storage.sorted);
+
+        fs::write(dir.join(CONFIG_FILE), r#"{"budgets": {"meetings": 10}}"#).unwrap();
+        assert_eq!(load(&dir).unwrap().budgets["meetings"], 10.0);
+
+        fs::write(dir.join(CONFIG_FILE), r#"{"timezone": "Europe/Rome"}"#).unwrap();
+        let tz = load(&dir).unwrap().time_zone().unwrap();
+        assert_eq!(tz, Some(chrono_tz::Europe::Rome));
+        fs::write(dir.join(CONFIG_FILE), r#"{"timezone": "Mars/Olympus"}"#).unwrap();
+        assert!(load(&dir).unwrap().time_zone().is_err());
+
+        fs::write(dir.join(CONFIG_FILE), r#"{"smtp": {"host": 25}}"#).unwrap();
+        assert!(load(&dir).is_err());
+    }
+
+    #[test]
+    /// tests resolving the data directory given on the command line
+    fn test_data_dir() {
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(data_dir(Some(Path::new("cals"))).unwrap(), cwd.join("cals"));
+        let dir = TempDir::new("data-dir");
+        assert_eq!(data_dir(Some(&dir)).unwrap(), dir.to_path_buf());
+    }
+
+    #[test]
+    /// tests listing the data directory and the shared roots, relative to it
+    fn test_data_roots() {
+        let dir = TempDir::new("data-roots");
+        let _ = fs::remove_file(dir.join(CONFIG_FILE));
+        assert_eq!(data_roots(&dir).unwrap(), vec![dir.to_path_buf()]);
+        fs::write(
+            dir.join(CONFIG_FILE),
+            r#"{"shared_roots": ["/mnt/team", "../family"]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            data_roots(&dir).unwrap(),
+            [
+                dir.to_path_buf(),
+                Path::new("/mnt/team").to_path_buf(),
+                dir.join("../family")
+            ]
+        );
+    }
+}
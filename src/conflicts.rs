@@ -0,0 +1,250 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::calendar::Calendar;
+use crate::calendar_error::CalendarError;
+use crate::event::Event;
+use crate::snapshot;
+use crate::storage;
+
+/// Marker of the conflicting copies made by Syncthing ("work.sync-conflict-20220713-093000-ABCDEFG.json")
+const SYNCTHING_MARKER: &str = ".sync-conflict-";
+/// Marker of the conflicting copies made by Dropbox ("work (Laptop's conflicted copy 2022-07-13).json")
+const DROPBOX_MARKER: &str = "conflicted copy";
+
+/// A copy of a file of the data directory left by a sync tool, as both the local and the
+/// remote file changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictFile {
+    pub path: PathBuf,
+    /// The file it is a conflicting copy of
+    pub original: PathBuf,
+}
+
+/// Returns the path of the file the given one is a conflicting copy of, or None if it is
+/// not a conflicting copy
+pub fn original_of(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let original = if let Some(start) = name.find(SYNCTHING_MARKER) {
+        let rest = &name[start + SYNCTHING_MARKER.len()..];
+        format!(
+            "{}{}",
+            &name[..start],
+            rest.find('.').map_or("", |i| &rest[i..])
+        )
+    } else {
+        let marker = name.find(DROPBOX_MARKER)?;
+        let start = name[..marker].rfind(" (")?;
+        let end = marker + name[marker..].find(')')? + 1;
+        format!("{}{}", &name[..start], &name[end..])
+    };
+    Some(path.with_file_name(original))
+}
+
+/// Returns the conflicting copies in the data directory, sorted by path
+pub fn find(data_dir: &Path) -> Result<Vec<ConflictFile>, CalendarError> {
+    let mut conflicts = Vec::new();
+    for ent in fs::read_dir(data_dir)?.flatten() {
+        let path = ent.path();
+        if let Some(original) = original_of(&path) {
+            conflicts.push(ConflictFile { path, original });
+        }
+    }
+    conflicts.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(conflicts)
+}
+
+/// Merges the events of two versions of a calendar, by eid: the changes made on only one
+/// side since the `base` version (if known) are kept, the events changed on both sides are
+/// taken from the one modified last. Without a base, the events removed on one side are kept.
+/// The other properties of the calendar are the ones of `ours`
+pub fn merge(base: Option<&Calendar>, ours: &Calendar, theirs: &Calendar) -> Calendar {
+    let mut merged = ours.clone();
+    let eids: BTreeSet<u64> = ours
+        .events_by_eid()
+        .into_iter()
+        .chain(theirs.events_by_eid())
+        .map(|(eid, _)| eid)
+        .collect();
+    for eid in eids {
+        let old = base.and_then(|b| b.peek_event(eid));
+        let resolved = match (ours.peek_event(eid), theirs.peek_event(eid)) {
+            (Some(o), Some(t)) if o == t || old == Some(t) => Some(o),
+            (Some(o), Some(t)) if old == Some(o) => Some(t),
+            (Some(o), Some(t)) => Some(newest(o, t)),
+            // removed on one side, unchanged on the other
+            (Some(e), None) | (None, Some(e)) if old == Some(e) => None,
+            (Some(e), None) | (None, Some(e)) => Some(e),
+            (None, None) => None,
+        };
+        match resolved {
+            Some(ev) if ours.peek_event(eid) != Some(ev) => merged.insert_event(eid, ev.clone()),
+            None if ours.peek_event(eid).is_some() => {
+                let _ = merged.remove_event(eid);
+            }
+            _ => (),
+        }
+    }
+    merged
+}
+
+/// Returns the version of an event modified last, preferring the first one
+fn newest<'a>(a: &'a Event, b: &'a Event) -> &'a Event {
+    if b.get_metadata().get_modification() > a.get_metadata().get_modification() {
+        b
+    } else {
+        a
+    }
+}
+
+/// Merges a conflicting copy of a calendar into the calendar, using its latest snapshot as
+/// their common version, saves it as modified by `user` and removes the copy. Returns the
+/// calendar before and after merging
+pub fn resolve(
+    conflict: &ConflictFile,
+    user: &str,
+    data_dir: &Path,
+) -> Result<(Calendar, Calendar), CalendarError> {
+    let ours = storage::read_calendar(&conflict.original)?;
+    let theirs = storage::read_calendar(&conflict.path)?;
+    if ours.get_id() != theirs.get_id() {
+        return Err(CalendarError::Unknown(format!(
+            "{}: not a copy of calendar {}",
+            conflict.path.display(),
+            ours.get_id()
+        )));
+    }
+    let base = match snapshot::list(ours.get_id(), data_dir)?.last() {
+        Some(s) => Some(snapshot::load(ours.get_id(), s, data_dir)?),
+        None => None,
+    };
+    let merged = merge(base.as_ref(), &ours, &theirs);
+    if !storage::save_audited(&ours, &merged, user, data_dir) {
+        return Err(CalendarError::Unknown(format!(
+            "Cannot write calendar {}",
+            ours.get_id()
+        )));
+    }
+    fs::remove_file(&conflict.path)
+        .map_err(|e| CalendarError::Unknown(format!("{}: {}", conflict.path.display(), e)))?;
+    Ok((ours, merged))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use chrono::Duration;
+
+    use crate::calendar::Calendar;
+    use crate::clock::{self, FixedClock};
+    use crate::conflicts::{find, merge, original_of, resolve};
+    use crate::event::Event;
+    use crate::snapshot;
+    use crate::storage::{known_calendars, read_calendar, save_calendar};
+    use crate::testing::TempDir;
+
+    #[test]
+    /// tests recognizing the conflicting copies made by Syncthing and Dropbox
+    fn test_original_of() {
+        let original = |name: &str| original_of(Path::new(name));
+        assert_eq!(
+            original("data/work.sync-conflict-20220713-093000-ABCDEFG.json"),
+            Some(PathBuf::from("data/work.json"))
+        );
+        assert_eq!(
+            original("work (Laptop's conflicted copy 2022-07-13).json"),
+            Some(PathBuf::from("work.json"))
+        );
+        assert_eq!(
+            original("users (conflicted copy).json"),
+            Some(PathBuf::from("users.json"))
+        );
+        assert_eq!(original("work.json"), None);
+        assert_eq!(original("work (copy).json"), None);
+    }
+
+    #[test]
+    /// tests merging the events changed on either side, or both, since the base version
+    fn test_merge() {
+        let ev = |title: &str| Event::new(title, "", "13/07/2022", "09:30", 1.0, None, None, None);
+        let mut base = Calendar::new("owner", "work");
+        for (eid, title) in [
+            (1, "kept"),
+            (2, "ours"),
+            (3, "theirs"),
+            (4, "both"),
+            (5, "gone"),
+        ] {
+            base.insert_event(eid, ev(title));
+        }
+        let mut ours = base.clone();
+        let mut theirs = base.clone();
+        ours.update_event(2, |e| e.set_location("room 1")).unwrap();
+        theirs
+            .update_event(3, |e| e.set_location("room 2"))
+            .unwrap();
+        ours.update_event(4, |e| e.set_location("room 3")).unwrap();
+        let later = clock::now().naive_local() + Duration::hours(1);
+        clock::with(FixedClock::at(later), || {
+            theirs
+                .update_event(4, |e| e.set_location("room 4"))
+                .unwrap()
+        });
+        theirs.remove_event(5).unwrap();
+        theirs.insert_event(6, ev("new"));
+
+        let merged = merge(Some(&base), &ours, &theirs);
+        assert_eq!(merged.peek_event(1), base.peek_event(1));
+        assert_eq!(merged.peek_event(2), ours.peek_event(2));
+        assert_eq!(merged.peek_event(3), theirs.peek_event(3));
+        assert_eq!(merged.peek_event(4), theirs.peek_event(4));
+        assert!(merged.peek_event(5).is_none());
+        assert_eq!(merged.peek_event(6), theirs.peek_event(6));
+        assert_eq!(merge(Some(&base), &theirs, &ours), merged);
+
+        // without a base, nothing is removed
+        let merged = merge(None, &ours, &theirs);
+        assert_eq!(merged.peek_event(5), ours.peek_event(5));
+        assert_eq!(merged.peek_event(2), ours.peek_event(2));
+    }
+
+    #[test]
+    /// tests merging a conflicting copy of a calendar against its latest snapshot
+    fn test_resolve() {
+        let dir = TempDir::new("conflicts");
+        let ev = |title: &str| Event::new(title, "", "13/07/2022", "09:30", 1.0, None, None, None);
+        let mut base = Calendar::new("owner", "work");
+        base.insert_event(1, ev("standup"));
+        base.insert_event(2, ev("review"));
+        assert!(save_calendar(&base, &dir));
+        snapshot::create(&base, clock::now().naive_local(), &dir).unwrap();
+
+        let mut theirs = base.clone();
+        theirs.remove_event(2).unwrap();
+        theirs.insert_event(3, ev("retro"));
+        assert!(save_calendar(&theirs, &dir));
+        let copy = dir.join("work.sync-conflict-20220713-093000-ABCDEFG.json");
+        fs::rename(dir.join("work.json"), &copy).unwrap();
+        let mut ours = base.clone();
+        ours.update_event(1, |e| e.set_location("room 1")).unwrap();
+        assert!(save_calendar(&ours, &dir));
+
+        let found = find(&dir).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].original, dir.join("work.json"));
+        // the copy is not read as another calendar
+        assert_eq!(known_calendars(&dir).unwrap().len(), 1);
+
+        let (before, after) = resolve(&found[0], "tester", &dir).unwrap();
+        assert_eq!(before, ours);
+        assert_eq!(after.peek_event(1), ours.peek_event(1));
+        assert!(after.peek_event(2).is_none());
+        assert_eq!(after.peek_event(3), theirs.peek_event(3));
+        assert_eq!(read_calendar(&dir.join("work.json")).unwrap(), after);
+        assert!(!copy.exists());
+        assert!(find(&dir).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt::Write;
+
+use chrono::{Duration, NaiveDateTime};
+
+use crate::calendar::Calendar;
+use crate::config::CountdownThresholds;
+
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// How close an upcoming event is, according to the thresholds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Urgent,
+    Soon,
+    Later,
+}
+
+impl Urgency {
+    pub fn of(remaining: Duration, thresholds: &CountdownThresholds) -> Urgency {
+        if remaining <= Duration::hours(thresholds.urgent_hours.into()) {
+            Urgency::Urgent
+        } else if remaining <= Duration::days(thresholds.soon_days.into()) {
+            Urgency::Soon
+        } else {
+            Urgency::Later
+        }
+    }
+}
+
+/// Formats the time remaining until an event with its two most significant units,
+/// e.g. "3d 4h", "5h 20m" or "12m"
+pub fn remaining(d: Duration) -> String {
+    let (days, hours, minutes) = (d.num_days(), d.num_hours() % 24, d.num_minutes() % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+/// Lists (at most `limit`) occurrences of the events starting after `now`, optionally only
+/// those with the given tag, from the closest one: one per line, with the time remaining.
+/// The lines are colored according to their urgency if `color` is set
+pub fn render(
+    cal: &Calendar,
+    now: NaiveDateTime,
+    tag: Option<&str>,
+    limit: usize,
+    thresholds: &CountdownThresholds,
+    color: bool,
+) -> String {
+    let mut out = String::new();
+    let upcoming = cal
+        .list_events_between(Some(now), None)
+        .into_iter()
+        // the ongoing events are not upcoming
+        .filter(|ev| ev.get_start() >= now)
+        .filter(|ev| tag.is_none_or(|tag| ev.has_tag(tag)))
+        .take(limit);
+    for ev in upcoming {
+        let left = ev.get_start() - now;
+        let line = format!(
+            "{:>8}  {}  {}",
+            remaining(left),
+            ev.get_start().format("%a %d/%m/%Y %H:%M"),
+            ev.get_title()
+        );
+        let _ = match (color, Urgency::of(left, thresholds)) {
+            (true, Urgency::Urgent) => writeln!(out, "{}{}{}", RED, line, RESET),
+            (true, Urgency::Soon) => writeln!(out, "{}{}{}", YELLOW, line, RESET),
+            _ => writeln!(out, "{}", line),
+        };
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+
+    use crate::calendar::Calendar;
+    use crate::config::CountdownThresholds;
+    use crate::countdown::{remaining, render, Urgency};
+    use crate::event::Event;
+
+    #[test]
+    /// tests listing the upcoming events with the time remaining
+    fn test_render() {
+        let mut cal = Calendar::new("owner", "uni");
+        let tags = |t: &str| Some(vec![String::from(t)]);
+        cal.add_event(Event::new(
+            "algebra",
+            "",
+            "14/07/2022",
+            "09:00",
+            2.0,
+            None,
+            None,
+            tags("exam"),
+        ));
+        cal.add_event(Event::new(
+            "thesis",
+            "",
+            "20/07/2022",
+            "12:00",
+            1.0,
+            None,
+            None,
+            tags("deadline"),
+        ));
+        cal.add_event(Event::new(
+            "physics",
+            "",
+            "30/07/2022",
+            "09:00",
+            2.0,
+            None,
+            None,
+            tags("exam"),
+        ));
+        cal.add_event(Event::new(
+            "past",
+            "",
+            "01/07/2022",
+            "09:00",
+            2.0,
+            None,
+            None,
+            tags("exam"),
+        ));
+        let now = NaiveDate::from_ymd_opt(2022, 7, 13)
+            .unwrap()
+            .and_hms_opt(18, 30, 0)
+            .unwrap();
+        let thresholds = CountdownThresholds::default();
+
+        let out = render(&cal, now, None, 10, &thresholds, false);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("algebra") && lines[0].contains("14h 30m"));
+        assert!(lines[1].ends_with("thesis") && lines[1].contains("6d 17h"));
+        assert!(lines[2].ends_with("physics"));
+
+        let out = render(&cal, now, Some("exam"), 10, &thresholds, true);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("\x1b[31m"));
+        assert!(!lines[1].starts_with('\x1b'));
+        assert_eq!(
+            render(&cal, now, None, 1, &thresholds, false)
+                .lines()
+                .count(),
+            1
+        );
+
+        assert_eq!(remaining(Duration::minutes(12)), "12m");
+        assert_eq!(Urgency::of(Duration::days(3), &thresholds), Urgency::Soon);
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::BTreeSet;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime, Weekday};
+
+use crate::event::{Event, HolidayAction, HolidayRule};
+
+/// The meetings of a course (or of any other weekly activity of a term): on some days of
+/// the week, at the same hours, from the first day of the term to the last one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Course {
+    pub title: String,
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub first: NaiveDate,
+    pub last: NaiveDate,
+    /// Dates without meetings
+    pub except: BTreeSet<NaiveDate>,
+    /// Id of the calendar whose events are holidays, without meetings
+    pub holidays: Option<String>,
+    pub location: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl Course {
+    /// Returns one weekly recurring event for each day of the week the course meets on,
+    /// repeating until the last day of the term: excluded dates and holidays are skipped
+    pub fn events(&self) -> Result<Vec<Event>, String> {
+        if self.first > self.last {
+            return Err(format!(
+                "the term ends ({}) before it starts ({})",
+                self.last.format("%d/%m/%Y"),
+                self.first.format("%d/%m/%Y")
+            ));
+        }
+        if self.start >= self.end {
+            return Err(String::from("the meetings must end after they start"));
+        }
+        let mut events = Vec::new();
+        for day in &self.days {
+            // the first meeting on this day of the week
+            let offset =
+                (7 + day.num_days_from_monday() - self.first.weekday().num_days_from_monday()) % 7;
+            let first = self.first + Duration::days(offset as i64);
+            if first > self.last {
+                continue;
+            }
+            let reps = (self.last - first).num_days() / 7;
+            let except: BTreeSet<NaiveDate> = self
+                .except
+                .iter()
+                .filter(|d| d.weekday() == *day && **d >= first && **d <= self.last)
+                .copied()
+                .collect();
+            if reps == 0 && except.contains(&first) {
+                continue;
+            }
+            let mut ev = Event::new(
+                &self.title,
+                "",
+                &first.format("%d/%m/%Y").to_string(),
+                &self.start.format("%H:%M").to_string(),
+                0.0,
+                self.location.as_deref(),
+                Some(format!("weekly {}", reps).as_str()).filter(|_| reps > 0),
+                Some(self.tags.clone()).filter(|t| !t.is_empty()),
+            );
+            ev.set_duration(&(self.end - self.start));
+            ev.set_exdates(except);
+            ev.set_holidays(self.holidays.as_ref().map(|calendar| HolidayRule {
+                action: HolidayAction::Skip,
+                weekends: false,
+                calendar: Some(calendar.clone()),
+            }));
+            events.push(ev);
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use chrono::{Datelike, NaiveDate, NaiveTime, Weekday};
+
+    use crate::course::Course;
+    use crate::testing::date;
+
+    #[test]
+    /// tests generating the meetings of a course
+    fn test_course_events() {
+        // from thursday 15/09 to tuesday 20/12
+        let mut course = Course {
+            title: String::from("algebra"),
+            days: vec![Weekday::Mon, Weekday::Wed],
+            start: NaiveTime::from_hms_opt(10, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(11, 30, 0).unwrap(),
+            first: date(9, 15),
+            last: date(12, 20),
+            except: BTreeSet::from([date(11, 2), date(11, 3)]),
+            holidays: Some(String::from("holidays")),
+            location: Some(String::from("room 1")),
+            tags: vec![String::from("uni")],
+        };
+        let events = course.events().unwrap();
+        assert_eq!(events.len(), 2);
+        let mondays: Vec<NaiveDate> = events[0].occurrences().map(|dt| dt.date()).collect();
+        assert_eq!(mondays.first(), Some(&date(9, 19)));
+        assert_eq!(mondays.last(), Some(&date(12, 19)));
+        assert_eq!(mondays.len(), 14);
+        let wednesdays: Vec<NaiveDate> = events[1].occurrences().map(|dt| dt.date()).collect();
+        assert_eq!(wednesdays.first(), Some(&date(9, 21)));
+        assert_eq!(wednesdays.last(), Some(&date(12, 14)));
+        assert!(!wednesdays.contains(&date(11, 2)));
+        assert!(wednesdays.iter().all(|d| d.weekday() == Weekday::Wed));
+        assert_eq!(events[0].get_duration(), 5400);
+        assert!(events[0].get_recurrence().unwrap().holidays().is_some());
+        // a single meeting
+        course.last = date(9, 19);
+        let events = course.events().unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].get_recurrence().is_none());
+
+        course.last = date(9, 1);
+        assert!(course.events().is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use std::str::FromStr;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+
+/// Matching times are searched at most this many years after the start, since some
+/// expressions never match (e.g. the 30th of february)
+const MAX_SEARCH_YEARS: i32 = 28;
+
+/// A standard 5-field cron expression: minute, hour, day of the month, month and day of the week.
+/// Each field is `*`, a value, a range `a-b`, a step `*/n` or `a-b/n`, or a list of those
+/// separated by commas; months and days of the week can also be given by name (jan, mon).
+/// As in cron, if both the day of the month and the day of the week are restricted, a day
+/// matches if either does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Parses a value of a field, possibly given by name (`names[i]` being the value `offset + i`)
+fn parse_value(s: &str, min: u32, max: u32, names: &[&str], offset: u32) -> Result<u32, String> {
+    let lower = s.to_lowercase();
+    let value = match names.iter().position(|n| *n == lower) {
+        Some(i) => i as u32 + offset,
+        None => s.parse().map_err(|_| format!("invalid value {}", s))?,
+    };
+    if value < min || value > max {
+        return Err(format!("{} is out of range {}-{}", s, min, max));
+    }
+    Ok(value)
+}
+
+/// Parses a field into the set of its values, as a bit mask.
+/// Returns whether the field is unrestricted (`*`) as well
+fn parse_field(
+    s: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+    offset: u32,
+) -> Result<(u64, bool), String> {
+    let mut mask = 0u64;
+    for part in s.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("invalid step {}", step)),
+            },
+            None => (part, 1),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (
+                parse_value(first, min, max, names, offset)?,
+                parse_value(last, min, max, names, offset)?,
+            ),
+            None => {
+                let value = parse_value(range, min, max, names, offset)?;
+                // "a/n" means from a to the end, every n
+                (value, if step > 1 { max } else { value })
+            }
+        };
+        if first > last {
+            return Err(format!("invalid range {}", range));
+        }
+        for v in (first..=last).step_by(step as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok((mask, s == "*"))
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_ascii_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("{} does not have 5 fields", s));
+        }
+        let (minutes, _) = parse_field(fields[0], 0, 59, &[], 0)?;
+        let (hours, _) = parse_field(fields[1], 0, 23, &[], 0)?;
+        let (days_of_month, any_day_of_month) = parse_field(fields[2], 1, 31, &[], 0)?;
+        let (months, _) = parse_field(fields[3], 1, 12, &MONTHS, 1)?;
+        let (mut days_of_week, any_day_of_week) = parse_field(fields[4], 0, 7, &DAYS, 0)?;
+        // both 0 and 7 are sunday
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week |= 1;
+        }
+        Ok(CronSchedule {
+            minutes,
+            hours,
+            days_of_month,
+            months,
+            days_of_week,
+            any_day_of_month,
+            any_day_of_week,
+        })
+    }
+}
+
+impl CronSchedule {
+    /// Returns whether the schedule runs on the given day
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        let dom = self.days_of_month & (1 << date.day()) != 0;
+        let dow = self.days_of_week & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (false, false) => dom || dow,
+            _ => dom && dow,
+        }
+    }
+
+    /// Returns the first time matching the schedule at or after `from`, if any
+    pub fn next_from(&self, from: NaiveDateTime) -> Option<NaiveDateTime> {
+        // the schedule runs at the start of the minutes
+        let mut dt = from.with_second(0)?.with_nanosecond(0)?;
+        if dt < from {
+            dt = dt.checked_add_signed(Duration::minutes(1))?;
+        }
+        let last_year = from.year().checked_add(MAX_SEARCH_YEARS)?;
+        let mut date = dt.date();
+        let mut time = Some(dt.time());
+        while date.year() <= last_year {
+            if self.matches_day(date) {
+                let t = time.unwrap_or(NaiveTime::MIN);
+                for hour in t.hour()..24 {
+                    if self.hours & (1 << hour) == 0 {
+                        continue;
+                    }
+                    let first_minute = if hour == t.hour() { t.minute() } else { 0 };
+                    if let Some(minute) = (first_minute..60).find(|m| self.minutes & (1 << m) != 0)
+                    {
+                        return date.and_hms_opt(hour, minute, 0);
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+            time = None;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cron::CronSchedule;
+    use crate::testing::at;
+
+    #[test]
+    /// tests parsing and evaluating cron expressions
+    fn test_next_from() {
+        // every weekday at 9:00, from friday 15/07/2022
+        let cron: CronSchedule = "0 9 * * 1-5".parse().unwrap();
+        assert_eq!(cron.next_from(at(15, 8, 0)), Some(at(15, 9, 0)));
+        assert_eq!(cron.next_from(at(15, 9, 0)), Some(at(15, 9, 0)));
+        assert_eq!(cron.next_from(at(15, 9, 1)), Some(at(18, 9, 0)));
+        let cron: CronSchedule = "*/20 8-9 * * sat,sun".parse().unwrap();
+        assert_eq!(cron.next_from(at(15, 10, 0)), Some(at(16, 8, 0)));
+        assert_eq!(cron.next_from(at(16, 8, 1)), Some(at(16, 8, 20)));
+        assert_eq!(cron.next_from(at(16, 9, 41)), Some(at(17, 8, 0)));
+        // either the 1st of the month or a sunday
+        let cron: CronSchedule = "30 12 1 * 0".parse().unwrap();
+        assert_eq!(cron.next_from(at(18, 0, 0)), Some(at(24, 12, 30)));
+        assert_eq!(
+            cron.next_from(at(31, 13, 0)),
+            at(1, 12, 30).checked_add_months(chrono::Months::new(1))
+        );
+        // never matches
+        let cron: CronSchedule = "0 0 30 feb *".parse().unwrap();
+        assert_eq!(cron.next_from(at(1, 0, 0)), None);
+
+        assert!("0 9 * *".parse::<CronSchedule>().is_err());
+        assert!("60 9 * * *".parse::<CronSchedule>().is_err());
+        assert!("0 9 * * 5-1".parse::<CronSchedule>().is_err());
+        assert!("*/0 9 * * *".parse::<CronSchedule>().is_err());
+    }
+}
@@ -0,0 +1,565 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+#[cfg(feature = "grpc")]
+use std::net::TcpListener;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "grpc")]
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::calendar::{Calendar, CalendarDiff};
+use crate::calendar_error::CalendarError;
+use crate::cli::{self, Filter};
+#[cfg(feature = "grpc")]
+use crate::grpc;
+use crate::users::{self, Access, Users};
+use crate::{config, notify, report, storage};
+
+/// How long the client waits for the daemon before falling back to reading the files
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long the daemon waits for a client to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// Longest request the daemon reads, in bytes
+const MAX_REQUEST: u64 = 1 << 20;
+
+/// A request to the daemon: modify a calendar with some commands, then list its events with
+/// some filters. Exchanged as a single line of JSON, as is the response
+#[derive(Serialize, Deserialize)]
+struct Request {
+    calendar: String,
+    /// Authenticates the user, if the daemon has any (see [`Users`])
+    #[serde(default)]
+    token: Option<String>,
+    #[serde(default)]
+    filters: Vec<Filter>,
+    /// Commands modifying the calendar, each as the words of its command line
+    /// (e.g. `["remove", "42"]`): either all of them succeed or the calendar is not modified
+    #[serde(default)]
+    commands: Vec<Vec<String>>,
+    /// Version of the calendar the commands were meant for (its etag in a previous response):
+    /// required with commands, that are refused if the calendar has changed since
+    #[serde(default)]
+    if_match: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Response {
+    /// The events listed by the filters, and the version of the calendar they were listed from
+    Output {
+        output: String,
+        etag: String,
+    },
+    /// The calendar was modified after the version given with the commands: the current
+    /// version is returned, and the commands are not executed
+    Conflict {
+        etag: String,
+    },
+    Error(String),
+}
+
+/// Returns the version of the calendar, that changes whenever its contents do
+pub(crate) fn etag(cal: &Calendar) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(cal.get_id());
+    hasher.update(cal.get_name());
+    hasher.update(cal.get_owner());
+    hasher.update(serde_json::to_vec(cal.get_availability()).unwrap_or_default());
+    // in a fixed order, unlike the map of the events
+    for (eid, ev) in cal.events_by_eid() {
+        hasher.update(eid.to_be_bytes());
+        hasher.update(serde_json::to_vec(ev).unwrap_or_default());
+    }
+    users::hex(&hasher.finalize()[..16])
+}
+
+/// A calendar kept in memory, along with the modification times of its files when it was read
+pub(crate) struct CachedCalendar {
+    pub(crate) cal: Calendar,
+    /// version of the calendar as stored, even if redacted in memory
+    pub(crate) etag: String,
+    path: PathBuf,
+    stamp: (Option<SystemTime>, Option<SystemTime>),
+}
+
+/// Returns the modification times of the calendar file at `path` and of its change log
+fn file_stamp(path: &Path) -> (Option<SystemTime>, Option<SystemTime>) {
+    let mtime = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    (mtime(path), mtime(&path.with_extension("log")))
+}
+
+/// Returns the path of the socket the daemon listens on
+pub fn socket_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(".daemon.sock")
+}
+
+/// Outcome of the commands executed on a calendar by [`Cache::execute`]
+pub(crate) enum Executed {
+    /// The calendar is not at the version the commands were meant for, but at this one
+    Conflict(String),
+    /// The commands modified the calendar this way
+    Done(#[cfg_attr(not(feature = "grpc"), allow(dead_code))] CalendarDiff),
+}
+
+/// Keeps the calendars in memory, reloading them when their files change
+pub(crate) struct Cache {
+    data_dir: PathBuf,
+    calendars: HashMap<String, CachedCalendar>,
+    /// whether the details of the events that are not public are hidden
+    redact: bool,
+}
+
+impl Cache {
+    pub(crate) fn new(data_dir: &Path, redact: bool) -> Cache {
+        Cache {
+            data_dir: data_dir.to_path_buf(),
+            calendars: HashMap::new(),
+            redact,
+        }
+    }
+
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub(crate) fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    pub(crate) fn get(&mut self, key: &str) -> Result<&CachedCalendar, CalendarError> {
+        let stale = match self.calendars.get(key) {
+            Some(cached) => file_stamp(&cached.path) != cached.stamp,
+            None => true,
+        };
+        if stale {
+            debug!("Loading calendar {}", key);
+            let mut cal = storage::resolve_calendar(key, &self.data_dir)?;
+            let path = storage::calendar_path(cal.get_id(), &self.data_dir)?;
+            let stamp = file_stamp(&path);
+            let etag = etag(&cal);
+            if self.redact {
+                cal = cal.redacted();
+            }
+            self.calendars.insert(
+                key.to_string(),
+                CachedCalendar {
+                    cal,
+                    etag,
+                    path,
+                    stamp,
+                },
+            );
+        }
+        match self.calendars.get(key) {
+            Some(cached) => Ok(cached),
+            None => Err(CalendarError::CalendarNotFound(key.to_string())),
+        }
+    }
+
+    /// Checks that the user the token belongs to has the access needed to the calendar,
+    /// returning their name. Without users, anyone can read the calendars but no one can
+    /// modify them
+    pub(crate) fn authorize(
+        &mut self,
+        key: &str,
+        token: Option<&str>,
+        needed: Access,
+    ) -> Result<Option<String>, CalendarError> {
+        let users = Users::load(&self.data_dir)?;
+        if users.is_empty() && needed == Access::Read {
+            return Ok(None);
+        }
+        let denied = || CalendarError::Unknown(format!("Access denied to calendar {}", key));
+        let user = token
+            .and_then(|t| users.authenticate(t))
+            .ok_or_else(denied)?;
+        let id = self
+            .get(key)
+            .map_err(|_| denied())?
+            .cal
+            .get_id()
+            .to_string();
+        match user.access(&id) {
+            Some(access) if access >= needed => Ok(Some(user.name.clone())),
+            _ => Err(denied()),
+        }
+    }
+
+    /// Executes the commands on the calendar on behalf of the user, saving it only if all
+    /// of them succeed, unless the calendar is not at the version `if_match`
+    pub(crate) fn execute(
+        &mut self,
+        key: &str,
+        user: &str,
+        commands: &[Vec<String>],
+        if_match: &str,
+    ) -> Result<Executed, CalendarError> {
+        let mut cal = storage::resolve_calendar(key, &self.data_dir)?;
+        let current = etag(&cal);
+        if current != if_match {
+            return Ok(Executed::Conflict(current));
+        }
+        let before = cal.clone();
+        for words in commands {
+            let cmd =
+                cli::parse_command(words).map_err(|e| CalendarError::Unknown(e.to_string()))?;
+            if !cli::runs_in_daemon(&cmd) {
+                return Err(CalendarError::Unknown(format!(
+                    "{} cannot be executed by the daemon",
+                    words.join(" ")
+                )));
+            }
+            if !cli::exec_subcommand(&mut cal, cmd, false, &self.data_dir) {
+                return Err(CalendarError::Unknown(format!(
+                    "{} failed: calendar not modified",
+                    words.join(" ")
+                )));
+            }
+        }
+        if !storage::save_audited(&before, &cal, user, &self.data_dir) {
+            return Err(CalendarError::Unknown(format!(
+                "Cannot write calendar {}",
+                cal.get_id()
+            )));
+        }
+        info!(
+            "Executed {} commands on calendar {}",
+            commands.len(),
+            cal.get_id()
+        );
+        // reloaded (and redacted) on the next request
+        self.calendars.remove(key);
+        Ok(Executed::Done(before.diff(&cal)))
+    }
+
+    fn answer(&mut self, req: Request) -> Result<Response, CalendarError> {
+        let needed = if req.commands.is_empty() {
+            Access::Read
+        } else {
+            Access::Write
+        };
+        let user = self.authorize(&req.calendar, req.token.as_deref(), needed)?;
+        if !req.commands.is_empty() {
+            let if_match = req.if_match.as_deref().ok_or_else(|| {
+                CalendarError::Unknown(String::from(
+                    "Commands need if_match, the etag of the calendar they are meant for",
+                ))
+            })?;
+            // writing always needs an authenticated user
+            let user = user.unwrap_or_default();
+            if let Executed::Conflict(etag) =
+                self.execute(&req.calendar, &user, &req.commands, if_match)?
+            {
+                return Ok(Response::Conflict { etag });
+            }
+        }
+        let cached = self.get(&req.calendar)?;
+        Ok(Response::Output {
+            output: req
+                .filters
+                .into_iter()
+                .map(|f| cli::render_list(&cached.cal, f))
+                .collect(),
+            etag: cached.etag.clone(),
+        })
+    }
+}
+
+/// Reads the request sent on the stream, waiting for it at most [`REQUEST_TIMEOUT`] and
+/// reading at most [`MAX_REQUEST`] bytes
+fn read_request(stream: &UnixStream) -> Result<Request, String> {
+    stream
+        .set_read_timeout(Some(REQUEST_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST));
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .map_err(|e| format!("Bad request: {}", e))?;
+    if !line.ends_with('\n') && line.len() as u64 >= MAX_REQUEST {
+        return Err(format!("Bad request: longer than {} bytes", MAX_REQUEST));
+    }
+    serde_json::from_str(&line).map_err(|e| format!("Bad request: {}", e))
+}
+
+/// Answers the request sent on the stream. The request is read before locking the cache,
+/// so that a slow client does not make the others wait
+fn handle(stream: UnixStream, cache: &Mutex<Cache>) -> Result<(), CalendarError> {
+    let response = match read_request(&stream) {
+        Ok(req) => {
+            let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+            cache
+                .answer(req)
+                .unwrap_or_else(|e| Response::Error(format!("{:?}", e)))
+        }
+        Err(e) => Response::Error(e),
+    };
+    let mut out =
+        serde_json::to_string(&response).map_err(|e| CalendarError::Unknown(e.to_string()))?;
+    out.push('\n');
+    (&stream).write_all(out.as_bytes())?;
+    Ok(())
+}
+
+/// Runs the daemon, serving requests on the socket inside the data directory until killed.
+/// If there are users (see [`Users`]), each request needs the token of a user with the
+/// access to the calendar it reads or modifies. If configured, the gRPC interface is served
+/// as well. The configured webhooks are called when events start, and the state of the calendars is
+/// published to the configured MQTT broker. If `redact`, the answers hide the details of
+/// the events that are not public
+pub fn serve(data_dir: &Path, redact: bool) -> Result<(), CalendarError> {
+    let sock = socket_path(data_dir);
+    if sock.exists() {
+        if UnixStream::connect(&sock).is_ok() {
+            return Err(CalendarError::Unknown(format!(
+                "A daemon is already listening on {}",
+                sock.display()
+            )));
+        }
+        // left behind by a daemon that did not terminate cleanly
+        fs::remove_file(&sock)?;
+    }
+    let listener = UnixListener::bind(&sock)?;
+    info!("Daemon listening on {}", sock.display());
+    let config = config::load(data_dir)?;
+    let cache = Arc::new(Mutex::new(Cache::new(data_dir, redact)));
+    if let Some(grpc) = &config.grpc {
+        serve_grpc(&grpc.listen, cache.clone())?;
+    }
+    if !config.webhooks.is_empty() || config.mqtt.is_some() {
+        info!("Watching the calendars for events starting");
+        notify::spawn(data_dir, config);
+    }
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle(stream, &cache) {
+                    report::warning(format!("Failed to serve a request: {:?}", e));
+                }
+            }
+            Err(e) => report::warning(format!("Failed to accept a connection: {}", e)),
+        }
+    }
+    Ok(())
+}
+
+/// Serves the gRPC interface (see [`crate::grpc`]) on the address in the background
+#[cfg(feature = "grpc")]
+fn serve_grpc(listen: &str, cache: Arc<Mutex<Cache>>) -> Result<(), CalendarError> {
+    let listener = TcpListener::bind(listen)
+        .map_err(|e| CalendarError::InvalidConfig(format!("grpc.listen {}: {}", listen, e)))?;
+    thread::spawn(move || {
+        if let Err(e) = grpc::serve(listener, cache) {
+            report::warning(format!("{:?}", e));
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(feature = "grpc"))]
+fn serve_grpc(_listen: &str, _cache: Arc<Mutex<Cache>>) -> Result<(), CalendarError> {
+    report::warning("gRPC is not supported: rebuild with the `grpc` feature");
+    Ok(())
+}
+
+/// Asks the daemon (if running) to list the events of the calendar with the given filters.
+/// Returns None if the daemon cannot be reached, so that the caller can read the files
+pub fn query(
+    data_dir: &Path,
+    calendar: &str,
+    token: Option<String>,
+    filters: Vec<Filter>,
+) -> Option<Result<String, CalendarError>> {
+    let stream = UnixStream::connect(socket_path(data_dir)).ok()?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT)).ok()?;
+    let req = Request {
+        calendar: calendar.to_string(),
+        token,
+        filters,
+        commands: Vec::new(),
+        if_match: None,
+    };
+    let mut line = serde_json::to_string(&req).ok()?;
+    line.push('\n');
+    (&stream).write_all(line.as_bytes()).ok()?;
+    let mut resp = String::new();
+    BufReader::new(&stream).read_line(&mut resp).ok()?;
+    match serde_json::from_str(&resp).ok()? {
+        Response::Output { output, .. } => Some(Ok(output)),
+        Response::Conflict { etag } => Some(Err(CalendarError::Unknown(format!(
+            "Calendar {} modified concurrently (now at version {})",
+            calendar, etag
+        )))),
+        Response::Error(e) => Some(Err(CalendarError::Unknown(e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+    use std::path::{Path, PathBuf};
+    use std::thread;
+
+    use crate::calendar::Calendar;
+    use crate::daemon::{etag, read_request, Cache, Executed, MAX_REQUEST};
+    use crate::event::Event;
+    use crate::storage;
+    use crate::testing::TempDir;
+
+    /// Returns a new data directory with the calendar "work", holding one event
+    fn data_dir(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("daemon-{}", name));
+        let mut cal = Calendar::new("owner", "work");
+        cal.add_event(Event::new(
+            "review",
+            "",
+            "13/07/2022",
+            "09:30",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        assert!(storage::save_calendar(&cal, &dir));
+        dir
+    }
+
+    /// Returns whether the daemon refuses to execute the command on the calendar "work",
+    /// checking that it is left unchanged
+    fn refused(dir: &Path, words: &[&str]) -> bool {
+        let before = storage::resolve_calendar("work", dir).unwrap();
+        let words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+        let result = Cache::new(dir, false).execute("work", "alice", &[words], &etag(&before));
+        assert_eq!(storage::resolve_calendar("work", dir).unwrap(), before);
+        result.is_err()
+    }
+
+    #[test]
+    /// tests that the version of a calendar changes with its contents only
+    fn test_etag() {
+        let mut cal = Calendar::new("owner", "work");
+        let empty = etag(&cal);
+        let ev = Event::new("review", "", "13/07/2022", "09:30", 1.0, None, None, None);
+        cal.add_event(ev.clone());
+        let one = etag(&cal);
+        assert_ne!(one, empty);
+        assert_eq!(etag(&cal.clone()), one);
+        cal.get_event(cal.events_by_eid()[0].0)
+            .unwrap()
+            .set_title("retro");
+        assert_ne!(etag(&cal), one);
+        cal.clear();
+        assert_eq!(etag(&cal), empty);
+    }
+
+    #[test]
+    /// tests that the daemon executes the commands changing the calendar, but not the ones
+    /// reading or writing the files of the user running it
+    fn test_execute() {
+        let dir = data_dir("execute");
+        let cal = storage::resolve_calendar("work", &dir).unwrap();
+        let words = vec![["add", "retro", "", "14/07/2022", "16:00", "1"]
+            .map(String::from)
+            .to_vec()];
+        let done = Cache::new(&dir, false).execute("work", "alice", &words, &etag(&cal));
+        assert!(matches!(done, Ok(Executed::Done(diff)) if diff.added.len() == 1));
+
+        let out = dir.join("x.ics");
+        let out = out.to_str().unwrap();
+        assert!(refused(&dir, &["export", "--out", out]));
+        assert!(refused(&dir, &["freebusy", "--output", out]));
+        assert!(refused(&dir, &["add", "--from-file", out]));
+        assert!(refused(&dir, &["edit", "1", "--editor"]));
+        assert!(refused(&dir, &["list"]));
+        assert!(!PathBuf::from(out).exists());
+    }
+
+    #[test]
+    /// tests that the daemon does not export the calendar, with its time zones, to a file
+    fn test_export_refused() {
+        let dir = data_dir("export");
+        let out = dir.join("work.ics");
+        let out = out.to_str().unwrap();
+        assert!(refused(&dir, &["export", "--out", out]));
+        assert!(refused(&dir, &["export", "--format", "ics"]));
+        assert!(!PathBuf::from(out).exists());
+    }
+
+    #[test]
+    /// tests that the daemon does not publish the appointment slots to a file, nor books them from one
+    fn test_slots_refused() {
+        let dir = data_dir("slots");
+        let out = dir.join("slots.html");
+        let out = out.to_str().unwrap();
+        assert!(refused(&dir, &["slots", "publish", "--output", out]));
+        assert!(!PathBuf::from(out).exists());
+        let invites = dir.join("invites.ics");
+        fs::write(&invites, "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n").unwrap();
+        assert!(refused(
+            &dir,
+            &["slots", "accept", invites.to_str().unwrap()]
+        ));
+    }
+
+    #[test]
+    /// tests that the daemon does not write a shared event to a file
+    fn test_share_refused() {
+        let dir = data_dir("share");
+        let out = dir.join("review.ics");
+        let out = out.to_str().unwrap();
+        assert!(refused(&dir, &["share", "1", "--out", out]));
+        assert!(!PathBuf::from(out).exists());
+    }
+
+    #[test]
+    /// tests that the daemon does not import, nor diff, the events of a file
+    fn test_import_refused() {
+        let dir = data_dir("import");
+        let file = dir.join("month.ics");
+        let ics = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//test//EN\r\nBEGIN:VEVENT\r\n\
+            UID:retro@team\r\nDTSTAMP:20220701T000000Z\r\nDTSTART:20220714T160000\r\n\
+            DTEND:20220714T170000\r\nSUMMARY:retro\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        fs::write(&file, ics).unwrap();
+        let file = file.to_str().unwrap();
+        assert!(refused(&dir, &["import", file]));
+        assert!(refused(&dir, &["import", file, "--diff"]));
+    }
+
+    #[test]
+    /// tests that the daemon does not read files to validate them
+    fn test_ics_validate_refused() {
+        let dir = data_dir("validate");
+        let file = dir.join("work.json");
+        assert!(file.exists());
+        assert!(refused(&dir, &["ics", "validate", file.to_str().unwrap()]));
+    }
+
+    #[test]
+    /// tests that the requests are read with a time and size limit
+    fn test_read_request() {
+        let (client, server) = UnixStream::pair().unwrap();
+        // a client sending nothing is given up on
+        let e = read_request(&server).err().unwrap();
+        assert!(e.starts_with("Bad request"), "{}", e);
+        drop((client, server));
+
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let writer = thread::spawn(move || {
+            let _ = client.write_all(&vec![b'x'; MAX_REQUEST as usize + 1]);
+        });
+        let e = read_request(&server).err().unwrap();
+        assert!(e.contains("longer than"), "{}", e);
+        drop(server);
+        writer.join().unwrap();
+
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client.write_all(b"{\"calendar\": \"work\"}\n").unwrap();
+        assert_eq!(read_request(&server).unwrap().calendar, "work");
+    }
+}
@@ -0,0 +1,319 @@
+use std::fmt::Write;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use clap::ValueEnum;
+
+use crate::availability::escape_html;
+use crate::calendar::Calendar;
+use crate::calendar_error::CalendarError;
+#[cfg(feature = "email")]
+use crate::config::SmtpConfig;
+use crate::event::format_lead_time;
+use crate::notify;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DigestFormat {
+    /// Plain text
+    Text,
+    /// An HTML document, for emails
+    Html,
+}
+
+/// Returns the days covered by the digest, given the current date: tomorrow,
+/// or the rest of the week (today included) if `week` is set
+pub fn period(today: NaiveDate, week: bool) -> (NaiveDate, NaiveDate) {
+    if week {
+        let days_left = 6 - today.weekday().num_days_from_monday();
+        (today, today + Duration::days(days_left as i64))
+    } else {
+        let tomorrow = today + Duration::days(1);
+        (tomorrow, tomorrow)
+    }
+}
+
+/// Returns the subject of the digest of the calendar for the given days
+pub fn subject(cal: &Calendar, first: NaiveDate, last: NaiveDate) -> String {
+    if first == last {
+        format!(
+            "{}: agenda for {}",
+            cal.get_name(),
+            first.format("%A %d/%m/%Y")
+        )
+    } else {
+        format!(
+            "{}: agenda from {} to {}",
+            cal.get_name(),
+            first.format("%A %d/%m/%Y"),
+            last.format("%A %d/%m/%Y")
+        )
+    }
+}
+
+/// Renders the agenda of the calendar for the days from `first` to `last` (included),
+/// grouped by day, followed by the reminders due in those days if `reminders`
+pub fn render(
+    cal: &Calendar,
+    first: NaiveDate,
+    last: NaiveDate,
+    format: DigestFormat,
+    reminders: bool,
+) -> String {
+    let from = first.and_time(NaiveTime::MIN);
+    let until = (last + Duration::days(1)).and_time(NaiveTime::MIN) - Duration::seconds(1);
+    let events = cal.list_events_between(Some(from), Some(until));
+    let title = subject(cal, first, last);
+
+    let mut out = String::new();
+    match format {
+        DigestFormat::Text => {
+            let _ = writeln!(out, "{}", title);
+        }
+        DigestFormat::Html => {
+            let _ = write!(
+                out,
+                "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{0}</title></head>\n\
+                <body>\n<h1>{0}</h1>\n",
+                escape_html(&title)
+            );
+        }
+    }
+    if events.is_empty() {
+        match format {
+            DigestFormat::Text => out.push_str("\nNo events\n"),
+            DigestFormat::Html => out.push_str("<p>No events</p>\n"),
+        }
+    }
+    let mut day: Option<NaiveDate> = None;
+    for ev in events.iter() {
+        let start = ev.get_start();
+        if day != Some(start.date()) {
+            let heading = start.format("%A %d/%m/%Y");
+            match format {
+                DigestFormat::Text => {
+                    let _ = writeln!(out, "\n{}", heading);
+                }
+                DigestFormat::Html => {
+                    if day.is_some() {
+                        out.push_str("</ul>\n");
+                    }
+                    let _ = writeln!(out, "<h2>{}</h2>\n<ul>", heading);
+                }
+            }
+            day = Some(start.date());
+        }
+        let end: NaiveDateTime = start + Duration::seconds(ev.get_duration());
+        let hours = format!("{}-{}", start.format("%H:%M"), end.format("%H:%M"));
+        let location = ev.get_location();
+        match format {
+            DigestFormat::Text => {
+                let _ = write!(out, "  {}  {}", hours, ev.get_title());
+                if !location.is_empty() {
+                    let _ = write!(out, " @ {}", location);
+                }
+                out.push('\n');
+            }
+            DigestFormat::Html => {
+                let _ = write!(out, "<li>{} <b>{}</b>", hours, escape_html(ev.get_title()));
+                if !location.is_empty() {
+                    let _ = write!(out, " @ {}", escape_html(location));
+                }
+                out.push_str("</li>\n");
+            }
+        }
+    }
+    if format == DigestFormat::Html && day.is_some() {
+        out.push_str("</ul>\n");
+    }
+    if reminders {
+        render_reminders(&mut out, cal, from, until, format);
+    }
+    if format == DigestFormat::Html {
+        out.push_str("</body>\n</html>\n");
+    }
+    out
+}
+
+/// Renders the reminders due from `from` to `until` (included)
+fn render_reminders(
+    out: &mut String,
+    cal: &Calendar,
+    from: NaiveDateTime,
+    until: NaiveDateTime,
+    format: DigestFormat,
+) {
+    let reminders = notify::reminders_between(cal, from - Duration::seconds(1), until);
+    match format {
+        DigestFormat::Text => out.push_str("\nReminders\n"),
+        DigestFormat::Html => out.push_str("<h2>Reminders</h2>\n<ul>\n"),
+    }
+    if reminders.is_empty() {
+        match format {
+            DigestFormat::Text => out.push_str("  No reminders\n"),
+            DigestFormat::Html => out.push_str("<li>No reminders</li>\n"),
+        }
+    }
+    for r in reminders.iter() {
+        let due = r.due.format("%a %d/%m %H:%M");
+        let start = r.event.get_start().format("%d/%m/%Y %H:%M");
+        let lead = format_lead_time(r.lead);
+        match format {
+            DigestFormat::Text => {
+                let _ = writeln!(
+                    out,
+                    "  {}  {} in {} ({})",
+                    due,
+                    r.event.get_title(),
+                    lead,
+                    start
+                );
+            }
+            DigestFormat::Html => {
+                let _ = writeln!(
+                    out,
+                    "<li>{} <b>{}</b> in {} ({})</li>",
+                    due,
+                    escape_html(r.event.get_title()),
+                    lead,
+                    start
+                );
+            }
+        }
+    }
+    if format == DigestFormat::Html {
+        out.push_str("</ul>\n");
+    }
+}
+
+/// Sends the digest by email to `to`, through the configured SMTP server
+#[cfg(feature = "email")]
+pub fn send(
+    smtp: &SmtpConfig,
+    to: &str,
+    subject: &str,
+    body: String,
+    format: DigestFormat,
+) -> Result<(), CalendarError> {
+    use lettre::message::header::ContentType;
+    use lettre::transport::smtp::authentication::Credentials;
+    use lettre::{Message, SmtpTransport, Transport};
+
+    let err = |e: &dyn std::fmt::Display| CalendarError::Unknown(format!("Cannot send email: {e}"));
+    let content_type = match format {
+        DigestFormat::Text => ContentType::TEXT_PLAIN,
+        DigestFormat::Html => ContentType::TEXT_HTML,
+    };
+    let email = Message::builder()
+        .from(smtp.from.parse().map_err(|e| err(&e))?)
+        .to(to.parse().map_err(|e| err(&e))?)
+        .subject(subject)
+        .header(content_type)
+        .body(body)
+        .map_err(|e| err(&e))?;
+    let mut transport = SmtpTransport::starttls_relay(&smtp.host).map_err(|e| err(&e))?;
+    if let Some(port) = smtp.port {
+        transport = transport.port(port);
+    }
+    if let (Some(user), Some(pass)) = (&smtp.username, &smtp.password) {
+        transport = transport.credentials(Credentials::new(user.clone(), pass.clone()));
+    }
+    transport.build().send(&email).map_err(|e| err(&e))?;
+    Ok(())
+}
+
+/// Without the `email` feature, digests can only be printed
+#[cfg(not(feature = "email"))]
+pub fn send(
+    _smtp: &crate::config::SmtpConfig,
+    _to: &str,
+    _subject: &str,
+    _body: String,
+    _format: DigestFormat,
+) -> Result<(), CalendarError> {
+    Err(CalendarError::Unknown(
+        "Emails are not supported: rebuild with the `email` feature".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+
+    use crate::calendar::Calendar;
+    use crate::digest::{period, render, DigestFormat};
+    use crate::event::Event;
+
+    #[test]
+    /// tests the days covered by the digests
+    fn test_period() {
+        // a wednesday
+        let today = NaiveDate::from_ymd_opt(2022, 7, 13).unwrap();
+        let tomorrow = NaiveDate::from_ymd_opt(2022, 7, 14).unwrap();
+        let sunday = NaiveDate::from_ymd_opt(2022, 7, 17).unwrap();
+        assert_eq!(period(today, false), (tomorrow, tomorrow));
+        assert_eq!(period(today, true), (today, sunday));
+        assert_eq!(period(sunday, true), (sunday, sunday));
+    }
+
+    #[test]
+    /// tests rendering the agenda as text and HTML
+    fn test_render() {
+        let mut cal = Calendar::new("owner", "work");
+        let ev = |title, date, time| {
+            Event::new(title, "", date, time, 1.0, Some("R&D <lab>"), None, None)
+        };
+        cal.add_event(ev("standup", "14/07/2022", "09:00"));
+        cal.add_event(ev("review", "14/07/2022", "15:00"));
+        cal.add_event(ev("retro", "15/07/2022", "11:00"));
+        cal.add_event(ev("elsewhen", "20/07/2022", "11:00"));
+        let first = NaiveDate::from_ymd_opt(2022, 7, 14).unwrap();
+        let last = NaiveDate::from_ymd_opt(2022, 7, 15).unwrap();
+
+        let text = render(&cal, first, first, DigestFormat::Text, false);
+        assert_eq!(
+            text,
+            "work: agenda for Thursday 14/07/2022\n\n\
+            Thursday 14/07/2022\n  \
+            09:00-10:00  standup @ R&D <lab>\n  \
+            15:00-16:00  review @ R&D <lab>\n"
+        );
+
+        let html = render(&cal, first, last, DigestFormat::Html, false);
+        assert_eq!(html.matches("<li>").count(), 3);
+        assert_eq!(html.matches("<h2>").count(), 2);
+        assert!(html.contains("R&amp;D &lt;lab&gt;"));
+        assert!(!html.contains("elsewhen"));
+
+        let empty = NaiveDate::from_ymd_opt(2022, 7, 16).unwrap();
+        assert!(render(&cal, empty, empty, DigestFormat::Text, false).ends_with("No events\n"));
+    }
+
+    #[test]
+    /// tests listing the reminders due in the days of the agenda
+    fn test_render_reminders() {
+        let mut cal = Calendar::new("owner", "work");
+        let mut ev = |title, date, leads: Vec<Duration>| {
+            let mut ev = Event::new(title, "", date, "11:00", 1.0, None, None, None);
+            ev.set_reminders(leads);
+            cal.add_event(ev);
+        };
+        ev(
+            "release",
+            "21/07/2022",
+            vec![Duration::days(1), Duration::weeks(1)],
+        );
+        ev("retro", "15/07/2022", vec![Duration::hours(25)]);
+        ev("standup", "14/07/2022", Vec::new());
+        let day = NaiveDate::from_ymd_opt(2022, 7, 14).unwrap();
+
+        let text = render(&cal, day, day, DigestFormat::Text, true);
+        assert!(text.ends_with(
+            "\nReminders\n  \
+            Thu 14/07 10:00  retro in 25 hours (15/07/2022 11:00)\n  \
+            Thu 14/07 11:00  release in 1 week (21/07/2022 11:00)\n"
+        ));
+        let html = render(&cal, day, day, DigestFormat::Html, true);
+        assert_eq!(html.matches("<li>").count(), 3);
+        let next = NaiveDate::from_ymd_opt(2022, 7, 16).unwrap();
+        assert!(render(&cal, next, next, DigestFormat::Text, true).ends_with("No reminders\n"));
+    }
+}
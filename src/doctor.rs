@@ -0,0 +1,434 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::blobs::{self, BLOBS_DIR};
+use crate::calendar::Calendar;
+use crate::calendar_error::CalendarError;
+use crate::event::HolidayRule;
+use crate::snapshot::SNAPSHOTS_DIR;
+use crate::storage;
+use crate::users::hex;
+
+/// A problem found in the data directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// A file that looks like a calendar but cannot be read
+    Unreadable { path: PathBuf, error: String },
+    /// Several files hold a calendar with the same id
+    DuplicateCalendar { id: String, paths: Vec<PathBuf> },
+    /// The event is equal to another one of the calendar, with a lower eid
+    DuplicateEvent { calendar: String, eid: u64, of: u64 },
+    /// The event ends before it starts
+    NegativeDuration { calendar: String, eid: u64 },
+    /// The event is related to an event that is not in the calendar
+    DanglingRelation {
+        calendar: String,
+        eid: u64,
+        related: u64,
+    },
+    /// The holidays of the recurrence of the event are in a calendar that does not exist
+    MissingHolidays {
+        calendar: String,
+        eid: u64,
+        holidays: String,
+    },
+    /// The calendar file and its change log take more than the maximum size
+    Oversized { path: PathBuf, size: u64 },
+    /// Contents of an attachment no event (or snapshot) refers to
+    OrphanBlob { hash: String },
+    /// Contents of an attachment that do not match their hash
+    CorruptBlob { hash: String },
+}
+
+impl Issue {
+    /// Whether the issue can be repaired by [`fix`]
+    pub fn is_fixable(&self) -> bool {
+        matches!(
+            self,
+            Issue::DuplicateEvent { .. }
+                | Issue::NegativeDuration { .. }
+                | Issue::DanglingRelation { .. }
+                | Issue::MissingHolidays { .. }
+                | Issue::OrphanBlob { .. }
+        )
+    }
+
+    /// Returns the id of the calendar to modify to repair the issue, if any
+    fn calendar(&self) -> Option<&str> {
+        match self {
+            Issue::DuplicateEvent { calendar, .. }
+            | Issue::NegativeDuration { calendar, .. }
+            | Issue::DanglingRelation { calendar, .. }
+            | Issue::MissingHolidays { calendar, .. } => Some(calendar),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::Unreadable { path, error } => {
+                write!(f, "{}: cannot be read ({})", path.display(), error)
+            }
+            Issue::DuplicateCalendar { id, paths } => {
+                let paths: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+                write!(
+                    f,
+                    "calendar {} is in several files: {}",
+                    id,
+                    paths.join(", ")
+                )
+            }
+            Issue::DuplicateEvent { calendar, eid, of } => {
+                write!(f, "{}: event {} is a copy of event {}", calendar, eid, of)
+            }
+            Issue::NegativeDuration { calendar, eid } => {
+                write!(f, "{}: event {} ends before it starts", calendar, eid)
+            }
+            Issue::DanglingRelation {
+                calendar,
+                eid,
+                related,
+            } => write!(
+                f,
+                "{}: event {} is related to the missing event {}",
+                calendar, eid, related
+            ),
+            Issue::MissingHolidays {
+                calendar,
+                eid,
+                holidays,
+            } => write!(
+                f,
+                "{}: the holidays of event {} are in the missing calendar {}",
+                calendar, eid, holidays
+            ),
+            Issue::Oversized { path, size } => {
+                write!(f, "{}: {} bytes", path.display(), size)
+            }
+            Issue::OrphanBlob { hash } => write!(f, "attachment {} is not used", hash),
+            Issue::CorruptBlob { hash } => {
+                write!(f, "attachment {} does not match its hash", hash)
+            }
+        }
+    }
+}
+
+/// Returns the hashes of the attachments of the events of the calendar
+fn attachments(cal: &Calendar) -> impl Iterator<Item = String> + '_ {
+    cal.events_by_eid()
+        .into_iter()
+        .flat_map(|(_, ev)| ev.get_attachments().iter().map(|a| a.hash.clone()))
+}
+
+/// Returns the hashes of the attachments referred to by the snapshots of the calendars
+fn snapshot_attachments(data_dir: &Path) -> BTreeSet<String> {
+    let mut hashes = BTreeSet::new();
+    let dirs = fs::read_dir(data_dir.join(SNAPSHOTS_DIR))
+        .into_iter()
+        .flatten();
+    for dir in dirs.flatten() {
+        for object in fs::read_dir(dir.path()).into_iter().flatten().flatten() {
+            let cal = fs::read_to_string(object.path())
+                .ok()
+                .and_then(|contents| serde_json::from_str::<Calendar>(&contents).ok());
+            if let Some(cal) = cal {
+                hashes.extend(attachments(&cal));
+            }
+        }
+    }
+    hashes
+}
+
+/// Looks for the issues of the events of the calendar
+fn check_calendar(cal: &Calendar, ids: &BTreeSet<String>, issues: &mut Vec<Issue>) {
+    let id = cal.get_id();
+    let events = cal.events_by_eid();
+    let eids: HashSet<u64> = events.iter().map(|(eid, _)| *eid).collect();
+    for (i, (eid, ev)) in events.iter().enumerate() {
+        if let Some((of, _)) = events[..i].iter().find(|(_, other)| *other == *ev) {
+            issues.push(Issue::DuplicateEvent {
+                calendar: id.to_string(),
+                eid: *eid,
+                of: *of,
+            });
+        }
+        if ev.get_duration() < 0 {
+            issues.push(Issue::NegativeDuration {
+                calendar: id.to_string(),
+                eid: *eid,
+            });
+        }
+        for related in ev.get_related_to() {
+            if !eids.contains(related) {
+                issues.push(Issue::DanglingRelation {
+                    calendar: id.to_string(),
+                    eid: *eid,
+                    related: *related,
+                });
+            }
+        }
+        let holidays = ev
+            .get_recurrence()
+            .and_then(|rec| rec.holidays()?.calendar.clone());
+        if let Some(holidays) = holidays.filter(|h| !ids.contains(h)) {
+            issues.push(Issue::MissingHolidays {
+                calendar: id.to_string(),
+                eid: *eid,
+                holidays,
+            });
+        }
+    }
+}
+
+/// Scans the data directory, returning the issues found. Calendar files (along with their
+/// change log) larger than `max_size` bytes are reported
+pub fn scan(data_dir: &Path, max_size: u64) -> Result<Vec<Issue>, CalendarError> {
+    let mut issues = Vec::new();
+    let mut calendars: BTreeMap<String, Vec<(Calendar, PathBuf)>> = BTreeMap::new();
+    let mut known = storage::known_calendars(data_dir)?;
+    known.sort_by(|a, b| a.1.cmp(&b.1));
+    for (cal, path) in known {
+        match cal {
+            Ok(cal) => {
+                let log = path.with_extension("log");
+                let size = [&path, &log]
+                    .iter()
+                    .filter_map(|p| fs::metadata(p).ok())
+                    .map(|m| m.len())
+                    .sum();
+                if size > max_size {
+                    issues.push(Issue::Oversized {
+                        path: path.clone(),
+                        size,
+                    });
+                }
+                calendars
+                    .entry(cal.get_id().to_string())
+                    .or_default()
+                    .push((cal, path));
+            }
+            Err(e) => issues.push(Issue::Unreadable {
+                path,
+                error: format!("{:?}", e),
+            }),
+        }
+    }
+
+    let ids: BTreeSet<String> = calendars.keys().cloned().collect();
+    let mut used = snapshot_attachments(data_dir);
+    for (id, copies) in calendars.iter() {
+        if copies.len() > 1 {
+            issues.push(Issue::DuplicateCalendar {
+                id: id.clone(),
+                paths: copies.iter().map(|(_, path)| path.clone()).collect(),
+            });
+        }
+        for (cal, _) in copies {
+            check_calendar(cal, &ids, &mut issues);
+            used.extend(attachments(cal));
+        }
+    }
+
+    let mut blobs: Vec<PathBuf> = fs::read_dir(data_dir.join(BLOBS_DIR))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .collect();
+    blobs.sort();
+    for path in blobs {
+        let hash = path.file_name().and_then(|name| name.to_str());
+        let (hash, data) = match hash.map(|hash| (hash, blobs::load(hash, data_dir))) {
+            Some((hash, Ok(data))) => (hash.to_string(), data),
+            // e.g. left behind by an interrupted write
+            _ => continue,
+        };
+        if !used.contains(&hash) {
+            issues.push(Issue::OrphanBlob { hash });
+        } else if hex(&Sha256::digest(data)) != hash {
+            issues.push(Issue::CorruptBlob { hash });
+        }
+    }
+    Ok(issues)
+}
+
+/// Repairs the fixable issues on behalf of `user`, returning those repaired:
+/// - the copies of the events are removed
+/// - the events ending before they start last no time instead
+/// - the relations to the missing events are removed
+/// - the holidays in the missing calendars are no longer considered
+/// - the unused attachments are deleted
+pub fn fix(issues: &[Issue], user: &str, data_dir: &Path) -> Result<Vec<Issue>, CalendarError> {
+    let mut fixed = Vec::new();
+    let mut by_calendar: BTreeMap<&str, Vec<&Issue>> = BTreeMap::new();
+    for issue in issues.iter().filter(|i| i.is_fixable()) {
+        match (issue.calendar(), issue) {
+            (Some(id), _) => by_calendar.entry(id).or_default().push(issue),
+            (None, Issue::OrphanBlob { hash }) => {
+                let path = data_dir.join(BLOBS_DIR).join(hash);
+                fs::remove_file(&path)
+                    .map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))?;
+                fixed.push(issue.clone());
+            }
+            _ => (),
+        }
+    }
+    for (id, issues) in by_calendar {
+        let before = storage::read_calendar(&storage::calendar_path(id, data_dir)?)?;
+        let mut cal = before.clone();
+        for issue in issues.iter() {
+            let repaired = match issue {
+                Issue::DuplicateEvent { eid, .. } => cal.remove_event(*eid).is_ok(),
+                Issue::NegativeDuration { eid, .. } => cal
+                    .update_event(*eid, |ev| ev.set_duration(&chrono::Duration::zero()))
+                    .is_ok(),
+                Issue::DanglingRelation { eid, related, .. } => cal
+                    .update_event(*eid, |ev| {
+                        let related_to = ev.get_related_to().iter();
+                        ev.set_related_to(related_to.filter(|r| *r != related).copied().collect())
+                    })
+                    .is_ok(),
+                Issue::MissingHolidays { eid, .. } => cal
+                    .update_event(*eid, |ev| {
+                        let rule = ev.get_recurrence().and_then(|rec| rec.holidays()).cloned();
+                        ev.set_holidays(rule.map(|rule| HolidayRule {
+                            calendar: None,
+                            ..rule
+                        }))
+                    })
+                    .is_ok(),
+                _ => false,
+            };
+            if repaired {
+                fixed.push((*issue).clone());
+            }
+        }
+        if !storage::save_audited(&before, &cal, user, data_dir) {
+            return Err(CalendarError::Unknown(format!(
+                "Cannot write calendar {}",
+                id
+            )));
+        }
+    }
+    Ok(fixed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::Duration;
+
+    use crate::blobs;
+    use crate::calendar::Calendar;
+    use crate::doctor::{fix, scan, Issue};
+    use crate::event::{Attachment, Event, HolidayAction, HolidayRule};
+    use crate::storage::{resolve_calendar, save_calendar};
+    use crate::testing::TempDir;
+
+    #[test]
+    /// tests finding the problems of a data directory and repairing them
+    fn test_scan_fix() {
+        let dir = TempDir::new("doctor");
+        let ev = |title: &str| Event::new(title, "", "13/07/2022", "09:30", 1.0, None, None, None);
+        let mut work = Calendar::new("owner", "work");
+        let mut review = ev("review");
+        review.add_attachment(Attachment {
+            hash: blobs::store(b"minutes", &dir).unwrap(),
+            size: 7,
+            fmttype: None,
+            filename: None,
+        });
+        work.insert_event(1, review.clone());
+        work.insert_event(2, review);
+        let mut backwards = ev("backwards");
+        backwards.set_duration(&Duration::hours(-1));
+        work.insert_event(3, backwards);
+        let mut follow_up = ev("follow-up");
+        follow_up.set_related_to(vec![1, 99]);
+        work.insert_event(4, follow_up);
+        let mut standup = ev("standup");
+        standup.set_recurrence("weekly 3");
+        standup.set_holidays(Some(HolidayRule {
+            action: HolidayAction::Skip,
+            weekends: true,
+            calendar: Some(String::from("missing")),
+        }));
+        work.insert_event(5, standup);
+        assert!(save_calendar(&work, &dir));
+        let home = Calendar::new("owner", "home");
+        assert!(save_calendar(&home, &dir));
+        fs::copy(dir.join("home.json"), dir.join("home-copy.json")).unwrap();
+        fs::write(dir.join("broken.json"), "{ not a calendar").unwrap();
+        let orphan = blobs::store(b"old slides", &dir).unwrap();
+
+        let issues = scan(&dir, 1 << 20).unwrap();
+        let fixable = vec![
+            Issue::DuplicateEvent {
+                calendar: String::from("work"),
+                eid: 2,
+                of: 1,
+            },
+            Issue::NegativeDuration {
+                calendar: String::from("work"),
+                eid: 3,
+            },
+            Issue::DanglingRelation {
+                calendar: String::from("work"),
+                eid: 4,
+                related: 99,
+            },
+            Issue::MissingHolidays {
+                calendar: String::from("work"),
+                eid: 5,
+                holidays: String::from("missing"),
+            },
+            Issue::OrphanBlob { hash: orphan },
+        ];
+        for issue in fixable.iter() {
+            assert!(issues.contains(issue), "{}", issue);
+        }
+        assert!(issues
+            .iter()
+            .any(|i| matches!(i, Issue::Unreadable { path, .. } if path.ends_with("broken.json"))));
+        assert!(issues
+            .iter()
+            .any(|i| matches!(i, Issue::DuplicateCalendar { id, paths } if id == "home" && paths.len() == 2)));
+        assert_eq!(issues.len(), fixable.len() + 2);
+        assert_eq!(
+            scan(&dir, 10)
+                .unwrap()
+                .iter()
+                .filter(|i| matches!(i, Issue::Oversized { .. }))
+                .count(),
+            3
+        );
+
+        let mut fixed = fix(&issues, "me", &dir).unwrap();
+        fixed.sort_by_key(|i| i.to_string());
+        let mut expected = fixable.clone();
+        expected.sort_by_key(|i| i.to_string());
+        assert_eq!(fixed, expected);
+        let left = scan(&dir, 1 << 20).unwrap();
+        assert_eq!(left.len(), 2);
+        assert!(left.iter().all(|i| !i.is_fixable()));
+
+        let work = resolve_calendar("work", &dir).unwrap();
+        assert_eq!(work.get_size(), 4);
+        assert_eq!(work.peek_event(3).unwrap().get_duration(), 0);
+        assert_eq!(work.peek_event(4).unwrap().get_related_to(), [1]);
+        let rule = work
+            .peek_event(5)
+            .unwrap()
+            .get_recurrence()
+            .unwrap()
+            .holidays();
+        assert_eq!(rule.unwrap().calendar, None);
+    }
+}
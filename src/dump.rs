@@ -0,0 +1,212 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::calendar::Calendar;
+use crate::calendar_error::CalendarError;
+use crate::config::{self, Config, CONFIG_FILE};
+use crate::{clock, report, storage};
+
+/// Version of the format of the dumps, increased on every incompatible change
+pub const DUMP_VERSION: u32 = 1;
+
+/// The contents of a data directory as a single document: its calendars and configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Dump {
+    pub version: u32,
+    /// When the dump was taken
+    pub created: DateTime<Local>,
+    /// The configuration, if the data directory has one
+    #[serde(default)]
+    pub config: Option<Config>,
+    /// The calendars, sorted by id
+    pub calendars: Vec<Calendar>,
+}
+
+fn io_error(path: &Path, e: impl std::fmt::Display) -> CalendarError {
+    CalendarError::Unknown(format!("{}: {}", path.display(), e))
+}
+
+/// Dumps the calendars and the configuration in the data directory. The calendars that
+/// cannot be read are reported and left out
+pub fn create(data_dir: &Path) -> Result<Dump, CalendarError> {
+    let config = if data_dir.join(CONFIG_FILE).exists() {
+        Some(config::load(data_dir)?)
+    } else {
+        None
+    };
+    let mut calendars = Vec::new();
+    for (cal, path) in storage::known_calendars(data_dir)? {
+        match cal {
+            Ok(cal) => calendars.push(cal),
+            Err(e) => report::warning(format!(
+                "Cannot read calendar at {}: {:?}, not dumped",
+                path.display(),
+                e
+            )),
+        }
+    }
+    calendars.sort_by(|a, b| a.get_id().cmp(b.get_id()));
+    Ok(Dump {
+        version: DUMP_VERSION,
+        created: clock::now(),
+        config,
+        calendars,
+    })
+}
+
+/// Restores a dump into the data directory on behalf of `user`, returning the ids of the
+/// calendars restored. The calendars (and the configuration) already in the data directory
+/// are replaced only if `overwrite`: otherwise nothing is written
+pub fn restore(
+    dump: Dump,
+    overwrite: bool,
+    user: &str,
+    data_dir: &Path,
+) -> Result<Vec<String>, CalendarError> {
+    if dump.version > DUMP_VERSION {
+        return Err(CalendarError::Unknown(format!(
+            "Dump in version {} of the format, newer than the supported {}",
+            dump.version, DUMP_VERSION
+        )));
+    }
+    let mut ids = HashSet::new();
+    let mut existing = Vec::new();
+    for cal in dump.calendars.iter() {
+        let path = storage::calendar_path(cal.get_id(), data_dir)?;
+        if !ids.insert(cal.get_id()) {
+            return Err(CalendarError::CalendarAlreadyExists(format!(
+                "{} (twice in the dump)",
+                cal.get_id()
+            )));
+        }
+        if path.exists() {
+            existing.push(cal.get_id().to_string());
+        }
+    }
+    let config_path = data_dir.join(CONFIG_FILE);
+    if dump.config.is_some() && config_path.exists() {
+        existing.push(String::from(CONFIG_FILE));
+    }
+    if !overwrite && !existing.is_empty() {
+        return Err(CalendarError::CalendarAlreadyExists(format!(
+            "{} (restore with overwrite to replace them)",
+            existing.join(", ")
+        )));
+    }
+
+    if let Some(config) = &dump.config {
+        let contents = serde_json::to_string_pretty(config)
+            .map_err(|e| CalendarError::Unknown(e.to_string()))?;
+        fs::write(&config_path, contents).map_err(|e| io_error(&config_path, e))?;
+    }
+    let mut restored = Vec::new();
+    for mut cal in dump.calendars {
+        cal.migrate();
+        let path = storage::calendar_path(cal.get_id(), data_dir)?;
+        let saved = match storage::read_calendar(&path) {
+            Ok(before) => storage::save_audited(&before, &cal, user, data_dir),
+            Err(_) if !path.exists() => {
+                let mut before = Calendar::new(cal.get_owner(), cal.get_name());
+                before.set_id(cal.get_id());
+                storage::save_audited(&before, &cal, user, data_dir)
+            }
+            // unreadable: replaced as a whole
+            Err(_) => storage::save_calendar(&cal, data_dir),
+        };
+        if !saved {
+            return Err(CalendarError::Unknown(format!(
+                "Cannot write calendar {} (restored: {})",
+                cal.get_id(),
+                restored.join(", ")
+            )));
+        }
+        restored.push(cal.get_id().to_string());
+    }
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::calendar::Calendar;
+    use crate::config::{Config, HttpConfig, CONFIG_FILE};
+    use crate::dump::{create, restore, Dump, DUMP_VERSION};
+    use crate::event::Event;
+    use crate::storage::{self, known_calendars, save_calendar};
+    use crate::testing::TempDir;
+    use crate::users::Users;
+
+    #[test]
+    /// tests dumping a data directory and restoring it into another one
+    fn test_dump_restore() {
+        let src = TempDir::new("dump-src");
+        let dst = TempDir::new("dump-dst");
+        let mut work = Calendar::new("owner", "work");
+        work.add_event(Event::new(
+            "review",
+            "",
+            "13/07/2022",
+            "09:30",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        assert!(save_calendar(&work, &src));
+        assert!(save_calendar(&Calendar::new("owner", "home"), &src));
+        let config = Config {
+            http: HttpConfig {
+                timeout_secs: 5,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        fs::write(
+            src.join(CONFIG_FILE),
+            serde_json::to_string(&config).unwrap(),
+        )
+        .unwrap();
+        // not a calendar, and not dumped
+        Users::default().save(&src).unwrap();
+
+        let dump = create(&src).unwrap();
+        assert_eq!(dump.version, DUMP_VERSION);
+        assert_eq!(dump.config, Some(config));
+        let ids: Vec<&str> = dump.calendars.iter().map(|c| c.get_id()).collect();
+        assert_eq!(ids, vec!["home", "work"]);
+        let json = serde_json::to_string(&dump).unwrap();
+        let parsed: Dump = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, dump);
+
+        assert_eq!(
+            restore(parsed.clone(), false, "me", &dst).unwrap(),
+            vec!["home", "work"]
+        );
+        assert_eq!(create(&dst).unwrap().calendars, dump.calendars);
+        assert_eq!(known_calendars(&dst).unwrap().len(), 2);
+
+        // nothing is written unless overwriting
+        let mut changed = parsed.clone();
+        changed.calendars[1].clear();
+        changed.calendars.push(Calendar::new("owner", "new"));
+        assert!(restore(changed.clone(), false, "me", &dst).is_err());
+        assert!(storage::resolve_calendar("new", &dst).is_err());
+        restore(changed, true, "me", &dst).unwrap();
+        assert_eq!(
+            storage::resolve_calendar("work", &dst).unwrap().get_size(),
+            0
+        );
+        assert!(storage::resolve_calendar("new", &dst).is_ok());
+
+        let newer = Dump {
+            version: DUMP_VERSION + 1,
+            ..parsed
+        };
+        assert!(restore(newer, true, "me", &dst).is_err());
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::process::Command;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+use serde::Deserialize;
+
+use crate::calendar_error::CalendarError;
+use crate::event::{Event, HolidayRule, Pause};
+
+/// Explains how to edit the buffer, at its top
+const HEADER: &str = "\
+# Edit the event, then save and exit: exit without saving any change to abort.
+# Lines starting with '#' are ignored
+";
+
+/// The fields of an event, as edited in the buffer
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EditableEvent {
+    title: String,
+    start: String,
+    duration: f64,
+    location: String,
+    recurrence: String,
+    on_holiday: String,
+    holiday_calendar: String,
+    weekends: bool,
+    except: Vec<String>,
+    paused: Vec<String>,
+    tags: Vec<String>,
+    transparency: String,
+    class: String,
+    color: String,
+    important: bool,
+    milestone: bool,
+    related_to: Vec<String>,
+    description: String,
+}
+
+/// Formats the event as a TOML buffer, each field preceded by a comment explaining it
+pub fn to_buffer(ev: &Event) -> String {
+    let rec = ev.get_recurrence();
+    let rule = rec.and_then(|rec| rec.holidays());
+    let string = |s: &str| toml::Value::String(s.to_string());
+    let fields: Vec<(&str, &str, toml::Value)> =
+        vec![
+        ("title", "", string(ev.get_title())),
+        (
+            "start",
+            "start date and time (%d/%m/%Y %H:%M)",
+            string(&ev.get_start().format("%d/%m/%Y %H:%M").to_string()),
+        ),
+        (
+            "duration",
+            "duration in hours",
+            toml::Value::Float(ev.get_duration() as f64 / 3600.0),
+        ),
+        ("location", "", string(ev.get_location())),
+        (
+            "recurrence",
+            "e.g. \"weekly 10 2\", \"monthly 12 on third thu\", \"cron(0 9 * * 1-5) 20\" or \"\"",
+            string(&rec.map(|rec| rec.syntax()).unwrap_or_default()),
+        ),
+        (
+            "on_holiday",
+            "what happens to the occurrences on holidays: \"skip\", \"shift\" or \"\"",
+            string(&rule.map(|r| format!("{:?}", r.action).to_lowercase()).unwrap_or_default()),
+        ),
+        (
+            "holiday_calendar",
+            "id of the calendar whose events are holidays",
+            string(rule.and_then(|r| r.calendar.as_deref()).unwrap_or_default()),
+        ),
+        (
+            "weekends",
+            "whether saturdays and sundays are holidays",
+            toml::Value::Boolean(rule.is_some_and(|r| r.weekends)),
+        ),
+        (
+            "except",
+            "dates (%d/%m/%Y) without occurrences",
+            toml::Value::Array(
+                rec.into_iter()
+                    .flat_map(|rec| rec.exdates())
+                    .map(|d| string(&d.format("%d/%m/%Y").to_string()))
+                    .collect(),
+            ),
+        ),
+        (
+            "paused",
+            "ranges of dates without occurrences (%d/%m/%Y - %d/%m/%Y)",
+            toml::Value::Array(
+                rec.into_iter()
+                    .flat_map(|rec| rec.pauses())
+                    .map(|p| string(&p.to_string()))
+                    .collect(),
+            ),
+        ),
+        (
+            "tags",
+            "",
+            toml::Value::Array(ev.get_metadata().get_tags().iter().map(|t| string(t)).collect()),
+        ),
+        (
+            "transparency",
+            "busy or free",
+            string(&format!("{:?}", ev.get_transparency()).to_lowercase()),
+        ),
+        (
+            "class",
+            "public, private or confidential",
+            string(&format!("{:?}", ev.get_class()).to_lowercase()),
+        ),
+        (
+            "color",
+            "red, orange, yellow, green, cyan, blue, purple, pink, gray or \"\"",
+            string(&ev.get_color().map(|c| c.to_string()).unwrap_or_default()),
+        ),
+        (
+            "important",
+            "whether the event is emphasized in the lists",
+            toml::Value::Boolean(ev.is_important()),
+        ),
+        (
+            "milestone",
+            "whether the event is a milestone, with no duration",
+            toml::Value::Boolean(ev.is_milestone()),
+        ),
+        (
+            "related_to",
+            "eids of the related events",
+            toml::Value::Array(
+                ev.get_related_to()
+                    .iter()
+                    .map(|eid| string(&eid.to_string()))
+                    .collect(),
+            ),
+        ),
+        ("description", "", string(ev.get_description())),
+    ];
+    let mut out = String::from(HEADER);
+    for (key, comment, value) in fields {
+        out.push('\n');
+        if !comment.is_empty() {
+            out.push_str(&format!("# {}\n", comment));
+        }
+        let mut table = toml::Table::new();
+        table.insert(key.to_string(), value);
+        out.push_str(&toml::to_string(&table).unwrap_or_default());
+    }
+    out
+}
+
+/// Applies the fields in the buffer to (a copy of) the event, validating them
+pub fn from_buffer(ev: &Event, buffer: &str) -> Result<Event, String> {
+    let fields: EditableEvent = toml::from_str(buffer).map_err(|e| e.message().to_string())?;
+    let mut ev = ev.clone();
+    ev.set_title(&fields.title);
+    ev.set_description(&fields.description);
+    ev.set_location(&fields.location);
+    let start = NaiveDateTime::parse_from_str(fields.start.trim(), "%d/%m/%Y %H:%M")
+        .map_err(|_| format!("invalid start {}: expected %d/%m/%Y %H:%M", fields.start))?;
+    ev.set_start_date((start.day(), start.month(), start.year()));
+    ev.set_start_time((start.hour(), start.minute(), 0));
+    if !fields.duration.is_finite() || fields.duration < 0.0 {
+        return Err(format!("invalid duration {}", fields.duration));
+    }
+    ev.set_duration(&Duration::seconds((fields.duration * 3600.0).round() as i64));
+    ev.set_recurrence(&fields.recurrence);
+    if !fields.recurrence.trim().is_empty() && ev.get_recurrence().is_none() {
+        return Err(format!("invalid recurrence {}", fields.recurrence));
+    }
+    let rule = match fields.on_holiday.trim() {
+        "" => None,
+        action => Some(HolidayRule {
+            action: action.parse()?,
+            weekends: fields.weekends,
+            calendar: Some(fields.holiday_calendar.trim().to_string()).filter(|c| !c.is_empty()),
+        }),
+    };
+    if rule.is_some() && !ev.set_holidays(rule) {
+        return Err(String::from("on_holiday needs a recurrence"));
+    }
+    let exdates = fields
+        .except
+        .iter()
+        .map(|d| {
+            NaiveDate::parse_from_str(d.trim(), "%d/%m/%Y")
+                .map_err(|_| format!("invalid date {}: expected %d/%m/%Y", d))
+        })
+        .collect::<Result<BTreeSet<NaiveDate>, String>>()?;
+    if !exdates.is_empty() && !ev.set_exdates(exdates) {
+        return Err(String::from("except needs a recurrence"));
+    }
+    let pauses = fields
+        .paused
+        .iter()
+        .map(|range| {
+            let dates = range.split_once(" - ").and_then(|(from, until)| {
+                let from = NaiveDate::parse_from_str(from.trim(), "%d/%m/%Y").ok()?;
+                let until = NaiveDate::parse_from_str(until.trim(), "%d/%m/%Y").ok()?;
+                Some(Pause { from, until })
+            });
+            dates.ok_or_else(|| format!("invalid pause {}: expected %d/%m/%Y - %d/%m/%Y", range))
+        })
+        .collect::<Result<Vec<Pause>, String>>()?;
+    if !pauses.is_empty() && !ev.set_pauses(pauses) {
+        return Err(String::from("paused needs a recurrence"));
+    }
+    ev.set_tags(fields.tags);
+    ev.set_transparency(fields.transparency.parse()?);
+    ev.set_class(fields.class.parse()?);
+    ev.set_color(match fields.color.trim() {
+        "" => None,
+        color => Some(color.parse()?),
+    });
+    ev.set_important(fields.important);
+    ev.set_milestone(fields.milestone);
+    if fields.milestone && ev.get_duration() != 0 {
+        return Err(String::from("a milestone has no duration: set it to 0"));
+    }
+    let related = fields
+        .related_to
+        .iter()
+        .map(|eid| {
+            eid.trim()
+                .parse()
+                .map_err(|_| format!("invalid eid {}", eid))
+        })
+        .collect::<Result<Vec<u64>, String>>()?;
+    ev.set_related_to(related);
+    Ok(ev)
+}
+
+/// Lets the user edit the event with `editor` (a command, possibly with arguments), reopening
+/// the buffer with an explanation while it is not valid. Returns the edited event, or None if
+/// the user did not change anything
+pub fn edit(ev: &Event, editor: &str, eid: u64) -> Result<Option<Event>, CalendarError> {
+    let mut words = editor.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| CalendarError::InvalidConfig(String::from("no editor set")))?;
+    let args: Vec<&str> = words.collect();
+    let path = std::env::temp_dir().join(format!("calenda-rs-{}-{}.toml", std::process::id(), eid));
+    let mut buffer = to_buffer(ev);
+    let io_error = |e: std::io::Error| CalendarError::Unknown(format!("{}: {}", editor, e));
+    let result = loop {
+        fs::write(&path, &buffer).map_err(io_error)?;
+        let status = Command::new(program)
+            .args(&args)
+            .arg(&path)
+            .status()
+            .map_err(io_error)?;
+        if !status.success() {
+            break Err(CalendarError::Unknown(format!(
+                "{} failed: {}",
+                editor, status
+            )));
+        }
+        let edited = fs::read_to_string(&path).map_err(io_error)?;
+        if edited == buffer {
+            // saving an invalid buffer again as it is gives up
+            break match buffer.strip_prefix("# ERROR: ") {
+                Some(e) => Err(CalendarError::Unknown(
+                    e.lines().next().unwrap_or_default().to_string(),
+                )),
+                None => Ok(None),
+            };
+        }
+        match from_buffer(ev, &edited) {
+            Ok(edited) => break Ok(Some(edited)),
+            Err(e) => {
+                // the error is explained on top of the buffer, in place of any previous one
+                let body = edited
+                    .strip_prefix("# ERROR: ")
+                    .and_then(|b| b.split_once('\n'))
+                    .map_or(edited.as_str(), |(_, b)| b);
+                buffer = format!("# ERROR: {}\n{}", e.replace('\n', " "), body);
+            }
+        }
+    };
+    let _ = fs::remove_file(&path);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use chrono::NaiveDate;
+
+    use crate::editor::{edit, from_buffer, to_buffer};
+    use crate::event::{Class, Color, Event};
+
+    fn event() -> Event {
+        let mut ev = Event::new(
+            "review",
+            "first line\nsecond \"line\"",
+            "13/07/2022",
+            "09:30",
+            2.0,
+            Some("room 1"),
+            Some("monthly 5 on last fri"),
+            Some(vec![String::from("work")]),
+        );
+        ev.set_class(Class::Private);
+        ev.set_color(Some(Color::Blue));
+        ev.set_important(true);
+        ev.add_related(u64::MAX);
+        ev.set_exdates(BTreeSet::from([
+            NaiveDate::from_ymd_opt(2022, 7, 29).unwrap()
+        ]));
+        ev.pause(
+            NaiveDate::from_ymd_opt(2022, 8, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2022, 8, 31).unwrap(),
+        );
+        ev
+    }
+
+    #[test]
+    /// tests that an unchanged buffer gives back the same event
+    fn test_buffer() {
+        let ev = event();
+        let buffer = to_buffer(&ev);
+        assert_eq!(from_buffer(&ev, &buffer), Ok(ev.clone()));
+
+        let edited = buffer
+            .replace("title = \"review\"", "title = \"retro\"")
+            .replace("duration = 2.0", "duration = 0.25");
+        let edited = from_buffer(&ev, &edited).unwrap();
+        assert_eq!(edited.get_title(), "retro");
+        assert_eq!(edited.get_duration(), 900);
+
+        assert!(from_buffer(&ev, &buffer.replace("09:30", "9.30")).is_err());
+        assert!(from_buffer(&ev, &buffer.replace("\"private\"", "\"secret\"")).is_err());
+        assert!(from_buffer(&ev, &buffer.replace("\"blue\"", "\"teal\"")).is_err());
+        let plain = from_buffer(&ev, &buffer.replace("\"blue\"", "\"\"")).unwrap();
+        assert_eq!(plain.get_color(), None);
+        assert!(from_buffer(&ev, &buffer.replace("title =", "name =")).is_err());
+        let milestone = buffer.replace("milestone = false", "milestone = true");
+        assert!(from_buffer(&ev, &milestone).is_err());
+        let milestone = milestone.replace("duration = 2.0", "duration = 0.0");
+        assert!(from_buffer(&ev, &milestone).unwrap().is_milestone());
+        assert!(from_buffer(&ev, &buffer.replace("\"monthly", "\"sometimes")).is_err());
+        assert!(from_buffer(&ev, &buffer.replace("29/07/2022", "29/07")).is_err());
+        assert!(from_buffer(&ev, &buffer.replace(" - 31/08/2022", "")).is_err());
+    }
+
+    /// Writes an editor script applying the sed expression to the file it is given, without
+    /// `sed -i` (whose syntax differs between GNU and BSD), and returns its path
+    #[cfg(unix)]
+    fn sed_editor(name: &str, expr: &str) -> String {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!(
+            "calenda-rs-editor-{}-{}.sh",
+            std::process::id(),
+            name
+        ));
+        let script = format!(
+            "#!/bin/sh\nsed '{}' \"$1\" > \"$1.tmp\" && mv \"$1.tmp\" \"$1\"\n",
+            expr
+        );
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    /// tests editing events with an external command
+    fn test_edit() {
+        let ev = event();
+        assert_eq!(edit(&ev, "true", 1).unwrap(), None);
+        let retitle = sed_editor("retitle", "s/review/retro/");
+        let edited = edit(&ev, &retitle, 1).unwrap().unwrap();
+        assert_eq!(edited.get_title(), "retro");
+        // an invalid edit is reopened with the error on top, and abandoned if not fixed
+        let invalid = sed_editor("invalid", "s/09:30/99:99/");
+        assert!(edit(&ev, &invalid, 1).is_err());
+        assert!(edit(&ev, "false", 1).is_err());
+        let _ = std::fs::remove_file(retitle);
+        let _ = std::fs::remove_file(invalid);
+    }
+}
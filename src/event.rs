@@ -1,13 +1,24 @@
-use chrono::{DateTime, Duration, Local, Months, NaiveDate, NaiveDateTime, NaiveTime};
+use chrono::{
+    DateTime, Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, Offset,
+    TimeZone, Weekday,
+};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
 use std::fmt::Result as fmtResult;
 use std::fmt::{Debug, Display};
-use std::hash::{Hash, Hasher};
+use std::hash::Hash;
 use std::result::Result;
 use std::str::FromStr;
 use std::vec;
 
+use chrono_tz::Tz;
 use log::warn;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::calendar_error::CalendarError;
+use crate::cron::CronSchedule;
+use crate::{clock, planner, solar};
 
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
 pub enum Cadence {
@@ -18,6 +29,8 @@ pub enum Cadence {
     Weekly,
     Monthly,
     Yearly,
+    /// At the times matching a 5-field cron expression (see [`CronSchedule`])
+    Cron(String),
 }
 
 impl FromStr for Cadence {
@@ -37,6 +50,7 @@ impl FromStr for Cadence {
     }
 }
 
+#[derive(Debug)]
 pub enum ParseRecurrenceError {
     UnknownCadence(String),
     BadFormat(String),
@@ -50,11 +64,237 @@ impl Display for ParseRecurrenceError {
     }
 }
 
+/// What happens to the occurrences of a recurrent event falling on a holiday
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum HolidayAction {
+    /// the occurrence does not take place
+    Skip,
+    /// the occurrence is moved to the next working day, at the same time
+    Shift,
+}
+
+impl FromStr for HolidayAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(HolidayAction::Skip),
+            "shift" => Ok(HolidayAction::Shift),
+            _ => Err(format!("{} is neither skip nor shift", s)),
+        }
+    }
+}
+
+/// The days on which the occurrences of a recurrent event do not take place: weekends and/or
+/// the days with some event in a holiday calendar
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
+pub struct HolidayRule {
+    pub action: HolidayAction,
+    /// whether saturdays and sundays are holidays
+    pub weekends: bool,
+    /// id of the calendar whose events are holidays
+    pub calendar: Option<String>,
+}
+
+impl HolidayRule {
+    /// Returns whether `date` is a holiday, given the dates of the holiday calendar
+    fn is_holiday(&self, date: NaiveDate, holidays: &BTreeSet<NaiveDate>) -> bool {
+        (self.weekends && matches!(date.weekday(), Weekday::Sat | Weekday::Sun))
+            || holidays.contains(&date)
+    }
+}
+
+/// The event that an event is scheduled after, and the gap between the end of the first
+/// occurrence of that event and its start: moving that event offers to move this one too
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Follows {
+    pub eid: u64,
+    #[serde(serialize_with = "duration_to_secs")]
+    #[serde(deserialize_with = "secs_to_duration")]
+    pub gap: Duration,
+}
+
+/// A range of days (both included) in which a recurrent event is paused, e.g. during the
+/// holidays: its occurrences on these days do not take place, but still count as repetitions
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct Pause {
+    pub from: NaiveDate,
+    pub until: NaiveDate,
+}
+
+impl Pause {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        (self.from..=self.until).contains(&date)
+    }
+}
+
+impl Display for Pause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmtResult {
+        write!(
+            f,
+            "{} - {}",
+            self.from.format("%d/%m/%Y"),
+            self.until.format("%d/%m/%Y")
+        )
+    }
+}
+
+/// Holidays are searched at most this many days after an occurrence to shift
+const MAX_SHIFT_DAYS: u32 = 366;
+
+/// Occurrences compared with the next one for overlaps: enough for the gaps between them to
+/// repeat, for all the cadences, days of the month and cron expressions
+const OVERLAP_CHECKS: usize = 100;
+
+/// Descriptions this long (in characters) or longer are cut when an event is displayed
+pub const DESCRIPTION_PREVIEW: usize = 50;
+
+/// An empty set of dates, e.g. the holidays of an unknown calendar
+static NO_DATES: BTreeSet<NaiveDate> = BTreeSet::new();
+
+/// The day of the month a monthly (or yearly) recurrence takes place on, as the `pos`-th
+/// day of the month among the given days of the week (counting from the end of the month if
+/// negative), like RRULE BYDAY and BYSETPOS: e.g. the third thursday, or the last weekday
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
+pub struct MonthDay {
+    pub pos: i32,
+    pub days: Vec<Weekday>,
+}
+
+impl MonthDay {
+    /// Returns the matching day of the month, or None if the month has no such day
+    /// (e.g. the fifth thursday of a month with only four)
+    pub fn in_month(&self, year: i32, month: u32) -> Option<NaiveDate> {
+        let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+        let matching: Vec<NaiveDate> = first
+            .iter_days()
+            .take_while(|d| d.month() == month)
+            .filter(|d| self.days.contains(&d.weekday()))
+            .collect();
+        let index = if self.pos > 0 {
+            self.pos as usize - 1
+        } else {
+            matching
+                .len()
+                .checked_sub(self.pos.unsigned_abs() as usize)?
+        };
+        matching.get(index).copied()
+    }
+}
+
+impl FromStr for MonthDay {
+    type Err = ParseRecurrenceError;
+
+    /// Parses the position and the days, as in "third thu", "last weekday", "1st mon,wed"
+    /// or "-1 day". The days can also be "day" (any day), "weekday" or "weekend"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseRecurrenceError::BadFormat(s.to_string());
+        let (pos, days) = s.trim().split_once(' ').ok_or_else(err)?;
+        let pos = match pos.to_lowercase().as_str() {
+            "first" | "1st" => 1,
+            "second" | "2nd" => 2,
+            "third" | "3rd" => 3,
+            "fourth" | "4th" => 4,
+            "fifth" | "5th" => 5,
+            "last" => -1,
+            n => match n.parse::<i32>() {
+                Ok(n) if n != 0 && n.abs() <= 31 => n,
+                _ => return Err(err()),
+            },
+        };
+        let days = match days.trim().to_lowercase().as_str() {
+            "day" => vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+                Weekday::Sat,
+                Weekday::Sun,
+            ],
+            "weekday" => vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+            ],
+            "weekend" => vec![Weekday::Sat, Weekday::Sun],
+            list => list
+                .split(',')
+                .map(|d| d.trim().parse::<Weekday>().map_err(|_| err()))
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(MonthDay { pos, days })
+    }
+}
+
+impl MonthDay {
+    /// Describes the day of the month, as in "the third Thu" or "the last weekday"
+    pub fn humanize(&self) -> String {
+        let pos = match self.pos {
+            1 => String::from("first"),
+            2 => String::from("second"),
+            3 => String::from("third"),
+            4 => String::from("fourth"),
+            5 => String::from("fifth"),
+            -1 => String::from("last"),
+            -2 => String::from("second to last"),
+            n if n < 0 => format!("{}th to last", -n),
+            n => format!("{}th", n),
+        };
+        let days = match self.days.len() {
+            7 => String::from("day"),
+            5 if !self.days.contains(&Weekday::Sat) && !self.days.contains(&Weekday::Sun) => {
+                String::from("weekday")
+            }
+            2 if self.days.contains(&Weekday::Sat) && self.days.contains(&Weekday::Sun) => {
+                String::from("weekend day")
+            }
+            _ => {
+                let days: Vec<String> = self.days.iter().map(|d| d.to_string()).collect();
+                days.join(" or ")
+            }
+        };
+        format!("the {} {}", pos, days)
+    }
+}
+
+impl Display for MonthDay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmtResult {
+        let days: Vec<String> = self.days.iter().map(|d| d.to_string()).collect();
+        write!(f, "{} {}", self.pos, days.join(","))
+    }
+}
+
+/// What the time of the occurrences of a recurrent event is fixed in: the local time, so
+/// that they take place at the same time of the day across the DST transitions (floating),
+/// or UTC, so that they take place at the same instant, moving on the local clock when its
+/// offset from UTC changes
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Anchor {
+    #[default]
+    Floating,
+    Utc,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
 pub struct Recurrence {
     cadence: Cadence,
     repetitions: usize,
     interval: Option<usize>,
+    #[serde(default)]
+    month_day: Option<MonthDay>,
+    #[serde(default)]
+    holidays: Option<HolidayRule>,
+    /// Dates with no occurrence (like the ICS EXDATE property), that still count as repetitions
+    #[serde(default)]
+    exdates: BTreeSet<NaiveDate>,
+    /// Ranges of days with no occurrence, sorted and not overlapping
+    #[serde(default)]
+    pauses: Vec<Pause>,
+    #[serde(default)]
+    anchor: Anchor,
 }
 
 impl Recurrence {
@@ -81,6 +321,166 @@ impl Recurrence {
     pub fn set_interval(&mut self, new_interval: Option<usize>) {
         self.interval = new_interval;
     }
+
+    /// Returns the day of the month the occurrences take place on, if not the day of the start
+    pub fn month_day(&self) -> Option<&MonthDay> {
+        self.month_day.as_ref()
+    }
+
+    pub fn set_month_day(&mut self, month_day: Option<MonthDay>) {
+        self.month_day = month_day;
+    }
+
+    /// Returns what happens to the occurrences falling on holidays, if they are treated specially
+    pub fn holidays(&self) -> Option<&HolidayRule> {
+        self.holidays.as_ref()
+    }
+
+    pub fn set_holidays(&mut self, rule: Option<HolidayRule>) {
+        self.holidays = rule;
+    }
+
+    pub fn exdates(&self) -> &BTreeSet<NaiveDate> {
+        &self.exdates
+    }
+
+    pub fn set_exdates(&mut self, exdates: BTreeSet<NaiveDate>) {
+        self.exdates = exdates;
+    }
+
+    pub fn pauses(&self) -> &[Pause] {
+        &self.pauses
+    }
+
+    /// Pauses the recurrence from `from` until `until` (both included), merging the pause
+    /// with the ones it overlaps or follows immediately
+    pub fn pause(&mut self, from: NaiveDate, until: NaiveDate) {
+        let (mut from, mut until) = (from.min(until), from.max(until));
+        self.pauses.retain(|p| {
+            let touches = p.from <= until.succ_opt().unwrap_or(until)
+                && p.until >= from.pred_opt().unwrap_or(from);
+            if touches {
+                from = from.min(p.from);
+                until = until.max(p.until);
+            }
+            !touches
+        });
+        self.pauses.push(Pause { from, until });
+        self.pauses.sort();
+    }
+
+    pub fn set_pauses(&mut self, pauses: Vec<Pause>) {
+        self.pauses.clear();
+        for p in pauses {
+            self.pause(p.from, p.until);
+        }
+    }
+
+    /// Returns whether the recurrence is paused on `date`
+    pub fn is_paused(&self, date: NaiveDate) -> bool {
+        self.pauses.iter().any(|p| p.contains(date))
+    }
+
+    /// Moves the excluded dates and the pauses by `days`, with the occurrences they are
+    /// about: returns None if a date would be out of range
+    fn move_dates(&mut self, days: Duration) -> Option<()> {
+        self.exdates = self
+            .exdates
+            .iter()
+            .map(|d| d.checked_add_signed(days))
+            .collect::<Option<BTreeSet<NaiveDate>>>()?;
+        for p in self.pauses.iter_mut() {
+            p.from = p.from.checked_add_signed(days)?;
+            p.until = p.until.checked_add_signed(days)?;
+        }
+        Some(())
+    }
+
+    pub fn anchor(&self) -> Anchor {
+        self.anchor
+    }
+
+    pub fn set_anchor(&mut self, anchor: Anchor) {
+        self.anchor = anchor;
+    }
+
+    /// Formats the recurrence in the syntax it is parsed from (see `parse_recurrence`).
+    /// The holiday rule and the excluded dates are not part of it
+    pub fn syntax(&self) -> String {
+        if let Cadence::Cron(expr) = &self.cadence {
+            let anchor = if self.anchor == Anchor::Utc {
+                " utc"
+            } else {
+                ""
+            };
+            return format!("cron({}) {}{}", expr, self.repetitions, anchor);
+        }
+        let mut s = format!("{:?} {}", self.cadence, self.repetitions).to_lowercase();
+        if let Some(interval) = self.interval {
+            s.push_str(&format!(" {}", interval));
+        }
+        if let Some(month_day) = &self.month_day {
+            s.push_str(&format!(" on {}", month_day));
+        }
+        if self.anchor == Anchor::Utc {
+            s.push_str(" utc");
+        }
+        s
+    }
+
+    /// Describes the recurrence of an event with the given first occurrence and end of the
+    /// last one, as in "every 2 weeks on Mon, 10 times, until 12/09/2022"
+    pub fn humanize(&self, first: NaiveDateTime, last: NaiveDateTime) -> String {
+        let unit = match &self.cadence {
+            Cadence::Secondly => "second",
+            Cadence::Minutely => "minute",
+            Cadence::Hourly => "hour",
+            Cadence::Daily => "day",
+            Cadence::Weekly => "week",
+            Cadence::Monthly => "month",
+            Cadence::Yearly => "year",
+            Cadence::Cron(expr) => expr,
+        };
+        let mut s = match (&self.cadence, self.interval.unwrap_or(1)) {
+            (Cadence::Cron(_), _) => format!("on the schedule {}", unit),
+            (_, 1) => format!("every {}", unit),
+            (_, n) => format!("every {} {}s", n, unit),
+        };
+        match (&self.cadence, &self.month_day) {
+            (Cadence::Weekly, _) => s.push_str(&format!(" on {}", first.weekday())),
+            (Cadence::Monthly, None) => s.push_str(&format!(" on day {}", first.day())),
+            (Cadence::Monthly, Some(md)) => s.push_str(&format!(" on {}", md.humanize())),
+            (Cadence::Yearly, None) => s.push_str(&format!(" on {}", first.format("%d %b"))),
+            (Cadence::Yearly, Some(md)) => {
+                s.push_str(&format!(" on {} of {}", md.humanize(), first.format("%b")))
+            }
+            _ => (),
+        }
+        match self.repetitions {
+            0 => s.push_str(", once"),
+            n => s.push_str(&format!(", {} times", n + 1)),
+        }
+        s.push_str(&format!(", until {}", last.format("%d/%m/%Y")));
+        if !self.exdates.is_empty() {
+            s.push_str(&format!(" (except on {} dates)", self.exdates.len()));
+        }
+        for p in self.pauses.iter() {
+            s.push_str(&format!(
+                ", paused from {} to {}",
+                p.from.format("%d/%m/%Y"),
+                p.until.format("%d/%m/%Y")
+            ));
+        }
+        match self.holidays.as_ref().map(|h| h.action) {
+            Some(HolidayAction::Skip) => s.push_str(", skipping holidays"),
+            Some(HolidayAction::Shift) => s.push_str(", moved after holidays"),
+            None => (),
+        }
+        if self.anchor == Anchor::Utc {
+            s.push_str(", at the same UTC time");
+        }
+        s
+    }
 }
 
 impl Default for Recurrence {
@@ -89,29 +489,136 @@ impl Default for Recurrence {
             cadence: Cadence::Weekly,
             repetitions: 0,
             interval: None,
+            month_day: None,
+            holidays: None,
+            exdates: BTreeSet::new(),
+            pauses: Vec::new(),
+            anchor: Anchor::Floating,
         }
     }
 }
 
-fn duration_to_min<S>(dur: &Duration, ser: S) -> Result<S::Ok, S::Error>
+/// Durations are stored in seconds: calendars saved in older formats stored minutes,
+/// and are converted when read (see `Calendar::migrate`)
+fn duration_to_secs<S>(dur: &Duration, ser: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    ser.serialize_i64(dur.num_minutes())
+    ser.serialize_i64(dur.num_seconds())
 }
 
-fn min_to_duration<'de, D>(de: D) -> Result<Duration, D::Error>
+fn secs_to_duration<'de, D>(de: D) -> Result<Duration, D::Error>
 where
     D: Deserializer<'de>,
 {
     let x = i64::deserialize(de);
     match x {
-        Ok(val) => Ok(Duration::minutes(val)),
+        Ok(val) => Ok(Duration::seconds(val)),
         Err(e) => Err(e),
     }
 }
 
+fn durations_to_secs<S>(durs: &[Duration], ser: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    ser.collect_seq(durs.iter().map(|d| d.num_seconds()))
+}
+
+fn secs_to_durations<'de, D>(de: D) -> Result<Vec<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let secs = Vec::<i64>::deserialize(de)?;
+    Ok(secs.into_iter().map(Duration::seconds).collect())
+}
+
+/// Returns the duration of the given hours, rounded to the second: `None` if they are not
+/// finite, negative or too many
+pub fn duration_from_hours(hours: f64) -> Option<Duration> {
+    if !hours.is_finite() || hours < 0.0 {
+        return None;
+    }
+    Duration::try_seconds((hours * 3600.0).round() as i64)
+}
+
+/// Parses a lead time, as a number of weeks, days, hours and minutes: "1w", "2 days",
+/// "1d12h", "30 min"
+pub fn parse_lead_time(s: &str) -> Option<Duration> {
+    let s = s.trim().to_lowercase();
+    let mut total = Duration::zero();
+    let mut rest = s.as_str();
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let n: i64 = rest[..digits].parse().ok()?;
+        rest = rest[digits..].trim_start();
+        let unit = rest
+            .find(|c: char| !c.is_alphabetic())
+            .unwrap_or(rest.len());
+        let part = match &rest[..unit] {
+            "w" | "week" | "weeks" => Duration::try_weeks(n)?,
+            "d" | "day" | "days" => Duration::try_days(n)?,
+            "h" | "hour" | "hours" => Duration::try_hours(n)?,
+            "m" | "min" | "mins" | "minute" | "minutes" => Duration::try_minutes(n)?,
+            _ => return None,
+        };
+        total = total.checked_add(&part)?;
+        rest = rest[unit..].trim_start();
+    }
+    (total > Duration::zero()).then_some(total)
+}
+
+/// Cuts a text `max` user-perceived characters (grapheme clusters) long or longer to its
+/// first `max - 1` characters followed by "..."
+pub fn truncate(s: &str, max: usize) -> Cow<'_, str> {
+    match s.grapheme_indices(true).nth(max.saturating_sub(1)) {
+        Some((cut, _)) => Cow::Owned(format!("{}...", &s[..cut])),
+        None => Cow::Borrowed(s),
+    }
+}
+
+/// Formats a lead time in the largest units that fit it exactly, e.g. "1 week", "36 hours"
+pub fn format_lead_time(lead: Duration) -> String {
+    let minutes = lead.num_minutes();
+    let (n, unit) = [(7 * 24 * 60, "week"), (24 * 60, "day"), (60, "hour")]
+        .into_iter()
+        .find(|(m, _)| minutes % m == 0)
+        .map(|(m, unit)| (minutes / m, unit))
+        .unwrap_or((minutes, "minute"));
+    format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" })
+}
+
+/// Parses a recurrence: the cadence, the number of repetitions and optionally the interval,
+/// followed for monthly and yearly cadences by "on" and the day of the month (see [`MonthDay`]),
+/// e.g. "monthly 12 on third thu". The cadence can also be a cron expression in parentheses,
+/// followed by the number of repetitions, e.g. "cron(0 9 * * 1-5) 20"
 fn parse_recurrence(s: &str) -> Option<Recurrence> {
+    // the anchor, if any, is the last word
+    let s = s.trim();
+    let (s, anchor) = match s.rsplit_once(char::is_whitespace) {
+        Some((rest, last)) if last.eq_ignore_ascii_case("utc") => (rest, Anchor::Utc),
+        Some((rest, last)) if last.eq_ignore_ascii_case("floating") => (rest, Anchor::Floating),
+        _ => (s, Anchor::Floating),
+    };
+    if let Some(cron) = s.trim().strip_prefix("cron(") {
+        let (expr, reps) = cron.split_once(')')?;
+        expr.parse::<CronSchedule>().ok()?;
+        return match reps.trim().parse::<usize>() {
+            Ok(reps) if reps > 0 => Some(Recurrence {
+                cadence: Cadence::Cron(expr.split_ascii_whitespace().collect::<Vec<_>>().join(" ")),
+                repetitions: reps,
+                anchor,
+                ..Default::default()
+            }),
+            _ => None,
+        };
+    }
+    let (s, month_day) = match s.split_once(" on ") {
+        Some((s, month_day)) => (s, Some(month_day.parse::<MonthDay>().ok()?)),
+        None => (s, None),
+    };
     let components: Vec<&str> = s.split_ascii_whitespace().collect();
     if components.len() < 2 || components.len() > 3 {
         return None;
@@ -130,57 +637,416 @@ fn parse_recurrence(s: &str) -> Option<Recurrence> {
             if val == 0 {
                 return None;
             }
-            return Some(Recurrence {
+            if month_day.is_some() && !matches!(c, Cadence::Monthly | Cadence::Yearly) {
+                return None;
+            }
+            Some(Recurrence {
                 cadence: c,
                 repetitions: val,
                 interval: interv,
-            });
+                month_day,
+                anchor,
+                ..Default::default()
+            })
+        }
+        (_, _) => None,
+    }
+}
+
+/// Computes the `i`-th occurrence of an event starting at `start` and repeating with `cadence`,
+/// or None if it cannot be represented
+fn nth_occurrence(start: NaiveDateTime, cadence: &Cadence, i: usize) -> Option<NaiveDateTime> {
+    let n = i64::try_from(i).ok()?;
+    let months = |per_step: u32| {
+        u32::try_from(i)
+            .ok()?
+            .checked_mul(per_step)
+            .map(Months::new)
+    };
+    match cadence {
+        Cadence::Secondly => start.checked_add_signed(Duration::try_seconds(n)?),
+        Cadence::Minutely => start.checked_add_signed(Duration::try_minutes(n)?),
+        Cadence::Hourly => start.checked_add_signed(Duration::try_hours(n)?),
+        Cadence::Daily => start.checked_add_signed(Duration::try_days(n)?),
+        Cadence::Weekly => start.checked_add_signed(Duration::try_weeks(n)?),
+        Cadence::Monthly => start.checked_add_months(months(1)?),
+        Cadence::Yearly => start.checked_add_months(months(12)?),
+        // the occurrences depend on the previous one (see `Event::occurrences_avoiding`)
+        Cadence::Cron(_) => None,
+    }
+}
+
+/// Moves an occurrence, computed in the local time of the start of its event, to the local
+/// time in `tz` of the same instant: they differ if the offset of `tz` from UTC changed in between
+fn anchored_to_utc<Tz: TimeZone>(
+    tz: &Tz,
+    start: NaiveDateTime,
+    dt: NaiveDateTime,
+) -> NaiveDateTime {
+    let Some(offset) = tz.offset_from_local_datetime(&start).earliest() else {
+        return dt;
+    };
+    let utc = dt - Duration::seconds(offset.fix().local_minus_utc().into());
+    utc + Duration::seconds(
+        tz.offset_from_utc_datetime(&utc)
+            .fix()
+            .local_minus_utc()
+            .into(),
+    )
+}
+
+/// Whether an event blocks the time it spans (like a meeting) or not (like a holiday or a
+/// reminder): free events never conflict with other events. Maps to the ICS TRANSP property
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Transparency {
+    #[default]
+    Busy,
+    Free,
+}
+
+impl FromStr for Transparency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "busy" | "opaque" => Ok(Transparency::Busy),
+            "free" | "transparent" => Ok(Transparency::Free),
+            _ => Err(format!("{} is neither busy nor free", s)),
+        }
+    }
+}
+
+/// Where the owner works on a day, recorded by an all-day event of its own (see
+/// [`crate::working_location`]). Maps to the ICS X-WORKING-LOCATION property
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
+pub enum WorkPlace {
+    Home,
+    Office,
+    /// Any other place, e.g. a client site
+    Other(String),
+}
+
+impl FromStr for WorkPlace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" => Err(String::from("The working location cannot be empty")),
+            "home" => Ok(WorkPlace::Home),
+            "office" => Ok(WorkPlace::Office),
+            _ => Ok(WorkPlace::Other(s.trim().to_string())),
+        }
+    }
+}
+
+impl Display for WorkPlace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorkPlace::Home => write!(f, "home"),
+            WorkPlace::Office => write!(f, "office"),
+            WorkPlace::Other(place) => write!(f, "{}", place),
+        }
+    }
+}
+
+/// Who may see the details of an event: the title and description of private and confidential
+/// events are hidden when the calendar is shared redacted. Maps to the ICS CLASS property
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Class {
+    #[default]
+    Public,
+    Private,
+    Confidential,
+}
+
+impl FromStr for Class {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "public" => Ok(Class::Public),
+            "private" => Ok(Class::Private),
+            "confidential" => Ok(Class::Confidential),
+            _ => Err(format!("{} is not public, private or confidential", s)),
+        }
+    }
+}
+
+/// Color an event is shown in, e.g. to set it apart from the others in the lists: one of the
+/// CSS color names understood by most clients. Maps to the ICS COLOR property (RFC 7986)
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Color {
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Cyan,
+    Blue,
+    Purple,
+    Pink,
+    Gray,
+}
+
+impl Color {
+    pub const ALL: [Color; 9] = [
+        Color::Red,
+        Color::Orange,
+        Color::Yellow,
+        Color::Green,
+        Color::Cyan,
+        Color::Blue,
+        Color::Purple,
+        Color::Pink,
+        Color::Gray,
+    ];
+
+    /// Returns the escape sequence drawing the text in this color, in a 256 colors terminal
+    pub fn ansi(&self) -> &'static str {
+        match self {
+            Color::Red => "\x1b[38;5;196m",
+            Color::Orange => "\x1b[38;5;208m",
+            Color::Yellow => "\x1b[38;5;220m",
+            Color::Green => "\x1b[38;5;40m",
+            Color::Cyan => "\x1b[38;5;44m",
+            Color::Blue => "\x1b[38;5;33m",
+            Color::Purple => "\x1b[38;5;129m",
+            Color::Pink => "\x1b[38;5;205m",
+            Color::Gray => "\x1b[38;5;245m",
         }
-        (_, _) => {
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        let name = if name == "grey" {
+            "gray"
+        } else {
+            name.as_str()
+        };
+        Color::ALL
+            .into_iter()
+            .find(|c| c.to_string() == name)
+            .ok_or_else(|| {
+                let names: Vec<String> = Color::ALL.iter().map(|c| c.to_string()).collect();
+                format!("{} is not one of {}", s, names.join(", "))
+            })
+    }
+}
+
+impl Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmtResult {
+        let name = match self {
+            Color::Red => "red",
+            Color::Orange => "orange",
+            Color::Yellow => "yellow",
+            Color::Green => "green",
+            Color::Cyan => "cyan",
+            Color::Blue => "blue",
+            Color::Purple => "purple",
+            Color::Pink => "pink",
+            Color::Gray => "gray",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Geographic position of the location of an event, stored in millionths of a degree.
+/// Maps to the ICS GEO property
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Geo {
+    lat: i32,
+    lon: i32,
+}
+
+impl Geo {
+    /// Returns the position at the given latitude and longitude, if they are valid
+    pub fn new(lat: f64, lon: f64) -> Option<Geo> {
+        if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
             return None;
         }
+        Some(Geo {
+            lat: (lat * 1e6).round() as i32,
+            lon: (lon * 1e6).round() as i32,
+        })
+    }
+    pub fn lat(&self) -> f64 {
+        self.lat as f64 / 1e6
+    }
+    pub fn lon(&self) -> f64 {
+        self.lon as f64 / 1e6
     }
 }
 
-pub fn next_occurrence(ev: &Event, cadence: &Cadence) -> (NaiveDateTime, NaiveDateTime) {
-    let ev_start = ev.get_start_date().and_time(ev.get_start_time());
-    let ev_end = ev_start + Duration::seconds(ev.get_duration());
-    match cadence {
-        Cadence::Secondly => (
-            ev_start + Duration::seconds(1),
-            ev_end + Duration::seconds(1),
-        ),
-        Cadence::Minutely => (
-            ev_start + Duration::minutes(1),
-            ev_end + Duration::minutes(1),
-        ),
-        Cadence::Hourly => (ev_start + Duration::hours(1), ev_end + Duration::hours(1)),
-        Cadence::Daily => (ev_start + Duration::days(1), ev_end + Duration::days(1)),
-        Cadence::Weekly => (ev_start + Duration::weeks(1), ev_end + Duration::weeks(1)),
-        Cadence::Monthly => (
-            NaiveDateTime::new(ev_start.date() + Months::new(1), ev_start.time()),
-            NaiveDateTime::new(ev_end.date() + Months::new(1), ev_end.time()),
-        ),
-        Cadence::Yearly => (
-            NaiveDateTime::new(ev_start.date() + Months::new(12), ev_start.time()),
-            NaiveDateTime::new(ev_end.date() + Months::new(12), ev_end.time()),
-        ),
+impl FromStr for Geo {
+    type Err = String;
+
+    /// Parses "lat,lon" or "lat;lon" (as in GEO), optionally as a geo URI ("geo:lat,lon")
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coords = s.trim().trim_start_matches("geo:");
+        let (lat, lon) = coords
+            .split_once([',', ';'])
+            .ok_or_else(|| format!("{} is not a latitude and a longitude", s))?;
+        let parse = |c: &str| c.trim().parse::<f64>().map_err(|e| format!("{}: {}", s, e));
+        Geo::new(parse(lat)?, parse(lon)?).ok_or_else(|| format!("{}: out of range", s))
+    }
+}
+
+impl Display for Geo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.6},{:.6}", self.lat(), self.lon())
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+
+/// A time of the day relative to the sunrise or the sunset at a place, such as 30 minutes
+/// before the sunset: the start of every occurrence of an event at such a time is computed
+/// for the date of the occurrence (see [`solar::sun_times`])
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct SunTime {
+    event: SunEvent,
+    /// Minutes after the sunrise or the sunset, before it if negative
+    offset: i64,
+    place: Geo,
+}
+
+impl SunTime {
+    /// Parses the time relative to the sun at the place: "sunset", "30m before sunset",
+    /// "1h after sunrise", "1h30m before sunrise"
+    pub fn parse(s: &str, place: Geo) -> Result<SunTime, String> {
+        let s = s.trim().to_lowercase();
+        let (offset, sign, event) = match s.rsplit_once(" before ") {
+            Some((offset, event)) => (Some(offset), -1, event),
+            None => match s.rsplit_once(" after ") {
+                Some((offset, event)) => (Some(offset), 1, event),
+                None => (None, 0, s.as_str()),
+            },
+        };
+        let event = match event.trim() {
+            "sunrise" => SunEvent::Sunrise,
+            "sunset" => SunEvent::Sunset,
+            _ => return Err(format!("{}: neither sunrise nor sunset", s)),
+        };
+        let offset = match offset {
+            Some(offset) => {
+                let compact = offset
+                    .replace("minutes", "m")
+                    .replace("mins", "m")
+                    .replace("min", "m")
+                    .replace("hours", "h")
+                    .replace("hour", "h")
+                    .replace(' ', "");
+                planner::parse_duration(&compact)
+                    .ok_or_else(|| format!("{}: invalid offset {}", s, offset))?
+                    .num_minutes()
+                    * sign
+            }
+            None => 0,
+        };
+        Ok(SunTime {
+            event,
+            offset,
+            place,
+        })
+    }
+
+    pub fn event(&self) -> SunEvent {
+        self.event
+    }
+    pub fn offset(&self) -> i64 {
+        self.offset
+    }
+    pub fn place(&self) -> Geo {
+        self.place
+    }
+
+    /// Returns the local time on the date, or None if the sun does not rise or set on that
+    /// date at the place
+    pub fn on(&self, date: NaiveDate) -> Option<NaiveDateTime> {
+        let (sunrise, sunset) = solar::sun_times(date, &self.place)?;
+        let utc = match self.event {
+            SunEvent::Sunrise => sunrise,
+            SunEvent::Sunset => sunset,
+        }
+        .checked_add_signed(Duration::minutes(self.offset))?;
+        Some(Local.from_utc_datetime(&utc).naive_local())
+    }
+}
+
+impl Display for SunTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let event = format!("{:?}", self.event).to_lowercase();
+        match self.offset {
+            0 => write!(f, "{}", event),
+            offset if offset < 0 => write!(f, "{}m before {}", -offset, event),
+            offset => write!(f, "{}m after {}", offset, event),
+        }
+    }
+}
+
+/// A file attached to an event (like the ICS ATTACH property): its contents are stored
+/// apart, under their hash (see [`crate::blobs`])
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
+pub struct Attachment {
+    /// SHA-256 of the contents, as hexadecimal
+    pub hash: String,
+    /// Size of the contents, in bytes
+    pub size: u64,
+    /// Media type of the contents, e.g. "application/pdf"
+    #[serde(default)]
+    pub fmttype: Option<String>,
+    #[serde(default)]
+    pub filename: Option<String>,
+}
+
+/// Outcome of an occurrence of an event, recorded after it ended
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
+pub struct CheckIn {
+    /// Start of the occurrence
+    pub occurrence: NaiveDateTime,
+    pub note: String,
+    /// From 1 to 5
+    pub rating: Option<u8>,
+    /// When the outcome was recorded
+    pub time: DateTime<Local>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
 pub struct EventMetadata {
     tags: Vec<String>,
     creation: DateTime<Local>,
     modification: DateTime<Local>,
+    /// Number of times the event has been modified
+    #[serde(default)]
+    revision: u32,
+    /// Outcomes of the occurrences, by start of the occurrence
+    #[serde(default)]
+    checkins: Vec<CheckIn>,
+    /// Starts of the occurrences done, for the events tracked as habits
+    #[serde(default)]
+    done: BTreeSet<NaiveDateTime>,
+    /// eids of the events that have to end before this one starts
+    #[serde(default)]
+    depends_on: Vec<u64>,
 }
 
 impl Default for EventMetadata {
     fn default() -> Self {
         EventMetadata {
             tags: Vec::default(),
-            creation: Local::now(),
-            modification: Local::now(),
+            creation: clock::now(),
+            modification: clock::now(),
+            revision: 0,
+            checkins: Vec::new(),
+            done: BTreeSet::new(),
+            depends_on: Vec::new(),
         }
     }
 }
@@ -195,6 +1061,44 @@ impl EventMetadata {
     pub fn get_creation(&self) -> DateTime<Local> {
         self.creation
     }
+    pub fn get_modification(&self) -> DateTime<Local> {
+        self.modification
+    }
+    pub fn get_revision(&self) -> u32 {
+        self.revision
+    }
+    pub fn get_depends_on(&self) -> &[u64] {
+        &self.depends_on
+    }
+    pub fn set_depends_on(&mut self, eids: Vec<u64>) {
+        self.depends_on = eids;
+    }
+    /// Returns the outcome recorded for the occurrence starting at `occurrence`, if any
+    pub fn get_checkin(&self, occurrence: NaiveDateTime) -> Option<&CheckIn> {
+        self.checkins.iter().find(|c| c.occurrence == occurrence)
+    }
+    /// Returns whether the occurrence starting at `occurrence` was done
+    pub fn is_done(&self, occurrence: NaiveDateTime) -> bool {
+        self.done.contains(&occurrence)
+    }
+    pub fn mark_done(&mut self, occurrence: NaiveDateTime) {
+        self.done.insert(occurrence);
+    }
+    /// Records the outcome of an occurrence, replacing the one recorded before (if any)
+    pub fn check_in(&mut self, checkin: CheckIn) {
+        self.checkins.retain(|c| c.occurrence != checkin.occurrence);
+        self.checkins.push(checkin);
+        self.checkins.sort_by_key(|c| c.occurrence);
+    }
+    #[cfg_attr(not(feature = "cli"), allow(dead_code))]
+    pub(crate) fn set_revision(&mut self, revision: u32) {
+        self.revision = revision;
+    }
+    /// Records a modification of the event made now
+    pub(crate) fn touch(&mut self) {
+        self.modification = clock::now();
+        self.revision += 1;
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
@@ -203,15 +1107,67 @@ pub struct Event {
     description: String,
     start_date: NaiveDate,
     start_time: NaiveTime,
-    #[serde(serialize_with = "duration_to_min")]
-    #[serde(deserialize_with = "min_to_duration")]
+    #[serde(serialize_with = "duration_to_secs")]
+    #[serde(deserialize_with = "secs_to_duration")]
     duration: Duration,
     location: String,
+    /// Position of the location, if known
+    #[serde(default)]
+    geo: Option<Geo>,
+    /// Time zone of the location (IANA name), the occurrences are also shown in
+    #[serde(default)]
+    time_zone: Option<String>,
+    /// Time needed to get to the location, before the start of the event
+    #[serde(default = "Duration::zero")]
+    #[serde(serialize_with = "duration_to_secs")]
+    #[serde(deserialize_with = "secs_to_duration")]
+    travel_time: Duration,
+    /// Time relative to the sun the occurrences start at, instead of the start time
+    #[serde(default)]
+    sun: Option<SunTime>,
     recurrence: Option<Recurrence>,
+    #[serde(default)]
+    transparency: Transparency,
+    #[serde(default)]
+    class: Class,
+    /// eids of the events this one is related to, e.g. the meeting it follows up
+    #[serde(default)]
+    related_to: Vec<u64>,
+    /// The event this one is scheduled after, kept to move them together
+    #[serde(default)]
+    follows: Option<Follows>,
+    /// How long before the start of each occurrence the reminders are due, longest first
+    #[serde(default)]
+    #[serde(serialize_with = "durations_to_secs")]
+    #[serde(deserialize_with = "secs_to_durations")]
+    reminders: Vec<Duration>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    /// Names of the rooms and other shared resources booked by the event (see
+    /// [`crate::resources`])
+    #[serde(default)]
+    resources: Vec<String>,
+    /// UID of the event in the calendar it was imported from, if any
+    #[serde(default)]
+    uid: Option<String>,
+    /// Where the owner works on the day of the event, if it records just that
+    #[serde(default)]
+    working_location: Option<WorkPlace>,
+    /// Color the event is shown in, if not the default one
+    #[serde(default)]
+    color: Option<Color>,
+    /// Whether the event is marked as important, to be emphasized in the lists
+    #[serde(default)]
+    important: bool,
+    /// Whether the event is a milestone of a project: it has no duration, and it does not
+    /// conflict with the other events
+    #[serde(default)]
+    milestone: bool,
     metadata: EventMetadata,
 }
 
 impl Event {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         event_title: &str,
         descr: &str,
@@ -252,7 +1208,7 @@ impl Event {
                         "Unrecognized date format {}: defaults to current date",
                         start_date
                     );
-                    Local::now().date_naive()
+                    clock::now().date_naive()
                 }
             },
             start_time: match time {
@@ -262,7 +1218,7 @@ impl Event {
                         "Unrecognized time format {}: defaults to current time",
                         start_time
                     );
-                    Local::now().time()
+                    clock::now().time()
                 }
             },
             duration: d,
@@ -270,56 +1226,282 @@ impl Event {
                 Some(loc) => String::from(loc),
                 None => String::from(""),
             },
+            geo: None,
+            time_zone: None,
+            travel_time: Duration::zero(),
+            sun: None,
             recurrence: match recurr {
                 Some(val) => parse_recurrence(val),
                 None => None,
             },
+            transparency: Transparency::Busy,
+            class: Class::Public,
+            related_to: Vec::new(),
+            follows: None,
+            reminders: Vec::new(),
+            attachments: Vec::new(),
+            resources: Vec::new(),
+            uid: None,
+            working_location: None,
+            color: None,
+            important: false,
+            milestone: false,
             metadata: match tags {
                 Some(t) => EventMetadata {
                     tags: t,
-                    creation: Local::now(),
-                    modification: Local::now(),
+                    creation: clock::now(),
+                    modification: clock::now(),
+                    revision: 0,
+                    checkins: Vec::new(),
+                    done: BTreeSet::new(),
+                    depends_on: Vec::new(),
                 },
                 None => EventMetadata::default(),
             },
         }
     }
 
+    /// Returns the start of every occurrence of this event (just its start if it is not
+    /// recurrent), in chronological order. Occurrences are skipped or shifted on weekends if
+    /// the recurrence says so, but the holiday calendar is not known (see
+    /// [`Event::occurrences_avoiding`])
+    pub fn occurrences(&self) -> impl Iterator<Item = NaiveDateTime> + '_ {
+        self.occurrences_avoiding(None)
+    }
+
+    /// Returns the start of every occurrence of this event, in chronological order, given the
+    /// dates of the holiday calendar of its recurrence (if known). Shifted occurrences colliding
+    /// with (or moved after) a later occurrence are dropped
+    pub fn occurrences_avoiding<'a>(
+        &'a self,
+        holidays: Option<&'a BTreeSet<NaiveDate>>,
+    ) -> impl Iterator<Item = NaiveDateTime> + 'a {
+        let holidays = holidays.unwrap_or(&NO_DATES);
+        let start = self.get_start();
+        let sun = self.sun;
+        let mut sun_day: Option<NaiveDate> = None;
+        let (cadence, reps, interval, month_day, rule, exdates, pauses, anchor) =
+            match &self.recurrence {
+                Some(rec) => (
+                    rec.cadence.clone(),
+                    rec.repetitions,
+                    rec.interval.unwrap_or(1).max(1),
+                    rec.month_day.as_ref(),
+                    rec.holidays.as_ref(),
+                    &rec.exdates,
+                    rec.pauses.as_slice(),
+                    rec.anchor,
+                ),
+                None => (
+                    Cadence::Daily,
+                    0,
+                    1,
+                    None,
+                    None,
+                    &NO_DATES,
+                    [].as_slice(),
+                    Anchor::Floating,
+                ),
+            };
+        let cron = match &cadence {
+            Cadence::Cron(expr) => expr.parse::<CronSchedule>().ok(),
+            _ => None,
+        };
+        let mut prev: Option<NaiveDateTime> = None;
+        let mut last: Option<NaiveDateTime> = None;
+        (0..=reps)
+            .map_while(move |i| match &cron {
+                // the times matching the expression, from the start of the event
+                Some(cron) => {
+                    let from = match prev {
+                        Some(prev) => prev.checked_add_signed(Duration::minutes(1))?,
+                        None => start,
+                    };
+                    prev = cron.next_from(from);
+                    prev
+                }
+                None => nth_occurrence(start, &cadence, i.checked_mul(interval)?),
+            })
+            // the occurrence is moved to the day of its month, if it has one
+            .filter_map(move |dt| match month_day {
+                Some(month_day) => month_day
+                    .in_month(dt.year(), dt.month())
+                    .map(|day| day.and_time(start.time()))
+                    .filter(|dt| *dt >= start),
+                None => Some(dt),
+            })
+            .map(move |dt| match anchor {
+                Anchor::Floating => dt,
+                Anchor::Utc => anchored_to_utc(&Local, start, dt),
+            })
+            .filter_map(move |dt| {
+                let dt = match rule {
+                    Some(rule) if rule.is_holiday(dt.date(), holidays) => match rule.action {
+                        HolidayAction::Skip => return None,
+                        HolidayAction::Shift => (1..=MAX_SHIFT_DAYS)
+                            .map_while(|d| dt.checked_add_signed(Duration::days(d as i64)))
+                            .find(|shifted| !rule.is_holiday(shifted.date(), holidays))?,
+                    },
+                    _ => dt,
+                };
+                if last.is_some_and(|last| dt <= last) {
+                    return None;
+                }
+                last = Some(dt);
+                Some(dt)
+            })
+            // no occurrence on the excluded dates, even if shifted there, nor while paused
+            .filter(move |dt| !exdates.contains(&dt.date()))
+            .filter(move |dt| !pauses.iter().any(|p| p.contains(dt.date())))
+            // if relative to the sun, at most one a day, and none on the dates the sun does not
+            // rise or set
+            .filter_map(move |dt| match sun {
+                Some(_) if sun_day == Some(dt.date()) => None,
+                Some(sun) => {
+                    sun_day = Some(dt.date());
+                    sun.on(dt.date())
+                }
+                None => Some(dt),
+            })
+    }
+
+    /// Returns the end of the occurrence of this event starting at `start`
+    pub fn occurrence_end(&self, start: NaiveDateTime) -> NaiveDateTime {
+        start
+            .checked_add_signed(self.duration)
+            .unwrap_or(NaiveDateTime::MAX)
+    }
+
+    /// Returns this event joined with `other`, starting no later than `tolerance` after this
+    /// one ends: the joined one lasts until the later end, and has the descriptions, tags,
+    /// attachments, resources and related events of both. Recurrent events cannot be joined
+    pub fn joined(&self, other: &Event, tolerance: Duration) -> Result<Event, CalendarError> {
+        if self.recurrence.is_some() || other.recurrence.is_some() {
+            return Err(CalendarError::Unknown(String::from(
+                "recurrent events cannot be joined",
+            )));
+        }
+        let (start, end) = (self.get_start(), self.occurrence_end(self.get_start()));
+        let (other_start, other_end) = (other.get_start(), other.occurrence_end(other.get_start()));
+        if other_start < start || other_start - end > tolerance {
+            return Err(CalendarError::Unknown(format!(
+                "\"{}\" starts {} after \"{}\" ends, more than {}",
+                other.title,
+                planner::format_duration(other_start - end),
+                self.title,
+                planner::format_duration(tolerance)
+            )));
+        }
+        let mut joined = self.clone();
+        joined.duration = end.max(other_end) - start;
+        if other.title != self.title {
+            joined.title = format!("{} + {}", self.title, other.title);
+        }
+        joined.description = [self.description.as_str(), other.description.as_str()]
+            .into_iter()
+            .filter(|d| !d.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        if joined.location.is_empty() {
+            joined.location = other.location.clone();
+        }
+        let mut tags = self.metadata.get_tags();
+        for tag in other.metadata.get_tags() {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        joined.set_tags(tags);
+        for attachment in other.attachments.iter() {
+            joined.add_attachment(attachment.clone());
+        }
+        for eid in other.related_to.iter() {
+            joined.add_related(*eid);
+        }
+        joined.set_resources([self.resources.clone(), other.resources.clone()].concat());
+        Ok(joined)
+    }
+
+    /// Checks that the occurrences of this event do not overlap with each other, as the ones
+    /// of a 2-hour event repeating hourly would
+    pub fn check_recurrence(&self) -> Result<(), CalendarError> {
+        let starts: Vec<NaiveDateTime> = self.occurrences().take(OVERLAP_CHECKS).collect();
+        let shortest = starts.windows(2).map(|w| w[1] - w[0]).min();
+        // described only if overlapping, as it walks through all the occurrences
+        match shortest.filter(|gap| self.duration > *gap) {
+            Some(gap) => Err(CalendarError::InvalidRecurrence(format!(
+                "\"{}\" lasts {} but repeats {}, with occurrences {} apart: each would \
+                 overlap with the next one",
+                self.title,
+                planner::format_duration(self.duration),
+                self.humanize_recurrence().unwrap_or_default(),
+                planner::format_duration(gap)
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that the event has no duration if it is a milestone
+    pub fn check_milestone(&self) -> Result<(), CalendarError> {
+        match self.milestone && !self.duration.is_zero() {
+            true => Err(CalendarError::Unknown(format!(
+                "\"{}\" is a milestone, but lasts {}: a milestone has no duration",
+                self.title,
+                planner::format_duration(self.duration)
+            ))),
+            false => Ok(()),
+        }
+    }
+
+    /// Splits the occurrence of this event starting at `start` in the parts on each day it
+    /// covers, e.g. to show a multi-day event on all its days. An occurrence ending at
+    /// midnight does not cover the next day
+    pub fn occurrence_days(&self, start: NaiveDateTime) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        let end = self.occurrence_end(start);
+        let mut days = Vec::new();
+        let mut from = start;
+        loop {
+            let midnight = from
+                .date()
+                .succ_opt()
+                .map_or(NaiveDateTime::MAX, |d| d.and_time(NaiveTime::MIN));
+            if end <= midnight {
+                days.push((from, end));
+                return days;
+            }
+            days.push((from, midnight));
+            from = midnight;
+        }
+    }
+
+    /// Returns whether some occurrence of this event overlaps with some occurrence of `other`
+    /// (free events and milestones do not overlap with anything).
+    /// Events are half-open intervals: an event ending when the other starts does not overlap
+    /// with it, but two events starting at the same time always do
     pub fn overlaps(&self, other: &Event) -> bool {
-        let self_start = self.start_date.and_time(self.start_time);
-        let other_start = other.get_start_date().and_time(other.get_start_time());
-        let self_end = self_start + self.duration;
-        let other_end = other_start + Duration::seconds(other.get_duration());
-        let mut overlap = other_start <= self_end && other_end >= self_start;
-        if overlap {
-            overlap
-        } else {
-            if self.recurrence.is_some() {
-                let rec = self.recurrence.as_ref().unwrap();
-                let cad = rec.cadence();
-                let cnt = rec.repetitions;
-                for _ in 0..cnt {
-                    let (new_start, new_end) = next_occurrence(&self, cad);
-                    overlap = other_start <= new_start && other_end >= new_end;
-                    if overlap {
-                        return overlap;
-                    }
-                }
+        if self.transparency == Transparency::Free || other.transparency == Transparency::Free {
+            return false;
+        }
+        // a milestone is a point in time, not a span of busy time
+        if self.milestone || other.milestone {
+            return false;
+        }
+        let mut self_occ = self.occurrences().peekable();
+        let mut other_occ = other.occurrences().peekable();
+        // both sequences are sorted, so an occurrence entirely before the current occurrence
+        // of the other event cannot overlap with any of its later occurrences either
+        while let (Some(&s1), Some(&s2)) = (self_occ.peek(), other_occ.peek()) {
+            let (e1, e2) = (self.occurrence_end(s1), other.occurrence_end(s2));
+            if s1 == s2 || (s1 < e2 && s2 < e1) {
+                return true;
             }
-            if other.get_recurrence().is_some() {
-                let rec = self.recurrence.as_ref().unwrap();
-                let cad = rec.cadence();
-                let cnt = rec.repetitions;
-                for _ in 0..cnt {
-                    let (new_start, new_end) = next_occurrence(&other, cad);
-                    overlap = new_start <= self_end && new_end >= self_start;
-                    if overlap {
-                        return overlap;
-                    }
-                }
+            if e2 <= s1 {
+                other_occ.next();
+            } else {
+                self_occ.next();
             }
-            false
         }
+        false
     }
 
     pub fn set_title(&mut self, new_title: &str) {
@@ -346,12 +1528,55 @@ impl Event {
             None => false,
         }
     }
+    /// Moves the event (all its occurrences, with the dates excluded from them and its
+    /// pauses) forward by `offset`, or backward if negative: returns false, leaving the event
+    /// unchanged, if it would start out of the range of the dates
+    pub fn move_by(&mut self, offset: Duration) -> bool {
+        let Some(start) = self.get_start().checked_add_signed(offset) else {
+            return false;
+        };
+        if let Some(rec) = self.recurrence.as_mut() {
+            let mut moved = rec.clone();
+            if moved.move_dates(start.date() - self.start_date).is_none() {
+                return false;
+            }
+            *rec = moved;
+        }
+        self.start_date = start.date();
+        self.start_time = start.time();
+        true
+    }
     pub fn set_duration(&mut self, new_duration: &Duration) {
         self.duration = Duration::to_owned(new_duration);
     }
     pub fn set_location(&mut self, loc: &str) {
         self.location = String::from(loc);
     }
+    pub fn set_geo(&mut self, geo: Option<Geo>) {
+        self.geo = geo;
+    }
+    pub fn set_time_zone(&mut self, tz: Option<Tz>) {
+        self.time_zone = tz.map(|tz| tz.name().to_string());
+    }
+    pub fn set_uid(&mut self, uid: &str) {
+        self.uid = Some(uid.to_string());
+    }
+    pub fn set_travel_time(&mut self, travel_time: &Duration) {
+        self.travel_time = travel_time.to_owned();
+    }
+    /// Starts the occurrences at a time relative to the sun (or at the start time, if None),
+    /// moving the start time to the one on the start date.
+    /// Returns false if the sun does not rise or set on the start date at the place
+    pub fn set_sun(&mut self, sun: Option<SunTime>) -> bool {
+        if let Some(sun) = sun {
+            match sun.on(self.start_date) {
+                Some(start) => self.start_time = start.time(),
+                None => return false,
+            }
+        }
+        self.sun = sun;
+        true
+    }
 
     pub fn set_recurrence(&mut self, rec: &str) {
         self.recurrence = parse_recurrence(rec);
@@ -360,6 +1585,135 @@ impl Event {
     pub fn set_tags(&mut self, tags: Vec<String>) {
         self.metadata.set_tags(tags);
     }
+    pub fn set_transparency(&mut self, transp: Transparency) {
+        self.transparency = transp;
+    }
+    pub fn set_working_location(&mut self, place: Option<WorkPlace>) {
+        self.working_location = place;
+    }
+    /// Sets what happens to the occurrences falling on holidays.
+    /// Returns false if the event is not recurrent
+    pub fn set_holidays(&mut self, rule: Option<HolidayRule>) -> bool {
+        match self.recurrence.as_mut() {
+            Some(rec) => {
+                rec.set_holidays(rule);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Sets the dates excluded from the recurrence: returns false if the event is not recurrent
+    pub fn set_exdates(&mut self, exdates: BTreeSet<NaiveDate>) -> bool {
+        match self.recurrence.as_mut() {
+            Some(rec) => {
+                rec.set_exdates(exdates);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Pauses the recurrence from `from` until `until` (both included): returns false if the
+    /// event is not recurrent
+    pub fn pause(&mut self, from: NaiveDate, until: NaiveDate) -> bool {
+        match self.recurrence.as_mut() {
+            Some(rec) => {
+                rec.pause(from, until);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Sets the ranges of days the recurrence is paused in (none to resume it): returns false
+    /// if the event is not recurrent
+    pub fn set_pauses(&mut self, pauses: Vec<Pause>) -> bool {
+        match self.recurrence.as_mut() {
+            Some(rec) => {
+                rec.set_pauses(pauses);
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn set_class(&mut self, class: Class) {
+        self.class = class;
+    }
+    pub fn set_color(&mut self, color: Option<Color>) {
+        self.color = color;
+    }
+    pub fn set_important(&mut self, important: bool) {
+        self.important = important;
+    }
+    pub fn set_milestone(&mut self, milestone: bool) {
+        self.milestone = milestone;
+    }
+    pub fn set_related_to(&mut self, eids: Vec<u64>) {
+        self.related_to = eids;
+    }
+    /// Sets the lead times of the reminders of each occurrence: the non-positive ones are
+    /// ignored, as the duplicates
+    pub fn set_reminders(&mut self, mut leads: Vec<Duration>) {
+        leads.retain(|lead| *lead > Duration::zero());
+        leads.sort_by(|a, b| b.cmp(a));
+        leads.dedup();
+        self.reminders = leads;
+    }
+    /// Sets the resources booked by the event, ignoring the duplicates
+    pub fn set_resources(&mut self, mut names: Vec<String>) {
+        names.sort();
+        names.dedup();
+        self.resources = names;
+    }
+    /// Attaches a file to the event, unless one with the same contents is already attached
+    pub fn add_attachment(&mut self, attachment: Attachment) {
+        if !self.attachments.iter().any(|a| a.hash == attachment.hash) {
+            self.attachments.push(attachment);
+        }
+    }
+    pub fn set_attachments(&mut self, attachments: Vec<Attachment>) {
+        self.attachments = attachments;
+    }
+    pub fn set_follows(&mut self, follows: Option<Follows>) {
+        self.follows = follows;
+    }
+    /// Makes this event depend on the event with the given eid, if not already: that event
+    /// has to end before this one starts
+    pub fn add_dependency(&mut self, eid: u64) {
+        if !self.metadata.depends_on.contains(&eid) {
+            self.metadata.depends_on.push(eid);
+        }
+    }
+    pub fn set_depends_on(&mut self, eids: Vec<u64>) {
+        self.metadata.set_depends_on(eids);
+    }
+    /// Relates this event to the event with the given eid, if not already
+    pub fn add_related(&mut self, eid: u64) {
+        if !self.related_to.contains(&eid) {
+            self.related_to.push(eid);
+        }
+    }
+
+    /// Hides the details of the event if it is not public, leaving only its times: the title
+    /// becomes "Busy", and the description, place, attachments, tags, color, resources, links
+    /// to other events and check-ins (with their notes) are cleared
+    pub fn redact(&mut self) {
+        if self.class != Class::Public {
+            self.title = String::from("Busy");
+            self.description.clear();
+            self.location.clear();
+            self.geo = None;
+            self.working_location = None;
+            self.attachments.clear();
+            self.resources.clear();
+            self.color = None;
+            self.important = false;
+            self.uid = None;
+            self.related_to.clear();
+            self.follows = None;
+            self.metadata.tags.clear();
+            self.metadata.depends_on.clear();
+            self.metadata.checkins.clear();
+        }
+    }
 
     pub fn get_title(&self) -> &str {
         self.title.as_str()
@@ -373,6 +1727,10 @@ impl Event {
     pub fn get_start_time(&self) -> NaiveTime {
         self.start_time
     }
+    /// Returns the start date and time of this event
+    pub fn get_start(&self) -> NaiveDateTime {
+        self.start_date.and_time(self.start_time)
+    }
     /// returns the duration of this event, in seconds
     pub fn get_duration(&self) -> i64 {
         self.duration.num_seconds()
@@ -381,20 +1739,140 @@ impl Event {
     pub fn get_location(&self) -> &str {
         self.location.as_str()
     }
+    /// Returns the position of the location of this event, if known
+    pub fn get_geo(&self) -> Option<Geo> {
+        self.geo
+    }
+    /// Returns the time zone of the location of this event, if set
+    pub fn get_time_zone(&self) -> Option<Tz> {
+        self.time_zone.as_deref().and_then(|tz| tz.parse().ok())
+    }
+    /// Returns the UID of the event in the calendar it was imported from, if any
+    pub fn get_uid(&self) -> Option<&str> {
+        self.uid.as_deref()
+    }
+    /// Returns the time relative to the sun the occurrences of this event start at, if any
+    pub fn get_sun(&self) -> Option<SunTime> {
+        self.sun
+    }
+    /// Returns the time needed to get to the location of this event
+    pub fn get_travel_time(&self) -> Duration {
+        self.travel_time
+    }
 
     /// Returns the recurrence of this event, if any
     pub fn get_recurrence(&self) -> Option<&Recurrence> {
         self.recurrence.as_ref()
     }
 
+    /// Describes the recurrence of the event, if any (see [`Recurrence::humanize`])
+    pub fn humanize_recurrence(&self) -> Option<String> {
+        let rec = self.recurrence.as_ref()?;
+        let first = self.get_start();
+        let last = self.occurrences().last().unwrap_or(first);
+        Some(rec.humanize(first, self.occurrence_end(last)))
+    }
+
+    /// Returns whether this event blocks the time it spans
+    pub fn get_transparency(&self) -> Transparency {
+        self.transparency
+    }
+
+    /// Returns where the owner works on the day of the event, if it records just that
+    pub fn get_working_location(&self) -> Option<&WorkPlace> {
+        self.working_location.as_ref()
+    }
+
+    /// Returns who may see the details of this event
+    pub fn get_class(&self) -> Class {
+        self.class
+    }
+
+    /// Returns the color this event is shown in, if not the default one
+    pub fn get_color(&self) -> Option<Color> {
+        self.color
+    }
+
+    /// Returns whether this event is marked as important
+    pub fn is_important(&self) -> bool {
+        self.important
+    }
+
+    /// Returns whether this event is a milestone
+    pub fn is_milestone(&self) -> bool {
+        self.milestone
+    }
+
+    /// Returns the eids of the events this one is related to
+    pub fn get_related_to(&self) -> &[u64] {
+        &self.related_to
+    }
+
+    /// Returns the event this one is scheduled after, if kept
+    pub fn get_follows(&self) -> Option<Follows> {
+        self.follows
+    }
+
+    /// Returns the eids of the events that have to end before this one starts: the ones it
+    /// depends on, and the one it is scheduled after
+    pub fn dependencies(&self) -> Vec<u64> {
+        let mut eids = self.metadata.depends_on.clone();
+        if let Some(follows) = self.follows {
+            if !eids.contains(&follows.eid) {
+                eids.push(follows.eid);
+            }
+        }
+        eids
+    }
+
+    /// Returns the files attached to this event
+    pub fn get_attachments(&self) -> &[Attachment] {
+        &self.attachments
+    }
+
+    /// Returns how long before each occurrence the reminders are due, longest first
+    pub fn get_reminders(&self) -> &[Duration] {
+        &self.reminders
+    }
+
+    /// Returns the names of the resources booked by this event, sorted
+    pub fn get_resources(&self) -> &[String] {
+        &self.resources
+    }
+
+    /// Returns whether this event is tagged with `tag`
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.metadata.tags.iter().any(|t| t == tag)
+    }
+
     pub fn get_metadata(&self) -> EventMetadata {
         self.metadata.clone()
     }
+
+    /// Records a modification of the event made now (see [`Calendar::update_event`])
+    ///
+    /// [`Calendar::update_event`]: crate::calendar::Calendar::update_event
+    pub(crate) fn touch(&mut self) {
+        self.metadata.touch();
+    }
+    /// Records the outcome of an occurrence of the event (see [`EventMetadata::check_in`])
+    pub fn check_in(&mut self, checkin: CheckIn) {
+        self.metadata.check_in(checkin);
+    }
+    /// Marks the occurrence starting at `occurrence` as done
+    pub fn mark_done(&mut self, occurrence: NaiveDateTime) {
+        self.metadata.mark_done(occurrence);
+    }
+    /// Sets the revision of the event, e.g. to the SEQUENCE of an imported one
+    #[cfg_attr(not(feature = "cli"), allow(dead_code))]
+    pub(crate) fn set_revision(&mut self, revision: u32) {
+        self.metadata.set_revision(revision);
+    }
 }
 
 impl Default for Event {
     fn default() -> Event {
-        let now = Local::now();
+        let now = clock::now();
         Event {
             title: String::new(),
             description: String::new(),
@@ -402,44 +1880,64 @@ impl Default for Event {
             start_time: now.time(),
             duration: Duration::zero(),
             location: String::from(""),
+            geo: None,
+            time_zone: None,
+            travel_time: Duration::zero(),
+            sun: None,
             recurrence: None,
+            transparency: Transparency::Busy,
+            class: Class::Public,
+            related_to: Vec::new(),
+            follows: None,
+            reminders: Vec::new(),
+            attachments: Vec::new(),
+            resources: Vec::new(),
+            uid: None,
+            working_location: None,
+            color: None,
+            important: false,
+            milestone: false,
             metadata: EventMetadata::default(),
         }
     }
 }
 
+/// Displays the start, title and location of the event, followed by its description: cut to
+/// [`DESCRIPTION_PREVIEW`] characters, unless with the alternate flag (`{:#}`)
 impl Display for Event {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut h = std::collections::hash_map::DefaultHasher::new();
-        self.hash(&mut h);
-        let hashval = h.finish();
-
-        let desc = self.get_description();
+        let desc = match f.alternate() {
+            true => Cow::Borrowed(self.get_description()),
+            false => truncate(self.get_description(), DESCRIPTION_PREVIEW),
+        };
         let mut loc = String::from(self.get_location());
         if !loc.is_empty() {
             loc = " @ ".to_owned() + &loc;
         }
         write!(
             f,
-            "[eid = {}]\n[{} - {}] {}{}\n{}",
-            hashval,
+            "[{} - {}] {}{}\n{}",
             self.get_start_date().format("%d/%m/%Y"),
             self.get_start_time().format("%H:%M"),
             self.get_title(),
             &loc,
-            if desc.len() < 50 {
-                desc.to_string()
-            } else {
-                desc[0..49].to_string() + "..."
-            }
+            desc
         )
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::event::{Cadence, Event, Recurrence};
+    use crate::calendar_error::CalendarError;
+    use crate::event::{
+        anchored_to_utc, format_lead_time, parse_lead_time, truncate, Anchor, Attachment, Cadence,
+        CheckIn, Class, Color, Event, Geo, HolidayAction, HolidayRule, MonthDay, Pause, Recurrence,
+        SunEvent, SunTime, Transparency, WorkPlace,
+    };
+    use crate::solar::sun_times;
     use chrono::{Datelike, Duration, NaiveDate, NaiveTime, Timelike};
+    use chrono::{FixedOffset, Local, LocalResult, NaiveDateTime, TimeZone};
+    use std::collections::BTreeSet;
 
     #[test]
     /// tests the new function
@@ -622,6 +2120,21 @@ mod tests {
         );
     }
 
+    #[test]
+    /// tests that the occurrences are the given interval of cadences apart
+    fn test_recurrent_interval() {
+        let starts = |rec: &str| -> Vec<(u32, u32)> {
+            Event::new("ev", "", "06/06/2022", "10:00", 1.0, None, Some(rec), None)
+                .occurrences()
+                .map(|dt| (dt.day(), dt.month()))
+                .collect()
+        };
+        assert_eq!(starts("weekly 2 2"), [(6, 6), (20, 6), (4, 7)]);
+        assert_eq!(starts("weekly 2 1"), starts("weekly 2"));
+        assert_eq!(starts("daily 3 10"), [(6, 6), (16, 6), (26, 6), (6, 7)]);
+        assert_eq!(starts("monthly 2 3"), [(6, 6), (6, 9), (6, 12)]);
+    }
+
     #[test]
     /// Test recurrent events (invalid)
     fn test_recurrent_bad() {
@@ -674,4 +2187,724 @@ mod tests {
         );
         assert_eq!(ev_zero_rep.get_recurrence(), None);
     }
+
+    #[test]
+    /// tests that the occurrences past the representable dates are left out
+    fn test_recurrent_overflow() {
+        for recurrence in [
+            "weekly 3 99999999999",
+            "monthly 3 4294967296",
+            "yearly 3 357913942",
+        ] {
+            let ev = Event::new(
+                "test",
+                "test",
+                "13/07/2022",
+                "09:30",
+                1.0,
+                None,
+                Some(recurrence),
+                None,
+            );
+            assert_eq!(ev.occurrences().count(), 1, "{}", recurrence);
+        }
+    }
+
+    #[test]
+    /// tests that durations are saved with full precision
+    fn test_duration_precision() {
+        let mut ev = Event::default();
+        ev.set_duration(&Duration::seconds(90));
+        let json = serde_json::to_string(&ev).unwrap();
+        let read: Event = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.get_duration(), 90);
+        assert_eq!(read, ev);
+    }
+
+    #[test]
+    /// tests that a milestone has no duration, and does not overlap with other events
+    fn test_milestone() {
+        let meeting = Event::new("meeting", "", "13/07/2022", "09:00", 2.0, None, None, None);
+        let mut release = Event::new("release", "", "13/07/2022", "10:00", 0.0, None, None, None);
+        assert!(release.check_milestone().is_ok());
+        release.set_milestone(true);
+        assert!(release.check_milestone().is_ok());
+        assert!(!release.overlaps(&meeting) && !meeting.overlaps(&release));
+        // at the same time as another milestone
+        let mut freeze = release.clone();
+        freeze.set_title("freeze");
+        assert!(!release.overlaps(&freeze));
+        release.set_duration(&Duration::hours(1));
+        assert!(release.check_milestone().is_err());
+        release.set_milestone(false);
+        assert!(release.check_milestone().is_ok());
+        assert!(release.overlaps(&meeting));
+    }
+
+    #[test]
+    /// tests rejecting the recurrences whose occurrences overlap with each other
+    fn test_check_recurrence() {
+        let mut ev = Event::new(
+            "shift",
+            "",
+            "04/07/2022",
+            "09:00",
+            2.0,
+            None,
+            Some("hourly 5"),
+            None,
+        );
+        match ev.check_recurrence() {
+            Err(CalendarError::InvalidRecurrence(e)) => assert!(e.contains("1h apart"), "{}", e),
+            _ => panic!("overlapping occurrences accepted"),
+        }
+        ev.set_recurrence("hourly 5 2");
+        assert!(ev.check_recurrence().is_ok());
+        // 9 days long, every week
+        ev.set_duration(&Duration::days(9));
+        ev.set_recurrence("weekly 4");
+        assert!(ev.check_recurrence().is_err());
+        ev.set_recurrence("weekly 4 2");
+        assert!(ev.check_recurrence().is_ok());
+        ev.set_recurrence("monthly 12 on first thu");
+        assert!(ev.check_recurrence().is_ok());
+        // on working days: a day apart, but three days over the weekends
+        ev.set_duration(&Duration::hours(30));
+        ev.set_recurrence("cron(0 9 * * 1-5) 20");
+        assert!(ev.check_recurrence().is_err());
+        ev.set_recurrence("");
+        assert!(ev.check_recurrence().is_ok());
+    }
+
+    #[test]
+    /// tests splitting the occurrences in their days
+    fn test_occurrence_days() {
+        let at = |d, h| {
+            NaiveDate::from_ymd_opt(2022, 7, d)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+        };
+        let mut ev = Event::new("test", "", "30/06/2022", "09:00", 1.0, None, None, None);
+        assert_eq!(ev.occurrence_days(at(1, 9)), [(at(1, 9), at(1, 10))]);
+        ev.set_duration(&Duration::hours(39));
+        assert_eq!(
+            ev.occurrence_days(at(1, 9)),
+            [(at(1, 9), at(2, 0)), (at(2, 0), at(3, 0))]
+        );
+        // ending at midnight
+        ev.set_duration(&Duration::hours(15));
+        assert_eq!(ev.occurrence_days(at(1, 9)), [(at(1, 9), at(2, 0))]);
+        ev.set_duration(&Duration::zero());
+        assert_eq!(ev.occurrence_days(at(1, 9)), [(at(1, 9), at(1, 9))]);
+    }
+
+    /// builds an event for the overlap tests
+    fn ev(date: &str, time: &str, hours: f32, recurr: Option<&str>) -> Event {
+        Event::new("test", "", date, time, hours, None, recurr, None)
+    }
+
+    #[test]
+    /// tests overlaps between single events
+    fn test_overlaps_single() {
+        let base = ev("01/01/2022", "10:00", 1.0, None);
+        let cases = [
+            // disjoint
+            (ev("01/01/2022", "12:00", 1.0, None), false),
+            (ev("01/01/2022", "08:00", 1.0, None), false),
+            // touching endpoints
+            (ev("01/01/2022", "11:00", 1.0, None), false),
+            (ev("01/01/2022", "09:00", 1.0, None), false),
+            // partial overlap
+            (ev("01/01/2022", "10:30", 1.0, None), true),
+            (ev("01/01/2022", "09:30", 1.0, None), true),
+            // containment
+            (ev("01/01/2022", "10:15", 0.5, None), true),
+            (ev("01/01/2022", "09:00", 3.0, None), true),
+            // same interval, and instants at its start
+            (ev("01/01/2022", "10:00", 1.0, None), true),
+            (ev("01/01/2022", "10:00", 0.0, None), true),
+            // other days
+            (ev("02/01/2022", "10:00", 1.0, None), false),
+        ];
+        for (other, expected) in cases {
+            assert_eq!(base.overlaps(&other), expected, "{:?}", other);
+            assert_eq!(other.overlaps(&base), expected, "{:?}", other);
+        }
+        let instant = ev("01/01/2022", "10:00", 0.0, None);
+        assert!(instant.overlaps(&instant.clone()));
+    }
+
+    #[test]
+    /// tests overlaps between a recurrent and a single event
+    fn test_overlaps_recurrent_single() {
+        // from 01/01 to 06/01, 10:00 to 11:00
+        let daily = ev("01/01/2022", "10:00", 1.0, Some("daily 5"));
+        let cases = [
+            (ev("01/01/2022", "10:30", 1.0, None), true),
+            (ev("04/01/2022", "10:30", 1.0, None), true),
+            (ev("06/01/2022", "09:00", 3.0, None), true),
+            (ev("04/01/2022", "11:00", 1.0, None), false),
+            (ev("07/01/2022", "10:00", 1.0, None), false),
+            (ev("31/12/2021", "10:00", 1.0, None), false),
+        ];
+        for (other, expected) in cases {
+            assert_eq!(daily.overlaps(&other), expected, "{:?}", other);
+            assert_eq!(other.overlaps(&daily), expected, "{:?}", other);
+        }
+        // occurrences longer than the cadence overlap with each other
+        let long = ev("01/01/2022", "10:00", 36.0, Some("daily 1"));
+        let instant = ev("02/01/2022", "22:00", 0.0, None);
+        assert!(long.overlaps(&instant));
+        assert!(instant.overlaps(&long));
+    }
+
+    #[test]
+    /// tests overlaps between recurrent events
+    fn test_overlaps_recurrent_recurrent() {
+        let daily = ev("01/01/2022", "10:00", 1.0, Some("daily 5"));
+        let cases = [
+            // the third occurrence of the daily event
+            (ev("03/01/2022", "10:30", 1.0, Some("weekly 4")), true),
+            (ev("03/01/2022", "12:00", 1.0, Some("weekly 4")), false),
+            // starts after the last occurrence of the daily event
+            (ev("07/01/2022", "10:00", 1.0, Some("weekly 4")), false),
+            // the last occurrence overlaps with the first of the daily event
+            (ev("01/12/2021", "10:30", 1.0, Some("monthly 1")), true),
+            (ev("01/12/2021", "10:30", 1.0, Some("monthly 2")), true),
+            (ev("01/11/2021", "10:30", 1.0, Some("monthly 1")), false),
+            (ev("01/01/2022", "11:00", 1.0, Some("hourly 10")), false),
+        ];
+        for (other, expected) in cases {
+            assert_eq!(daily.overlaps(&other), expected, "{:?}", other);
+            assert_eq!(other.overlaps(&daily), expected, "{:?}", other);
+        }
+    }
+
+    #[test]
+    /// tests that free events never overlap
+    fn test_overlaps_free() {
+        let busy = ev("01/01/2022", "10:00", 1.0, Some("daily 5"));
+        let mut holiday = ev("03/01/2022", "00:00", 24.0, None);
+        assert!(busy.overlaps(&holiday));
+        holiday.set_transparency(Transparency::Free);
+        assert!(!busy.overlaps(&holiday));
+        assert!(!holiday.overlaps(&busy));
+        assert!(!holiday.overlaps(&holiday.clone()));
+    }
+
+    #[test]
+    /// tests hiding the details of private events
+    fn test_redact() {
+        let mut ev = Event::new(
+            "doctor",
+            "checkup",
+            "13/07/2022",
+            "09:00",
+            1.0,
+            Some("hospital"),
+            None,
+            None,
+        );
+        let public = ev.clone();
+        ev.redact();
+        assert_eq!(ev, public);
+        assert_eq!("Confidential".parse(), Ok(Class::Confidential));
+        assert!("secret".parse::<Class>().is_err());
+        ev.set_class(Class::Private);
+        ev.redact();
+        assert_eq!(ev.get_title(), "Busy");
+        assert_eq!(ev.get_description(), "");
+        assert_eq!(ev.get_location(), "");
+        assert_eq!(ev.get_start(), public.get_start());
+        assert_eq!(ev.get_duration(), public.get_duration());
+
+        // a private event with all its details set exposes only its times
+        let mut ev = Event::new(
+            "secret title",
+            "secret description",
+            "13/07/2022",
+            "09:00",
+            1.0,
+            Some("secret location"),
+            Some("weekly 3"),
+            Some(vec![String::from("secret-tag")]),
+        );
+        ev.set_uid("secret@example.com");
+        ev.set_working_location(Some(WorkPlace::Other(String::from("secret site"))));
+        ev.set_resources(vec![String::from("secret room")]);
+        ev.set_color(Some(Color::Red));
+        ev.set_important(true);
+        ev.add_related(7);
+        ev.add_dependency(8);
+        ev.add_attachment(Attachment {
+            hash: String::from("0123"),
+            size: 4,
+            fmttype: None,
+            filename: Some(String::from("secret.pdf")),
+        });
+        ev.check_in(CheckIn {
+            occurrence: ev.get_start(),
+            note: String::from("secret note"),
+            rating: Some(4),
+            time: Local::now(),
+        });
+        ev.set_class(Class::Private);
+        let private = ev.clone();
+        ev.redact();
+        let json = serde_json::to_string(&ev).unwrap();
+        assert!(!json.contains("secret"), "{}", json);
+        assert!(!json.contains("0123"), "{}", json);
+        assert_eq!(ev.get_color(), None);
+        assert!(!ev.is_important());
+        assert!(ev.get_related_to().is_empty());
+        assert!(ev.get_metadata().get_depends_on().is_empty());
+        assert!(ev.get_metadata().get_checkin(ev.get_start()).is_none());
+        assert_eq!(ev.get_start(), private.get_start());
+        assert_eq!(ev.get_duration(), private.get_duration());
+        assert!(ev.occurrences().eq(private.occurrences()));
+        assert_eq!(ev.get_transparency(), private.get_transparency());
+    }
+
+    #[test]
+    /// tests skipping and shifting the occurrences falling on holidays
+    fn test_holidays() {
+        // from friday to tuesday
+        let mut ev = Event::new(
+            "standup",
+            "",
+            "15/07/2022",
+            "09:00",
+            0.5,
+            None,
+            Some("daily 4"),
+            None,
+        );
+        let days = |ev: &Event, holidays| -> Vec<u32> {
+            ev.occurrences_avoiding(holidays)
+                .map(|dt| dt.day())
+                .collect()
+        };
+        assert_eq!(days(&ev, None), [15, 16, 17, 18, 19]);
+        let mut rule = HolidayRule {
+            action: HolidayAction::Skip,
+            weekends: true,
+            calendar: Some(String::from("holidays")),
+        };
+        assert!(ev.set_holidays(Some(rule.clone())));
+        let holidays = BTreeSet::from([NaiveDate::from_ymd_opt(2022, 7, 19).unwrap()]);
+        assert_eq!(days(&ev, None), [15, 18, 19]);
+        assert_eq!(days(&ev, Some(&holidays)), [15, 18]);
+        // the occurrences on the weekend are moved to monday, and merged
+        rule.action = HolidayAction::Shift;
+        ev.set_holidays(Some(rule));
+        assert_eq!(days(&ev, Some(&holidays)), [15, 18, 20]);
+        assert_eq!(ev.occurrences().next(), Some(ev.get_start()));
+        // excluded dates
+        assert!(ev.set_exdates(BTreeSet::from([
+            NaiveDate::from_ymd_opt(2022, 7, 18).unwrap()
+        ])));
+        assert_eq!(days(&ev, Some(&holidays)), [15, 20]);
+
+        let mut single = Event::default();
+        assert!(!single.set_holidays(None));
+        assert!(!single.set_exdates(BTreeSet::new()));
+        assert!(!single.pause(NaiveDate::MIN, NaiveDate::MAX));
+    }
+
+    #[test]
+    /// tests pausing a recurrence between two dates, and resuming it
+    fn test_pause() {
+        let mut ev = Event::new(
+            "standup",
+            "",
+            "28/07/2022",
+            "09:00",
+            1.0,
+            None,
+            Some("weekly 6"),
+            None,
+        );
+        let date = |d, m| NaiveDate::from_ymd_opt(2022, m, d).unwrap();
+        let days = |ev: &Event| -> Vec<(u32, u32)> {
+            ev.occurrences().map(|dt| (dt.day(), dt.month())).collect()
+        };
+        assert!(ev.pause(date(1, 8), date(10, 8)));
+        // merged with the pauses it overlaps or follows immediately
+        ev.pause(date(31, 8), date(11, 8));
+        let rec = ev.get_recurrence().unwrap();
+        assert_eq!(
+            rec.pauses(),
+            [Pause {
+                from: date(1, 8),
+                until: date(31, 8)
+            }]
+        );
+        assert!(rec.is_paused(date(15, 8)) && !rec.is_paused(date(1, 9)));
+        // the paused occurrences still count as repetitions
+        assert_eq!(days(&ev), [(28, 7), (1, 9), (8, 9)]);
+        assert!(ev
+            .humanize_recurrence()
+            .unwrap()
+            .ends_with(", paused from 01/08/2022 to 31/08/2022"));
+        assert!(ev.set_pauses(Vec::new()));
+        assert_eq!(days(&ev).len(), 7);
+    }
+
+    #[test]
+    /// tests recurrences on the n-th day of the week of the month
+    fn test_month_day() {
+        let md: MonthDay = "third thu".parse().unwrap();
+        assert_eq!(md.pos, 3);
+        assert_eq!(md.days, [chrono::Weekday::Thu]);
+        assert_eq!(md.in_month(2022, 7), NaiveDate::from_ymd_opt(2022, 7, 21));
+        let last: MonthDay = "last weekday".parse().unwrap();
+        // the 31st of july 2022 is a sunday
+        assert_eq!(last.in_month(2022, 7), NaiveDate::from_ymd_opt(2022, 7, 29));
+        assert!("fifth fri"
+            .parse::<MonthDay>()
+            .unwrap()
+            .in_month(2022, 6)
+            .is_none());
+        assert!("0 fri".parse::<MonthDay>().is_err());
+        assert!("last someday".parse::<MonthDay>().is_err());
+
+        let days = |rec: &str| -> Vec<(u32, u32)> {
+            Event::new("ev", "", "01/06/2022", "10:00", 1.0, None, Some(rec), None)
+                .occurrences()
+                .map(|dt| (dt.day(), dt.month()))
+                .collect()
+        };
+        // the fifth friday of june, july and august: june has only four
+        assert_eq!(days("monthly 2 on fifth fri"), [(29, 7)]);
+        assert_eq!(
+            days("monthly 3 on last weekday"),
+            [(30, 6), (29, 7), (31, 8), (30, 9)]
+        );
+        // every other month
+        assert_eq!(days("monthly 2 2 on 1st mon"), [(6, 6), (1, 8), (3, 10)]);
+        let ev = Event::new(
+            "ev",
+            "",
+            "01/06/2022",
+            "10:00",
+            1.0,
+            None,
+            Some("daily 2 on last fri"),
+            None,
+        );
+        assert!(ev.get_recurrence().is_none());
+    }
+
+    #[test]
+    /// tests recurrences following a cron expression
+    fn test_cron() {
+        // from friday 15/07/2022, every weekday at 9:00 and 14:30
+        let ev = Event::new(
+            "check",
+            "",
+            "15/07/2022",
+            "12:00",
+            0.5,
+            None,
+            Some("cron(0,30 9,14 * * mon-fri) 3"),
+            None,
+        );
+        assert_eq!(
+            ev.get_recurrence().map(|rec| rec.cadence()),
+            Some(&Cadence::Cron(String::from("0,30 9,14 * * mon-fri")))
+        );
+        let at = |d, h, m| -> NaiveDateTime {
+            NaiveDate::from_ymd_opt(2022, 7, d)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+        };
+        let occurrences: Vec<NaiveDateTime> = ev.occurrences().collect();
+        assert_eq!(
+            occurrences,
+            [at(15, 14, 0), at(15, 14, 30), at(18, 9, 0), at(18, 9, 30)]
+        );
+        for rec in [
+            "cron(0 9 * *) 3",
+            "cron(0 9 * * *)",
+            "cron(0 9 * * * 3",
+            "cron(0 9 * * *) 0",
+        ] {
+            let ev = Event::new(
+                "check",
+                "",
+                "15/07/2022",
+                "12:00",
+                0.5,
+                None,
+                Some(rec),
+                None,
+            );
+            assert!(ev.get_recurrence().is_none(), "{}", rec);
+        }
+    }
+
+    /// Central European time in 2022: UTC+2 from 27/03 01:00 UTC to 30/10 01:00 UTC, UTC+1 otherwise
+    #[derive(Debug, Clone, Copy)]
+    struct Cet;
+
+    impl TimeZone for Cet {
+        type Offset = FixedOffset;
+
+        fn from_offset(_offset: &FixedOffset) -> Cet {
+            Cet
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            // good enough away from the transitions
+            LocalResult::Single(self.offset_from_utc_datetime(&(*local - Duration::hours(1))))
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            let at = |m, d| {
+                NaiveDate::from_ymd_opt(2022, m, d)
+                    .unwrap()
+                    .and_hms_opt(1, 0, 0)
+            };
+            let summer = Some(*utc) >= at(3, 27) && Some(*utc) < at(10, 30);
+            FixedOffset::east_opt(if summer { 7200 } else { 3600 }).unwrap()
+        }
+    }
+
+    #[test]
+    /// tests recurrences fixed in local time or in UTC across the DST transitions
+    fn test_anchor() {
+        for (syntax, anchor, formatted) in [
+            ("daily 5", Anchor::Floating, "daily 5"),
+            ("weekly 3 2 floating", Anchor::Floating, "weekly 3 2"),
+            ("daily 5 utc", Anchor::Utc, "daily 5 utc"),
+            (
+                "monthly 2 on last fri UTC",
+                Anchor::Utc,
+                "monthly 2 on -1 Fri utc",
+            ),
+            (
+                "cron(0 9 * * *) 3 utc",
+                Anchor::Utc,
+                "cron(0 9 * * *) 3 utc",
+            ),
+        ] {
+            let ev = Event::new(
+                "standup",
+                "",
+                "25/03/2022",
+                "09:00",
+                1.0,
+                None,
+                Some(syntax),
+                None,
+            );
+            let rec = ev.get_recurrence().unwrap();
+            assert_eq!(rec.anchor(), anchor, "{}", syntax);
+            assert_eq!(rec.syntax(), formatted);
+        }
+        let ev = Event::new(
+            "standup",
+            "",
+            "25/03/2022",
+            "09:00",
+            1.0,
+            None,
+            Some("daily 5"),
+            None,
+        );
+        assert!(ev.occurrences().all(|dt| dt.time() == ev.get_start_time()));
+
+        let at = |d, h| {
+            NaiveDate::from_ymd_opt(2022, 3, d)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+        };
+        let start = at(25, 9);
+        // same offset as the start
+        assert_eq!(anchored_to_utc(&Cet, start, at(26, 9)), at(26, 9));
+        // 08:00 UTC is 10:00 in summer time
+        assert_eq!(anchored_to_utc(&Cet, start, at(28, 9)), at(28, 10));
+    }
+
+    #[test]
+    /// tests occurrences starting at a time relative to the sunset, on every date
+    fn test_sun_time() {
+        let rome: Geo = "41.9028,12.4964".parse().unwrap();
+        for (s, event, offset, formatted) in [
+            ("sunset", SunEvent::Sunset, 0, "sunset"),
+            (
+                "30 min before sunset",
+                SunEvent::Sunset,
+                -30,
+                "30m before sunset",
+            ),
+            (
+                "1h after Sunrise",
+                SunEvent::Sunrise,
+                60,
+                "60m after sunrise",
+            ),
+            (
+                "1h30m before sunrise",
+                SunEvent::Sunrise,
+                -90,
+                "90m before sunrise",
+            ),
+        ] {
+            let sun = SunTime::parse(s, rome).unwrap();
+            assert_eq!((sun.event(), sun.offset()), (event, offset), "{}", s);
+            assert_eq!(sun.to_string(), formatted);
+        }
+        assert!(SunTime::parse("noon", rome).is_err());
+        assert!(SunTime::parse("ten before sunset", rome).is_err());
+
+        let mut ev = Event::new(
+            "aperitivo",
+            "",
+            "01/07/2022",
+            "00:00",
+            2.0,
+            None,
+            Some("weekly 20"),
+            None,
+        );
+        let sun = SunTime::parse("30 min before sunset", rome).unwrap();
+        assert!(ev.set_sun(Some(sun)));
+        assert_eq!(ev.get_sun(), Some(sun));
+        let occurrences: Vec<NaiveDateTime> = ev.occurrences().collect();
+        assert_eq!(occurrences.len(), 21);
+        assert_eq!(occurrences[0], ev.get_start());
+        for dt in occurrences {
+            assert_eq!(dt.weekday(), chrono::Weekday::Fri);
+            let sunset = sun_times(dt.date(), &rome).unwrap().1 - Duration::minutes(30);
+            assert_eq!(dt, Local.from_utc_datetime(&sunset).naive_local());
+        }
+
+        // no occurrences while the sun does not set
+        let svalbard: Geo = "78.2232,15.6267".parse().unwrap();
+        let sun = SunTime::parse("sunset", svalbard).unwrap();
+        assert!(!ev.set_sun(Some(sun)));
+        ev.set_start_date((1, 3, 2022));
+        assert!(ev.set_sun(Some(sun)));
+        let dates: Vec<NaiveDate> = ev.occurrences().map(|dt| dt.date()).collect();
+        assert!(dates.len() < 21);
+        assert!(dates.iter().all(|d| d.month() < 5 || d.month() > 8));
+
+        // at most one occurrence a day
+        ev.set_recurrence("hourly 48");
+        let dates: Vec<NaiveDate> = ev.occurrences().map(|dt| dt.date()).collect();
+        assert_eq!(dates.len(), 3);
+    }
+
+    #[test]
+    /// tests cutting long descriptions on the boundaries of the characters
+    fn test_truncate() {
+        assert_eq!(truncate("short", 50), "short");
+        let long = "è".repeat(60);
+        assert_eq!(truncate(&long, 50), format!("{}...", "è".repeat(49)));
+        // combining accents and emoji sequences are not split
+        let combined = "e\u{301}".repeat(50);
+        assert_eq!(
+            truncate(&combined, 50),
+            format!("{}...", "e\u{301}".repeat(49))
+        );
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        assert_eq!(
+            truncate(&family.repeat(3), 3),
+            format!("{}...", family.repeat(2))
+        );
+
+        let mut ev = Event::new("title", &long, "13/07/2022", "09:30", 1.0, None, None, None);
+        assert!(ev.to_string().ends_with("è..."));
+        assert!(format!("{:#}", ev).ends_with(&long));
+        ev.set_description("ok");
+        assert_eq!(ev.to_string(), format!("{:#}", ev));
+    }
+
+    #[test]
+    /// tests parsing and formatting the lead times of the reminders
+    fn test_lead_time() {
+        for (s, minutes, formatted) in [
+            ("1w", 7 * 24 * 60, "1 week"),
+            ("2 days", 2 * 24 * 60, "2 days"),
+            ("1d12h", 36 * 60, "36 hours"),
+            ("1 hour 30 min", 90, "90 minutes"),
+            ("15m", 15, "15 minutes"),
+        ] {
+            let lead = parse_lead_time(s).unwrap();
+            assert_eq!(lead.num_minutes(), minutes, "{}", s);
+            assert_eq!(format_lead_time(lead), formatted);
+        }
+        // the parts overflow when summed, each one alone does not
+        assert!(parse_lead_time("15000000000w").is_some());
+        for invalid in [
+            "",
+            "0d",
+            "1 fortnight",
+            "d",
+            "-1d",
+            "15000000000w 15000000000w",
+        ] {
+            assert_eq!(parse_lead_time(invalid), None, "{}", invalid);
+        }
+
+        let mut ev = Event::default();
+        ev.set_reminders(vec![
+            Duration::days(1),
+            Duration::zero(),
+            Duration::weeks(1),
+            Duration::days(1),
+        ]);
+        assert_eq!(ev.get_reminders(), [Duration::weeks(1), Duration::days(1)]);
+        let json = serde_json::to_string(&ev).unwrap();
+        assert_eq!(serde_json::from_str::<Event>(&json).unwrap(), ev);
+    }
+
+    #[test]
+    /// tests describing recurrences in words
+    fn test_humanize_recurrence() {
+        let humanize = |date: &str, rec: &str| {
+            Event::new("ev", "", date, "09:00", 1.0, None, Some(rec), None)
+                .humanize_recurrence()
+                .unwrap()
+        };
+        assert_eq!(
+            humanize("11/07/2022", "weekly 9 2"),
+            "every 2 weeks on Mon, 10 times, until 14/11/2022"
+        );
+        assert_eq!(
+            humanize("21/07/2022", "monthly 5 on third thu"),
+            "every month on the third Thu, 6 times, until 15/12/2022"
+        );
+        assert_eq!(
+            humanize("29/07/2022", "monthly 1 on last weekday"),
+            "every month on the last weekday, 2 times, until 31/08/2022"
+        );
+        assert_eq!(
+            humanize("13/07/2022", "yearly 2"),
+            "every year on 13 Jul, 3 times, until 13/07/2024"
+        );
+        assert_eq!(
+            humanize("13/07/2022", "daily 1 utc"),
+            "every day, 2 times, until 14/07/2022, at the same UTC time"
+        );
+        assert!(humanize("13/07/2022", "cron(0 9 * * 1-5) 4")
+            .starts_with("on the schedule 0 9 * * 1-5, 5 times, until "));
+        assert!(
+            Event::new("ev", "", "13/07/2022", "09:00", 1.0, None, None, None)
+                .humanize_recurrence()
+                .is_none()
+        );
+    }
 }
@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use chrono::{Datelike, Duration, Local, NaiveDateTime, TimeZone, Timelike, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::calendar::Calendar;
+use crate::calendar_error::CalendarError;
+use crate::clock;
+use crate::config::ExchangeConfig;
+use crate::event::{Class, Event, Geo, Transparency};
+use crate::http::Client;
+use crate::storage::calendar_path;
+use crate::sync::{self, Change, Conflict};
+
+/// Environment variable holding the access token, if it is not in the configuration
+pub const TOKEN_VAR: &str = "CALENDA_RS_GRAPH_TOKEN";
+
+/// State of the synchronization of a calendar with Exchange, stored next to the calendar:
+/// the delta link returned by the last pull fetches only the changes made since then
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncState {
+    /// Id of the Exchange calendar pulled (the default one if missing)
+    pub source: Option<String>,
+    pub delta_link: Option<String>,
+    /// The events as last pulled, to tell the local changes from the remote ones
+    #[serde(default)]
+    pub base: HashMap<u64, Event>,
+}
+
+/// What a pull changed in the calendar
+#[derive(Debug, Default, PartialEq)]
+pub struct Pulled {
+    /// Number of events added or updated
+    pub updated: usize,
+    pub removed: usize,
+    /// Events changed both locally and remotely, left as they are
+    pub conflicts: Vec<Conflict>,
+}
+
+fn state_path(id: &str, data_dir: &Path) -> Result<std::path::PathBuf, CalendarError> {
+    Ok(calendar_path(id, data_dir)?.with_extension("exchange"))
+}
+
+/// Reads the synchronization state of the calendar with the given id: the default one if
+/// it has never been pulled
+pub fn load_state(id: &str, data_dir: &Path) -> Result<SyncState, CalendarError> {
+    let path = state_path(id, data_dir)?;
+    if !path.exists() {
+        return Ok(SyncState::default());
+    }
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))?;
+    serde_json::from_str(&contents)
+        .map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))
+}
+
+pub fn save_state(id: &str, state: &SyncState, data_dir: &Path) -> Result<(), CalendarError> {
+    let path = state_path(id, data_dir)?;
+    let contents =
+        serde_json::to_string_pretty(state).map_err(|e| CalendarError::Unknown(e.to_string()))?;
+    fs::write(&path, contents)
+        .map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))
+}
+
+/// Returns the eid of the local copy of an Exchange event, derived from its id so that
+/// later changes to the event replace the copy
+pub fn eid(graph_id: &str) -> u64 {
+    let hash = Sha256::new()
+        .chain_update("exchange:")
+        .chain_update(graph_id)
+        .finalize();
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&hash[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+/// Parses a dateTimeTimeZone: times in UTC (as requested) are converted to local time
+fn graph_time(v: &Value) -> Option<NaiveDateTime> {
+    let dt = v.get("dateTime")?.as_str()?;
+    // the fractional seconds have 7 digits
+    let dt = NaiveDateTime::parse_from_str(dt.split('.').next()?, "%Y-%m-%dT%H:%M:%S").ok()?;
+    match v.get("timeZone").and_then(Value::as_str) {
+        Some("UTC") | None => Some(
+            Utc.from_utc_datetime(&dt)
+                .with_timezone(&Local)
+                .naive_local(),
+        ),
+        Some(_) => Some(dt),
+    }
+}
+
+/// Converts an event of the Microsoft Graph API to a local event, if it is valid
+pub fn event_from_graph(item: &Value) -> Option<Event> {
+    let text = |key: &str| item.get(key).and_then(Value::as_str).unwrap_or_default();
+    let start = graph_time(item.get("start")?)?;
+    let end = graph_time(item.get("end")?)?;
+    let mut ev = Event::default();
+    ev.set_title(text("subject"));
+    ev.set_description(text("bodyPreview"));
+    ev.set_start_date((start.day(), start.month(), start.year()));
+    ev.set_start_time((start.hour(), start.minute(), start.second()));
+    ev.set_duration(&(end - start).max(Duration::zero()));
+    if let Some(location) = item.get("location") {
+        let name = location.get("displayName").and_then(Value::as_str);
+        ev.set_location(name.unwrap_or_default());
+        let coordinates = location.get("coordinates");
+        let coordinate = |key| coordinates?.get(key)?.as_f64();
+        if let (Some(lat), Some(lon)) = (coordinate("latitude"), coordinate("longitude")) {
+            ev.set_geo(Geo::new(lat, lon));
+        }
+    }
+    if text("showAs") == "free" {
+        ev.set_transparency(Transparency::Free);
+    }
+    if let Ok(class) = text("sensitivity").parse::<Class>() {
+        ev.set_class(class);
+    }
+    let tags: Vec<String> = item
+        .get("categories")
+        .and_then(Value::as_array)
+        .map(|c| {
+            c.iter()
+                .filter_map(|t| t.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default();
+    ev.set_tags(tags);
+    Some(ev)
+}
+
+/// Applies a page of changes returned by the delta query at `time` to the calendar. The
+/// events also changed locally since the last pull are reported as conflicts
+pub fn apply_page(
+    cal: &mut Calendar,
+    page: &Value,
+    base: &mut HashMap<u64, Event>,
+    time: NaiveDateTime,
+) -> Pulled {
+    let mut pulled = Pulled::default();
+    let items = page.get("value").and_then(Value::as_array);
+    for item in items.into_iter().flatten() {
+        let id = match item.get("id").and_then(Value::as_str) {
+            Some(id) => eid(id),
+            None => continue,
+        };
+        let cancelled = item.get("isCancelled").and_then(Value::as_bool) == Some(true);
+        if item.get("@removed").is_some() || cancelled {
+            base.remove(&id);
+            if cal.remove_event(id).is_ok() {
+                pulled.removed += 1;
+            }
+        } else if let Some(ev) = event_from_graph(item) {
+            let change = sync::reconcile(id, base.get(&id), cal.peek_event(id), ev.clone(), time);
+            base.insert(id, ev);
+            match change {
+                Change::Apply(ev) => {
+                    cal.insert_event(id, ev);
+                    pulled.updated += 1;
+                }
+                Change::KeepLocal => (),
+                Change::Conflict(conflict) => pulled.conflicts.push(*conflict),
+            }
+        }
+    }
+    pulled
+}
+
+/// Sends a GET request to the Microsoft Graph API, returning the JSON response
+fn get(client: &mut Client, url: &str, token: &str) -> Result<Value, CalendarError> {
+    let auth = format!("Bearer {}", token);
+    let headers = [
+        ("Authorization", auth.as_str()),
+        ("Prefer", "outlook.timezone=\"UTC\""),
+    ];
+    let body = client.get(url, &headers)?;
+    serde_json::from_str(&body).map_err(|e| CalendarError::Unknown(format!("GET {}: {}", url, e)))
+}
+
+/// Pulls the events of the Exchange calendar `source` (the default one if missing) into the
+/// calendar: only the changes since the last pull are fetched, unless `full` or the source
+/// changed. The events changed both locally and remotely are left as they are, and returned
+/// as conflicts
+pub fn pull(
+    cal: &mut Calendar,
+    config: &ExchangeConfig,
+    source: Option<&str>,
+    full: bool,
+    client: &mut Client,
+    data_dir: &Path,
+) -> Result<Pulled, CalendarError> {
+    let token = config
+        .token
+        .clone()
+        .or_else(|| std::env::var(TOKEN_VAR).ok())
+        .ok_or_else(|| {
+            CalendarError::InvalidConfig(format!("no Exchange access token (set {})", TOKEN_VAR))
+        })?;
+    let mut state = load_state(cal.get_id(), data_dir)?;
+    let resume = state
+        .delta_link
+        .clone()
+        .filter(|_| !full && state.source.as_deref() == source);
+    let mut url = match resume {
+        Some(link) => link,
+        None => {
+            let now = clock::now().naive_utc();
+            let calendar = source.map_or(String::new(), |id| format!("/calendars/{}", id));
+            format!(
+                "{}/me{}/calendarView/delta?startDateTime={}Z&endDateTime={}Z",
+                config.base_url.trim_end_matches('/'),
+                calendar,
+                (now - Duration::days(config.past_days.into())).format("%Y-%m-%dT%H:%M:%S"),
+                (now + Duration::days(config.future_days.into())).format("%Y-%m-%dT%H:%M:%S"),
+            )
+        }
+    };
+    let now = clock::now().naive_local();
+    let mut pulled = Pulled::default();
+    loop {
+        let page = get(client, &url, &token)?;
+        let mut p = apply_page(cal, &page, &mut state.base, now);
+        pulled.updated += p.updated;
+        pulled.removed += p.removed;
+        pulled.conflicts.append(&mut p.conflicts);
+        let link = |key: &str| page.get(key).and_then(Value::as_str).map(String::from);
+        if let Some(next) = link("@odata.nextLink") {
+            url = next;
+        } else {
+            state.source = source.map(String::from);
+            state.delta_link = link("@odata.deltaLink");
+            break;
+        }
+    }
+    save_state(cal.get_id(), &state, data_dir)?;
+    info!(
+        "Pulled {} events from Exchange into {} ({} removed, {} conflicts)",
+        pulled.updated,
+        cal.get_id(),
+        pulled.removed,
+        pulled.conflicts.len()
+    );
+    Ok(pulled)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{Local, NaiveDate, TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::calendar::Calendar;
+    use crate::event::{Class, Transparency};
+    use crate::exchange::{apply_page, eid};
+
+    #[test]
+    /// tests applying the changes returned by a delta query
+    fn test_apply_page() {
+        let mut cal = Calendar::new("owner", "work");
+        let mut base = HashMap::new();
+        let now = NaiveDate::from_ymd_opt(2022, 7, 14)
+            .unwrap()
+            .and_hms_opt(8, 0, 0)
+            .unwrap();
+        let page = json!({
+            "value": [
+                {
+                    "id": "AAMk1",
+                    "subject": "review",
+                    "bodyPreview": "quarterly",
+                    "start": {"dateTime": "2022-07-13T09:30:00.0000000", "timeZone": "UTC"},
+                    "end": {"dateTime": "2022-07-13T11:00:00.0000000", "timeZone": "UTC"},
+                    "location": {
+                        "displayName": "room 1",
+                        "coordinates": {"latitude": 45.4642, "longitude": 9.19}
+                    },
+                    "showAs": "busy",
+                    "sensitivity": "private",
+                    "categories": ["work"]
+                },
+                {
+                    "id": "AAMk2",
+                    "subject": "lunch",
+                    "start": {"dateTime": "2022-07-13T12:00:00.0000000", "timeZone": "UTC"},
+                    "end": {"dateTime": "2022-07-13T13:00:00.0000000", "timeZone": "UTC"},
+                    "showAs": "free"
+                },
+                {"id": "AAMk3", "subject": "no times"}
+            ],
+            "@odata.deltaLink": "https://graph.microsoft.com/v1.0/me/calendarView/delta?$deltatoken=x"
+        });
+        let pulled = apply_page(&mut cal, &page, &mut base, now);
+        assert_eq!((pulled.updated, pulled.removed), (2, 0));
+        assert_eq!(base.len(), 2);
+        assert_eq!(cal.get_size(), 2);
+        let ev = cal.peek_event(eid("AAMk1")).unwrap();
+        let start = NaiveDate::from_ymd_opt(2022, 7, 13)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap();
+        let local = Utc.from_utc_datetime(&start).with_timezone(&Local);
+        assert_eq!(ev.get_start(), local.naive_local());
+        assert_eq!(ev.get_duration(), 5400);
+        assert_eq!(ev.get_location(), "room 1");
+        assert!(ev.get_geo().is_some());
+        assert_eq!(ev.get_class(), Class::Private);
+        assert!(ev.has_tag("work"));
+        let lunch = cal.peek_event(eid("AAMk2")).unwrap();
+        assert_eq!(lunch.get_transparency(), Transparency::Free);
+
+        // the next delta updates, removes and cancels events
+        let page = json!({
+            "value": [
+                {
+                    "id": "AAMk1",
+                    "subject": "retro",
+                    "start": {"dateTime": "2022-07-13T09:30:00.0000000", "timeZone": "UTC"},
+                    "end": {"dateTime": "2022-07-13T10:00:00.0000000", "timeZone": "UTC"}
+                },
+                {"id": "AAMk2", "@removed": {"reason": "deleted"}},
+                {"id": "AAMk4", "@removed": {"reason": "deleted"}}
+            ]
+        });
+        let pulled = apply_page(&mut cal, &page, &mut base, now);
+        assert_eq!((pulled.updated, pulled.removed), (1, 1));
+        assert_eq!(cal.get_size(), 1);
+        assert_eq!(cal.peek_event(eid("AAMk1")).unwrap().get_title(), "retro");
+        assert_ne!(eid("AAMk1"), eid("AAMk2"));
+
+        // an event changed both locally and remotely is left as it is
+        cal.update_event(eid("AAMk1"), |ev| ev.set_location("room 2"))
+            .unwrap();
+        let page = json!({
+            "value": [{
+                "id": "AAMk1",
+                "subject": "retrospective",
+                "start": {"dateTime": "2022-07-13T09:30:00.0000000", "timeZone": "UTC"},
+                "end": {"dateTime": "2022-07-13T10:00:00.0000000", "timeZone": "UTC"}
+            }]
+        });
+        let pulled = apply_page(&mut cal, &page, &mut base, now);
+        assert_eq!(pulled.updated, 0);
+        assert_eq!(pulled.conflicts.len(), 1);
+        assert_eq!(pulled.conflicts[0].remote.get_title(), "retrospective");
+        assert_eq!(cal.peek_event(eid("AAMk1")).unwrap().get_title(), "retro");
+        assert_eq!(base[&eid("AAMk1")].get_title(), "retrospective");
+    }
+}
@@ -0,0 +1,423 @@
+//! C ABI of the library core, to embed it in other languages (e.g. in Python through ctypes).
+//! Build the shared library with
+//! `cargo rustc --lib --release --no-default-features --features ffi --crate-type cdylib`;
+//! the declarations are in `include/calendar.h`.
+//!
+//! The functions never unwind into the caller: they return a status (or a null pointer), and
+//! `calendar_last_error` describes the last error on the thread. Strings are UTF-8 and
+//! NUL-terminated; the strings returned are freed with `calendar_string_free`, the calendars
+//! with `calendar_free`.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use serde_json::Value;
+
+use crate::calendar::Calendar;
+use crate::calendar_error::CalendarError;
+use crate::event::{self, Event};
+
+/// Outcome of a call
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalendarStatus {
+    Ok = 0,
+    /// A null pointer, a string that is not UTF-8 or a value that cannot be parsed
+    InvalidArgument = 1,
+    NotFound = 2,
+    ParsingFailed = 3,
+    Failed = 4,
+    /// A bug in the library: the objects passed to the call should not be used again
+    Panicked = 5,
+}
+
+/// An error, as reported to the caller
+struct Failure(CalendarStatus, String);
+
+impl From<CalendarError> for Failure {
+    fn from(e: CalendarError) -> Failure {
+        let status = match e {
+            CalendarError::CalendarNotFound(_) | CalendarError::EventNotFound(_) => {
+                CalendarStatus::NotFound
+            }
+            CalendarError::IcsParsingFailed(_) => CalendarStatus::ParsingFailed,
+            CalendarError::InvalidCalendarName(_)
+            | CalendarError::InvalidConfig(_)
+            | CalendarError::InvalidRecurrence(_) => CalendarStatus::InvalidArgument,
+            CalendarError::CalendarAlreadyExists(_) | CalendarError::Unknown(_) => {
+                CalendarStatus::Failed
+            }
+        };
+        Failure(status, format!("{:?}", e))
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> Failure {
+    Failure(CalendarStatus::InvalidArgument, msg.into())
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: &str) {
+    // the message is cut at the first NUL, if any
+    let msg = msg.split('\0').next().unwrap_or_default();
+    let msg = CString::new(msg).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+/// Calls `f`, turning its errors and panics into a status and the last error
+fn guard(f: impl FnOnce() -> Result<(), Failure>) -> CalendarStatus {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => CalendarStatus::Ok,
+        Ok(Err(Failure(status, msg))) => {
+            set_last_error(&msg);
+            status
+        }
+        Err(_) => {
+            set_last_error("the library panicked");
+            CalendarStatus::Panicked
+        }
+    }
+}
+
+/// Calls `f`, returning the object it builds, or null on errors and panics
+fn guard_ptr<T>(f: impl FnOnce() -> Result<T, Failure>) -> *mut T {
+    let mut built = None;
+    let status = guard(|| {
+        built = Some(f()?);
+        Ok(())
+    });
+    match (status, built) {
+        (CalendarStatus::Ok, Some(built)) => Box::into_raw(Box::new(built)),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Reads a string argument
+///
+/// # Safety
+/// `s` is null or a NUL-terminated string, valid for `'a`
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str, Failure> {
+    opt_str_arg(s, name)?.ok_or_else(|| invalid(format!("{} is null", name)))
+}
+
+/// Reads an optional string argument, None if null
+///
+/// # Safety
+/// `s` is null or a NUL-terminated string, valid for `'a`
+unsafe fn opt_str_arg<'a>(s: *const c_char, name: &str) -> Result<Option<&'a str>, Failure> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map(Some)
+        .map_err(|_| invalid(format!("{} is not UTF-8", name)))
+}
+
+/// Reads a date-time argument in the ISO 8601 format (e.g. 2022-07-13T09:30:00)
+///
+/// # Safety
+/// `s` is null or a NUL-terminated string
+unsafe fn time_arg(s: *const c_char, name: &str) -> Result<Option<NaiveDateTime>, Failure> {
+    opt_str_arg(s, name)?
+        .map(|t| {
+            t.parse()
+                .map_err(|_| invalid(format!("{} is not a date and time: {}", name, t)))
+        })
+        .transpose()
+}
+
+/// # Safety
+/// `cal` is null or was returned by this library and not freed
+unsafe fn cal_arg<'a>(cal: *mut Calendar) -> Result<&'a mut Calendar, Failure> {
+    cal.as_mut().ok_or_else(|| invalid("the calendar is null"))
+}
+
+fn string_out(s: String) -> Result<*mut c_char, Failure> {
+    CString::new(s)
+        .map(CString::into_raw)
+        .map_err(|_| Failure(CalendarStatus::Failed, String::from("NUL in the output")))
+}
+
+/// Returns the message of the last error on the calling thread, or null if there was none.
+/// The message is valid until the next call on the thread
+#[no_mangle]
+pub extern "C" fn calendar_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |msg| msg.as_ptr()))
+}
+
+/// Creates an empty calendar, or returns null on errors
+///
+/// # Safety
+/// `owner` and `name` are NUL-terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn calendar_new(owner: *const c_char, name: *const c_char) -> *mut Calendar {
+    guard_ptr(|| {
+        Ok(Calendar::new(
+            str_arg(owner, "owner")?,
+            str_arg(name, "name")?,
+        ))
+    })
+}
+
+/// Reads a calendar from its JSON representation (the format the calendars are stored in),
+/// or returns null on errors
+///
+/// # Safety
+/// `json` is a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn calendar_from_json(json: *const c_char) -> *mut Calendar {
+    guard_ptr(|| {
+        let mut cal: Calendar = serde_json::from_str(str_arg(json, "json")?)
+            .map_err(|e| Failure(CalendarStatus::ParsingFailed, e.to_string()))?;
+        cal.migrate();
+        Ok(cal)
+    })
+}
+
+/// Returns the JSON representation of the calendar, or null on errors
+///
+/// # Safety
+/// `cal` was returned by this library and not freed
+#[no_mangle]
+pub unsafe extern "C" fn calendar_to_json(cal: *mut Calendar) -> *mut c_char {
+    let mut out = ptr::null_mut();
+    guard(|| {
+        let json = serde_json::to_string(cal_arg(cal)?)
+            .map_err(|e| Failure(CalendarStatus::Failed, e.to_string()))?;
+        out = string_out(json)?;
+        Ok(())
+    });
+    out
+}
+
+/// Frees a calendar
+///
+/// # Safety
+/// `cal` is null or was returned by this library and not freed
+#[no_mangle]
+pub unsafe extern "C" fn calendar_free(cal: *mut Calendar) {
+    if !cal.is_null() {
+        drop(Box::from_raw(cal));
+    }
+}
+
+/// Frees a string returned by this library
+///
+/// # Safety
+/// `s` is null or was returned by this library and not freed
+#[no_mangle]
+pub unsafe extern "C" fn calendar_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Adds an event starting on `start_date` (%d/%m/%Y or %Y-%m-%d) at `start_time` (%H:%M),
+/// lasting the given hours. `location` and `recurrence` (e.g. "weekly 10") may be null.
+/// The eid of the event is written to `eid`: an event equal to one already in the calendar
+/// is not added again, and the eid of the latter is written
+///
+/// # Safety
+/// `cal` was returned by this library and not freed, the strings are null or NUL-terminated
+/// and `eid` is null or points to writable memory
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn calendar_add_event(
+    cal: *mut Calendar,
+    title: *const c_char,
+    description: *const c_char,
+    start_date: *const c_char,
+    start_time: *const c_char,
+    hours: f64,
+    location: *const c_char,
+    recurrence: *const c_char,
+    eid: *mut u64,
+) -> CalendarStatus {
+    guard(|| {
+        let cal = cal_arg(cal)?;
+        let date = str_arg(start_date, "start_date")?;
+        if ["%d/%m/%Y", "%Y-%m-%d"]
+            .iter()
+            .all(|fmt| NaiveDate::parse_from_str(date, fmt).is_err())
+        {
+            return Err(invalid(format!("invalid start date {}", date)));
+        }
+        let time = str_arg(start_time, "start_time")?;
+        if NaiveTime::parse_from_str(time, "%H:%M").is_err() {
+            return Err(invalid(format!("invalid start time {}", time)));
+        }
+        let duration = event::duration_from_hours(hours)
+            .ok_or_else(|| invalid(format!("invalid duration {}", hours)))?;
+        let rec = opt_str_arg(recurrence, "recurrence")?;
+        let mut ev = Event::new(
+            str_arg(title, "title")?,
+            opt_str_arg(description, "description")?.unwrap_or_default(),
+            date,
+            time,
+            0.0,
+            opt_str_arg(location, "location")?,
+            rec,
+            None,
+        );
+        if let (Some(rec), None) = (rec, ev.get_recurrence()) {
+            return Err(invalid(format!("invalid recurrence {}", rec)));
+        }
+        ev.set_duration(&duration);
+        let added = cal
+            .add_event(ev.clone())
+            .added
+            .or_else(|| cal.eid_of(&ev))
+            .ok_or_else(|| Failure(CalendarStatus::Failed, String::from("event not added")))?;
+        if let Some(eid) = eid.as_mut() {
+            *eid = added;
+        }
+        Ok(())
+    })
+}
+
+/// Removes the event with the given eid
+///
+/// # Safety
+/// `cal` was returned by this library and not freed
+#[no_mangle]
+pub unsafe extern "C" fn calendar_remove_event(cal: *mut Calendar, eid: u64) -> CalendarStatus {
+    guard(|| {
+        cal_arg(cal)?.remove_event(eid)?;
+        Ok(())
+    })
+}
+
+/// Writes to `out` a JSON array of the occurrences of the events between `from` and `until`
+/// (in the ISO 8601 format, e.g. 2022-07-13T09:30:00; either may be null), each one with
+/// the eid of its event. The array is freed with `calendar_string_free`
+///
+/// # Safety
+/// `cal` was returned by this library and not freed, `from` and `until` are null or
+/// NUL-terminated and `out` points to writable memory
+#[no_mangle]
+pub unsafe extern "C" fn calendar_events_between(
+    cal: *mut Calendar,
+    from: *const c_char,
+    until: *const c_char,
+    out: *mut *mut c_char,
+) -> CalendarStatus {
+    guard(|| {
+        let cal = cal_arg(cal)?;
+        let out = out.as_mut().ok_or_else(|| invalid("out is null"))?;
+        let (from, until) = (time_arg(from, "from")?, time_arg(until, "until")?);
+        let events: Vec<Value> = cal
+            .list_occurrences_between(from, until)
+            .iter()
+            .map(|(eid, ev)| {
+                let mut value = serde_json::to_value(ev).unwrap_or(Value::Null);
+                if let Value::Object(fields) = &mut value {
+                    fields.insert(String::from("eid"), (*eid).into());
+                }
+                value
+            })
+            .collect();
+        *out = string_out(Value::Array(events).to_string())?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+
+    use serde_json::Value;
+
+    use crate::ffi::{
+        calendar_add_event, calendar_events_between, calendar_free, calendar_from_json,
+        calendar_last_error, calendar_new, calendar_remove_event, calendar_string_free,
+        calendar_to_json, CalendarStatus,
+    };
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    #[test]
+    /// tests creating a calendar, adding events and querying them through the C ABI
+    fn test_ffi() {
+        unsafe {
+            let cal = calendar_new(c("owner").as_ptr(), c("work").as_ptr());
+            assert!(!cal.is_null());
+            let mut eid = 0;
+            let status = calendar_add_event(
+                cal,
+                c("standup").as_ptr(),
+                ptr::null(),
+                c("11/07/2022").as_ptr(),
+                c("09:00").as_ptr(),
+                0.25,
+                c("room 1").as_ptr(),
+                c("daily 4").as_ptr(),
+                &mut eid,
+            );
+            assert_eq!(status, CalendarStatus::Ok);
+            let bad_date = calendar_add_event(
+                cal,
+                c("review").as_ptr(),
+                ptr::null(),
+                c("31/02/2022").as_ptr(),
+                c("09:00").as_ptr(),
+                1.0,
+                ptr::null(),
+                ptr::null(),
+                ptr::null_mut(),
+            );
+            assert_eq!(bad_date, CalendarStatus::InvalidArgument);
+            let err = CStr::from_ptr(calendar_last_error()).to_str().unwrap();
+            assert!(err.contains("31/02/2022"));
+            let too_long = calendar_add_event(
+                cal,
+                c("review").as_ptr(),
+                ptr::null(),
+                c("12/07/2022").as_ptr(),
+                c("09:00").as_ptr(),
+                1e15,
+                ptr::null(),
+                ptr::null(),
+                ptr::null_mut(),
+            );
+            assert_eq!(too_long, CalendarStatus::InvalidArgument);
+
+            let mut out = ptr::null_mut();
+            let from = c("2022-07-12T00:00:00");
+            let status = calendar_events_between(cal, from.as_ptr(), ptr::null(), &mut out);
+            assert_eq!(status, CalendarStatus::Ok);
+            let events: Value =
+                serde_json::from_str(CStr::from_ptr(out).to_str().unwrap()).unwrap();
+            calendar_string_free(out);
+            let events = events.as_array().unwrap();
+            assert_eq!(events.len(), 4);
+            assert_eq!(events[0]["eid"], eid);
+            assert_eq!(events[0]["title"], "standup");
+            assert_eq!(events[0]["duration"], 900);
+
+            let json = calendar_to_json(cal);
+            let copy = calendar_from_json(json);
+            calendar_string_free(json);
+            assert!(!copy.is_null());
+            assert_eq!(*copy, *cal);
+            assert!(calendar_from_json(c("{").as_ptr()).is_null());
+
+            assert_eq!(calendar_remove_event(cal, eid), CalendarStatus::Ok);
+            assert_eq!(calendar_remove_event(cal, eid), CalendarStatus::NotFound);
+            let status =
+                calendar_events_between(ptr::null_mut(), ptr::null(), ptr::null(), &mut out);
+            assert_eq!(status, CalendarStatus::InvalidArgument);
+            calendar_free(cal);
+            calendar_free(copy);
+        }
+    }
+}
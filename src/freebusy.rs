@@ -0,0 +1,244 @@
+use chrono::{Local, NaiveDateTime, TimeZone, Utc};
+
+use crate::calendar::Calendar;
+use crate::clock;
+use crate::event::Transparency;
+use crate::ics;
+
+/// Kind of the periods of a VFREEBUSY component
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FbType {
+    Busy,
+    Free,
+}
+
+/// Merges the overlapping (or adjacent) intervals, returning them sorted
+pub fn merge(
+    mut intervals: Vec<(NaiveDateTime, NaiveDateTime)>,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    intervals.sort_unstable();
+    let mut merged: Vec<(NaiveDateTime, NaiveDateTime)> = Vec::new();
+    for (start, end) in intervals {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Computes the intervals of time between `from` and `until` taken by the busy events
+/// of the calendar, merged and clipped to the period
+pub fn busy_intervals(
+    cal: &Calendar,
+    from: NaiveDateTime,
+    until: NaiveDateTime,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let busy = cal
+        .list_events_between(None, Some(until))
+        .iter()
+        .filter(|ev| ev.get_transparency() == Transparency::Busy)
+        .map(|ev| (ev.get_start(), ev.occurrence_end(ev.get_start())))
+        .filter(|(start, end)| *end > from && *start < until)
+        .map(|(start, end)| (start.max(from), end.min(until)))
+        .collect();
+    merge(busy)
+}
+
+/// Formats a local date and time as an iCalendar UTC date-time
+fn ics_utc(dt: NaiveDateTime) -> String {
+    let utc = match Local.from_local_datetime(&dt).earliest() {
+        Some(local) => local.with_timezone(&Utc).naive_utc(),
+        // skipped by a DST change: not a valid local time
+        None => dt,
+    };
+    utc.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Parses an iCalendar date-time: UTC ones are converted to local time
+fn parse_ics_time(s: &str) -> Option<NaiveDateTime> {
+    match s.strip_suffix('Z') {
+        Some(utc) => NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|dt| {
+                Utc.from_utc_datetime(&dt)
+                    .with_timezone(&Local)
+                    .naive_local()
+            }),
+        None => NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%S").ok(),
+    }
+}
+
+/// Reads the busy periods (as local times) in the VFREEBUSY components of an iCalendar
+/// document, such as the ones exported by other people. Periods are either "start/end"
+/// or "start/duration"
+pub fn parse_vfreebusy(text: &str) -> Result<Vec<(NaiveDateTime, NaiveDateTime)>, String> {
+    let unfolded = icalendar::parser::unfold(text);
+    let cal = icalendar::parser::read_calendar(&unfolded).map_err(|e| e.to_string())?;
+    let mut busy = Vec::new();
+    for comp in cal.components.iter().filter(|c| c.name == "VFREEBUSY") {
+        for prop in comp.properties.iter().filter(|p| p.name == "FREEBUSY") {
+            let fbtype = prop
+                .params
+                .iter()
+                .find(|p| p.key == "FBTYPE")
+                .and_then(|p| p.val.as_ref())
+                .map_or("BUSY", |v| v.as_str());
+            if fbtype == "FREE" {
+                continue;
+            }
+            for period in prop.val.as_str().split(',') {
+                let parsed = period.split_once('/').and_then(|(start, end)| {
+                    let start = parse_ics_time(start)?;
+                    let end = match end.starts_with(['P', '+']) {
+                        true => start + ics::parse_duration(end)?,
+                        false => parse_ics_time(end)?,
+                    };
+                    Some((start, end))
+                });
+                busy.push(parsed.ok_or_else(|| format!("invalid period {}", period))?);
+            }
+        }
+    }
+    Ok(merge(busy))
+}
+
+/// Generates an iCalendar document with a VFREEBUSY component, listing the periods of the
+/// given type (as local times) between `from` and `until`: no details of the events are included
+pub fn vfreebusy(
+    organizer: &str,
+    from: NaiveDateTime,
+    until: NaiveDateTime,
+    periods: &[(NaiveDateTime, NaiveDateTime)],
+    fbtype: FbType,
+) -> String {
+    let mut lines = vec![
+        String::from("BEGIN:VFREEBUSY"),
+        format!(
+            "DTSTAMP:{}",
+            clock::now().with_timezone(&Utc).format("%Y%m%dT%H%M%SZ")
+        ),
+        format!("DTSTART:{}", ics_utc(from)),
+        format!("DTEND:{}", ics_utc(until)),
+    ];
+    if !organizer.is_empty() {
+        lines.push(format!(
+            "ORGANIZER;CN={}:",
+            organizer.replace([';', ':', ','], " ")
+        ));
+    }
+    let fbtype = match fbtype {
+        FbType::Busy => "BUSY",
+        FbType::Free => "FREE",
+    };
+    for (start, end) in periods {
+        lines.push(format!(
+            "FREEBUSY;FBTYPE={}:{}/{}",
+            fbtype,
+            ics_utc(*start),
+            ics_utc(*end)
+        ));
+    }
+    lines.push(String::from("END:VFREEBUSY"));
+    ics::vcalendar(vec![lines])
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::calendar::Calendar;
+    use crate::event::{Event, Transparency};
+    use crate::freebusy::{busy_intervals, ics_utc, parse_vfreebusy, vfreebusy, FbType};
+    use crate::testing::at;
+
+    #[test]
+    /// tests computing the busy intervals of a calendar
+    fn test_busy_intervals() {
+        let mut cal = Calendar::new("owner", "work");
+        cal.add_event(Event::new(
+            "standup",
+            "",
+            "11/07/2022",
+            "09:00",
+            1.0,
+            None,
+            Some("daily 4"),
+            None,
+        ));
+        cal.add_event(Event::new(
+            "review",
+            "",
+            "12/07/2022",
+            "09:30",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        let mut holiday = Event::new("holiday", "", "13/07/2022", "00:00", 24.0, None, None, None);
+        holiday.set_transparency(Transparency::Free);
+        cal.add_event(holiday);
+        assert_eq!(
+            busy_intervals(&cal, at(11, 9, 30), at(13, 12, 0)),
+            [
+                (at(11, 9, 30), at(11, 10, 0)),
+                (at(12, 9, 0), at(12, 10, 30)),
+                (at(13, 9, 0), at(13, 10, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    /// tests generating VFREEBUSY components
+    fn test_vfreebusy() {
+        let day = NaiveDate::from_ymd_opt(2022, 7, 13).unwrap();
+        let at = |h| day.and_hms_opt(h, 0, 0).unwrap();
+        let ics = vfreebusy(
+            "owner",
+            at(0),
+            at(23),
+            &[(at(9), at(10)), (at(14), at(15))],
+            FbType::Busy,
+        );
+        let lines: Vec<&str> = ics.split_terminator("\r\n").collect();
+        assert_eq!(lines.first(), Some(&"BEGIN:VCALENDAR"));
+        assert_eq!(lines.last(), Some(&"END:VCALENDAR"));
+        assert!(lines.contains(&"ORGANIZER;CN=owner:"));
+        let periods: Vec<&&str> = lines
+            .iter()
+            .filter(|l| l.starts_with("FREEBUSY;FBTYPE=BUSY:"))
+            .collect();
+        assert_eq!(periods.len(), 2);
+        assert_eq!(
+            *periods[0],
+            format!(
+                "FREEBUSY;FBTYPE=BUSY:{}/{}",
+                ics_utc(at(9)),
+                ics_utc(at(10))
+            )
+        );
+        // the result can be read back
+        assert!(icalendar::parser::read_calendar(&ics).is_ok());
+        assert_eq!(
+            parse_vfreebusy(&ics).unwrap(),
+            [(at(9), at(10)), (at(14), at(15))]
+        );
+        let other = [
+            "BEGIN:VCALENDAR",
+            "BEGIN:VFREEBUSY",
+            "FREEBUSY:20220713T090000/PT1H30M,20220713T100000/20220713T110000",
+            "FREEBUSY;FBTYPE=FREE:20220713T120000/PT1H",
+            "END:VFREEBUSY",
+            "END:VCALENDAR",
+        ];
+        assert_eq!(
+            parse_vfreebusy(&other.join("\r\n")).unwrap(),
+            [(at(9), at(11))]
+        );
+        assert!(parse_vfreebusy(
+            "BEGIN:VCALENDAR\r\nBEGIN:VFREEBUSY\r\nFREEBUSY:x/y\r\nEND:VFREEBUSY\r\nEND:VCALENDAR"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,75 @@
+//! Entry points of the fuzz targets in `fuzz/`, taking arbitrary bytes: each one feeds them to
+//! a parser and to what uses its results, which must return errors on invalid input instead of
+//! panicking. Run the targets with `cargo +nightly fuzz run <target>` (see `fuzz/Cargo.toml`).
+
+use std::env;
+
+use crate::calendar::Calendar;
+use crate::cli;
+use crate::event::Event;
+
+/// How many occurrences of the parsed recurrences are computed, bounding the time of a run
+const OCCURRENCES: usize = 1000;
+
+/// Parses the input as an iCalendar document, as `add --from-file` does, and adds its events
+/// to a calendar. The attachments are not stored
+pub fn ics(data: &[u8]) {
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(imported) = cli::parse_ics(input, None) else {
+        return;
+    };
+    let mut cal = Calendar::new("owner", "fuzz");
+    for ev in imported {
+        recurrence_of(&ev.event);
+        cal.add_event(ev.event);
+    }
+    let _ = cal.list_events_between(None, None);
+}
+
+/// Parses the input as a recurrence (e.g. "monthly 5 2 on last fri"), and computes the first
+/// occurrences of an event repeating with it
+pub fn recurrence(data: &[u8]) {
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+    let mut ev = Event::new("fuzz", "", "13/07/2022", "09:30", 1.0, None, None, None);
+    ev.set_recurrence(input);
+    recurrence_of(&ev);
+}
+
+/// Parses the input as a line of the shell (e.g. `add "lunch" "" 13/07/2022 12:30 1`), and
+/// adds the event if it is a valid add command
+pub fn command(data: &[u8]) {
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(words) = cli::split_words(input) else {
+        return;
+    };
+    if let Ok(cli::Commands::Add(x)) = cli::parse_command(&words) {
+        // without a data directory, as with the default configuration
+        let data_dir = env::temp_dir().join("calendar-fuzz-missing");
+        if let Ok(ev) = cli::event_from_args(x, &data_dir) {
+            recurrence_of(&ev);
+            let _ = ev.check_recurrence();
+            Calendar::new("owner", "fuzz").add_event(ev);
+        }
+    }
+}
+
+/// Computes what is derived from the recurrence of the event, if any
+fn recurrence_of(ev: &Event) {
+    let Some(rec) = ev.get_recurrence() else {
+        return;
+    };
+    let _ = rec.syntax();
+    // the description walks through all the occurrences
+    if rec.repetitions() <= OCCURRENCES {
+        let _ = ev.humanize_recurrence();
+    }
+    for dt in ev.occurrences().take(OCCURRENCES) {
+        let _ = ev.occurrence_end(dt);
+    }
+}
@@ -0,0 +1,816 @@
+//! gRPC interface of the daemon, as defined in proto/calendar.proto. The messages and the
+//! routing of the service are written out here, so that building does not need protoc
+
+// the methods answer with the Status of tonic, as the code generated by protoc does
+#![allow(clippy::result_large_err)]
+
+use std::convert::Infallible;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use log::info;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::body::BoxBody;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{http, Body, BoxFuture, Context, Poll, Service, StdError};
+use tonic::server::{Grpc, NamedService, ServerStreamingService, UnaryService};
+use tonic::{Request, Response, Status};
+
+use crate::calendar::{Calendar, CalendarDiff};
+use crate::calendar_error::CalendarError;
+use crate::cli;
+use crate::daemon::{Cache, Executed};
+use crate::event;
+use crate::storage;
+use crate::users::Access;
+
+/// How often the watched calendars are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Format of the times in the messages
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListCalendarsRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListCalendarsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub calendars: Vec<CalendarInfo>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetCalendarRequest {
+    #[prost(string, tag = "1")]
+    pub calendar: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CalendarInfo {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, tag = "3")]
+    pub owner: String,
+    #[prost(uint64, tag = "4")]
+    pub events: u64,
+    #[prost(string, tag = "5")]
+    pub etag: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListEventsRequest {
+    #[prost(string, tag = "1")]
+    pub calendar: String,
+    #[prost(string, tag = "2")]
+    pub from: String,
+    #[prost(string, tag = "3")]
+    pub until: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Event {
+    #[prost(uint64, tag = "1")]
+    pub eid: u64,
+    #[prost(string, tag = "2")]
+    pub title: String,
+    #[prost(string, tag = "3")]
+    pub description: String,
+    #[prost(string, tag = "4")]
+    pub start: String,
+    #[prost(string, tag = "5")]
+    pub end: String,
+    #[prost(string, tag = "6")]
+    pub location: String,
+    #[prost(string, repeated, tag = "7")]
+    pub tags: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListEventsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub events: Vec<Event>,
+    #[prost(string, tag = "2")]
+    pub etag: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AddEventRequest {
+    #[prost(string, tag = "1")]
+    pub calendar: String,
+    #[prost(string, tag = "2")]
+    pub if_match: String,
+    #[prost(string, tag = "3")]
+    pub title: String,
+    #[prost(string, tag = "4")]
+    pub description: String,
+    #[prost(string, tag = "5")]
+    pub start_date: String,
+    #[prost(string, tag = "6")]
+    pub start_time: String,
+    #[prost(double, tag = "7")]
+    pub hours: f64,
+    #[prost(string, tag = "8")]
+    pub location: String,
+    #[prost(string, tag = "9")]
+    pub recurrence: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RemoveEventRequest {
+    #[prost(string, tag = "1")]
+    pub calendar: String,
+    #[prost(string, tag = "2")]
+    pub if_match: String,
+    #[prost(uint64, tag = "3")]
+    pub eid: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Command {
+    #[prost(string, repeated, tag = "1")]
+    pub words: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExecuteRequest {
+    #[prost(string, tag = "1")]
+    pub calendar: String,
+    #[prost(string, tag = "2")]
+    pub if_match: String,
+    #[prost(message, repeated, tag = "3")]
+    pub commands: Vec<Command>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MutationResponse {
+    #[prost(string, tag = "1")]
+    pub etag: String,
+    #[prost(bool, tag = "2")]
+    pub conflict: bool,
+    #[prost(uint64, repeated, tag = "3")]
+    pub added: Vec<u64>,
+    #[prost(uint64, repeated, tag = "4")]
+    pub edited: Vec<u64>,
+    #[prost(uint64, repeated, tag = "5")]
+    pub removed: Vec<u64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WatchRequest {
+    #[prost(string, repeated, tag = "1")]
+    pub calendars: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CalendarChange {
+    #[prost(string, tag = "1")]
+    pub calendar: String,
+    #[prost(string, tag = "2")]
+    pub etag: String,
+    #[prost(uint64, repeated, tag = "3")]
+    pub added: Vec<u64>,
+    #[prost(uint64, repeated, tag = "4")]
+    pub edited: Vec<u64>,
+    #[prost(uint64, repeated, tag = "5")]
+    pub removed: Vec<u64>,
+    #[prost(bool, tag = "6")]
+    pub deleted: bool,
+}
+
+fn status(e: CalendarError) -> Status {
+    let msg = format!("{:?}", e);
+    match e {
+        CalendarError::CalendarNotFound(_) | CalendarError::EventNotFound(_) => {
+            Status::not_found(msg)
+        }
+        CalendarError::CalendarAlreadyExists(_) => Status::already_exists(msg),
+        CalendarError::InvalidCalendarName(_)
+        | CalendarError::IcsParsingFailed(_)
+        | CalendarError::InvalidRecurrence(_) => Status::invalid_argument(msg),
+        CalendarError::InvalidConfig(_) => Status::failed_precondition(msg),
+        CalendarError::Unknown(_) => Status::unknown(msg),
+    }
+}
+
+/// Returns the token in the metadata of the request ("authorization: Bearer <token>")
+fn token<T>(req: &Request<T>) -> Option<String> {
+    let value = req.metadata().get("authorization")?.to_str().ok()?;
+    value.strip_prefix("Bearer ").map(|t| t.trim().to_string())
+}
+
+fn time_arg(t: &str, name: &str) -> Result<Option<NaiveDateTime>, Status> {
+    if t.is_empty() {
+        return Ok(None);
+    }
+    t.parse()
+        .map(Some)
+        .map_err(|_| Status::invalid_argument(format!("{} is not a date and time: {}", name, t)))
+}
+
+/// A calendar watched by a client, as last sent to it
+struct Watched {
+    key: String,
+    etag: String,
+    cal: Calendar,
+}
+
+/// Answers the requests with the calendars kept in memory by the daemon
+struct Handler {
+    cache: Arc<Mutex<Cache>>,
+}
+
+impl Handler {
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Checks that the user has the access needed to the calendar (see [`Cache::authorize`]),
+    /// returning their name
+    fn authorize(
+        cache: &mut Cache,
+        key: &str,
+        token: Option<&str>,
+        needed: Access,
+    ) -> Result<Option<String>, Status> {
+        cache.authorize(key, token, needed).map_err(|e| match e {
+            CalendarError::Unknown(msg) => Status::permission_denied(msg),
+            e => status(e),
+        })
+    }
+
+    fn info(cache: &mut Cache, key: &str) -> Result<CalendarInfo, Status> {
+        let cached = cache.get(key).map_err(status)?;
+        Ok(CalendarInfo {
+            id: cached.cal.get_id().to_string(),
+            name: cached.cal.get_name().to_string(),
+            owner: cached.cal.get_owner().to_string(),
+            events: cached.cal.get_size() as u64,
+            etag: cached.etag.clone(),
+        })
+    }
+
+    /// Returns the ids of the calendars in the data directory the user can read
+    fn readable(cache: &mut Cache, token: Option<&str>) -> Result<Vec<String>, Status> {
+        let known = storage::known_calendars(cache.data_dir()).map_err(status)?;
+        let mut ids: Vec<String> = known
+            .into_iter()
+            .filter_map(|(cal, _)| cal.ok().map(|c| c.get_id().to_string()))
+            .filter(|id| Self::authorize(cache, id, token, Access::Read).is_ok())
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    fn list_calendars(
+        &self,
+        req: Request<ListCalendarsRequest>,
+    ) -> Result<ListCalendarsResponse, Status> {
+        let token = token(&req);
+        let mut cache = self.cache();
+        let calendars = Self::readable(&mut cache, token.as_deref())?
+            .iter()
+            .map(|id| Self::info(&mut cache, id))
+            .collect::<Result<_, _>>()?;
+        Ok(ListCalendarsResponse { calendars })
+    }
+
+    fn get_calendar(&self, req: Request<GetCalendarRequest>) -> Result<CalendarInfo, Status> {
+        let token = token(&req);
+        let key = &req.get_ref().calendar;
+        let mut cache = self.cache();
+        Self::authorize(&mut cache, key, token.as_deref(), Access::Read)?;
+        Self::info(&mut cache, key)
+    }
+
+    fn list_events(&self, req: Request<ListEventsRequest>) -> Result<ListEventsResponse, Status> {
+        let token = token(&req);
+        let req = req.into_inner();
+        let from = time_arg(&req.from, "from")?;
+        let until = time_arg(&req.until, "until")?;
+        let mut cache = self.cache();
+        Self::authorize(&mut cache, &req.calendar, token.as_deref(), Access::Read)?;
+        let cached = cache.get(&req.calendar).map_err(status)?;
+        let cal = &cached.cal;
+        let events = cal
+            .list_occurrences_between(from, until)
+            .iter()
+            .map(|(eid, ev)| {
+                let start = ev.get_start();
+                let end = start + chrono::Duration::seconds(ev.get_duration());
+                Event {
+                    eid: *eid,
+                    title: ev.get_title().to_string(),
+                    description: ev.get_description().to_string(),
+                    start: start.format(TIME_FORMAT).to_string(),
+                    end: end.format(TIME_FORMAT).to_string(),
+                    location: ev.get_location().to_string(),
+                    tags: ev.get_metadata().get_tags(),
+                }
+            })
+            .collect();
+        Ok(ListEventsResponse {
+            events,
+            etag: cached.etag.clone(),
+        })
+    }
+
+    /// Executes the commands on the calendar, as the daemon does for the requests on its socket
+    fn mutate(
+        &self,
+        key: &str,
+        token: Option<&str>,
+        if_match: &str,
+        commands: &[Vec<String>],
+    ) -> Result<MutationResponse, Status> {
+        let mut cache = self.cache();
+        // writing always needs an authenticated user
+        let user = Self::authorize(&mut cache, key, token, Access::Write)?.unwrap_or_default();
+        let diff = match cache
+            .execute(key, &user, commands, if_match)
+            .map_err(status)?
+        {
+            Executed::Conflict(etag) => {
+                return Ok(MutationResponse {
+                    etag,
+                    conflict: true,
+                    ..Default::default()
+                })
+            }
+            Executed::Done(diff) => diff,
+        };
+        Ok(MutationResponse {
+            etag: cache.get(key).map_err(status)?.etag.clone(),
+            conflict: false,
+            added: diff.added,
+            edited: diff.edited,
+            removed: diff.removed,
+        })
+    }
+
+    fn add_event(&self, req: Request<AddEventRequest>) -> Result<MutationResponse, Status> {
+        let token = token(&req);
+        let req = req.into_inner();
+        if event::duration_from_hours(req.hours).is_none() {
+            return Err(Status::invalid_argument(format!(
+                "Invalid duration {}",
+                req.hours
+            )));
+        }
+        let mut words = vec![
+            String::from("add"),
+            req.title,
+            req.description,
+            req.start_date,
+            req.start_time,
+            req.hours.to_string(),
+            req.location,
+        ];
+        if !req.recurrence.is_empty() {
+            words.push(req.recurrence);
+        }
+        self.mutate(&req.calendar, token.as_deref(), &req.if_match, &[words])
+    }
+
+    fn remove_event(&self, req: Request<RemoveEventRequest>) -> Result<MutationResponse, Status> {
+        let token = token(&req);
+        let req = req.get_ref();
+        let words = vec![String::from("remove"), req.eid.to_string()];
+        self.mutate(&req.calendar, token.as_deref(), &req.if_match, &[words])
+    }
+
+    /// Executes the commands of the command line changing the calendar (see
+    /// [`cli::runs_in_daemon`]), refusing the request if any other is among them
+    fn execute(&self, req: Request<ExecuteRequest>) -> Result<MutationResponse, Status> {
+        let token = token(&req);
+        let req = req.into_inner();
+        let commands: Vec<Vec<String>> = req.commands.into_iter().map(|c| c.words).collect();
+        for words in commands.iter() {
+            let cmd =
+                cli::parse_command(words).map_err(|e| Status::invalid_argument(e.to_string()))?;
+            if !cli::runs_in_daemon(&cmd) {
+                return Err(Status::permission_denied(format!(
+                    "{} cannot be executed by the daemon",
+                    words.join(" ")
+                )));
+            }
+        }
+        self.mutate(&req.calendar, token.as_deref(), &req.if_match, &commands)
+    }
+
+    /// Returns the calendars to watch for the request, in their current state
+    fn watch(&self, req: Request<WatchRequest>) -> Result<Vec<Watched>, Status> {
+        let token = token(&req);
+        let mut cache = self.cache();
+        let mut keys = req.into_inner().calendars;
+        if keys.is_empty() {
+            keys = Self::readable(&mut cache, token.as_deref())?;
+        }
+        keys.into_iter()
+            .map(|key| {
+                Self::authorize(&mut cache, &key, token.as_deref(), Access::Read)?;
+                let cached = cache.get(&key).map_err(status)?;
+                Ok(Watched {
+                    etag: cached.etag.clone(),
+                    cal: cached.cal.clone(),
+                    key,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the changes to the watched calendars since they were last checked. The
+    /// calendars deleted are not watched anymore
+    fn poll(&self, watched: &mut Vec<Watched>) -> Vec<CalendarChange> {
+        let mut cache = self.cache();
+        let mut changes = Vec::new();
+        watched.retain_mut(|w| match cache.get(&w.key) {
+            Ok(cached) if cached.etag != w.etag => {
+                let CalendarDiff {
+                    added,
+                    removed,
+                    edited,
+                } = w.cal.diff(&cached.cal);
+                changes.push(CalendarChange {
+                    calendar: cached.cal.get_id().to_string(),
+                    etag: cached.etag.clone(),
+                    added,
+                    edited,
+                    removed,
+                    deleted: false,
+                });
+                w.etag = cached.etag.clone();
+                w.cal = cached.cal.clone();
+                true
+            }
+            Err(CalendarError::CalendarNotFound(_)) => {
+                changes.push(CalendarChange {
+                    calendar: w.cal.get_id().to_string(),
+                    deleted: true,
+                    ..Default::default()
+                });
+                false
+            }
+            // unchanged, or unreadable for now
+            _ => true,
+        });
+        changes
+    }
+}
+
+/// A method of the service answering a single message, run on the blocking threads of
+/// the runtime since the calendars are read from the disk
+struct Unary<Req, Res>(
+    Arc<Handler>,
+    fn(&Handler, Request<Req>) -> Result<Res, Status>,
+);
+
+impl<Req: Send + 'static, Res: Send + 'static> UnaryService<Req> for Unary<Req, Res> {
+    type Response = Res;
+    type Future = BoxFuture<Response<Res>, Status>;
+
+    fn call(&mut self, req: Request<Req>) -> Self::Future {
+        let (handler, method) = (self.0.clone(), self.1);
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || method(&handler, req).map(Response::new))
+                .await
+                .map_err(|e| Status::internal(e.to_string()))?
+        })
+    }
+}
+
+/// The Watch method: streams the changes to the calendars until the client disconnects
+struct Watch(Arc<Handler>);
+
+impl ServerStreamingService<WatchRequest> for Watch {
+    type Response = CalendarChange;
+    type ResponseStream = ReceiverStream<Result<CalendarChange, Status>>;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, req: Request<WatchRequest>) -> Self::Future {
+        let handler = self.0.clone();
+        Box::pin(async move {
+            let h = handler.clone();
+            let mut watched = tokio::task::spawn_blocking(move || h.watch(req))
+                .await
+                .map_err(|e| Status::internal(e.to_string()))??;
+            let (tx, rx) = mpsc::channel(16);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(WATCH_INTERVAL);
+                while !tx.is_closed() && !watched.is_empty() {
+                    interval.tick().await;
+                    let h = handler.clone();
+                    let Ok((w, changes)) = tokio::task::spawn_blocking(move || {
+                        let changes = h.poll(&mut watched);
+                        (watched, changes)
+                    })
+                    .await
+                    else {
+                        break;
+                    };
+                    watched = w;
+                    for change in changes {
+                        if tx.send(Ok(change)).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+            Ok(Response::new(ReceiverStream::new(rx)))
+        })
+    }
+}
+
+/// Runs a method of the service on a request
+async fn run<Req, Res, B>(method: Unary<Req, Res>, req: http::Request<B>) -> http::Response<BoxBody>
+where
+    Req: prost::Message + Default + Send + 'static,
+    Res: prost::Message + Send + 'static,
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    Grpc::new(ProstCodec::default()).unary(method, req).await
+}
+
+/// The service as routed by the server, i.e. the code protoc would generate from the proto file
+#[derive(Clone)]
+struct CalendarServiceServer(Arc<Handler>);
+
+impl NamedService for CalendarServiceServer {
+    const NAME: &'static str = "calendar.v1.CalendarService";
+}
+
+impl<B> Service<http::Request<B>> for CalendarServiceServer
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let h = self.0.clone();
+        let method = req
+            .uri()
+            .path()
+            .strip_prefix("/calendar.v1.CalendarService/")
+            .unwrap_or_default()
+            .to_string();
+        Box::pin(async move {
+            let resp = match method.as_str() {
+                "ListCalendars" => run(Unary(h, Handler::list_calendars), req).await,
+                "GetCalendar" => run(Unary(h, Handler::get_calendar), req).await,
+                "ListEvents" => run(Unary(h, Handler::list_events), req).await,
+                "AddEvent" => run(Unary(h, Handler::add_event), req).await,
+                "RemoveEvent" => run(Unary(h, Handler::remove_event), req).await,
+                "Execute" => run(Unary(h, Handler::execute), req).await,
+                "Watch" => {
+                    Grpc::new(ProstCodec::default())
+                        .server_streaming(Watch(h), req)
+                        .await
+                }
+                _ => Status::unimplemented(format!("No method {}", method)).into_http(),
+            };
+            Ok(resp)
+        })
+    }
+}
+
+/// Serves the gRPC interface on the listener, sharing the calendars in memory with the
+/// socket of the daemon. Returns only if the server fails
+pub(crate) fn serve(listener: TcpListener, cache: Arc<Mutex<Cache>>) -> Result<(), CalendarError> {
+    let addr = listener
+        .local_addr()
+        .map_err(|e| CalendarError::Unknown(e.to_string()))?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| CalendarError::Unknown(format!("Cannot start the gRPC server: {}", e)))?;
+    let io_error = |e: std::io::Error| CalendarError::Unknown(format!("{}: {}", addr, e));
+    listener.set_nonblocking(true).map_err(io_error)?;
+    info!("gRPC interface listening on {}", addr);
+    runtime.block_on(async move {
+        let listener = tokio::net::TcpListener::from_std(listener).map_err(io_error)?;
+        let incoming = TcpListenerStream::new(listener);
+        tonic::transport::Server::builder()
+            .add_service(CalendarServiceServer(Arc::new(Handler { cache })))
+            .serve_with_incoming(incoming)
+            .await
+            .map_err(|e| CalendarError::Unknown(format!("gRPC server on {}: {}", addr, e)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use tonic::client::Grpc;
+    use tonic::codec::ProstCodec;
+    use tonic::codegen::http::uri::PathAndQuery;
+    use tonic::transport::{Channel, Endpoint};
+    use tonic::{Code, Request, Status};
+
+    use crate::calendar::Calendar;
+    use crate::daemon::Cache;
+    use crate::grpc::{
+        self, AddEventRequest, CalendarChange, CalendarInfo, Command, ExecuteRequest,
+        GetCalendarRequest, ListCalendarsRequest, ListCalendarsResponse, ListEventsRequest,
+        ListEventsResponse, MutationResponse, RemoveEventRequest, WatchRequest,
+    };
+    use crate::storage;
+    use crate::testing::TempDir;
+    use crate::users::{Access, Users};
+
+    fn request<T>(msg: T, token: &str) -> Request<T> {
+        let mut req = Request::new(msg);
+        let value = format!("Bearer {}", token).parse().unwrap();
+        req.metadata_mut().insert("authorization", value);
+        req
+    }
+
+    fn path(method: &str) -> PathAndQuery {
+        PathAndQuery::try_from(format!("/calendar.v1.CalendarService/{}", method)).unwrap()
+    }
+
+    async fn call<Req, Res>(
+        client: &mut Grpc<Channel>,
+        method: &str,
+        req: Request<Req>,
+    ) -> Result<Res, Status>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Res: prost::Message + Default + Send + Sync + 'static,
+    {
+        client.ready().await.unwrap();
+        let codec = ProstCodec::default();
+        let resp = client.unary(req, path(method), codec).await?;
+        Ok(resp.into_inner())
+    }
+
+    #[test]
+    /// tests listing, modifying and watching a calendar through the gRPC interface
+    fn test_grpc() {
+        let dir = TempDir::new("grpc");
+        for name in ["work", "private"] {
+            assert!(storage::save_calendar(&Calendar::new("owner", name), &dir));
+        }
+        let mut users = Users::default();
+        let token = users.add("alice").unwrap();
+        users.grant("alice", "work", Access::Write).unwrap();
+        users.save(&dir).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cache = Arc::new(Mutex::new(Cache::new(&dir, false)));
+        thread::spawn(move || grpc::serve(listener, cache));
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let endpoint = Endpoint::from_shared(format!("http://{}", addr)).unwrap();
+            let mut client = Grpc::new(endpoint.connect().await.unwrap());
+
+            let list: ListCalendarsResponse = call(
+                &mut client,
+                "ListCalendars",
+                request(ListCalendarsRequest {}, &token),
+            )
+            .await
+            .unwrap();
+            assert_eq!(list.calendars.len(), 1);
+            let etag = list.calendars[0].etag.clone();
+            let denied = call::<_, CalendarInfo>(
+                &mut client,
+                "GetCalendar",
+                Request::new(GetCalendarRequest {
+                    calendar: String::from("work"),
+                }),
+            )
+            .await;
+            assert_eq!(denied.unwrap_err().code(), Code::PermissionDenied);
+
+            client.ready().await.unwrap();
+            let watch = request(
+                WatchRequest {
+                    calendars: vec![String::from("work")],
+                },
+                &token,
+            );
+            let mut changes = client
+                .server_streaming::<_, CalendarChange, _>(
+                    watch,
+                    path("Watch"),
+                    ProstCodec::default(),
+                )
+                .await
+                .unwrap()
+                .into_inner();
+
+            let add = AddEventRequest {
+                calendar: String::from("work"),
+                if_match: String::from("stale"),
+                title: String::from("review"),
+                start_date: String::from("13/07/2022"),
+                start_time: String::from("09:30"),
+                hours: 1.5,
+                ..Default::default()
+            };
+            let negative = AddEventRequest {
+                hours: -1.0,
+                ..add.clone()
+            };
+            let invalid =
+                call::<_, MutationResponse>(&mut client, "AddEvent", request(negative, &token))
+                    .await;
+            assert_eq!(invalid.unwrap_err().code(), Code::InvalidArgument);
+            let conflict: MutationResponse =
+                call(&mut client, "AddEvent", request(add.clone(), &token))
+                    .await
+                    .unwrap();
+            assert!(conflict.conflict);
+            assert_eq!(conflict.etag, etag);
+            let add = AddEventRequest {
+                if_match: etag,
+                ..add
+            };
+            let added: MutationResponse = call(&mut client, "AddEvent", request(add, &token))
+                .await
+                .unwrap();
+            assert!(!added.conflict);
+            assert_eq!(added.added.len(), 1);
+
+            let change = changes.message().await.unwrap().unwrap();
+            assert_eq!(change.calendar, "work");
+            assert_eq!(change.etag, added.etag);
+            assert_eq!(change.added, added.added);
+
+            let events: ListEventsResponse = call(
+                &mut client,
+                "ListEvents",
+                request(
+                    ListEventsRequest {
+                        calendar: String::from("work"),
+                        from: String::from("2022-07-13T00:00:00"),
+                        ..Default::default()
+                    },
+                    &token,
+                ),
+            )
+            .await
+            .unwrap();
+            assert_eq!(events.events.len(), 1);
+            assert_eq!(events.events[0].eid, added.added[0]);
+            assert_eq!(events.events[0].start, "2022-07-13T09:30:00");
+            assert_eq!(events.events[0].end, "2022-07-13T11:00:00");
+
+            let remove = RemoveEventRequest {
+                calendar: String::from("work"),
+                if_match: added.etag,
+                eid: added.added[0],
+            };
+            let removed: MutationResponse =
+                call(&mut client, "RemoveEvent", request(remove, &token))
+                    .await
+                    .unwrap();
+            assert_eq!(removed.removed, added.added);
+            let change = changes.message().await.unwrap().unwrap();
+            assert_eq!(change.removed, added.added);
+
+            // only the commands changing the calendar are executed, not the ones writing files
+            let out = dir.join("x.ics");
+            let export = ExecuteRequest {
+                calendar: String::from("work"),
+                if_match: removed.etag,
+                commands: vec![Command {
+                    words: vec![
+                        String::from("export"),
+                        String::from("--out"),
+                        out.to_string_lossy().into_owned(),
+                    ],
+                }],
+            };
+            let refused =
+                call::<_, MutationResponse>(&mut client, "Execute", request(export, &token)).await;
+            assert_eq!(refused.unwrap_err().code(), Code::PermissionDenied);
+            assert!(!out.exists());
+
+            let unknown = call::<_, CalendarInfo>(
+                &mut client,
+                "Reschedule",
+                request(ListCalendarsRequest {}, &token),
+            )
+            .await;
+            assert_eq!(unknown.unwrap_err().code(), Code::Unimplemented);
+        });
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt::{self, Display};
+
+use chrono::{Duration, NaiveDate, NaiveTime};
+
+use crate::calendar::Calendar;
+use crate::event::Event;
+
+/// Tag of the recurrent events tracked as habits
+pub const HABIT_TAG: &str = "habit";
+
+/// Returns whether the event is tracked as a habit: recurrent and tagged with [`HABIT_TAG`]
+pub fn is_habit(ev: &Event) -> bool {
+    ev.get_recurrence().is_some() && ev.has_tag(HABIT_TAG)
+}
+
+/// How consistently a habit has been kept
+#[derive(Debug, PartialEq, Eq)]
+pub struct Streaks {
+    /// Occurrences done in a row, up to the last one
+    pub current: usize,
+    pub longest: usize,
+    pub done: usize,
+    /// Occurrences up to today
+    pub due: usize,
+}
+
+impl Streaks {
+    /// Returns the percentage of the occurrences due that were done
+    pub fn rate(&self) -> f64 {
+        match self.due {
+            0 => 0.0,
+            due => self.done as f64 * 100.0 / due as f64,
+        }
+    }
+}
+
+impl Display for Streaks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "streak of {} (longest {}), done {} of {} ({:.0}%)",
+            self.current,
+            self.longest,
+            self.done,
+            self.due,
+            self.rate()
+        )
+    }
+}
+
+/// Computes the streaks of the event with this eid over its occurrences up to `today`
+/// (included): the ones of today not done yet do not break the current streak
+pub fn streaks(cal: &Calendar, eid: u64, today: NaiveDate) -> Option<Streaks> {
+    let metadata = cal.peek_event(eid)?.get_metadata();
+    let until = (today + Duration::days(1)).and_time(NaiveTime::MIN) - Duration::seconds(1);
+    let occurrences: Vec<_> = cal
+        .occurrences_until(eid, until)
+        .into_iter()
+        .map(|dt| (dt.date(), metadata.is_done(dt)))
+        .collect();
+    let (mut current, mut longest) = (0, 0);
+    for (_, done) in occurrences.iter() {
+        current = if *done { current + 1 } else { 0 };
+        longest = longest.max(current);
+    }
+    // still to be done today
+    if let Some(pending) = occurrences
+        .iter()
+        .rposition(|(date, done)| *date < today || *done)
+        .filter(|last| *last + 1 < occurrences.len())
+    {
+        current = occurrences[..=pending]
+            .iter()
+            .rev()
+            .take_while(|(_, done)| *done)
+            .count();
+    }
+    Some(Streaks {
+        current,
+        longest,
+        done: occurrences.iter().filter(|(_, done)| *done).count(),
+        due: occurrences.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::calendar::Calendar;
+    use crate::event::Event;
+    use crate::habits::{is_habit, streaks, Streaks};
+
+    #[test]
+    /// tests computing the streaks of the occurrences done of a habit
+    fn test_streaks() {
+        let mut cal = Calendar::new("owner", "me");
+        let tags = Some(vec![String::from("habit")]);
+        let run = Event::new(
+            "run",
+            "",
+            "11/07/2022",
+            "07:00",
+            1.0,
+            None,
+            Some("daily 30"),
+            tags,
+        );
+        assert!(is_habit(&run));
+        cal.insert_event(1, run);
+        let day = |d| NaiveDate::from_ymd_opt(2022, 7, d).unwrap();
+        assert_eq!(
+            streaks(&cal, 1, day(11)),
+            Some(Streaks {
+                current: 0,
+                longest: 0,
+                done: 0,
+                due: 1
+            })
+        );
+        for d in [11, 12, 14, 15, 16] {
+            cal.update_event(1, |ev| ev.mark_done(day(d).and_hms_opt(7, 0, 0).unwrap()))
+                .unwrap();
+        }
+        let streak = |today| streaks(&cal, 1, day(today)).unwrap();
+        // the run of the 17th is still to be done
+        assert_eq!(
+            streak(17),
+            Streaks {
+                current: 3,
+                longest: 3,
+                done: 5,
+                due: 7
+            }
+        );
+        assert_eq!(
+            streak(17).to_string(),
+            "streak of 3 (longest 3), done 5 of 7 (71%)"
+        );
+        assert_eq!(streak(18).current, 0);
+        assert_eq!(streak(13).current, 2);
+        assert!(streaks(&cal, 2, day(17)).is_none());
+        let once = Event::new("run", "", "11/07/2022", "07:00", 1.0, None, None, None);
+        assert!(!is_habit(&once));
+    }
+}
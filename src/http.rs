@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::calendar_error::CalendarError;
+use crate::config::HttpConfig;
+use crate::report;
+use crate::users::hex;
+
+/// Directory of the cached responses, inside the data directory
+pub const CACHE_DIR: &str = ".http-cache";
+
+/// A response kept on disk, sent again to the server to only download it if it changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    #[serde(default)]
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    pub body: String,
+}
+
+/// What the server answered to a request (never, without the `http` feature)
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+enum Fetched {
+    /// The cached response is still valid
+    NotModified,
+    Body(CacheEntry),
+}
+
+/// Returns the host a URL points to, requests to which are rate-limited together
+pub fn host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split(['/', '?', '#']).next().unwrap_or_default()
+}
+
+/// Returns how long to wait before sending a request at `now`, if the last one to the same
+/// host was sent at `last`
+pub fn wait_time(last: Option<Instant>, now: Instant, min_interval: Duration) -> Duration {
+    last.map_or(Duration::ZERO, |last| {
+        min_interval.saturating_sub(now.saturating_duration_since(last))
+    })
+}
+
+/// Returns the headers making a request conditional on the cached response having changed
+pub fn conditional_headers(entry: &CacheEntry) -> Vec<(&'static str, String)> {
+    let mut headers = Vec::new();
+    if let Some(etag) = &entry.etag {
+        headers.push(("If-None-Match", etag.clone()));
+    }
+    if let Some(modified) = &entry.last_modified {
+        headers.push(("If-Modified-Since", modified.clone()));
+    }
+    headers
+}
+
+/// HTTP client shared by the integrations fetching remote data: requests time out, go
+/// through the configured proxy and are spaced out per host, and the responses to requests
+/// without credentials are cached on disk
+pub struct Client {
+    config: HttpConfig,
+    cache_dir: Option<PathBuf>,
+    /// When the last request to each host was sent
+    last_request: HashMap<String, Instant>,
+    #[cfg(feature = "http")]
+    agent: ureq::Agent,
+}
+
+impl Client {
+    pub fn new(config: &HttpConfig, data_dir: &Path) -> Result<Client, CalendarError> {
+        #[cfg(feature = "http")]
+        let agent = {
+            let mut builder = ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(config.timeout_secs))
+                .try_proxy_from_env(true);
+            if let Some(proxy) = &config.proxy {
+                let proxy = ureq::Proxy::new(proxy)
+                    .map_err(|e| CalendarError::InvalidConfig(format!("proxy {}: {}", proxy, e)))?;
+                builder = builder.proxy(proxy);
+            }
+            builder.build()
+        };
+        Ok(Client {
+            config: config.clone(),
+            cache_dir: config.cache.then(|| data_dir.join(CACHE_DIR)),
+            last_request: HashMap::new(),
+            #[cfg(feature = "http")]
+            agent,
+        })
+    }
+
+    fn cache_path(&self, url: &str) -> Option<PathBuf> {
+        let name = hex(&Sha256::digest(url));
+        Some(self.cache_dir.as_ref()?.join(name).with_extension("json"))
+    }
+
+    /// Returns the cached response to a request for the URL, if any
+    pub fn cached(&self, url: &str) -> Option<CacheEntry> {
+        let contents = fs::read_to_string(self.cache_path(url)?).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    fn store(&self, url: &str, entry: &CacheEntry) {
+        let path = match self.cache_path(url) {
+            Some(path) => path,
+            None => return,
+        };
+        let stored = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::to_string(entry).map_err(|e| e.to_string()))
+            .and_then(|contents| fs::write(&path, contents).map_err(|e| e.to_string()));
+        if let Err(e) = stored {
+            report::warning(format!("Cannot cache {}: {}", url, e));
+        }
+    }
+
+    /// Waits until a request can be sent to the host of the URL
+    fn throttle(&mut self, url: &str) {
+        let min_interval = Duration::from_millis(self.config.min_interval_ms);
+        let last = self.last_request.get(host(url)).copied();
+        let wait = wait_time(last, Instant::now(), min_interval);
+        if !wait.is_zero() {
+            debug!("Waiting {:?} before requesting {}", wait, url);
+            std::thread::sleep(wait);
+        }
+        self.last_request
+            .insert(host(url).to_string(), Instant::now());
+    }
+
+    /// Sends a GET request, returning the body of the response. Requests without headers
+    /// are cached: the body is only downloaded again if it changed, and the cached one is
+    /// returned if the server cannot be reached
+    pub fn get(&mut self, url: &str, headers: &[(&str, &str)]) -> Result<String, CalendarError> {
+        let cached = if headers.is_empty() {
+            self.cached(url)
+        } else {
+            None
+        };
+        let mut all: Vec<(&str, String)> = headers
+            .iter()
+            .map(|(name, value)| (*name, value.to_string()))
+            .collect();
+        for (name, value) in cached.iter().flat_map(conditional_headers) {
+            all.push((name, value));
+        }
+        self.throttle(url);
+        match (self.send(url, &all), cached) {
+            (Ok(Fetched::Body(entry)), _) => {
+                if headers.is_empty() {
+                    self.store(url, &entry);
+                }
+                Ok(entry.body)
+            }
+            (Ok(Fetched::NotModified), Some(cached)) => {
+                debug!("{} not modified", url);
+                Ok(cached.body)
+            }
+            (Ok(Fetched::NotModified), None) => Err(CalendarError::Unknown(format!(
+                "GET {}: not modified, but not cached",
+                url
+            ))),
+            (Err(e), Some(cached)) => {
+                report::warning(format!("{:?}: using the cached response", e));
+                Ok(cached.body)
+            }
+            (Err(e), None) => Err(e),
+        }
+    }
+
+    #[cfg(feature = "http")]
+    fn send(&self, url: &str, headers: &[(&str, String)]) -> Result<Fetched, CalendarError> {
+        let err = |e: &dyn std::fmt::Display| CalendarError::Unknown(format!("GET {}: {}", url, e));
+        let mut request = self.agent.get(url);
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        let response = request.call().map_err(|e| err(&e))?;
+        if response.status() == 304 {
+            return Ok(Fetched::NotModified);
+        }
+        let header = |name| response.header(name).map(String::from);
+        let (etag, last_modified) = (header("ETag"), header("Last-Modified"));
+        let body = response.into_string().map_err(|e| err(&e))?;
+        Ok(Fetched::Body(CacheEntry {
+            etag,
+            last_modified,
+            body,
+        }))
+    }
+
+    /// Without the `http` feature, nothing can be downloaded
+    #[cfg(not(feature = "http"))]
+    fn send(&self, url: &str, _headers: &[(&str, String)]) -> Result<Fetched, CalendarError> {
+        Err(CalendarError::Unknown(format!(
+            "Cannot GET {}: rebuild with the `http` feature",
+            url
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::config::HttpConfig;
+    use crate::http::{conditional_headers, host, wait_time, CacheEntry, Client, CACHE_DIR};
+    use crate::testing::TempDir;
+
+    #[test]
+    /// tests spacing out the requests to the same host
+    fn test_rate_limit() {
+        assert_eq!(
+            host("https://example.com:8443/cal.ics?x=1"),
+            "example.com:8443"
+        );
+        assert_eq!(host("http://example.com"), "example.com");
+        let now = Instant::now();
+        let second = Duration::from_secs(1);
+        assert_eq!(wait_time(None, now, second), Duration::ZERO);
+        assert_eq!(wait_time(Some(now), now, second), second);
+        let later = now + Duration::from_millis(400);
+        assert_eq!(
+            wait_time(Some(now), later, second),
+            Duration::from_millis(600)
+        );
+        assert_eq!(
+            wait_time(Some(now), now + second * 2, second),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    /// tests making requests conditional on the cached responses
+    fn test_cache() {
+        let dir = TempDir::new("http");
+        let config = HttpConfig {
+            min_interval_ms: 0,
+            ..HttpConfig::default()
+        };
+        let client = Client::new(&config, &dir).unwrap();
+        let url = "https://example.com/cal.ics";
+        assert_eq!(client.cached(url), None);
+        let entry = CacheEntry {
+            etag: Some(String::from("\"v1\"")),
+            last_modified: None,
+            body: String::from("BEGIN:VCALENDAR"),
+        };
+        client.store(url, &entry);
+        assert_eq!(client.cached(url), Some(entry.clone()));
+        assert_eq!(
+            conditional_headers(&entry),
+            [("If-None-Match", String::from("\"v1\""))]
+        );
+        assert!(dir.join(CACHE_DIR).is_dir());
+
+        // the cached response is returned when the server cannot be reached
+        let mut client = client;
+        if !cfg!(feature = "http") {
+            assert_eq!(client.get(url, &[]).unwrap(), entry.body);
+            assert!(client.get(url, &[("Authorization", "x")]).is_err());
+        }
+
+        let uncached = Client::new(
+            &HttpConfig {
+                cache: false,
+                ..config
+            },
+            &dir,
+        )
+        .unwrap();
+        assert_eq!(uncached.cached(url), None);
+    }
+}
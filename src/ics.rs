@@ -0,0 +1,1031 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::hash::{Hash, Hasher};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{
+    DateTime, Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc,
+};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+
+use crate::clock;
+use crate::event::{
+    Anchor, Attachment, Cadence, Class, Event, Recurrence, Transparency, WorkPlace,
+};
+use crate::ics_writer::{escape_text, param_value, write_lines, MAX_LINE_LEN};
+
+/// Components identified by a UID and stamped with a DTSTAMP (RFC 5545)
+const STAMPED: [&str; 4] = ["VEVENT", "VTODO", "VJOURNAL", "VFREEBUSY"];
+/// Properties that can occur at most once in the components with a UID
+const AT_MOST_ONCE: [&str; 20] = [
+    "UID",
+    "DTSTAMP",
+    "DTSTART",
+    "DTEND",
+    "DUE",
+    "DURATION",
+    "SUMMARY",
+    "DESCRIPTION",
+    "LOCATION",
+    "GEO",
+    "CLASS",
+    "CREATED",
+    "LAST-MODIFIED",
+    "ORGANIZER",
+    "PRIORITY",
+    "SEQUENCE",
+    "STATUS",
+    "TRANSP",
+    "URL",
+    "RECURRENCE-ID",
+];
+
+/// Formats a date and time as a local ("floating") iCalendar date-time
+pub fn date_time(dt: NaiveDateTime) -> String {
+    dt.format("%Y%m%dT%H%M%S").to_string()
+}
+
+/// Formats an instant as an iCalendar date-time in UTC, as needed by CREATED and LAST-MODIFIED
+fn utc(dt: DateTime<Local>) -> String {
+    dt.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Formats a duration as a DURATION value, e.g. "PT1H30M"
+pub fn duration(d: Duration) -> String {
+    let secs = d.num_seconds();
+    let mut out = String::from(if secs < 0 { "-PT" } else { "PT" });
+    let secs = secs.abs();
+    for (n, unit) in [(secs / 3600, 'H'), (secs / 60 % 60, 'M'), (secs % 60, 'S')] {
+        if n > 0 {
+            let _ = write!(out, "{}{}", n, unit);
+        }
+    }
+    if secs == 0 {
+        out.push_str("0S");
+    }
+    out
+}
+
+/// Parses a DURATION value, e.g. "PT1H30M", "P1D" or "-PT15M"
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let (sign, rest) = match s.trim().strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, s.trim().trim_start_matches('+')),
+    };
+    let mut secs = 0;
+    let mut n = String::new();
+    let mut time = false;
+    for c in rest.strip_prefix('P')?.chars() {
+        let unit = match (c, time) {
+            ('0'..='9', _) => {
+                n.push(c);
+                continue;
+            }
+            ('T', false) => {
+                time = true;
+                continue;
+            }
+            ('W', false) => 7 * 86400,
+            ('D', false) => 86400,
+            ('H', true) => 3600,
+            ('M', true) => 60,
+            ('S', true) => 1,
+            _ => return None,
+        };
+        secs = n
+            .parse::<i64>()
+            .ok()?
+            .checked_mul(unit)
+            .and_then(|s| s.checked_add(secs))?;
+        n.clear();
+    }
+    if !n.is_empty() {
+        return None;
+    }
+    Duration::try_seconds(sign * secs)
+}
+
+/// Returns why the (unfolded) document is not well formed, if so: the iCalendar library panics
+/// on the components not closed in order, and on the content lines starting with a multi-byte
+/// character within their first 5 bytes
+pub fn structure_error(doc: &str) -> Option<String> {
+    let mut open: Vec<&str> = Vec::new();
+    let mut offset = 0;
+    for (n, line) in doc.split('\n').enumerate() {
+        let start = offset;
+        offset += line.len() + 1;
+        let line = line.trim_end_matches('\r');
+        if !(doc.is_char_boundary((start + 3).min(doc.len()))
+            && doc.is_char_boundary((start + 5).min(doc.len())))
+        {
+            return Some(format!(
+                "line {} starts with an unexpected character",
+                n + 1
+            ));
+        }
+        if let Some(name) = line.strip_prefix("BEGIN:") {
+            open.push(name.trim());
+        } else if let Some(name) = line.strip_prefix("END:") {
+            if open.pop() != Some(name.trim()) {
+                return Some(format!("line {}: END:{} closes no component", n + 1, name));
+            }
+        }
+    }
+    open.last()
+        .map(|name| format!("BEGIN:{} is never closed", name))
+}
+
+/// Formats an offset from UTC as a UTC-OFFSET value, e.g. "+0100" or "-0430"
+fn utc_offset(secs: i32) -> String {
+    let sign = if secs < 0 { '-' } else { '+' };
+    let secs = secs.abs();
+    let mut out = format!("{}{:02}{:02}", sign, secs / 3600, secs / 60 % 60);
+    if secs % 60 != 0 {
+        let _ = write!(out, "{:02}", secs % 60);
+    }
+    out
+}
+
+/// Returns the content lines of the VTIMEZONE component of the time zone between the local
+/// times `from` and `until`: an observance for the offset at `from`, and one for each change
+/// of the offset in between (e.g. the DST transitions), at the local time it happens
+pub fn vtimezone(tz: Tz, from: NaiveDateTime, until: NaiveDateTime) -> Vec<String> {
+    let to_utc = |dt: NaiveDateTime| {
+        tz.from_local_datetime(&dt)
+            .earliest()
+            .map_or(dt, |d| d.naive_utc())
+    };
+    let offset = |utc: NaiveDateTime| tz.offset_from_utc_datetime(&utc);
+    let observance = |at: NaiveDateTime, from: i32, to: &<Tz as TimeZone>::Offset| {
+        let kind = if to.dst_offset().is_zero() {
+            "STANDARD"
+        } else {
+            "DAYLIGHT"
+        };
+        let mut lines = vec![
+            format!("BEGIN:{}", kind),
+            format!("DTSTART:{}", date_time(at)),
+            format!("TZOFFSETFROM:{}", utc_offset(from)),
+            format!("TZOFFSETTO:{}", utc_offset(to.fix().local_minus_utc())),
+        ];
+        if let Some(name) = to.abbreviation() {
+            lines.push(format!("TZNAME:{}", name));
+        }
+        lines.push(format!("END:{}", kind));
+        lines
+    };
+    let mut lines = vec![
+        String::from("BEGIN:VTIMEZONE"),
+        format!("TZID:{}", tz.name()),
+    ];
+    let mut utc = to_utc(from);
+    let mut current = offset(utc);
+    let secs = current.fix().local_minus_utc();
+    lines.extend(observance(from, secs, &current));
+    let end = to_utc(until);
+    // the offset changes at most once a day: the instant it changes is found by bisection
+    while utc < end {
+        let next = (utc + Duration::days(1)).min(end);
+        if offset(next).fix() != current.fix() {
+            let (mut before, mut after) = (utc, next);
+            while after - before > Duration::seconds(1) {
+                let mid = before + (after - before) / 2;
+                if offset(mid).fix() == current.fix() {
+                    before = mid;
+                } else {
+                    after = mid;
+                }
+            }
+            let old = current.fix().local_minus_utc();
+            current = offset(after);
+            let local = after + Duration::seconds(old.into());
+            lines.extend(observance(local, old, &current));
+        }
+        utc = next;
+    }
+    lines.push(String::from("END:VTIMEZONE"));
+    lines
+}
+
+/// Returns the first and the last instant of the occurrences of the event (local times)
+pub fn span(ev: &Event) -> (NaiveDateTime, NaiveDateTime) {
+    let start = ev.get_start();
+    let last = ev.occurrences().last().unwrap_or(start);
+    (start, ev.occurrence_end(last))
+}
+
+/// Returns the RRULE of the recurrence of the event, if it can be expressed as one, with the
+/// starts of the occurrences it gives (see [`rule_dates`])
+fn rrule(ev: &Event) -> Option<(String, BTreeSet<NaiveDateTime>)> {
+    let rec = ev.get_recurrence()?;
+    let freq = match rec.cadence() {
+        Cadence::Secondly => "SECONDLY",
+        Cadence::Minutely => "MINUTELY",
+        Cadence::Hourly => "HOURLY",
+        Cadence::Daily => "DAILY",
+        Cadence::Weekly => "WEEKLY",
+        Cadence::Monthly => "MONTHLY",
+        Cadence::Yearly => "YEARLY",
+        Cadence::Cron(_) => return None,
+    };
+    let dates = rule_dates(ev, rec);
+    let mut rule = format!("FREQ={};COUNT={}", freq, dates.len());
+    if let Some(interval) = rec.interval() {
+        let _ = write!(rule, ";INTERVAL={}", interval);
+    }
+    if let Some(month_day) = rec.month_day() {
+        let days: Vec<String> = month_day
+            .days
+            .iter()
+            .map(|d| d.to_string()[..2].to_uppercase())
+            .collect();
+        let _ = write!(rule, ";BYDAY={};BYSETPOS={}", days.join(","), month_day.pos);
+    }
+    Some((rule, dates))
+}
+
+/// Returns the starts of the occurrences of the RRULE of the event, as read by the other
+/// applications: the ones of its recurrence alone (without holidays, excluded dates, pauses
+/// or times of the sun), always including the start of the event, and skipping the months
+/// without the day of the start instead of moving to their last day
+fn rule_dates(ev: &Event, rec: &Recurrence) -> BTreeSet<NaiveDateTime> {
+    let start = ev.get_start();
+    let mut bare = ev.clone();
+    bare.set_holidays(None);
+    bare.set_exdates(BTreeSet::new());
+    bare.set_pauses(Vec::new());
+    bare.set_sun(None);
+    let months = match rec.cadence() {
+        Cadence::Monthly if rec.month_day().is_none() => 1,
+        Cadence::Yearly if rec.month_day().is_none() => 12,
+        _ => 0,
+    };
+    let interval = rec.interval().unwrap_or(1).max(1);
+    let mut dates: BTreeSet<NaiveDateTime> = bare
+        .occurrences()
+        .enumerate()
+        .filter(|(i, _)| {
+            months == 0
+                || u32::try_from(i * interval * months)
+                    .ok()
+                    .and_then(|n| start.checked_add_months(Months::new(n)))
+                    .is_some_and(|dt| dt.day() == start.day())
+        })
+        .map(|(_, dt)| dt)
+        .collect();
+    dates.insert(start);
+    dates
+}
+
+/// Returns the ATTACH property with the contents of the attachment inline, in base64
+pub fn attach(attachment: &Attachment, data: &[u8]) -> String {
+    let mut line = String::from("ATTACH");
+    if let Some(fmttype) = &attachment.fmttype {
+        let _ = write!(line, ";FMTTYPE={}", param_value(fmttype));
+    }
+    if let Some(filename) = &attachment.filename {
+        let _ = write!(line, ";FILENAME={}", param_value(filename));
+    }
+    let _ = write!(
+        line,
+        ";ENCODING=BASE64;VALUE=BINARY:{}",
+        BASE64.encode(data)
+    );
+    line
+}
+
+/// Decodes a BINARY value (base64), ignoring the whitespace left by folding
+pub fn decode_binary(val: &str) -> Result<Vec<u8>, String> {
+    let val: String = val.chars().filter(|c| !c.is_whitespace()).collect();
+    BASE64.decode(val).map_err(|e| e.to_string())
+}
+
+/// Returns the content lines of a VEVENT component describing the event. Its times are in
+/// the time zone `tz` (whose VTIMEZONE must be in the same document, see [`vtimezone`]), or
+/// floating without one. The occurrences skip the dates of the holiday calendar `holidays`.
+/// The attachments are inline, with the contents returned by `data`: the ones it has none
+/// for are left out
+pub fn vevent(
+    eid: u64,
+    ev: &Event,
+    tz: Option<Tz>,
+    holidays: Option<&BTreeSet<NaiveDate>>,
+    data: impl Fn(&Attachment) -> Option<Vec<u8>>,
+) -> Vec<String> {
+    let start = ev.get_start();
+    let metadata = ev.get_metadata();
+    // the times of the events recurring at a fixed instant are in UTC
+    let anchored = ev
+        .get_recurrence()
+        .is_some_and(|rec| rec.anchor() == Anchor::Utc);
+    let date_time = |dt: NaiveDateTime| match Local.from_local_datetime(&dt).earliest() {
+        Some(local) if anchored => utc(local),
+        _ => date_time(dt),
+    };
+    let tzid = match tz {
+        Some(tz) if !anchored => format!(";TZID={}", param_value(tz.name())),
+        _ => String::new(),
+    };
+    let mut lines = vec![
+        String::from("BEGIN:VEVENT"),
+        format!("UID:{}", eid),
+        format!("DTSTAMP:{}", utc(clock::now())),
+        format!("DTSTART{}:{}", tzid, date_time(start)),
+        format!("DTEND{}:{}", tzid, date_time(ev.occurrence_end(start))),
+        format!("SUMMARY:{}", escape_text(ev.get_title())),
+        format!("SEQUENCE:{}", metadata.get_revision()),
+        format!("CREATED:{}", utc(metadata.get_creation())),
+        format!("LAST-MODIFIED:{}", utc(metadata.get_modification())),
+    ];
+    if !ev.get_description().is_empty() {
+        lines.push(format!("DESCRIPTION:{}", escape_text(ev.get_description())));
+    }
+    if !ev.get_location().is_empty() {
+        lines.push(format!("LOCATION:{}", escape_text(ev.get_location())));
+    }
+    if let Some(geo) = ev.get_geo() {
+        lines.push(format!("GEO:{:.6};{:.6}", geo.lat(), geo.lon()));
+    }
+    if ev.get_travel_time() > Duration::zero() {
+        lines.push(format!(
+            "X-APPLE-TRAVEL-DURATION;VALUE=DURATION:{}",
+            duration(ev.get_travel_time())
+        ));
+    }
+    let tags = metadata.get_tags();
+    if !tags.is_empty() {
+        let tags: Vec<String> = tags.iter().map(|t| escape_text(t)).collect();
+        lines.push(format!("CATEGORIES:{}", tags.join(",")));
+    }
+    if !ev.get_resources().is_empty() {
+        let resources: Vec<String> = ev.get_resources().iter().map(|r| escape_text(r)).collect();
+        lines.push(format!("RESOURCES:{}", resources.join(",")));
+    }
+    if let Some((rule, dates)) = rrule(ev) {
+        lines.push(format!("RRULE:{}", rule));
+        // the occurrences the rule gives that the event has not are excluded (on the excluded
+        // dates, in the pauses, or on the holidays), the ones of the event it does not give
+        // (moved after the holidays, or at the times of the sun) added
+        let occurrences: BTreeSet<NaiveDateTime> = ev.occurrences_avoiding(holidays).collect();
+        let exdates: Vec<String> = dates
+            .difference(&occurrences)
+            .map(|dt| date_time(*dt))
+            .collect();
+        if !exdates.is_empty() {
+            lines.push(format!("EXDATE{}:{}", tzid, exdates.join(",")));
+        }
+        let rdates: Vec<String> = occurrences
+            .difference(&dates)
+            .map(|dt| date_time(*dt))
+            .collect();
+        if !rdates.is_empty() {
+            lines.push(format!("RDATE{}:{}", tzid, rdates.join(",")));
+        }
+    }
+    if ev.get_transparency() == Transparency::Free {
+        lines.push(String::from("TRANSP:TRANSPARENT"));
+    }
+    match ev.get_working_location() {
+        Some(WorkPlace::Home) => lines.push(String::from("X-WORKING-LOCATION:HOME")),
+        Some(WorkPlace::Office) => lines.push(String::from("X-WORKING-LOCATION:OFFICE")),
+        Some(WorkPlace::Other(place)) => {
+            lines.push(format!("X-WORKING-LOCATION:{}", escape_text(place)))
+        }
+        None => (),
+    }
+    match ev.get_class() {
+        Class::Public => (),
+        Class::Private => lines.push(String::from("CLASS:PRIVATE")),
+        Class::Confidential => lines.push(String::from("CLASS:CONFIDENTIAL")),
+    }
+    if let Some(color) = ev.get_color() {
+        lines.push(format!("COLOR:{}", color));
+    }
+    if ev.is_important() {
+        lines.push(String::from("X-CALENDA-RS-IMPORTANT:TRUE"));
+    }
+    if ev.is_milestone() {
+        lines.push(String::from("X-CALENDA-RS-MILESTONE:TRUE"));
+    }
+    for related in ev.get_related_to() {
+        lines.push(format!("RELATED-TO:{}", related));
+    }
+    for attachment in ev.get_attachments() {
+        if let Some(data) = data(attachment) {
+            lines.push(attach(attachment, &data));
+        }
+    }
+    lines.push(String::from("END:VEVENT"));
+    lines
+}
+
+/// Returns the name of the property of a content line, e.g. "DTSTART" for
+/// "DTSTART;TZID=Europe/Rome:20220713T090000"
+fn property_name(line: &str) -> &str {
+    line.split([';', ':']).next().unwrap_or_default()
+}
+
+/// Adds the UID and the DTSTAMP a component needs, if missing: the UID is derived from the
+/// contents of the component, so that writing it again gives the same one
+fn stamped(mut component: Vec<String>) -> Vec<String> {
+    let stamped = component
+        .first()
+        .and_then(|line| line.strip_prefix("BEGIN:"))
+        .is_some_and(|name| STAMPED.contains(&name));
+    if !stamped {
+        return component;
+    }
+    let has = |lines: &[String], name: &str| lines.iter().any(|l| property_name(l) == name);
+    if !has(&component, "DTSTAMP") {
+        component.insert(1, format!("DTSTAMP:{}", utc(clock::now())));
+    }
+    if !has(&component, "UID") {
+        let mut hasher = DefaultHasher::new();
+        component
+            .iter()
+            .filter(|l| property_name(l) != "DTSTAMP")
+            .for_each(|l| l.hash(&mut hasher));
+        component.insert(1, format!("UID:{:016x}@calenda-rs", hasher.finish()));
+    }
+    component
+}
+
+/// Generates an iCalendar document with the given components, with the VERSION and PRODID
+/// of the calendar and the UID and DTSTAMP of the components that need them, if missing
+pub fn vcalendar(components: Vec<Vec<String>>) -> String {
+    let mut lines = vec![
+        String::from("BEGIN:VCALENDAR"),
+        String::from("VERSION:2.0"),
+        String::from("PRODID:-//calenda-rs//EN"),
+    ];
+    lines.extend(components.into_iter().flat_map(stamped));
+    lines.push(String::from("END:VCALENDAR"));
+    write_lines(lines)
+}
+
+/// A component being validated: its name, the line it begins at, its properties (with the
+/// line of each one) and the names of its subcomponents
+struct Open<'a> {
+    name: &'a str,
+    line: usize,
+    properties: Vec<(&'a str, &'a str, usize)>,
+    children: Vec<&'a str>,
+}
+
+impl Open<'_> {
+    fn count(&self, name: &str) -> usize {
+        self.properties
+            .iter()
+            .filter(|(n, _, _)| *n == name)
+            .count()
+    }
+
+    /// Describes the required properties missing from the component, the ones repeated
+    /// that can occur at most once, and the other rules of RFC 5545 it breaks
+    fn problems(&self, method: bool) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut require = |names: &[&str]| {
+            for name in names {
+                if self.count(name) == 0 {
+                    problems.push(format!(
+                        "line {}: {} without {}",
+                        self.line, self.name, name
+                    ));
+                }
+            }
+        };
+        match self.name {
+            "VCALENDAR" => require(&["PRODID", "VERSION"]),
+            "VEVENT" if !method => require(&["UID", "DTSTAMP", "DTSTART"]),
+            "VEVENT" | "VTODO" | "VJOURNAL" | "VFREEBUSY" => require(&["UID", "DTSTAMP"]),
+            "VTIMEZONE" => require(&["TZID"]),
+            "STANDARD" | "DAYLIGHT" => require(&["DTSTART", "TZOFFSETFROM", "TZOFFSETTO"]),
+            "VALARM" => require(&["ACTION", "TRIGGER"]),
+            _ => (),
+        }
+        let once: &[&str] = match self.name {
+            "VCALENDAR" => &["PRODID", "VERSION", "CALSCALE", "METHOD"],
+            name if STAMPED.contains(&name) => &AT_MOST_ONCE,
+            _ => &[],
+        };
+        for (i, (name, _, line)) in self.properties.iter().enumerate() {
+            if once.contains(name) && self.properties[..i].iter().any(|(n, _, _)| n == name) {
+                problems.push(format!("line {}: {} repeated in {}", line, name, self.name));
+            }
+        }
+        for (name, value, line) in self.properties.iter() {
+            match *name {
+                "VERSION" if self.name == "VCALENDAR" && *value != "2.0" => {
+                    problems.push(format!("line {}: VERSION {} instead of 2.0", line, value))
+                }
+                "DTSTAMP" if !value.ends_with('Z') => {
+                    problems.push(format!("line {}: DTSTAMP {} not in UTC", line, value))
+                }
+                _ => (),
+            }
+        }
+        if self.name == "VEVENT" && self.count("DTEND") > 0 && self.count("DURATION") > 0 {
+            problems.push(format!(
+                "line {}: VEVENT with both DTEND and DURATION",
+                self.line
+            ));
+        }
+        let children = |names: &[&str]| self.children.iter().any(|c| names.contains(c));
+        if self.name == "VCALENDAR" && self.children.is_empty() {
+            problems.push(format!("line {}: VCALENDAR without components", self.line));
+        }
+        if self.name == "VTIMEZONE" && !children(&["STANDARD", "DAYLIGHT"]) {
+            problems.push(format!(
+                "line {}: VTIMEZONE without STANDARD or DAYLIGHT",
+                self.line
+            ));
+        }
+        problems
+    }
+}
+
+/// Checks an iCalendar document against the structural rules of RFC 5545, returning the
+/// problems found (with the line of each one): the lines terminated by CRLF and folded at 75
+/// octets, the well-formed content lines, the components nested in a single VCALENDAR, with
+/// their required properties and without the ones repeated that can occur only once
+pub fn validate(doc: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    // the unfolded content lines, with the line each one starts at
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut bare_lf = None;
+    for (i, line) in doc.split_inclusive('\n').enumerate() {
+        let n = i + 1;
+        let content = match line.strip_suffix("\r\n") {
+            Some(content) => content,
+            None => {
+                bare_lf = bare_lf.or(Some(n));
+                line.trim_end_matches('\n')
+            }
+        };
+        if content.len() > MAX_LINE_LEN {
+            problems.push(format!(
+                "line {}: {} octets long, not folded at {}",
+                n,
+                content.len(),
+                MAX_LINE_LEN
+            ));
+        }
+        match (content.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some((_, last))) => last.push_str(rest),
+            (Some(_), None) => problems.push(format!("line {}: continues no line", n)),
+            (None, _) if content.is_empty() => problems.push(format!("line {}: empty", n)),
+            (None, _) => lines.push((n, content.to_string())),
+        }
+    }
+    if let Some(n) = bare_lf {
+        problems.push(format!("line {}: not terminated by CRLF", n));
+    }
+    let mut open: Vec<Open> = Vec::new();
+    let mut calendars = 0;
+    let mut method = false;
+    for (n, line) in lines.iter() {
+        let name = property_name(line);
+        // the value starts at the first colon not in a quoted parameter value
+        let mut quoted = false;
+        let colon = line.char_indices().find_map(|(i, c)| match c {
+            '"' => {
+                quoted = !quoted;
+                None
+            }
+            ':' if !quoted => Some(i),
+            _ => None,
+        });
+        let Some(colon) = colon else {
+            problems.push(format!("line {}: no value", n));
+            continue;
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            problems.push(format!("line {}: invalid property name \"{}\"", n, name));
+            continue;
+        }
+        let value = &line[colon + 1..];
+        match name.to_ascii_uppercase().as_str() {
+            "BEGIN" => {
+                if open.is_empty() {
+                    if value != "VCALENDAR" {
+                        problems.push(format!("line {}: {} outside of VCALENDAR", n, value));
+                    }
+                    calendars += 1;
+                }
+                if let Some(parent) = open.last_mut() {
+                    parent.children.push(value);
+                }
+                open.push(Open {
+                    name: value,
+                    line: *n,
+                    properties: Vec::new(),
+                    children: Vec::new(),
+                });
+            }
+            "END" => match open.pop() {
+                Some(component) if component.name == value => {
+                    problems.extend(component.problems(method));
+                }
+                Some(component) => {
+                    problems.push(format!(
+                        "line {}: END:{} closes BEGIN:{} of line {}",
+                        n, value, component.name, component.line
+                    ));
+                    return problems;
+                }
+                None => {
+                    problems.push(format!("line {}: END:{} closes no component", n, value));
+                    return problems;
+                }
+            },
+            _ => match open.last_mut() {
+                Some(component) => {
+                    if component.name == "VCALENDAR" && name == "METHOD" {
+                        method = true;
+                    }
+                    component.properties.push((name, value, *n));
+                }
+                None => problems.push(format!("line {}: {} outside of VCALENDAR", n, name)),
+            },
+        }
+    }
+    if let Some(component) = open.last() {
+        problems.push(format!(
+            "line {}: BEGIN:{} is never closed",
+            component.line, component.name
+        ));
+    }
+    match calendars {
+        0 => problems.push(String::from("no VCALENDAR")),
+        1 => (),
+        n => problems.push(format!("{} VCALENDAR in the same file", n)),
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use chrono::{Duration, NaiveDate};
+
+    use crate::event::{Class, Color, Event, HolidayAction, HolidayRule, WorkPlace};
+    use crate::ics::{
+        duration, parse_duration, span, structure_error, validate, vcalendar, vevent, vtimezone,
+    };
+    use crate::ics_writer::write_lines;
+    use crate::working_location;
+
+    #[test]
+    /// tests describing events as VEVENTs
+    fn test_vevent() {
+        let mut ev = Event::new(
+            "review, final",
+            "line 1\nline 2",
+            "13/07/2022",
+            "09:30",
+            2.0,
+            Some("room 1"),
+            Some("monthly 5 2 on last fri"),
+            Some(vec![String::from("work")]),
+        );
+        ev.set_class(Class::Private);
+        ev.set_color(Some(Color::Orange));
+        ev.set_important(true);
+        ev.add_related(42);
+        ev.set_geo("45.4642,9.19".parse().ok());
+        ev.set_travel_time(&Duration::minutes(30));
+        ev.set_resources(vec![String::from("room 1"), String::from("projector")]);
+        ev.set_exdates(BTreeSet::from([
+            NaiveDate::from_ymd_opt(2022, 9, 30).unwrap()
+        ]));
+        ev.pause(
+            NaiveDate::from_ymd_opt(2022, 11, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2022, 11, 30).unwrap(),
+        );
+        let ics = vcalendar(vec![vevent(7, &ev, None, None, |_| None)]);
+        for line in [
+            "UID:7",
+            "DTSTART:20220713T093000",
+            "DTEND:20220713T113000",
+            "SUMMARY:review\\, final",
+            "DESCRIPTION:line 1\\nline 2",
+            // the start is not on the last friday: it counts as an occurrence, excluded
+            "RRULE:FREQ=MONTHLY;COUNT=7;INTERVAL=2;BYDAY=FR;BYSETPOS=-1",
+            "CLASS:PRIVATE",
+            "COLOR:orange",
+            "X-CALENDA-RS-IMPORTANT:TRUE",
+            "RELATED-TO:42",
+            "EXDATE:20220713T093000,20220930T093000,20221125T093000",
+            "GEO:45.464200;9.190000",
+            "X-APPLE-TRAVEL-DURATION;VALUE=DURATION:PT30M",
+            "RESOURCES:projector,room 1",
+            "SEQUENCE:0",
+        ] {
+            assert!(ics.contains(&format!("{}\r\n", line)), "{}", line);
+        }
+        assert!(icalendar::parser::read_calendar(&ics).is_ok());
+
+        let office = working_location::event(
+            &WorkPlace::Office,
+            NaiveDate::from_ymd_opt(2022, 7, 13).unwrap(),
+        );
+        let ics = vevent(8, &office, None, None, |_| None);
+        assert!(ics.contains(&String::from("X-WORKING-LOCATION:OFFICE")));
+        assert!(ics.contains(&String::from("TRANSP:TRANSPARENT")));
+    }
+
+    #[test]
+    /// tests that the RRULE, EXDATE and RDATE written give the occurrences of the event
+    fn test_rrule_occurrences() {
+        // no occurrence in the months without the day of the start, but on their last day
+        let ev = Event::new(
+            "rent",
+            "",
+            "31/01/2022",
+            "09:00",
+            1.0,
+            None,
+            Some("monthly 3"),
+            None,
+        );
+        let ics = vevent(1, &ev, None, None, |_| None);
+        for line in [
+            "RRULE:FREQ=MONTHLY;COUNT=2",
+            "RDATE:20220228T090000,20220430T090000",
+        ] {
+            assert!(ics.contains(&String::from(line)), "{:?}", ics);
+        }
+        assert!(!ics.iter().any(|l| l.starts_with("EXDATE")));
+
+        // the occurrences on the holidays are moved, or skipped
+        let mut ev = Event::new(
+            "review",
+            "",
+            "15/07/2022",
+            "09:00",
+            1.0,
+            None,
+            Some("weekly 3"),
+            None,
+        );
+        let holidays = BTreeSet::from([NaiveDate::from_ymd_opt(2022, 7, 22).unwrap()]);
+        let mut rule = HolidayRule {
+            action: HolidayAction::Shift,
+            weekends: true,
+            calendar: Some(String::from("holidays")),
+        };
+        ev.set_holidays(Some(rule.clone()));
+        let ics = vevent(1, &ev, None, Some(&holidays), |_| None);
+        for line in [
+            "RRULE:FREQ=WEEKLY;COUNT=4",
+            "EXDATE:20220722T090000",
+            "RDATE:20220725T090000",
+        ] {
+            assert!(ics.contains(&String::from(line)), "{:?}", ics);
+        }
+        rule.action = HolidayAction::Skip;
+        ev.set_holidays(Some(rule));
+        let ics = vevent(1, &ev, None, Some(&holidays), |_| None);
+        assert!(ics.contains(&String::from("EXDATE:20220722T090000")));
+        assert!(!ics.iter().any(|l| l.starts_with("RDATE")));
+        // without the dates of the holiday calendar, only the weekends are known
+        let ics = vevent(1, &ev, None, None, |_| None);
+        assert!(!ics.iter().any(|l| l.starts_with("EXDATE")));
+    }
+
+    #[test]
+    /// tests that the documents written have the required properties, added to the components
+    /// missing them, and pass the validation
+    fn test_required_properties() {
+        let ev = Event::new("review", "", "13/07/2022", "09:30", 2.0, None, None, None);
+        let doc = vcalendar(vec![vevent(7, &ev, None, None, |_| None)]);
+        assert_eq!(validate(&doc), Vec::<String>::new());
+        let component = || {
+            vec![
+                String::from("BEGIN:VFREEBUSY"),
+                String::from("FREEBUSY:20220713T090000Z/20220713T100000Z"),
+                String::from("END:VFREEBUSY"),
+            ]
+        };
+        let doc = vcalendar(vec![component()]);
+        let lines: Vec<&str> = doc.split_terminator("\r\n").collect();
+        assert_eq!(lines[1..3], ["VERSION:2.0", "PRODID:-//calenda-rs//EN"]);
+        assert!(lines[4].starts_with("UID:") && lines[4].ends_with("@calenda-rs"));
+        assert!(lines[5].starts_with("DTSTAMP:") && lines[5].ends_with('Z'));
+        assert_eq!(validate(&doc), Vec::<String>::new());
+        // the same contents, the same UID
+        assert!(vcalendar(vec![component()]).contains(lines[4]));
+        // the components that need none are left as they are
+        let doc = vcalendar(vec![vec![
+            String::from("BEGIN:X-NOTE"),
+            String::from("END:X-NOTE"),
+        ]]);
+        assert!(!doc.contains("UID:"));
+    }
+
+    #[test]
+    /// tests finding where the documents break the structural rules of RFC 5545
+    fn test_validate() {
+        let check = |lines: &[&str]| validate(&write_lines(lines));
+        let valid = [
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            "PRODID:-//example//EN",
+            "BEGIN:VEVENT",
+            "UID:1@example.com",
+            "DTSTAMP:20220713T090000Z",
+            "DTSTART;TZID=\"Europe/Rome\":20220713T093000",
+            "SUMMARY:planning",
+            "BEGIN:VALARM",
+            "ACTION:DISPLAY",
+            "TRIGGER:-PT15M",
+            "END:VALARM",
+            "END:VEVENT",
+            "END:VCALENDAR",
+        ];
+        assert_eq!(check(&valid), Vec::<String>::new());
+        let without = |name: &str| -> Vec<String> {
+            let lines: Vec<&str> = valid
+                .iter()
+                .filter(|l| !l.starts_with(name))
+                .copied()
+                .collect();
+            check(&lines)
+        };
+        assert_eq!(without("PRODID"), ["line 1: VCALENDAR without PRODID"]);
+        assert_eq!(without("UID"), ["line 4: VEVENT without UID"]);
+        assert_eq!(without("DTSTART"), ["line 4: VEVENT without DTSTART"]);
+        assert_eq!(without("TRIGGER"), ["line 9: VALARM without TRIGGER"]);
+        let mut repeated = valid.to_vec();
+        repeated.insert(7, "SUMMARY:again");
+        assert_eq!(check(&repeated), ["line 9: SUMMARY repeated in VEVENT"]);
+        let mut both = valid.to_vec();
+        both.insert(7, "DTEND:20220713T103000");
+        both.insert(7, "DURATION:PT1H");
+        assert_eq!(
+            check(&both),
+            ["line 4: VEVENT with both DTEND and DURATION"]
+        );
+        let mut local = valid.to_vec();
+        local[5] = "DTSTAMP:20220713T090000";
+        assert_eq!(
+            check(&local),
+            ["line 6: DTSTAMP 20220713T090000 not in UTC"]
+        );
+        let mut version = valid.to_vec();
+        version[1] = "VERSION:1.0";
+        assert_eq!(check(&version), ["line 2: VERSION 1.0 instead of 2.0"]);
+        assert_eq!(
+            check(&valid[..13]),
+            ["line 1: BEGIN:VCALENDAR is never closed"]
+        );
+        assert_eq!(
+            check(&[
+                "BEGIN:VCALENDAR",
+                "VERSION:2.0",
+                "PRODID:x",
+                "END:VCALENDAR"
+            ]),
+            ["line 1: VCALENDAR without components"]
+        );
+        let mut bad = valid.to_vec();
+        bad[7] = "SUMMARY planning";
+        bad[6] = "DT START:20220713T093000";
+        assert_eq!(
+            check(&bad),
+            [
+                "line 7: invalid property name \"DT START\"",
+                "line 8: no value",
+                "line 4: VEVENT without DTSTART"
+            ]
+        );
+        // the lines have to be terminated by CRLF and folded
+        let doc =
+            write_lines(valid).replace("SUMMARY:planning", &format!("SUMMARY:{}", "x".repeat(80)));
+        assert_eq!(validate(&doc), ["line 8: 88 octets long, not folded at 75"]);
+        let doc = valid.join("\n");
+        assert_eq!(validate(&doc), ["line 1: not terminated by CRLF"]);
+        // METHOD:CANCEL and the like can leave DTSTART out
+        let mut cancel = valid.to_vec();
+        cancel.insert(3, "METHOD:CANCEL");
+        cancel.retain(|l| !l.starts_with("DTSTART"));
+        assert_eq!(check(&cancel), Vec::<String>::new());
+    }
+
+    #[test]
+    /// tests formatting and parsing DURATION values
+    fn test_duration() {
+        assert_eq!(duration(Duration::minutes(90)), "PT1H30M");
+        assert_eq!(duration(Duration::seconds(-15)), "-PT15S");
+        assert_eq!(duration(Duration::zero()), "PT0S");
+        assert_eq!(parse_duration("PT1H30M"), Some(Duration::minutes(90)));
+        assert_eq!(parse_duration("P1DT2H"), Some(Duration::hours(26)));
+        assert_eq!(parse_duration("P1W"), Some(Duration::days(7)));
+        assert_eq!(parse_duration("-PT15M"), Some(Duration::minutes(-15)));
+        assert_eq!(parse_duration("PT1H30"), None);
+        assert_eq!(parse_duration("P1H"), None);
+        assert_eq!(parse_duration("1H"), None);
+        assert_eq!(parse_duration("P99999999999W"), None);
+        assert_eq!(parse_duration("PT9223372036854775807H"), None);
+    }
+
+    #[test]
+    /// tests rejecting the documents the iCalendar library cannot parse
+    fn test_structure_error() {
+        let doc = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nSUMMARY:x\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        assert_eq!(structure_error(doc), None);
+        let nested = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nBEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n";
+        assert!(structure_error(nested).is_some());
+        let mismatched = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nEND:VCALENDAR\r\n";
+        assert!(structure_error(mismatched).is_some());
+        assert!(structure_error("BEGIN:VCALENDAR\r\naaé:x\r\nEND:VCALENDAR\r\n").is_some());
+    }
+
+    #[test]
+    /// tests describing time zones with their DST transitions, and events in them
+    fn test_vtimezone() {
+        let at = |y, m, d, h| {
+            NaiveDate::from_ymd_opt(y, m, d)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+        };
+        let rome = vtimezone(
+            chrono_tz::Europe::Rome,
+            at(2022, 1, 10, 0),
+            at(2022, 12, 10, 0),
+        );
+        assert_eq!(
+            rome.join("\n"),
+            [
+                "BEGIN:VTIMEZONE",
+                "TZID:Europe/Rome",
+                "BEGIN:STANDARD",
+                "DTSTART:20220110T000000",
+                "TZOFFSETFROM:+0100",
+                "TZOFFSETTO:+0100",
+                "TZNAME:CET",
+                "END:STANDARD",
+                "BEGIN:DAYLIGHT",
+                "DTSTART:20220327T020000",
+                "TZOFFSETFROM:+0100",
+                "TZOFFSETTO:+0200",
+                "TZNAME:CEST",
+                "END:DAYLIGHT",
+                "BEGIN:STANDARD",
+                "DTSTART:20221030T030000",
+                "TZOFFSETFROM:+0200",
+                "TZOFFSETTO:+0100",
+                "TZNAME:CET",
+                "END:STANDARD",
+                "END:VTIMEZONE",
+            ]
+            .join("\n")
+        );
+        let new_york = vtimezone(
+            chrono_tz::America::New_York,
+            at(2022, 7, 1, 9),
+            at(2023, 7, 1, 9),
+        )
+        .join("\n");
+        for part in [
+            "BEGIN:DAYLIGHT\nDTSTART:20220701T090000\nTZOFFSETFROM:-0400\nTZOFFSETTO:-0400",
+            "BEGIN:STANDARD\nDTSTART:20221106T020000\nTZOFFSETFROM:-0400\nTZOFFSETTO:-0500",
+            "BEGIN:DAYLIGHT\nDTSTART:20230312T020000\nTZOFFSETFROM:-0500\nTZOFFSETTO:-0400",
+        ] {
+            assert!(new_york.contains(part), "{}", part);
+        }
+        assert_eq!(new_york.matches("BEGIN:").count(), 4);
+        let kolkata = vtimezone(
+            chrono_tz::Asia::Kolkata,
+            at(2022, 1, 1, 0),
+            at(2023, 1, 1, 0),
+        );
+        assert_eq!(kolkata.len(), 9);
+        assert!(kolkata.contains(&String::from("TZOFFSETTO:+0530")));
+
+        let ev = Event::new(
+            "standup",
+            "",
+            "21/03/2022",
+            "09:00",
+            1.0,
+            None,
+            Some("weekly 2"),
+            None,
+        );
+        assert_eq!(span(&ev), (at(2022, 3, 21, 9), at(2022, 4, 4, 10)));
+        let tz = Some(chrono_tz::Europe::Rome);
+        let (from, until) = span(&ev);
+        let ics = vcalendar(vec![
+            vtimezone(chrono_tz::Europe::Rome, from, until),
+            vevent(1, &ev, tz, None, |_| None),
+        ]);
+        assert!(ics.contains("DTSTART;TZID=Europe/Rome:20220321T090000\r\n"));
+        assert!(ics.contains("DTEND;TZID=Europe/Rome:20220321T100000\r\n"));
+        assert!(icalendar::parser::read_calendar(&ics).is_ok());
+    }
+}
@@ -0,0 +1,253 @@
+/// Lines longer than this many octets are folded (RFC 5545)
+pub const MAX_LINE_LEN: usize = 75;
+
+/// Escapes the characters with a special meaning in TEXT values: backslashes, semicolons,
+/// commas and the newlines (CRLF, LF or a lone CR). The other control characters, not allowed
+/// in TEXT values, are left out, but for tabs
+pub fn escape_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\r' | '\n' => {
+                if c == '\r' && chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                out.push_str("\\n");
+            }
+            '\t' => out.push(c),
+            c if c.is_ascii_control() => (),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Reverses [`escape_text`]: "\n" (or "\N") is a newline, and the other escaped characters
+/// are themselves. A backslash escaping nothing is kept as it is
+pub fn unescape_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(c @ ('\\' | ';' | ',')) => out.push(c),
+            Some(c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Quotes a parameter value if it contains characters with a special meaning: the double
+/// quotes, not allowed in it, become single ones, and the control characters are left out
+pub fn param_value(s: &str) -> String {
+    let value: String = s
+        .chars()
+        .filter(|c| *c == '\t' || !c.is_ascii_control())
+        .map(|c| if c == '"' { '\'' } else { c })
+        .collect();
+    if value.contains([':', ';', ',']) {
+        format!("\"{}\"", value)
+    } else {
+        value
+    }
+}
+
+/// Folds a content line into lines of at most 75 octets, joined by CRLF: the continuation
+/// lines start with a space, that counts towards their length, and the characters encoded in
+/// more than one octet are never split
+pub fn fold(line: &str) -> String {
+    let mut out = String::with_capacity(line.len() + line.len() / MAX_LINE_LEN * 3);
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > MAX_LINE_LEN {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out
+}
+
+/// Joins the content lines, folding the long ones and terminating each with CRLF
+pub fn write_lines<S: AsRef<str>>(lines: impl IntoIterator<Item = S>) -> String {
+    let mut out = String::new();
+    for line in lines {
+        out.push_str(&fold(line.as_ref()));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ics_writer::{
+        escape_text, fold, param_value, unescape_text, write_lines, MAX_LINE_LEN,
+    };
+
+    /// Characters encoded in 1, 2, 3 and 4 octets
+    const WIDTHS: [char; 4] = ['a', 'è', '€', '😀'];
+
+    #[test]
+    /// tests escaping each character with a special meaning in TEXT values, alone and mixed
+    /// with the others
+    fn test_escape_text() {
+        for (text, escaped) in [
+            ("", ""),
+            ("plain text", "plain text"),
+            ("a,b", "a\\,b"),
+            ("a;b", "a\\;b"),
+            ("a\\b", "a\\\\b"),
+            ("a\nb", "a\\nb"),
+            ("a\r\nb", "a\\nb"),
+            ("a\rb", "a\\nb"),
+            ("a\n\nb", "a\\n\\nb"),
+            ("a\r\rb", "a\\n\\nb"),
+            ("a\n\r\nb", "a\\n\\nb"),
+            ("a\tb", "a\tb"),
+            ("a\u{0}\u{7}\u{1b}\u{7f}b", "ab"),
+            ("\\n", "\\\\n"),
+            ("\\,", "\\\\\\,"),
+            (",;\\\n", "\\,\\;\\\\\\n"),
+            ("è, € ; 😀\n", "è\\, € \\; 😀\\n"),
+            ("key: value", "key: value"),
+            ("\"quoted\"", "\"quoted\""),
+        ] {
+            assert_eq!(escape_text(text), escaped, "{:?}", text);
+        }
+    }
+
+    #[test]
+    /// tests that unescaping gives back the text escaped, with its newlines as LF, for all the
+    /// ASCII characters and some wider ones, in every pair
+    fn test_unescape_text() {
+        let chars: Vec<char> = (0..128u8)
+            .map(char::from)
+            .filter(|c| !c.is_ascii_control() || matches!(c, '\t' | '\n'))
+            .chain(WIDTHS)
+            .collect();
+        for a in chars.iter() {
+            for b in chars.iter() {
+                let text: String = [*a, *b, *a].iter().collect();
+                assert_eq!(unescape_text(&escape_text(&text)), text, "{:?}", text);
+            }
+        }
+        assert_eq!(unescape_text(&escape_text("a\r\nb\rc")), "a\nb\nc");
+        // escaped by other writers
+        assert_eq!(unescape_text("a\\Nb"), "a\nb");
+        assert_eq!(unescape_text("a\\:b\\"), "a\\:b\\");
+    }
+
+    #[test]
+    /// tests quoting the parameter values with special characters
+    fn test_param_value() {
+        assert_eq!(param_value("Europe/Rome"), "Europe/Rome");
+        assert_eq!(param_value("report, final.pdf"), "\"report, final.pdf\"");
+        assert_eq!(param_value("a;b"), "\"a;b\"");
+        assert_eq!(param_value("mailto:me"), "\"mailto:me\"");
+        assert_eq!(param_value("say \"hi\""), "say 'hi'");
+        assert_eq!(param_value("a\nb:c"), "\"ab:c\"");
+    }
+
+    /// Checks that the line is folded as fully as possible into lines of at most 75 octets,
+    /// that give it back when unfolded
+    fn check_folded(line: &str) {
+        let folded = fold(line);
+        let lines: Vec<&str> = folded.split("\r\n").collect();
+        for (i, l) in lines.iter().enumerate() {
+            assert!(l.len() <= MAX_LINE_LEN, "{:?} too long", l);
+            assert_eq!(i > 0, l.starts_with(' '), "{:?}", l);
+            // the next character did not fit
+            if let Some(next) = lines.get(i + 1) {
+                let c = next[1..].chars().next().unwrap();
+                assert!(l.len() + c.len_utf8() > MAX_LINE_LEN, "{:?} not full", l);
+            }
+        }
+        let unfolded: String = lines
+            .iter()
+            .enumerate()
+            .map(|(i, l)| if i == 0 { *l } else { &l[1..] })
+            .collect();
+        assert_eq!(unfolded, line);
+    }
+
+    #[test]
+    /// tests folding lines of every length up to a few folds, made of characters of each width
+    /// after prefixes of each length, so that the folds fall at every position within them
+    fn test_fold() {
+        assert_eq!(fold(""), "");
+        assert_eq!(fold(&"a".repeat(75)), "a".repeat(75));
+        assert_eq!(fold(&"a".repeat(76)), format!("{}\r\n a", "a".repeat(75)));
+        for c in WIDTHS {
+            for prefix in 0..4 {
+                for n in 0..=(4 * MAX_LINE_LEN / c.len_utf8()) {
+                    let line = "a".repeat(prefix) + &c.to_string().repeat(n);
+                    check_folded(&line);
+                }
+            }
+        }
+        let mixed: String = WIDTHS.iter().cycle().take(500).collect();
+        check_folded(&mixed);
+    }
+
+    #[test]
+    /// tests joining the content lines, each terminated by CRLF
+    fn test_write_lines() {
+        assert_eq!(write_lines(Vec::<String>::new()), "");
+        assert_eq!(
+            write_lines(["BEGIN:VEVENT", "END:VEVENT"]),
+            "BEGIN:VEVENT\r\nEND:VEVENT\r\n"
+        );
+        let long = format!("DESCRIPTION:{}", "è".repeat(100));
+        let out = write_lines([long.as_str(), "END:VEVENT"]);
+        let lines: Vec<&str> = out.split_terminator("\r\n").collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines.iter().all(|l| l.len() <= 75));
+        assert!(lines[1].starts_with(' ') && lines[2].starts_with(' '));
+        assert_eq!(lines[..3].concat().replace(" è", "è"), long);
+    }
+
+    #[test]
+    /// tests that the iCalendar library reads back the escaped and folded values
+    fn test_round_trip() {
+        let texts = [
+            "Review, final; then lunch",
+            "line 1\nline 2\r\nline 3",
+            "C:\\path\\to\\file, or \\n literally",
+            &"è, 😀; €\n".repeat(40),
+        ];
+        let mut lines = vec![
+            String::from("BEGIN:VCALENDAR"),
+            String::from("BEGIN:VEVENT"),
+        ];
+        lines.extend(
+            texts
+                .iter()
+                .map(|t| format!("DESCRIPTION:{}", escape_text(t))),
+        );
+        lines.push(String::from("END:VEVENT"));
+        lines.push(String::from("END:VCALENDAR"));
+        let doc = write_lines(lines);
+        let unfolded = icalendar::parser::unfold(&doc);
+        let cal = icalendar::parser::read_calendar(&unfolded).unwrap();
+        let read: Vec<String> = cal.components[0]
+            .properties
+            .iter()
+            .map(|p| unescape_text(p.val.as_str()))
+            .collect();
+        let expected: Vec<String> = texts.iter().map(|t| t.replace("\r\n", "\n")).collect();
+        assert_eq!(read, expected);
+    }
+}
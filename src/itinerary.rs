@@ -0,0 +1,543 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use chrono_tz::Tz;
+use icalendar::parser::{Component, Property};
+
+use crate::event::{Event, Geo};
+use crate::ics;
+use crate::zones;
+
+/// Tag of the events of the flights
+pub const TRAVEL_TAG: &str = "travel";
+
+/// An airport, known by its IATA code
+pub struct Airport {
+    pub code: &'static str,
+    lat: f64,
+    lon: f64,
+    zone: &'static str,
+}
+
+impl Airport {
+    pub fn geo(&self) -> Option<Geo> {
+        Geo::new(self.lat, self.lon)
+    }
+    pub fn time_zone(&self) -> Tz {
+        self.zone.parse().unwrap_or(Tz::UTC)
+    }
+}
+
+const fn airport(code: &'static str, lat: f64, lon: f64, zone: &'static str) -> Airport {
+    Airport {
+        code,
+        lat,
+        lon,
+        zone,
+    }
+}
+
+/// The airports the flights of the itineraries can depart from and arrive to
+const AIRPORTS: [Airport; 72] = [
+    // Europe
+    airport("LHR", 51.470, -0.454, "Europe/London"),
+    airport("LGW", 51.148, -0.190, "Europe/London"),
+    airport("STN", 51.885, 0.235, "Europe/London"),
+    airport("MAN", 53.354, -2.275, "Europe/London"),
+    airport("EDI", 55.950, -3.373, "Europe/London"),
+    airport("DUB", 53.421, -6.270, "Europe/Dublin"),
+    airport("CDG", 49.010, 2.548, "Europe/Paris"),
+    airport("ORY", 48.723, 2.379, "Europe/Paris"),
+    airport("NCE", 43.658, 7.216, "Europe/Paris"),
+    airport("AMS", 52.310, 4.768, "Europe/Amsterdam"),
+    airport("BRU", 50.901, 4.484, "Europe/Brussels"),
+    airport("FRA", 50.033, 8.571, "Europe/Berlin"),
+    airport("MUC", 48.354, 11.786, "Europe/Berlin"),
+    airport("BER", 52.367, 13.503, "Europe/Berlin"),
+    airport("ZRH", 47.458, 8.556, "Europe/Zurich"),
+    airport("GVA", 46.238, 6.109, "Europe/Zurich"),
+    airport("VIE", 48.110, 16.570, "Europe/Vienna"),
+    airport("FCO", 41.800, 12.239, "Europe/Rome"),
+    airport("CIA", 41.799, 12.595, "Europe/Rome"),
+    airport("MXP", 45.630, 8.723, "Europe/Rome"),
+    airport("LIN", 45.445, 9.277, "Europe/Rome"),
+    airport("BGY", 45.674, 9.704, "Europe/Rome"),
+    airport("VCE", 45.505, 12.352, "Europe/Rome"),
+    airport("NAP", 40.886, 14.291, "Europe/Rome"),
+    airport("PSA", 43.684, 10.393, "Europe/Rome"),
+    airport("BLQ", 44.535, 11.289, "Europe/Rome"),
+    airport("CTA", 37.467, 15.066, "Europe/Rome"),
+    airport("PMO", 38.176, 13.091, "Europe/Rome"),
+    airport("MAD", 40.472, -3.561, "Europe/Madrid"),
+    airport("BCN", 41.297, 2.078, "Europe/Madrid"),
+    airport("LIS", 38.774, -9.134, "Europe/Lisbon"),
+    airport("CPH", 55.618, 12.656, "Europe/Copenhagen"),
+    airport("OSL", 60.194, 11.100, "Europe/Oslo"),
+    airport("ARN", 59.652, 17.919, "Europe/Stockholm"),
+    airport("HEL", 60.317, 24.963, "Europe/Helsinki"),
+    airport("WAW", 52.166, 20.967, "Europe/Warsaw"),
+    airport("PRG", 50.101, 14.260, "Europe/Prague"),
+    airport("BUD", 47.437, 19.256, "Europe/Budapest"),
+    airport("ATH", 37.936, 23.947, "Europe/Athens"),
+    airport("IST", 41.262, 28.727, "Europe/Istanbul"),
+    airport("KEF", 63.985, -22.606, "Atlantic/Reykjavik"),
+    // Middle East, Asia and Oceania
+    airport("DXB", 25.253, 55.365, "Asia/Dubai"),
+    airport("DOH", 25.273, 51.608, "Asia/Qatar"),
+    airport("TLV", 32.011, 34.887, "Asia/Jerusalem"),
+    airport("DEL", 28.556, 77.100, "Asia/Kolkata"),
+    airport("BOM", 19.090, 72.866, "Asia/Kolkata"),
+    airport("SIN", 1.364, 103.991, "Asia/Singapore"),
+    airport("BKK", 13.690, 100.750, "Asia/Bangkok"),
+    airport("HKG", 22.308, 113.918, "Asia/Hong_Kong"),
+    airport("PEK", 40.080, 116.585, "Asia/Shanghai"),
+    airport("PVG", 31.144, 121.808, "Asia/Shanghai"),
+    airport("ICN", 37.460, 126.441, "Asia/Seoul"),
+    airport("NRT", 35.765, 140.386, "Asia/Tokyo"),
+    airport("HND", 35.549, 139.780, "Asia/Tokyo"),
+    airport("SYD", -33.946, 151.177, "Australia/Sydney"),
+    airport("MEL", -37.669, 144.841, "Australia/Melbourne"),
+    // Africa
+    airport("CAI", 30.122, 31.406, "Africa/Cairo"),
+    airport("JNB", -26.139, 28.246, "Africa/Johannesburg"),
+    airport("NBO", -1.319, 36.928, "Africa/Nairobi"),
+    // Americas
+    airport("JFK", 40.641, -73.778, "America/New_York"),
+    airport("EWR", 40.690, -74.175, "America/New_York"),
+    airport("BOS", 42.366, -71.010, "America/New_York"),
+    airport("IAD", 38.953, -77.456, "America/New_York"),
+    airport("MIA", 25.793, -80.290, "America/New_York"),
+    airport("ATL", 33.641, -84.428, "America/New_York"),
+    airport("ORD", 41.978, -87.905, "America/Chicago"),
+    airport("DFW", 32.900, -97.040, "America/Chicago"),
+    airport("DEN", 39.856, -104.674, "America/Denver"),
+    airport("LAX", 33.942, -118.408, "America/Los_Angeles"),
+    airport("SFO", 37.621, -122.379, "America/Los_Angeles"),
+    airport("YYZ", 43.677, -79.625, "America/Toronto"),
+    airport("GRU", -23.435, -46.473, "America/Sao_Paulo"),
+];
+
+/// Returns the airport with the IATA code, if known
+pub fn find_airport(code: &str) -> Option<&'static Airport> {
+    AIRPORTS.iter().find(|a| a.code == code)
+}
+
+/// A flight of an itinerary, with its times local to the airports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Flight {
+    /// Designator of the airline and number, as "AZ610"
+    pub number: String,
+    pub from: &'static str,
+    pub to: &'static str,
+    pub departure: NaiveDateTime,
+    pub arrival: NaiveDateTime,
+}
+
+impl Flight {
+    /// Returns the event of the flight, from the departure to the arrival, with the check-in
+    /// as its travel time, and the event of the arrival lasting `after` (to get out of the
+    /// airport), both with their times moved to the zone `home` (the one of the system if
+    /// None). The arrival is to be related to the flight once added.
+    /// Returns None if the times do not exist in the zones of the airports
+    pub fn events(
+        &self,
+        home: Option<Tz>,
+        check_in: Duration,
+        after: Duration,
+    ) -> Option<(Event, Event)> {
+        let (from, to) = (find_airport(self.from)?, find_airport(self.to)?);
+        let departure = zones::convert(self.departure, Some(from.time_zone()), Some(Tz::UTC))?;
+        let mut arrival = zones::convert(self.arrival, Some(to.time_zone()), Some(Tz::UTC))?;
+        // an overnight arrival given without its date is on the next day
+        if arrival <= departure && arrival + Duration::days(1) > departure {
+            arrival += Duration::days(1);
+        }
+        if arrival <= departure {
+            return None;
+        }
+        let landing = zones::convert(arrival, Some(Tz::UTC), Some(to.time_zone()))?;
+        let mut flight = new_event(
+            &format!("Flight {} {}-{}", self.number, self.from, self.to),
+            &format!(
+                "Arrives at {} on {} local time",
+                self.to,
+                landing.format("%d/%m/%Y %H:%M")
+            ),
+            zones::convert(departure, Some(Tz::UTC), home)?,
+            arrival - departure,
+            from,
+        );
+        flight.set_travel_time(&check_in);
+        let arrival = new_event(
+            &format!("Arrival {} at {}", self.number, self.to),
+            "",
+            zones::convert(arrival, Some(Tz::UTC), home)?,
+            after,
+            to,
+        );
+        Some((flight, arrival))
+    }
+}
+
+fn new_event(
+    title: &str,
+    descr: &str,
+    start: NaiveDateTime,
+    length: Duration,
+    airport: &Airport,
+) -> Event {
+    let mut ev = Event::new(
+        title,
+        descr,
+        &start.format("%d/%m/%Y").to_string(),
+        &start.format("%H:%M").to_string(),
+        0.0,
+        Some(airport.code),
+        None,
+        Some(vec![TRAVEL_TAG.to_string()]),
+    );
+    ev.set_duration(&length);
+    ev.set_geo(airport.geo());
+    ev.set_time_zone(Some(airport.time_zone()));
+    ev
+}
+
+/// A meaningful word of an itinerary
+#[derive(Debug, PartialEq)]
+enum Token {
+    Number(String),
+    Airport(&'static str),
+    Date(NaiveDate),
+    /// A time, and the days it is after the date before it ("07:30+1")
+    Time(NaiveTime, i64),
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Splits the text in the tokens of the flights, ignoring the other words
+fn tokenize(text: &str) -> Vec<Token> {
+    let words: Vec<&str> = text
+        .split(|c: char| c.is_whitespace() || ",;()[]|→–—>".contains(c))
+        .flat_map(|w| match NaiveDate::parse_from_str(w, "%Y-%m-%d") {
+            Ok(_) => vec![w],
+            Err(_) => w.split('-').collect(),
+        })
+        .map(|w| w.trim_matches(|c: char| c == '.' || c == ':' || c == '*'))
+        .filter(|w| !w.is_empty())
+        .collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        let word = words[i];
+        // "ON 20 OCT 2026" is a date, not the flight ON20
+        let next = match date(&words[(i + 1).min(words.len())..]) {
+            Some(_) => "",
+            None => words.get(i + 1).copied().unwrap_or_default(),
+        };
+        if let Some(number) = flight_number(word, next) {
+            i += if number.len() > word.len() { 2 } else { 1 };
+            tokens.push(Token::Number(number));
+            continue;
+        }
+        if let Some(airport) = find_airport(word) {
+            tokens.push(Token::Airport(airport.code));
+        } else if let Some((date, used)) = date(&words[i..]) {
+            tokens.push(Token::Date(date));
+            i += used;
+            continue;
+        } else if let Some((mut time, mut days)) = time(word) {
+            let mut used = 1;
+            // "02:15 PM", "07:30 +1"
+            for next in words.iter().skip(i + 1).take(2) {
+                match next.to_ascii_lowercase().as_str() {
+                    "am" if time.hour() == 12 => time -= Duration::hours(12),
+                    "pm" if time.hour() < 12 => time += Duration::hours(12),
+                    "am" | "pm" => (),
+                    n if n.starts_with('+') && n[1..].parse::<i64>().is_ok() => {
+                        days = n[1..].parse().unwrap_or(0);
+                    }
+                    _ => break,
+                }
+                used += 1;
+            }
+            tokens.push(Token::Time(time, days));
+            i += used;
+            continue;
+        }
+        i += 1;
+    }
+    tokens
+}
+
+/// Parses the number of a flight, as "AZ610" or "AZ 610" (with the next word)
+fn flight_number(word: &str, next: &str) -> Option<String> {
+    let designator = word.get(..2)?;
+    let valid = designator
+        .chars()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        && designator.chars().any(|c| c.is_ascii_uppercase());
+    if !valid {
+        return None;
+    }
+    let digits = |s: &str| (1..=4).contains(&s.len()) && s.chars().all(|c| c.is_ascii_digit());
+    if digits(&word[2..]) {
+        Some(word.to_string())
+    } else if word.len() == 2 && digits(next) {
+        Some(format!("{}{}", word, next))
+    } else {
+        None
+    }
+}
+
+/// Parses the date at the start of the words ("20/10/2026", "2026-10-20", "20 Oct 2026",
+/// "20OCT2026"), returning it with the number of words it takes
+fn date(words: &[&str]) -> Option<(NaiveDate, usize)> {
+    for fmt in ["%d/%m/%Y", "%Y-%m-%d", "%d.%m.%Y", "%d%b%Y"] {
+        if let Ok(date) = NaiveDate::parse_from_str(words.first()?, fmt) {
+            return Some((date, 1));
+        }
+    }
+    let (day, month, year) = (words.first()?, words.get(1)?, words.get(2)?);
+    let month = month.get(..3)?.to_ascii_lowercase();
+    let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    let date = NaiveDate::from_ymd_opt(year.parse().ok()?, month, day.parse().ok()?)?;
+    Some((date, 3))
+}
+
+/// Parses a time ("14:05", "07:30+1"), with the days it is after its date
+fn time(word: &str) -> Option<(NaiveTime, i64)> {
+    let (time, days) = match word.split_once('+') {
+        Some((time, days)) => (time, days.parse().ok()?),
+        None => (word, 0),
+    };
+    let time = NaiveTime::parse_from_str(time, "%H:%M").ok()?;
+    Some((time, days))
+}
+
+/// Finds the flights in the text of an itinerary, e.g. a confirmation email: each flight is
+/// its number, followed by the airports of departure and arrival and their times, in order.
+/// The times take the last date before them
+pub fn parse_text(text: &str) -> Vec<Flight> {
+    let mut flights = Vec::new();
+    let mut date = None;
+    let mut number = None;
+    let (mut airports, mut times) = (Vec::new(), Vec::new());
+    for token in tokenize(text) {
+        match token {
+            Token::Number(n) => {
+                number = Some(n);
+                airports.clear();
+                times.clear();
+            }
+            Token::Airport(code) if number.is_some() && airports.len() < 2 => airports.push(code),
+            Token::Date(d) => date = Some(d),
+            Token::Time(t, days) if number.is_some() && times.len() < 2 => {
+                if let Some(d) = date {
+                    times.push(d.and_time(t) + Duration::days(days));
+                }
+            }
+            _ => (),
+        }
+        if airports.len() == 2 && times.len() == 2 {
+            if let Some(number) = number.take() {
+                flights.push(Flight {
+                    number,
+                    from: airports[0],
+                    to: airports[1],
+                    departure: times[0],
+                    arrival: times[1],
+                });
+            }
+        }
+    }
+    flights
+}
+
+/// Returns the time of the DTSTART or DTEND property in the zone of the airport: the
+/// airlines write them in UTC, in a zone given by TZID or floating (local to the airport)
+fn airport_time(prop: &Property, airport: &Airport) -> Option<NaiveDateTime> {
+    let val = prop.val.as_str();
+    let dt = NaiveDateTime::parse_from_str(val.trim_end_matches('Z'), "%Y%m%dT%H%M%S").ok()?;
+    let zone = prop
+        .params
+        .iter()
+        .find(|p| p.key.as_str() == "TZID")
+        .and_then(|p| p.val.as_ref())
+        .and_then(|tz| tz.as_str().trim_matches('"').parse::<Tz>().ok());
+    match (val.ends_with('Z'), zone) {
+        (true, _) => zones::convert(dt, Some(Tz::UTC), Some(airport.time_zone())),
+        (false, Some(tz)) => zones::convert(dt, Some(tz), Some(airport.time_zone())),
+        (false, None) => Some(dt),
+    }
+}
+
+/// Returns the flight of the VEVENT of an airline, if it is one: its number and airports
+/// are in its summary, location or description
+fn ics_flight(comp: &Component) -> Option<Flight> {
+    let prop = |name: &str| comp.properties.iter().find(|p| p.name.as_str() == name);
+    let text: Vec<&str> = ["SUMMARY", "LOCATION", "DESCRIPTION"]
+        .iter()
+        .filter_map(|name| prop(name).map(|p| p.val.as_str()))
+        .collect();
+    let tokens = tokenize(&text.join("\n").replace("\\n", "\n").replace("\\,", ","));
+    let number = tokens.iter().find_map(|t| match t {
+        Token::Number(n) => Some(n.clone()),
+        _ => None,
+    })?;
+    let mut airports = tokens.iter().filter_map(|t| match t {
+        Token::Airport(code) => Some(*code),
+        _ => None,
+    });
+    let from = airports.next()?;
+    let to = airports.find(|code| *code != from)?;
+    let (from, to) = (find_airport(from)?, find_airport(to)?);
+    let departure = airport_time(prop("DTSTART")?, from)?;
+    let arrival = match (prop("DTEND"), prop("DURATION")) {
+        (Some(end), _) => airport_time(end, to)?,
+        // without an end, the duration is the flight time
+        (None, Some(length)) => {
+            let utc = zones::convert(departure, Some(from.time_zone()), Some(Tz::UTC))?;
+            let utc = utc + ics::parse_duration(length.val.as_str())?;
+            zones::convert(utc, Some(Tz::UTC), Some(to.time_zone()))?
+        }
+        (None, None) => return None,
+    };
+    Some(Flight {
+        number,
+        from: from.code,
+        to: to.code,
+        departure,
+        arrival,
+    })
+}
+
+/// Finds the flights in the iCalendar document of an airline, returning them with the
+/// number of the other events (hotels, cars) left out
+pub fn parse_ics(buf: &str) -> Result<(Vec<Flight>, usize), String> {
+    // the iCalendar library panics on the documents cut short
+    if !buf.trim_end().ends_with("END:VCALENDAR") {
+        return Err(String::from(
+            "END:VCALENDAR missing, the document is incomplete",
+        ));
+    }
+    let unfolded = icalendar::parser::unfold(buf);
+    let cal = icalendar::parser::read_calendar(&unfolded)?;
+    let mut flights = Vec::new();
+    let mut others = 0;
+    for comp in cal.components.iter().filter(|c| c.name == "VEVENT") {
+        match ics_flight(comp) {
+            Some(flight) => flights.push(flight),
+            None => others += 1,
+        }
+    }
+    Ok((flights, others))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+    use chrono_tz::Tz;
+
+    use crate::itinerary::{find_airport, parse_ics, parse_text, AIRPORTS};
+
+    #[test]
+    /// tests finding the flights in the text of a confirmation email, and their events
+    fn test_parse_text() {
+        for airport in AIRPORTS.iter() {
+            assert!(airport.zone.parse::<Tz>().is_ok(), "{}", airport.code);
+        }
+        let text = "Booking ABC123 - DEPARTS ON 20 OCT 2026\n\
+                    Flight AZ 610, Tue 20 Oct 2026\n\
+                    Departure: Rome Fiumicino (FCO) 10:15\n\
+                    Arrival: New York (JFK) 02:05 PM\n\
+                    Flight AZ611 JFK 27/10/2026 17:30 → FCO 07:50 +1\n\
+                    Seat 12A, Terminal T1\n";
+        let flights = parse_text(text);
+        assert_eq!(flights.len(), 2, "{:?}", flights);
+        let at = |d, h, m| {
+            NaiveDate::from_ymd_opt(2026, 10, d)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+        };
+        assert_eq!(flights[0].number, "AZ610");
+        assert_eq!((flights[0].from, flights[0].to), ("FCO", "JFK"));
+        assert_eq!(flights[0].departure, at(20, 10, 15));
+        assert_eq!(flights[0].arrival, at(20, 14, 5));
+        assert_eq!((flights[1].from, flights[1].to), ("JFK", "FCO"));
+        assert_eq!(flights[1].arrival, at(28, 7, 50));
+
+        let rome = Some(Tz::Europe__Rome);
+        let (flight, arrival) = flights[0]
+            .events(rome, Duration::hours(2), Duration::minutes(30))
+            .unwrap();
+        assert_eq!(flight.get_title(), "Flight AZ610 FCO-JFK");
+        assert_eq!(flight.get_start(), at(20, 10, 15));
+        assert_eq!(
+            flight.get_duration(),
+            Duration::minutes(9 * 60 + 50).num_seconds()
+        );
+        assert_eq!(flight.get_travel_time(), Duration::hours(2));
+        assert_eq!(flight.get_time_zone(), rome);
+        assert_eq!(arrival.get_start(), at(20, 20, 5));
+        assert_eq!(arrival.get_time_zone(), Some(Tz::America__New_York));
+        assert_eq!(arrival.get_location(), "JFK");
+        assert_eq!(arrival.get_geo(), find_airport("JFK").unwrap().geo());
+
+        // an overnight arrival without its date
+        let flights = parse_text("BA117 LHR 20/10/2026 23:30 JFK 02:10");
+        let (flight, arrival) = flights[0]
+            .events(rome, Duration::zero(), Duration::zero())
+            .unwrap();
+        assert_eq!(
+            flight.get_duration(),
+            Duration::minutes(7 * 60 + 40).num_seconds()
+        );
+        assert_eq!(arrival.get_start(), at(21, 8, 10));
+        assert!(parse_text("AZ610 XXX 20/10/2026 10:15 YYY 14:05").is_empty());
+    }
+
+    #[test]
+    /// tests finding the flights in the iCalendar documents of the airlines
+    fn test_parse_ics() {
+        let ics = "BEGIN:VCALENDAR\r\n\
+                   VERSION:2.0\r\n\
+                   BEGIN:VEVENT\r\n\
+                   UID:1\r\n\
+                   SUMMARY:LH 1842 Frankfurt - Barcelona\r\n\
+                   LOCATION:Frankfurt (FRA)\r\n\
+                   DESCRIPTION:Flight LH1842 from FRA to BCN\\nSeat 14C\r\n\
+                   DTSTART:20261020T081500Z\r\n\
+                   DURATION:PT2H5M\r\n\
+                   END:VEVENT\r\n\
+                   BEGIN:VEVENT\r\n\
+                   UID:2\r\n\
+                   SUMMARY:Hotel Arts\r\n\
+                   DTSTART:20261020T150000\r\n\
+                   END:VEVENT\r\n\
+                   BEGIN:VEVENT\r\n\
+                   UID:3\r\n\
+                   SUMMARY:Flight LH1843 BCN-FRA\r\n\
+                   DTSTART;TZID=Europe/Madrid:20261023T190000\r\n\
+                   DTEND;TZID=Europe/Berlin:20261023T210500\r\n\
+                   END:VEVENT\r\n\
+                   END:VCALENDAR\r\n";
+        let (flights, others) = parse_ics(ics).unwrap();
+        assert_eq!(others, 1);
+        assert_eq!(flights.len(), 2);
+        let at = |d, h, m| {
+            NaiveDate::from_ymd_opt(2026, 10, d)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+        };
+        // the times in UTC are moved to the zones of the airports
+        assert_eq!(flights[0].number, "LH1842");
+        assert_eq!((flights[0].from, flights[0].to), ("FRA", "BCN"));
+        assert_eq!(flights[0].departure, at(20, 10, 15));
+        assert_eq!(flights[0].arrival, at(20, 12, 20));
+        assert_eq!(flights[1].departure, at(23, 19, 0));
+        assert_eq!(flights[1].arrival, at(23, 21, 5));
+        assert!(parse_ics("BEGIN:VCALENDAR\r\nBEGIN:VEVENT").is_err());
+    }
+}
@@ -1,4 +1,80 @@
-pub mod cli;
+pub mod anonymize;
+pub mod audit;
+pub mod availability;
+#[cfg(feature = "cli")]
+pub mod blobs;
 pub mod calendar;
 pub mod calendar_error;
-pub mod event;
\ No newline at end of file
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod clock;
+pub mod config;
+#[cfg(feature = "cli")]
+pub mod conflicts;
+pub mod countdown;
+pub mod course;
+pub mod cron;
+#[cfg(all(feature = "cli", unix))]
+pub mod daemon;
+#[cfg(feature = "cli")]
+pub mod digest;
+#[cfg(feature = "cli")]
+pub mod doctor;
+#[cfg(feature = "cli")]
+pub mod dump;
+#[cfg(feature = "cli")]
+pub mod editor;
+pub mod event;
+#[cfg(feature = "cli")]
+pub mod exchange;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod freebusy;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+#[cfg(all(feature = "grpc", unix))]
+pub mod grpc;
+pub mod habits;
+#[cfg(feature = "cli")]
+pub mod http;
+pub mod ics;
+pub mod ics_writer;
+pub mod itinerary;
+#[cfg(feature = "cli")]
+pub mod maintenance;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "cli")]
+pub mod notify;
+pub mod planner;
+#[cfg(feature = "cli")]
+pub mod prompt;
+#[cfg(feature = "cli")]
+pub mod qr;
+pub mod query;
+pub mod report;
+pub mod resources;
+pub mod review;
+pub mod rotation;
+#[cfg(feature = "cli")]
+pub mod shell;
+#[cfg(feature = "cli")]
+pub mod snapshot;
+pub mod solar;
+#[cfg(feature = "cli")]
+pub mod stats;
+#[cfg(feature = "cli")]
+pub mod storage;
+#[cfg(feature = "cli")]
+pub mod subscription;
+#[cfg(feature = "cli")]
+pub mod sync;
+#[cfg(feature = "cli")]
+pub mod table;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod timeline;
+#[cfg(feature = "cli")]
+pub mod users;
+pub mod working_location;
+pub mod zones;
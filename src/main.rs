@@ -1,66 +1,149 @@
-use log::{error, warn};
 use std::fs;
 use std::path::Path;
 
 use calendar_lib::cli::{self, Cli, Commands};
+#[cfg(unix)]
+use calendar_lib::daemon;
+use calendar_lib::{audit, config, report, shell, storage};
+
+/// Read-only queries on a calendar are answered by the daemon, if it is running.
+/// Returns false if the query has to be executed by reading the calendar files
+#[cfg(unix)]
+fn query_daemon(args: &Cli, data_dir: &Path) -> bool {
+    let cal = match &args.view {
+        Some(cal) if args.script.is_none() && !args.dry_run => cal,
+        _ => return false,
+    };
+    let mut filters = Vec::new();
+    for cmd in args.subcommand.iter().chain(args.chained.iter()) {
+        match cmd {
+            Commands::List(f) => filters.push(f.clone().fit_terminal()),
+            _ => return false,
+        }
+    }
+    if filters.is_empty() {
+        return false;
+    }
+    let token = args
+        .token
+        .clone()
+        .or_else(|| std::env::var("CALENDA_RS_TOKEN").ok());
+    match daemon::query(data_dir, cal, token, filters) {
+        Some(Ok(out)) => {
+            print!("{}", out);
+            true
+        }
+        Some(Err(e)) => {
+            report::warning(format!("The daemon failed: {:?}", e));
+            false
+        }
+        None => false,
+    }
+}
 
 fn main() {
+    let mut args = Cli::parse_cli();
+
     // Initialize logging
-    env_logger::init();
+    report::init_logging(args.verbosity(), args.log_json);
 
-    let mut data_dir = std::env::current_dir().expect("Cannot access the current directory");
-    data_dir.push("data");
+    let data_dir = match config::data_dir(args.data_dir.as_deref()) {
+        Ok(dir) => dir,
+        Err(e) => {
+            report::error(format!("Cannot access the data directory: {:?}", e));
+            return;
+        }
+    };
     if let Err(e) = fs::create_dir_all(data_dir.as_path()) {
-        error!("Data directory creation failed: {e}");
+        report::error(format!("Data directory creation failed: {e}"));
         return;
     }
 
-    let args = Cli::parse_cli();
+    if args.daemon {
+        #[cfg(unix)]
+        if let Err(e) = daemon::serve(&data_dir, args.redact) {
+            report::error(format!("{:?}", e));
+        }
+        #[cfg(not(unix))]
+        report::error("The daemon is only supported on unix systems");
+        return;
+    }
+    #[cfg(unix)]
+    if query_daemon(&args, &data_dir) {
+        return;
+    }
 
     let (readonly, res) = cli::Cli::exec_commands(&args, data_dir.as_path());
 
-    let mut cal = res
-        .expect("Error opening the calendar")
-        .expect("Missing calendar");
-    let result = match (args.subcommand, readonly) {
-        (Some(Commands::Add(x)), false) => match cli::handle_add(&mut cal, x) {
-            Ok(x) => x,
+    // commands such as --list and --delete do not operate on a calendar
+    let mut cal = match res {
+        Ok(Some(cal)) => cal,
+        Ok(None) => return,
+        Err(e) => {
+            report::error(format!("{:?}", e));
+            // e.g. `ics validate` failing a CI job
+            std::process::exit(1);
+        }
+    };
+    // the calendar as it was before executing any command, to save (or show) only the changes
+    let mut before = cal.clone();
+    // commands given on the command line, chained with ';' or read from a script
+    let mut commands: Vec<Commands> = args.subcommand.into_iter().collect();
+    commands.append(&mut args.chained);
+    if let Some(script) = &args.script {
+        match cli::parse_script(Path::new(script)) {
+            Ok(mut cmds) => commands.append(&mut cmds),
             Err(e) => {
-                error!("{}", e);
-                false
+                report::error(format!("{:?}", e));
+                return;
             }
-        },
-        (Some(Commands::Edit(x)), false) => match cli::handle_edit(&mut cal, x) {
-            Ok(x) => x,
-            Err(e) => {
-                error!("{}", e);
-                false
+        }
+    }
+    // all commands are applied to the calendar in memory, that is saved only if all of them
+    // succeed (no commands to perform => ok to save result)
+    let total = commands.len();
+    let mut result = true;
+    for (i, cmd) in commands.into_iter().enumerate() {
+        let ok = match cmd {
+            Commands::Shell => {
+                match shell::run(&mut cal, &mut before, readonly, args.dry_run, &data_dir) {
+                    // any unsaved change is written when leaving the shell
+                    Ok(_) => true,
+                    Err(e) => {
+                        report::error(format!("{:?}", e));
+                        false
+                    }
+                }
             }
-        },
-        (Some(Commands::Remove(rm)), false) => cli::handle_remove(&mut cal, rm),
-        (Some(Commands::List(l)), _) => cli::handle_list(&cal, l),
-        (Some(Commands::Set(params)), false) => cli::handle_params(&mut cal, params),
-        (Some(_), true) => {
-            warn!(
-                "Calendar {} cannot be modified! (rerun with --edit)",
-                cal.get_name()
-            );
-            eprintln!(
-                "Calendar {} cannot be modified! (rerun with --edit)",
-                cal.get_name()
-            );
-            false
+            cmd => cli::exec_subcommand(&mut cal, cmd, readonly, &data_dir),
+        };
+        if !ok {
+            if total > 1 {
+                report::error(format!(
+                    "Command {} of {} failed: calendar not modified",
+                    i + 1,
+                    total
+                ));
+            }
+            result = false;
+            break;
         }
-        (None, _) => true, // no commands to perform => ok to save result
-    };
+    }
 
-    if result
-        && !cli::save_calendar(
-            &cal,
-            &data_dir.join(Path::new(cal.get_name()).with_extension("json")),
-        )
+    if result && args.dry_run {
+        match storage::calendar_path(cal.get_id(), data_dir.as_path()) {
+            Ok(path) => cli::print_dry_run(&before, &cal, &path),
+            Err(e) => report::error(format!("{:?}", e)),
+        }
+    } else if result
+        // the calendars opened read-only (e.g. from a shared root) are never written
+        && !readonly
+        && !storage::save_audited(&before, &cal, &audit::current_user(), data_dir.as_path())
     {
-        warn!("Cannot write calendar {} to {}", cal, data_dir.display());
-        eprintln!("Cannot write calendar {} to {}", cal, data_dir.display());
+        report::warning(format!(
+            "Cannot write calendar {} to {}",
+            cal,
+            data_dir.display()
+        ));
     }
 }
@@ -0,0 +1,344 @@
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration as StdDuration;
+
+use chrono::{Duration, NaiveDateTime};
+
+use crate::calendar::Calendar;
+use crate::calendar_error::CalendarError;
+use crate::config::Config;
+use crate::doctor::{self, Issue};
+use crate::http;
+use crate::snapshot::{self, SNAPSHOTS_DIR};
+use crate::storage;
+use crate::subscription;
+
+/// Directory of the archived events inside the data directory, with a calendar file per
+/// calendar
+pub const ARCHIVE_DIR: &str = "archive";
+/// Temporary files older than this are left by interrupted writes, and removed
+const STALE_TEMP_FILE: StdDuration = StdDuration::from_secs(3600);
+/// Calendars larger than this are reported, as by `doctor` by default
+const MAX_CALENDAR_SIZE: u64 = 10 * 1024 * 1024;
+
+/// What a maintenance run did, and the errors that stopped some of its tasks
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub snapshots_deleted: usize,
+    pub temp_files_removed: usize,
+    pub feeds_refreshed: usize,
+    pub events_archived: usize,
+    pub calendars_compacted: usize,
+    /// Calendars rewritten from an older format (see [`Calendar::migrate`])
+    pub calendars_migrated: usize,
+    /// Problems found by checking the data directory (see [`doctor::scan`])
+    pub issues: Vec<Issue>,
+    pub errors: Vec<String>,
+}
+
+impl Summary {
+    /// Whether the run completed all its tasks and found no problems
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty() && self.errors.is_empty()
+    }
+
+    fn record<T>(&mut self, task: &str, result: Result<T, CalendarError>) -> Option<T> {
+        match result {
+            Ok(val) => Some(val),
+            Err(e) => {
+                self.errors.push(format!("{}: {:?}", task, e));
+                None
+            }
+        }
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (what, count) in [
+            ("Snapshots deleted", self.snapshots_deleted),
+            ("Temporary files removed", self.temp_files_removed),
+            ("Feeds refreshed", self.feeds_refreshed),
+            ("Events archived", self.events_archived),
+            ("Calendars compacted", self.calendars_compacted),
+            ("Calendars migrated", self.calendars_migrated),
+            ("Problems found", self.issues.len()),
+            ("Errors", self.errors.len()),
+        ] {
+            writeln!(f, "{:<25}{}", format!("{}:", what), count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the housekeeping of the data directory at `now`, on behalf of `user`: deletes the
+/// snapshots and archives the events older than the retention periods of the configuration,
+/// removes the temporary files left by interrupted writes, refreshes the stale feeds,
+/// compacts the change logs into the calendar files, rewrites the calendars in an older
+/// format and checks the data directory for problems. The tasks failing are reported in the
+/// summary, without stopping the others
+pub fn run(config: &Config, user: &str, now: NaiveDateTime, data_dir: &Path) -> Summary {
+    let mut summary = Summary::default();
+    let days = |days: u32| now - Duration::days(days.into());
+    if let Some(before) = config.maintenance.snapshot_retention_days.map(days) {
+        for id in snapshot_ids(data_dir) {
+            let deleted = snapshot::prune(&id, before, data_dir);
+            summary.snapshots_deleted += summary.record(&id, deleted).unwrap_or(0);
+        }
+    }
+    summary.temp_files_removed = remove_temp_files(data_dir);
+
+    let known = storage::known_calendars(data_dir);
+    let mut calendars: Vec<(Calendar, PathBuf)> = summary
+        .record("calendars", known)
+        .unwrap_or_default()
+        .into_iter()
+        // the unreadable calendars are reported by the check
+        .filter_map(|(cal, path)| cal.ok().map(|cal| (cal, path)))
+        .collect();
+    calendars.sort_by(|a, b| a.1.cmp(&b.1));
+    let mut client = None;
+    for (cal, path) in calendars.iter_mut() {
+        let id = cal.get_id().to_string();
+        let subscribed = summary.record(&id, subscription::load(&id, data_dir));
+        let before = cal.clone();
+        if let Some(Some(_)) = subscribed {
+            if client.is_none() {
+                let new = http::Client::new(&config.http, data_dir);
+                client = summary.record("feeds", new);
+            }
+            if let Some(client) = client.as_mut() {
+                let refreshed = subscription::refresh(cal, now, false, client, data_dir);
+                if let Some(Some(_)) = summary.record(&id, refreshed) {
+                    summary.feeds_refreshed += 1;
+                }
+            }
+        } else if let Some(ended) = config.maintenance.archive_after_days.map(days) {
+            // the events of the feeds would be fetched again
+            let archived = archive(cal, ended, data_dir);
+            summary.events_archived += summary.record(&id, archived).unwrap_or(0);
+        }
+        if before != *cal && !storage::save_audited(&before, cal, user, data_dir) {
+            summary
+                .errors
+                .push(format!("{}: cannot write the calendar", id));
+        }
+        if cal.is_migrated() || path.with_extension("log").exists() {
+            if !storage::save_calendar(cal, data_dir) {
+                summary
+                    .errors
+                    .push(format!("{}: cannot compact the calendar", id));
+            } else if cal.is_migrated() {
+                summary.calendars_migrated += 1;
+            } else {
+                summary.calendars_compacted += 1;
+            }
+        }
+    }
+
+    let issues = doctor::scan(data_dir, MAX_CALENDAR_SIZE);
+    summary.issues = summary.record("check", issues).unwrap_or_default();
+    summary
+}
+
+/// Returns the ids of the calendars with snapshots
+fn snapshot_ids(data_dir: &Path) -> Vec<String> {
+    let mut ids: Vec<String> = fs::read_dir(data_dir.join(SNAPSHOTS_DIR))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().to_str().map(String::from))
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// Removes the stale temporary files left in the data directory (and in the directories of
+/// the snapshots) by interrupted writes, returning their number
+fn remove_temp_files(data_dir: &Path) -> usize {
+    let dirs = std::iter::once(data_dir.to_path_buf()).chain(
+        snapshot_ids(data_dir)
+            .into_iter()
+            .map(|id| data_dir.join(SNAPSHOTS_DIR).join(id)),
+    );
+    let mut removed = 0;
+    for dir in dirs {
+        for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            let stale = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.elapsed().ok())
+                .is_some_and(|age| age > STALE_TEMP_FILE);
+            if path.extension().is_some_and(|ext| ext == "tmp")
+                && stale
+                && fs::remove_file(&path).is_ok()
+            {
+                removed += 1;
+            }
+        }
+    }
+    removed
+}
+
+/// Moves the events of the calendar whose last occurrence ended before `before` to its
+/// archive, a calendar with the same id in the archive directory. Returns their number
+pub fn archive(
+    cal: &mut Calendar,
+    before: NaiveDateTime,
+    data_dir: &Path,
+) -> Result<usize, CalendarError> {
+    let ended: Vec<u64> = cal
+        .events_by_eid()
+        .into_iter()
+        .filter(|(_, ev)| {
+            ev.occurrences()
+                .last()
+                .is_some_and(|start| ev.occurrence_end(start) < before)
+        })
+        .map(|(eid, _)| eid)
+        .collect();
+    if ended.is_empty() {
+        return Ok(0);
+    }
+    let dir = data_dir.join(ARCHIVE_DIR);
+    fs::create_dir_all(&dir)
+        .map_err(|e| CalendarError::Unknown(format!("{}: {}", dir.display(), e)))?;
+    let mut archived = match storage::calendar_path(cal.get_id(), &dir)? {
+        path if path.exists() => storage::read_calendar(&path)?,
+        _ => {
+            let mut archived = Calendar::new(cal.get_owner(), cal.get_name());
+            archived.set_id(cal.get_id());
+            archived
+        }
+    };
+    for eid in ended.iter() {
+        if let Some(ev) = cal.peek_event(*eid) {
+            archived.insert_event(*eid, ev.clone());
+        }
+    }
+    // the events are removed from the calendar once safe in the archive
+    if !storage::save_calendar(&archived, &dir) {
+        return Err(CalendarError::Unknown(format!(
+            "Cannot write the archive of {}",
+            cal.get_id()
+        )));
+    }
+    for eid in ended.iter() {
+        cal.remove_event(*eid)?;
+    }
+    Ok(ended.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::NaiveDate;
+
+    use crate::calendar::Calendar;
+    use crate::config::{Config, MaintenanceConfig};
+    use crate::event::Event;
+    use crate::maintenance::{run, ARCHIVE_DIR};
+    use crate::snapshot;
+    use crate::storage;
+
+    #[test]
+    /// tests archiving the old events, deleting the old snapshots, compacting the logs and
+    /// migrating the calendars in an older format
+    fn test_run() {
+        let dir = TempDir::new("maintenance");
+        let at = |d: u32| {
+            NaiveDate::from_ymd_opt(2022, 7, d)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap()
+        };
+        use crate::testing::TempDir;
+        let before = Calendar::new("owner", "work");
+        assert!(storage::save_calendar(&before, &dir));
+        let mut cal = before.clone();
+        cal.add_event(Event::new(
+            "old",
+            "",
+            "01/07/2022",
+            "09:30",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        // the last occurrence is on the 11th
+        let rec = Some("daily 10");
+        cal.add_event(Event::new(
+            "daily",
+            "",
+            "01/07/2022",
+            "08:00",
+            1.0,
+            None,
+            rec,
+            None,
+        ));
+        cal.add_event(Event::new(
+            "new",
+            "",
+            "14/07/2022",
+            "09:30",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        assert!(storage::save_changes(&before, &cal, &dir));
+        snapshot::create(&before, at(1), &dir).unwrap();
+        snapshot::create(&cal, at(2), &dir).unwrap();
+        // a calendar saved before the files were versioned
+        let mut old = serde_json::to_value(Calendar::new("owner", "old")).unwrap();
+        old.as_object_mut().unwrap().remove("version");
+        fs::write(dir.join("old.json"), old.to_string()).unwrap();
+
+        let config = Config {
+            maintenance: MaintenanceConfig {
+                snapshot_retention_days: Some(7),
+                archive_after_days: Some(5),
+            },
+            ..Config::default()
+        };
+        let summary = run(&config, "owner", at(15), &dir);
+        assert!(summary.is_ok(), "{:?}", summary);
+        assert_eq!(summary.snapshots_deleted, 1);
+        assert_eq!(summary.events_archived, 1);
+        assert_eq!(summary.calendars_compacted, 1);
+        assert_eq!(summary.calendars_migrated, 1);
+        assert!(!dir.join("work.log").exists());
+        assert!(!storage::read_calendar(&dir.join("old"))
+            .unwrap()
+            .is_migrated());
+        let titles = |cal: &Calendar| {
+            let mut titles: Vec<String> = cal
+                .events_by_eid()
+                .iter()
+                .map(|(_, ev)| ev.get_title().to_string())
+                .collect();
+            titles.sort();
+            titles
+        };
+        let work = storage::read_calendar(&dir.join("work")).unwrap();
+        assert_eq!(titles(&work), ["daily", "new"]);
+        let archived = storage::read_calendar(&dir.join(ARCHIVE_DIR).join("work")).unwrap();
+        assert_eq!(titles(&archived), ["old"]);
+        assert_eq!(snapshot::list("work", &dir).unwrap().len(), 1);
+
+        // the archive keeps the events archived before
+        let summary = run(&config, "owner", at(18), &dir);
+        assert_eq!(summary.events_archived, 1);
+        let archived = storage::read_calendar(&dir.join(ARCHIVE_DIR).join("work")).unwrap();
+        assert_eq!(titles(&archived), ["daily", "old"]);
+        assert_eq!(summary.calendars_migrated, 0);
+        assert_eq!(summary.to_string().lines().count(), 8);
+    }
+}
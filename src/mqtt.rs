@@ -0,0 +1,83 @@
+use std::thread;
+use std::time::Duration;
+
+use log::debug;
+use rumqttc::{Client, MqttOptions, QoS};
+
+use crate::calendar_error::CalendarError;
+use crate::config::MqttConfig;
+use crate::notify::CalendarState;
+use crate::report;
+
+/// How long to wait before reconnecting to the broker
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Publishes the state of the calendars to an MQTT broker, as retained messages
+pub struct Publisher {
+    client: Client,
+    config: MqttConfig,
+    /// Payloads last published, to publish only changes
+    last: Option<(String, String)>,
+}
+
+impl Publisher {
+    /// Connects to the broker: the connection is kept alive (and reestablished) in the background
+    pub fn connect(config: MqttConfig) -> Publisher {
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let (Some(user), Some(pass)) = (&config.username, &config.password) {
+            options.set_credentials(user, pass);
+        }
+        let (client, mut connection) = Client::new(options, 10);
+        thread::spawn(move || {
+            for notification in connection.iter() {
+                match notification {
+                    Ok(n) => debug!("MQTT: {:?}", n),
+                    Err(e) => {
+                        report::warning(format!("MQTT connection error: {}", e));
+                        thread::sleep(RECONNECT_DELAY);
+                    }
+                }
+            }
+        });
+        Publisher {
+            client,
+            config,
+            last: None,
+        }
+    }
+
+    pub fn config(&self) -> &MqttConfig {
+        &self.config
+    }
+
+    /// Publishes the state, if it changed since the last time
+    pub fn publish(&mut self, state: &CalendarState) -> Result<(), CalendarError> {
+        let next = serde_json::to_string(&state.next)
+            .map_err(|e| CalendarError::Unknown(e.to_string()))?;
+        let busy = String::from(if state.busy { "busy" } else { "free" });
+        let payloads = (next, busy);
+        if self.last.as_ref() == Some(&payloads) {
+            return Ok(());
+        }
+        let err = |e: rumqttc::ClientError| CalendarError::Unknown(format!("MQTT: {}", e));
+        self.client
+            .publish(
+                &self.config.next_event_topic,
+                QoS::AtLeastOnce,
+                true,
+                payloads.0.clone(),
+            )
+            .map_err(err)?;
+        self.client
+            .publish(
+                &self.config.busy_topic,
+                QoS::AtLeastOnce,
+                true,
+                payloads.1.clone(),
+            )
+            .map_err(err)?;
+        self.last = Some(payloads);
+        Ok(())
+    }
+}
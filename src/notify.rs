@@ -0,0 +1,713 @@
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::calendar::Calendar;
+use crate::calendar_error::CalendarError;
+use crate::clock::{self, Clock};
+use crate::config::{Config, WebhookConfig};
+use crate::event::{format_lead_time, Event, Transparency};
+#[cfg(feature = "mqtt")]
+use crate::mqtt;
+use crate::{report, storage};
+
+/// How often the notifier looks for events that have started
+const TICK: Duration = Duration::from_secs(30);
+
+/// Returns the occurrences of the events of the calendar starting after `after`, up to `until`,
+/// with the eids of their events
+pub fn starting_between(
+    cal: &Calendar,
+    after: NaiveDateTime,
+    until: NaiveDateTime,
+) -> Vec<(u64, Cow<'_, Event>)> {
+    cal.list_occurrences_between(Some(after), Some(until))
+        .into_iter()
+        .filter(|(_, ev)| ev.get_start() > after)
+        .collect()
+}
+
+/// A reminder of an occurrence of an event, due some time before its start
+#[derive(Debug, Clone)]
+pub struct Reminder<'a> {
+    /// When the reminder is due
+    pub due: NaiveDateTime,
+    /// How long before the start of the occurrence
+    pub lead: chrono::Duration,
+    pub eid: u64,
+    pub event: Cow<'a, Event>,
+}
+
+/// Returns the reminders of the occurrences of the events of the calendar due after `after`,
+/// up to `until`, in the order they are due
+pub fn reminders_between(
+    cal: &Calendar,
+    after: NaiveDateTime,
+    until: NaiveDateTime,
+) -> Vec<Reminder<'_>> {
+    let mut leads: Vec<chrono::Duration> = cal
+        .events_by_eid()
+        .iter()
+        .flat_map(|(_, ev)| ev.get_reminders().iter().copied())
+        .collect();
+    leads.sort();
+    leads.dedup();
+    let mut reminders: Vec<Reminder> = leads
+        .into_iter()
+        .filter_map(|lead| {
+            Some((
+                after.checked_add_signed(lead)?,
+                until.checked_add_signed(lead)?,
+                lead,
+            ))
+        })
+        .flat_map(|(after, until, lead)| {
+            starting_between(cal, after, until)
+                .into_iter()
+                .filter(move |(_, ev)| ev.get_reminders().contains(&lead))
+                .map(move |(eid, ev)| Reminder {
+                    due: ev.get_start() - lead,
+                    lead,
+                    eid,
+                    event: ev,
+                })
+        })
+        .collect();
+    reminders.sort_by_key(|r| (r.due, r.event.get_start()));
+    reminders
+}
+
+/// A reminder snoozed until some time, after which the notifier sends it again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snooze {
+    pub eid: u64,
+    /// Start of the occurrence of the event the reminder is about
+    pub start: NaiveDateTime,
+    pub until: NaiveDateTime,
+}
+
+fn snoozes_path(id: &str, data_dir: &Path) -> Result<PathBuf, CalendarError> {
+    Ok(storage::calendar_path(id, data_dir)?.with_extension("snoozes"))
+}
+
+/// Reads the reminders snoozed in the calendar with the given id
+pub fn load_snoozes(id: &str, data_dir: &Path) -> Result<Vec<Snooze>, CalendarError> {
+    let path = snoozes_path(id, data_dir)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))?;
+    serde_json::from_str(&contents)
+        .map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))
+}
+
+/// Stores the reminders snoozed in the calendar with the given id
+pub fn save_snoozes(id: &str, snoozes: &[Snooze], data_dir: &Path) -> Result<(), CalendarError> {
+    let path = snoozes_path(id, data_dir)?;
+    let written = if snoozes.is_empty() {
+        fs::remove_file(&path).or_else(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(e),
+        })
+    } else {
+        let contents = serde_json::to_string_pretty(snoozes)
+            .map_err(|e| CalendarError::Unknown(e.to_string()))?;
+        fs::write(&path, contents)
+    };
+    written.map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))
+}
+
+/// Snoozes the reminders of the event with the given eid for its current (or next)
+/// occurrence, so that the notifier reminds of it again after `duration`
+pub fn snooze(
+    cal: &Calendar,
+    eid: u64,
+    now: NaiveDateTime,
+    duration: chrono::Duration,
+    data_dir: &Path,
+) -> Result<Snooze, CalendarError> {
+    let ev = cal
+        .peek_event(eid)
+        .ok_or(CalendarError::EventNotFound(eid))?;
+    let start = ev
+        .occurrences()
+        .find(|start| ev.occurrence_end(*start) > now)
+        .ok_or_else(|| CalendarError::Unknown(format!("Event {} is over", eid)))?;
+    let snooze = Snooze {
+        eid,
+        start,
+        until: now + duration,
+    };
+    let mut snoozes = load_snoozes(cal.get_id(), data_dir)?;
+    snoozes.retain(|s| (s.eid, s.start) != (eid, start));
+    snoozes.push(snooze.clone());
+    save_snoozes(cal.get_id(), &snoozes, data_dir)?;
+    Ok(snooze)
+}
+
+/// Returns the occurrence of the event the snoozed reminder is about, if the event still exists
+fn snoozed_occurrence(cal: &Calendar, snooze: &Snooze) -> Option<Event> {
+    let mut ev = cal.peek_event(snooze.eid)?.clone();
+    let start = snooze.start;
+    ev.set_start_date((start.day(), start.month(), start.year()));
+    ev.set_start_time((start.hour(), start.minute(), start.second()));
+    Some(ev)
+}
+
+/// Fills the placeholders of the template with the values of the event (occurrence) with the
+/// given eid, and with the lead time of the reminder of the occurrence, if any (`{reminder}`, e.g. "1 day").
+/// Unknown placeholders are left as they are; values are escaped as JSON strings if `json`
+pub fn render_template(
+    template: &str,
+    cal: &Calendar,
+    eid: u64,
+    ev: &Event,
+    lead: Option<chrono::Duration>,
+    json: bool,
+) -> String {
+    let start = ev.get_start();
+    let end = start + chrono::Duration::seconds(ev.get_duration());
+    let value = |name: &str| -> Option<String> {
+        let val = match name {
+            "calendar" => cal.get_name().to_string(),
+            "calendar_id" => cal.get_id().to_string(),
+            "eid" => eid.to_string(),
+            "reminder" => lead.map(format_lead_time).unwrap_or_default(),
+            "title" => ev.get_title().to_string(),
+            "description" => ev.get_description().to_string(),
+            "location" => ev.get_location().to_string(),
+            "start" => start.format("%d/%m/%Y %H:%M").to_string(),
+            "end" => end.format("%d/%m/%Y %H:%M").to_string(),
+            _ => return None,
+        };
+        if json {
+            let quoted = serde_json::Value::String(val).to_string();
+            Some(quoted[1..quoted.len() - 1].to_string())
+        } else {
+            Some(val)
+        }
+    };
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        match after
+            .find('}')
+            .and_then(|close| Some((close, value(&after[..close])?)))
+        {
+            Some((close, val)) => {
+                out.push_str(&val);
+                rest = &after[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Sends the body to the webhook
+#[cfg(feature = "webhooks")]
+pub fn call_webhook(hook: &WebhookConfig, body: &str) -> Result<(), CalendarError> {
+    ureq::post(&hook.url)
+        .set("Content-Type", &hook.content_type)
+        .send_string(body)
+        .map_err(|e| CalendarError::Unknown(format!("Webhook {} failed: {}", hook.url, e)))?;
+    Ok(())
+}
+
+/// Without the `webhooks` feature, webhooks cannot be called
+#[cfg(not(feature = "webhooks"))]
+pub fn call_webhook(hook: &WebhookConfig, _body: &str) -> Result<(), CalendarError> {
+    Err(CalendarError::Unknown(format!(
+        "Cannot call webhook {}: rebuild with the `webhooks` feature",
+        hook.url
+    )))
+}
+
+/// Calls the webhooks interested in the calendar for each of its events that started
+/// after `after`, up to `until`, and for each reminder due in the meantime, including the
+/// snoozed ones (that are then forgotten). Returns the number of events and reminders the
+/// webhooks were called for
+fn notify(
+    hooks: &[WebhookConfig],
+    cal: &Calendar,
+    after: NaiveDateTime,
+    until: NaiveDateTime,
+    data_dir: &Path,
+) -> usize {
+    let snoozed = match load_snoozes(cal.get_id(), data_dir) {
+        Ok(snoozes) => snoozes,
+        Err(e) => {
+            report::warning(format!("{:?}", e));
+            Vec::new()
+        }
+    };
+    let (due, snoozed): (Vec<Snooze>, Vec<Snooze>) =
+        snoozed.into_iter().partition(|s| s.until <= until);
+    if !due.is_empty() {
+        // saved before calling the webhooks, so that they are not called again after a restart
+        if let Err(e) = save_snoozes(cal.get_id(), &snoozed, data_dir) {
+            report::warning(format!("{:?}", e));
+        }
+    }
+
+    let hooks: Vec<&WebhookConfig> = hooks
+        .iter()
+        .filter(|h| h.calendars.is_empty() || h.calendars.iter().any(|c| c == cal.get_id()))
+        .collect();
+    if hooks.is_empty() {
+        return 0;
+    }
+    let call = |eid: u64, ev: &Event, lead: Option<chrono::Duration>| {
+        for hook in hooks.iter() {
+            let json = hook.content_type.contains("json");
+            let body = render_template(&hook.template, cal, eid, ev, lead, json);
+            if let Err(e) = call_webhook(hook, &body) {
+                report::warning(format!("{:?}", e));
+            }
+        }
+    };
+    let mut sent = 0;
+    for snooze in due.iter() {
+        if let Some(ev) = snoozed_occurrence(cal, snooze) {
+            info!(
+                "Snoozed event \"{}\" of {} starts at {}",
+                ev.get_title(),
+                cal.get_id(),
+                snooze.start
+            );
+            let lead = snooze.start - snooze.until;
+            call(
+                snooze.eid,
+                &ev,
+                (lead > chrono::Duration::zero()).then_some(lead),
+            );
+            sent += 1;
+        }
+    }
+    let reminders = reminders_between(cal, after, until);
+    for reminder in reminders.iter() {
+        info!(
+            "Event \"{}\" of {} starts in {}",
+            reminder.event.get_title(),
+            cal.get_id(),
+            format_lead_time(reminder.lead)
+        );
+        call(reminder.eid, &reminder.event, Some(reminder.lead));
+    }
+    let started = starting_between(cal, after, until);
+    for (eid, ev) in started.iter() {
+        info!("Event \"{}\" of {} started", ev.get_title(), cal.get_id());
+        call(*eid, ev, None);
+    }
+    sent + started.len() + reminders.len()
+}
+
+/// The next event to start, as published to MQTT
+#[derive(Debug, PartialEq, Serialize)]
+pub struct NextEvent {
+    pub calendar: String,
+    pub title: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+/// The state of a set of calendars at some time
+#[derive(Debug, PartialEq)]
+pub struct CalendarState {
+    pub next: Option<NextEvent>,
+    /// Whether a busy event is taking place
+    pub busy: bool,
+}
+
+/// Computes the state of the calendars at the given time
+pub fn state<'a>(
+    cals: impl IntoIterator<Item = &'a Calendar>,
+    now: NaiveDateTime,
+) -> CalendarState {
+    let mut next: Option<NextEvent> = None;
+    let mut busy = false;
+    for cal in cals {
+        for ev in cal.list_events_between(None, None) {
+            let start = ev.get_start();
+            let end = ev.occurrence_end(start);
+            if start > now {
+                if next.as_ref().is_none_or(|n| start < n.start) {
+                    next = Some(NextEvent {
+                        calendar: cal.get_name().to_string(),
+                        title: ev.get_title().to_string(),
+                        start,
+                        end,
+                    });
+                }
+            } else if end > now && ev.get_transparency() == Transparency::Busy {
+                busy = true;
+            }
+        }
+    }
+    CalendarState { next, busy }
+}
+
+/// Watches the calendars in the data directory, calling the webhooks when their events start
+/// and publishing their state to the MQTT broker, if configured
+pub struct Notifier {
+    data_dir: PathBuf,
+    config: Config,
+    clock: Arc<dyn Clock>,
+    /// When the calendars were last checked
+    last: NaiveDateTime,
+    #[cfg(feature = "mqtt")]
+    publisher: Option<mqtt::Publisher>,
+}
+
+impl Notifier {
+    pub fn new(data_dir: &Path, config: Config, clock: Arc<dyn Clock>) -> Notifier {
+        #[cfg(not(feature = "mqtt"))]
+        if config.mqtt.is_some() {
+            report::warning("MQTT is not supported: rebuild with the `mqtt` feature");
+        }
+        Notifier {
+            data_dir: data_dir.to_path_buf(),
+            #[cfg(feature = "mqtt")]
+            publisher: config.mqtt.clone().map(mqtt::Publisher::connect),
+            config,
+            last: clock.now().naive_local(),
+            clock,
+        }
+    }
+
+    /// Looks for the events started and the reminders due since the last check. Returns the
+    /// number of events and reminders the webhooks were called for
+    pub fn check(&mut self) -> usize {
+        let now = self.clock.now().naive_local();
+        debug!("Looking for events started since {}", self.last);
+        let calendars: Vec<Calendar> = match storage::known_calendars(&self.data_dir) {
+            Ok(calendars) => calendars
+                .into_iter()
+                .filter_map(|(cal, _)| cal.ok())
+                .collect(),
+            Err(e) => {
+                report::warning(format!("Cannot read the calendars: {:?}", e));
+                Vec::new()
+            }
+        };
+        let started = calendars
+            .iter()
+            .map(|cal| notify(&self.config.webhooks, cal, self.last, now, &self.data_dir))
+            .sum();
+        #[cfg(feature = "mqtt")]
+        if let Some(publisher) = self.publisher.as_mut() {
+            let ids = &publisher.config().calendars;
+            let watched = calendars
+                .iter()
+                .filter(|c| ids.is_empty() || ids.iter().any(|id| id == c.get_id()));
+            if let Err(e) = publisher.publish(&state(watched, now)) {
+                report::warning(format!("{:?}", e));
+            }
+        }
+        self.last = now;
+        started
+    }
+}
+
+/// Runs a [`Notifier`] in a background thread, until the process exits. It reads the time
+/// from the clock of the calling thread
+pub fn spawn(data_dir: &Path, config: Config) -> thread::JoinHandle<()> {
+    let mut notifier = Notifier::new(data_dir, config, clock::current());
+    thread::spawn(move || loop {
+        notifier.check();
+        thread::sleep(TICK);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use chrono::{DateTime, Duration, Local, NaiveDateTime};
+
+    use crate::clock::{Clock, FixedClock};
+    use crate::config::{Config, WebhookConfig};
+
+    use crate::calendar::Calendar;
+    use crate::event::Event;
+    use crate::event::Transparency;
+    use crate::notify::{
+        load_snoozes, reminders_between, render_template, snooze, starting_between, state, Notifier,
+    };
+    use crate::storage::save_calendar;
+    use crate::testing::{at, TempDir};
+
+    #[test]
+    /// tests finding the events that started since the last check
+    fn test_starting_between() {
+        let mut cal = Calendar::new("owner", "work");
+        cal.add_event(Event::new(
+            "standup",
+            "",
+            "11/07/2022",
+            "09:00",
+            1.0,
+            None,
+            Some("daily 4"),
+            None,
+        ));
+        let started = |after, until| starting_between(&cal, after, until).len();
+        assert_eq!(started(at(13, 8, 59), at(13, 9, 0)), 1);
+        assert_eq!(started(at(13, 9, 0), at(13, 9, 1)), 0);
+        assert_eq!(started(at(12, 8, 0), at(13, 10, 0)), 2);
+        assert_eq!(started(at(15, 8, 0), at(16, 10, 0)), 1);
+        assert_eq!(started(at(16, 8, 0), at(17, 10, 0)), 0);
+    }
+
+    #[test]
+    /// tests filling the webhook templates
+    fn test_render_template() {
+        let cal = Calendar::new("owner", "work");
+        let ev = Event::new(
+            "say \"hi\"",
+            "",
+            "13/07/2022",
+            "09:00",
+            1.0,
+            Some("room {1}"),
+            None,
+            None,
+        );
+        assert_eq!(
+            render_template(
+                "{title} at {location}, {start}-{end} {unknown} {",
+                &cal,
+                1,
+                &ev,
+                None,
+                false
+            ),
+            "say \"hi\" at room {1}, 13/07/2022 09:00-13/07/2022 10:00 {unknown} {"
+        );
+        assert_eq!(
+            render_template(
+                r#"{"text": "{calendar}: {title}"}"#,
+                &cal,
+                1,
+                &ev,
+                None,
+                true
+            ),
+            r#"{"text": "work: say \"hi\""}"#
+        );
+        assert_eq!(
+            render_template(
+                "{title} in {reminder}",
+                &cal,
+                1,
+                &ev,
+                Some(Duration::days(1)),
+                false
+            ),
+            "say \"hi\" in 1 day"
+        );
+    }
+
+    #[test]
+    /// tests finding the reminders due since the last check
+    fn test_reminders_between() {
+        let mut cal = Calendar::new("owner", "work");
+        let mut ev = Event::new(
+            "standup",
+            "",
+            "11/07/2022",
+            "09:00",
+            1.0,
+            None,
+            Some("daily 4"),
+            None,
+        );
+        ev.set_reminders(vec![Duration::minutes(15), Duration::days(1)]);
+        cal.add_event(ev);
+        cal.add_event(Event::new(
+            "review",
+            "",
+            "12/07/2022",
+            "09:15",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        let due = |after, until| {
+            reminders_between(&cal, after, until)
+                .iter()
+                .map(|r| (r.due, r.lead.num_minutes(), r.event.get_start()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(due(at(11, 8, 0), at(11, 8, 44)), vec![]);
+        assert_eq!(
+            due(at(11, 8, 0), at(11, 8, 45)),
+            vec![(at(11, 8, 45), 15, at(11, 9, 0))]
+        );
+        assert_eq!(
+            due(at(11, 8, 45), at(12, 9, 0)),
+            vec![
+                (at(11, 9, 0), 24 * 60, at(12, 9, 0)),
+                (at(12, 8, 45), 15, at(12, 9, 0)),
+                (at(12, 9, 0), 24 * 60, at(13, 9, 0)),
+            ]
+        );
+        // due the day before the first occurrence
+        assert_eq!(
+            due(at(10, 0, 0), at(10, 12, 0)),
+            vec![(at(10, 9, 0), 24 * 60, at(11, 9, 0))]
+        );
+    }
+
+    #[test]
+    /// tests computing the next event and the busy state
+    fn test_state() {
+        let mut work = Calendar::new("owner", "work");
+        work.add_event(Event::new(
+            "standup",
+            "",
+            "11/07/2022",
+            "09:00",
+            1.0,
+            None,
+            Some("daily 4"),
+            None,
+        ));
+        let mut home = Calendar::new("owner", "home");
+        let mut holiday = Event::new("holiday", "", "13/07/2022", "00:00", 24.0, None, None, None);
+        holiday.set_transparency(Transparency::Free);
+        home.add_event(holiday);
+        let cals = [work, home];
+
+        let st = state(&cals, at(12, 9, 30));
+        assert!(st.busy);
+        assert_eq!(st.next.as_ref().unwrap().title, "holiday");
+        // free events do not make the user busy
+        let st = state(&cals, at(13, 8, 0));
+        assert!(!st.busy);
+        let next = st.next.unwrap();
+        assert_eq!((next.title.as_str(), next.start), ("standup", at(13, 9, 0)));
+        assert_eq!(next.calendar, "work");
+        let st = state(&cals, at(16, 0, 0));
+        assert_eq!((st.next, st.busy), (None, false));
+    }
+
+    /// A clock moved forward by the test
+    struct TestClock(Mutex<NaiveDateTime>);
+
+    impl Clock for TestClock {
+        fn now(&self) -> DateTime<Local> {
+            FixedClock::at(*self.0.lock().unwrap()).now()
+        }
+    }
+
+    #[test]
+    /// tests watching the calendars as time passes
+    fn test_notifier() {
+        let dir = TempDir::new("notifier");
+        let mut cal = Calendar::new("owner", "work");
+        cal.add_event(Event::new(
+            "standup",
+            "",
+            "11/07/2022",
+            "09:00",
+            0.25,
+            None,
+            Some("daily 4"),
+            None,
+        ));
+        assert!(save_calendar(&cal, &dir));
+        let config = Config {
+            webhooks: vec![WebhookConfig {
+                url: String::from("http://127.0.0.1:9/"),
+                template: String::from("{title}"),
+                content_type: String::from("text/plain"),
+                calendars: Vec::new(),
+            }],
+            ..Config::default()
+        };
+
+        let clock = Arc::new(TestClock(Mutex::new(at(11, 8, 0))));
+        let mut notifier = Notifier::new(&dir, config, clock.clone());
+        let mut check_at = |t| {
+            *clock.0.lock().unwrap() = t;
+            notifier.check()
+        };
+        assert_eq!(check_at(at(11, 8, 30)), 0);
+        assert_eq!(check_at(at(11, 9, 0)), 1);
+        assert_eq!(check_at(at(11, 9, 30)), 0);
+        // the daemon was not running in between
+        assert_eq!(check_at(at(13, 9, 30)), 2);
+    }
+
+    #[test]
+    /// tests that snoozed reminders are sent again once, even across restarts
+    fn test_snooze() {
+        let dir = TempDir::new("snooze");
+        let mut cal = Calendar::new("owner", "work");
+        cal.insert_event(
+            7,
+            Event::new(
+                "standup",
+                "",
+                "11/07/2022",
+                "09:00",
+                1.0,
+                None,
+                Some("daily 4"),
+                None,
+            ),
+        );
+        assert!(save_calendar(&cal, &dir));
+        let (eid, ev) = &starting_between(&cal, at(12, 8, 0), at(13, 10, 0))[1];
+        assert_eq!(
+            render_template("{calendar_id}/{eid}", &cal, *eid, ev, None, false),
+            "work/7"
+        );
+        assert!(snooze(&cal, 8, at(12, 8, 50), Duration::minutes(10), &dir).is_err());
+        assert!(snooze(&cal, 7, at(15, 10, 30), Duration::minutes(10), &dir).is_err());
+        // the occurrence started, but not ended yet
+        let s = snooze(&cal, 7, at(12, 9, 5), Duration::minutes(10), &dir).unwrap();
+        assert_eq!((s.start, s.until), (at(12, 9, 0), at(12, 9, 15)));
+        let s = snooze(&cal, 7, at(12, 9, 5), Duration::minutes(5), &dir).unwrap();
+        assert_eq!(load_snoozes("work", &dir).unwrap(), [s]);
+
+        let config = Config {
+            webhooks: vec![WebhookConfig {
+                url: String::from("http://127.0.0.1:9/"),
+                template: String::from("{title}"),
+                content_type: String::from("text/plain"),
+                calendars: Vec::new(),
+            }],
+            ..Config::default()
+        };
+        let clock = Arc::new(TestClock(Mutex::new(at(12, 9, 5))));
+        let mut notifier = Notifier::new(&dir, config.clone(), clock.clone());
+        *clock.0.lock().unwrap() = at(12, 9, 6);
+        assert_eq!(notifier.check(), 0);
+        // restarted after the snooze expired
+        *clock.0.lock().unwrap() = at(12, 9, 20);
+        let mut notifier = Notifier::new(&dir, config.clone(), clock.clone());
+        *clock.0.lock().unwrap() = at(12, 9, 21);
+        assert_eq!(notifier.check(), 1);
+        assert!(load_snoozes("work", &dir).unwrap().is_empty());
+        let mut notifier = Notifier::new(&dir, config, clock.clone());
+        *clock.0.lock().unwrap() = at(12, 9, 22);
+        assert_eq!(notifier.check(), 0);
+    }
+}
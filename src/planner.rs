@@ -0,0 +1,458 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+
+use crate::calendar::Calendar;
+#[cfg(feature = "cli")]
+use crate::cli::split_words;
+use crate::config::WorkingHours;
+use crate::event::{Event, Transparency};
+
+/// A task to be scheduled, with its estimated duration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Task {
+    pub title: String,
+    pub duration: Duration,
+    pub tags: Vec<String>,
+}
+
+/// Parses a list of tasks, one per line: the title (quoted if it contains spaces), the
+/// estimated duration in hours and optionally some tags. Empty lines and lines starting
+/// with '#' are skipped
+#[cfg(feature = "cli")]
+pub fn parse_tasks(text: &str) -> Result<Vec<Task>, String> {
+    let mut tasks = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |e: &str| format!("line {}: {}", i + 1, e);
+        let words = split_words(line).map_err(|e| err(&e))?;
+        if words.len() < 2 {
+            return Err(err("expected a title and a duration"));
+        }
+        let hours: f64 = match words[1].parse() {
+            Ok(h) if h > 0.0 => h,
+            _ => return Err(err(&format!("invalid duration {}", words[1]))),
+        };
+        tasks.push(Task {
+            title: words[0].clone(),
+            duration: Duration::seconds((hours * 3600.0).round() as i64),
+            tags: words[2..].to_vec(),
+        });
+    }
+    Ok(tasks)
+}
+
+/// Parses the period to schedule the tasks in, given the current date: "today", "tomorrow",
+/// "this week", "next week", "next N days" or "next N weeks" (from today) or a range of dates
+/// "%d/%m/%Y-%d/%m/%Y" (both included)
+pub fn parse_within(s: &str, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+    let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    let next_days = |s: &str| -> Option<i64> {
+        let s = s.strip_prefix("next ")?;
+        let n: i64 = match (s.strip_suffix(" days"), s.strip_suffix(" weeks")) {
+            (Some(days), _) => days.parse().ok()?,
+            (_, Some(weeks)) => weeks.parse::<i64>().ok()?.checked_mul(7)?,
+            _ => return None,
+        };
+        (n > 0).then_some(n)
+    };
+    match s.trim().to_lowercase().as_str() {
+        "today" => Some((today, today)),
+        "tomorrow" => Some((today + Duration::days(1), today + Duration::days(1))),
+        "this week" => Some((today, monday + Duration::days(6))),
+        "next week" => Some((monday + Duration::days(7), monday + Duration::days(13))),
+        days if next_days(days).is_some() => {
+            next_days(days).map(|n| (today, today + Duration::days(n - 1)))
+        }
+        range => {
+            let (from, until) = range.split_once('-')?;
+            let from = NaiveDate::parse_from_str(from.trim(), "%d/%m/%Y").ok()?;
+            let until = NaiveDate::parse_from_str(until.trim(), "%d/%m/%Y").ok()?;
+            (from <= until).then_some((from, until))
+        }
+    }
+}
+
+/// Computes the free slots of the calendar in the working hours of the days from `first` to
+/// `last`, after `now`: the time taken by busy events is not free
+pub fn free_slots(
+    cal: &Calendar,
+    first: NaiveDate,
+    last: NaiveDate,
+    hours: &WorkingHours,
+    now: NaiveDateTime,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let windows = first
+        .iter_days()
+        .take_while(|d| *d <= last)
+        .filter(|d| hours.days.contains(&d.weekday()))
+        .map(|d| (d.and_time(hours.start), d.and_time(hours.end)))
+        .collect();
+    subtract_busy(cal, windows, now)
+}
+
+/// Returns the intervals of time taken by the busy events of the calendar that end after
+/// `from` and start before `until`
+fn busy_times(
+    cal: &Calendar,
+    from: NaiveDateTime,
+    until: NaiveDateTime,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    cal.list_events_between(None, Some(until))
+        .iter()
+        .filter(|ev| ev.get_transparency() == Transparency::Busy)
+        .map(|ev| (ev.get_start(), ev.occurrence_end(ev.get_start())))
+        .filter(|(_, end)| *end > from)
+        .collect()
+}
+
+/// Removes from the windows of time (sorted and not overlapping) the time before `now` and
+/// the time taken by the busy events of the calendar, returning what is left
+pub fn subtract_busy(
+    cal: &Calendar,
+    windows: Vec<(NaiveDateTime, NaiveDateTime)>,
+    now: NaiveDateTime,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let busy = match (windows.first(), windows.last()) {
+        (Some(first), Some(last)) => busy_times(cal, first.0, last.1),
+        _ => return Vec::new(),
+    };
+    subtract(windows, busy, now)
+}
+
+/// Removes from the windows of time (sorted and not overlapping) the time before `now` and
+/// the busy intervals
+fn subtract(
+    windows: Vec<(NaiveDateTime, NaiveDateTime)>,
+    mut busy: Vec<(NaiveDateTime, NaiveDateTime)>,
+    now: NaiveDateTime,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    busy.sort_unstable();
+    let mut slots = Vec::new();
+    for (start, end) in windows {
+        let mut start = start.max(now);
+        for (busy_start, busy_end) in busy.iter() {
+            if *busy_start >= end || start >= end {
+                break;
+            }
+            if *busy_end <= start {
+                continue;
+            }
+            if *busy_start > start {
+                slots.push((start, *busy_start));
+            }
+            start = start.max(*busy_end);
+        }
+        if start < end {
+            slots.push((start, end));
+        }
+    }
+    slots
+}
+
+/// Computes the slots free in all the calendars in the working hours of the days from `first`
+/// to `last`, after `now`: the time taken by their busy events and the `busy` intervals
+/// (e.g. imported from the free/busy data of other people) is not free
+pub fn common_free_slots(
+    cals: &[&Calendar],
+    busy: &[(NaiveDateTime, NaiveDateTime)],
+    first: NaiveDate,
+    last: NaiveDate,
+    hours: &WorkingHours,
+    now: NaiveDateTime,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let windows: Vec<(NaiveDateTime, NaiveDateTime)> = first
+        .iter_days()
+        .take_while(|d| *d <= last)
+        .filter(|d| hours.days.contains(&d.weekday()))
+        .map(|d| (d.and_time(hours.start), d.and_time(hours.end)))
+        .collect();
+    let (from, until) = match (windows.first(), windows.last()) {
+        (Some(first), Some(last)) => (first.0, last.1),
+        _ => return Vec::new(),
+    };
+    let mut all_busy = busy.to_vec();
+    for cal in cals {
+        all_busy.append(&mut busy_times(cal, from, until));
+    }
+    subtract(windows, all_busy, now)
+}
+
+/// Parses the duration of a meeting: hours and minutes ("1h", "45m", "1h30m") or a
+/// number of hours ("1.5")
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim().to_lowercase();
+    if let Ok(hours) = s.parse::<f64>() {
+        return (hours > 0.0).then(|| Duration::seconds((hours * 3600.0).round() as i64));
+    }
+    let (hours, minutes) = match s.split_once('h') {
+        Some((h, m)) => (h, m.strip_suffix('m').unwrap_or(m)),
+        None => ("0", s.strip_suffix('m')?),
+    };
+    let hours: i64 = hours.parse().ok()?;
+    let minutes: i64 = if minutes.is_empty() {
+        0
+    } else {
+        minutes.parse().ok()?
+    };
+    let d = Duration::hours(hours) + Duration::minutes(minutes);
+    (d > Duration::zero()).then_some(d)
+}
+
+/// Formats a duration compactly, as days, hours and minutes ("1h30m", "2d")
+pub fn format_duration(d: Duration) -> String {
+    let minutes = d.num_minutes();
+    let parts = [
+        (minutes / (24 * 60), "d"),
+        (minutes / 60 % 24, "h"),
+        (minutes % 60, "m"),
+    ];
+    let out: String = parts
+        .iter()
+        .filter(|(n, _)| *n != 0)
+        .map(|(n, unit)| format!("{}{}", n, unit))
+        .collect();
+    if out.is_empty() {
+        String::from("0m")
+    } else {
+        out
+    }
+}
+
+/// Proposes meeting times of the given duration in the free slots, starting on multiples
+/// of `step` or at the end of the slots. The best ones come first: earlier days, then the
+/// times that do not split a slot (starting or ending with it), then earlier times
+pub fn meeting_times(
+    slots: &[(NaiveDateTime, NaiveDateTime)],
+    duration: Duration,
+    step: Duration,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let mut times = Vec::new();
+    for (start, end) in slots {
+        if *end - *start < duration {
+            continue;
+        }
+        let step_secs = step.num_seconds().max(1);
+        let since_midnight = start.num_seconds_from_midnight() as i64;
+        let mut t =
+            *start + Duration::seconds((step_secs - since_midnight % step_secs) % step_secs);
+        times.push(*start);
+        while t + duration <= *end {
+            times.push(t);
+            t += step;
+        }
+        times.push(*end - duration);
+    }
+    times.sort_unstable();
+    times.dedup();
+    let mut times: Vec<(NaiveDateTime, NaiveDateTime)> =
+        times.into_iter().map(|t| (t, t + duration)).collect();
+    times.sort_by_key(|(start, end)| {
+        let edge = slots.iter().any(|(s, e)| s == start || e == end);
+        (start.date(), !edge, *start)
+    });
+    times
+}
+
+/// Schedules the tasks, in order, in the first free slot long enough for each of them.
+/// Returns the events of the scheduled tasks, and the tasks that do not fit anywhere
+pub fn schedule(
+    tasks: Vec<Task>,
+    mut slots: Vec<(NaiveDateTime, NaiveDateTime)>,
+) -> (Vec<Event>, Vec<Task>) {
+    let mut planned = Vec::new();
+    let mut unscheduled = Vec::new();
+    for task in tasks {
+        match slots
+            .iter_mut()
+            .find(|(start, end)| *end - *start >= task.duration)
+        {
+            Some(slot) => {
+                let start = slot.0;
+                slot.0 = start + task.duration;
+                let mut ev = Event::default();
+                ev.set_title(&task.title);
+                ev.set_start_date((start.day(), start.month(), start.year()));
+                ev.set_start_time((start.hour(), start.minute(), start.second()));
+                ev.set_duration(&task.duration);
+                ev.set_tags(task.tags);
+                planned.push(ev);
+            }
+            None => unscheduled.push(task),
+        }
+    }
+    (planned, unscheduled)
+}
+
+#[cfg(all(test, feature = "cli"))]
+mod tests {
+    use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+    use crate::calendar::Calendar;
+    use crate::config::WorkingHours;
+    use crate::event::{Event, Transparency};
+    use crate::planner::{
+        common_free_slots, format_duration, free_slots, meeting_times, parse_duration, parse_tasks,
+        parse_within, schedule,
+    };
+    use crate::testing::at;
+
+    #[test]
+    /// tests parsing task lists
+    fn test_parse_tasks() {
+        let tasks = parse_tasks("# tasks\n\"Write report\" 2.5 work\n\nemails 0.25\n").unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].title, "Write report");
+        assert_eq!(tasks[0].duration, Duration::minutes(150));
+        assert_eq!(tasks[0].tags, ["work"]);
+        assert_eq!(tasks[1].duration, Duration::minutes(15));
+        assert!(parse_tasks("report").is_err());
+        assert!(parse_tasks("ok 1\nreport soon")
+            .unwrap_err()
+            .starts_with("line 2"));
+    }
+
+    #[test]
+    /// tests parsing the scheduling periods
+    fn test_parse_within() {
+        // a wednesday
+        let today = NaiveDate::from_ymd_opt(2022, 7, 13).unwrap();
+        let day = |d| NaiveDate::from_ymd_opt(2022, 7, d).unwrap();
+        assert_eq!(parse_within("today", today), Some((today, today)));
+        assert_eq!(parse_within("This Week", today), Some((today, day(17))));
+        assert_eq!(parse_within("next week", today), Some((day(18), day(24))));
+        assert_eq!(
+            parse_within("01/07/2022-03/07/2022", today),
+            Some((day(1), day(3)))
+        );
+        assert_eq!(parse_within("03/07/2022-01/07/2022", today), None);
+        assert_eq!(parse_within("next 5 days", today), Some((today, day(17))));
+        assert_eq!(parse_within("next 0 days", today), None);
+        assert_eq!(parse_within("next 2 weeks", today), Some((today, day(26))));
+        assert_eq!(parse_within("someday", today), None);
+    }
+
+    #[test]
+    /// tests finding free slots around events and scheduling tasks in them
+    fn test_schedule() {
+        let mut cal = Calendar::new("owner", "work");
+        cal.add_event(Event::new(
+            "standup",
+            "",
+            "11/07/2022",
+            "09:00",
+            1.0,
+            None,
+            Some("daily 4"),
+            None,
+        ));
+        cal.add_event(Event::new(
+            "lunch",
+            "",
+            "13/07/2022",
+            "12:00",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        let mut holiday = Event::new("holiday", "", "14/07/2022", "00:00", 24.0, None, None, None);
+        holiday.set_transparency(Transparency::Free);
+        cal.add_event(holiday);
+        let hours = WorkingHours {
+            start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            days: vec![Weekday::Wed, Weekday::Thu],
+        };
+        let first = NaiveDate::from_ymd_opt(2022, 7, 13).unwrap();
+        let last = NaiveDate::from_ymd_opt(2022, 7, 17).unwrap();
+
+        let slots = free_slots(&cal, first, last, &hours, at(13, 9, 30));
+        assert_eq!(
+            slots,
+            [
+                (at(13, 10, 0), at(13, 12, 0)),
+                (at(13, 13, 0), at(13, 17, 0)),
+                (at(14, 10, 0), at(14, 17, 0)),
+            ]
+        );
+
+        let tasks = parse_tasks("a 1.5\nb 3\nc 1\nd 8\n").unwrap();
+        let (planned, unscheduled) = schedule(tasks, slots);
+        let starts: Vec<(&str, NaiveDateTime)> = planned
+            .iter()
+            .map(|ev| (ev.get_title(), ev.get_start()))
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                ("a", at(13, 10, 0)),
+                ("b", at(13, 13, 0)),
+                ("c", at(13, 16, 0)),
+            ]
+        );
+        assert_eq!(unscheduled.len(), 1);
+        assert_eq!(unscheduled[0].title, "d");
+    }
+
+    #[test]
+    /// tests finding the meeting times free in several calendars
+    fn test_meeting_times() {
+        assert_eq!(parse_duration("1h"), Some(Duration::hours(1)));
+        assert_eq!(parse_duration("1h30m"), Some(Duration::minutes(90)));
+        assert_eq!(parse_duration("45m"), Some(Duration::minutes(45)));
+        assert_eq!(parse_duration("0.5"), Some(Duration::minutes(30)));
+        assert_eq!(parse_duration("0m"), None);
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(format_duration(Duration::minutes(90)), "1h30m");
+        assert_eq!(format_duration(Duration::hours(48)), "2d");
+        assert_eq!(format_duration(Duration::zero()), "0m");
+
+        let mut alice = Calendar::new("alice", "alice");
+        alice.add_event(Event::new(
+            "standup",
+            "",
+            "13/07/2022",
+            "09:00",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        let mut bob = Calendar::new("bob", "bob");
+        bob.add_event(Event::new(
+            "lunch",
+            "",
+            "13/07/2022",
+            "12:00",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        let hours = WorkingHours {
+            start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            days: vec![Weekday::Wed],
+        };
+        let day = NaiveDate::from_ymd_opt(2022, 7, 13).unwrap();
+        // carol is busy in the afternoon
+        let busy = [(at(13, 13, 30), at(13, 17, 0))];
+        let slots = common_free_slots(&[&alice, &bob], &busy, day, day, &hours, at(13, 8, 0));
+        assert_eq!(
+            slots,
+            [
+                (at(13, 10, 0), at(13, 12, 0)),
+                (at(13, 13, 0), at(13, 13, 30))
+            ]
+        );
+
+        let times = meeting_times(&slots, Duration::hours(1), Duration::minutes(30));
+        let starts: Vec<NaiveDateTime> = times.iter().map(|(start, _)| *start).collect();
+        // the times starting or ending with a slot come first
+        assert_eq!(starts, [at(13, 10, 0), at(13, 11, 0), at(13, 10, 30)]);
+        assert!(times.iter().all(|(s, e)| *e - *s == Duration::hours(1)));
+        assert!(meeting_times(&slots, Duration::hours(3), Duration::minutes(30)).is_empty());
+    }
+}
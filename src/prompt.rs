@@ -0,0 +1,181 @@
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use crate::calendar_error::CalendarError;
+use crate::report;
+
+/// Completes the word under the cursor among a list of words
+pub(crate) struct WordCompleter {
+    words: Vec<String>,
+}
+
+impl WordCompleter {
+    pub(crate) fn new(mut words: Vec<String>) -> WordCompleter {
+        words.sort_unstable();
+        words.dedup();
+        WordCompleter { words }
+    }
+}
+
+impl Completer for WordCompleter {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        // the word being completed starts after the last whitespace (or opening quote)
+        let start = line[..pos]
+            .rfind(|c: char| c.is_whitespace())
+            .map_or(0, |i| i + 1);
+        let prefix = &line[start..pos];
+        let candidates = self
+            .words
+            .iter()
+            .filter(|w| w.starts_with(prefix) || w.trim_start_matches('"').starts_with(prefix))
+            .cloned()
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for WordCompleter {
+    type Hint = String;
+}
+impl Highlighter for WordCompleter {}
+impl Validator for WordCompleter {}
+impl Helper for WordCompleter {}
+
+/// Source of the answers to the questions asked by a [`Prompt`]
+pub trait Answers {
+    /// Reads the answer to `question`, offering to complete it among `words`.
+    /// Returns None if the user gave up answering
+    fn read(&mut self, question: &str, words: &[String]) -> Result<Option<String>, CalendarError>;
+}
+
+/// Reads the answers from the terminal, with line editing and completion
+pub struct Terminal {
+    editor: Editor<WordCompleter, DefaultHistory>,
+}
+
+impl Terminal {
+    pub fn new() -> Result<Terminal, CalendarError> {
+        let editor = Editor::new().map_err(|e| CalendarError::Unknown(e.to_string()))?;
+        Ok(Terminal { editor })
+    }
+}
+
+impl Answers for Terminal {
+    fn read(&mut self, question: &str, words: &[String]) -> Result<Option<String>, CalendarError> {
+        self.editor
+            .set_helper(Some(WordCompleter::new(words.to_vec())));
+        match self.editor.readline(question) {
+            Ok(line) => Ok(Some(line)),
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => Ok(None),
+            Err(e) => Err(CalendarError::Unknown(e.to_string())),
+        }
+    }
+}
+
+/// Asks the user a sequence of questions, with default answers and validation
+pub struct Prompt<A: Answers> {
+    answers: A,
+}
+
+impl<A: Answers> Prompt<A> {
+    pub fn new(answers: A) -> Prompt<A> {
+        Prompt { answers }
+    }
+
+    /// Asks a question until the answer is accepted by `parse`, completing it among `words`.
+    /// An empty answer stands for the default one, if any
+    pub fn ask<T>(
+        &mut self,
+        question: &str,
+        default: Option<&str>,
+        words: &[String],
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> Result<T, CalendarError> {
+        let question = match default {
+            Some(d) if !d.is_empty() => format!("{} [{}]: ", question, d),
+            _ => format!("{}: ", question),
+        };
+        loop {
+            let answer = self
+                .answers
+                .read(&question, words)?
+                .ok_or_else(|| CalendarError::Unknown(String::from("aborted by the user")))?;
+            let answer = match (answer.trim(), default) {
+                ("", Some(d)) => d,
+                (answer, _) => answer,
+            };
+            match parse(answer) {
+                Ok(val) => return Ok(val),
+                Err(e) => report::error(e),
+            }
+        }
+    }
+
+    /// Asks for some text, that may be empty if there is no default
+    pub fn text(&mut self, question: &str, default: &str) -> Result<String, CalendarError> {
+        self.ask(question, Some(default), &[], |s| Ok(s.to_string()))
+    }
+
+    /// Asks for some text that cannot be empty
+    pub fn required(
+        &mut self,
+        question: &str,
+        default: Option<&str>,
+    ) -> Result<String, CalendarError> {
+        self.ask(question, default, &[], |s| match s {
+            "" => Err(String::from("an answer is required")),
+            s => Ok(s.to_string()),
+        })
+    }
+
+    /// Asks a yes or no question
+    pub fn confirm(&mut self, question: &str, default: bool) -> Result<bool, CalendarError> {
+        let default = if default { "y" } else { "n" };
+        self.ask(question, Some(default), &[], |s| {
+            match s.to_lowercase().as_str() {
+                "y" | "yes" => Ok(true),
+                "n" | "no" => Ok(false),
+                _ => Err(format!("answer yes or no, not {}", s)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prompt::Prompt;
+    use crate::testing::ScriptedAnswers;
+
+    #[test]
+    /// tests asking questions with defaults and validation
+    fn test_prompt() {
+        let mut p = Prompt::new(ScriptedAnswers::new(&[
+            "", "x", "", "  name ", "maybe", "NO", "seven", "7",
+        ]));
+        assert_eq!(p.text("Description", "none").unwrap(), "none");
+        assert_eq!(p.text("Location", "").unwrap(), "x");
+        // invalid answers are asked again
+        assert_eq!(p.required("Title", None).unwrap(), "name");
+        assert!(!p.confirm("Add?", true).unwrap());
+        let n: u32 = p
+            .ask("Number", None, &[], |s| {
+                s.parse().map_err(|_| s.to_string())
+            })
+            .unwrap();
+        assert_eq!(n, 7);
+        // no more answers
+        assert!(p.text("Anything", "").is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeSet;
+
+use chrono::NaiveDate;
+
+use crate::calendar_error::CalendarError;
+use crate::event::{truncate, Event};
+use crate::ics;
+
+/// Properties of the events kept in the QR codes: the ones needed to import them
+const PROPERTIES: [&str; 12] = [
+    "BEGIN", "END", "UID", "DTSTAMP", "DTSTART", "DTEND", "SUMMARY", "LOCATION", "GEO", "RRULE",
+    "EXDATE", "RDATE",
+];
+/// Descriptions longer than this many characters are cut, to keep the codes scannable
+const DESCRIPTION_MAX: usize = 200;
+
+/// Returns a minimal iCalendar document with the event, small enough for a QR code: its
+/// times are floating and only the properties needed to import it are kept, with the
+/// description cut. The occurrences skip the dates of the holiday calendar `holidays`
+pub fn payload(eid: u64, ev: &Event, holidays: Option<&BTreeSet<NaiveDate>>) -> String {
+    let mut ev = ev.clone();
+    let description = truncate(ev.get_description(), DESCRIPTION_MAX).into_owned();
+    ev.set_description(&description);
+    let lines: Vec<String> = ics::vevent(eid, &ev, None, holidays, |_| None)
+        .into_iter()
+        .filter(|line| {
+            let name = line.split([':', ';']).next().unwrap_or_default();
+            PROPERTIES.contains(&name) || (name == "DESCRIPTION" && !description.is_empty())
+        })
+        .collect();
+    ics::vcalendar(vec![lines])
+}
+
+/// Renders the text as a QR code drawn with Unicode blocks, to be scanned from the terminal
+#[cfg(feature = "qr")]
+pub fn render(text: &str) -> Result<String, CalendarError> {
+    use qrcode::render::unicode::Dense1x2;
+    use qrcode::{EcLevel, QrCode};
+
+    let code = QrCode::with_error_correction_level(text, EcLevel::L)
+        .map_err(|e| CalendarError::Unknown(format!("Cannot encode the QR code: {}", e)))?;
+    // light modules on dark terminals are drawn as dark ones, for the readers to recognize
+    Ok(code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .quiet_zone(true)
+        .build())
+}
+
+/// Without the `qr` feature, the events can only be shared as files
+#[cfg(not(feature = "qr"))]
+pub fn render(_text: &str) -> Result<String, CalendarError> {
+    Err(CalendarError::Unknown(
+        "QR codes are not supported: rebuild with the `qr` feature".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event::Event;
+    use crate::qr::{payload, render};
+
+    #[test]
+    /// tests keeping only the properties needed to import the event
+    fn test_payload() {
+        let mut ev = Event::new(
+            "review",
+            &"Ordine del giorno. ".repeat(20),
+            "13/07/2022",
+            "09:30",
+            1.0,
+            None,
+            Some("weekly 3"),
+            None,
+        );
+        ev.set_location("room 1");
+        ev.set_tags(vec![String::from("work")]);
+        let shared = payload(42, &ev, None);
+        for line in [
+            "VERSION:2.0",
+            "UID:42",
+            "DTSTART:20220713T093000",
+            "SUMMARY:review",
+            "LOCATION:room 1",
+        ] {
+            assert!(shared.lines().any(|l| l == line), "{} missing", line);
+        }
+        assert!(shared.contains("RRULE:"));
+        assert!(shared.contains("Ordine del giorno. Ordine"));
+        assert!(!shared.contains("CATEGORIES") && !shared.contains("CREATED"));
+        assert!(shared.len() < 1000);
+
+        let ev = Event::new("lunch", "", "13/07/2022", "12:30", 1.0, None, None, None);
+        assert!(!payload(1, &ev, None).contains("DESCRIPTION"));
+        if cfg!(feature = "qr") {
+            let code = render(&payload(1, &ev, None)).unwrap();
+            assert!(code.lines().count() > 10);
+        } else {
+            assert!(render("").is_err());
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use chrono::NaiveDate;
+
+use crate::event::Event;
+
+/// A field of an event that a condition is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Title,
+    Description,
+    Location,
+    Tag,
+    Color,
+    Date,
+    Important,
+    Recurrent,
+}
+
+/// How a field is compared with the value of a condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Contains,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Text(String),
+    Date(NaiveDate),
+    Flag(bool),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Condition {
+    field: Field,
+    op: Op,
+    value: Value,
+}
+
+/// A selection of events, as the conditions on their fields all to be met, joined by
+/// "and": `tag = course101 and date >= 01/10/2022 and title ~ "lab"`. Texts are compared
+/// ignoring case, `~` matches the ones containing the value, dates are compared with the
+/// start date of the event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    conditions: Vec<Condition>,
+}
+
+/// The operators, longest first so that "<=" is not read as "<"
+const OPS: [(&str, Op); 7] = [
+    ("!=", Op::Ne),
+    ("<=", Op::Le),
+    (">=", Op::Ge),
+    ("=", Op::Eq),
+    ("~", Op::Contains),
+    ("<", Op::Lt),
+    (">", Op::Gt),
+];
+
+/// Splits the expression at the "and" outside of double quotes
+fn split_and(s: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut quoted = false;
+    for word in s.split(' ') {
+        let last = parts.last_mut().unwrap();
+        if !quoted && word.eq_ignore_ascii_case("and") {
+            parts.push(String::new());
+            continue;
+        }
+        quoted ^= word.matches('"').count() % 2 == 1;
+        if !last.is_empty() {
+            last.push(' ');
+        }
+        last.push_str(word);
+    }
+    parts
+}
+
+fn parse_condition(s: &str) -> Result<Condition, String> {
+    let (at, op, len) = OPS
+        .iter()
+        .filter_map(|(sym, op)| s.find(sym).map(|at| (at, *op, sym.len())))
+        .min_by_key(|(at, _, len)| (*at, usize::MAX - len))
+        .ok_or_else(|| format!("Missing operator in condition \"{}\"", s))?;
+    let name = s[..at].trim().to_lowercase();
+    let raw = s[at + len..].trim();
+    let raw = raw
+        .strip_prefix('"')
+        .and_then(|r| r.strip_suffix('"'))
+        .unwrap_or(raw);
+    let field = match name.as_str() {
+        "title" => Field::Title,
+        "description" => Field::Description,
+        "location" => Field::Location,
+        "tag" => Field::Tag,
+        "color" => Field::Color,
+        "date" => Field::Date,
+        "important" => Field::Important,
+        "recurrent" => Field::Recurrent,
+        _ => return Err(format!("Unknown field \"{}\"", name)),
+    };
+    let value = match field {
+        Field::Date => Value::Date(
+            ["%d/%m/%Y", "%Y-%m-%d"]
+                .iter()
+                .find_map(|fmt| NaiveDate::parse_from_str(raw, fmt).ok())
+                .ok_or_else(|| format!("Invalid date {}: expected dd/mm/yyyy", raw))?,
+        ),
+        Field::Important | Field::Recurrent => match raw.to_lowercase().as_str() {
+            "true" | "yes" => Value::Flag(true),
+            "false" | "no" => Value::Flag(false),
+            _ => {
+                return Err(format!(
+                    "Invalid value \"{}\" of {}: true or false",
+                    raw, name
+                ))
+            }
+        },
+        _ => Value::Text(raw.to_lowercase()),
+    };
+    let allowed = match value {
+        Value::Date(_) => true,
+        Value::Flag(_) => matches!(op, Op::Eq | Op::Ne),
+        Value::Text(_) => matches!(op, Op::Eq | Op::Ne | Op::Contains),
+    };
+    if !allowed {
+        return Err(format!("Invalid operator in condition \"{}\"", s));
+    }
+    Ok(Condition { field, op, value })
+}
+
+impl Query {
+    /// Parses an expression, made of conditions `field op value` joined by "and". The fields
+    /// are title, description, location, tag, color (compared with =, != or ~), date (with
+    /// =, !=, <, <=, > or >=), important and recurrent (true or false)
+    pub fn parse(s: &str) -> Result<Query, String> {
+        let conditions = split_and(s)
+            .iter()
+            .map(|c| match c.trim() {
+                "" => Err(format!("Empty condition in \"{}\"", s)),
+                c => parse_condition(c),
+            })
+            .collect::<Result<Vec<Condition>, String>>()?;
+        Ok(Query { conditions })
+    }
+
+    /// Returns true if the event meets all the conditions
+    pub fn matches(&self, ev: &Event) -> bool {
+        self.conditions.iter().all(|c| c.matches(ev))
+    }
+}
+
+impl Condition {
+    fn matches(&self, ev: &Event) -> bool {
+        let text = |s: &str, v: &str| match self.op {
+            Op::Contains => s.to_lowercase().contains(v),
+            _ => s.to_lowercase() == v,
+        };
+        let met = match (&self.value, self.field) {
+            (Value::Text(v), Field::Title) => text(ev.get_title(), v),
+            (Value::Text(v), Field::Description) => text(ev.get_description(), v),
+            (Value::Text(v), Field::Location) => text(ev.get_location(), v),
+            (Value::Text(v), Field::Tag) => {
+                ev.get_metadata().get_tags().iter().any(|tag| text(tag, v))
+            }
+            (Value::Text(v), Field::Color) => text(
+                &ev.get_color().map(|c| c.to_string()).unwrap_or_default(),
+                v,
+            ),
+            (Value::Date(d), _) => {
+                let start = ev.get_start().date();
+                return match self.op {
+                    Op::Eq => start == *d,
+                    Op::Ne => start != *d,
+                    Op::Lt => start < *d,
+                    Op::Le => start <= *d,
+                    Op::Gt => start > *d,
+                    Op::Ge => start >= *d,
+                    Op::Contains => false,
+                };
+            }
+            (Value::Flag(b), Field::Important) => ev.is_important() == *b,
+            (Value::Flag(b), _) => ev.get_recurrence().is_some() == *b,
+            (Value::Text(_), _) => false,
+        };
+        match self.op {
+            Op::Ne => !met,
+            _ => met,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Query;
+    use crate::event::Event;
+
+    #[test]
+    /// tests parsing the expressions, and that the invalid ones are errors
+    fn test_parse() {
+        assert!(Query::parse("tag = course101").is_ok());
+        assert!(Query::parse("title ~ \"lab and exam\" and date >= 01/10/2022").is_ok());
+        assert!(Query::parse("important=true AND recurrent != no").is_ok());
+        assert!(Query::parse("").is_err());
+        assert!(Query::parse("tag = a and").is_err());
+        assert!(Query::parse("tag course101").is_err());
+        assert!(Query::parse("owner = me").is_err());
+        assert!(Query::parse("date >= 32/10/2022").is_err());
+        assert!(Query::parse("title < b").is_err());
+        assert!(Query::parse("important = maybe").is_err());
+    }
+
+    #[test]
+    /// tests selecting events with the conditions on their fields
+    fn test_matches() {
+        let lecture = Event::new(
+            "Lecture",
+            "",
+            "03/10/2022",
+            "09:00",
+            2.0,
+            Some("Room A"),
+            Some("weekly 12"),
+            Some(vec![String::from("course101")]),
+        );
+        let lab = Event::new(
+            "Lab and exam",
+            "",
+            "05/10/2022",
+            "14:00",
+            3.0,
+            None,
+            None,
+            Some(vec![String::from("course101"), String::from("lab")]),
+        );
+        let matching = |q: &str| {
+            let q = Query::parse(q).unwrap();
+            [&lecture, &lab]
+                .iter()
+                .filter(|ev| q.matches(ev))
+                .map(|ev| ev.get_title())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(matching("tag = COURSE101"), vec!["Lecture", "Lab and exam"]);
+        assert_eq!(matching("tag = lab"), vec!["Lab and exam"]);
+        assert_eq!(matching("tag != lab"), vec!["Lecture"]);
+        assert_eq!(matching("title ~ \"lab and\""), vec!["Lab and exam"]);
+        assert_eq!(matching("date > 03/10/2022"), vec!["Lab and exam"]);
+        assert_eq!(
+            matching("date <= 2022-10-03 and location ~ room"),
+            vec!["Lecture"]
+        );
+        assert_eq!(
+            matching("recurrent = false and tag = course101"),
+            vec!["Lab and exam"]
+        );
+        assert!(matching("important = true").is_empty());
+    }
+}
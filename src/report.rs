@@ -0,0 +1,54 @@
+use std::fmt::Display;
+#[cfg(feature = "cli")]
+use std::io::Write;
+
+#[cfg(feature = "cli")]
+use chrono::Local;
+use log::{error, warn};
+#[cfg(feature = "cli")]
+use log::{Level, LevelFilter};
+
+/// Configures the logger: `verbosity` is the number of -v flags given (or -1 if --quiet)
+/// and `json` selects machine-readable output, one JSON object per line.
+/// The RUST_LOG environment variable, if set, takes precedence over the verbosity
+#[cfg(feature = "cli")]
+pub fn init_logging(verbosity: i8, json: bool) {
+    let level = match verbosity {
+        i8::MIN..=-1 => LevelFilter::Error,
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(level).parse_default_env();
+    if json {
+        builder.format(|buf, record| {
+            let entry = serde_json::json!({
+                "timestamp": Local::now().to_rfc3339(),
+                "level": record.level().to_string(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", entry)
+        });
+    } else {
+        builder.format(|buf, record| match record.level() {
+            Level::Error => writeln!(buf, "error: {}", record.args()),
+            Level::Warn => writeln!(buf, "warning: {}", record.args()),
+            lvl => writeln!(buf, "[{} {}] {}", lvl, record.target(), record.args()),
+        });
+    }
+    // the logger may have already been initialized (e.g. by tests)
+    let _ = builder.try_init();
+}
+
+/// Reports a warning to the user
+pub fn warning(msg: impl Display) {
+    warn!("{}", msg);
+}
+
+/// Reports an error to the user
+pub fn error(msg: impl Display) {
+    error!("{}", msg);
+}
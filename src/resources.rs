@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::NaiveDateTime;
+
+use crate::calendar::Calendar;
+use crate::config::ResourceConfig;
+
+/// A time when a resource is booked by more events than its capacity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DoubleBooking {
+    pub resource: String,
+    /// Start of the occurrence exceeding the capacity of the resource
+    pub start: NaiveDateTime,
+    /// Calendar id and eid of the events booking the resource at that time, sorted
+    pub events: Vec<(String, u64)>,
+}
+
+/// Returns how many events can book the resource at the same time: the resources missing
+/// from the configuration can be booked by one event
+pub fn capacity(name: &str, resources: &[ResourceConfig]) -> usize {
+    resources
+        .iter()
+        .find(|r| r.name == name)
+        .map_or(1, |r| r.capacity.max(1))
+}
+
+/// Finds the times when a resource is booked by more events of the calendars than its
+/// capacity, among the occurrences starting from `from`. Every set of events double-booking a
+/// resource is reported once, at its first occurrence, sorted by resource and time
+pub fn double_bookings(
+    calendars: &[&Calendar],
+    resources: &[ResourceConfig],
+    from: NaiveDateTime,
+) -> Vec<DoubleBooking> {
+    // occurrences booking each resource: (start, end, calendar id, eid)
+    let mut bookings: BTreeMap<String, Vec<(NaiveDateTime, NaiveDateTime, &str, u64)>> =
+        BTreeMap::new();
+    for cal in calendars {
+        for (eid, ev) in cal.list_occurrences_between(Some(from), None) {
+            if ev.get_resources().is_empty() {
+                continue;
+            }
+            let (start, end) = (ev.get_start(), ev.occurrence_end(ev.get_start()));
+            for name in ev.get_resources() {
+                bookings
+                    .entry(name.clone())
+                    .or_default()
+                    .push((start, end, cal.get_id(), eid));
+            }
+        }
+    }
+    let mut found = Vec::new();
+    for (name, mut occurrences) in bookings {
+        let cap = capacity(&name, resources);
+        let mut reported: HashSet<Vec<(String, u64)>> = HashSet::new();
+        let mut active: Vec<(NaiveDateTime, NaiveDateTime, &str, u64)> = Vec::new();
+        occurrences.sort_unstable();
+        for occ in occurrences {
+            // as for overlapping events, two occurrences starting together always collide
+            active.retain(|(start, end, _, _)| *end > occ.0 || *start == occ.0);
+            active.push(occ);
+            if active.len() <= cap {
+                continue;
+            }
+            let mut events: Vec<(String, u64)> = active
+                .iter()
+                .map(|(_, _, id, eid)| (id.to_string(), *eid))
+                .collect();
+            events.sort_unstable();
+            events.dedup();
+            if events.len() > cap && reported.insert(events.clone()) {
+                found.push(DoubleBooking {
+                    resource: name.clone(),
+                    start: occ.0,
+                    events,
+                });
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+
+    use crate::calendar::Calendar;
+    use crate::config::ResourceConfig;
+    use crate::event::Event;
+    use crate::resources::{capacity, double_bookings};
+
+    #[test]
+    /// tests finding the resources booked by more events than their capacity, across calendars
+    fn test_double_bookings() {
+        let ev = |title: &str, time: &str, rec: Option<&str>, resources: &[&str]| {
+            let mut ev = Event::new(title, "", "13/07/2022", time, 1.0, None, rec, None);
+            ev.set_resources(resources.iter().map(|r| r.to_string()).collect());
+            ev
+        };
+        let resources = vec![
+            ResourceConfig {
+                name: String::from("room 1"),
+                capacity: 1,
+            },
+            ResourceConfig {
+                name: String::from("projector"),
+                capacity: 2,
+            },
+        ];
+        assert_eq!(capacity("projector", &resources), 2);
+        assert_eq!(capacity("van", &resources), 1);
+
+        let mut work = Calendar::new("owner", "work");
+        work.add_event(ev("standup", "09:00", Some("daily 5"), &["room 1"]));
+        work.add_event(ev("review", "14:00", None, &["room 1", "projector"]));
+        let mut team = Calendar::new("owner", "team");
+        team.add_event(ev("retro", "09:30", Some("weekly 2"), &["room 1"]));
+        team.add_event(ev("demo", "14:30", None, &["projector"]));
+        team.add_event(ev("lunch", "12:00", None, &["room 1"]));
+        let from = NaiveDate::from_ymd_opt(2022, 7, 13)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+
+        let found = double_bookings(&[&work, &team], &resources, from);
+        // the projector is booked by two events at most, and the retro collides with the
+        // standup on the 13th only
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].resource, "room 1");
+        assert_eq!(found[0].start, from + Duration::minutes(570));
+        assert_eq!(found[0].events.len(), 2);
+        assert!(
+            found[0]
+                .events
+                .iter()
+                .any(|(id, eid)| id == "team"
+                    && team.peek_event(*eid).unwrap().get_title() == "retro")
+        );
+        assert!(found[0].events.iter().any(
+            |(id, eid)| id == "work" && work.peek_event(*eid).unwrap().get_title() == "standup"
+        ));
+
+        // a third event booking the projector exceeds its capacity
+        team.add_event(ev("training", "14:00", None, &["projector"]));
+        let found = double_bookings(&[&work, &team], &resources, from);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].resource, "projector");
+        assert_eq!(found[0].events.len(), 3);
+
+        // the occurrences before `from` are not checked
+        assert!(double_bookings(&[&work, &team], &resources, from + Duration::days(1)).is_empty());
+    }
+}
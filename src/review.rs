@@ -0,0 +1,152 @@
+use std::fmt::Write;
+
+use chrono::NaiveDateTime;
+
+use crate::calendar::Calendar;
+
+/// Renders a rating from 1 to 5 as stars, e.g. "★★★☆☆"
+pub fn stars(rating: u8) -> String {
+    let rating = rating.min(5) as usize;
+    "★".repeat(rating) + &"☆".repeat(5 - rating)
+}
+
+/// Renders the occurrences of the events started since `from` and ended by `now`, grouped
+/// by day, each with the note and the rating of its check-in (if any), followed by how many
+/// were checked in and their average rating
+pub fn render(cal: &Calendar, from: NaiveDateTime, now: NaiveDateTime) -> String {
+    let mut out = String::new();
+    let past: Vec<_> = cal
+        .list_events_between(Some(from), Some(now))
+        .into_iter()
+        .filter(|ev| ev.get_start() >= from && ev.occurrence_end(ev.get_start()) <= now)
+        .collect();
+    let mut day = None;
+    let mut ratings = Vec::new();
+    let mut checked_in = 0;
+    for ev in past.iter() {
+        let (start, end) = (ev.get_start(), ev.occurrence_end(ev.get_start()));
+        if day != Some(start.date()) {
+            if day.is_some() {
+                out.push('\n');
+            }
+            let _ = writeln!(out, "{}", start.format("%A %d/%m/%Y"));
+            day = Some(start.date());
+        }
+        let metadata = ev.get_metadata();
+        let checkin = metadata.get_checkin(start);
+        let outcome = match checkin {
+            Some(c) => c.rating.map(stars).unwrap_or_default(),
+            None => String::from("(no check-in)"),
+        };
+        let line = format!(
+            "  {}-{}  {}  {}",
+            start.format("%H:%M"),
+            end.format("%H:%M"),
+            ev.get_title(),
+            outcome
+        );
+        let _ = writeln!(out, "{}", line.trim_end());
+        if let Some(c) = checkin {
+            checked_in += 1;
+            ratings.extend(c.rating);
+            for line in c.note.lines() {
+                let _ = writeln!(out, "               {}", line);
+            }
+        }
+    }
+    if past.is_empty() {
+        out.push_str("No past events to review\n");
+        return out;
+    }
+    let _ = write!(out, "\n{} of {} events checked in", checked_in, past.len());
+    if !ratings.is_empty() {
+        let average = ratings.iter().map(|r| *r as f64).sum::<f64>() / ratings.len() as f64;
+        let _ = write!(out, ", rated {:.1} on average", average);
+    }
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, NaiveDate, TimeZone};
+
+    use crate::calendar::Calendar;
+    use crate::event::{CheckIn, Event};
+    use crate::review::{render, stars};
+
+    #[test]
+    /// tests reviewing the past events with their check-ins
+    fn test_review() {
+        let mut cal = Calendar::new("owner", "work");
+        let ev = |title, date, time| Event::new(title, "", date, time, 1.0, None, None, None);
+        let standup = Event::new(
+            "standup",
+            "",
+            "11/07/2022",
+            "09:00",
+            1.0,
+            None,
+            Some("daily 10"),
+            None,
+        );
+        cal.insert_event(1, standup);
+        cal.insert_event(2, ev("review", "12/07/2022", "14:00"));
+        cal.insert_event(3, ev("retro", "13/07/2022", "11:00"));
+        let at = |d, h, m| {
+            NaiveDate::from_ymd_opt(2022, 7, d)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+        };
+        let time = Local.from_local_datetime(&at(12, 16, 0)).unwrap();
+        cal.update_event(1, |ev| {
+            ev.check_in(CheckIn {
+                occurrence: at(12, 9, 0),
+                note: String::from("short one"),
+                rating: Some(4),
+                time,
+            })
+        })
+        .unwrap();
+        cal.update_event(2, |ev| {
+            ev.check_in(CheckIn {
+                occurrence: at(12, 14, 0),
+                note: String::from("approved\nmerge on Friday"),
+                rating: None,
+                time,
+            })
+        })
+        .unwrap();
+        assert_eq!(
+            cal.last_ended_occurrence(1, at(13, 9, 30)),
+            Some(at(12, 9, 0))
+        );
+        assert_eq!(cal.last_ended_occurrence(3, at(13, 11, 30)), None);
+
+        // the retro is not over yet
+        let review = render(&cal, at(11, 0, 0), at(13, 11, 30));
+        let expected = [
+            "Monday 11/07/2022",
+            "  09:00-10:00  standup  (no check-in)",
+            "",
+            "Tuesday 12/07/2022",
+            "  09:00-10:00  standup  ★★★★☆",
+            "               short one",
+            "  14:00-15:00  review",
+            "               approved",
+            "               merge on Friday",
+            "",
+            "Wednesday 13/07/2022",
+            "  09:00-10:00  standup  (no check-in)",
+            "",
+            "2 of 4 events checked in, rated 4.0 on average",
+        ];
+        assert_eq!(review.lines().collect::<Vec<_>>(), expected);
+        assert_eq!(stars(2), "★★☆☆☆");
+        assert_eq!(
+            render(&cal, at(14, 0, 0), at(14, 8, 0)),
+            "No past events to review\n"
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::BTreeSet;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::calendar::Calendar;
+use crate::calendar_error::CalendarError;
+use crate::event::{Cadence, Event};
+
+/// A rotation of the members of a team through a duty (e.g. on-call), a shift after the other
+/// in turn, each lasting a day or a week, from the first day until the last one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rotation {
+    /// Name of the rotation, the tag of its shifts
+    pub name: String,
+    pub members: Vec<String>,
+    /// Daily or weekly
+    pub cadence: Cadence,
+    pub first: NaiveDate,
+    pub last: NaiveDate,
+}
+
+/// Title of the shifts of the member in the rotation `name`
+pub fn shift_title(name: &str, member: &str) -> String {
+    format!("{}: {}", name, member)
+}
+
+impl Rotation {
+    /// Returns one recurring event for each member, repeating every as many shifts as the
+    /// members are, for the shifts starting until the last day of the rotation
+    pub fn events(&self) -> Result<Vec<Event>, String> {
+        if self.members.is_empty() {
+            return Err(String::from("the rotation has no members"));
+        }
+        if self.first > self.last {
+            return Err(format!(
+                "the rotation ends ({}) before it starts ({})",
+                self.last.format("%d/%m/%Y"),
+                self.first.format("%d/%m/%Y")
+            ));
+        }
+        let (days, cadence) = match self.cadence {
+            Cadence::Daily => (1, "daily"),
+            Cadence::Weekly => (7, "weekly"),
+            _ => return Err(String::from("the shifts last a day or a week")),
+        };
+        let turn = days * self.members.len() as i64;
+        let mut events = Vec::new();
+        for (i, member) in self.members.iter().enumerate() {
+            let first = self.first + Duration::days(days * i as i64);
+            if first > self.last {
+                break;
+            }
+            let reps = (self.last - first).num_days() / turn;
+            let mut ev = Event::new(
+                &shift_title(&self.name, member),
+                "",
+                &first.format("%d/%m/%Y").to_string(),
+                "00:00",
+                0.0,
+                None,
+                Some(format!("{} {} {}", cadence, reps, self.members.len()).as_str())
+                    .filter(|_| reps > 0),
+                Some(vec![self.name.clone()]),
+            );
+            ev.set_duration(&Duration::days(days));
+            events.push(ev);
+        }
+        Ok(events)
+    }
+}
+
+/// Finds the shift of the rotation `name` covering the day: the eid of its event, the start of
+/// the shift and the member on duty
+fn shift_on(cal: &Calendar, name: &str, date: NaiveDate) -> Option<(u64, NaiveDateTime, String)> {
+    let prefix = shift_title(name, "");
+    let day = date.and_time(NaiveTime::MIN);
+    cal.events_by_eid()
+        .into_iter()
+        .filter(|(_, ev)| ev.get_metadata().get_tags().iter().any(|tag| tag == name))
+        .find_map(|(eid, ev)| {
+            let member = ev.get_title().strip_prefix(&prefix)?;
+            let start = ev
+                .occurrences()
+                .take_while(|start| *start <= day)
+                .filter(|start| ev.occurrence_end(*start) > day)
+                .last()?;
+            Some((eid, start, member.to_string()))
+        })
+}
+
+/// Hands the shift of the rotation `name` covering the day over to `member`: the shift is
+/// excluded from the recurring event of the member on duty, and added as a single event of
+/// the other one. Returns the member that was on duty
+pub fn swap(
+    cal: &mut Calendar,
+    name: &str,
+    date: NaiveDate,
+    member: &str,
+) -> Result<String, CalendarError> {
+    let (eid, start, on_duty) = shift_on(cal, name, date).ok_or_else(|| {
+        CalendarError::Unknown(format!(
+            "No shift of the rotation {} on {}",
+            name,
+            date.format("%d/%m/%Y")
+        ))
+    })?;
+    if on_duty == member {
+        return Err(CalendarError::Unknown(format!(
+            "{} is already on duty on {}",
+            member,
+            date.format("%d/%m/%Y")
+        )));
+    }
+    let title = shift_title(name, member);
+    let Some(end) = cal
+        .peek_event(eid)
+        .filter(|ev| ev.get_recurrence().is_some())
+        .map(|ev| ev.occurrence_end(start))
+    else {
+        // a single shift, already swapped
+        cal.update_event(eid, |ev| ev.set_title(&title))?;
+        return Ok(on_duty);
+    };
+    cal.update_event(eid, |ev| {
+        let mut exdates: BTreeSet<NaiveDate> = ev
+            .get_recurrence()
+            .map(|rec| rec.exdates().clone())
+            .unwrap_or_default();
+        exdates.insert(start.date());
+        ev.set_exdates(exdates)
+    })?;
+    let mut shift = Event::new(
+        &title,
+        "",
+        &start.format("%d/%m/%Y").to_string(),
+        &start.format("%H:%M").to_string(),
+        0.0,
+        None,
+        None,
+        Some(vec![name.to_string()]),
+    );
+    shift.set_duration(&(end - start));
+    cal.add_event(shift);
+    Ok(on_duty)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::calendar::Calendar;
+    use crate::event::Cadence;
+    use crate::rotation::{swap, Rotation};
+    use crate::testing::date;
+
+    /// Returns the member on duty on each day, as the titles of the events on it
+    fn on_duty(cal: &Calendar, day: NaiveDate) -> Vec<String> {
+        let from = day.and_hms_opt(12, 0, 0);
+        cal.list_events_between(from, from)
+            .iter()
+            .map(|ev| ev.get_title().to_string())
+            .collect()
+    }
+
+    #[test]
+    /// tests generating the shifts of the members in turn, and swapping them
+    fn test_rotation() {
+        let mut rotation = Rotation {
+            name: String::from("on-call"),
+            members: ["alice", "bob", "carol"].map(String::from).to_vec(),
+            cadence: Cadence::Weekly,
+            first: date(9, 5),
+            last: date(10, 30),
+        };
+        let events = rotation.events().unwrap();
+        assert_eq!(events.len(), 3);
+        let starts: Vec<Vec<NaiveDate>> = events
+            .iter()
+            .map(|ev| ev.occurrences().map(|dt| dt.date()).collect())
+            .collect();
+        assert_eq!(starts[0], [date(9, 5), date(9, 26), date(10, 17)]);
+        assert_eq!(starts[1], [date(9, 12), date(10, 3), date(10, 24)]);
+        assert_eq!(starts[2], [date(9, 19), date(10, 10)]);
+        assert_eq!(events[0].get_duration(), 7 * 24 * 3600);
+
+        let mut cal = Calendar::new("owner", "ops");
+        for ev in events {
+            cal.add_event(ev);
+        }
+        assert_eq!(on_duty(&cal, date(9, 28)), ["on-call: alice"]);
+        // bob takes the shift of alice
+        assert_eq!(
+            swap(&mut cal, "on-call", date(9, 28), "bob").unwrap(),
+            "alice"
+        );
+        assert_eq!(on_duty(&cal, date(9, 28)), ["on-call: bob"]);
+        assert_eq!(on_duty(&cal, date(9, 26)), ["on-call: bob"]);
+        assert_eq!(on_duty(&cal, date(10, 17)), ["on-call: alice"]);
+        // then carol takes it
+        assert_eq!(
+            swap(&mut cal, "on-call", date(10, 2), "carol").unwrap(),
+            "bob"
+        );
+        assert_eq!(on_duty(&cal, date(9, 30)), ["on-call: carol"]);
+        assert_eq!(cal.get_size(), 4);
+        assert!(swap(&mut cal, "on-call", date(10, 2), "carol").is_err());
+        assert!(swap(&mut cal, "on-call", date(12, 1), "carol").is_err());
+        assert!(swap(&mut cal, "support", date(9, 28), "carol").is_err());
+
+        rotation.cadence = Cadence::Monthly;
+        assert!(rotation.events().is_err());
+        rotation.cadence = Cadence::Daily;
+        rotation.members.clear();
+        assert!(rotation.events().is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use std::path::Path;
+use std::sync::mpsc::TryRecvError;
+
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+
+use crate::audit;
+use crate::calendar::Calendar;
+use crate::calendar_error::CalendarError;
+use crate::cli::{self, Commands};
+use crate::prompt::WordCompleter;
+use crate::{report, storage};
+
+/// Commands understood by the shell besides the subcommands
+const SHELL_COMMANDS: [&str; 4] = ["save", "exit", "quit", "help"];
+/// Subcommands available in the shell
+const SUBCOMMANDS: [&str; 32] = [
+    "add",
+    "remove",
+    "edit",
+    "join",
+    "shift",
+    "list",
+    "show",
+    "share",
+    "export",
+    "import",
+    "set",
+    "digest",
+    "countdown",
+    "plan",
+    "schedule",
+    "availability",
+    "course",
+    "rotation",
+    "itinerary",
+    "freebusy",
+    "stats",
+    "heatmap",
+    "project",
+    "checkin",
+    "review",
+    "done",
+    "habits",
+    "location",
+    "exchange",
+    "sync",
+    "subscribe",
+    "refresh",
+];
+
+/// Returns the words completed in the shell: subcommands, tags and event titles
+fn completions(cal: &Calendar) -> Vec<String> {
+    let mut words: Vec<String> = SUBCOMMANDS
+        .iter()
+        .chain(SHELL_COMMANDS.iter())
+        .map(|s| s.to_string())
+        .collect();
+    words.extend(cal.tags());
+    for ev in cal.list_events_between(None, None) {
+        let title = ev.get_title();
+        if title.contains(char::is_whitespace) {
+            words.push(format!("\"{}\"", title));
+        } else {
+            words.push(title.to_string());
+        }
+    }
+    words
+}
+
+/// Saves the changes made to the calendar since it was `last_saved`
+fn save(last_saved: &Calendar, cal: &Calendar, dry_run: bool, data_dir: &Path) -> bool {
+    if dry_run {
+        println!("Dry run: calendar {} not saved", cal.get_name());
+        return false;
+    }
+    let saved = storage::save_audited(last_saved, cal, &audit::current_user(), data_dir);
+    if !saved {
+        report::warning(format!(
+            "Cannot write calendar {} to {}",
+            cal.get_name(),
+            data_dir.display()
+        ));
+    }
+    saved
+}
+
+/// Runs an interactive shell on the calendar, executing one subcommand per line.
+/// The calendar is written to disk on `save`, and `last_saved` is updated accordingly;
+/// returns whether there are unsaved changes when the user leaves the shell
+pub fn run(
+    cal: &mut Calendar,
+    last_saved: &mut Calendar,
+    readonly: bool,
+    dry_run: bool,
+    data_dir: &Path,
+) -> Result<bool, CalendarError> {
+    let mut editor: Editor<WordCompleter, DefaultHistory> =
+        Editor::new().map_err(|e| CalendarError::Unknown(e.to_string()))?;
+    let prompt = format!("{}{}> ", cal.get_id(), if readonly { " (ro)" } else { "" });
+    let mut modified = false;
+    println!("Type `help` for the list of commands, `exit` to save and leave");
+    editor.set_helper(Some(WordCompleter::new(completions(cal))));
+    let mut changes = cal.subscribe();
+    loop {
+        // the events are scanned again for completions only if they changed
+        let mut changed = changes.try_iter().count() > 0;
+        if let Err(TryRecvError::Disconnected) = changes.try_recv() {
+            // the calendar was replaced as a whole
+            changes = cal.subscribe();
+            changed = true;
+        }
+        if changed {
+            editor.set_helper(Some(WordCompleter::new(completions(cal))));
+        }
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(CalendarError::Unknown(e.to_string())),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+        match line {
+            "exit" | "quit" => break,
+            "help" => {
+                cli::print_commands_help();
+                println!("\nShell commands: {}", SHELL_COMMANDS.join(", "));
+                continue;
+            }
+            "save" => {
+                if !readonly && save(last_saved, cal, dry_run, data_dir) {
+                    *last_saved = cal.clone();
+                    modified = false;
+                }
+                continue;
+            }
+            _ => (),
+        }
+        let cmd = match cli::split_words(line) {
+            Ok(words) => cli::parse_command(&words),
+            Err(e) => {
+                report::error(e);
+                continue;
+            }
+        };
+        match cmd {
+            Ok(Commands::List(l)) => {
+                cli::handle_list(cal, l);
+            }
+            Ok(cmd) => {
+                if cli::exec_subcommand(cal, cmd, readonly, data_dir) {
+                    modified = true;
+                }
+            }
+            Err(e) => {
+                let _ = e.print();
+            }
+        }
+    }
+    Ok(modified)
+}
@@ -0,0 +1,207 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::calendar::Calendar;
+use crate::calendar_error::CalendarError;
+use crate::storage::validate_id;
+use crate::users::hex;
+
+/// Directory of the snapshots inside the data directory, with a subdirectory per calendar
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+/// File listing the snapshots of a calendar, inside its directory
+const INDEX_FILE: &str = "index.json";
+
+/// A snapshot of a calendar: its contents are stored once per distinct state, in a file
+/// named after their hash
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub time: NaiveDateTime,
+    pub hash: String,
+}
+
+fn snapshots_dir(id: &str, data_dir: &Path) -> Result<PathBuf, CalendarError> {
+    validate_id(id)?;
+    Ok(data_dir.join(SNAPSHOTS_DIR).join(id))
+}
+
+fn io_error(path: &Path, e: impl std::fmt::Display) -> CalendarError {
+    CalendarError::Unknown(format!("{}: {}", path.display(), e))
+}
+
+/// Returns the snapshots of the calendar with the given id, from the oldest one
+pub fn list(id: &str, data_dir: &Path) -> Result<Vec<Snapshot>, CalendarError> {
+    let path = snapshots_dir(id, data_dir)?.join(INDEX_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents = fs::read_to_string(&path).map_err(|e| io_error(&path, e))?;
+    serde_json::from_str(&contents).map_err(|e| io_error(&path, e))
+}
+
+/// Takes a snapshot of the calendar at the given time. Its contents are only written if
+/// no other snapshot has the same ones
+pub fn create(
+    cal: &Calendar,
+    time: NaiveDateTime,
+    data_dir: &Path,
+) -> Result<Snapshot, CalendarError> {
+    let dir = snapshots_dir(cal.get_id(), data_dir)?;
+    fs::create_dir_all(&dir).map_err(|e| io_error(&dir, e))?;
+    // the maps in a JSON value are sorted, so equal calendars have the same contents
+    let contents = serde_json::to_value(cal)
+        .map(|v| v.to_string())
+        .map_err(|e| CalendarError::Unknown(e.to_string()))?;
+    let hash = hex(&Sha256::digest(&contents));
+    let object = dir.join(&hash).with_extension("json");
+    if !object.exists() {
+        fs::write(&object, &contents).map_err(|e| io_error(&object, e))?;
+    }
+
+    let mut snapshots = list(cal.get_id(), data_dir)?;
+    let snapshot = Snapshot { time, hash };
+    snapshots.push(snapshot.clone());
+    snapshots.sort_by_key(|s| s.time);
+    write_index(&dir, &snapshots)?;
+    Ok(snapshot)
+}
+
+fn write_index(dir: &Path, snapshots: &[Snapshot]) -> Result<(), CalendarError> {
+    let index = dir.join(INDEX_FILE);
+    let tmp = index.with_extension("json.tmp");
+    let contents = serde_json::to_string_pretty(snapshots)
+        .map_err(|e| CalendarError::Unknown(e.to_string()))?;
+    fs::write(&tmp, contents)
+        .and_then(|_| fs::rename(&tmp, &index))
+        .map_err(|e| io_error(&index, e))
+}
+
+/// Deletes the snapshots of the calendar with the given id taken before `before` (but the
+/// last one, kept to restore the calendar), along with the contents no other snapshot has.
+/// Returns the number of snapshots deleted
+pub fn prune(id: &str, before: NaiveDateTime, data_dir: &Path) -> Result<usize, CalendarError> {
+    let mut snapshots = list(id, data_dir)?;
+    let last = snapshots.pop();
+    let (old, mut kept): (Vec<Snapshot>, Vec<Snapshot>) =
+        snapshots.into_iter().partition(|s| s.time < before);
+    if old.is_empty() {
+        return Ok(0);
+    }
+    kept.extend(last);
+    let dir = snapshots_dir(id, data_dir)?;
+    write_index(&dir, &kept)?;
+    for snapshot in old.iter() {
+        if kept.iter().all(|s| s.hash != snapshot.hash) {
+            let object = dir.join(&snapshot.hash).with_extension("json");
+            if object.exists() {
+                fs::remove_file(&object).map_err(|e| io_error(&object, e))?;
+            }
+        }
+    }
+    Ok(old.len())
+}
+
+/// Finds a snapshot given a prefix of its hash, or a date (%d/%m/%Y, optionally followed by
+/// %H:%M): the last snapshot taken by then (by the end of the day, for a date) is returned
+pub fn find<'a>(snapshots: &'a [Snapshot], at: &str) -> Result<&'a Snapshot, CalendarError> {
+    let when = NaiveDateTime::parse_from_str(at, "%d/%m/%Y %H:%M")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(at, "%d/%m/%Y")
+                .ok()
+                .and_then(|d| d.and_hms_opt(23, 59, 59))
+        });
+    if let Some(when) = when {
+        return snapshots
+            .iter()
+            .rev()
+            .find(|s| s.time <= when)
+            .ok_or_else(|| CalendarError::Unknown(format!("No snapshot taken by {}", at)));
+    }
+    let matching: Vec<&Snapshot> = snapshots
+        .iter()
+        .filter(|s| !at.is_empty() && s.hash.starts_with(at))
+        .collect();
+    match matching.last() {
+        None => Err(CalendarError::Unknown(format!(
+            "No snapshot matching {}",
+            at
+        ))),
+        Some(last) if matching.iter().any(|s| s.hash != last.hash) => Err(CalendarError::Unknown(
+            format!("{} matches more than one snapshot", at),
+        )),
+        Some(last) => Ok(last),
+    }
+}
+
+/// Reads the contents of the snapshot of the calendar with the given id
+pub fn load(id: &str, snapshot: &Snapshot, data_dir: &Path) -> Result<Calendar, CalendarError> {
+    let object = snapshots_dir(id, data_dir)?
+        .join(&snapshot.hash)
+        .with_extension("json");
+    let contents = fs::read_to_string(&object).map_err(|e| io_error(&object, e))?;
+    serde_json::from_str(&contents).map_err(|e| io_error(&object, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::calendar::Calendar;
+    use crate::event::Event;
+    use crate::snapshot::{create, find, list, load, prune, SNAPSHOTS_DIR};
+    use crate::testing::{at, TempDir};
+
+    #[test]
+    /// tests taking snapshots of a calendar and finding them by date or hash
+    fn test_snapshots() {
+        let dir = TempDir::new("snapshots");
+
+        let mut cal = Calendar::new("owner", "work");
+        assert!(list(cal.get_id(), &dir).unwrap().is_empty());
+        let first = create(&cal, at(11, 9, 0), &dir).unwrap();
+        cal.add_event(Event::new(
+            "review",
+            "",
+            "13/07/2022",
+            "09:30",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        let second = create(&cal, at(12, 9, 0), &dir).unwrap();
+        // the same contents are stored once
+        let third = create(&cal.clone(), at(14, 9, 0), &dir).unwrap();
+        assert_ne!(first.hash, second.hash);
+        assert_eq!(second.hash, third.hash);
+        let objects = fs::read_dir(dir.join(SNAPSHOTS_DIR).join("work")).unwrap();
+        assert_eq!(objects.count(), 3);
+
+        let snapshots = list("work", &dir).unwrap();
+        assert_eq!(snapshots, [first.clone(), second.clone(), third.clone()]);
+        assert_eq!(find(&snapshots, "11/07/2022").unwrap(), &first);
+        assert_eq!(find(&snapshots, "12/07/2022 08:00").unwrap(), &first);
+        assert_eq!(find(&snapshots, "13/07/2022").unwrap(), &second);
+        assert!(find(&snapshots, "10/07/2022").is_err());
+        assert_eq!(find(&snapshots, &first.hash[..8]).unwrap(), &first);
+        assert_eq!(find(&snapshots, &second.hash[..8]).unwrap(), &third);
+        assert!(find(&snapshots, "").is_err());
+        assert!(find(&snapshots, "xyz").is_err());
+
+        assert_eq!(load("work", &first, &dir).unwrap().get_size(), 0);
+        assert_eq!(load("work", &third, &dir).unwrap(), cal);
+
+        // the contents of the second snapshot are kept, shared with the third one
+        assert_eq!(prune("work", at(13, 0, 0), &dir).unwrap(), 2);
+        assert_eq!(list("work", &dir).unwrap().len(), 1);
+        assert_eq!(load("work", &third, &dir).unwrap(), cal);
+        assert!(load("work", &first, &dir).is_err());
+        // the last snapshot is never deleted
+        assert_eq!(prune("work", at(20, 0, 0), &dir).unwrap(), 0);
+        assert_eq!(list("work", &dir).unwrap(), [third]);
+    }
+}
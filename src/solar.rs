@@ -0,0 +1,72 @@
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::event::Geo;
+
+/// Altitude of the center of the sun at sunrise and sunset, in degrees: below the horizon
+/// because of the refraction and of the radius of its disc
+const HORIZON: f64 = -0.833;
+
+/// Returns the sunrise and the sunset (in UTC) on the date at the place, or None if the sun
+/// does not rise or does not set on that date (polar day and night).
+///
+/// Uses the sunrise equation (as NOAA's simplified algorithm), accurate to about a minute
+/// away from the polar circles
+pub fn sun_times(date: NaiveDate, place: &Geo) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1)?;
+    let (lat, lon) = (place.lat().to_radians(), place.lon());
+    // mean solar time at the place, in days since J2000
+    let n = (date - epoch).num_days() as f64 - lon / 360.0;
+    let anomaly = (357.5291 + 0.98560028 * n).rem_euclid(360.0).to_radians();
+    let center =
+        1.9148 * anomaly.sin() + 0.02 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic = (anomaly.to_degrees() + center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit = 2451545.0 + n + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic).sin();
+    let declination = (ecliptic.sin() * 23.4397_f64.to_radians().sin()).asin();
+    let cos_hour = (HORIZON.to_radians().sin() - lat.sin() * declination.sin())
+        / (lat.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&cos_hour) {
+        return None;
+    }
+    let hour = cos_hour.acos().to_degrees() / 360.0;
+    Some((
+        julian_to_utc(transit - hour)?,
+        julian_to_utc(transit + hour)?,
+    ))
+}
+
+/// Converts a julian day to the UTC time, to the second
+fn julian_to_utc(jd: f64) -> Option<NaiveDateTime> {
+    let secs = ((jd - 2440587.5) * 86400.0).round() as i64;
+    chrono::DateTime::from_timestamp(secs, 0).map(|dt| dt.naive_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    use crate::event::Geo;
+    use crate::solar::sun_times;
+
+    #[test]
+    /// tests the sunrise and sunset against published times, and the polar day and night
+    fn test_sun_times() {
+        let near = |a: NaiveDateTime, b: &str| {
+            let b = NaiveDateTime::parse_from_str(b, "%Y-%m-%d %H:%M").unwrap();
+            assert!((a - b).num_minutes().abs() <= 2, "{} is not {}", a, b);
+        };
+        let rome: Geo = "41.9028,12.4964".parse().unwrap();
+        let summer = NaiveDate::from_ymd_opt(2022, 6, 21).unwrap();
+        let (rise, set) = sun_times(summer, &rome).unwrap();
+        near(rise, "2022-06-21 03:36");
+        near(set, "2022-06-21 18:48");
+        let (rise, set) = sun_times(NaiveDate::from_ymd_opt(2022, 12, 21).unwrap(), &rome).unwrap();
+        near(rise, "2022-12-21 06:34");
+        near(set, "2022-12-21 15:42");
+
+        let svalbard: Geo = "78.2232,15.6267".parse().unwrap();
+        assert!(sun_times(summer, &svalbard).is_none());
+        assert!(sun_times(NaiveDate::from_ymd_opt(2022, 12, 21).unwrap(), &svalbard).is_none());
+    }
+}
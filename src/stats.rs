@@ -0,0 +1,476 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Write};
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::calendar::Calendar;
+use crate::freebusy;
+use crate::planner::format_duration;
+
+/// Levels of the sparklines, from the lowest value to the highest
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// Shades of the cells of the heatmap, from the days without busy events to the busiest ones
+const SHADES: [char; 5] = ['·', '░', '▒', '▓', '█'];
+/// Colors of the shades (in the 256 colors palette): greens, as in the contribution graphs
+/// of GitHub
+const SHADE_COLORS: [u8; 5] = [240, 22, 28, 34, 40];
+const RESET: &str = "\x1b[0m";
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatsFormat {
+    /// A table, for the terminal
+    Text,
+    /// Comma separated values, one row per period (e.g. for gnuplot or pandas)
+    Csv,
+    /// An array of objects, one per period
+    Json,
+}
+
+/// How long the periods aggregated are
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Bucket {
+    Day,
+    /// From Monday to Sunday
+    Week,
+}
+
+/// The aggregates of a period of the calendar
+#[derive(Debug, Serialize, PartialEq)]
+pub struct PeriodStats {
+    /// First day of the period
+    pub start: NaiveDate,
+    /// Hours taken by the busy events, without counting twice the overlapping ones
+    pub busy_hours: f64,
+    /// Number of occurrences starting in the period
+    pub events: usize,
+    /// Hours taken by the events with each tag (used in any period)
+    pub tags: BTreeMap<String, f64>,
+}
+
+/// A week in which the events with a tag take longer than its budget
+#[derive(Debug, PartialEq)]
+pub struct OverBudget {
+    pub tag: String,
+    /// Monday of the week
+    pub week: NaiveDate,
+    pub hours: f64,
+    pub budget: f64,
+}
+
+impl Display for OverBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of events tagged \"{}\" in the week of {}, over the budget of {}",
+            format_hours(self.hours),
+            self.tag,
+            self.week.format("%a %d/%m/%Y"),
+            format_hours(self.budget)
+        )
+    }
+}
+
+/// Rounds the hours to the minute (two decimals), for readable outputs
+fn hours(d: Duration) -> f64 {
+    (d.num_minutes() as f64 / 60.0 * 100.0).round() / 100.0
+}
+
+/// Formats the hours compactly, e.g. "2h30m"
+fn format_hours(h: f64) -> String {
+    format_duration(Duration::minutes((h * 60.0).round() as i64))
+}
+
+/// Aggregates the events of the days from `first` to `last` (included) by day or by week:
+/// the weeks are cut to the days given
+pub fn aggregate(
+    cal: &Calendar,
+    first: NaiveDate,
+    last: NaiveDate,
+    by: Bucket,
+) -> Vec<PeriodStats> {
+    let mut periods = Vec::new();
+    let mut start = first;
+    while start <= last {
+        let next = match by {
+            Bucket::Day => start + Duration::days(1),
+            Bucket::Week => {
+                start + Duration::days(7 - start.weekday().num_days_from_monday() as i64)
+            }
+        };
+        let end = next.min(last + Duration::days(1));
+        let (from, until) = (start.and_time(NaiveTime::MIN), end.and_time(NaiveTime::MIN));
+        let busy = freebusy::busy_intervals(cal, from, until)
+            .into_iter()
+            .map(|(s, e)| e - s)
+            .fold(Duration::zero(), |total, d| total + d);
+        let mut events = 0;
+        let mut tags: BTreeMap<String, Duration> = BTreeMap::new();
+        for ev in cal.list_events_between(Some(from), Some(until)) {
+            let occurrence = (ev.get_start(), ev.occurrence_end(ev.get_start()));
+            if occurrence.0 >= until {
+                continue;
+            }
+            if occurrence.0 >= from {
+                events += 1;
+            }
+            let taken = occurrence.1.min(until) - occurrence.0.max(from);
+            for tag in ev.get_metadata().get_tags() {
+                *tags.entry(tag).or_insert_with(Duration::zero) += taken;
+            }
+        }
+        periods.push(PeriodStats {
+            start,
+            busy_hours: hours(busy),
+            events,
+            tags: tags.into_iter().map(|(t, d)| (t, hours(d))).collect(),
+        });
+        start = end;
+    }
+    // the same columns in every period
+    let all_tags: Vec<String> = periods
+        .iter()
+        .flat_map(|p| p.tags.keys().cloned())
+        .collect();
+    for period in periods.iter_mut() {
+        for tag in all_tags.iter() {
+            period.tags.entry(tag.clone()).or_insert(0.0);
+        }
+    }
+    periods
+}
+
+/// Returns the weeks (from Monday to Sunday) covering the days from `first` to `last` in
+/// which the events with a tag take longer than its weekly budget, in hours
+pub fn over_budget(
+    cal: &Calendar,
+    budgets: &BTreeMap<String, f64>,
+    first: NaiveDate,
+    last: NaiveDate,
+) -> Vec<OverBudget> {
+    if budgets.is_empty() {
+        return Vec::new();
+    }
+    let monday = first - Duration::days(first.weekday().num_days_from_monday() as i64);
+    let sunday = last + Duration::days(6 - last.weekday().num_days_from_monday() as i64);
+    aggregate(cal, monday, sunday, Bucket::Week)
+        .into_iter()
+        .flat_map(|week| {
+            budgets.iter().filter_map(move |(tag, budget)| {
+                let hours = week.tags.get(tag).copied().unwrap_or_default();
+                (hours > *budget).then(|| OverBudget {
+                    tag: tag.clone(),
+                    week: week.start,
+                    hours,
+                    budget: *budget,
+                })
+            })
+        })
+        .collect()
+}
+
+/// Quotes a CSV field, if needed
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Renders the aggregates as CSV, with a header: the date (as %Y-%m-%d), the busy hours,
+/// the number of events and the hours of each tag
+pub fn render_csv(periods: &[PeriodStats]) -> String {
+    let tags: Vec<&String> = periods
+        .first()
+        .map(|p| p.tags.keys().collect())
+        .unwrap_or_default();
+    let mut out = String::from("date,busy_hours,events");
+    for tag in tags.iter() {
+        out.push_str(&format!(",{}", csv_field(&format!("tag:{}", tag))));
+    }
+    out.push('\n');
+    for period in periods {
+        let _ = write!(
+            out,
+            "{},{:.2},{}",
+            period.start.format("%Y-%m-%d"),
+            period.busy_hours,
+            period.events
+        );
+        for hours in period.tags.values() {
+            let _ = write!(out, ",{:.2}", hours);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Renders the values as a sparkline, one character per value scaled to the highest one
+pub fn sparkline(values: &[f64]) -> String {
+    let max = values.iter().cloned().fold(0.0, f64::max);
+    values
+        .iter()
+        .map(|v| match max > 0.0 {
+            true => SPARKS[((v / max) * (SPARKS.len() - 1) as f64).round() as usize],
+            false => SPARKS[0],
+        })
+        .collect()
+}
+
+/// Renders the aggregates as a table, followed by the sparklines of the busy hours, of the
+/// events and of the hours of each tag if `sparklines` is set
+pub fn render_text(periods: &[PeriodStats], sparklines: bool) -> String {
+    let tags: Vec<&String> = periods
+        .first()
+        .map(|p| p.tags.keys().collect())
+        .unwrap_or_default();
+    let widths: Vec<usize> = tags.iter().map(|t| t.chars().count().max(6)).collect();
+    let mut out = format!("{:<15} {:>6} {:>6}", "Period", "Busy", "Events");
+    for (tag, width) in tags.iter().zip(widths.iter()) {
+        let _ = write!(out, " {:>width$}", tag);
+    }
+    out.push('\n');
+    for period in periods {
+        let _ = write!(
+            out,
+            "{:<15} {:>6} {:>6}",
+            period.start.format("%a %d/%m/%Y"),
+            format_hours(period.busy_hours),
+            period.events
+        );
+        for (hours, width) in period.tags.values().zip(widths.iter()) {
+            let _ = write!(out, " {:>width$}", format_hours(*hours));
+        }
+        out.push('\n');
+    }
+    if sparklines && !periods.is_empty() {
+        let mut series = vec![
+            (
+                "busy",
+                periods.iter().map(|p| p.busy_hours).collect::<Vec<f64>>(),
+            ),
+            ("events", periods.iter().map(|p| p.events as f64).collect()),
+        ];
+        for tag in tags.iter() {
+            series.push((tag.as_str(), periods.iter().map(|p| p.tags[*tag]).collect()));
+        }
+        let width = series
+            .iter()
+            .map(|(name, _)| name.chars().count())
+            .max()
+            .unwrap_or(0);
+        out.push('\n');
+        for (name, values) in series {
+            let _ = writeln!(out, "{:<width$} {}", name, sparkline(&values));
+        }
+    }
+    out
+}
+
+/// Renders the busy hours of the days (aggregated by day) as a heatmap, with a column per
+/// week and a row per day of the week: the busier the day, the darker its cell. The months
+/// are written above the weeks they start in, and the total hours and the busiest day below
+pub fn render_heatmap(days: &[PeriodStats], color: bool) -> String {
+    let (Some(first), Some(last)) = (days.first(), days.last()) else {
+        return String::new();
+    };
+    let monday = first.start - Duration::days(first.start.weekday().num_days_from_monday() as i64);
+    let weeks = ((last.start - monday).num_days() / 7 + 1) as usize;
+    let max = days.iter().map(|d| d.busy_hours).fold(0.0, f64::max);
+    let level = |h: f64| match h > 0.0 {
+        true => ((h / max * 4.0).ceil() as usize).clamp(1, 4),
+        false => 0,
+    };
+    let mut grid = vec![vec![None; weeks]; 7];
+    for day in days {
+        let offset = (day.start - monday).num_days() as usize;
+        grid[offset % 7][offset / 7] = Some(level(day.busy_hours));
+    }
+    let mut months = vec![' '; weeks];
+    let mut free_from = 0;
+    for day in days
+        .iter()
+        .filter(|d| d.start.day() == 1 || d.start == first.start)
+    {
+        let week = (day.start - monday).num_days() as usize / 7;
+        let name = day.start.format("%b").to_string();
+        if week >= free_from && week + name.len() <= weeks {
+            months.splice(week..week + name.len(), name.chars());
+            free_from = week + name.len() + 1;
+        }
+    }
+    let shade = |level: usize| match color {
+        true => format!(
+            "\x1b[38;5;{}m{}{}",
+            SHADE_COLORS[level], SHADES[level], RESET
+        ),
+        false => SHADES[level].to_string(),
+    };
+    let mut out = format!(
+        "    {}\n",
+        months.into_iter().collect::<String>().trim_end()
+    );
+    for (row, name) in grid.iter().zip(["Mon", "", "Wed", "", "Fri", "", ""]) {
+        let cells: String = row
+            .iter()
+            .map(|cell| cell.map(shade).unwrap_or_else(|| String::from(" ")))
+            .collect();
+        let _ = writeln!(out, "{:<3} {}", name, cells.trim_end());
+    }
+    let legend: String = (0..SHADES.len()).map(shade).collect();
+    let _ = writeln!(out, "    Less {} More", legend);
+    let total: f64 = days.iter().map(|d| d.busy_hours).sum();
+    let busy_days = days.iter().filter(|d| d.busy_hours > 0.0).count();
+    let _ = write!(
+        out,
+        "\n{} scheduled in {} {}",
+        format_hours(total),
+        busy_days,
+        if busy_days == 1 { "day" } else { "days" }
+    );
+    match days.iter().find(|d| d.busy_hours == max && max > 0.0) {
+        Some(busiest) => {
+            let _ = writeln!(
+                out,
+                ", the busiest is {} ({})",
+                busiest.start.format("%a %d/%m/%Y"),
+                format_hours(max)
+            );
+        }
+        None => out.push('\n'),
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{Duration, NaiveDate};
+
+    use crate::calendar::Calendar;
+    use crate::event::{Event, Transparency};
+    use crate::stats::{
+        aggregate, over_budget, render_csv, render_heatmap, render_text, sparkline, Bucket,
+        OverBudget,
+    };
+
+    #[test]
+    /// tests aggregating the events by day and by week, and rendering the aggregates
+    fn test_stats() {
+        let mut cal = Calendar::new("owner", "work");
+        let tags = |t: &[&str]| Some(t.iter().map(|t| t.to_string()).collect());
+        let ev =
+            |title, date, time, hours, t| Event::new(title, "", date, time, hours, None, None, t);
+        let mut standup = ev("standup", "11/07/2022", "09:00", 1.0, tags(&["work"]));
+        standup.set_duration(&Duration::minutes(30));
+        cal.insert_event(1, standup);
+        // overlapping with the standup
+        cal.insert_event(
+            2,
+            ev("review", "11/07/2022", "09:00", 2.0, tags(&["work", "a,b"])),
+        );
+        cal.insert_event(3, ev("deploy", "13/07/2022", "23:00", 2.0, tags(&["work"])));
+        let mut gym = ev("gym", "18/07/2022", "18:00", 1.0, None);
+        gym.set_transparency(Transparency::Free);
+        cal.insert_event(4, gym);
+
+        let day = |d| NaiveDate::from_ymd_opt(2022, 7, d).unwrap();
+        let days = aggregate(&cal, day(11), day(14), Bucket::Day);
+        assert_eq!(days.len(), 4);
+        assert_eq!(days[0].busy_hours, 2.0);
+        assert_eq!(days[0].events, 2);
+        assert_eq!(days[0].tags["work"], 2.5);
+        assert_eq!(days[0].tags["a,b"], 2.0);
+        assert_eq!(days[1].tags["a,b"], 0.0);
+        // the deploy spans two days
+        assert_eq!((days[2].busy_hours, days[2].events), (1.0, 1));
+        assert_eq!((days[3].busy_hours, days[3].events), (1.0, 0));
+
+        // the weeks are cut to the days given
+        let weeks = aggregate(&cal, day(13), day(20), Bucket::Week);
+        assert_eq!(weeks.len(), 2);
+        assert_eq!((weeks[0].start, weeks[1].start), (day(13), day(18)));
+        assert_eq!((weeks[0].busy_hours, weeks[0].events), (2.0, 1));
+        // the gym is free time
+        assert_eq!((weeks[1].busy_hours, weeks[1].events), (0.0, 1));
+
+        let csv = render_csv(&days);
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("date,busy_hours,events,\"tag:a,b\",tag:work")
+        );
+        assert_eq!(lines.next(), Some("2022-07-11,2.00,2,2.00,2.50"));
+        assert_eq!(csv.lines().count(), 5);
+        let json = serde_json::to_value(&days).unwrap();
+        assert_eq!(json[0]["start"], "2022-07-11");
+        assert_eq!(json[0]["tags"]["work"], 2.5);
+
+        assert_eq!(sparkline(&[0.0, 1.0, 3.5, 7.0]), "▁▂▅█");
+        assert_eq!(sparkline(&[0.0, 0.0]), "▁▁");
+        let text = render_text(&days, true);
+        assert!(text.contains("Mon 11/07/2022      2h      2"));
+        assert!(text.lines().any(|l| l == "busy   █▁▅▅"));
+        assert!(!render_text(&days, false).contains('█'));
+    }
+
+    #[test]
+    /// tests drawing the busy hours of the days as a heatmap
+    fn test_heatmap() {
+        let mut cal = Calendar::new("owner", "work");
+        let ev = |date, hours| Event::new("work", "", date, "09:00", hours, None, None, None);
+        cal.insert_event(1, ev("11/07/2022", 4.0));
+        cal.insert_event(2, ev("12/07/2022", 1.0));
+        let first = NaiveDate::from_ymd_opt(2022, 7, 1).unwrap();
+        let last = NaiveDate::from_ymd_opt(2022, 8, 31).unwrap();
+        let days = aggregate(&cal, first, last, Bucket::Day);
+        let heatmap = render_heatmap(&days, false);
+        let lines: Vec<&str> = heatmap.lines().collect();
+        // from the week of Monday 27/06 to the one of Monday 29/08
+        assert_eq!(lines[0], "    Jul  Aug");
+        assert_eq!(lines[1], "Mon  ·█·······");
+        assert_eq!(lines[2], "     ·░·······");
+        // Friday 01/07 is the first day
+        assert_eq!(lines[5], "Fri ·········");
+        assert_eq!(lines[8], "    Less ·░▒▓█ More");
+        assert_eq!(
+            lines[10],
+            "5h scheduled in 2 days, the busiest is Mon 11/07/2022 (4h)"
+        );
+        assert!(render_heatmap(&days, true).contains("\x1b[38;5;40m█\x1b[0m"));
+        assert!(render_heatmap(&[], false).is_empty());
+    }
+
+    #[test]
+    /// tests finding the weeks in which the tags take longer than their budgets
+    fn test_over_budget() {
+        let mut cal = Calendar::new("owner", "work");
+        let tags = Some(vec![String::from("meetings")]);
+        let meeting = |date| Event::new("sync", "", date, "10:00", 3.0, None, None, tags.clone());
+        for (eid, date) in [(1, "11/07/2022"), (2, "13/07/2022"), (3, "18/07/2022")] {
+            cal.insert_event(eid, meeting(date));
+        }
+        let budgets = BTreeMap::from([(String::from("meetings"), 5.0)]);
+        let day = |d| NaiveDate::from_ymd_opt(2022, 7, d).unwrap();
+        // the whole weeks are checked
+        let over = over_budget(&cal, &budgets, day(14), day(20));
+        assert_eq!(
+            over,
+            [OverBudget {
+                tag: String::from("meetings"),
+                week: day(11),
+                hours: 6.0,
+                budget: 5.0,
+            }]
+        );
+        assert_eq!(
+            over[0].to_string(),
+            "6h of events tagged \"meetings\" in the week of Mon 11/07/2022, over the budget of 5h"
+        );
+        assert!(over_budget(&cal, &budgets, day(18), day(24)).is_empty());
+        assert!(over_budget(&cal, &BTreeMap::new(), day(11), day(17)).is_empty());
+    }
+}
@@ -0,0 +1,968 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+use log::info;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, AuditEntry, ChangeListener};
+use crate::availability::Availability;
+use crate::calendar::{slugify, Calendar, MAX_ID_LEN};
+use crate::calendar_error::CalendarError;
+use crate::config::{self, StorageConfig};
+use crate::conflicts;
+use crate::event::Event;
+use crate::report;
+use crate::users;
+
+/// File stems that cannot be used on some platforms (Windows device names)
+const RESERVED_NAMES: [&str; 22] = [
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Files of the data directory that are not calendars, whose names cannot be calendar ids
+const DATA_FILES: [&str; 2] = [config::CONFIG_FILE, users::USERS_FILE];
+
+/// Checks that the given id can be safely used as a file stem inside the data directory:
+/// path separators, relative components, reserved names (including the ones of the other
+/// files of the data directory) and overly long names are rejected
+pub fn validate_id(id: &str) -> Result<(), CalendarError> {
+    let reason = if id.is_empty() {
+        Some("empty name")
+    } else if id.len() > MAX_ID_LEN {
+        Some("name too long")
+    } else if id == "." || id == ".." || id.starts_with('.') {
+        Some("names cannot start with '.'")
+    } else if id.contains(['/', '\\', '\0', ':']) {
+        Some("names cannot contain path separators")
+    } else if RESERVED_NAMES.contains(&id.to_lowercase().as_str()) {
+        Some("reserved name")
+    } else if DATA_FILES.iter().any(|file| {
+        Path::new(file)
+            .file_stem()
+            .is_some_and(|stem| stem.eq_ignore_ascii_case(id))
+    }) {
+        Some("name of a file of the data directory")
+    } else {
+        None
+    };
+    match reason {
+        Some(r) => Err(CalendarError::InvalidCalendarName(format!("{id}: {r}"))),
+        None => Ok(()),
+    }
+}
+
+/// Returns the path of the file storing the calendar with the given id
+pub fn calendar_path(id: &str, data_dir: &Path) -> Result<PathBuf, CalendarError> {
+    validate_id(id)?;
+    Ok(data_dir.join(id).with_extension("json"))
+}
+
+/// A change to a calendar, stored as a line of its change log
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Change {
+    Add { eid: u64, event: Event },
+    Update { eid: u64, event: Event },
+    Remove { eid: u64 },
+    Meta { name: String, owner: String },
+    Availability { windows: Vec<Availability> },
+}
+
+impl Change {
+    fn apply(self, cal: &mut Calendar) {
+        match self {
+            Change::Add { eid, event } | Change::Update { eid, event } => {
+                cal.insert_event(eid, event)
+            }
+            Change::Remove { eid } => {
+                let _ = cal.remove_event(eid);
+            }
+            Change::Meta { name, owner } => {
+                cal.set_name(&name);
+                cal.set_owner(&owner);
+            }
+            Change::Availability { windows } => cal.set_availability(windows),
+        }
+    }
+}
+
+/// The change log is compacted into the calendar file when it gets larger than this
+/// fraction of the calendar file...
+const COMPACTION_RATIO: u64 = 4;
+/// ...and of this size (in bytes)
+const COMPACTION_MIN_SIZE: u64 = 64 * 1024;
+
+/// Applies the changes in the log file at `p` to the calendar. Replaying stops at the
+/// first malformed line, as it may have been partially written
+fn replay_log(cal: &mut Calendar, p: &Path) -> Result<(), CalendarError> {
+    let f = File::open(p)?;
+    for (lineno, line) in BufReader::new(f).lines().enumerate() {
+        match serde_json::from_str::<Change>(&line?) {
+            Ok(change) => change.apply(cal),
+            Err(e) => {
+                report::warning(format!(
+                    "{}:{}: ignoring the rest of the change log: {}",
+                    p.display(),
+                    lineno + 1,
+                    e
+                ));
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the calendar at `p` (the path of its files, without extension), along with the
+/// dates of the holiday calendars referenced by its events
+pub fn read_calendar(p: &Path) -> Result<Calendar, CalendarError> {
+    let mut cal = read_calendar_file(p)?;
+    let data_dir = p.parent().unwrap_or_else(|| Path::new("."));
+    for id in cal.holiday_calendars() {
+        // the holidays of the holiday calendar itself are not needed to list its days
+        match calendar_path(&id, data_dir).and_then(|path| read_calendar_file(&path)) {
+            Ok(holidays) => cal.set_holidays(&id, holidays.event_days()),
+            Err(_) => report::warning(format!(
+                "Holiday calendar {} of {} not found",
+                id,
+                cal.get_id()
+            )),
+        }
+    }
+    Ok(cal)
+}
+
+fn read_calendar_file(p: &Path) -> Result<Calendar, CalendarError> {
+    let p2 = &p.with_extension("json");
+    if Path::exists(p2) {
+        let f = File::open(p2)?;
+        let reader = BufReader::new(f);
+        if let Ok::<Calendar, _>(mut cal) = serde_json::from_reader(reader) {
+            // calendars saved before ids were introduced are identified by their file stem
+            if cal.get_id().is_empty() {
+                if let Some(stem) = p2.file_stem() {
+                    cal.set_id(&stem.to_string_lossy());
+                }
+            }
+            let log = p2.with_extension("log");
+            if log.exists() {
+                replay_log(&mut cal, &log)?;
+            }
+            // calendars in an older format are upgraded in memory, their files are rewritten
+            // in the current one by the next change saved (or by maintenance)
+            if cal.migrate() {
+                info!(
+                    "Calendar {} is migrated to format version {}",
+                    cal.get_id(),
+                    cal.get_version()
+                );
+            }
+            return Ok(cal);
+        }
+    }
+    Err(CalendarError::CalendarNotFound(
+        p2.to_string_lossy().to_string(),
+    ))
+}
+
+/// Finds the calendar referred to by `key`, that can be either its id or its display name
+pub fn resolve_calendar(key: &str, p: &Path) -> Result<Calendar, CalendarError> {
+    // fast path: key is the id of the calendar
+    if validate_id(key).is_ok() && slugify(key) == key {
+        if let Ok(cal) = read_calendar(&p.join(key)) {
+            return Ok(cal);
+        }
+    }
+    // stops at the first match, dropping the receiver stops reading the other calendars
+    for (cal, _) in stream_calendars(p)? {
+        if let Ok(cal) = cal {
+            if cal.get_id() == key || cal.get_name() == key {
+                return Ok(cal);
+            }
+        }
+    }
+    Err(CalendarError::CalendarNotFound(key.to_string()))
+}
+
+/// Finds the calendar referred to by `key` in the data directories, searched in order,
+/// returning it along with the directory it was found in. The directories that cannot be
+/// read (e.g. a share not mounted) are warned about and skipped
+pub fn resolve_in_roots(
+    key: &str,
+    roots: &[PathBuf],
+) -> Result<(Calendar, PathBuf), CalendarError> {
+    for root in roots {
+        match resolve_calendar(key, root) {
+            Ok(cal) => return Ok((cal, root.clone())),
+            Err(CalendarError::CalendarNotFound(_)) => (),
+            Err(e) => report::warning(format!("{}: {:?}", root.display(), e)),
+        }
+    }
+    Err(CalendarError::CalendarNotFound(key.to_string()))
+}
+
+pub fn create_calendar(
+    calname: &str,
+    cal_owner: &str,
+    cal_id: Option<&str>,
+    p: &Path,
+) -> Result<Calendar, CalendarError> {
+    let mut cal = Calendar::new(cal_owner, calname);
+    if let Some(id) = cal_id {
+        // an explicit id is not escaped, so that the user gets exactly the requested file name
+        cal.set_id(id);
+    }
+    let cal_file = calendar_path(cal.get_id(), p)?;
+    let dir_iter = fs::read_dir(p)?;
+
+    for entry in dir_iter.flatten() {
+        if entry.path() == cal_file {
+            return Err(CalendarError::CalendarAlreadyExists(
+                cal.get_id().to_string(),
+            ));
+        }
+    }
+    Ok(cal)
+}
+
+/// Creates the calendar `name` with the owner and settings of the calendar `src` (its id or
+/// name), also copying its events if `with_events`. The new calendar is not saved
+pub fn clone_calendar(
+    src: &str,
+    name: &str,
+    with_events: bool,
+    p: &Path,
+) -> Result<Calendar, CalendarError> {
+    let src = resolve_calendar(src, p)?;
+    let mut cal = create_calendar(name, src.get_owner(), None, p)?;
+    cal.copy_from(&src, with_events);
+    Ok(cal)
+}
+
+pub fn delete_calendar(key: &str, p: &Path) -> bool {
+    match resolve_calendar(key, p).and_then(|cal| calendar_path(cal.get_id(), p)) {
+        Ok(path) => {
+            for ext in [
+                "log",
+                "audit",
+                "exchange",
+                "subscription",
+                "conflicts",
+                "snoozes",
+            ] {
+                let log = path.with_extension(ext);
+                if log.exists() && fs::remove_file(log).is_err() {
+                    return false;
+                }
+            }
+            fs::remove_file(path).is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
+/// A calendar found in the data directory (or the error that occurred reading it) and its path
+pub type KnownCalendar = (Result<Calendar, CalendarError>, PathBuf);
+
+/// Returns the paths of the calendar files in the data directory (besides the configuration
+/// and the users), warning about the conflicting copies of any file, which are skipped
+fn calendar_files(p: &Path) -> Result<Vec<PathBuf>, CalendarError> {
+    let mut paths = Vec::new();
+    for ent in fs::read_dir(p)?.flatten() {
+        let path = ent.path();
+        if conflicts::original_of(&path).is_some() {
+            report::warning(format!(
+                "{}: conflicting copy left by a sync tool, merge it with `conflicts resolve`",
+                path.display()
+            ));
+        } else if path.extension().is_some_and(|ext| ext == "json")
+            && !path.ends_with(config::CONFIG_FILE)
+            && !path.ends_with(users::USERS_FILE)
+        {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+/// Reads all the calendars in the data directory in parallel: each one is sent on the
+/// returned channel (in no particular order) as soon as it has been read.
+/// Dropping the receiver stops reading the remaining calendars
+pub fn stream_calendars(p: &Path) -> Result<Receiver<KnownCalendar>, CalendarError> {
+    let paths = calendar_files(p)?;
+    let (tx, rx) = mpsc::channel();
+    rayon::spawn(move || {
+        let _ = paths.into_par_iter().try_for_each_with(tx, |tx, path| {
+            let cal = read_calendar(&path);
+            tx.send((cal, path)).map_err(|_| ())
+        });
+    });
+    Ok(rx)
+}
+
+/// Reads all the calendars in the data directory, along with the path of their file
+pub fn known_calendars(p: &Path) -> Result<Vec<KnownCalendar>, CalendarError> {
+    Ok(stream_calendars(p)?.into_iter().collect())
+}
+
+/// Reads the calendars in all the data directories, along with the directory each one was
+/// found in: the calendars with the same id as one in an earlier directory are hidden by it.
+/// The directories that cannot be read are warned about and skipped
+pub fn known_in_roots(roots: &[PathBuf]) -> Vec<(KnownCalendar, PathBuf)> {
+    let mut found: Vec<(KnownCalendar, PathBuf)> = Vec::new();
+    for root in roots {
+        let known = match known_calendars(root) {
+            Ok(known) => known,
+            Err(e) => {
+                report::warning(format!("{}: {:?}", root.display(), e));
+                continue;
+            }
+        };
+        let ids: Vec<String> = found
+            .iter()
+            .filter_map(|((cal, _), _)| cal.as_ref().ok().map(|c| c.get_id().to_string()))
+            .collect();
+        found.extend(
+            known
+                .into_iter()
+                .filter(|(cal, _)| {
+                    cal.as_ref()
+                        .map_or(true, |c| !ids.iter().any(|id| id == c.get_id()))
+                })
+                .map(|known| (known, root.clone())),
+        );
+    }
+    found
+}
+
+/// Writes the calendar as JSON in the format set in the configuration: sorting turns it
+/// into a JSON value first, whose maps (the events, by id) are ordered by key
+fn write_calendar(writer: impl Write, cal: &Calendar, format: &StorageConfig) -> bool {
+    let value = if format.sorted {
+        match serde_json::to_value(cal) {
+            Ok(v) => Some(v),
+            Err(_) => return false,
+        }
+    } else {
+        None
+    };
+    match (value, format.pretty) {
+        (Some(v), true) => serde_json::to_writer_pretty(writer, &v),
+        (Some(v), false) => serde_json::to_writer(writer, &v),
+        (None, true) => serde_json::to_writer_pretty(writer, cal),
+        (None, false) => serde_json::to_writer(writer, cal),
+    }
+    .is_ok()
+}
+
+/// Writes the whole calendar to its file inside the data directory, discarding its change log
+pub fn save_calendar(cal: &Calendar, data_dir: &Path) -> bool {
+    let p = match calendar_path(cal.get_id(), data_dir) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    let format = config::load(data_dir).map_or_else(
+        |e| {
+            report::warning(format!("{:?}: saving in the default format", e));
+            StorageConfig::default()
+        },
+        |c| c.storage,
+    );
+    // the calendar is written to a temporary file, then moved over the old one
+    let tmp = p.with_extension("json.tmp");
+    let written = match File::create(&tmp) {
+        Ok(f) => {
+            let mut writer = BufWriter::new(f);
+            write_calendar(&mut writer, cal, &format) && writer.flush().is_ok()
+        }
+        Err(_) => false,
+    };
+    if !written || fs::rename(&tmp, &p).is_err() {
+        let _ = fs::remove_file(&tmp);
+        return false;
+    }
+    let log = p.with_extension("log");
+    !log.exists() || fs::remove_file(log).is_ok()
+}
+
+/// Saves the changes made to a calendar, given its state `before` (as read from its file)
+/// and `after` the changes: these are appended to the calendar's change log, instead of
+/// rewriting the whole file. The log is compacted into the calendar file when it grows
+/// too large with respect to it, and the file is rewritten if in an older format
+pub fn save_changes(before: &Calendar, after: &Calendar, data_dir: &Path) -> bool {
+    let p = match calendar_path(after.get_id(), data_dir) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    let cal_size = match fs::metadata(&p) {
+        Ok(m) => m.len(),
+        // new calendar: no file to append changes to
+        Err(_) => return save_calendar(after, data_dir),
+    };
+    if before.get_id() != after.get_id() {
+        return save_calendar(after, data_dir);
+    }
+    let log = p.with_extension("log");
+    let log_size = fs::metadata(&log).map_or(0, |m| m.len());
+    if log_size > COMPACTION_MIN_SIZE && log_size > cal_size / COMPACTION_RATIO {
+        return save_calendar(after, data_dir);
+    }
+
+    let diff = before.diff(after);
+    let mut changes = Vec::new();
+    if before.get_name() != after.get_name() || before.get_owner() != after.get_owner() {
+        changes.push(Change::Meta {
+            name: after.get_name().to_string(),
+            owner: after.get_owner().to_string(),
+        });
+    }
+    if before.get_availability() != after.get_availability() {
+        changes.push(Change::Availability {
+            windows: after.get_availability().to_vec(),
+        });
+    }
+    for eid in diff.removed {
+        changes.push(Change::Remove { eid });
+    }
+    for (eids, added) in [(diff.added, true), (diff.edited, false)] {
+        for eid in eids {
+            if let Some(ev) = after.peek_event(eid) {
+                let event = ev.clone();
+                changes.push(if added {
+                    Change::Add { eid, event }
+                } else {
+                    Change::Update { eid, event }
+                });
+            }
+        }
+    }
+    if changes.is_empty() {
+        return true;
+    }
+    // the changes cannot be appended to a file in an older format, rewritten instead
+    if before.is_migrated() {
+        return save_calendar(after, data_dir);
+    }
+    let f = match OpenOptions::new().create(true).append(true).open(&log) {
+        Ok(f) => f,
+        Err(_) => return false,
+    };
+    let mut writer = BufWriter::new(f);
+    for change in changes {
+        match serde_json::to_string(&change) {
+            Ok(line) => {
+                if writeln!(writer, "{}", line).is_err() {
+                    return false;
+                }
+            }
+            Err(_) => return false,
+        }
+    }
+    writer.flush().is_ok()
+}
+
+/// Persists the modifications of the calendars in their audit logs, next to their files
+/// (one JSON object per line)
+pub struct AuditLog<'a> {
+    data_dir: &'a Path,
+}
+
+impl<'a> AuditLog<'a> {
+    pub fn new(data_dir: &'a Path) -> AuditLog<'a> {
+        AuditLog { data_dir }
+    }
+}
+
+impl ChangeListener for AuditLog<'_> {
+    fn changed(&mut self, calendar_id: &str, entry: &AuditEntry) {
+        let written = calendar_path(calendar_id, self.data_dir).and_then(|p| {
+            let line =
+                serde_json::to_string(entry).map_err(|e| CalendarError::Unknown(e.to_string()))?;
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(p.with_extension("audit"))
+                .and_then(|mut f| writeln!(f, "{}", line))
+                .map_err(|e| CalendarError::Unknown(e.to_string()))
+        });
+        if let Err(e) = written {
+            report::warning(format!(
+                "Cannot write the audit log of {}: {:?}",
+                calendar_id, e
+            ));
+        }
+    }
+}
+
+/// Reads the audit log of the calendar with the given id: it is empty if the calendar
+/// has never been modified
+pub fn read_audit_log(id: &str, data_dir: &Path) -> Result<Vec<AuditEntry>, CalendarError> {
+    let p = calendar_path(id, data_dir)?.with_extension("audit");
+    if !p.exists() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for (lineno, line) in BufReader::new(File::open(&p)?).lines().enumerate() {
+        let entry = serde_json::from_str(&line?).map_err(|e| {
+            CalendarError::Unknown(format!("{}:{}: {}", p.display(), lineno + 1, e))
+        })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Saves the changes made to a calendar as [`save_changes`] does, recording them in its
+/// audit log as made by `user`
+pub fn save_audited(before: &Calendar, after: &Calendar, user: &str, data_dir: &Path) -> bool {
+    if !save_changes(before, after, data_dir) {
+        return false;
+    }
+    audit::emit(before, after, user, &mut AuditLog::new(data_dir));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::Duration;
+
+    use crate::audit::AuditAction;
+    use crate::availability::Availability;
+    use crate::calendar::{Calendar, FORMAT_VERSION, MAX_ID_LEN};
+    use crate::config::CONFIG_FILE;
+    use crate::event::{Event, HolidayAction, HolidayRule};
+    use crate::storage::{
+        calendar_path, clone_calendar, create_calendar, delete_calendar, known_calendars,
+        known_in_roots, read_audit_log, read_calendar, resolve_calendar, resolve_in_roots,
+        save_audited, save_calendar, save_changes, validate_id, COMPACTION_MIN_SIZE,
+    };
+    use crate::testing::TempDir;
+
+    #[test]
+    /// tests that unsafe ids are rejected
+    fn test_validate_id() {
+        assert!(validate_id("work").is_ok());
+        assert!(validate_id("my-work-cal").is_ok());
+        assert!(validate_id("").is_err());
+        assert!(validate_id(".").is_err());
+        assert!(validate_id("..").is_err());
+        assert!(validate_id(".hidden").is_err());
+        assert!(validate_id("../../evil").is_err());
+        assert!(validate_id("a/b").is_err());
+        assert!(validate_id("a\\b").is_err());
+        assert!(validate_id("CON").is_err());
+        assert!(validate_id("lpt1").is_err());
+        assert!(validate_id("users").is_err());
+        assert!(validate_id("Users").is_err());
+        assert!(validate_id("users-2").is_ok());
+        assert!(validate_id("config").is_err());
+        assert!(validate_id("CONFIG").is_err());
+        assert!(validate_id(&"x".repeat(MAX_ID_LEN)).is_ok());
+        assert!(validate_id(&"x".repeat(MAX_ID_LEN + 1)).is_err());
+    }
+
+    #[test]
+    /// tests that calendars cannot be created outside the data directory
+    fn test_traversal() {
+        let dir = TempDir::new("traversal");
+
+        // names are escaped into a safe id
+        let cal = create_calendar("../../evil", "owner", None, &dir).unwrap();
+        assert_eq!(cal.get_id(), "evil");
+        assert_eq!(
+            calendar_path(cal.get_id(), &dir).unwrap(),
+            dir.join("evil.json")
+        );
+        // explicit ids are validated instead
+        assert!(create_calendar("name", "owner", Some("../../evil"), &dir).is_err());
+        assert!(create_calendar("name", "owner", Some("nul"), &dir).is_err());
+        // nor can they replace the user database
+        assert!(create_calendar("users", "owner", None, &dir).is_err());
+        assert!(create_calendar("name", "owner", Some("USERS"), &dir).is_err());
+        // or the configuration
+        assert!(create_calendar("config", "owner", None, &dir).is_err());
+        assert!(create_calendar("name", "owner", Some("Config"), &dir).is_err());
+        // saving a calendar with a bad id fails
+        let mut bad = Calendar::new("owner", "bad");
+        bad.set_id("../bad");
+        assert!(!save_calendar(&bad, &dir));
+    }
+
+    #[test]
+    /// tests that calendars can be referred to by either their id or name
+    fn test_resolve() {
+        let dir = TempDir::new("resolve");
+
+        let cal = create_calendar("My Calendar", "owner", None, &dir).unwrap();
+        assert!(save_calendar(&cal, &dir));
+        assert!(dir.join("my-calendar.json").exists());
+        assert_eq!(resolve_calendar("my-calendar", &dir).unwrap(), cal);
+        assert_eq!(resolve_calendar("My Calendar", &dir).unwrap(), cal);
+        assert!(resolve_calendar("Other Calendar", &dir).is_err());
+        assert!(create_calendar("My Calendar", "owner", None, &dir).is_err());
+    }
+
+    #[test]
+    /// tests cloning calendars, with and without their events
+    fn test_clone() {
+        let dir = TempDir::new("clone");
+
+        let mut cal = create_calendar("Fall 2022", "owner", None, &dir).unwrap();
+        cal.add_availability(
+            Availability::parse("office hours", "mon-fri", "09:00-17:00").unwrap(),
+        );
+        cal.add_event(Event::new(
+            "lecture",
+            "",
+            "05/09/2022",
+            "10:00",
+            2.0,
+            None,
+            None,
+            None,
+        ));
+        assert!(save_calendar(&cal, &dir));
+
+        let empty = clone_calendar("fall-2022", "Spring 2023", false, &dir).unwrap();
+        assert_eq!(empty.get_id(), "spring-2023");
+        assert_eq!(empty.get_owner(), "owner");
+        assert_eq!(empty.get_availability(), cal.get_availability());
+        assert_eq!(empty.get_size(), 0);
+        let full = clone_calendar("Fall 2022", "Fall 2023", true, &dir).unwrap();
+        assert_eq!(full.diff(&cal), Default::default());
+        assert!(clone_calendar("Fall 2022", "Fall 2022", false, &dir).is_err());
+        assert!(clone_calendar("Winter", "Spring 2023", false, &dir).is_err());
+    }
+
+    #[test]
+    /// tests that changes are appended to the change log and replayed when reading
+    fn test_change_log() {
+        let dir = TempDir::new("change-log");
+        let cal_file = dir.join("log-test.json");
+        let log_file = dir.join("log-test.log");
+
+        let mut cal = Calendar::new("owner", "log test");
+        cal.add_event(Event::new(
+            "e1",
+            "",
+            "01/01/2022",
+            "10:00",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        assert!(save_changes(&cal, &cal, &dir));
+        assert!(cal_file.exists() && !log_file.exists());
+        let cal_size = fs::metadata(&cal_file).unwrap().len();
+
+        let before = read_calendar(&cal_file).unwrap();
+        cal.add_event(Event::new(
+            "e2",
+            "",
+            "02/01/2022",
+            "10:00",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        cal.set_owner("other");
+        assert!(save_changes(&before, &cal, &dir));
+        // the calendar file is untouched, changes are in the log
+        assert_eq!(fs::metadata(&cal_file).unwrap().len(), cal_size);
+        assert!(log_file.exists());
+        assert_eq!(read_calendar(&cal_file).unwrap(), cal);
+
+        let before = read_calendar(&cal_file).unwrap();
+        let eid = *before.diff(&Calendar::new("", "")).removed.first().unwrap();
+        cal.remove_event(eid).unwrap();
+        assert!(save_changes(&before, &cal, &dir));
+        assert_eq!(read_calendar(&cal_file).unwrap(), cal);
+
+        // a full save compacts the log
+        assert!(save_calendar(&cal, &dir));
+        assert!(!log_file.exists());
+        assert_eq!(read_calendar(&cal_file).unwrap(), cal);
+    }
+
+    #[test]
+    /// tests that calendars saved before durations were stored in seconds are migrated
+    fn test_migration() {
+        let dir = TempDir::new("migration");
+        let cal_file = dir.join("old.json");
+
+        let mut ev = Event::default();
+        ev.set_duration(&Duration::minutes(90));
+        let mut cal = Calendar::new("owner", "old");
+        cal.add_event(ev);
+        // an unversioned calendar, with durations in minutes
+        let mut json = serde_json::to_value(&cal).unwrap();
+        let obj = json.as_object_mut().unwrap();
+        obj.remove("version");
+        for ev in obj["events"].as_object_mut().unwrap().values_mut() {
+            ev["duration"] = serde_json::json!(90);
+        }
+        fs::write(&cal_file, serde_json::to_string(&json).unwrap()).unwrap();
+
+        let stored_version = || {
+            let saved: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(&cal_file).unwrap()).unwrap();
+            saved["version"].clone()
+        };
+        let read = read_calendar(&cal_file).unwrap();
+        assert_eq!(read.get_version(), FORMAT_VERSION);
+        assert!(read.is_migrated());
+        assert_eq!(read.events_by_eid(), cal.events_by_eid());
+        // reading the calendar, or saving it unchanged, leaves the file as it is
+        assert!(save_changes(&read, &read.clone(), &dir));
+        assert_eq!(stored_version(), serde_json::Value::Null);
+        // the first change rewrites the file in the current format, instead of logging it
+        let mut after = read.clone();
+        after.add_event(Event::new(
+            "new",
+            "",
+            "20/07/2022",
+            "10:00",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        assert!(save_changes(&read, &after, &dir));
+        assert_eq!(stored_version(), serde_json::json!(FORMAT_VERSION));
+        assert!(!dir.join("old.log").exists());
+        let read = read_calendar(&cal_file).unwrap();
+        assert!(!read.is_migrated());
+        assert_eq!(read.events_by_eid(), after.events_by_eid());
+    }
+
+    #[test]
+    /// tests reading the holidays of a calendar from its holiday calendar
+    fn test_holidays() {
+        let dir = TempDir::new("holidays");
+
+        let mut holidays = Calendar::new("owner", "holidays");
+        holidays.add_event(Event::new(
+            "bank holiday",
+            "",
+            "19/07/2022",
+            "00:00",
+            24.0,
+            None,
+            None,
+            None,
+        ));
+        assert!(save_calendar(&holidays, &dir));
+        let mut work = Calendar::new("owner", "work");
+        let mut standup = Event::new(
+            "standup",
+            "",
+            "18/07/2022",
+            "09:00",
+            0.5,
+            None,
+            Some("daily 2"),
+            None,
+        );
+        standup.set_holidays(Some(HolidayRule {
+            action: HolidayAction::Skip,
+            weekends: false,
+            calendar: Some(String::from("holidays")),
+        }));
+        work.add_event(standup);
+        assert!(save_calendar(&work, &dir));
+
+        let read = read_calendar(&dir.join("work")).unwrap();
+        let days: Vec<String> = read
+            .list_events_between(None, None)
+            .iter()
+            .map(|ev| ev.get_start_date().format("%d").to_string())
+            .collect();
+        assert_eq!(days, ["18", "20"]);
+    }
+
+    #[test]
+    /// tests that a large change log is compacted
+    fn test_compaction() {
+        let dir = TempDir::new("compaction");
+        let log_file = dir.join("compaction-test.log");
+
+        let mut cal = Calendar::new("owner", "compaction test");
+        assert!(save_calendar(&cal, &dir));
+        let mut i = 0;
+        while fs::metadata(&log_file).map_or(0, |m| m.len()) <= COMPACTION_MIN_SIZE {
+            let before = cal.clone();
+            let descr = "x".repeat(1000);
+            cal.add_event(Event::new(
+                &i.to_string(),
+                &descr,
+                "01/01/2022",
+                "10:00",
+                1.0,
+                None,
+                None,
+                None,
+            ));
+            assert!(save_changes(&before, &cal, &dir));
+            i += 1;
+        }
+        let before = cal.clone();
+        cal.add_event(Event::new(
+            "last",
+            "",
+            "01/01/2022",
+            "10:00",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        assert!(save_changes(&before, &cal, &dir));
+        assert!(!log_file.exists());
+        assert_eq!(read_calendar(&dir.join("compaction-test")).unwrap(), cal);
+    }
+
+    #[test]
+    /// tests reading all the calendars in the data directory
+    fn test_known_calendars() {
+        let dir = TempDir::new("known");
+
+        for i in 0..20 {
+            let cal = Calendar::new("owner", &format!("Calendar {i}"));
+            assert!(save_calendar(&cal, &dir));
+        }
+        fs::write(dir.join("broken.json"), "{ not a calendar").unwrap();
+        fs::write(dir.join("notes.txt"), "ignored").unwrap();
+        fs::write(dir.join(CONFIG_FILE), "{}").unwrap();
+
+        let known = known_calendars(&dir).unwrap();
+        assert_eq!(known.len(), 21);
+        assert_eq!(known.iter().filter(|(cal, _)| cal.is_ok()).count(), 20);
+        assert_eq!(
+            resolve_calendar("Calendar 13", &dir).unwrap().get_id(),
+            "calendar-13"
+        );
+    }
+
+    #[test]
+    /// tests finding the calendars in several data directories, the earlier ones hiding the
+    /// calendars with the same id in the later ones
+    fn test_data_roots() {
+        let dir = TempDir::new("roots");
+        let (personal, shared) = (dir.join("personal"), dir.join("shared"));
+        fs::create_dir_all(&personal).unwrap();
+        fs::create_dir_all(&shared).unwrap();
+        assert!(save_calendar(&Calendar::new("me", "Work"), &personal));
+        assert!(save_calendar(&Calendar::new("team", "Work"), &shared));
+        assert!(save_calendar(&Calendar::new("team", "Holidays"), &shared));
+        let roots = [personal.clone(), dir.join("unmounted"), shared.clone()];
+
+        let (work, root) = resolve_in_roots("work", &roots).unwrap();
+        assert_eq!((work.get_owner(), root), ("me", personal.clone()));
+        let (holidays, root) = resolve_in_roots("Holidays", &roots).unwrap();
+        assert_eq!((holidays.get_id(), root), ("holidays", shared.clone()));
+        assert!(resolve_in_roots("birthdays", &roots).is_err());
+
+        let mut known: Vec<(String, String)> = known_in_roots(&roots)
+            .into_iter()
+            .map(|((cal, _), root)| {
+                let cal = cal.unwrap();
+                (cal.get_owner().to_string(), root.display().to_string())
+            })
+            .collect();
+        known.sort();
+        assert_eq!(
+            known,
+            [
+                (String::from("me"), personal.display().to_string()),
+                (String::from("team"), shared.display().to_string())
+            ]
+        );
+    }
+
+    #[test]
+    /// tests recording the modifications of a calendar in its audit log
+    fn test_audit_log() {
+        let dir = TempDir::new("audit");
+
+        let mut cal = create_calendar("audited", "owner", None, &dir).unwrap();
+        assert!(read_audit_log("audited", &dir).unwrap().is_empty());
+        let before = cal.clone();
+        cal.add_event(Event::new(
+            "review",
+            "",
+            "13/07/2022",
+            "09:30",
+            1.0,
+            None,
+            None,
+            None,
+        ));
+        assert!(save_audited(&before, &cal, "alice", &dir));
+        let before = cal.clone();
+        let eid = cal.events_by_eid()[0].0;
+        cal.remove_event(eid).unwrap();
+        cal.set_owner("bob");
+        assert!(save_audited(&before, &cal, "bob", &dir));
+
+        let entries = read_audit_log("audited", &dir).unwrap();
+        let actions: Vec<(&str, AuditAction)> = entries
+            .iter()
+            .map(|e| (e.user.as_str(), e.action))
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                ("alice", AuditAction::Add),
+                ("bob", AuditAction::Meta),
+                ("bob", AuditAction::Remove)
+            ]
+        );
+        assert_eq!(entries[2].eid, Some(eid));
+        assert!(delete_calendar("audited", &dir));
+        assert!(!dir.join("audited.audit").exists());
+    }
+
+    #[test]
+    /// tests saving compact files, with the events sorted by id
+    fn test_storage_format() {
+        let dir = TempDir::new("storage-format");
+        let cal_file = dir.join("format-test.json");
+
+        let mut cal = Calendar::new("owner", "format test");
+        for i in 0..20 {
+            cal.add_event(Event::new(
+                &format!("e{}", i),
+                "",
+                "01/01/2022",
+                "10:00",
+                1.0,
+                None,
+                None,
+                None,
+            ));
+        }
+        assert!(save_calendar(&cal, &dir));
+        assert!(fs::read_to_string(&cal_file).unwrap().contains('\n'));
+
+        fs::write(
+            dir.join(CONFIG_FILE),
+            r#"{"storage": {"pretty": false, "sorted": true}}"#,
+        )
+        .unwrap();
+        assert!(save_calendar(&cal, &dir));
+        let contents = fs::read_to_string(&cal_file).unwrap();
+        assert!(!contents.contains('\n'));
+        assert_eq!(read_calendar(&cal_file).unwrap(), cal);
+        // the same calendar is always saved the same way
+        let copy = read_calendar(&cal_file).unwrap();
+        assert!(save_calendar(&copy, &dir));
+        assert_eq!(fs::read_to_string(&cal_file).unwrap(), contents);
+    }
+}
@@ -0,0 +1,229 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use chrono::{Duration, NaiveDateTime};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::calendar::Calendar;
+use crate::calendar_error::CalendarError;
+use crate::cli;
+use crate::event::Event;
+use crate::http::Client;
+use crate::storage::calendar_path;
+
+/// Minutes between two refreshes of a feed, unless given when subscribing
+pub const DEFAULT_REFRESH_MINUTES: u32 = 360;
+
+/// A calendar subscribed to a remote iCalendar feed, stored next to the calendar: the
+/// events of the calendar are replaced by those of the feed on every refresh
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subscription {
+    pub url: String,
+    /// Minutes after the last fetch the feed is stale
+    pub refresh_minutes: u32,
+    #[serde(default)]
+    pub last_fetch: Option<NaiveDateTime>,
+}
+
+impl Subscription {
+    pub fn new(url: &str, refresh_minutes: u32) -> Subscription {
+        Subscription {
+            url: url.to_string(),
+            refresh_minutes,
+            last_fetch: None,
+        }
+    }
+
+    /// Returns whether the feed needs to be fetched again at `now`
+    pub fn is_stale(&self, now: NaiveDateTime) -> bool {
+        self.last_fetch
+            .is_none_or(|t| now - t >= Duration::minutes(self.refresh_minutes.into()))
+    }
+}
+
+fn subscription_path(id: &str, data_dir: &Path) -> Result<PathBuf, CalendarError> {
+    Ok(calendar_path(id, data_dir)?.with_extension("subscription"))
+}
+
+/// Reads the subscription of the calendar with the given id, if it has one
+pub fn load(id: &str, data_dir: &Path) -> Result<Option<Subscription>, CalendarError> {
+    let path = subscription_path(id, data_dir)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))?;
+    serde_json::from_str(&contents)
+        .map(Some)
+        .map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))
+}
+
+pub fn save(id: &str, sub: &Subscription, data_dir: &Path) -> Result<(), CalendarError> {
+    let path = subscription_path(id, data_dir)?;
+    let contents =
+        serde_json::to_string_pretty(sub).map_err(|e| CalendarError::Unknown(e.to_string()))?;
+    fs::write(&path, contents)
+        .map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))
+}
+
+/// Returns the eid of the local copy of an event of a feed, derived from its UID so that
+/// the copy keeps its eid across refreshes
+pub fn eid(uid: &str) -> u64 {
+    let hash = Sha256::new()
+        .chain_update("feed:")
+        .chain_update(uid)
+        .finalize();
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&hash[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+/// Replaces the events of the calendar with those of the iCalendar feed, returning their
+/// number. The events without a UID are identified by their position in the feed
+pub fn apply_feed(cal: &mut Calendar, feed: &str) -> Result<usize, CalendarError> {
+    // e.g. the error page of a server
+    if !feed.trim_start().starts_with("BEGIN:VCALENDAR") {
+        return Err(CalendarError::IcsParsingFailed(String::from(
+            "not an iCalendar feed",
+        )));
+    }
+    let unfolded = icalendar::parser::unfold(feed);
+    let parsed = icalendar::parser::read_calendar(&unfolded)
+        .map_err(|e| CalendarError::IcsParsingFailed(e.to_string()))?;
+    cal.clear();
+    let events = parsed.components.into_iter().filter(|c| c.name == "VEVENT");
+    for (i, comp) in events.enumerate() {
+        let uid = comp
+            .properties
+            .iter()
+            .find(|p| p.name == "UID")
+            .map_or_else(|| format!("#{}", i), |p| p.val.to_string());
+        let mut ev = Event::default();
+        // the attachments of the feed are not kept
+        cli::match_property(&mut ev, comp, None);
+        cal.insert_event(eid(&uid), ev);
+    }
+    Ok(cal.get_size())
+}
+
+/// Fetches the feed of the subscription at `now`, replacing the events of the calendar.
+/// Returns their number
+pub fn fetch_into(
+    cal: &mut Calendar,
+    sub: &mut Subscription,
+    now: NaiveDateTime,
+    client: &mut Client,
+) -> Result<usize, CalendarError> {
+    let count = apply_feed(cal, &client.get(&sub.url, &[])?)?;
+    sub.last_fetch = Some(now);
+    info!(
+        "Fetched {} events from {} into {}",
+        count,
+        sub.url,
+        cal.get_id()
+    );
+    Ok(count)
+}
+
+/// Fetches the feed the calendar is subscribed to at `now`, replacing its events. Returns
+/// None if the calendar is not subscribed to any feed, or if the feed is not stale and
+/// `force` is false
+pub fn refresh(
+    cal: &mut Calendar,
+    now: NaiveDateTime,
+    force: bool,
+    client: &mut Client,
+    data_dir: &Path,
+) -> Result<Option<usize>, CalendarError> {
+    let mut sub = match load(cal.get_id(), data_dir)? {
+        Some(sub) if force || sub.is_stale(now) => sub,
+        _ => return Ok(None),
+    };
+    let count = fetch_into(cal, &mut sub, now, client)?;
+    save(cal.get_id(), &sub, data_dir)?;
+    Ok(Some(count))
+}
+
+/// Refreshes the calendar with the given id in another process, if it is subscribed to a
+/// feed that is stale at `now`: the command reading the calendar does not wait for it
+pub fn refresh_in_background(id: &str, now: NaiveDateTime, data_dir: &Path) {
+    match load(id, data_dir) {
+        Ok(Some(sub)) if sub.is_stale(now) => (),
+        _ => return,
+    }
+    // the data directory is inside the working directory of the process
+    let spawned = std::env::current_exe().and_then(|exe| {
+        Command::new(exe)
+            .args(["--quiet", "--edit", id, "refresh"])
+            .current_dir(data_dir.parent().unwrap_or(data_dir))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+    });
+    match spawned {
+        Ok(child) => debug!("Refreshing {} in process {}", id, child.id()),
+        Err(e) => debug!("Cannot refresh {} in the background: {}", id, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+
+    use crate::calendar::Calendar;
+    use crate::subscription::{apply_feed, eid, Subscription};
+
+    #[test]
+    /// tests replacing the events of a calendar with those of a feed
+    fn test_apply_feed() {
+        let mut cal = Calendar::new("owner", "holidays");
+        let feed = "BEGIN:VCALENDAR\r\n\
+                    VERSION:2.0\r\n\
+                    BEGIN:VEVENT\r\n\
+                    UID:ferragosto@example.com\r\n\
+                    DTSTART:20220815T000000\r\n\
+                    DTEND:20220816T000000\r\n\
+                    SUMMARY:Ferragosto\r\n\
+                    END:VEVENT\r\n\
+                    BEGIN:VEVENT\r\n\
+                    DTSTART:20221101T000000\r\n\
+                    SUMMARY:Ognissanti\r\n\
+                    END:VEVENT\r\n\
+                    END:VCALENDAR\r\n";
+        assert_eq!(apply_feed(&mut cal, feed).unwrap(), 2);
+        let ev = cal.peek_event(eid("ferragosto@example.com")).unwrap();
+        assert_eq!(ev.get_title(), "Ferragosto");
+        assert_eq!(ev.get_duration(), 24 * 3600);
+        assert!(cal.peek_event(eid("#1")).is_some());
+
+        // the events removed from the feed are removed from the calendar
+        let (first, rest) = feed.split_once("BEGIN:VEVENT").unwrap();
+        let (_, rest) = rest.split_once("END:VEVENT\r\n").unwrap();
+        assert_eq!(
+            apply_feed(&mut cal, &format!("{}{}", first, rest)).unwrap(),
+            1
+        );
+        assert!(cal.peek_event(eid("ferragosto@example.com")).is_none());
+        assert!(apply_feed(&mut cal, "<html>Not Found</html>").is_err());
+        assert_eq!(cal.get_size(), 1);
+    }
+
+    #[test]
+    /// tests when a feed needs to be fetched again
+    fn test_is_stale() {
+        let now = NaiveDate::from_ymd_opt(2022, 7, 13)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let mut sub = Subscription::new("https://example.com/cal.ics", 60);
+        assert!(sub.is_stale(now));
+        sub.last_fetch = Some(now - Duration::minutes(30));
+        assert!(!sub.is_stale(now));
+        sub.last_fetch = Some(now - Duration::minutes(60));
+        assert!(sub.is_stale(now));
+    }
+}
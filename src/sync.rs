@@ -0,0 +1,306 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDateTime;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::calendar::Calendar;
+use crate::calendar_error::CalendarError;
+use crate::event::Event;
+use crate::prompt::{Answers, Prompt};
+use crate::storage::calendar_path;
+
+/// How the events changed both locally and remotely since the last sync are resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConflictPolicy {
+    /// Asks the user, for each conflict
+    Ask,
+    /// Keeps the local version
+    Local,
+    /// Takes the remote version
+    Remote,
+    /// Stores the conflicts, to be resolved later
+    Defer,
+}
+
+/// An event changed both locally and remotely since the last sync
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Conflict {
+    pub eid: u64,
+    /// When the conflict was detected
+    pub time: NaiveDateTime,
+    /// The event as of the last sync
+    pub base: Event,
+    pub local: Event,
+    pub remote: Event,
+}
+
+/// What to do with an event received from a remote calendar
+#[derive(Debug, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum Change {
+    /// Take the remote version
+    Apply(Event),
+    /// Only the local version changed since the last sync
+    KeepLocal,
+    Conflict(Box<Conflict>),
+}
+
+/// Returns the fields of the event as compared and merged: the timestamps of its creation
+/// and modification are left out, its tags are a field
+fn fields(ev: &Event) -> Map<String, Value> {
+    let mut fields = match serde_json::to_value(ev) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    };
+    if let Some(metadata) = fields.remove("metadata") {
+        fields.insert(String::from("tags"), metadata["tags"].clone());
+    }
+    fields
+}
+
+/// Returns the names of the fields whose values differ between the events
+pub fn differing_fields(a: &Event, b: &Event) -> Vec<String> {
+    let (a, b) = (fields(a), fields(b));
+    a.keys()
+        .filter(|f| a.get(*f) != b.get(*f))
+        .cloned()
+        .collect()
+}
+
+fn same(a: &Event, b: &Event) -> bool {
+    differing_fields(a, b).is_empty()
+}
+
+/// Decides what to do with the `remote` version of the event `eid` at `time`, given its
+/// version as of the last sync and the local one, if any
+pub fn reconcile(
+    eid: u64,
+    base: Option<&Event>,
+    local: Option<&Event>,
+    remote: Event,
+    time: NaiveDateTime,
+) -> Change {
+    match (base, local) {
+        (Some(base), Some(local)) if !same(base, local) && !same(local, &remote) => {
+            if same(base, &remote) {
+                Change::KeepLocal
+            } else {
+                Change::Conflict(Box::new(Conflict {
+                    eid,
+                    time,
+                    base: base.clone(),
+                    local: local.clone(),
+                    remote,
+                }))
+            }
+        }
+        _ => Change::Apply(remote),
+    }
+}
+
+/// Returns the local event, with the given fields taken from the remote one
+pub fn merge(local: &Event, remote: &Event, from_remote: &[String]) -> Event {
+    let remote_fields = fields(remote);
+    let mut merged = match serde_json::to_value(local) {
+        Ok(Value::Object(merged)) => merged,
+        _ => return local.clone(),
+    };
+    for field in from_remote {
+        let value = remote_fields.get(field).cloned().unwrap_or(Value::Null);
+        if field == "tags" {
+            merged["metadata"]["tags"] = value;
+        } else {
+            merged.insert(field.clone(), value);
+        }
+    }
+    serde_json::from_value(Value::Object(merged)).unwrap_or_else(|_| local.clone())
+}
+
+/// Describes how the local and remote versions of the conflict differ, a field per line
+pub fn describe(conflict: &Conflict) -> String {
+    let (local, remote) = (fields(&conflict.local), fields(&conflict.remote));
+    let mut out = String::new();
+    for field in differing_fields(&conflict.local, &conflict.remote) {
+        let show = |v: Option<&Value>| v.map_or(String::from("-"), Value::to_string);
+        let _ = writeln!(
+            out,
+            "\t{}: {} (local) / {} (remote)",
+            field,
+            show(local.get(&field)),
+            show(remote.get(&field))
+        );
+    }
+    out
+}
+
+/// Resolves the conflict with the policy, asking `prompt` if the policy is to ask. Returns the
+/// version of the event to keep, or None if the conflict is deferred
+pub fn resolve<A: Answers>(
+    conflict: &Conflict,
+    policy: ConflictPolicy,
+    prompt: &mut Prompt<A>,
+) -> Result<Option<Event>, CalendarError> {
+    match policy {
+        ConflictPolicy::Local => return Ok(Some(conflict.local.clone())),
+        ConflictPolicy::Remote => return Ok(Some(conflict.remote.clone())),
+        ConflictPolicy::Defer => return Ok(None),
+        ConflictPolicy::Ask => (),
+    }
+    println!(
+        "Event {} \"{}\" changed both locally and remotely:\n{}",
+        conflict.eid,
+        conflict.local.get_title(),
+        describe(conflict)
+    );
+    let choice = prompt.ask(
+        "Keep (l)ocal, (r)emote, (m)erge or (d)efer",
+        Some("d"),
+        &[],
+        |s| match s {
+            "l" | "local" => Ok('l'),
+            "r" | "remote" => Ok('r'),
+            "m" | "merge" => Ok('m'),
+            "d" | "defer" => Ok('d'),
+            _ => Err(format!("answer l, r, m or d, not {}", s)),
+        },
+    )?;
+    Ok(match choice {
+        'l' => Some(conflict.local.clone()),
+        'r' => Some(conflict.remote.clone()),
+        'm' => {
+            let mut from_remote = Vec::new();
+            for field in differing_fields(&conflict.local, &conflict.remote) {
+                let question = format!("Remote {}", field);
+                if prompt.confirm(&question, false)? {
+                    from_remote.push(field);
+                }
+            }
+            Some(merge(&conflict.local, &conflict.remote, &from_remote))
+        }
+        _ => None,
+    })
+}
+
+/// Replaces the event of the conflict with its resolution
+pub fn apply(cal: &mut Calendar, eid: u64, resolved: Event) -> Result<(), CalendarError> {
+    if cal.peek_event(eid).is_some() {
+        cal.update_event(eid, |ev| *ev = resolved)
+    } else {
+        cal.insert_event(eid, resolved);
+        Ok(())
+    }
+}
+
+fn conflicts_path(id: &str, data_dir: &Path) -> Result<PathBuf, CalendarError> {
+    Ok(calendar_path(id, data_dir)?.with_extension("conflicts"))
+}
+
+/// Reads the unresolved conflicts of the calendar with the given id
+pub fn load_conflicts(id: &str, data_dir: &Path) -> Result<Vec<Conflict>, CalendarError> {
+    let path = conflicts_path(id, data_dir)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))?;
+    serde_json::from_str(&contents)
+        .map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))
+}
+
+/// Stores the unresolved conflicts of the calendar with the given id
+pub fn save_conflicts(
+    id: &str,
+    conflicts: &[Conflict],
+    data_dir: &Path,
+) -> Result<(), CalendarError> {
+    let path = conflicts_path(id, data_dir)?;
+    let written = if conflicts.is_empty() {
+        fs::remove_file(&path).or_else(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(e),
+        })
+    } else {
+        let contents = serde_json::to_string_pretty(conflicts)
+            .map_err(|e| CalendarError::Unknown(e.to_string()))?;
+        fs::write(&path, contents)
+    };
+    written.map_err(|e| CalendarError::Unknown(format!("{}: {}", path.display(), e)))
+}
+
+/// Adds conflicts to the unresolved ones, replacing older conflicts on the same events
+pub fn defer(id: &str, conflicts: Vec<Conflict>, data_dir: &Path) -> Result<(), CalendarError> {
+    let mut stored = load_conflicts(id, data_dir)?;
+    stored.retain(|c| conflicts.iter().all(|new| new.eid != c.eid));
+    stored.extend(conflicts);
+    save_conflicts(id, &stored, data_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::event::Event;
+    use crate::prompt::Prompt;
+    use crate::sync::{differing_fields, merge, reconcile, resolve, Change, ConflictPolicy};
+    use crate::testing::ScriptedAnswers;
+
+    #[test]
+    /// tests detecting and resolving the events changed both locally and remotely
+    fn test_conflicts() {
+        let time = NaiveDate::from_ymd_opt(2022, 7, 13)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let base = Event::new("review", "", "13/07/2022", "09:30", 1.0, None, None, None);
+        let mut local = base.clone();
+        local.set_title("retro");
+        local.set_tags(vec![String::from("team")]);
+        let mut remote = Event::new("review", "", "13/07/2022", "10:00", 1.0, None, None, None);
+        remote.set_location("room 1");
+
+        // without local changes, the remote version is taken
+        let change = reconcile(1, Some(&base), Some(&base), remote.clone(), time);
+        assert_eq!(change, Change::Apply(remote.clone()));
+        assert!(matches!(
+            reconcile(1, None, Some(&local), remote.clone(), time),
+            Change::Apply(_)
+        ));
+        // the remote version is the same as last time
+        let unchanged = reconcile(1, Some(&base), Some(&local), base.clone(), time);
+        assert_eq!(unchanged, Change::KeepLocal);
+        let conflict = match reconcile(1, Some(&base), Some(&local), remote.clone(), time) {
+            Change::Conflict(c) => c,
+            change => panic!("{:?} is not a conflict", change),
+        };
+        assert_eq!(
+            differing_fields(&conflict.local, &conflict.remote),
+            ["location", "start_time", "tags", "title"]
+        );
+
+        let merged = merge(&local, &remote, &[String::from("start_time")]);
+        assert_eq!(merged.get_title(), "retro");
+        assert_eq!(merged.get_start(), remote.get_start());
+        assert!(merged.has_tag("team"));
+        assert_eq!(merged.get_location(), "");
+
+        let mut prompt = Prompt::new(ScriptedAnswers::new(&[]));
+        let kept = resolve(&conflict, ConflictPolicy::Local, &mut prompt).unwrap();
+        assert_eq!(kept, Some(local.clone()));
+        assert_eq!(
+            resolve(&conflict, ConflictPolicy::Defer, &mut prompt).unwrap(),
+            None
+        );
+        // merging field by field: location, start_time, tags, title
+        let mut prompt = Prompt::new(ScriptedAnswers::new(&["x", "m", "y", "", "n", "no"]));
+        let merged = resolve(&conflict, ConflictPolicy::Ask, &mut prompt)
+            .unwrap()
+            .unwrap();
+        assert_eq!(merged.get_location(), "room 1");
+        assert_eq!(merged.get_start(), base.get_start());
+        assert_eq!(merged.get_title(), "retro");
+    }
+}
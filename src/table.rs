@@ -0,0 +1,253 @@
+use std::fmt::Write;
+
+use textwrap::core::display_width;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Columns are separated by this many spaces
+const GAP: usize = 2;
+/// Flexible columns are not shrunk below this width (unless their contents are narrower)
+const MIN_WIDTH: usize = 8;
+/// Details are indented by this many spaces under their row
+const INDENT: usize = 2;
+/// Ends the style of a row
+const RESET: &str = "\x1b[0m";
+
+/// A column of a [`Table`]
+pub struct Column {
+    header: String,
+    /// Whether the column is shrunk (its cells cut) when the table is too wide
+    flexible: bool,
+}
+
+impl Column {
+    /// A column whose cells are never cut, e.g. dates and ids
+    pub fn fixed(header: &str) -> Column {
+        Column {
+            header: header.to_string(),
+            flexible: false,
+        }
+    }
+    /// A column shrunk when the table does not fit, e.g. titles
+    pub fn flexible(header: &str) -> Column {
+        Column {
+            header: header.to_string(),
+            flexible: true,
+        }
+    }
+}
+
+/// Rows of text aligned in columns, fitted to the width of the terminal: the widest flexible
+/// columns are shrunk first, cutting their cells with an ellipsis
+pub struct Table {
+    columns: Vec<Column>,
+    /// The cells of each row, the lines shown (indented) under it and the escape sequences
+    /// styling it (empty if plain)
+    rows: Vec<(Vec<String>, Vec<String>, String)>,
+}
+
+impl Table {
+    pub fn new(columns: Vec<Column>) -> Table {
+        Table {
+            columns,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Adds a row, followed by the given lines (e.g. a description), wrapped to the table
+    pub fn add_row(&mut self, cells: Vec<String>, details: Vec<String>) {
+        self.add_styled_row(cells, details, "");
+    }
+
+    /// Adds a row drawn with the given escape sequences (e.g. a color), not counted in the
+    /// widths of its cells. The lines under it are plain
+    pub fn add_styled_row(&mut self, cells: Vec<String>, details: Vec<String>, style: &str) {
+        self.rows.push((cells, details, style.to_string()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Computes the width of each column: the widest cell, unless the table is wider than
+    /// `width` (if any)
+    fn widths(&self, width: Option<usize>) -> Vec<usize> {
+        let mut widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, col)| {
+                self.rows
+                    .iter()
+                    .filter_map(|(cells, _, _)| cells.get(i))
+                    .map(|cell| display_width(cell))
+                    .chain([display_width(&col.header)])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let Some(width) = width else {
+            return widths;
+        };
+        let gaps = GAP * self.columns.len().saturating_sub(1);
+        while widths.iter().sum::<usize>() + gaps > width {
+            // shrinks the widest flexible column that can still be shrunk
+            let widest = widths
+                .iter()
+                .enumerate()
+                .filter(|(i, w)| self.columns[*i].flexible && **w > MIN_WIDTH)
+                .max_by_key(|(i, w)| (**w, usize::MAX - i))
+                .map(|(i, _)| i);
+            match widest {
+                Some(i) => widths[i] -= 1,
+                None => break,
+            }
+        }
+        widths
+    }
+
+    /// Renders the table, fitted to `width` columns if given
+    pub fn render(&self, width: Option<usize>) -> String {
+        let widths = self.widths(width);
+        let mut out = String::new();
+        let headers: Vec<&str> = self.columns.iter().map(|c| c.header.as_str()).collect();
+        render_line(&mut out, &headers, &widths, "");
+        for (cells, details, style) in self.rows.iter() {
+            let cells: Vec<&str> = cells.iter().map(|c| c.as_str()).collect();
+            render_line(&mut out, &cells, &widths, style);
+            for detail in details {
+                let detail = detail.trim_end();
+                let indent = " ".repeat(INDENT);
+                let text = match width {
+                    Some(width) => textwrap::fill(
+                        detail,
+                        textwrap::Options::new(width.saturating_sub(INDENT).max(MIN_WIDTH))
+                            .initial_indent(&indent)
+                            .subsequent_indent(&indent),
+                    ),
+                    None => textwrap::indent(detail, &indent),
+                };
+                let _ = writeln!(out, "{}", text.trim_end_matches('\n'));
+            }
+        }
+        out
+    }
+}
+
+/// Appends a line with the cells aligned to the widths of the columns, drawn with `style`
+fn render_line(out: &mut String, cells: &[&str], widths: &[usize], style: &str) {
+    let line: Vec<String> = widths
+        .iter()
+        .enumerate()
+        .map(|(i, w)| fit(cells.get(i).copied().unwrap_or(""), *w))
+        .collect();
+    let line = line.join(&" ".repeat(GAP));
+    let line = line.trim_end();
+    if style.is_empty() {
+        let _ = writeln!(out, "{}", line);
+    } else {
+        let _ = writeln!(out, "{}{}{}", style, line, RESET);
+    }
+}
+
+/// Pads the text to `width` columns, cutting it with an ellipsis if it is wider
+pub fn fit(s: &str, width: usize) -> String {
+    // the first line only: the other ones would break the table
+    let s = s.lines().next().unwrap_or("");
+    let mut used = display_width(s);
+    if used <= width {
+        return format!("{}{}", s, " ".repeat(width - used));
+    }
+    let ellipsis = if width >= 4 { "..." } else { "" };
+    let mut out = String::new();
+    used = 0;
+    for g in s.graphemes(true) {
+        let w = display_width(g);
+        if used + w + ellipsis.len() > width {
+            break;
+        }
+        out.push_str(g);
+        used += w;
+    }
+    out.push_str(ellipsis);
+    used += ellipsis.len();
+    format!("{}{}", out, " ".repeat(width.saturating_sub(used)))
+}
+
+#[cfg(test)]
+mod tests {
+    use textwrap::core::display_width;
+
+    use crate::table::{fit, Column, Table};
+
+    #[test]
+    /// tests padding and cutting cells, wide characters included
+    fn test_fit() {
+        assert_eq!(fit("abc", 5), "abc  ");
+        assert_eq!(fit("abcdefgh", 6), "abc...");
+        assert_eq!(fit("più attività", 9), "più at...");
+        // double-width characters
+        assert_eq!(fit("会議室の予約", 9), "会議室...");
+        assert_eq!(display_width(&fit("会議室の予約", 8)), 8);
+        assert_eq!(fit("first\nsecond", 10), "first     ");
+    }
+
+    #[test]
+    /// tests aligning the columns, and shrinking the flexible ones to the width
+    fn test_render() {
+        let mut table = Table::new(vec![
+            Column::fixed("Start"),
+            Column::flexible("Title"),
+            Column::flexible("Location"),
+            Column::fixed("Eid"),
+        ]);
+        table.add_row(
+            vec![
+                String::from("13/07/2022 09:30"),
+                String::from("Quarterly review of the roadmap"),
+                String::from("room 1"),
+                String::from("42"),
+            ],
+            vec![String::from("Repeats every week, 10 times")],
+        );
+        table.add_row(
+            vec![
+                String::from("14/07/2022 10:00"),
+                String::from("standup"),
+                String::from("Main building, second floor, room 12"),
+                String::from("7"),
+            ],
+            Vec::new(),
+        );
+        let out = table.render(None);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("Start             Title"));
+        assert_eq!(lines[1].find("room 1"), lines[3].find("Main building"));
+        assert_eq!(lines[2], "  Repeats every week, 10 times");
+
+        let out = table.render(Some(60));
+        assert!(out.lines().all(|l| display_width(l) <= 60), "{}", out);
+        assert!(out.contains("Quarterly revi...  room 1"), "{}", out);
+        assert!(out.contains("Main building, ..."));
+        assert!(out.contains("42"));
+        // the fixed columns are kept whole, even if too wide
+        let out = table.render(Some(20));
+        assert!(out.contains("13/07/2022 09:30"));
+
+        // the styles do not count in the widths
+        table.add_styled_row(
+            vec![String::from("15/07/2022 10:00"), String::from("retro")],
+            Vec::new(),
+            "\x1b[1m",
+        );
+        let out = table.render(Some(60));
+        let last = out.lines().last().unwrap();
+        assert!(
+            last.starts_with("\x1b[1m15/07/2022 10:00  retro"),
+            "{}",
+            out
+        );
+        assert!(last.ends_with("\x1b[0m"));
+        assert!(out.contains("Quarterly revi...  room 1"), "{}", out);
+    }
+}
@@ -0,0 +1,250 @@
+//! Proptest strategies generating random library types, for property-based tests, scripted
+//! answers to the interactive prompts and the dates the tests are set on. Available to the crate's tests and, with the `testing` feature, to other crates
+#[cfg(feature = "cli")]
+use std::collections::VecDeque;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use proptest::arbitrary::Arbitrary;
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::*;
+
+use crate::calendar::Calendar;
+#[cfg(feature = "cli")]
+use crate::calendar_error::CalendarError;
+use crate::event::{Cadence, Class, Event, Recurrence, Transparency};
+#[cfg(feature = "cli")]
+use crate::prompt::Answers;
+
+/// Returns the day of the month `m` of 2022, the year the tests are set in
+pub fn date(m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2022, m, d).unwrap()
+}
+
+/// Returns the time of the day `d` of July 2022, the month most tests are set in
+pub fn at(d: u32, h: u32, m: u32) -> NaiveDateTime {
+    date(7, d).and_hms_opt(h, m, 0).unwrap()
+}
+
+/// A directory of its own for a test, removed when dropped: the tests running in parallel,
+/// or at once in different processes, do not share their files
+#[derive(Debug)]
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "calendar-test-{}-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+pub fn arb_cadence() -> impl Strategy<Value = Cadence> {
+    prop_oneof![
+        Just(Cadence::Secondly),
+        Just(Cadence::Minutely),
+        Just(Cadence::Hourly),
+        Just(Cadence::Daily),
+        Just(Cadence::Weekly),
+        Just(Cadence::Monthly),
+        Just(Cadence::Yearly),
+    ]
+}
+
+pub fn arb_recurrence() -> impl Strategy<Value = Recurrence> {
+    (arb_cadence(), 1..1000usize, option::of(1..100usize)).prop_map(|(cad, rep, interval)| {
+        let mut rec = Recurrence::default();
+        rec.set_cadence(cad);
+        rec.set_repetitions(rep);
+        rec.set_interval(interval);
+        rec
+    })
+}
+
+pub fn arb_event() -> impl Strategy<Value = Event> {
+    (
+        ("\\PC{0,40}", "\\PC{0,200}", "\\PC{0,40}"),
+        (1970..2100i32, 1..=12u32, 1..=28u32),
+        (0..24u32, 0..60u32),
+        0..10_000_000i64,
+        option::of(arb_recurrence()),
+        vec("[a-z]{1,10}", 0..5),
+        prop_oneof![Just(Transparency::Busy), Just(Transparency::Free)],
+        prop_oneof![
+            Just(Class::Public),
+            Just(Class::Private),
+            Just(Class::Confidential)
+        ],
+    )
+        .prop_map(
+            |((title, descr, loc), (y, m, d), (hh, mm), dur, rec, tags, transp, class)| {
+                let mut ev = Event::default();
+                ev.set_title(&title);
+                ev.set_description(&descr);
+                ev.set_location(&loc);
+                ev.set_start_date((d, m, y));
+                ev.set_start_time((hh, mm, 0));
+                ev.set_duration(&Duration::seconds(dur));
+                if let Some(rec) = rec {
+                    ev.set_recurrence(&rec.syntax());
+                }
+                ev.set_tags(tags);
+                ev.set_transparency(transp);
+                ev.set_class(class);
+                ev
+            },
+        )
+}
+
+pub fn arb_calendar() -> impl Strategy<Value = Calendar> {
+    ("\\PC{0,20}", "\\PC{1,20}", vec(arb_event(), 0..20)).prop_map(|(owner, name, events)| {
+        let mut cal = Calendar::new(&owner, &name);
+        // bypasses the duplicate and overlap checks of add_event, only the contents matter here
+        for (eid, ev) in events.into_iter().enumerate() {
+            cal.insert_event(eid as u64, ev);
+        }
+        cal
+    })
+}
+
+impl Arbitrary for Recurrence {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        arb_recurrence().boxed()
+    }
+}
+
+impl Arbitrary for Event {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        arb_event().boxed()
+    }
+}
+
+impl Arbitrary for Calendar {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        arb_calendar().boxed()
+    }
+}
+
+/// Answers the questions of a prompt in order, then gives up
+#[cfg(feature = "cli")]
+pub struct ScriptedAnswers(VecDeque<String>);
+
+#[cfg(feature = "cli")]
+impl ScriptedAnswers {
+    pub fn new(answers: &[&str]) -> ScriptedAnswers {
+        ScriptedAnswers(answers.iter().map(|a| a.to_string()).collect())
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Answers for ScriptedAnswers {
+    fn read(
+        &mut self,
+        _question: &str,
+        _words: &[String],
+    ) -> Result<Option<String>, CalendarError> {
+        Ok(self.0.pop_front())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::calendar::Calendar;
+    #[cfg(feature = "cli")]
+    use crate::cli::parse_ics;
+    use crate::event::{Event, Recurrence};
+    #[cfg(feature = "cli")]
+    use crate::ics;
+
+    proptest! {
+        #[test]
+        /// recurrences are preserved by their JSON serialization
+        fn test_recurrence_json_roundtrip(rec: Recurrence) {
+            let json = serde_json::to_string(&rec).unwrap();
+            prop_assert_eq!(serde_json::from_str::<Recurrence>(&json).unwrap(), rec);
+        }
+
+        #[test]
+        /// recurrences are preserved by their textual representation
+        fn test_recurrence_text_roundtrip(rec: Recurrence) {
+            let mut ev = Event::default();
+            ev.set_recurrence(&rec.syntax());
+            prop_assert_eq!(ev.get_recurrence(), Some(&rec));
+        }
+
+        #[test]
+        /// events are preserved by their JSON serialization
+        fn test_event_json_roundtrip(ev: Event) {
+            let json = serde_json::to_string(&ev).unwrap();
+            prop_assert_eq!(serde_json::from_str::<Event>(&json).unwrap(), ev);
+        }
+
+        #[test]
+        /// calendars are preserved by their JSON serialization
+        fn test_calendar_json_roundtrip(cal: Calendar) {
+            let json = serde_json::to_string_pretty(&cal).unwrap();
+            prop_assert_eq!(serde_json::from_str::<Calendar>(&json).unwrap(), cal);
+        }
+
+        #[test]
+        #[cfg(feature = "cli")]
+        /// events are preserved by their export to ICS and import back, in the fields that
+        /// iCalendar describes
+        fn test_event_ics_roundtrip(ev: Event) {
+            let doc = ics::vcalendar(vec![ics::vevent(1, &ev, None, None, |_| None)]);
+            let imported = parse_ics(&doc, None).unwrap();
+            prop_assert_eq!(imported.len(), 1);
+            let read = &imported[0].event;
+            prop_assert_eq!(read.get_title(), ev.get_title());
+            prop_assert_eq!(read.get_description(), ev.get_description());
+            prop_assert_eq!(read.get_location(), ev.get_location());
+            prop_assert_eq!(read.get_start(), ev.get_start());
+            prop_assert_eq!(read.get_duration(), ev.get_duration());
+            prop_assert_eq!(read.get_transparency(), ev.get_transparency());
+            prop_assert_eq!(read.get_class(), ev.get_class());
+            prop_assert!(read.occurrences().take(100).eq(ev.occurrences().take(100)));
+        }
+    }
+}
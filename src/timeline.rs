@@ -0,0 +1,400 @@
+use std::fmt::Write;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::calendar::Calendar;
+use crate::event::truncate;
+
+/// Marker of the days a task takes place on
+const BAR: char = '█';
+/// Marker of the day of a milestone
+const DIAMOND: char = '◆';
+/// Titles longer than this are cut in the label column
+const MAX_LABEL: usize = 30;
+/// Length of the dates above the columns ("dd/mm")
+const DATE_LEN: usize = 5;
+/// Group of the events without tags
+const NO_GROUP: &str = "other";
+
+/// An event drawn in a timeline, with its occurrences in the range of the timeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    pub eid: u64,
+    pub title: String,
+    /// The first tag of the event, as the project it is part of
+    pub group: Option<String>,
+    pub milestone: bool,
+    /// Start and end of each occurrence, sorted
+    pub spans: Vec<(NaiveDateTime, NaiveDateTime)>,
+}
+
+impl Task {
+    fn start(&self) -> NaiveDateTime {
+        self.spans[0].0
+    }
+}
+
+/// Returns the events of the calendar (with `tag`, if any) with occurrences between `from`
+/// and `until` (both included) as tasks, grouped by their first tag, the groups sorted by
+/// their first start and the tasks of a group by their start
+pub fn tasks(cal: &Calendar, from: NaiveDate, until: NaiveDate, tag: Option<&str>) -> Vec<Task> {
+    let from_dt = from.and_time(NaiveTime::MIN);
+    let until_dt = until.and_hms_opt(23, 59, 59).unwrap();
+    let mut tasks: Vec<Task> = Vec::new();
+    for (eid, ev) in cal.events_by_eid() {
+        if tag.is_some_and(|tag| !ev.has_tag(tag)) {
+            continue;
+        }
+        let spans: Vec<(NaiveDateTime, NaiveDateTime)> = cal
+            .occurrences_until(eid, until_dt)
+            .into_iter()
+            .map(|start| (start, ev.occurrence_end(start)))
+            .filter(|(start, end)| *end > from_dt || *start >= from_dt)
+            .collect();
+        if spans.is_empty() {
+            continue;
+        }
+        tasks.push(Task {
+            eid,
+            title: ev.get_title().to_string(),
+            group: ev.get_metadata().get_tags().into_iter().next(),
+            milestone: ev.is_milestone(),
+            spans,
+        });
+    }
+    tasks.sort_by_key(|t| t.start());
+    let mut groups: Vec<Option<String>> = Vec::new();
+    for task in tasks.iter() {
+        if !groups.contains(&task.group) {
+            groups.push(task.group.clone());
+        }
+    }
+    tasks.sort_by_key(|t| groups.iter().position(|g| *g == t.group));
+    tasks
+}
+
+/// Draws the tasks as a Gantt chart, a column per day from `from` to `until`: the days of
+/// each occurrence as a bar, the milestones as diamonds, under the dates of the mondays
+pub fn render(tasks: &[Task], from: NaiveDate, until: NaiveDate) -> String {
+    let days = ((until - from).num_days() + 1).max(0) as usize;
+    let label = |t: &Task| format!("  {} [{}]", truncate(&t.title, MAX_LABEL), t.eid);
+    let width = tasks
+        .iter()
+        .map(|t| label(t).chars().count())
+        .chain(tasks.iter().map(|t| group_name(t).chars().count()))
+        .max()
+        .unwrap_or(0)
+        + 2;
+    // the dates of the mondays, and of the first day if it does not hide the first monday
+    let monday = (7 - from.weekday().num_days_from_monday() as usize) % 7;
+    let first = (monday > DATE_LEN).then_some(0);
+    let mut header = vec![' '; days];
+    for i in first.into_iter().chain((monday..days).step_by(7)) {
+        if i + DATE_LEN <= days {
+            let date = (from + Duration::days(i as i64))
+                .format("%d/%m")
+                .to_string();
+            header.splice(i..i + DATE_LEN, date.chars());
+        }
+    }
+    let mut out = format!(
+        "{:width$}{}\n",
+        "",
+        header.into_iter().collect::<String>().trim_end()
+    );
+    let mut group = None;
+    for task in tasks {
+        if group != Some(&task.group) {
+            group = Some(&task.group);
+            let _ = writeln!(out, "{}", group_name(task));
+        }
+        let mut cells = vec![' '; days];
+        for (start, end) in task.spans.iter() {
+            // an occurrence ending at midnight does not take place on the next day
+            let last = match task.milestone || end <= start {
+                true => start.date(),
+                false => (*end - Duration::nanoseconds(1)).date(),
+            };
+            let mark = if task.milestone { DIAMOND } else { BAR };
+            let mut day = start.date().max(from);
+            while day <= last.min(until) {
+                cells[(day - from).num_days() as usize] = mark;
+                day += Duration::days(1);
+            }
+        }
+        let _ = writeln!(
+            out,
+            "{:width$}{}",
+            label(task),
+            cells.into_iter().collect::<String>().trim_end()
+        );
+    }
+    out
+}
+
+fn group_name(task: &Task) -> &str {
+    task.group.as_deref().unwrap_or(NO_GROUP)
+}
+
+/// Replaces the characters that end a task name in the Gantt diagrams
+fn task_name(title: &str) -> String {
+    title
+        .chars()
+        .map(|c| match c {
+            ':' | ';' | '#' | '[' | ']' | '\n' | '\r' => ' ',
+            c => c,
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Formats a duration in minutes in the largest unit of Mermaid that fits it exactly
+fn mermaid_duration(minutes: i64) -> String {
+    match minutes {
+        m if m != 0 && m % (24 * 60) == 0 => format!("{}d", m / (24 * 60)),
+        m if m != 0 && m % 60 == 0 => format!("{}h", m / 60),
+        m => format!("{}m", m),
+    }
+}
+
+/// Writes the tasks as a Mermaid Gantt diagram titled `title`, a section per group: each
+/// occurrence is a task (a milestone for the milestones), critical if the event is important
+pub fn mermaid_gantt(tasks: &[Task], title: &str, important: impl Fn(u64) -> bool) -> String {
+    let mut out = String::from("gantt\n");
+    let _ = writeln!(out, "    title {}", task_name(title));
+    out.push_str("    dateFormat YYYY-MM-DD HH:mm\n    axisFormat %d/%m\n");
+    let mut group = None;
+    for task in tasks {
+        if group != Some(&task.group) {
+            group = Some(&task.group);
+            let _ = writeln!(out, "    section {}", task_name(group_name(task)));
+        }
+        for (i, (start, end)) in task.spans.iter().enumerate() {
+            let mut tags = Vec::new();
+            if important(task.eid) {
+                tags.push(String::from("crit"));
+            }
+            if task.milestone {
+                tags.push(String::from("milestone"));
+            }
+            tags.push(match i {
+                0 => format!("e{}", task.eid),
+                i => format!("e{}_{}", task.eid, i + 1),
+            });
+            let _ = writeln!(
+                out,
+                "    {} :{}, {}, {}",
+                task_name(&task.title),
+                tags.join(", "),
+                start.format("%Y-%m-%d %H:%M"),
+                mermaid_duration((*end - *start).num_minutes())
+            );
+        }
+    }
+    out
+}
+
+/// Writes the tasks as a PlantUML Gantt diagram titled `title`, starting on `from`, with a
+/// separator per group: each occurrence is a task over its days (a milestone for the
+/// milestones), named after the event and numbered if it repeats
+pub fn plantuml_gantt(tasks: &[Task], title: &str, from: NaiveDate) -> String {
+    let mut out = String::from("@startgantt\n");
+    let _ = writeln!(out, "title {}", task_name(title));
+    let _ = writeln!(out, "Project starts {}", from.format("%Y-%m-%d"));
+    let mut names: Vec<String> = Vec::new();
+    let mut group = None;
+    for task in tasks {
+        if group != Some(&task.group) {
+            group = Some(&task.group);
+            let _ = writeln!(out, "-- {} --", task_name(group_name(task)));
+        }
+        for (start, end) in task.spans.iter() {
+            // the names identify the tasks, so they have to be unique
+            let base = task_name(&task.title);
+            let name = (1..)
+                .map(|n| match n {
+                    1 => base.clone(),
+                    n => format!("{} ({})", base, n),
+                })
+                .find(|name| !names.contains(name))
+                .unwrap();
+            names.push(name.clone());
+            let last = match task.milestone || end <= start {
+                true => start.date(),
+                false => (*end - Duration::nanoseconds(1)).date(),
+            };
+            match task.milestone {
+                true => {
+                    let _ = writeln!(out, "[{}] happens {}", name, start.format("%Y-%m-%d"));
+                }
+                false => {
+                    let _ = writeln!(
+                        out,
+                        "[{}] starts {} and ends {}",
+                        name,
+                        start.format("%Y-%m-%d"),
+                        last.format("%Y-%m-%d")
+                    );
+                }
+            }
+        }
+    }
+    out.push_str("@endgantt\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::calendar::Calendar;
+    use crate::event::Event;
+    use crate::timeline::{mermaid_gantt, plantuml_gantt, render, tasks};
+
+    #[test]
+    /// tests drawing the events of a project over some days, with a milestone
+    fn test_timeline() {
+        let tags = |t: &str| Some(vec![t.to_string()]);
+        let mut cal = Calendar::new("owner", "test");
+        let design = Event::new(
+            "design",
+            "",
+            "04/07/2022",
+            "09:00",
+            48.0,
+            None,
+            None,
+            tags("app"),
+        );
+        let standup = Event::new(
+            "standup",
+            "",
+            "06/07/2022",
+            "09:00",
+            1.0,
+            None,
+            Some("weekly 3"),
+            tags("app"),
+        );
+        let mut release = Event::new(
+            "release",
+            "",
+            "15/07/2022",
+            "17:00",
+            0.0,
+            None,
+            None,
+            tags("app"),
+        );
+        release.set_milestone(true);
+        let dentist = Event::new("dentist", "", "01/07/2022", "10:00", 1.0, None, None, None);
+        let late = Event::new("late", "", "01/08/2022", "10:00", 1.0, None, None, None);
+        for (eid, ev) in [
+            (1, design),
+            (2, standup),
+            (3, release),
+            (4, dentist),
+            (5, late),
+        ] {
+            cal.insert_event(eid, ev);
+        }
+        let from = NaiveDate::from_ymd_opt(2022, 7, 1).unwrap();
+        let until = NaiveDate::from_ymd_opt(2022, 7, 17).unwrap();
+        let all = tasks(&cal, from, until, None);
+        // the groups by their first start, the tasks by their start
+        assert_eq!(
+            all.iter().map(|t| t.eid).collect::<Vec<u64>>(),
+            vec![4, 1, 2, 3]
+        );
+        assert_eq!(all[2].spans.len(), 2);
+        let app = tasks(&cal, from, until, Some("app"));
+        assert_eq!(app.len(), 3);
+        let out = render(&all, from, until);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "                  04/07  11/07",
+                "other",
+                "  dentist [4]  █",
+                "app",
+                "  design [1]      ███",
+                "  standup [2]       █      █",
+                "  release [3]                ◆",
+            ]
+        );
+    }
+
+    #[test]
+    /// tests writing the events of the projects as Mermaid and PlantUML Gantt diagrams
+    fn test_gantt() {
+        let mut cal = Calendar::new("owner", "test");
+        let mut spec = Event::new(
+            "spec: draft",
+            "",
+            "04/07/2022",
+            "09:00",
+            48.0,
+            None,
+            None,
+            Some(vec![String::from("app")]),
+        );
+        spec.set_important(true);
+        let review = Event::new(
+            "review",
+            "",
+            "06/07/2022",
+            "14:00",
+            1.0,
+            None,
+            Some("weekly 2"),
+            Some(vec![String::from("app")]),
+        );
+        let mut release = Event::new("release", "", "15/07/2022", "17:00", 0.0, None, None, None);
+        release.set_milestone(true);
+        for (eid, ev) in [(1, spec), (2, review), (3, release)] {
+            cal.insert_event(eid, ev);
+        }
+        let from = NaiveDate::from_ymd_opt(2022, 7, 1).unwrap();
+        let until = NaiveDate::from_ymd_opt(2022, 7, 31).unwrap();
+        let all = tasks(&cal, from, until, None);
+        let important = |eid| cal.peek_event(eid).is_some_and(|ev| ev.is_important());
+        assert_eq!(
+            mermaid_gantt(&all, "Team: Q3", important),
+            [
+                "gantt",
+                "    title Team  Q3",
+                "    dateFormat YYYY-MM-DD HH:mm",
+                "    axisFormat %d/%m",
+                "    section app",
+                "    spec  draft :crit, e1, 2022-07-04 09:00, 2d",
+                "    review :e2, 2022-07-06 14:00, 1h",
+                "    review :e2_2, 2022-07-13 14:00, 1h",
+                "    review :e2_3, 2022-07-20 14:00, 1h",
+                "    section other",
+                "    release :milestone, e3, 2022-07-15 17:00, 0m",
+                "",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            plantuml_gantt(&all, "Team: Q3", from),
+            [
+                "@startgantt",
+                "title Team  Q3",
+                "Project starts 2022-07-01",
+                "-- app --",
+                "[spec  draft] starts 2022-07-04 and ends 2022-07-06",
+                "[review] starts 2022-07-06 and ends 2022-07-06",
+                "[review (2)] starts 2022-07-13 and ends 2022-07-13",
+                "[review (3)] starts 2022-07-20 and ends 2022-07-20",
+                "-- other --",
+                "[release] happens 2022-07-15",
+                "@endgantt",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::calendar_error::CalendarError;
+
+/// Name of the file listing the users of the daemon, inside the data directory
+pub const USERS_FILE: &str = "users.json";
+/// Grants access to all the calendars, in place of a calendar id
+pub const ALL_CALENDARS: &str = "*";
+
+/// What a user can do with a calendar: writing implies reading
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// A user of the daemon, authenticated by a token: only a salted hash of the token is stored
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct User {
+    pub name: String,
+    salt: String,
+    token_hash: String,
+    /// Access to the calendars, by id (or [`ALL_CALENDARS`])
+    #[serde(default)]
+    pub calendars: BTreeMap<String, Access>,
+}
+
+impl User {
+    /// Returns the access of the user to the calendar with the given id, if any
+    pub fn access(&self, calendar: &str) -> Option<Access> {
+        let all = self.calendars.get(ALL_CALENDARS).copied();
+        self.calendars.get(calendar).copied().max(all)
+    }
+}
+
+/// The users of the daemon. If there are none, the daemon is open to anyone for reading
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Users {
+    users: Vec<User>,
+}
+
+/// Encodes bytes as lowercase hexadecimal
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{:02x}", b);
+        s
+    })
+}
+
+/// Returns a new random token (or salt) as hexadecimal, with `len` bytes of entropy
+fn random_hex(len: usize) -> String {
+    hex(&(0..len).map(|_| rand::random::<u8>()).collect::<Vec<u8>>())
+}
+
+fn hash_token(salt: &str, token: &str) -> String {
+    hex(&Sha256::new()
+        .chain_update(salt)
+        .chain_update(token)
+        .finalize())
+}
+
+/// Compares two strings in a time independent of where they differ
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+impl Users {
+    /// Reads the users in the data directory: there are none if the file does not exist
+    pub fn load(data_dir: &Path) -> Result<Users, CalendarError> {
+        let path = data_dir.join(USERS_FILE);
+        if !path.exists() {
+            return Ok(Users::default());
+        }
+        let contents = fs::read_to_string(&path)?;
+        serde_json::from_str(&contents)
+            .map_err(|e| CalendarError::InvalidConfig(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<(), CalendarError> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| CalendarError::Unknown(e.to_string()))?;
+        fs::write(data_dir.join(USERS_FILE), contents)
+            .map_err(|e| CalendarError::Unknown(format!("Cannot write {}: {}", USERS_FILE, e)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    pub fn list(&self) -> &[User] {
+        &self.users
+    }
+
+    fn get_mut(&mut self, name: &str) -> Result<&mut User, CalendarError> {
+        self.users
+            .iter_mut()
+            .find(|u| u.name == name)
+            .ok_or_else(|| CalendarError::Unknown(format!("No user {}", name)))
+    }
+
+    /// Adds a user with no access to any calendar, returning its token: it cannot be
+    /// recovered later, since only its hash is stored
+    pub fn add(&mut self, name: &str) -> Result<String, CalendarError> {
+        if self.users.iter().any(|u| u.name == name) {
+            return Err(CalendarError::Unknown(format!(
+                "User {} already exists",
+                name
+            )));
+        }
+        let token = random_hex(32);
+        let salt = random_hex(16);
+        self.users.push(User {
+            name: name.to_string(),
+            token_hash: hash_token(&salt, &token),
+            salt,
+            calendars: BTreeMap::new(),
+        });
+        Ok(token)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), CalendarError> {
+        let before = self.users.len();
+        self.users.retain(|u| u.name != name);
+        if self.users.len() == before {
+            return Err(CalendarError::Unknown(format!("No user {}", name)));
+        }
+        Ok(())
+    }
+
+    /// Gives the user the access to the calendar (or to [`ALL_CALENDARS`]), replacing the
+    /// previous one
+    pub fn grant(
+        &mut self,
+        name: &str,
+        calendar: &str,
+        access: Access,
+    ) -> Result<(), CalendarError> {
+        self.get_mut(name)?
+            .calendars
+            .insert(calendar.to_string(), access);
+        Ok(())
+    }
+
+    pub fn revoke(&mut self, name: &str, calendar: &str) -> Result<(), CalendarError> {
+        self.get_mut(name)?.calendars.remove(calendar);
+        Ok(())
+    }
+
+    /// Returns the user the token belongs to, if any
+    pub fn authenticate(&self, token: &str) -> Option<&User> {
+        self.users
+            .iter()
+            .find(|u| constant_time_eq(&hash_token(&u.salt, token), &u.token_hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::testing::TempDir;
+    use crate::users::{Access, Users, ALL_CALENDARS};
+
+    #[test]
+    /// tests authenticating users and checking their access to the calendars
+    fn test_users() {
+        let mut users = Users::default();
+        let alice = users.add("alice").unwrap();
+        let bob = users.add("bob").unwrap();
+        assert!(users.add("bob").is_err());
+        assert_ne!(alice, bob);
+        users.grant("alice", "work", Access::Write).unwrap();
+        users.grant("bob", ALL_CALENDARS, Access::Read).unwrap();
+        users.grant("bob", "home", Access::Write).unwrap();
+        assert!(users.grant("carol", "work", Access::Read).is_err());
+
+        let user = users.authenticate(&alice).unwrap();
+        assert_eq!(user.name, "alice");
+        assert_eq!(user.access("work"), Some(Access::Write));
+        assert_eq!(user.access("home"), None);
+        let user = users.authenticate(&bob).unwrap();
+        assert_eq!(user.access("work"), Some(Access::Read));
+        assert_eq!(user.access("home"), Some(Access::Write));
+        assert!(users.authenticate("").is_none());
+        assert!(users.authenticate(&alice[1..]).is_none());
+
+        // the tokens are not stored
+        let dir = TempDir::new("users");
+        users.save(&dir).unwrap();
+        let loaded = Users::load(&dir).unwrap();
+        assert_eq!(loaded, users);
+        assert!(!fs::read_to_string(dir.join("users.json"))
+            .unwrap()
+            .contains(&alice));
+
+        users.revoke("alice", "work").unwrap();
+        assert_eq!(users.authenticate(&alice).unwrap().access("work"), None);
+        users.remove("alice").unwrap();
+        assert!(users.authenticate(&alice).is_none());
+        assert!(users.remove("alice").is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+use crate::calendar::Calendar;
+use crate::event::{Event, Transparency, WorkPlace};
+
+/// Parses a day from today on: today, tomorrow, the name of a weekday (the next one, today
+/// included) or a date as %d/%m/%Y or %Y-%m-%d
+pub fn parse_day(s: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let s = s.trim().to_lowercase();
+    match s.as_str() {
+        "today" => Some(today),
+        "tomorrow" => Some(today + Duration::days(1)),
+        day => match day.parse::<Weekday>() {
+            Ok(weekday) => {
+                let ahead = weekday.num_days_from_monday() as i64
+                    - today.weekday().num_days_from_monday() as i64;
+                Some(today + Duration::days(ahead.rem_euclid(7)))
+            }
+            Err(_) => ["%d/%m/%Y", "%Y-%m-%d"]
+                .iter()
+                .find_map(|fmt| NaiveDate::parse_from_str(day, fmt).ok()),
+        },
+    }
+}
+
+/// Parses the days from `from` to `to` (see [`parse_day`]), just `from` without `to`: a
+/// weekday as `to` is the next one from `from`, e.g. from fri to mon
+pub fn parse_range(
+    from: &str,
+    to: Option<&str>,
+    today: NaiveDate,
+) -> Result<(NaiveDate, NaiveDate), String> {
+    let invalid = |s: &str| format!("Invalid day {}", s);
+    let first = parse_day(from, today).ok_or_else(|| invalid(from))?;
+    let last = match to {
+        Some(to) if to.trim().parse::<Weekday>().is_ok() => parse_day(to, first),
+        Some(to) => parse_day(to, today),
+        None => Some(first),
+    }
+    .ok_or_else(|| invalid(to.unwrap_or_default()))?;
+    if last < first {
+        return Err(format!(
+            "The last day {} comes before the first one {}",
+            last.format("%d/%m/%Y"),
+            first.format("%d/%m/%Y")
+        ));
+    }
+    Ok((first, last))
+}
+
+/// Returns the all-day event recording where the owner works on `date`: it is free, so that
+/// it never conflicts with the events of the day
+pub fn event(place: &WorkPlace, date: NaiveDate) -> Event {
+    let mut ev = Event::new(
+        &format!("Working from {}", place),
+        "",
+        &date.format("%d/%m/%Y").to_string(),
+        "00:00",
+        24.0,
+        None,
+        None,
+        None,
+    );
+    ev.set_transparency(Transparency::Free);
+    ev.set_working_location(Some(place.clone()));
+    ev
+}
+
+/// Sets where the owner works on each day from `first` to `last` (included), replacing the
+/// working locations already set on those days. Returns the eids of the events added
+pub fn set(cal: &mut Calendar, place: &WorkPlace, first: NaiveDate, last: NaiveDate) -> Vec<u64> {
+    let replaced: Vec<u64> = cal
+        .events_by_eid()
+        .into_iter()
+        .filter(|(_, ev)| {
+            ev.get_working_location().is_some() && (first..=last).contains(&ev.get_start_date())
+        })
+        .map(|(eid, _)| eid)
+        .collect();
+    for eid in replaced {
+        let _ = cal.remove_event(eid);
+    }
+    first
+        .iter_days()
+        .take_while(|d| *d <= last)
+        .filter_map(|d| cal.add_event(event(place, d)).added)
+        .collect()
+}
+
+/// Returns where the owner works on each day from `first` to `last` it was set for
+pub fn by_day(cal: &Calendar, first: NaiveDate, last: NaiveDate) -> BTreeMap<NaiveDate, WorkPlace> {
+    cal.events_by_eid()
+        .into_iter()
+        .filter(|(_, ev)| (first..=last).contains(&ev.get_start_date()))
+        .filter_map(|(_, ev)| Some((ev.get_start_date(), ev.get_working_location()?.clone())))
+        .collect()
+}
+
+/// Renders the working locations of the days from `first` to `last` as a banner, a line per
+/// day, e.g. "Mon 11/07: office"
+pub fn banner(cal: &Calendar, first: NaiveDate, last: NaiveDate) -> String {
+    by_day(cal, first, last)
+        .into_iter()
+        .map(|(date, place)| format!("{}: {}\n", date.format("%a %d/%m"), place))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::calendar::Calendar;
+    use crate::event::{Event, Transparency, WorkPlace};
+    use crate::working_location::{banner, by_day, parse_day, parse_range, set};
+
+    #[test]
+    /// tests setting where the owner works over a range of days
+    fn test_working_location() {
+        let day = |d| NaiveDate::from_ymd_opt(2022, 7, d).unwrap();
+        // monday
+        let today = day(11);
+        assert_eq!(parse_day("mon", today), Some(day(11)));
+        assert_eq!(parse_day("Wednesday", today), Some(day(13)));
+        assert_eq!(parse_day("sun", day(13)), Some(day(17)));
+        assert_eq!(parse_day("tomorrow", today), Some(day(12)));
+        assert_eq!(parse_day("2022-07-20", today), Some(day(20)));
+        assert_eq!(parse_day("someday", today), None);
+        assert_eq!(
+            parse_range("fri", Some("mon"), today),
+            Ok((day(15), day(18)))
+        );
+        assert_eq!(parse_range("wed", None, today), Ok((day(13), day(13))));
+        assert!(parse_range("wed", Some("today"), today).is_err());
+        assert!(parse_range("today", Some("someday"), today).is_err());
+        assert_eq!("Office".parse(), Ok(WorkPlace::Office));
+        assert_eq!(
+            "client site".parse(),
+            Ok(WorkPlace::Other(String::from("client site")))
+        );
+        assert!("  ".parse::<WorkPlace>().is_err());
+
+        let mut cal = Calendar::new("owner", "work");
+        let standup = Event::new("standup", "", "12/07/2022", "09:00", 1.0, None, None, None);
+        let standup = cal.add_event(standup).added.unwrap();
+        let added = set(&mut cal, &WorkPlace::Office, day(11), day(13));
+        assert_eq!(added.len(), 3);
+        let ev = cal.peek_event(added[1]).unwrap();
+        assert_eq!(ev.get_title(), "Working from office");
+        assert_eq!(ev.get_transparency(), Transparency::Free);
+        assert!(!ev.overlaps(cal.peek_event(standup).unwrap()));
+
+        // wednesday is replaced
+        set(&mut cal, &WorkPlace::Home, day(13), day(14));
+        assert_eq!(cal.get_size(), 5);
+        let week = by_day(&cal, day(11), day(17));
+        assert_eq!(week.len(), 4);
+        assert_eq!(week[&day(13)], WorkPlace::Home);
+        assert_eq!(
+            banner(&cal, day(12), day(13)),
+            "Tue 12/07: office\nWed 13/07: home\n"
+        );
+    }
+}
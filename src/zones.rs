@@ -0,0 +1,222 @@
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::event::Geo;
+
+/// Positions farther than this from every place in [`PLACES`] (in km) are in no known zone,
+/// e.g. in the middle of the oceans
+const MAX_DISTANCE: f64 = 1200.0;
+/// Mean radius of the Earth, in km
+const EARTH_RADIUS: f64 = 6371.0;
+
+/// Places of the time zones (latitude, longitude and zone), several for the largest ones:
+/// a position is in the zone of the nearest place
+const PLACES: [(f64, f64, &str); 121] = [
+    // Europe
+    (51.51, -0.13, "Europe/London"),
+    (55.95, -3.19, "Europe/London"),
+    (53.35, -6.26, "Europe/Dublin"),
+    (38.72, -9.14, "Europe/Lisbon"),
+    (40.42, -3.70, "Europe/Madrid"),
+    (41.39, 2.17, "Europe/Madrid"),
+    (48.86, 2.35, "Europe/Paris"),
+    (43.30, 5.37, "Europe/Paris"),
+    (50.85, 4.35, "Europe/Brussels"),
+    (52.37, 4.90, "Europe/Amsterdam"),
+    (52.52, 13.40, "Europe/Berlin"),
+    (48.14, 11.58, "Europe/Berlin"),
+    (47.38, 8.54, "Europe/Zurich"),
+    (48.21, 16.37, "Europe/Vienna"),
+    (41.90, 12.50, "Europe/Rome"),
+    (45.46, 9.19, "Europe/Rome"),
+    (38.12, 13.36, "Europe/Rome"),
+    (55.68, 12.57, "Europe/Copenhagen"),
+    (59.91, 10.75, "Europe/Oslo"),
+    (69.65, 18.96, "Europe/Oslo"),
+    (59.33, 18.07, "Europe/Stockholm"),
+    (60.17, 24.94, "Europe/Helsinki"),
+    (59.44, 24.75, "Europe/Tallinn"),
+    (56.95, 24.11, "Europe/Riga"),
+    (54.69, 25.28, "Europe/Vilnius"),
+    (52.23, 21.01, "Europe/Warsaw"),
+    (50.08, 14.44, "Europe/Prague"),
+    (47.50, 19.04, "Europe/Budapest"),
+    (44.43, 26.10, "Europe/Bucharest"),
+    (42.70, 23.32, "Europe/Sofia"),
+    (44.79, 20.45, "Europe/Belgrade"),
+    (45.81, 15.98, "Europe/Zagreb"),
+    (37.98, 23.73, "Europe/Athens"),
+    (41.01, 28.98, "Europe/Istanbul"),
+    (39.93, 32.86, "Europe/Istanbul"),
+    (50.45, 30.52, "Europe/Kyiv"),
+    (53.90, 27.57, "Europe/Minsk"),
+    (55.76, 37.62, "Europe/Moscow"),
+    (59.94, 30.31, "Europe/Moscow"),
+    (64.14, -21.94, "Atlantic/Reykjavik"),
+    // Asia
+    (56.84, 60.61, "Asia/Yekaterinburg"),
+    (54.99, 73.37, "Asia/Omsk"),
+    (55.03, 82.92, "Asia/Novosibirsk"),
+    (56.01, 92.87, "Asia/Krasnoyarsk"),
+    (52.29, 104.28, "Asia/Irkutsk"),
+    (62.03, 129.73, "Asia/Yakutsk"),
+    (43.12, 131.89, "Asia/Vladivostok"),
+    (59.56, 150.80, "Asia/Magadan"),
+    (53.02, 158.65, "Asia/Kamchatka"),
+    (32.09, 34.78, "Asia/Jerusalem"),
+    (33.89, 35.50, "Asia/Beirut"),
+    (31.95, 35.93, "Asia/Amman"),
+    (33.51, 36.29, "Asia/Damascus"),
+    (33.31, 44.37, "Asia/Baghdad"),
+    (24.71, 46.68, "Asia/Riyadh"),
+    (21.49, 39.19, "Asia/Riyadh"),
+    (25.29, 51.53, "Asia/Qatar"),
+    (25.20, 55.27, "Asia/Dubai"),
+    (35.69, 51.39, "Asia/Tehran"),
+    (34.53, 69.17, "Asia/Kabul"),
+    (41.30, 69.24, "Asia/Tashkent"),
+    (43.24, 76.89, "Asia/Almaty"),
+    (24.86, 67.01, "Asia/Karachi"),
+    (28.61, 77.21, "Asia/Kolkata"),
+    (19.08, 72.88, "Asia/Kolkata"),
+    (12.97, 77.59, "Asia/Kolkata"),
+    (22.57, 88.36, "Asia/Kolkata"),
+    (27.72, 85.32, "Asia/Kathmandu"),
+    (23.81, 90.41, "Asia/Dhaka"),
+    (16.87, 96.20, "Asia/Yangon"),
+    (13.76, 100.50, "Asia/Bangkok"),
+    (21.03, 105.85, "Asia/Ho_Chi_Minh"),
+    (10.82, 106.63, "Asia/Ho_Chi_Minh"),
+    (3.139, 101.687, "Asia/Kuala_Lumpur"),
+    (1.35, 103.82, "Asia/Singapore"),
+    (-6.21, 106.85, "Asia/Jakarta"),
+    (-8.65, 115.22, "Asia/Makassar"),
+    (14.60, 120.98, "Asia/Manila"),
+    (39.90, 116.41, "Asia/Shanghai"),
+    (31.23, 121.47, "Asia/Shanghai"),
+    (30.57, 104.07, "Asia/Shanghai"),
+    (43.83, 87.62, "Asia/Urumqi"),
+    (22.32, 114.17, "Asia/Hong_Kong"),
+    (25.03, 121.57, "Asia/Taipei"),
+    (37.57, 126.98, "Asia/Seoul"),
+    (35.68, 139.69, "Asia/Tokyo"),
+    (43.06, 141.35, "Asia/Tokyo"),
+    (47.89, 106.91, "Asia/Ulaanbaatar"),
+    // Africa
+    (30.04, 31.24, "Africa/Cairo"),
+    (33.57, -7.59, "Africa/Casablanca"),
+    (36.75, 3.06, "Africa/Algiers"),
+    (36.81, 10.18, "Africa/Tunis"),
+    (6.52, 3.38, "Africa/Lagos"),
+    (5.60, -0.19, "Africa/Accra"),
+    (14.72, -17.47, "Africa/Dakar"),
+    (-1.29, 36.82, "Africa/Nairobi"),
+    (9.03, 38.74, "Africa/Addis_Ababa"),
+    (-4.32, 15.31, "Africa/Kinshasa"),
+    (-26.20, 28.05, "Africa/Johannesburg"),
+    (-33.92, 18.42, "Africa/Johannesburg"),
+    // Americas
+    (40.71, -74.01, "America/New_York"),
+    (42.36, -71.06, "America/New_York"),
+    (33.75, -84.39, "America/New_York"),
+    (25.76, -80.19, "America/New_York"),
+    (43.65, -79.38, "America/Toronto"),
+    (45.50, -73.57, "America/Toronto"),
+    (41.88, -87.63, "America/Chicago"),
+    (29.76, -95.37, "America/Chicago"),
+    (32.78, -96.80, "America/Chicago"),
+    (44.98, -93.27, "America/Chicago"),
+    (39.74, -104.99, "America/Denver"),
+    (33.45, -112.07, "America/Phoenix"),
+    (34.05, -118.24, "America/Los_Angeles"),
+    (37.77, -122.42, "America/Los_Angeles"),
+    (47.61, -122.33, "America/Los_Angeles"),
+    (49.28, -123.12, "America/Vancouver"),
+    (61.22, -149.90, "America/Anchorage"),
+    (19.43, -99.13, "America/Mexico_City"),
+    (-23.55, -46.63, "America/Sao_Paulo"),
+    (-34.60, -58.38, "America/Argentina/Buenos_Aires"),
+    // Oceania
+    (-33.87, 151.21, "Australia/Sydney"),
+];
+
+/// Returns the distance between two positions on the Earth, in km
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (a.0.to_radians(), b.0.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (b.1 - a.1).to_radians();
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
+
+/// Infers the time zone at the position: the one of the nearest known place, if any is close
+/// enough. Near the borders between zones the inferred one may be wrong
+pub fn time_zone_at(geo: Geo) -> Option<Tz> {
+    PLACES
+        .iter()
+        .map(|(lat, lon, zone)| (distance((geo.lat(), geo.lon()), (*lat, *lon)), zone))
+        .filter(|(d, _)| *d <= MAX_DISTANCE)
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .and_then(|(_, zone)| zone.parse().ok())
+}
+
+fn to_utc(dt: NaiveDateTime, tz: Option<Tz>) -> Option<DateTime<Utc>> {
+    match tz {
+        Some(tz) => tz.from_local_datetime(&dt).earliest().map(|t| t.to_utc()),
+        None => Local
+            .from_local_datetime(&dt)
+            .earliest()
+            .map(|t| t.to_utc()),
+    }
+}
+
+/// Converts a time in the zone `from` to the time of the same instant in the zone `to`:
+/// without a zone, the one of the system. Returns None if the time does not exist in `from`
+/// (skipped by a change of its offset)
+pub fn convert(dt: NaiveDateTime, from: Option<Tz>, to: Option<Tz>) -> Option<NaiveDateTime> {
+    let utc = to_utc(dt, from)?;
+    Some(match to {
+        Some(tz) => utc.with_timezone(&tz).naive_local(),
+        None => utc.with_timezone(&Local).naive_local(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use chrono_tz::Tz;
+
+    use crate::event::Geo;
+    use crate::zones::{convert, distance, time_zone_at, PLACES};
+
+    #[test]
+    /// tests inferring the time zone of positions, and converting times between zones
+    fn test_zones() {
+        for (_, _, zone) in PLACES {
+            assert!(zone.parse::<Tz>().is_ok(), "unknown zone {}", zone);
+        }
+        assert!((distance((41.9, 12.5), (45.46, 9.19)) - 477.0).abs() < 5.0);
+        let zone = |lat, lon| time_zone_at(Geo::new(lat, lon).unwrap());
+        // JFK, Brooklyn
+        assert_eq!(zone(40.64, -73.78), Some(Tz::America__New_York));
+        assert_eq!(zone(43.77, 11.25), Some(Tz::Europe__Rome));
+        assert_eq!(zone(34.69, 135.50), Some(Tz::Asia__Tokyo));
+        assert_eq!(zone(-37.81, 144.96), Some(Tz::Australia__Sydney));
+        // the middle of the Pacific
+        assert_eq!(zone(-30.0, -130.0), None);
+
+        // landing in New York at 17:30 is 23:30 in Rome
+        let landing = NaiveDate::from_ymd_opt(2026, 10, 20)
+            .unwrap()
+            .and_hms_opt(17, 30, 0)
+            .unwrap();
+        let rome = convert(landing, Some(Tz::America__New_York), Some(Tz::Europe__Rome));
+        assert_eq!(rome, landing.checked_add_signed(chrono::Duration::hours(6)));
+        // 02:30 is skipped by the start of the DST in Rome
+        let skipped = NaiveDate::from_ymd_opt(2026, 3, 29)
+            .unwrap()
+            .and_hms_opt(2, 30, 0)
+            .unwrap();
+        assert_eq!(convert(skipped, Some(Tz::Europe__Rome), None), None);
+    }
+}
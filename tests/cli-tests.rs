@@ -1,36 +1,440 @@
+mod harness;
+
 use assert_cmd::prelude::*; // Add methods on commands
 use predicates::prelude::*; // Used for writing assertions
-use std::process::Command; // Run programs
 
-/*
+use calendar_lib::event::Event;
+use harness::{event, summary, DataDir};
+
+#[test]
+/// tests creating a calendar, and that it is listed
+fn create_calendar() {
+    let dir = DataDir::new("create");
+    dir.cmd().args(["-c", "Work Stuff"]).assert().success();
+    assert!(dir.path().join("work-stuff.json").exists());
+    assert_eq!(dir.calendar("Work Stuff").get_id(), "work-stuff");
+    dir.cmd()
+        .arg("-l")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Work Stuff [work-stuff]"));
+}
+
+#[test]
+/// tests adding an event from the positional arguments
+fn add_event() {
+    let dir = DataDir::new("add");
+    dir.seed("test", vec![]);
+    dir.cmd()
+        .args(["-e", "test", "add", "some title", "some description"])
+        .args(["11/03/2022", "11:17", "2"])
+        .assert()
+        .success();
+    assert_eq!(
+        summary(&dir.calendar("test")),
+        vec![(
+            String::from("some title"),
+            String::from("11/03/2022 11:17"),
+            120
+        )]
+    );
+}
+
+#[test]
+/// tests that the commands chained with ';' are all applied, or none of them
+fn add_chained() {
+    let dir = DataDir::new("chained");
+    dir.seed("test", vec![]);
+    dir.cmd()
+        .args(["-e", "test"])
+        .args(["add", "a", "", "11/03/2022", "09:00", "1", ";"])
+        .args(["add", "b", "", "12/03/2022", "09:00", "1"])
+        .assert()
+        .success();
+    assert_eq!(dir.calendar("test").get_size(), 2);
+    // the second event cannot be removed: the first one is not added either
+    dir.cmd()
+        .args(["-e", "test"])
+        .args(["add", "c", "", "13/03/2022", "09:00", "1", ";"])
+        .args(["remove", "1"])
+        .assert()
+        .stderr(predicate::str::contains("calendar not modified"));
+    assert_eq!(dir.calendar("test").get_size(), 2);
+}
+
+#[test]
+/// tests removing an event by eid, and all of them
+fn remove_event() {
+    let dir = DataDir::new("remove");
+    let eids = dir.seed(
+        "test",
+        vec![
+            event("a", "11/03/2022", "09:00", 1.0),
+            event("b", "12/03/2022", "09:00", 1.0),
+            event("c", "13/03/2022", "09:00", 1.0),
+        ],
+    );
+    dir.cmd()
+        .args(["-e", "test", "remove", &eids[1].to_string()])
+        .assert()
+        .success();
+    let titles: Vec<String> = summary(&dir.calendar("test"))
+        .into_iter()
+        .map(|(title, _, _)| title)
+        .collect();
+    assert_eq!(titles, vec!["a", "c"]);
+    // an unknown eid is reported, and nothing is removed
+    dir.cmd()
+        .args(["-e", "test", "remove", &eids[1].to_string()])
+        .assert()
+        .stderr(predicate::str::contains("not found"));
+    assert_eq!(dir.calendar("test").get_size(), 2);
+    dir.cmd()
+        .args(["-e", "test", "remove", "--all", "0"])
+        .assert()
+        .success();
+    assert_eq!(dir.calendar("test").get_size(), 0);
+}
+
+#[test]
+/// tests editing some fields of an event, keeping the others
+fn edit_event() {
+    let dir = DataDir::new("edit");
+    let eids = dir.seed("test", vec![event("a", "11/03/2022", "09:00", 1.0)]);
+    dir.cmd()
+        .args(["-e", "test", "edit", &eids[0].to_string(), "renamed", "new"])
+        .args(["12/03/2022", "10:30", "2"])
+        .assert()
+        .success();
+    let mut cal = dir.calendar("test");
+    assert_eq!(
+        summary(&cal),
+        vec![(
+            String::from("renamed"),
+            String::from("12/03/2022 10:30"),
+            120
+        )]
+    );
+    assert_eq!(cal.get_event(eids[0]).unwrap().get_description(), "new");
+}
+
+#[test]
+/// tests listing the events in a range of dates, with their eids
+fn list_events() {
+    let dir = DataDir::new("list");
+    let eids = dir.seed(
+        "test",
+        vec![
+            event("standup", "13/07/2022", "09:30", 1.0),
+            event("retro", "29/07/2022", "16:00", 1.0),
+            event("kickoff", "01/09/2022", "10:00", 1.0),
+        ],
+    );
+    dir.cmd()
+        .args(["-v", "test", "list"])
+        .args(["--from", "01/07/2022", "--until", "31/07/2022"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("standup"))
+        .stdout(predicate::str::contains(eids[0].to_string()))
+        .stdout(predicate::str::contains("retro"))
+        .stdout(predicate::str::contains("kickoff").not());
+    // listing does not modify the calendar
+    assert_eq!(dir.calendar("test").get_size(), 3);
+}
+
+#[test]
+/// tests that viewing a calendar that does not exist is reported
+fn view_missing_calendar() {
+    let dir = DataDir::new("missing");
+    dir.cmd()
+        .args(["-v", "nope", "list"])
+        .assert()
+        .stderr(predicate::str::contains("Calendar nope not found"));
+    assert!(!dir.path().join("nope.json").exists());
+}
+
+#[test]
+/// tests importing the events of an iCalendar file, and that importing it again does not
+/// duplicate them
+fn add_from_file() {
+    let dir = DataDir::new("import");
+    dir.seed("test", vec![]);
+    let ics = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/2_events_ok.ics");
+    dir.cmd()
+        .args(["-e", "test", "add", "--from-file", ics])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Imported 2"));
+    let expected = vec![
+        (
+            String::from("standup"),
+            String::from("13/07/2022 09:30"),
+            15,
+        ),
+        (
+            String::from("review"),
+            String::from("14/07/2022 15:00"),
+            120,
+        ),
+    ];
+    assert_eq!(summary(&dir.calendar("test")), expected);
+    dir.cmd()
+        .args(["-e", "test", "add", "--from-file", ics])
+        .assert()
+        .success();
+    assert_eq!(summary(&dir.calendar("test")), expected);
+}
+
+#[test]
+/// tests pausing the occurrences of a recurrent event, and resuming them
+fn pause_recurrence() {
+    let dir = DataDir::new("pause");
+    let standup = Event::new(
+        "standup",
+        "",
+        "28/07/2022",
+        "09:00",
+        1.0,
+        None,
+        Some("weekly 6"),
+        None,
+    );
+    let eids = dir.seed("test", vec![standup]);
+    let eid = eids[0].to_string();
+    let list = |dir: &DataDir| {
+        let out = dir
+            .cmd()
+            .args(["-v", "test", "list", "--from", "01/07/2022"])
+            .output()
+            .unwrap();
+        String::from_utf8(out.stdout)
+            .unwrap()
+            .matches("standup")
+            .count()
+    };
+    assert_eq!(list(&dir), 7);
+    dir.cmd()
+        .args(["-e", "test", "edit", &eid])
+        .args(["--pause-from", "01/08/2022", "--pause-until", "31/08/2022"])
+        .assert()
+        .success();
+    assert_eq!(list(&dir), 3);
+    dir.cmd()
+        .args(["-v", "test", "show", &eid])
+        .assert()
+        .stdout(predicate::str::contains("paused 01/08/2022 - 31/08/2022"));
+    dir.cmd()
+        .args(["-e", "test", "edit", &eid, "--resume"])
+        .assert()
+        .success();
+    assert_eq!(list(&dir), 7);
+    // a single event cannot be paused
+    let single = dir.seed("single", vec![event("a", "11/03/2022", "09:00", 1.0)]);
+    dir.cmd()
+        .args(["-e", "single", "edit", &single[0].to_string()])
+        .args(["--pause-from", "01/03/2022", "--pause-until", "31/03/2022"])
+        .assert()
+        .stderr(predicate::str::contains("needs a recurrent event"));
+}
+
+#[test]
+/// tests that moving an event past one depending on it, or removing it, is warned about and
+/// listed as blocked
+fn blocked_dependencies() {
+    let dir = DataDir::new("blocked");
+    let eids = dir.seed(
+        "test",
+        vec![
+            event("review", "13/07/2022", "09:00", 1.0),
+            event("report", "13/07/2022", "10:00", 1.0),
+        ],
+    );
+    let (review, report) = (eids[0].to_string(), eids[1].to_string());
+    dir.cmd()
+        .args(["-e", "test", "edit", &report, "--depends-on", &review])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("dependency not met").not());
+    dir.cmd()
+        .args(["-v", "test", "show", &report])
+        .assert()
+        .stdout(predicate::str::contains(format!(
+            "Depends on: review [{}]",
+            review
+        )));
+    dir.cmd()
+        .args(["-e", "test", "shift", &review, "--by", "30m"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(format!(
+            "dependency not met: \"report\" [{}] starts at 13/07/2022 10:00, before \"review\" [{}] ends at 13/07/2022 10:30",
+            report, review
+        )));
+    dir.cmd()
+        .args(["-v", "test", "list", "--blocked"])
+        .assert()
+        .stdout(predicate::str::contains(format!("\"report\" [{}]", report)));
+    dir.cmd()
+        .args(["-e", "test", "remove", &review])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(format!(
+            "depends on event {}, that was removed",
+            review
+        )));
+    dir.cmd()
+        .args(["-e", "test", "edit", &report, "--no-dependencies"])
+        .assert()
+        .success();
+    dir.cmd()
+        .args(["-v", "test", "list", "--blocked"])
+        .assert()
+        .stdout(predicate::str::is_empty());
+}
+
 #[test]
-fn add_event() -> Result<(), Box<dyn std::error::Error>> {
-    // TODO: common harness create calendar
-    let mut cmd = Command::cargo_bin("calendar")?;
-    cmd.args(["-c", "test"]);
-    cmd.assert().success();
-    cmd.args([
-        "-e",
+/// tests exporting the events in a range as a Mermaid Gantt diagram
+fn export_gantt() {
+    let dir = DataDir::new("gantt");
+    let eids = dir.seed(
         "test",
-        "add",
-        "some title",
-        "some description",
-        "11/03/2022",
-        "11:17",
-        "2.5",
-    ]);
-    cmd.assert().success();
-    Ok(())
-}
-*/
-
-/*#[test]
-fn add_from_file() -> Result<(), Box<dyn std::error::Error>> {
-    let mut cmd = Command::cargo_bin("calendar")?;
-    cmd.args(["add", "--from-file", "2_events_ok.ics"]);
-    let out = cmd.output().expect("failed adding events from ics file");
-    let outstr = std::str::from_utf8(&out.stdout)?;
-    println!("{}", outstr);
-    cmd.assert().to_string().contains("2 events");
-    Ok(())
-}*/
+        vec![
+            event("draft", "04/07/2022", "09:00", 2.0),
+            event("later", "01/08/2022", "09:00", 1.0),
+        ],
+    );
+    dir.cmd()
+        .args(["-v", "test", "export", "--format", "mermaid-gantt"])
+        .args(["--from", "01/07/2022", "--until", "31/07/2022"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("gantt\n"))
+        .stdout(predicate::str::contains(format!(
+            "draft :e{}, 2022-07-04 09:00, 2h",
+            eids[0]
+        )))
+        .stdout(predicate::str::contains("later").not());
+}
+
+#[test]
+/// tests viewing and listing the calendars of a shared root, that cannot be edited
+fn shared_roots() {
+    let dir = DataDir::new("roots");
+    let shared = DataDir::new("roots-shared");
+    dir.seed("personal", vec![]);
+    shared.seed("team", vec![event("standup", "13/07/2022", "09:30", 1.0)]);
+    std::fs::write(
+        dir.path().join("config.json"),
+        format!("{{\"shared_roots\": [{:?}]}}", shared.path()),
+    )
+    .unwrap();
+    dir.cmd()
+        .arg("-l")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("personal [personal]"))
+        .stdout(predicate::str::contains("(shared, read-only)"));
+    dir.cmd()
+        .args(["-v", "team", "list", "--from", "01/07/2022"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("standup"));
+    dir.cmd()
+        .args(["-e", "team", "add", "retro", "", "29/07/2022", "16:00", "1"])
+        .assert()
+        .stderr(predicate::str::contains("read-only"));
+    assert_eq!(shared.calendar("team").get_size(), 1);
+    assert!(!dir.path().join("team.json").exists());
+}
+
+#[test]
+/// tests that the exported calendars are valid iCalendar files, and that the invalid ones fail
+/// the validation
+fn validate_ics() {
+    let dir = DataDir::new("validate");
+    dir.seed("test", vec![event("standup", "13/07/2022", "09:30", 1.0)]);
+    let out = dir.path().join("test.ics");
+    dir.cmd()
+        .args(["-v", "test", "export", "--out"])
+        .arg(&out)
+        .assert()
+        .success();
+    dir.cmd()
+        .args(["ics", "validate"])
+        .arg(&out)
+        .assert()
+        .success()
+        .stdout(predicate::str::ends_with("valid\n"));
+    let exported = std::fs::read_to_string(&out).unwrap();
+    std::fs::write(&out, exported.replace("PRODID:-//calenda-rs//EN\r\n", "")).unwrap();
+    dir.cmd()
+        .args(["ics", "validate"])
+        .arg(&out)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("line 1: VCALENDAR without PRODID"));
+}
+
+#[test]
+/// tests that the events that are not public are exported and shared with only their times
+fn export_redacted() {
+    let dir = DataDir::new("redacted");
+    let mut private = event("doctor", "13/07/2022", "09:30", 1.0);
+    private.set_class(calendar_lib::event::Class::Private);
+    let eids = dir.seed("test", vec![private]);
+    dir.cmd()
+        .args(["-v", "test", "export", "--redact"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("SUMMARY:Busy"))
+        .stdout(predicate::str::contains("DTSTART"))
+        .stdout(predicate::str::contains("doctor").not());
+    dir.cmd()
+        .args(["-v", "test", "share", "--redact"])
+        .arg(eids[0].to_string())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("SUMMARY:Busy"))
+        .stdout(predicate::str::contains("doctor").not());
+    dir.cmd()
+        .args(["-v", "test", "export"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("SUMMARY:doctor"));
+}
+
+#[test]
+/// tests that the calendars of a shared root in an older format are migrated only in memory,
+/// without rewriting their files
+fn shared_roots_not_migrated() {
+    let dir = DataDir::new("roots-old");
+    let shared = DataDir::new("roots-old-shared");
+    dir.seed("personal", vec![]);
+    shared.seed("team", vec![event("standup", "13/07/2022", "09:30", 1.0)]);
+    // saved before the files were versioned
+    let file = shared.path().join("team.json");
+    let mut old: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+    old.as_object_mut().unwrap().remove("version");
+    std::fs::write(&file, old.to_string()).unwrap();
+    let contents = std::fs::read(&file).unwrap();
+    std::fs::write(
+        dir.path().join("config.json"),
+        format!("{{\"shared_roots\": [{:?}]}}", shared.path()),
+    )
+    .unwrap();
+    dir.cmd().arg("-l").assert().success();
+    dir.cmd()
+        .args(["-v", "team", "list", "--from", "01/07/2022"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("standup"));
+    dir.cmd()
+        .args(["-e", "team", "add", "retro", "", "29/07/2022", "16:00", "1"])
+        .assert()
+        .stderr(predicate::str::contains("read-only"));
+    dir.cmd().arg("maintenance").assert().success();
+    assert_eq!(std::fs::read(&file).unwrap(), contents);
+    assert!(shared.calendar("team").is_migrated());
+}
@@ -0,0 +1,85 @@
+//! Harness of the end-to-end tests: each test runs the binary on its own data directory,
+//! removed when the test ends, with the calendars seeded through the library
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use assert_cmd::prelude::*;
+use calendar_lib::calendar::Calendar;
+use calendar_lib::event::Event;
+use calendar_lib::storage;
+
+/// A temporary data directory, deleted when dropped
+pub struct DataDir {
+    path: PathBuf,
+}
+
+impl DataDir {
+    /// Creates an empty data directory, named after the test using it
+    pub fn new(test: &str) -> DataDir {
+        let path =
+            std::env::temp_dir().join(format!("calendar-test-cli-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        DataDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the command running the binary on this data directory, ignoring the
+    /// environment of the user running the tests
+    pub fn cmd(&self) -> Command {
+        let mut cmd = Command::cargo_bin("calenda-rs").unwrap();
+        cmd.arg("--data-dir")
+            .arg(&self.path)
+            .env_remove("CALENDA_RS_DATA")
+            .env_remove("CALENDA_RS_TOKEN");
+        cmd
+    }
+
+    /// Saves a calendar named `name` with the given events, returning their eids in order
+    pub fn seed(&self, name: &str, events: Vec<Event>) -> Vec<u64> {
+        let mut cal = storage::create_calendar(name, "tester", None, &self.path).unwrap();
+        let eids = events
+            .into_iter()
+            .map(|ev| cal.add_event(ev).added.unwrap())
+            .collect();
+        assert!(storage::save_calendar(&cal, &self.path));
+        eids
+    }
+
+    /// Reads the calendar with the given id or name, as saved by the commands
+    pub fn calendar(&self, key: &str) -> Calendar {
+        storage::resolve_calendar(key, &self.path).unwrap()
+    }
+}
+
+impl Drop for DataDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Builds an event lasting `hours`, starting at `date` ("13/07/2022") and `time` ("09:30")
+pub fn event(title: &str, date: &str, time: &str, hours: f32) -> Event {
+    Event::new(title, "", date, time, hours, None, None, None)
+}
+
+/// Returns the events of the calendar as (title, start, duration in minutes), by start
+pub fn summary(cal: &Calendar) -> Vec<(String, String, i64)> {
+    let mut events: Vec<&Event> = cal.events_by_eid().into_iter().map(|(_, ev)| ev).collect();
+    events.sort_by_key(|ev| ev.get_start());
+    events
+        .into_iter()
+        .map(|ev| {
+            (
+                ev.get_title().to_string(),
+                ev.get_start().format("%d/%m/%Y %H:%M").to_string(),
+                ev.get_duration() / 60,
+            )
+        })
+        .collect()
+}